@@ -82,6 +82,12 @@ def main():
 
 
 def read_frame_results(path):
+    # Runs with `--sink csv-zstd` or `--sink parquet` keep every candidate in one file.
+    if (path / "candidates.csv.zst").exists():
+        return pd.read_csv(path / "candidates.csv.zst")
+    if (path / "candidates.parquet").exists():
+        return pd.read_parquet(path / "candidates.parquet")
+
     frame_results = []
     frame_result_paths = sorted(path.glob("frame_*_results.csv"))
 
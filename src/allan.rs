@@ -0,0 +1,152 @@
+use crate::{error::BenchError, export::ImageFormat, overlay::Overlay, stats::median};
+use chrono::{DateTime, Utc};
+use std::path::Path;
+
+/// Size of the plot and the margins around its axes, in pixels.
+const PLOT_COLS: u32 = 640;
+const PLOT_ROWS: u32 = 480;
+const MARGIN_LEFT: f64 = 72.;
+const MARGIN_RIGHT: f64 = 24.;
+const MARGIN_TOP: f64 = 32.;
+const MARGIN_BOTTOM: f64 = 40.;
+
+const AXIS_RGBA: [u8; 4] = [0, 0, 0, 255];
+const GRID_RGBA: [u8; 4] = [210, 210, 210, 255];
+const CURVE_RGBA: [u8; 4] = [200, 30, 30, 255];
+
+/// Overlapping Allan deviation of a series at one averaging time.
+#[derive(Debug, Clone, Copy, serde::Serialize)]
+pub struct AllanPoint {
+    pub tau_s: f64,
+    pub deviation: f64,
+    /// Pairs of adjacent averages that went into the estimate.
+    pub pairs: usize,
+}
+
+/// Places timed samples on a regular grid at their median spacing, leaving gaps where frames
+/// were skipped, and returns the grid with its period in seconds.
+///
+/// Returns `None` for fewer than two samples.
+pub fn resample(samples: &[(DateTime<Utc>, f64)]) -> Option<(Vec<Option<f64>>, f64)> {
+    let (first, last) = (samples.first()?.0, samples.last()?.0);
+    let intervals: Vec<f64> = samples
+        .windows(2)
+        .map(|pair| (pair[1].0 - pair[0].0).as_seconds_f64())
+        .filter(|&interval| interval > 0.)
+        .collect();
+    let period_s = median(&intervals)?;
+
+    #[allow(clippy::cast_possible_truncation, clippy::cast_sign_loss)]
+    let slot = |time: DateTime<Utc>| ((time - first).as_seconds_f64() / period_s).round() as usize;
+    let mut grid = vec![None; slot(last) + 1];
+    for &(time, value) in samples {
+        grid[slot(time)] = Some(value);
+    }
+    Some((grid, period_s))
+}
+
+/// Overlapping Allan deviation at octave multiples of the sample period.
+///
+/// Only averages over complete clusters are compared, so gaps in the series shrink the number
+/// of pairs rather than biasing the estimate.
+#[allow(clippy::cast_precision_loss)]
+pub fn allan_deviation(samples: &[Option<f64>], period_s: f64) -> Vec<AllanPoint> {
+    // Running sums and counts, so each cluster average costs two lookups.
+    let mut sums = vec![0.; samples.len() + 1];
+    let mut counts = vec![0; samples.len() + 1];
+    for (i, sample) in samples.iter().enumerate() {
+        sums[i + 1] = sums[i] + sample.unwrap_or_default();
+        counts[i + 1] = counts[i] + usize::from(sample.is_some());
+    }
+    let average = |start: usize, m: usize| {
+        (counts[start + m] - counts[start] == m).then(|| (sums[start + m] - sums[start]) / m as f64)
+    };
+
+    std::iter::successors(Some(1), |m| Some(m * 2))
+        .take_while(|m| 2 * m < samples.len())
+        .filter_map(|m| {
+            let differences: Vec<f64> = (0..=samples.len() - 2 * m)
+                .filter_map(|k| Some(average(k + m, m)? - average(k, m)?))
+                .collect();
+            (!differences.is_empty()).then(|| {
+                let sum_squares: f64 = differences.iter().map(|d| d * d).sum();
+                AllanPoint {
+                    tau_s: m as f64 * period_s,
+                    deviation: (sum_squares / (2. * differences.len() as f64)).sqrt(),
+                    pairs: differences.len(),
+                }
+            })
+        })
+        .collect()
+}
+
+/// Plots the deviation against the averaging time on log-log axes with decade gridlines.
+pub fn plot(points: &[AllanPoint], dir: &Path, stem: &str, unit: &str) -> Result<(), BenchError> {
+    let cols = f64::from(PLOT_COLS);
+    let rows = f64::from(PLOT_ROWS);
+    let white = vec![u8::MAX; (PLOT_COLS * PLOT_ROWS * 3) as usize];
+    let mut overlay = Overlay::from_rgb(&white, PLOT_COLS, PLOT_ROWS);
+
+    let points: Vec<_> = points
+        .iter()
+        .filter(|point| point.tau_s > 0. && point.deviation > 0.)
+        .collect();
+    let decades = |values: &mut dyn Iterator<Item = f64>| {
+        let (low, high) = values.fold((f64::INFINITY, f64::NEG_INFINITY), |(low, high), v| {
+            (low.min(v.log10()), high.max(v.log10()))
+        });
+        if low.is_finite() {
+            (low.floor(), high.ceil().max(low.floor() + 1.))
+        } else {
+            (0., 1.)
+        }
+    };
+    let (x_low, x_high) = decades(&mut points.iter().map(|point| point.tau_s));
+    let (y_low, y_high) = decades(&mut points.iter().map(|point| point.deviation));
+    let (left, right) = (MARGIN_LEFT, cols - MARGIN_RIGHT);
+    let (top, bottom) = (MARGIN_TOP, rows - MARGIN_BOTTOM);
+    let col = |tau_s: f64| left + (tau_s.log10() - x_low) / (x_high - x_low) * (right - left);
+    let row = |value: f64| bottom - (value.log10() - y_low) / (y_high - y_low) * (bottom - top);
+
+    #[allow(clippy::cast_possible_truncation, clippy::cast_sign_loss)]
+    let pixel = |row: f64, col: f64| (row.max(0.) as usize, col.max(0.) as usize);
+    #[allow(clippy::cast_possible_truncation)]
+    for decade in x_low as i32..=x_high as i32 {
+        let x = col(10_f64.powi(decade));
+        overlay.draw_line((top, x), (bottom, x), GRID_RGBA);
+        overlay.draw_text(
+            pixel(bottom + 8., x - 8.),
+            &decade_label(decade),
+            1,
+            AXIS_RGBA,
+        );
+    }
+    #[allow(clippy::cast_possible_truncation)]
+    for decade in y_low as i32..=y_high as i32 {
+        let y = row(10_f64.powi(decade));
+        overlay.draw_line((y, left), (y, right), GRID_RGBA);
+        overlay.draw_text(pixel(y - 4., 8.), &decade_label(decade), 1, AXIS_RGBA);
+    }
+    overlay.draw_line((bottom, left), (bottom, right), AXIS_RGBA);
+    overlay.draw_line((top, left), (bottom, left), AXIS_RGBA);
+    overlay.draw_text(pixel(rows - 16., right - 48.), "TAU S", 1, AXIS_RGBA);
+    overlay.draw_text(pixel(8., 8.), &format!("ADEV {unit}"), 1, AXIS_RGBA);
+
+    let curve: Vec<_> = points
+        .iter()
+        .map(|point| (row(point.deviation), col(point.tau_s)))
+        .collect();
+    for pair in curve.windows(2) {
+        overlay.draw_line(pair[0], pair[1], CURVE_RGBA);
+    }
+    for &(y, x) in &curve {
+        overlay.draw_cross(pixel(y, x), 3, CURVE_RGBA);
+    }
+    overlay.save(dir, stem, ImageFormat::Png, false)
+}
+
+/// Label of a power of ten, in plain decimal notation.
+fn decade_label(exponent: i32) -> String {
+    let precision = usize::try_from(-exponent).unwrap_or(0);
+    format!("{:.precision$}", 10_f64.powi(exponent))
+}
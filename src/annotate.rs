@@ -0,0 +1,244 @@
+use crate::{
+    camera::CameraModel,
+    ephemeris::CelestialPosition,
+    overlay::{Overlay, SUN_RGBA},
+    sky::{SkyDirection, source_pixel},
+    systems::InsEnu,
+};
+use chrono::{DateTime, Utc};
+use sguaba::engineering::Orientation;
+use uom::si::{angle::degree, f64::Angle};
+
+/// Columns and rows of a glyph of the built-in font.
+const GLYPH_COLS: usize = 5;
+const GLYPH_ROWS: usize = 7;
+/// Blank pixels between characters and between lines, before scaling.
+const GLYPH_SPACING: usize = 1;
+
+/// Spacing of the graticule lines.
+const GRATICULE_AZIMUTH_STEP_DEG: f64 = 30.;
+const GRATICULE_ELEVATION_STEP_DEG: f64 = 10.;
+/// Elevation the cardinal directions are labelled at.
+const CARDINAL_LABEL_ELEVATION_DEG: f64 = 70.;
+
+pub const TEXT_RGBA: [u8; 4] = [255, 255, 255, 255];
+pub const SHADOW_RGBA: [u8; 4] = [0, 0, 0, 255];
+pub const GRATICULE_RGBA: [u8; 4] = [255, 255, 255, 96];
+
+/// What an annotated image shows besides the sky itself.
+#[derive(Debug, Clone, Copy)]
+pub struct FrameLabel {
+    pub frame_index: usize,
+    pub time: DateTime<Utc>,
+    /// Compass heading of the vehicle according to the INS.
+    pub heading_deg: f64,
+    pub estimated_heading_deg: Option<f64>,
+    pub sun: CelestialPosition,
+}
+
+impl FrameLabel {
+    fn lines(&self) -> Vec<String> {
+        let mut lines = vec![
+            format!("FRAME {:04}", self.frame_index),
+            self.time.format("%Y-%m-%d %H:%M:%S UTC").to_string(),
+            format!("HDG {:.1} DEG", self.heading_deg),
+        ];
+        if let Some(estimated_heading_deg) = self.estimated_heading_deg {
+            lines.push(format!("EST {estimated_heading_deg:.1} DEG"));
+        }
+        lines.push(format!(
+            "SUN AZ {:.1} EL {:.1}",
+            self.sun.azimuth.get::<degree>(),
+            self.sun.elevation.get::<degree>()
+        ));
+        lines
+    }
+}
+
+impl Overlay {
+    /// Draws text with its top left corner at a pixel, in the built-in font scaled up by an
+    /// integer factor, over a shadow that keeps it legible on any colour.
+    ///
+    /// Lower case letters are drawn as upper case, and unknown characters as `?`.
+    pub fn draw_text(
+        &mut self,
+        (row, col): (usize, usize),
+        text: &str,
+        scale: usize,
+        rgba: [u8; 4],
+    ) {
+        let scale = scale.max(1);
+        for (shadow, rgba) in [(scale, SHADOW_RGBA), (0, rgba)] {
+            for (line_index, line) in text.lines().enumerate() {
+                let top = row + shadow + line_index * (GLYPH_ROWS + GLYPH_SPACING) * scale;
+                for (char_index, c) in line.chars().enumerate() {
+                    let left = col + shadow + char_index * (GLYPH_COLS + GLYPH_SPACING) * scale;
+                    self.draw_glyph((top, left), glyph(c), scale, rgba);
+                }
+            }
+        }
+    }
+
+    #[allow(clippy::cast_precision_loss)]
+    fn draw_glyph(
+        &mut self,
+        (top, left): (usize, usize),
+        glyph: [u8; GLYPH_ROWS],
+        scale: usize,
+        rgba: [u8; 4],
+    ) {
+        for (glyph_row, bits) in glyph.iter().enumerate() {
+            for glyph_col in 0..GLYPH_COLS {
+                if bits & (1 << (GLYPH_COLS - 1 - glyph_col)) == 0 {
+                    continue;
+                }
+                for dr in 0..scale {
+                    for dc in 0..scale {
+                        self.put(
+                            (top + glyph_row * scale + dr) as f64,
+                            (left + glyph_col * scale + dc) as f64,
+                            rgba,
+                        );
+                    }
+                }
+            }
+        }
+    }
+
+    /// Draws lines of constant azimuth and elevation through the sky each pixel looks at.
+    ///
+    /// `directions` are in row-major order, as returned by [`crate::sky::sky_directions`], so
+    /// the lines follow the attitude and projection of the camera.
+    #[allow(clippy::cast_precision_loss)]
+    pub fn draw_graticule(&mut self, directions: &[Option<SkyDirection>], rgba: [u8; 4]) {
+        let cols = self.image().width() as usize;
+        let rows = self.image().height() as usize;
+        let cell = |direction: &Option<SkyDirection>| {
+            direction.map(|direction| {
+                let azimuth_deg = direction.azimuth.get::<degree>().rem_euclid(360.);
+                let elevation_deg = 90. - direction.zenith.get::<degree>();
+                (
+                    (azimuth_deg / GRATICULE_AZIMUTH_STEP_DEG).floor(),
+                    (elevation_deg / GRATICULE_ELEVATION_STEP_DEG).floor(),
+                )
+            })
+        };
+
+        // A pixel is on a line when its cell differs from the one to its right or below.
+        let mut mask = vec![false; rows * cols];
+        for row in 0..rows {
+            for col in 0..cols {
+                let Some(here) = directions.get(row * cols + col).and_then(cell) else {
+                    continue;
+                };
+                let crosses = [(row, col + 1), (row + 1, col)]
+                    .into_iter()
+                    .filter(|&(r, c)| r < rows && c < cols)
+                    .filter_map(|(r, c)| directions.get(r * cols + c).and_then(cell))
+                    .any(|there| there != here);
+                mask[row * cols + col] = crosses;
+            }
+        }
+        self.mask(&mask, rgba);
+    }
+
+    /// Labels the cardinal directions that are in view.
+    pub fn draw_cardinal_labels(
+        &mut self,
+        camera: &CameraModel,
+        car_in_ins_enu: Orientation<InsEnu>,
+        scale: usize,
+    ) {
+        for (label, azimuth_deg) in [("N", 0.), ("E", 90.), ("S", 180.), ("W", 270.)] {
+            let position = CelestialPosition {
+                azimuth: Angle::new::<degree>(azimuth_deg),
+                elevation: Angle::new::<degree>(CARDINAL_LABEL_ELEVATION_DEG),
+            };
+            if let Some(pixel) = source_pixel(camera, car_in_ins_enu, &position) {
+                self.draw_text(pixel, label, scale, TEXT_RGBA);
+            }
+        }
+    }
+
+    /// Makes an image self-describing: graticule, cardinal directions, an arrow towards the sun,
+    /// arrows along the true and estimated headings, and the frame details in the corner.
+    pub fn annotate(
+        &mut self,
+        camera: &CameraModel,
+        car_in_ins_enu: Orientation<InsEnu>,
+        directions: &[Option<SkyDirection>],
+        label: &FrameLabel,
+        scale: usize,
+    ) {
+        self.draw_graticule(directions, GRATICULE_RGBA);
+        self.draw_cardinal_labels(camera, car_in_ins_enu, scale);
+        self.draw_heading(
+            camera,
+            car_in_ins_enu,
+            label.sun.azimuth.get::<degree>(),
+            SUN_RGBA,
+        );
+        self.draw_heading_comparison(
+            camera,
+            car_in_ins_enu,
+            label.heading_deg,
+            label.estimated_heading_deg,
+        );
+        let margin = 4 * scale;
+        self.draw_text(
+            (margin, margin),
+            &label.lines().join("\n"),
+            scale,
+            TEXT_RGBA,
+        );
+    }
+}
+
+/// Rows of a character of the built-in 5x7 font, with the leftmost column in bit 4.
+fn glyph(c: char) -> [u8; GLYPH_ROWS] {
+    match c.to_ascii_uppercase() {
+        ' ' => [0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00],
+        '0' => [0x0E, 0x11, 0x13, 0x15, 0x19, 0x11, 0x0E],
+        '1' => [0x04, 0x0C, 0x04, 0x04, 0x04, 0x04, 0x0E],
+        '2' => [0x0E, 0x11, 0x01, 0x02, 0x04, 0x08, 0x1F],
+        '3' => [0x1F, 0x02, 0x04, 0x02, 0x01, 0x11, 0x0E],
+        '4' => [0x02, 0x06, 0x0A, 0x12, 0x1F, 0x02, 0x02],
+        '5' => [0x1F, 0x10, 0x1E, 0x01, 0x01, 0x11, 0x0E],
+        '6' => [0x06, 0x08, 0x10, 0x1E, 0x11, 0x11, 0x0E],
+        '7' => [0x1F, 0x01, 0x02, 0x04, 0x08, 0x08, 0x08],
+        '8' => [0x0E, 0x11, 0x11, 0x0E, 0x11, 0x11, 0x0E],
+        '9' => [0x0E, 0x11, 0x11, 0x0F, 0x01, 0x02, 0x0C],
+        'A' => [0x0E, 0x11, 0x11, 0x1F, 0x11, 0x11, 0x11],
+        'B' => [0x1E, 0x11, 0x11, 0x1E, 0x11, 0x11, 0x1E],
+        'C' => [0x0E, 0x11, 0x10, 0x10, 0x10, 0x11, 0x0E],
+        'D' => [0x1C, 0x12, 0x11, 0x11, 0x11, 0x12, 0x1C],
+        'E' => [0x1F, 0x10, 0x10, 0x1E, 0x10, 0x10, 0x1F],
+        'F' => [0x1F, 0x10, 0x10, 0x1E, 0x10, 0x10, 0x10],
+        'G' => [0x0E, 0x11, 0x10, 0x17, 0x11, 0x11, 0x0F],
+        'H' => [0x11, 0x11, 0x11, 0x1F, 0x11, 0x11, 0x11],
+        'I' => [0x0E, 0x04, 0x04, 0x04, 0x04, 0x04, 0x0E],
+        'J' => [0x07, 0x02, 0x02, 0x02, 0x02, 0x12, 0x0C],
+        'K' => [0x11, 0x12, 0x14, 0x18, 0x14, 0x12, 0x11],
+        'L' => [0x10, 0x10, 0x10, 0x10, 0x10, 0x10, 0x1F],
+        'M' => [0x11, 0x1B, 0x15, 0x15, 0x11, 0x11, 0x11],
+        'N' => [0x11, 0x11, 0x19, 0x15, 0x13, 0x11, 0x11],
+        'O' => [0x0E, 0x11, 0x11, 0x11, 0x11, 0x11, 0x0E],
+        'P' => [0x1E, 0x11, 0x11, 0x1E, 0x10, 0x10, 0x10],
+        'Q' => [0x0E, 0x11, 0x11, 0x11, 0x15, 0x12, 0x0D],
+        'R' => [0x1E, 0x11, 0x11, 0x1E, 0x14, 0x12, 0x11],
+        'S' => [0x0F, 0x10, 0x10, 0x0E, 0x01, 0x01, 0x1E],
+        'T' => [0x1F, 0x04, 0x04, 0x04, 0x04, 0x04, 0x04],
+        'U' => [0x11, 0x11, 0x11, 0x11, 0x11, 0x11, 0x0E],
+        'V' => [0x11, 0x11, 0x11, 0x11, 0x11, 0x0A, 0x04],
+        'W' => [0x11, 0x11, 0x11, 0x15, 0x15, 0x15, 0x0A],
+        'X' => [0x11, 0x11, 0x0A, 0x04, 0x0A, 0x11, 0x11],
+        'Y' => [0x11, 0x11, 0x11, 0x0A, 0x04, 0x04, 0x04],
+        'Z' => [0x1F, 0x01, 0x02, 0x04, 0x08, 0x10, 0x1F],
+        ':' => [0x00, 0x0C, 0x0C, 0x00, 0x0C, 0x0C, 0x00],
+        '-' => [0x00, 0x00, 0x00, 0x1F, 0x00, 0x00, 0x00],
+        '+' => [0x00, 0x04, 0x04, 0x1F, 0x04, 0x04, 0x00],
+        '.' => [0x00, 0x00, 0x00, 0x00, 0x00, 0x0C, 0x0C],
+        '/' => [0x00, 0x01, 0x02, 0x04, 0x08, 0x10, 0x00],
+        _ => [0x0E, 0x11, 0x01, 0x02, 0x04, 0x00, 0x04],
+    }
+}
@@ -0,0 +1,299 @@
+use crate::{error::BenchError, pipeline::Dataset, remote};
+use std::{
+    collections::BTreeMap,
+    fs::File,
+    io::{self, BufReader, Read},
+    path::{Component, Path, PathBuf},
+    sync::{Arc, Mutex},
+};
+
+/// File names of dataset archives, which are read in place rather than extracted.
+const ARCHIVE_SUFFIXES: [&str; 2] = [".tar.zst", ".tzst"];
+
+/// Size of tar headers and of the blocks file contents are padded to.
+const BLOCK: usize = 512;
+
+/// Archives indexed so far, kept until the process exits.
+static ARCHIVES: Mutex<Vec<Arc<DatasetArchive>>> = Mutex::new(Vec::new());
+
+/// Whether a path names a `.tar.zst` dataset archive.
+pub fn is_archive(path: &Path) -> bool {
+    path.file_name()
+        .and_then(|name| name.to_str())
+        .is_some_and(|name| ARCHIVE_SUFFIXES.iter().any(|suffix| name.ends_with(suffix)))
+}
+
+/// Archive a path points into, and the path inside of it.
+fn split(path: &Path) -> Option<(&Path, &Path)> {
+    let archive = path
+        .ancestors()
+        .skip(1)
+        .find(|ancestor| is_archive(ancestor))?;
+    Some((archive, path.strip_prefix(archive).ok()?))
+}
+
+/// Whether a dataset path points into an archive.
+pub fn is_archived(path: &Path) -> bool {
+    split(path).is_some()
+}
+
+/// Whether a dataset file can be read with plain filesystem calls, being neither in an
+/// archive nor in remote storage.
+pub fn is_plain_file(path: &Path) -> bool {
+    !is_archived(path) && !remote::is_remote(path)
+}
+
+/// Reads a dataset file, from its archive if it is in one and through the local cache if it
+/// is remote.
+pub fn read_file(path: &Path) -> Result<Vec<u8>, BenchError> {
+    match split(path) {
+        Some((archive, member)) => DatasetArchive::shared(archive)?.read(member),
+        None => std::fs::read(remote::local_path(path)?).map_err(|e| BenchError::dataset(path, e)),
+    }
+}
+
+/// Opens a dataset file like [`read_file`] reads it.
+pub fn open_file(path: &Path) -> Result<Box<dyn Read + Send>, BenchError> {
+    if is_archived(path) {
+        return Ok(Box::new(io::Cursor::new(read_file(path)?)));
+    }
+    let file = File::open(remote::local_path(path)?).map_err(|e| BenchError::dataset(path, e))?;
+    Ok(Box::new(file))
+}
+
+/// A dataset packed as a zstd-compressed tar archive.
+///
+/// Opening it decompresses it once to index it, keeping the logs and other small files in
+/// memory. Images are streamed out of the archive when read; runs read them in order, so the
+/// archive is only decompressed again when a read goes back to an earlier image.
+pub struct DatasetArchive {
+    path: PathBuf,
+    /// Where the archive is read from, a local copy for remote archives.
+    file: PathBuf,
+    /// Every file but the images, by path relative to the dataset.
+    files: BTreeMap<PathBuf, Vec<u8>>,
+    /// Position in the archive of every image, by path relative to the dataset.
+    images: BTreeMap<PathBuf, usize>,
+    stream: Mutex<Option<TarStream>>,
+}
+
+impl DatasetArchive {
+    pub fn open(path: &Path) -> Result<Self, BenchError> {
+        let error = |e| BenchError::dataset(path, e);
+        let file = remote::local_path(path)?;
+        let mut stream = TarStream::open(&file).map_err(error)?;
+        let mut files = BTreeMap::new();
+        let mut images = BTreeMap::new();
+        while let Some((name, size)) = stream.next_file().map_err(error)? {
+            if name.extension().is_some_and(|extension| extension == "png") {
+                stream.skip_data(size).map_err(error)?;
+                images.insert(name, stream.files - 1);
+            } else {
+                files.insert(name, stream.read_data(size).map_err(error)?);
+            }
+        }
+
+        // Archives usually hold the dataset in a directory of its own, found from its time log.
+        let time_path = Dataset::new("").time_path();
+        let root = files
+            .keys()
+            .filter(|name| name.ends_with(&time_path))
+            .find_map(|name| name.ancestors().nth(time_path.components().count()))
+            .map(Path::to_path_buf)
+            .unwrap_or_default();
+        let relative = |name: PathBuf| Some(name.strip_prefix(&root).ok()?.to_path_buf());
+        Ok(Self {
+            path: path.to_path_buf(),
+            file,
+            files: files
+                .into_iter()
+                .filter_map(|(name, data)| Some((relative(name)?, data)))
+                .collect(),
+            images: images
+                .into_iter()
+                .filter_map(|(name, position)| Some((relative(name)?, position)))
+                .collect(),
+            stream: Mutex::new(None),
+        })
+    }
+
+    /// The archive at `path`, indexed on first use.
+    pub fn shared(path: &Path) -> Result<Arc<Self>, BenchError> {
+        let mut archives = ARCHIVES.lock().unwrap();
+        if let Some(archive) = archives.iter().find(|archive| archive.path == path) {
+            return Ok(Arc::clone(archive));
+        }
+        let archive = Arc::new(Self::open(path)?);
+        archives.push(Arc::clone(&archive));
+        Ok(archive)
+    }
+
+    pub fn contains(&self, member: &Path) -> bool {
+        self.files.contains_key(member) || self.images.contains_key(member)
+    }
+
+    /// Images in the archive.
+    pub fn images(&self) -> usize {
+        self.images.len()
+    }
+
+    /// Reads a file by its path relative to the dataset.
+    pub fn read(&self, member: &Path) -> Result<Vec<u8>, BenchError> {
+        let full_path = self.path.join(member);
+        if let Some(data) = self.files.get(member) {
+            return Ok(data.clone());
+        }
+        let Some(&position) = self.images.get(member) else {
+            return Err(BenchError::dataset(
+                &full_path,
+                io::Error::new(io::ErrorKind::NotFound, "not in the archive"),
+            ));
+        };
+
+        let error = |e| BenchError::dataset(&full_path, e);
+        let mut stream = self.stream.lock().unwrap();
+        if stream.as_ref().is_none_or(|stream| stream.files > position) {
+            *stream = Some(TarStream::open(&self.file).map_err(error)?);
+        }
+        let stream = stream.as_mut().unwrap();
+        while let Some((_, size)) = stream.next_file().map_err(error)? {
+            if stream.files - 1 == position {
+                return stream.read_data(size).map_err(error);
+            }
+            stream.skip_data(size).map_err(error)?;
+        }
+        Err(error(io::Error::new(
+            io::ErrorKind::UnexpectedEof,
+            "archive ended before the file",
+        )))
+    }
+}
+
+/// Files of a tar archive read one after the other out of its zstd stream.
+struct TarStream {
+    reader: zstd::Decoder<'static, BufReader<File>>,
+    /// Files read or skipped so far.
+    files: usize,
+}
+
+impl TarStream {
+    fn open(path: &Path) -> io::Result<Self> {
+        Ok(Self {
+            reader: zstd::Decoder::new(File::open(path)?)?,
+            files: 0,
+        })
+    }
+
+    /// Path and size of the next regular file, whose contents must be read or skipped next.
+    ///
+    /// Directories and other entries are skipped, and GNU and pax long names are followed.
+    fn next_file(&mut self) -> io::Result<Option<(PathBuf, u64)>> {
+        let mut long_name = None;
+        loop {
+            let mut header = [0u8; BLOCK];
+            match self.reader.read_exact(&mut header) {
+                Ok(()) => {}
+                // Some writers leave out the blocks of zeros at the end.
+                Err(e) if e.kind() == io::ErrorKind::UnexpectedEof => return Ok(None),
+                Err(e) => return Err(e),
+            }
+            if header.iter().all(|&byte| byte == 0) {
+                return Ok(None);
+            }
+
+            let size = header_size(&header)?;
+            match header[156] {
+                b'0' | b'\0' | b'7' => {
+                    let name = long_name.take().unwrap_or_else(|| header_name(&header));
+                    self.files += 1;
+                    return Ok(Some((normalize(&name), size)));
+                }
+                b'L' => {
+                    let data = self.read_data(size)?;
+                    long_name = Some(field(&data).to_string());
+                }
+                b'x' => {
+                    let data = self.read_data(size)?;
+                    long_name = pax_path(&data).or(long_name);
+                }
+                _ => self.skip_data(size)?,
+            }
+        }
+    }
+
+    fn read_data(&mut self, size: u64) -> io::Result<Vec<u8>> {
+        let mut data = Vec::new();
+        (&mut self.reader).take(size).read_to_end(&mut data)?;
+        if (data.len() as u64) < size {
+            return Err(io::Error::new(
+                io::ErrorKind::UnexpectedEof,
+                "archive ended inside a file",
+            ));
+        }
+        self.skip_padding(size)?;
+        Ok(data)
+    }
+
+    fn skip_data(&mut self, size: u64) -> io::Result<()> {
+        io::copy(&mut (&mut self.reader).take(size), &mut io::sink())?;
+        self.skip_padding(size)
+    }
+
+    #[allow(clippy::cast_possible_truncation)]
+    fn skip_padding(&mut self, size: u64) -> io::Result<()> {
+        let padding = (BLOCK - (size % BLOCK as u64) as usize) % BLOCK;
+        self.reader.read_exact(&mut [0u8; BLOCK][..padding])
+    }
+}
+
+/// A NUL-terminated header field.
+fn field(bytes: &[u8]) -> &str {
+    let end = bytes
+        .iter()
+        .position(|&byte| byte == 0)
+        .unwrap_or(bytes.len());
+    std::str::from_utf8(&bytes[..end]).unwrap_or_default()
+}
+
+fn header_name(header: &[u8; BLOCK]) -> String {
+    let name = field(&header[..100]);
+    let prefix = field(&header[345..500]);
+    if &header[257..262] == b"ustar" && !prefix.is_empty() {
+        format!("{prefix}/{name}")
+    } else {
+        name.to_string()
+    }
+}
+
+/// Size of an entry, in octal or, for large files, big-endian binary.
+fn header_size(header: &[u8; BLOCK]) -> io::Result<u64> {
+    let size = &header[124..136];
+    if size[0] & 0x80 != 0 {
+        return Ok(size[4..]
+            .iter()
+            .fold(0, |value, &byte| value << 8 | u64::from(byte)));
+    }
+    let octal = field(size).trim_matches(|c: char| c == ' ' || c == '\0');
+    if octal.is_empty() {
+        return Ok(0);
+    }
+    u64::from_str_radix(octal, 8)
+        .map_err(|_| io::Error::new(io::ErrorKind::InvalidData, "bad tar entry size"))
+}
+
+/// The `path` record of a pax extended header, made of `length key=value\n` records.
+fn pax_path(data: &[u8]) -> Option<String> {
+    String::from_utf8_lossy(data).lines().find_map(|record| {
+        let (_, pair) = record.split_once(' ')?;
+        let (key, value) = pair.split_once('=')?;
+        (key == "path").then(|| value.to_string())
+    })
+}
+
+/// Drops the `./` components tar adds when archiving a directory from inside of it.
+fn normalize(name: &str) -> PathBuf {
+    Path::new(name)
+        .components()
+        .filter(|component| !matches!(component, Component::CurDir))
+        .collect()
+}
@@ -0,0 +1,288 @@
+use crate::{
+    archive,
+    error::BenchError,
+    integrity::{DatasetManifest, dataset_files, modified_ns},
+    leaderboard::stable_hash,
+    pipeline::Dataset,
+    remote,
+    stats::{mean, median},
+};
+use serde::{Deserialize, Serialize};
+use std::{
+    collections::{BTreeMap, HashMap},
+    path::{Path, PathBuf},
+    process::Command,
+    sync::{
+        Mutex,
+        atomic::{AtomicUsize, Ordering},
+    },
+};
+
+/// Per-frame columns summarized across runs, and whether only their magnitude matters.
+pub const METRICS: [(&str, bool); 4] = [
+    ("weighted_rmse", false),
+    ("best_weighted_rmse", false),
+    ("dop_rmse", false),
+    ("yaw_error_deg", true),
+];
+
+/// Experiment binary built alongside the running one.
+pub fn experiment_executable(experiment: &str) -> Result<PathBuf, BenchError> {
+    let current = std::env::current_exe()
+        .map_err(|e| BenchError::Config(format!("cannot find the running binary: {e}")))?;
+    let executable =
+        current.with_file_name(format!("{experiment}{}", std::env::consts::EXE_SUFFIX));
+    if !executable.exists() {
+        return Err(BenchError::Config(format!(
+            "no experiment binary at {}; build it with `cargo build --release --bin {experiment}`",
+            executable.display()
+        )));
+    }
+    Ok(executable)
+}
+
+/// Version of an experiment binary, which changes whenever it is built from different code.
+pub fn code_version(executable: &Path) -> Result<String, BenchError> {
+    let bytes = std::fs::read(executable)
+        .map_err(|e| BenchError::Config(format!("cannot read {}: {e}", executable.display())))?;
+    Ok(stable_hash(&bytes))
+}
+
+/// Version of a dataset, which changes whenever its files do.
+///
+/// Datasets with a `checksums.json` are versioned by the checksums in it, and others by the size
+/// and modification time of every file. Remote datasets are versioned by their path alone.
+pub fn dataset_version(path: &Path) -> Result<String, BenchError> {
+    let dataset = Dataset::new(path);
+    if remote::is_remote(path) {
+        return Ok(String::new());
+    }
+    if DatasetManifest::path(&dataset).exists() {
+        let manifest = DatasetManifest::read(&dataset)?;
+        // Modification times are left out, as checks update them for unchanged copies.
+        let checksums: BTreeMap<_, _> = manifest
+            .files
+            .iter()
+            .map(|(file, checksum)| (file, &checksum.sha256))
+            .collect();
+        return Ok(stable_hash(
+            serde_json::json!(checksums).to_string().as_bytes(),
+        ));
+    }
+
+    let files = if archive::is_archive(path) {
+        vec![PathBuf::new()]
+    } else {
+        dataset_files(&dataset)?
+    };
+    let mut stats = Vec::with_capacity(files.len());
+    for file in files {
+        let full_path = if file.as_os_str().is_empty() {
+            path.to_path_buf()
+        } else {
+            path.join(&file)
+        };
+        let metadata =
+            std::fs::metadata(&full_path).map_err(|e| BenchError::dataset(&full_path, e))?;
+        stats.push((file, metadata.len(), modified_ns(&metadata)));
+    }
+    Ok(stable_hash(serde_json::json!(stats).to_string().as_bytes()))
+}
+
+/// Identifies a run by everything that decides its results, including the
+/// [`dataset_version`].
+pub fn run_hash(
+    code_version: &str,
+    experiment: &str,
+    dataset: &Path,
+    dataset_version: &str,
+    args: &[String],
+) -> String {
+    let config =
+        serde_json::json!([code_version, experiment, dataset, dataset_version, args]).to_string();
+    stable_hash(config.as_bytes())
+}
+
+/// Results directories of completed runs by their [`run_hash`], kept between invocations so
+/// unchanged runs are not computed again.
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub struct CompletedRuns {
+    pub runs: BTreeMap<String, PathBuf>,
+}
+
+impl CompletedRuns {
+    /// Reads the completed runs, of which there are none before the first invocation.
+    pub fn read(path: &Path) -> Result<Self, BenchError> {
+        if !path.exists() {
+            return Ok(Self::default());
+        }
+        let file = std::fs::File::open(path).map_err(|e| BenchError::dataset(path, e))?;
+        serde_json::from_reader(file).map_err(|e| BenchError::dataset(path, e))
+    }
+
+    pub fn write(&self, path: &Path) -> Result<(), BenchError> {
+        let file =
+            std::fs::File::create(path).map_err(|e| BenchError::output(path.display(), e))?;
+        serde_json::to_writer_pretty(file, self).map_err(|e| BenchError::output(path.display(), e))
+    }
+
+    /// Results directory of a completed run, unless it has since been removed.
+    pub fn get(&self, hash: &str) -> Option<&Path> {
+        self.runs
+            .get(hash)
+            .map(PathBuf::as_path)
+            .filter(|dir| run_completed(dir))
+    }
+
+    pub fn insert(&mut self, hash: String, results_dir: &Path) {
+        let results_dir =
+            std::fs::canonicalize(results_dir).unwrap_or_else(|_| results_dir.to_path_buf());
+        self.runs.insert(hash, results_dir);
+    }
+}
+
+/// Runs an experiment on a dataset from `run_dir`, where it makes its results directory.
+///
+/// Returns whether it succeeded and the newest results directory in `run_dir`, which a failed
+/// run may still have written.
+pub fn run_experiment(
+    executable: &Path,
+    dataset: &Path,
+    args: &[String],
+    run_dir: &Path,
+) -> (bool, Option<PathBuf>) {
+    let status = std::fs::create_dir_all(run_dir).and_then(|()| {
+        Command::new(executable)
+            .arg(dataset)
+            .args(args)
+            .current_dir(run_dir)
+            .status()
+    });
+    let success = match status {
+        Ok(status) => status.success(),
+        Err(e) => {
+            eprintln!("failed to start {}: {e}", executable.display());
+            false
+        }
+    };
+
+    let results_dir = std::fs::read_dir(run_dir).ok().and_then(|entries| {
+        entries
+            .filter_map(Result::ok)
+            .map(|entry| entry.path())
+            .filter(|path| path.is_dir())
+            .max()
+    });
+    (success, results_dir)
+}
+
+/// Calls `f` on every item from at most `jobs` threads, and returns the results in item order.
+pub fn run_parallel<T: Sync, R: Send>(
+    items: &[T],
+    jobs: usize,
+    f: impl Fn(usize, &T) -> R + Sync,
+) -> Vec<R> {
+    // Workers take the next item until none are left.
+    let next = AtomicUsize::new(0);
+    let results = Mutex::new(Vec::new());
+    std::thread::scope(|scope| {
+        for _ in 0..jobs.max(1) {
+            scope.spawn(|| {
+                loop {
+                    let index = next.fetch_add(1, Ordering::Relaxed);
+                    let Some(item) = items.get(index) else {
+                        break;
+                    };
+                    let result = f(index, item);
+                    results.lock().unwrap().push((index, result));
+                }
+            });
+        }
+    });
+
+    let mut results = results.into_inner().unwrap();
+    results.sort_by_key(|(index, _)| *index);
+    results.into_iter().map(|(_, result)| result).collect()
+}
+
+/// Every numeric value of the metric columns of a results CSV.
+pub fn read_metrics(path: &Path) -> HashMap<&'static str, Vec<f64>> {
+    let mut metrics: HashMap<&str, Vec<f64>> = HashMap::new();
+    let Ok(mut reader) = csv::Reader::from_path(path) else {
+        eprintln!("no results at {}", path.display());
+        return metrics;
+    };
+    for row in reader.deserialize::<HashMap<String, String>>() {
+        let Ok(row) = row else {
+            continue;
+        };
+        for (metric, magnitude) in METRICS {
+            // Empty cells are frames where the metric could not be computed.
+            let Some(value) = row.get(metric).and_then(|value| value.parse::<f64>().ok()) else {
+                continue;
+            };
+            metrics
+                .entry(metric)
+                .or_default()
+                .push(if magnitude { value.abs() } else { value });
+        }
+    }
+    metrics
+}
+
+/// Whether a run wrote its summary without being interrupted.
+pub fn run_completed(results_dir: &Path) -> bool {
+    std::fs::File::open(results_dir.join("summary.json"))
+        .ok()
+        .and_then(|file| serde_json::from_reader::<_, serde_json::Value>(file).ok())
+        .is_some_and(|summary| summary["interrupted"] == false)
+}
+
+/// Frames processed and skipped according to the summary of a run.
+pub fn read_frame_counts(results_dir: &Path) -> (usize, usize) {
+    let summary: Option<serde_json::Value> = std::fs::File::open(results_dir.join("summary.json"))
+        .ok()
+        .and_then(|file| serde_json::from_reader(file).ok());
+    let Some(summary) = summary else {
+        return (0, 0);
+    };
+    let count = |value: &serde_json::Value| {
+        value
+            .as_u64()
+            .and_then(|count| usize::try_from(count).ok())
+            .unwrap_or_default()
+    };
+    let processed = count(&summary["frames_processed"]);
+    let skipped = summary["frames_skipped"]
+        .as_object()
+        .map(|reasons| reasons.values().map(count).sum())
+        .unwrap_or_default();
+    (processed, skipped)
+}
+
+#[derive(Debug, Clone, serde::Serialize)]
+pub struct MetricSummary {
+    pub metric: &'static str,
+    pub frames: usize,
+    pub mean: f64,
+    pub median: f64,
+}
+
+impl MetricSummary {
+    pub fn new(metric: &'static str, values: &[f64]) -> Option<Self> {
+        Some(Self {
+            metric,
+            frames: values.len(),
+            mean: mean(values)?,
+            median: median(values)?,
+        })
+    }
+
+    /// Summaries of whichever metrics have values, in the order of [`METRICS`].
+    pub fn all(values: &HashMap<&'static str, Vec<f64>>) -> Vec<Self> {
+        METRICS
+            .iter()
+            .filter_map(|(metric, _)| Self::new(metric, values.get(metric)?))
+            .collect()
+    }
+}
@@ -0,0 +1,242 @@
+use clap::Parser;
+use rumpus_benchmark::{
+    batch::{
+        MetricSummary, experiment_executable, read_frame_counts, read_metrics, run_experiment,
+        run_parallel,
+    },
+    pipeline::ResultWriter,
+};
+use std::{
+    collections::HashMap,
+    path::{Path, PathBuf},
+};
+
+/// Runs an experiment over several datasets and summarizes them together.
+///
+/// Each run writes its usual results directory inside a subdirectory of the batch results named
+/// after its dataset.
+fn main() {
+    let config = Cli::parse();
+    let datasets = dataset_paths(&config);
+    if datasets.is_empty() {
+        eprintln!("no datasets given");
+        std::process::exit(1);
+    }
+
+    let executable = experiment_executable(&config.experiment).unwrap_or_else(|e| {
+        eprintln!("{e}");
+        std::process::exit(1);
+    });
+    let results = ResultWriter::create().unwrap();
+
+    let runs = run_parallel(&datasets, config.jobs, |index, dataset| {
+        run_dataset(&executable, &config, dataset, index, results.dir())
+    });
+
+    let mut writer = results.csv("batch_summary.csv").unwrap();
+    let mut pooled: HashMap<&str, Vec<f64>> = HashMap::new();
+    let mut summaries = Vec::new();
+    for run in &runs {
+        let frames = run
+            .results_dir
+            .as_deref()
+            .map(|dir| read_metrics(&dir.join(&config.results_file)))
+            .unwrap_or_default();
+        for (metric, values) in &frames {
+            pooled.entry(metric).or_default().extend(values);
+        }
+        let summary = DatasetSummary::new(run, &frames);
+        for record in summary.metric_records() {
+            let _ = writer.serialize(record);
+        }
+        summaries.push(summary);
+    }
+
+    // Frames of every dataset pooled together.
+    let all = DatasetSummary {
+        dataset: "all".to_string(),
+        results_dir: None,
+        success: runs.iter().all(|run| run.success),
+        frames_processed: summaries.iter().map(|s| s.frames_processed).sum(),
+        frames_skipped: summaries.iter().map(|s| s.frames_skipped).sum(),
+        metrics: MetricSummary::all(&pooled),
+    };
+    for record in all.metric_records() {
+        let _ = writer.serialize(record);
+    }
+
+    for summary in summaries.iter().chain([&all]) {
+        print_summary(summary);
+    }
+
+    let path = results.dir().join("batch.json");
+    let file = std::fs::File::create(path).unwrap();
+    serde_json::to_writer_pretty(
+        file,
+        &Batch {
+            experiment: config.experiment.clone(),
+            experiment_args: config.experiment_args.clone(),
+            datasets: summaries,
+            all,
+        },
+    )
+    .unwrap();
+
+    if runs.iter().any(|run| !run.success) {
+        std::process::exit(1);
+    }
+}
+
+/// Datasets from the command line followed by those in the manifest.
+///
+/// Manifests list one dataset per line, relative to the manifest, with `#` starting a comment.
+fn dataset_paths(config: &Cli) -> Vec<PathBuf> {
+    let mut datasets = config.datasets.clone();
+    if let Some(manifest) = &config.manifest {
+        let contents = std::fs::read_to_string(manifest)
+            .unwrap_or_else(|e| panic!("cannot read {}: {e}", manifest.display()));
+        let base = manifest.parent().unwrap_or(Path::new("."));
+        datasets.extend(
+            contents
+                .lines()
+                .map(|line| line.split('#').next().unwrap_or_default().trim())
+                .filter(|line| !line.is_empty())
+                .map(|line| base.join(line)),
+        );
+    }
+    datasets
+}
+
+fn run_dataset(
+    executable: &Path,
+    config: &Cli,
+    dataset: &Path,
+    index: usize,
+    batch_dir: &Path,
+) -> Run {
+    let name = dataset.file_name().map_or_else(
+        || format!("dataset_{index}"),
+        |name| name.to_string_lossy().to_string(),
+    );
+    // Datasets with the same name are told apart by their position in the batch.
+    let run_dir = batch_dir.join(format!("{index:02}_{name}"));
+
+    // The experiment writes its results directory into its working directory.
+    let dataset = std::fs::canonicalize(dataset).unwrap_or_else(|_| dataset.to_path_buf());
+    println!("running {} on {}", config.experiment, dataset.display());
+    let (success, results_dir) =
+        run_experiment(executable, &dataset, &config.experiment_args, &run_dir);
+    if !success {
+        eprintln!("{} failed on {}", config.experiment, dataset.display());
+    }
+    Run {
+        dataset,
+        results_dir,
+        success,
+    }
+}
+
+fn print_summary(summary: &DatasetSummary) {
+    println!(
+        "{}: {} frames processed, {} skipped{}",
+        summary.dataset,
+        summary.frames_processed,
+        summary.frames_skipped,
+        if summary.success { "" } else { " (failed)" }
+    );
+    for metric in &summary.metrics {
+        println!(
+            "  {}: {} frames, mean {:.4}, median {:.4}",
+            metric.metric, metric.frames, metric.mean, metric.median
+        );
+    }
+}
+
+struct Run {
+    dataset: PathBuf,
+    results_dir: Option<PathBuf>,
+    success: bool,
+}
+
+#[derive(Parser)]
+struct Cli {
+    /// Experiment binary to run, such as `test_pattern_match`.
+    experiment: String,
+
+    datasets: Vec<PathBuf>,
+
+    /// File listing more datasets, one per line.
+    #[arg(long)]
+    manifest: Option<PathBuf>,
+
+    /// Number of datasets processed at once.
+    #[arg(short, long, default_value_t = 1)]
+    jobs: usize,
+
+    /// Per-frame results file inside each run directory.
+    #[arg(long, default_value = "results.csv")]
+    results_file: String,
+
+    /// Arguments passed to the experiment after the dataset path.
+    #[arg(last = true)]
+    experiment_args: Vec<String>,
+}
+
+#[derive(serde::Serialize)]
+struct DatasetSummary {
+    dataset: String,
+    results_dir: Option<PathBuf>,
+    success: bool,
+    frames_processed: usize,
+    frames_skipped: usize,
+    metrics: Vec<MetricSummary>,
+}
+
+impl DatasetSummary {
+    fn new(run: &Run, frames: &HashMap<&'static str, Vec<f64>>) -> Self {
+        let (frames_processed, frames_skipped) = run
+            .results_dir
+            .as_deref()
+            .map(read_frame_counts)
+            .unwrap_or_default();
+        Self {
+            dataset: run.dataset.display().to_string(),
+            results_dir: run.results_dir.clone(),
+            success: run.success,
+            frames_processed,
+            frames_skipped,
+            metrics: MetricSummary::all(frames),
+        }
+    }
+
+    fn metric_records(&self) -> impl Iterator<Item = MetricRecord<'_>> {
+        self.metrics.iter().map(|metric| MetricRecord {
+            dataset: &self.dataset,
+            frames_processed: self.frames_processed,
+            frames_skipped: self.frames_skipped,
+            metric: metric.metric,
+            frames: metric.frames,
+            mean: metric.mean,
+            median: metric.median,
+        })
+    }
+}
+
+#[derive(serde::Serialize)]
+struct MetricRecord<'a> {
+    dataset: &'a str,
+    frames_processed: usize,
+    frames_skipped: usize,
+    metric: &'static str,
+    frames: usize,
+    mean: f64,
+    median: f64,
+}
+
+#[derive(serde::Serialize)]
+struct Batch {
+    experiment: String,
+    experiment_args: Vec<String>,
+    datasets: Vec<DatasetSummary>,
+    all: DatasetSummary,
+}
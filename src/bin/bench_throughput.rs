@@ -0,0 +1,205 @@
+use clap::Parser;
+use rumpus_benchmark::{
+    camera::CameraModel,
+    cli::{DatasetArgs, SkyArgs, interrupt_on_ctrl_c, run_metadata},
+    estimator::{FrameInput, HeadingEstimator},
+    heading::SearchWindow,
+    io::ImageReader,
+    pipeline::{FrameContext, FrameProcessor, FrameRange, FrameSkip, ResultWriter},
+    run::SkipReason,
+    sky::{Sky, SkyModelBackend},
+    utils::downsample,
+};
+use std::{fs::File, time::Instant};
+use uom::si::{
+    f64::Length,
+    length::{micron, millimeter},
+};
+
+const FOCAL_LENGTH_MM: f64 = 8.0;
+
+/// Processes one frame over and over with every combination of thread count, downsample
+/// factor and sky backend, and reports the frame rate of each.
+fn main() {
+    let config = Cli::parse();
+    let results = ResultWriter::create().unwrap();
+    run_metadata(
+        env!("CARGO_BIN_NAME"),
+        results.started(),
+        &config.dataset,
+        &config.sky,
+    )
+    .write(results.dir())
+    .unwrap();
+
+    let pipeline = config
+        .dataset
+        .pipeline()
+        .unwrap()
+        .with_interrupt(interrupt_on_ctrl_c())
+        .with_frame_range(Some(FrameRange {
+            start: config.frame,
+            end: Some(config.frame + 1),
+        }))
+        .with_max_frames(Some(1));
+    let image_reader = config.dataset.prefetching_image_reader(&pipeline).unwrap();
+    let focal_length = Length::new::<millimeter>(FOCAL_LENGTH_MM);
+    let pixel_size = Length::new::<micron>(3.45);
+    let mut processor = ThroughputProcessor {
+        camera_model: CameraModel::for_mosaic(focal_length, pixel_size, 2048, 2448, &image_reader)
+            .with_principal_point(config.dataset.principal_point(&image_reader)),
+        image_reader,
+        sky: config.sky.sky().unwrap(),
+        config: &config,
+        writer: results.csv("throughput.csv").unwrap(),
+    };
+    let summary = pipeline.run(&mut processor);
+    if summary.frames_processed == 0 {
+        summary.print();
+    }
+}
+
+struct ThroughputProcessor<'a> {
+    camera_model: CameraModel,
+    image_reader: ImageReader,
+    sky: Sky,
+    config: &'a Cli,
+    writer: csv::Writer<File>,
+}
+
+impl FrameProcessor for ThroughputProcessor<'_> {
+    fn process(&mut self, frame: &FrameContext) -> Result<(), FrameSkip> {
+        let (image, image_circle) = self
+            .image_reader
+            .read_image_with_circle(&frame.image_path)
+            .map_err(|e| FrameSkip::new(SkipReason::UnreadableImage, e))?;
+        let yaw_offsets = SearchWindow {
+            center_deg: 0.,
+            half_width_deg: self.config.window_deg,
+        }
+        .offsets(self.config.resolution_deg);
+
+        println!(
+            "frame {:04}, {} candidates, {} iterations per configuration",
+            frame.frame_index,
+            yaw_offsets.len(),
+            self.config.iterations
+        );
+        println!(
+            "{:>10} {:>8} {:>10} {:>10} {:>10}",
+            "backend", "threads", "downsample", "ms/frame", "fps"
+        );
+        for &backend in &self.config.backends {
+            for &factor in &self.config.downsample {
+                let estimator = HeadingEstimator::new(
+                    self.camera_model.downsampled(factor),
+                    self.sky.clone().with_backend(backend),
+                );
+                let image = downsample(&image, factor);
+                let input = FrameInput {
+                    frame_index: frame.frame_index,
+                    image: &image,
+                    image_circle: image_circle.map(|circle| circle.in_rays(factor)),
+                    position: &frame.ins.position,
+                    time: frame.time,
+                    car_in_ins_enu: frame.ins.orientation,
+                    yaw_smear: frame.yaw_smear,
+                };
+                for &threads in &self.config.threads {
+                    let pool = rayon::ThreadPoolBuilder::new()
+                        .num_threads(threads)
+                        .build()
+                        .map_err(|e| FrameSkip::new(SkipReason::SimulationFailed, e))?;
+                    let seconds_per_frame = pool.install(|| {
+                        // The first pass warms up caches and lazily built tables.
+                        estimator.sweep(&input, &yaw_offsets);
+                        let t0 = Instant::now();
+                        for _ in 0..self.config.iterations {
+                            estimator.sweep(&input, &yaw_offsets);
+                        }
+                        #[allow(clippy::cast_precision_loss)]
+                        let iterations = self.config.iterations.max(1) as f64;
+                        t0.elapsed().as_secs_f64() / iterations
+                    });
+                    let record = ThroughputRecord {
+                        backend,
+                        threads,
+                        downsample: factor,
+                        rows: estimator.camera().rows(),
+                        cols: estimator.camera().cols(),
+                        candidates: yaw_offsets.len(),
+                        ms_per_frame: seconds_per_frame * 1e3,
+                        fps: seconds_per_frame.recip(),
+                    };
+                    println!(
+                        "{:>10} {:>8} {:>10} {:>10.1} {:>10.2}",
+                        format!("{backend:?}").to_lowercase(),
+                        threads,
+                        factor,
+                        record.ms_per_frame,
+                        record.fps
+                    );
+                    self.writer
+                        .serialize(record)
+                        .map_err(|e| FrameSkip::new(SkipReason::UnwritableResults, e))?;
+                }
+            }
+        }
+        self.writer
+            .flush()
+            .map_err(|e| FrameSkip::new(SkipReason::UnwritableResults, e))
+    }
+}
+
+#[derive(Parser)]
+struct Cli {
+    #[command(flatten)]
+    dataset: DatasetArgs,
+
+    #[command(flatten)]
+    sky: SkyArgs,
+
+    /// Index of the frame to process.
+    #[arg(long, default_value_t = 0)]
+    frame: usize,
+
+    /// Timed passes over the frame per configuration, after one to warm up.
+    #[arg(long, default_value_t = 5)]
+    iterations: usize,
+
+    /// Worker thread counts to try.
+    #[arg(long, value_delimiter = ',', default_value = "1,2,4,8")]
+    threads: Vec<usize>,
+
+    /// Factors to downsample the image and camera by.
+    #[arg(long, value_delimiter = ',', default_value = "1,2,4")]
+    downsample: Vec<usize>,
+
+    /// Sky backends to try.
+    #[arg(
+        long,
+        value_enum,
+        value_delimiter = ',',
+        default_value = "rayleigh,berry"
+    )]
+    backends: Vec<SkyModelBackend>,
+
+    #[arg(short, long, default_value_t = 0.1)]
+    resolution_deg: f64,
+
+    /// Half width of the yaw sweep.
+    #[arg(long, default_value_t = 5.0)]
+    window_deg: f64,
+}
+
+#[derive(serde::Serialize)]
+struct ThroughputRecord {
+    backend: SkyModelBackend,
+    threads: usize,
+    downsample: usize,
+    rows: usize,
+    cols: usize,
+    candidates: usize,
+    ms_per_frame: f64,
+    fps: f64,
+}
@@ -0,0 +1,100 @@
+use clap::Parser;
+use rumpus_benchmark::{
+    camera::CameraModel,
+    ephemeris::CelestialPosition,
+    io::{ImageReader, InsReader, TimeReader},
+    pipeline::Dataset,
+    sky::{self, SkyTableBuilder},
+    utils::measured_to_global,
+};
+use std::{path::PathBuf, time::Instant};
+use uom::si::{
+    f64::Length,
+    length::{micron, millimeter},
+};
+
+const FOCAL_LENGTH_MM: f64 = 8.0;
+
+/// Builds an empirical sky table from calibration frames whose INS attitude is trusted.
+fn main() {
+    let config = Cli::parse();
+    let dataset = Dataset::new(&config.dataset_path);
+
+    let ins_path = dataset.ins_path();
+    let ins_reader = InsReader::new();
+    let ins_frames = ins_reader.read_csv(&ins_path).unwrap();
+
+    let time_path = dataset.time_path();
+    let time_reader = TimeReader::new();
+    let time_frames = time_reader.read_csv(&time_path).unwrap();
+
+    let image_reader = ImageReader::new();
+
+    let focal_length = Length::new::<millimeter>(FOCAL_LENGTH_MM);
+    let pixel_size = Length::new::<micron>(3.45);
+    let camera_model = CameraModel::new(focal_length, pixel_size * 2.0, 1024, 1224);
+
+    let mut builder = SkyTableBuilder::new(config.resolution_deg);
+
+    let mut frame_count = 0;
+    for (frame_index, (time_frame, ins_frame)) in
+        time_frames.zip(ins_frames).enumerate().step_by(config.step)
+    {
+        let t0 = Instant::now();
+
+        let car_in_ins_enu = ins_frame.orientation;
+        let image_path = dataset.image_path(frame_index);
+        let image = match image_reader.read_image(image_path) {
+            Ok(image) => image,
+            Err(e) => {
+                eprintln!("failed to read image: {e}");
+                continue;
+            }
+        };
+        let measured = measured_to_global(&image, &camera_model, car_in_ins_enu);
+
+        let directions = sky::sky_directions(&camera_model, car_in_ins_enu);
+        let sun = CelestialPosition::sun(&ins_frame.position, time_frame.time);
+        builder.add(&measured, &directions, &sun);
+
+        frame_count += 1;
+        println!(
+            "[{:04}] frame {:04} in {:05} ms",
+            frame_count,
+            frame_index,
+            t0.elapsed().as_millis()
+        );
+
+        if let Some(max_frames) = config.max_frames
+            && frame_count >= max_frames
+        {
+            break;
+        }
+    }
+
+    let table = builder.build(config.min_samples);
+    table.write_csv(&config.output).unwrap();
+    println!("wrote sky table to {}", config.output.display());
+}
+
+#[derive(Parser)]
+struct Cli {
+    dataset_path: PathBuf,
+
+    #[arg(short, long, default_value = "sky_table.csv")]
+    output: PathBuf,
+
+    #[arg(short, long)]
+    max_frames: Option<usize>,
+
+    #[arg(short, long, default_value_t = 1)]
+    step: usize,
+
+    /// Size of each zenith and relative azimuth bin.
+    #[arg(short, long, default_value_t = 1.0)]
+    resolution_deg: f64,
+
+    /// Cells with fewer samples are left empty.
+    #[arg(long, default_value_t = 10)]
+    min_samples: usize,
+}
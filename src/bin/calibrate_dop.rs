@@ -0,0 +1,122 @@
+use clap::Parser;
+use rumpus_benchmark::{
+    camera::CameraModel,
+    cli::{DatasetArgs, SkyArgs, interrupt_on_ctrl_c},
+    exposure::ExposureGate,
+    io::ImageReader,
+    pipeline::{FrameContext, FrameProcessor, FrameSkip},
+    run::SkipReason,
+    sky::{self, DopCalibrationBuilder, Sky},
+    utils::measured_to_global,
+};
+use std::path::PathBuf;
+use uom::si::{
+    f64::Length,
+    length::{micron, millimeter},
+};
+
+const FOCAL_LENGTH_MM: f64 = 8.0;
+
+/// Fits the ratio of measured to simulated DoP by sky elevation across a dataset.
+///
+/// Pass the result to the other experiments with `--dop-calibration` to correct the simulation.
+fn main() {
+    let config = Cli::parse();
+    let pipeline = config
+        .dataset
+        .pipeline()
+        .unwrap()
+        .with_interrupt(interrupt_on_ctrl_c());
+
+    let focal_length = Length::new::<millimeter>(FOCAL_LENGTH_MM);
+    let pixel_size = Length::new::<micron>(3.45);
+    let image_reader = config.dataset.prefetching_image_reader(&pipeline).unwrap();
+    let mut processor = CalibrationProcessor {
+        camera_model: CameraModel::for_mosaic(focal_length, pixel_size, 2048, 2448, &image_reader)
+            .with_principal_point(config.dataset.principal_point(&image_reader)),
+        sky: config.sky.sky().unwrap(),
+        image_reader,
+        exposure_gate: config.dataset.exposure_gate(),
+        builder: DopCalibrationBuilder::new(config.resolution_deg),
+    };
+
+    let summary = pipeline.run(&mut processor);
+    summary.print();
+
+    let calibration = processor.builder.build(config.min_samples);
+    if calibration.is_empty() {
+        eprintln!("no elevation bin has {} samples", config.min_samples);
+        std::process::exit(1);
+    }
+    calibration.write_csv(&config.output).unwrap();
+    println!(
+        "wrote {} elevation bins to {}",
+        calibration.len(),
+        config.output.display()
+    );
+}
+
+/// Pairs the measured and simulated DoP of every pixel by the elevation it looks at.
+struct CalibrationProcessor {
+    camera_model: CameraModel,
+    sky: Sky,
+    image_reader: ImageReader,
+    exposure_gate: ExposureGate,
+    builder: DopCalibrationBuilder,
+}
+
+impl FrameProcessor for CalibrationProcessor {
+    fn process(&mut self, frame: &FrameContext) -> Result<(), FrameSkip> {
+        let car_in_ins_enu = frame.ins.orientation;
+
+        let (image, exposure, _) = self
+            .image_reader
+            .read_image_with_exposure(&frame.image_path, self.exposure_gate.dark_level())
+            .map_err(|e| FrameSkip::new(SkipReason::UnreadableImage, e))?;
+        // Badly exposed frames are left out even when the gate only flags them.
+        if self.exposure_gate.fails(&exposure) {
+            return Err(FrameSkip::new(
+                SkipReason::BadExposure,
+                format!(
+                    "{:.1}% of super-pixels are badly exposed",
+                    100. * exposure.bad_fraction()
+                ),
+            ));
+        }
+        let measured = measured_to_global(&image, &self.camera_model, car_in_ins_enu);
+        let simulated = self
+            .sky
+            .simulate_smeared(
+                &self.camera_model,
+                &frame.ins.position,
+                car_in_ins_enu,
+                frame.time,
+                frame.yaw_smear,
+            )
+            .map_err(|e| FrameSkip::new(SkipReason::SimulationFailed, e))?;
+
+        let directions = sky::sky_directions(&self.camera_model, car_in_ins_enu);
+        self.builder.add(&simulated, &measured, &directions);
+        Ok(())
+    }
+}
+
+#[derive(Parser)]
+struct Cli {
+    #[command(flatten)]
+    dataset: DatasetArgs,
+
+    #[command(flatten)]
+    sky: SkyArgs,
+
+    #[arg(short, long, default_value = "dop_calibration.csv")]
+    output: PathBuf,
+
+    /// Size of each elevation bin.
+    #[arg(short, long, default_value_t = 5.0)]
+    resolution_deg: f64,
+
+    /// Bins with fewer samples are left out of the curve.
+    #[arg(long, default_value_t = 1000)]
+    min_samples: usize,
+}
@@ -0,0 +1,218 @@
+use clap::Parser;
+use rayon::prelude::*;
+use rumpus_benchmark::{
+    camera::CameraModel,
+    cli::{DatasetArgs, SkyArgs, interrupt_on_ctrl_c},
+    error::BenchError,
+    exposure::ExposureGate,
+    io::{ImageReader, TimeFrame},
+    magnetic::MagneticModel,
+    motion::{FrameAlignment, MotionModel, align_frames},
+    pipeline::{FrameContext, FrameProcessor, FrameSkip},
+    run::SkipReason,
+    sky::Sky,
+    time_offset::{OffsetSample, TimeCalibration, best_latency, latencies_ms},
+    utils::{measured_to_global, weighted_rmse},
+};
+use std::path::PathBuf;
+use uom::si::{
+    f64::Length,
+    length::{micron, millimeter},
+};
+
+const FOCAL_LENGTH_MM: f64 = 8.0;
+
+/// Estimates the constant offset between the camera and INS clocks by scanning camera
+/// latencies and keeping the one with the lowest mean weighted RMSE over the selected frames.
+///
+/// Pass the result to the other experiments with `--time-calibration`.
+fn main() {
+    let config = Cli::parse();
+    let pipeline = config
+        .dataset
+        .pipeline()
+        .unwrap()
+        .with_interrupt(interrupt_on_ctrl_c());
+    let latencies = latencies_ms(
+        config.min_latency_ms,
+        config.max_latency_ms,
+        config.latency_step_ms,
+    );
+    if latencies.len() < 3 {
+        eprintln!("scan at least three latencies");
+        std::process::exit(1);
+    }
+
+    let focal_length = Length::new::<millimeter>(FOCAL_LENGTH_MM);
+    let pixel_size = Length::new::<micron>(3.45);
+    let image_reader = config.dataset.prefetching_image_reader(&pipeline).unwrap();
+    let mut processor = OffsetProcessor {
+        camera_model: CameraModel::for_mosaic(focal_length, pixel_size, 2048, 2448, &image_reader)
+            .with_principal_point(config.dataset.principal_point(&image_reader)),
+        sky: config.sky.sky().unwrap(),
+        image_reader,
+        exposure_gate: config.dataset.exposure_gate(),
+        motion_model: pipeline.motion_model(),
+        magnetic_model: config.dataset.magnetic_model().unwrap(),
+        latencies_ms: latencies.clone(),
+        rmse_sums: vec![0.; latencies.len()],
+        frames: 0,
+    };
+
+    let summary = pipeline.run(&mut processor);
+    summary.print();
+    if processor.frames == 0 {
+        eprintln!("no frame could be compared at every latency");
+        std::process::exit(1);
+    }
+
+    #[allow(clippy::cast_precision_loss)]
+    let samples: Vec<_> = latencies
+        .iter()
+        .zip(&processor.rmse_sums)
+        .map(|(&camera_latency_ms, rmse_sum)| OffsetSample {
+            camera_latency_ms,
+            mean_weighted_rmse: rmse_sum / processor.frames as f64,
+            frames: processor.frames,
+        })
+        .collect();
+    if let Some(scan_output) = &config.scan_output {
+        let mut writer = csv::Writer::from_path(scan_output).unwrap();
+        for sample in &samples {
+            writer.serialize(sample).unwrap();
+        }
+        writer.flush().unwrap();
+    }
+
+    let best = best_latency(&samples).unwrap();
+    let at_edge = [latencies[0], latencies[latencies.len() - 1]]
+        .iter()
+        .any(|&edge| (best.camera_latency_ms - edge).abs() < config.latency_step_ms / 2.);
+    if at_edge {
+        eprintln!(
+            "WARNING: the best latency is at the edge of the scan; widen --min-latency-ms or --max-latency-ms"
+        );
+    }
+    TimeCalibration::new(best.camera_latency_ms, best.frames, best.mean_weighted_rmse)
+        .write(&config.output)
+        .unwrap();
+    println!(
+        "camera latency {:.1} ms over {} frames, mean weighted rmse {:.4}; wrote {}",
+        best.camera_latency_ms,
+        best.frames,
+        best.mean_weighted_rmse,
+        config.output.display()
+    );
+}
+
+/// Sums the weighted RMSE of every frame with the INS state taken at each latency.
+struct OffsetProcessor<'a> {
+    camera_model: CameraModel,
+    sky: Sky,
+    image_reader: ImageReader,
+    exposure_gate: ExposureGate,
+    motion_model: &'a MotionModel,
+    magnetic_model: Option<MagneticModel>,
+    latencies_ms: Vec<f64>,
+    rmse_sums: Vec<f64>,
+    /// Frames compared at every latency.
+    frames: usize,
+}
+
+impl FrameProcessor for OffsetProcessor<'_> {
+    fn process(&mut self, frame: &FrameContext) -> Result<(), FrameSkip> {
+        // Frames near the ends of the INS log are left out entirely, so every latency is
+        // scored over the same frames.
+        let states = self
+            .latencies_ms
+            .iter()
+            .map(|&latency_ms| {
+                let time_frame = TimeFrame { time: frame.time };
+                let alignment = FrameAlignment::interpolate_ms(latency_ms);
+                let (_, state) =
+                    align_frames(std::iter::once(time_frame), self.motion_model, alignment)
+                        .next()?;
+                let mut state = state?;
+                if let Some(magnetic_model) = &self.magnetic_model {
+                    state.orientation =
+                        magnetic_model.true_heading(state.orientation, &state.position, frame.time);
+                }
+                Some(state)
+            })
+            .collect::<Option<Vec<_>>>()
+            .ok_or_else(|| {
+                FrameSkip::new(SkipReason::NoInsState, "no INS state at every latency")
+            })?;
+
+        let (image, exposure, _) = self
+            .image_reader
+            .read_image_with_exposure(&frame.image_path, self.exposure_gate.dark_level())
+            .map_err(|e| FrameSkip::new(SkipReason::UnreadableImage, e))?;
+        // Badly exposed frames are left out even when the gate only flags them.
+        if self.exposure_gate.fails(&exposure) {
+            return Err(FrameSkip::new(
+                SkipReason::BadExposure,
+                format!(
+                    "{:.1}% of super-pixels are badly exposed",
+                    100. * exposure.bad_fraction()
+                ),
+            ));
+        }
+
+        let residuals = states
+            .par_iter()
+            .map(|state| {
+                let simulated = self.sky.simulate_smeared(
+                    &self.camera_model,
+                    &state.position,
+                    state.orientation,
+                    state.time,
+                    frame.yaw_smear,
+                )?;
+                let measured = measured_to_global(&image, &self.camera_model, state.orientation);
+                Ok(weighted_rmse(&simulated, &measured))
+            })
+            .collect::<Result<Vec<_>, BenchError>>()
+            .map_err(|e| FrameSkip::new(SkipReason::SimulationFailed, e))?;
+        // A frame without a single comparable pixel says nothing about the offset.
+        if residuals.iter().any(|rmse| !rmse.is_finite()) {
+            return Err(FrameSkip::new(
+                SkipReason::SimulationFailed,
+                "no pixel is valid in both images at some latency",
+            ));
+        }
+
+        for (sum, rmse) in self.rmse_sums.iter_mut().zip(residuals) {
+            *sum += rmse;
+        }
+        self.frames += 1;
+        Ok(())
+    }
+}
+
+#[derive(Parser)]
+struct Cli {
+    #[command(flatten)]
+    dataset: DatasetArgs,
+
+    #[command(flatten)]
+    sky: SkyArgs,
+
+    #[arg(short, long, default_value = "time_calibration.json")]
+    output: PathBuf,
+
+    /// Also write the mean weighted RMSE at every latency to this CSV.
+    #[arg(long)]
+    scan_output: Option<PathBuf>,
+
+    /// Shortest delay between exposure and the camera frame time to try.
+    #[arg(long, default_value_t = -200.0, allow_negative_numbers = true)]
+    min_latency_ms: f64,
+
+    /// Longest delay between exposure and the camera frame time to try.
+    #[arg(long, default_value_t = 200.0, allow_negative_numbers = true)]
+    max_latency_ms: f64,
+
+    #[arg(long, default_value_t = 10.0)]
+    latency_step_ms: f64,
+}
@@ -0,0 +1,189 @@
+use clap::Parser;
+use rumpus_benchmark::{
+    pipeline::ResultWriter,
+    stats::{SignedRankTest, mean, median},
+};
+use std::{
+    collections::{BTreeMap, HashMap},
+    path::{Path, PathBuf},
+};
+
+/// Per-frame columns worth comparing, and whether only their magnitude matters.
+const METRICS: [(&str, bool); 4] = [
+    ("weighted_rmse", false),
+    ("best_weighted_rmse", false),
+    ("dop_rmse", false),
+    ("yaw_error_deg", true),
+];
+
+type FrameValues = BTreeMap<usize, HashMap<String, f64>>;
+
+/// Compares two results directories frame by frame.
+///
+/// Differences are run B minus run A, so negative values mean B has lower errors.
+fn main() {
+    let config = Cli::parse();
+    let run_a = read_results(&config.run_a, &config.results_file);
+    let run_b = read_results(&config.run_b, &config.results_file);
+
+    let results = ResultWriter::create().unwrap();
+    let mut writer = results.csv("comparison.csv").unwrap();
+    let mut summaries = Vec::new();
+
+    for (metric, magnitude) in METRICS {
+        let mut values_a = Vec::new();
+        let mut values_b = Vec::new();
+        for (frame_index, frame_a) in &run_a {
+            let Some((a, b)) = frame_a.get(metric).zip(
+                run_b
+                    .get(frame_index)
+                    .and_then(|frame_b| frame_b.get(metric)),
+            ) else {
+                continue;
+            };
+            let (a, b) = if magnitude {
+                (a.abs(), b.abs())
+            } else {
+                (*a, *b)
+            };
+            let _ = writer.serialize(FrameRecord {
+                frame_index: *frame_index,
+                metric,
+                run_a: a,
+                run_b: b,
+                difference: b - a,
+            });
+            values_a.push(a);
+            values_b.push(b);
+        }
+        if values_a.is_empty() {
+            continue;
+        }
+
+        let differences: Vec<f64> = values_a.iter().zip(&values_b).map(|(a, b)| b - a).collect();
+        let test = SignedRankTest::new(&differences);
+        let summary = MetricSummary {
+            metric,
+            frames: differences.len(),
+            mean_a: mean(&values_a).unwrap(),
+            mean_b: mean(&values_b).unwrap(),
+            mean_difference: mean(&differences).unwrap(),
+            median_difference: median(&differences).unwrap(),
+            p_value: test.map(|test| test.p_value),
+            significant: test.is_some_and(|test| test.p_value < config.alpha),
+        };
+        print_summary(&summary);
+        summaries.push(summary);
+    }
+
+    if summaries.is_empty() {
+        eprintln!(
+            "no frames with a metric in common; are both runs from the same experiment and dataset?"
+        );
+        std::process::exit(1);
+    }
+
+    let path = results.dir().join("comparison.json");
+    let file = std::fs::File::create(path).unwrap();
+    serde_json::to_writer_pretty(
+        file,
+        &Comparison {
+            run_a: config.run_a,
+            run_b: config.run_b,
+            alpha: config.alpha,
+            frames_a: run_a.len(),
+            frames_b: run_b.len(),
+            metrics: summaries,
+        },
+    )
+    .unwrap();
+}
+
+/// Reads every numeric column of a results CSV, keyed by frame index.
+fn read_results(run_dir: &Path, results_file: &str) -> FrameValues {
+    let path = run_dir.join(results_file);
+    let mut reader = csv::Reader::from_path(&path)
+        .unwrap_or_else(|e| panic!("cannot read {}: {e}", path.display()));
+
+    let mut frames = FrameValues::new();
+    for row in reader.deserialize::<HashMap<String, String>>() {
+        let row = row.unwrap();
+        let Some(frame_index) = row.get("frame_index").and_then(|i| i.parse().ok()) else {
+            continue;
+        };
+        // Empty cells are frames where the metric could not be computed.
+        let values = row
+            .into_iter()
+            .filter_map(|(column, value)| Some((column, value.parse().ok()?)))
+            .collect();
+        frames.insert(frame_index, values);
+    }
+    frames
+}
+
+fn print_summary(summary: &MetricSummary) {
+    let p_value_fmt = match summary.p_value {
+        Some(p_value) => format!("p = {p_value:.4}"),
+        None => "identical".to_string(),
+    };
+    println!(
+        "{}: {} frames, A {:.4}, B {:.4}, B - A mean {:+.4} median {:+.4}, {p_value_fmt}{}",
+        summary.metric,
+        summary.frames,
+        summary.mean_a,
+        summary.mean_b,
+        summary.mean_difference,
+        summary.median_difference,
+        if summary.significant {
+            " (significant)"
+        } else {
+            ""
+        }
+    );
+}
+
+#[derive(Parser)]
+struct Cli {
+    run_a: PathBuf,
+
+    run_b: PathBuf,
+
+    /// Per-frame results file inside each run directory.
+    #[arg(long, default_value = "results.csv")]
+    results_file: String,
+
+    /// Significance level of the Wilcoxon signed-rank test.
+    #[arg(long, default_value_t = 0.05)]
+    alpha: f64,
+}
+
+#[derive(serde::Serialize)]
+struct FrameRecord {
+    frame_index: usize,
+    metric: &'static str,
+    run_a: f64,
+    run_b: f64,
+    difference: f64,
+}
+
+#[derive(serde::Serialize)]
+struct MetricSummary {
+    metric: &'static str,
+    frames: usize,
+    mean_a: f64,
+    mean_b: f64,
+    mean_difference: f64,
+    median_difference: f64,
+    p_value: Option<f64>,
+    significant: bool,
+}
+
+#[derive(serde::Serialize)]
+struct Comparison {
+    run_a: PathBuf,
+    run_b: PathBuf,
+    alpha: f64,
+    frames_a: usize,
+    frames_b: usize,
+    metrics: Vec<MetricSummary>,
+}
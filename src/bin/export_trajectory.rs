@@ -0,0 +1,49 @@
+use clap::Parser;
+use rumpus_benchmark::trajectory::{TrajectoryFormat, TrajectoryPoint, write_geojson, write_kml};
+use std::path::PathBuf;
+
+/// Exports the route of a `test_pattern_match` run coloured by heading error, for a map viewer.
+fn main() {
+    let config = Cli::parse();
+    let results_path = config.results_dir.join("results.csv");
+    let mut reader = csv::Reader::from_path(&results_path)
+        .unwrap_or_else(|e| panic!("cannot read {}: {e}", results_path.display()));
+    let points: Vec<TrajectoryPoint> = reader
+        .deserialize()
+        .collect::<Result<_, _>>()
+        .unwrap_or_else(|e| {
+            panic!(
+                "{} has no positions, was it written by test_pattern_match? {e}",
+                results_path.display()
+            )
+        });
+
+    let output = config.output.unwrap_or_else(|| {
+        config
+            .results_dir
+            .join(format!("trajectory.{}", config.format.extension()))
+    });
+    match config.format {
+        TrajectoryFormat::Geojson => write_geojson(&points, &output, config.max_error_deg),
+        TrajectoryFormat::Kml => write_kml(&points, &output, config.max_error_deg),
+    }
+    .unwrap();
+
+    println!("wrote {} frames to {}", points.len(), output.display());
+}
+
+#[derive(Parser)]
+struct Cli {
+    results_dir: PathBuf,
+
+    #[arg(long, value_enum, default_value_t = TrajectoryFormat::Geojson)]
+    format: TrajectoryFormat,
+
+    /// Heading error shown in full red.
+    #[arg(long, default_value_t = 5.0)]
+    max_error_deg: f64,
+
+    /// Defaults to `trajectory.geojson` or `trajectory.kml` in the results directory.
+    #[arg(short, long)]
+    output: Option<PathBuf>,
+}
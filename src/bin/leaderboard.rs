@@ -0,0 +1,52 @@
+use clap::Parser;
+use rumpus_benchmark::{leaderboard::Leaderboard, pipeline::Dataset};
+use std::path::PathBuf;
+
+/// Prints every run completed on a dataset, best heading error first.
+fn main() {
+    let config = Cli::parse();
+    let dataset = Dataset::new(&config.dataset_path);
+    let leaderboard = Leaderboard::read(&dataset).unwrap();
+    if leaderboard.entries.is_empty() {
+        println!(
+            "no runs recorded in {}",
+            Leaderboard::path(&dataset).display()
+        );
+        return;
+    }
+
+    println!(
+        "{:>4}  {:<32}  {:<20}  {:<16}  {:>7}  {:>10}  {:>10}",
+        "rank", "run", "experiment", "config", "frames", "error deg", "runtime s"
+    );
+    for (rank, entry) in leaderboard
+        .ranked()
+        .into_iter()
+        .take(config.limit.unwrap_or(usize::MAX))
+        .enumerate()
+    {
+        let error_fmt = match entry.mean_heading_error_deg {
+            Some(error) => format!("{error:.3}"),
+            None => "-".to_string(),
+        };
+        println!(
+            "{:>4}  {:<32}  {:<20}  {:<16}  {:>7}  {:>10}  {:>10.1}",
+            rank + 1,
+            entry.run_name,
+            entry.experiment,
+            entry.config_hash,
+            entry.frames_processed,
+            error_fmt,
+            entry.runtime_s
+        );
+    }
+}
+
+#[derive(Parser)]
+struct Cli {
+    dataset_path: PathBuf,
+
+    /// Only show this many of the best runs.
+    #[arg(short, long)]
+    limit: Option<usize>,
+}
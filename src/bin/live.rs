@@ -0,0 +1,267 @@
+use aravis::{Aravis, BufferExt, CameraExt, StreamExt};
+use chrono::{DateTime, Utc};
+use clap::Parser;
+use rumpus_benchmark::{
+    camera::CameraModel,
+    cli::parse_turbidity,
+    estimator::{FrameInput, HeadingEstimator, estimate_heading},
+    heading::{AdaptiveWindow, HeadingEstimate},
+    io::{MosaicLayout, ray_image_from_mosaic},
+    output::{HeadingMessage, OutputFormat, UdpSink},
+    sky::{LightSourceMode, Sky, SkyModelBackend, SkyTable},
+    systems::{HeadingConventions, InsEnu, yaw_from_heading_deg},
+};
+use serde::Serialize;
+use sguaba::engineering::Orientation;
+use std::{error::Error, net::SocketAddr, path::PathBuf, time::Instant};
+use uom::{
+    ConstZero,
+    si::{
+        angle::degree,
+        f64::{Angle, Length},
+        length::{micron, millimeter},
+    },
+};
+
+const FOCAL_LENGTH_MM: f64 = 8.0;
+
+/// How long to wait for the camera to deliver a frame.
+const BUFFER_TIMEOUT_US: u64 = 2_000_000;
+
+/// Number of buffers queued on the camera stream.
+const STREAM_BUFFERS: usize = 4;
+
+/// Frames missed in a row after which the camera is taken to be gone.
+const MAX_CONSECUTIVE_TIMEOUTS: usize = 5;
+
+fn main() -> Result<(), Box<dyn Error + 'static>> {
+    let config = Cli::parse();
+
+    // Setup the camera and start streaming.
+    Aravis::initialize()?;
+    let camera = aravis::Camera::new(config.camera_id.as_deref())?;
+    if let Some(exposure_us) = config.exposure_us {
+        camera.set_exposure_time(exposure_us)?;
+    }
+    let (_, _, width, height) = camera.region()?;
+    let stream = camera.create_stream()?;
+    let payload = camera.payload()? as usize;
+    for _ in 0..STREAM_BUFFERS {
+        stream.push_buffer(aravis::Buffer::new_allocate(payload));
+    }
+    camera.start_acquisition()?;
+
+    // Setup camera model.
+    let focal_length = Length::new::<millimeter>(FOCAL_LENGTH_MM);
+    let pixel_size = Length::new::<micron>(3.45);
+    let camera_model = CameraModel::new(
+        focal_length,
+        pixel_size * 2.0,
+        height as usize / 2,
+        width as usize / 2,
+    );
+
+    // Setup sky model used to simulate candidates.
+    let mut sky = Sky::new(config.turbidity)
+        .with_backend(config.sky_model)
+        .with_light_source(config.light_source);
+    if let Some(sky_table) = &config.sky_table {
+        sky = sky.with_table(SkyTable::read_csv(sky_table)?);
+    }
+
+    let estimator = HeadingEstimator::new(camera_model, sky);
+
+    let udp_sink = match config.udp {
+        Some(addr) => Some(UdpSink::new(addr, config.udp_format)?),
+        None => None,
+    };
+
+    // There is no INS in the loop, so the camera is assumed level and candidates are offsets
+    // from a fixed reference heading.
+    let reference_yaw = Angle::new::<degree>(-config.reference_heading_deg);
+    let car_in_ins_enu: Orientation<InsEnu> = Orientation::tait_bryan_builder()
+        .yaw(reference_yaw)
+        .pitch(Angle::ZERO)
+        .roll(Angle::ZERO)
+        .build();
+    let position = InsEnu::position_from_inspva(config.lat, config.lon, config.height);
+
+    // Search the whole horizon until there is a confident estimate to track.
+    let mut search = AdaptiveWindow::new(180.0, config.window_deg)
+        .with_yaw_change_gain(0.0)
+        .with_min_confidence(config.min_confidence);
+
+    let mut frame_index = 0;
+    let mut timeouts = 0;
+    while config
+        .max_frames
+        .is_none_or(|max_frames| frame_index < max_frames)
+    {
+        let Some(buffer) = stream.timeout_pop_buffer(BUFFER_TIMEOUT_US) else {
+            timeouts += 1;
+            eprintln!("timed out waiting for a frame ({timeouts} in a row)");
+            if timeouts >= MAX_CONSECUTIVE_TIMEOUTS {
+                camera.stop_acquisition()?;
+                return Err(format!("no frame from the camera after {timeouts} timeouts").into());
+            }
+            continue;
+        };
+        timeouts = 0;
+
+        let t0 = Instant::now();
+        let time = Utc::now();
+        let image = ray_image_from_mosaic(
+            width as usize,
+            height as usize,
+            buffer.data(),
+            &config.mosaic_layout,
+        );
+        stream.push_buffer(buffer);
+        let image = match image {
+            Ok(image) => image,
+            Err(e) => {
+                eprintln!("failed to decode frame {frame_index}: {e}");
+                continue;
+            }
+        };
+
+        let yaw_offsets = search.window(0.).offsets(config.resolution_deg);
+        let frame = FrameInput {
+            frame_index,
+            image: &image,
+            image_circle: None,
+            position: &position,
+            time,
+            car_in_ins_enu,
+            yaw_smear: Angle::ZERO,
+        };
+        let estimate = estimate_heading(&estimator.sweep(&frame, &yaw_offsets));
+        search.update(0., estimate.as_ref());
+
+        let record = LiveRecord::new(frame_index, time, &config, estimate.as_ref(), t0);
+        println!("{}", serde_json::to_string(&record)?);
+        if let Some((udp_sink, estimate)) = udp_sink.as_ref().zip(estimate)
+            && let Err(e) = udp_sink.send(&HeadingMessage {
+                frame_index,
+                time,
+                heading_deg: estimated_heading_deg(&config, &estimate),
+                confidence: estimate.confidence(),
+            })
+        {
+            eprintln!("failed to send heading for frame {frame_index}: {e}");
+        }
+
+        frame_index += 1;
+    }
+
+    camera.stop_acquisition()?;
+    Ok(())
+}
+
+/// Compass heading of an estimate, relative to the configured reference heading.
+fn estimated_heading_deg(config: &Cli, estimate: &HeadingEstimate) -> f64 {
+    HeadingConventions::from_ins_yaw(
+        yaw_from_heading_deg(config.reference_heading_deg),
+        estimate.yaw_offset_deg,
+    )
+    .true_heading_deg
+}
+
+#[derive(Serialize)]
+struct LiveRecord {
+    frame_index: usize,
+    time: DateTime<Utc>,
+    /// Clockwise from true north.
+    heading_deg: Option<f64>,
+    weighted_rmse: Option<f64>,
+    confidence: Option<f64>,
+    latency_ms: u128,
+}
+
+impl LiveRecord {
+    fn new(
+        frame_index: usize,
+        time: DateTime<Utc>,
+        config: &Cli,
+        estimate: Option<&HeadingEstimate>,
+        t0: Instant,
+    ) -> Self {
+        Self {
+            frame_index,
+            time,
+            heading_deg: estimate.map(|e| estimated_heading_deg(config, e)),
+            weighted_rmse: estimate.map(|e| e.cost),
+            confidence: estimate.map(|e| e.confidence()),
+            latency_ms: t0.elapsed().as_millis(),
+        }
+    }
+}
+
+/// Estimates heading from a live polarization camera and streams the results.
+#[derive(Parser)]
+struct Cli {
+    /// Latitude of the camera in degrees.
+    #[arg(long, allow_negative_numbers = true)]
+    lat: f64,
+
+    /// Longitude of the camera in degrees.
+    #[arg(long, allow_negative_numbers = true)]
+    lon: f64,
+
+    /// Height of the camera above the ellipsoid in meters.
+    #[arg(long, default_value_t = 0.0, allow_negative_numbers = true)]
+    height: f64,
+
+    /// GenICam device id, otherwise the first camera found.
+    #[arg(long)]
+    camera_id: Option<String>,
+
+    #[arg(long)]
+    exposure_us: Option<f64>,
+
+    #[arg(short, long)]
+    max_frames: Option<usize>,
+
+    /// Also send each heading estimate as a UDP datagram to this address.
+    #[arg(long)]
+    udp: Option<SocketAddr>,
+
+    /// Format of the heading datagrams.
+    #[arg(long, value_enum, default_value_t = OutputFormat::Nmea)]
+    udp_format: OutputFormat,
+
+    /// Heading the camera is roughly pointed at, clockwise from true north.
+    #[arg(long, default_value_t = 0.0)]
+    reference_heading_deg: f64,
+
+    #[arg(short, long, default_value_t = 0.5)]
+    resolution_deg: f64,
+
+    /// Half width of the yaw search around the previous estimate.
+    #[arg(long, default_value_t = 5.0)]
+    window_deg: f64,
+
+    /// Match confidence below which the search falls back to the whole horizon.
+    #[arg(long, default_value_t = 0.0)]
+    min_confidence: f64,
+
+    /// Ratio of total to Rayleigh optical depth used by the sky model.
+    #[arg(long, default_value_t = 1.0, value_parser = parse_turbidity)]
+    turbidity: f64,
+
+    /// Polarization model used to simulate the sky.
+    #[arg(long, value_enum, default_value_t = SkyModelBackend::Rayleigh)]
+    sky_model: SkyModelBackend,
+
+    /// Lookup table for the empirical sky model.
+    #[arg(long, required_if_eq("sky_model", "empirical"))]
+    sky_table: Option<PathBuf>,
+
+    /// Body whose scattered light polarizes the sky.
+    #[arg(long, value_enum, default_value_t = LightSourceMode::Sun)]
+    light_source: LightSourceMode,
+
+    /// Polarizer angles in degrees of each 2x2 super-pixel, row by row.
+    #[arg(long, default_value_t = MosaicLayout::default())]
+    mosaic_layout: MosaicLayout,
+}
@@ -0,0 +1,78 @@
+use clap::Parser;
+use rumpus_benchmark::{integrity::DatasetManifest, pipeline::Dataset};
+use std::path::PathBuf;
+
+/// Writes the checksums and frame counts of a dataset to its `checksums.json`, which every run
+/// on it then checks first, or checks a dataset against them with `--check`.
+fn main() {
+    let config = Cli::parse();
+    let dataset = Dataset::new(&config.dataset_path);
+    let jobs = config
+        .jobs
+        .unwrap_or_else(|| std::thread::available_parallelism().map_or(1, usize::from));
+
+    if config.check {
+        let mut manifest = DatasetManifest::read(&dataset).unwrap_or_else(|e| {
+            eprintln!("{e}; write it with `manifest` first");
+            std::process::exit(1);
+        });
+        let problems = manifest.problems(&dataset, jobs, true);
+        if problems.is_empty() {
+            // Runs then only need the modification times to tell the dataset is unchanged.
+            if manifest.refresh_modified(&dataset) > 0 {
+                manifest.write(&dataset).unwrap();
+            }
+            println!(
+                "{} matches its {} files from {}",
+                dataset.path().display(),
+                manifest.files.len(),
+                manifest.created
+            );
+            return;
+        }
+        for problem in &problems {
+            eprintln!("error: {problem}");
+        }
+        std::process::exit(1);
+    }
+
+    let path = DatasetManifest::path(&dataset);
+    if path.exists() && !config.force {
+        eprintln!(
+            "{} already exists; check the dataset against it with --check, or replace it with --force",
+            path.display()
+        );
+        std::process::exit(1);
+    }
+    let manifest = DatasetManifest::build(&dataset, jobs).unwrap_or_else(|e| {
+        eprintln!("{e}");
+        std::process::exit(1);
+    });
+    manifest.write(&dataset).unwrap();
+    let bytes: u64 = manifest.files.values().map(|file| file.bytes).sum();
+    println!(
+        "{} files, {bytes} bytes, {} images",
+        manifest.files.len(),
+        manifest.images
+    );
+    for (log, records) in &manifest.records {
+        println!("  {}: {records} records", log.display());
+    }
+}
+
+#[derive(Parser)]
+struct Cli {
+    dataset_path: PathBuf,
+
+    /// Check the dataset against its checksums instead of writing them, hashing every file.
+    #[arg(long)]
+    check: bool,
+
+    /// Replace existing checksums, such as after deliberately changing the dataset.
+    #[arg(long, conflicts_with = "check")]
+    force: bool,
+
+    /// Files checksummed at once, instead of one per core.
+    #[arg(short, long)]
+    jobs: Option<usize>,
+}
@@ -0,0 +1,543 @@
+use clap::Parser;
+use rumpus_benchmark::{
+    batch::{
+        CompletedRuns, MetricSummary, code_version, dataset_version, experiment_executable,
+        read_frame_counts, read_metrics, run_completed, run_experiment, run_hash, run_parallel,
+    },
+    pipeline::ResultWriter,
+};
+use serde::Deserialize;
+use std::{
+    collections::{BTreeMap, HashMap},
+    path::{Path, PathBuf},
+};
+
+/// Runs a matrix of experiments described by a YAML manifest and reports on them together.
+///
+/// Every dataset of an experiment is run with every combination of its `matrix` options, at
+/// most `jobs` at once, and failed runs are tried again up to `retries` times:
+///
+/// ```yaml
+/// jobs: 4
+/// retries: 1
+/// experiments:
+///   - name: sweep
+///     experiment: test_pattern_match
+///     datasets: [data/drive_1, data/drive_2]
+///     args: [--window-deg, "5"]
+///     matrix:
+///       resolution-deg: [0.1, 0.05]
+///       sky-model: [rayleigh, berry]
+///       adaptive-window: [true, false]
+/// ```
+///
+/// Datasets are relative to the manifest. Matrix values of `true` and `false` turn a flag on
+/// or leave it out.
+///
+/// Runs that completed before with the same experiment binary, dataset and arguments are
+/// reported from their earlier results instead of being run again, unless `--force` is given
+/// or the dataset has changed since. They are recorded in `completed_runs.json` next to the
+/// manifest.
+fn main() {
+    let config = Cli::parse();
+    let manifest = Manifest::read(&config.manifest).unwrap_or_else(|e| {
+        eprintln!("{e}");
+        std::process::exit(1);
+    });
+    let base = config.manifest.parent().unwrap_or(Path::new("."));
+    let runs = manifest.plan(base);
+    if runs.is_empty() {
+        eprintln!("{} plans no runs", config.manifest.display());
+        std::process::exit(1);
+    }
+    if config.dry_run {
+        for run in &runs {
+            println!("{}", run.describe());
+        }
+        println!("{} runs", runs.len());
+        return;
+    }
+
+    // Check every experiment was built before starting any of them.
+    let mut executables = HashMap::new();
+    for run in &runs {
+        if !executables.contains_key(&run.experiment) {
+            let executable = experiment_executable(&run.experiment)
+                .and_then(|executable| Ok((code_version(&executable)?, executable)))
+                .unwrap_or_else(|e| {
+                    eprintln!("{e}");
+                    std::process::exit(1);
+                });
+            executables.insert(run.experiment.clone(), executable);
+        }
+    }
+    // Runs on a dataset that changed since they completed run again.
+    let mut dataset_versions = HashMap::new();
+    for run in &runs {
+        if !dataset_versions.contains_key(&run.dataset) {
+            let version = dataset_version(&run.dataset).unwrap_or_else(|e| {
+                eprintln!("{e}");
+                std::process::exit(1);
+            });
+            dataset_versions.insert(run.dataset.clone(), version);
+        }
+    }
+    let hashes: Vec<_> = runs
+        .iter()
+        .map(|run| {
+            let (version, _) = &executables[&run.experiment];
+            let dataset_version = &dataset_versions[&run.dataset];
+            run_hash(
+                version,
+                &run.experiment,
+                &run.dataset,
+                dataset_version,
+                &run.args(),
+            )
+        })
+        .collect();
+    let completed_path = base.join("completed_runs.json");
+    let mut completed = CompletedRuns::read(&completed_path).unwrap_or_else(|e| {
+        eprintln!("{e}");
+        std::process::exit(1);
+    });
+
+    let results = ResultWriter::create().unwrap();
+    std::fs::copy(&config.manifest, results.dir().join("manifest.yaml")).unwrap();
+    let jobs = config.jobs.or(manifest.jobs).unwrap_or(1);
+    let outcomes = run_parallel(&runs, jobs, |index, run| {
+        if let Some(results_dir) = completed.get(&hashes[index]).filter(|_| !config.force) {
+            println!(
+                "{} is unchanged since {}",
+                run.describe(),
+                results_dir.display()
+            );
+            return Outcome {
+                attempts: 0,
+                success: true,
+                reused: true,
+                results_dir: Some(results_dir.to_path_buf()),
+            };
+        }
+        let (_, executable) = &executables[&run.experiment];
+        let run_dir = results.dir().join(run.dir_name());
+        let mut attempts = 0;
+        loop {
+            attempts += 1;
+            println!("{} (attempt {attempts})", run.describe());
+            let (success, results_dir) =
+                run_experiment(executable, &run.dataset, &run.args(), &run_dir);
+            if success || attempts > manifest.retries {
+                if !success {
+                    eprintln!("{} failed after {attempts} attempts", run.describe());
+                }
+                return Outcome {
+                    attempts,
+                    success,
+                    reused: false,
+                    results_dir,
+                };
+            }
+        }
+    });
+
+    for (hash, outcome) in hashes.iter().zip(&outcomes) {
+        if let Some(results_dir) = &outcome.results_dir
+            && outcome.success
+            && !outcome.reused
+            && run_completed(results_dir)
+        {
+            completed.insert(hash.clone(), results_dir);
+        }
+    }
+    if let Err(e) = completed.write(&completed_path) {
+        eprintln!("{e}");
+    }
+
+    let mut run_writer = results.csv("report.csv").unwrap();
+    let mut configuration_writer = results.csv("configurations.csv").unwrap();
+    let mut configurations: BTreeMap<(String, String), Configuration> = BTreeMap::new();
+    let mut reports = Vec::new();
+    for ((run, outcome), hash) in runs.iter().zip(&outcomes).zip(&hashes) {
+        let frames = outcome
+            .results_dir
+            .as_deref()
+            .map(|dir| read_metrics(&dir.join(&config.results_file)))
+            .unwrap_or_default();
+        let (frames_processed, frames_skipped) = outcome
+            .results_dir
+            .as_deref()
+            .map(read_frame_counts)
+            .unwrap_or_default();
+        let report = RunReport {
+            name: run.name.clone(),
+            experiment: run.experiment.clone(),
+            dataset: run.dataset.display().to_string(),
+            parameters: run.parameters(),
+            config_hash: hash.clone(),
+            attempts: outcome.attempts,
+            success: outcome.success,
+            reused: outcome.reused,
+            results_dir: outcome.results_dir.clone(),
+            frames_processed,
+            frames_skipped,
+            metrics: MetricSummary::all(&frames),
+        };
+        for record in report.metric_records() {
+            let _ = run_writer.serialize(record);
+        }
+
+        // The same experiment and parameters are pooled over every dataset.
+        let configuration = configurations
+            .entry((run.name.clone(), run.parameters()))
+            .or_default();
+        configuration.runs += 1;
+        configuration.failed += usize::from(!outcome.success);
+        configuration.frames_processed += frames_processed;
+        for (metric, values) in frames {
+            configuration
+                .values
+                .entry(metric)
+                .or_default()
+                .extend(values);
+        }
+        reports.push(report);
+    }
+
+    let mut summaries = Vec::new();
+    for ((name, parameters), configuration) in &configurations {
+        let summary = ConfigurationSummary {
+            name: name.clone(),
+            parameters: parameters.clone(),
+            runs: configuration.runs,
+            failed: configuration.failed,
+            frames_processed: configuration.frames_processed,
+            metrics: MetricSummary::all(&configuration.values),
+        };
+        print_configuration(&summary);
+        for metric in &summary.metrics {
+            let _ = configuration_writer.serialize(ConfigurationRecord {
+                name,
+                parameters,
+                runs: summary.runs,
+                failed: summary.failed,
+                frames_processed: summary.frames_processed,
+                metric: metric.metric,
+                frames: metric.frames,
+                mean: metric.mean,
+                median: metric.median,
+            });
+        }
+        summaries.push(summary);
+    }
+    run_writer.flush().unwrap();
+    configuration_writer.flush().unwrap();
+
+    let file = std::fs::File::create(results.dir().join("report.json")).unwrap();
+    serde_json::to_writer_pretty(
+        file,
+        &Report {
+            runs: reports,
+            configurations: summaries,
+        },
+    )
+    .unwrap();
+
+    let failed = outcomes.iter().filter(|outcome| !outcome.success).count();
+    if failed > 0 {
+        eprintln!("{failed} of {} runs failed", outcomes.len());
+        std::process::exit(1);
+    }
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct Manifest {
+    /// Runs at once, unless given on the command line.
+    #[serde(default)]
+    jobs: Option<usize>,
+    /// Times a failed run is tried again.
+    #[serde(default)]
+    retries: usize,
+    experiments: Vec<ExperimentSpec>,
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct ExperimentSpec {
+    /// Name in the report, which defaults to the experiment.
+    #[serde(default)]
+    name: Option<String>,
+    /// Experiment binary, such as `test_pattern_match`.
+    experiment: String,
+    datasets: Vec<PathBuf>,
+    /// Options passed to every run.
+    #[serde(default)]
+    args: Vec<String>,
+    /// Values to try of each option, named without the leading dashes.
+    #[serde(default)]
+    matrix: BTreeMap<String, Vec<serde_yaml::Value>>,
+}
+
+impl Manifest {
+    fn read(path: &Path) -> Result<Self, String> {
+        let contents = std::fs::read_to_string(path)
+            .map_err(|e| format!("cannot read {}: {e}", path.display()))?;
+        let manifest: Self = serde_yaml::from_str(&contents)
+            .map_err(|e| format!("invalid manifest {}: {e}", path.display()))?;
+        for experiment in &manifest.experiments {
+            for (option, values) in &experiment.matrix {
+                if let Some(value) = values.iter().find(|value| option_value(value).is_none()) {
+                    return Err(format!(
+                        "{}: matrix option {option} has a value that is not a string, number or boolean: {value:?}",
+                        path.display()
+                    ));
+                }
+            }
+        }
+        Ok(manifest)
+    }
+
+    /// Every run of every experiment, with datasets resolved against `base`.
+    fn plan(&self, base: &Path) -> Vec<PlannedRun> {
+        let mut runs = Vec::new();
+        for experiment in &self.experiments {
+            let name = experiment
+                .name
+                .clone()
+                .unwrap_or_else(|| experiment.experiment.clone());
+            for dataset in &experiment.datasets {
+                let dataset = base.join(dataset);
+                let dataset = std::fs::canonicalize(&dataset).unwrap_or(dataset);
+                for options in matrix_combinations(&experiment.matrix) {
+                    runs.push(PlannedRun {
+                        index: runs.len(),
+                        name: name.clone(),
+                        experiment: experiment.experiment.clone(),
+                        dataset: dataset.clone(),
+                        fixed_args: experiment.args.clone(),
+                        options,
+                    });
+                }
+            }
+        }
+        runs
+    }
+}
+
+/// Text of a matrix value on the command line.
+fn option_value(value: &serde_yaml::Value) -> Option<String> {
+    match value {
+        serde_yaml::Value::String(value) => Some(value.clone()),
+        serde_yaml::Value::Number(value) => Some(value.to_string()),
+        serde_yaml::Value::Bool(value) => Some(value.to_string()),
+        _ => None,
+    }
+}
+
+/// Every combination of one value per option, which is a single empty one without options.
+fn matrix_combinations(
+    matrix: &BTreeMap<String, Vec<serde_yaml::Value>>,
+) -> Vec<Vec<(String, String)>> {
+    matrix
+        .iter()
+        .fold(vec![Vec::new()], |combinations, (option, values)| {
+            combinations
+                .iter()
+                .flat_map(|combination| {
+                    values.iter().filter_map(option_value).map(|value| {
+                        let mut combination = combination.clone();
+                        combination.push((option.clone(), value));
+                        combination
+                    })
+                })
+                .collect()
+        })
+}
+
+struct PlannedRun {
+    index: usize,
+    name: String,
+    experiment: String,
+    dataset: PathBuf,
+    fixed_args: Vec<String>,
+    /// Matrix option and value of this run.
+    options: Vec<(String, String)>,
+}
+
+impl PlannedRun {
+    fn args(&self) -> Vec<String> {
+        let mut args = self.fixed_args.clone();
+        for (option, value) in &self.options {
+            match value.as_str() {
+                "true" => args.push(format!("--{option}")),
+                "false" => {}
+                _ => args.extend([format!("--{option}"), value.clone()]),
+            }
+        }
+        args
+    }
+
+    fn parameters(&self) -> String {
+        self.options
+            .iter()
+            .map(|(option, value)| format!("{option}={value}"))
+            .collect::<Vec<_>>()
+            .join(" ")
+    }
+
+    fn dataset_name(&self) -> String {
+        self.dataset.file_name().map_or_else(
+            || "dataset".to_string(),
+            |name| name.to_string_lossy().to_string(),
+        )
+    }
+
+    fn dir_name(&self) -> String {
+        format!("{:03}_{}_{}", self.index, self.name, self.dataset_name())
+    }
+
+    fn describe(&self) -> String {
+        format!(
+            "{:03} {} on {} {}",
+            self.index,
+            self.name,
+            self.dataset_name(),
+            self.parameters()
+        )
+    }
+}
+
+struct Outcome {
+    attempts: usize,
+    success: bool,
+    /// Whether the results are those of an earlier invocation.
+    reused: bool,
+    results_dir: Option<PathBuf>,
+}
+
+/// Runs of the same experiment and parameters, pooled over their datasets.
+#[derive(Default)]
+struct Configuration {
+    runs: usize,
+    failed: usize,
+    frames_processed: usize,
+    values: HashMap<&'static str, Vec<f64>>,
+}
+
+fn print_configuration(summary: &ConfigurationSummary) {
+    println!(
+        "{} {}: {} runs, {} failed, {} frames processed",
+        summary.name, summary.parameters, summary.runs, summary.failed, summary.frames_processed
+    );
+    for metric in &summary.metrics {
+        println!(
+            "  {}: {} frames, mean {:.4}, median {:.4}",
+            metric.metric, metric.frames, metric.mean, metric.median
+        );
+    }
+}
+
+#[derive(Parser)]
+struct Cli {
+    /// YAML manifest of the experiments to run.
+    manifest: PathBuf,
+
+    /// Runs at once, instead of the manifest's `jobs`.
+    #[arg(short, long)]
+    jobs: Option<usize>,
+
+    /// Per-frame results file inside each run directory.
+    #[arg(long, default_value = "results.csv")]
+    results_file: String,
+
+    /// Run again even configurations that completed before.
+    #[arg(long)]
+    force: bool,
+
+    /// List the planned runs without starting them.
+    #[arg(long)]
+    dry_run: bool,
+}
+
+#[derive(serde::Serialize)]
+struct RunReport {
+    name: String,
+    experiment: String,
+    dataset: String,
+    parameters: String,
+    config_hash: String,
+    attempts: usize,
+    success: bool,
+    reused: bool,
+    results_dir: Option<PathBuf>,
+    frames_processed: usize,
+    frames_skipped: usize,
+    metrics: Vec<MetricSummary>,
+}
+
+impl RunReport {
+    fn metric_records(&self) -> impl Iterator<Item = RunRecord<'_>> {
+        self.metrics.iter().map(|metric| RunRecord {
+            name: &self.name,
+            dataset: &self.dataset,
+            parameters: &self.parameters,
+            config_hash: &self.config_hash,
+            attempts: self.attempts,
+            success: self.success,
+            reused: self.reused,
+            frames_processed: self.frames_processed,
+            frames_skipped: self.frames_skipped,
+            metric: metric.metric,
+            frames: metric.frames,
+            mean: metric.mean,
+            median: metric.median,
+        })
+    }
+}
+
+#[derive(serde::Serialize)]
+struct RunRecord<'a> {
+    name: &'a str,
+    dataset: &'a str,
+    parameters: &'a str,
+    config_hash: &'a str,
+    attempts: usize,
+    success: bool,
+    reused: bool,
+    frames_processed: usize,
+    frames_skipped: usize,
+    metric: &'static str,
+    frames: usize,
+    mean: f64,
+    median: f64,
+}
+
+#[derive(serde::Serialize)]
+struct ConfigurationSummary {
+    name: String,
+    parameters: String,
+    runs: usize,
+    failed: usize,
+    frames_processed: usize,
+    metrics: Vec<MetricSummary>,
+}
+
+#[derive(serde::Serialize)]
+struct ConfigurationRecord<'a> {
+    name: &'a str,
+    parameters: &'a str,
+    runs: usize,
+    failed: usize,
+    frames_processed: usize,
+    metric: &'static str,
+    frames: usize,
+    mean: f64,
+    median: f64,
+}
+
+#[derive(serde::Serialize)]
+struct Report {
+    runs: Vec<RunReport>,
+    configurations: Vec<ConfigurationSummary>,
+}
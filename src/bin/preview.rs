@@ -0,0 +1,450 @@
+use clap::{Parser, ValueEnum};
+use minifb::{Key, KeyRepeat, Window, WindowOptions};
+use rumpus::{
+    image::{Jet, RayImage},
+    ray::SensorFrame,
+};
+use rumpus_benchmark::{
+    camera::CameraModel,
+    cli::{DatasetArgs, SkyArgs, interrupt_on_ctrl_c},
+    ephemeris::CelestialPosition,
+    estimator::{Candidate, FrameInput, HeadingEstimator, estimate_heading},
+    exposure::{ExposureGate, ExposureQuality},
+    glare::{GlareConfig, GlareStrategy},
+    heading::SearchWindow,
+    image_circle::Circle,
+    io::ImageReader,
+    overlay::{Overlay, SUN_RGBA},
+    pipeline::{FrameContext, FrameProcessor, FrameSkip},
+    run::SkipReason,
+    systems::{HeadingConventions, InsEnu, heading_from_yaw_deg},
+    utils::{downsample, measured_to_global},
+};
+use sguaba::engineering::Orientation;
+use std::sync::{
+    Arc,
+    atomic::{AtomicBool, Ordering},
+};
+use uom::si::{
+    angle::degree,
+    f64::{Angle, Length},
+    length::{micron, millimeter},
+};
+
+const FOCAL_LENGTH_MM: f64 = 8.0;
+
+/// Height of the cost curve below the images, in pixels.
+const COST_ROWS: usize = 96;
+
+const COST_RGBA: [u8; 4] = [200, 30, 30, 255];
+const AXIS_RGBA: [u8; 4] = [255, 255, 255, 255];
+const GLARE_RGBA: [u8; 4] = [SUN_RGBA[0], SUN_RGBA[1], SUN_RGBA[2], 96];
+
+/// Step of the mounting angles per key press.
+const MOUNT_STEP_DEG: f64 = 0.1;
+
+/// Step of the bad exposure limit per key press, and where it starts if unset.
+const BAD_EXPOSURE_STEP: f64 = 0.05;
+const DEFAULT_MAX_BAD_EXPOSURE: f64 = 0.5;
+
+/// Step of the AoP histogram distance limit per key press, and where it starts if unset.
+const AOP_EMD_STEP_DEG: f64 = 1.;
+const DEFAULT_MAX_AOP_EMD_DEG: f64 = 10.;
+
+/// Runs the pattern match at reduced resolution with a coarse search and shows each frame in a
+/// window, so the search, glare, mounting and threshold parameters can be tuned before a full
+/// run.
+///
+/// Space pauses on a frame, up and down widen or narrow the search window, left and right
+/// shrink or grow the glare radius, M toggles glare masking, W and S tilt the camera mounting
+/// in pitch, A and D in roll, `[` and `]` lower or raise the bad exposure limit, `-` and `=`
+/// the AoP histogram distance limit, and Escape stops. Frames over either limit are marked.
+/// The options matching the final parameters are printed on exit.
+fn main() {
+    let config = Cli::parse();
+    let interrupted = interrupt_on_ctrl_c();
+    let pipeline = config
+        .dataset
+        .pipeline()
+        .unwrap()
+        .with_interrupt(Arc::clone(&interrupted));
+    let image_reader = config.dataset.prefetching_image_reader(&pipeline).unwrap();
+    let focal_length = Length::new::<millimeter>(FOCAL_LENGTH_MM);
+    let pixel_size = Length::new::<micron>(3.45);
+    let camera_model = CameraModel::for_mosaic(focal_length, pixel_size, 2048, 2448, &image_reader)
+        .with_principal_point(config.dataset.principal_point(&image_reader))
+        .downsampled(config.downsample);
+
+    let (cols, rows) = (2 * camera_model.cols(), camera_model.rows() + COST_ROWS);
+    let mut window = Window::new("rumpus preview", cols, rows, WindowOptions::default()).unwrap();
+    window.set_target_fps(30);
+
+    let glare = config.sky.glare();
+    let mounting_deg = (
+        config.dataset.mount_pitch_deg,
+        config.dataset.mount_roll_deg,
+    );
+    let mut processor = PreviewProcessor {
+        estimator: HeadingEstimator::new(camera_model, config.sky.sky().unwrap())
+            .with_glare(glare)
+            .with_aop_emd(true),
+        image_reader,
+        downsample: config.downsample,
+        resolution_deg: config.resolution_deg,
+        window_deg: config.window_deg,
+        glare,
+        mounting_deg,
+        pipeline_mounting_deg: mounting_deg,
+        exposure_gate: config.dataset.exposure_gate(),
+        max_bad_exposure: config.dataset.max_bad_exposure,
+        max_aop_emd_deg: config.max_aop_emd_deg,
+        paused: false,
+        interrupted,
+        window,
+        buffer: vec![0; cols * rows],
+    };
+    let summary = pipeline.run(&mut processor);
+    summary.print();
+    let mut options = format!(
+        "--downsample {} --resolution-deg {} --window-deg {} --glare {} --glare-radius-deg {} \
+         --mount-pitch-deg {:.1} --mount-roll-deg {:.1}",
+        processor.downsample,
+        processor.resolution_deg,
+        processor.window_deg,
+        processor
+            .glare
+            .strategy
+            .to_possible_value()
+            .expect("no strategy is skipped")
+            .get_name(),
+        processor.glare.radius_deg,
+        processor.mounting_deg.0,
+        processor.mounting_deg.1,
+    );
+    if let Some(max_bad_exposure) = processor.max_bad_exposure {
+        options += &format!(" --max-bad-exposure {max_bad_exposure:.2}");
+    }
+    if let Some(max_aop_emd_deg) = processor.max_aop_emd_deg {
+        options += &format!(" --max-aop-emd-deg {max_aop_emd_deg:.0}");
+    }
+    println!("{options}");
+}
+
+struct PreviewProcessor {
+    estimator: HeadingEstimator,
+    image_reader: ImageReader,
+    downsample: usize,
+    resolution_deg: f64,
+    window_deg: f64,
+    glare: GlareConfig,
+    /// Pitch and roll of the camera on the car, of which the pipeline already tilted the INS
+    /// attitude by `pipeline_mounting_deg`.
+    mounting_deg: (f64, f64),
+    pipeline_mounting_deg: (f64, f64),
+    exposure_gate: ExposureGate,
+    max_bad_exposure: Option<f64>,
+    max_aop_emd_deg: Option<f64>,
+    paused: bool,
+    /// Set when the window is closed, to stop the run.
+    interrupted: Arc<AtomicBool>,
+    window: Window,
+    /// Pixels of the window as `0RGB`.
+    buffer: Vec<u32>,
+}
+
+impl PreviewProcessor {
+    /// Applies the keys pressed since the last update, and returns whether the frame needs
+    /// estimating again.
+    fn handle_keys(&mut self) -> bool {
+        let mut changed = false;
+        for key in self.window.get_keys_pressed(KeyRepeat::Yes) {
+            match key {
+                Key::Space => self.paused = !self.paused,
+                Key::Up => self.window_deg += 1.,
+                Key::Down => self.window_deg = (self.window_deg - 1.).max(1.),
+                Key::Right => self.glare.radius_deg += 1.,
+                Key::Left => self.glare.radius_deg = (self.glare.radius_deg - 1.).max(1.),
+                Key::M => {
+                    self.glare.strategy = match self.glare.strategy {
+                        GlareStrategy::Mask => GlareStrategy::Keep,
+                        _ => GlareStrategy::Mask,
+                    };
+                }
+                Key::W => self.mounting_deg.0 += MOUNT_STEP_DEG,
+                Key::S => self.mounting_deg.0 -= MOUNT_STEP_DEG,
+                Key::D => self.mounting_deg.1 += MOUNT_STEP_DEG,
+                Key::A => self.mounting_deg.1 -= MOUNT_STEP_DEG,
+                Key::RightBracket => {
+                    self.max_bad_exposure = Some(step_limit(
+                        self.max_bad_exposure,
+                        DEFAULT_MAX_BAD_EXPOSURE,
+                        BAD_EXPOSURE_STEP,
+                    ));
+                }
+                Key::LeftBracket => {
+                    self.max_bad_exposure = Some(step_limit(
+                        self.max_bad_exposure,
+                        DEFAULT_MAX_BAD_EXPOSURE,
+                        -BAD_EXPOSURE_STEP,
+                    ));
+                }
+                Key::Equal => {
+                    self.max_aop_emd_deg = Some(step_limit(
+                        self.max_aop_emd_deg,
+                        DEFAULT_MAX_AOP_EMD_DEG,
+                        AOP_EMD_STEP_DEG,
+                    ));
+                }
+                Key::Minus => {
+                    self.max_aop_emd_deg = Some(step_limit(
+                        self.max_aop_emd_deg,
+                        DEFAULT_MAX_AOP_EMD_DEG,
+                        -AOP_EMD_STEP_DEG,
+                    ));
+                }
+                _ => continue,
+            }
+            changed |= key != Key::Space;
+        }
+        if changed {
+            self.estimator = self.estimator.clone().with_glare(self.glare);
+            self.exposure_gate = self
+                .exposure_gate
+                .with_max_bad_fraction(self.max_bad_exposure);
+            println!(
+                "window {:.0} deg, glare {:?} within {:.0} deg, mounting pitch {:.1} roll {:.1} deg",
+                self.window_deg,
+                self.glare.strategy,
+                self.glare.radius_deg,
+                self.mounting_deg.0,
+                self.mounting_deg.1
+            );
+        }
+        changed
+    }
+
+    /// INS attitude of a frame tilted by the mounting tuned so far.
+    fn car_in_ins_enu(&self, frame: &FrameContext) -> Orientation<InsEnu> {
+        let (yaw, pitch, roll) = frame.ins.orientation.to_tait_bryan_angles();
+        Orientation::tait_bryan_builder()
+            .yaw(yaw)
+            .pitch(pitch + Angle::new::<degree>(self.mounting_deg.0 - self.pipeline_mounting_deg.0))
+            .roll(roll + Angle::new::<degree>(self.mounting_deg.1 - self.pipeline_mounting_deg.1))
+            .build()
+    }
+
+    /// Estimates the heading of a frame and draws the measured and simulated sky at the
+    /// estimate side by side, above the cost curve.
+    fn render(
+        &mut self,
+        frame: &FrameContext,
+        image: &RayImage<SensorFrame>,
+        image_circle: Option<Circle>,
+        exposure: &ExposureQuality,
+    ) -> Result<(), FrameSkip> {
+        let camera = self.estimator.camera();
+        let attitude = self.car_in_ins_enu(frame);
+        let input = FrameInput {
+            frame_index: frame.frame_index,
+            image,
+            image_circle,
+            position: &frame.ins.position,
+            time: frame.time,
+            car_in_ins_enu: attitude,
+            yaw_smear: frame.yaw_smear,
+        };
+        let offsets = SearchWindow {
+            center_deg: 0.,
+            half_width_deg: self.window_deg,
+        }
+        .offsets(self.resolution_deg);
+        let candidates = self.estimator.sweep(&input, &offsets);
+        let estimate = estimate_heading(&candidates);
+        let aop_emd_deg = candidates
+            .iter()
+            .min_by(|a, b| a.weighted_rmse.total_cmp(&b.weighted_rmse))
+            .and_then(|best| best.aop_emd_deg);
+        let aop_mismatch = aop_emd_deg
+            .zip(self.max_aop_emd_deg)
+            .is_some_and(|(emd, max_emd)| emd > max_emd);
+
+        let (ins_yaw, pitch, roll) = attitude.to_tait_bryan_angles();
+        let ins_yaw_deg = ins_yaw.get::<degree>();
+        let yaw_offset_deg = estimate.map_or(0., |estimate| estimate.yaw_offset_deg);
+        let car_in_ins_enu: Orientation<InsEnu> = Orientation::tait_bryan_builder()
+            .yaw(ins_yaw + Angle::new::<degree>(yaw_offset_deg))
+            .pitch(pitch)
+            .roll(roll)
+            .build();
+        let measured = measured_to_global(input.image, camera, car_in_ins_enu);
+        let simulated = self
+            .estimator
+            .sky()
+            .simulate_smeared(
+                camera,
+                input.position,
+                car_in_ins_enu,
+                input.time,
+                input.yaw_smear,
+            )
+            .map_err(|e| FrameSkip::new(SkipReason::SimulationFailed, e))?;
+
+        #[allow(clippy::cast_possible_truncation)]
+        let (cols, rows) = (camera.cols() as u32, camera.rows() as u32);
+        let heading_deg = heading_from_yaw_deg(ins_yaw_deg);
+        let estimated_heading_deg = estimate
+            .map(|estimate| HeadingConventions::from_ins_yaw(ins_yaw_deg, estimate.yaw_offset_deg))
+            .map(|conventions| conventions.true_heading_deg);
+        let mut panels = [&measured, &simulated].map(|ray_image| {
+            let mut overlay = Overlay::from_rgb(&ray_image.aop_bytes(&Jet), cols, rows);
+            overlay.draw_heading_comparison(camera, attitude, heading_deg, estimated_heading_deg);
+            overlay
+        });
+        let sun = CelestialPosition::sun(input.position, input.time);
+        if let Some(mask) = self.glare.mask(camera, car_in_ins_enu, &sun) {
+            panels[1].mask(&mask, GLARE_RGBA);
+        }
+        let mut label = match estimate {
+            Some(_) => format!(
+                "FRAME {:04} OFFSET {yaw_offset_deg:.2} DEG",
+                frame.frame_index
+            ),
+            None => format!("FRAME {:04} NO ESTIMATE", frame.frame_index),
+        };
+        if self.exposure_gate.fails(exposure) {
+            label += " BAD EXPOSURE";
+        }
+        if aop_mismatch {
+            label += " AOP MISMATCH";
+        }
+        panels[0].draw_text((8, 8), &label, 1, AXIS_RGBA);
+        panels[1].draw_text(
+            (8, 8),
+            &format!(
+                "WINDOW {:.0} GLARE {:.0} PITCH {:.1} ROLL {:.1}{}",
+                self.window_deg,
+                self.glare.radius_deg,
+                self.mounting_deg.0,
+                self.mounting_deg.1,
+                if self.paused { " PAUSED" } else { "" }
+            ),
+            1,
+            AXIS_RGBA,
+        );
+        let cost = cost_curve(&candidates, yaw_offset_deg, 2 * cols, COST_ROWS);
+
+        // Copy the panels side by side with the cost curve below.
+        let width = 2 * cols as usize;
+        for (i, panel) in panels.iter().enumerate() {
+            for (x, y, pixel) in panel.image().enumerate_pixels() {
+                self.buffer[y as usize * width + i * cols as usize + x as usize] = rgb(pixel.0);
+            }
+        }
+        for (x, y, pixel) in cost.image().enumerate_pixels() {
+            self.buffer[(rows as usize + y as usize) * width + x as usize] = rgb(pixel.0);
+        }
+        self.window
+            .update_with_buffer(&self.buffer, width, rows as usize + COST_ROWS)
+            .map_err(|e| FrameSkip::new(SkipReason::UnwritableResults, e))
+    }
+}
+
+impl FrameProcessor for PreviewProcessor {
+    fn process(&mut self, frame: &FrameContext) -> Result<(), FrameSkip> {
+        let (image, exposure, image_circle) = self
+            .image_reader
+            .read_image_with_exposure(&frame.image_path, self.exposure_gate.dark_level())
+            .map_err(|e| FrameSkip::new(SkipReason::UnreadableImage, e))?;
+        let image = downsample(&image, self.downsample);
+        let image_circle = image_circle.map(|circle| circle.in_rays(self.downsample));
+
+        // While paused the frame is estimated again whenever a parameter changes.
+        let mut changed = true;
+        loop {
+            if changed {
+                self.render(frame, &image, image_circle, &exposure)?;
+            } else {
+                self.window.update();
+            }
+            if !self.window.is_open() || self.window.is_key_down(Key::Escape) {
+                self.interrupted.store(true, Ordering::SeqCst);
+                return Ok(());
+            }
+            changed = self.handle_keys();
+            if !self.paused && !changed {
+                return Ok(());
+            }
+        }
+    }
+}
+
+/// Plots the cost of each candidate against its yaw offset, with the estimate marked.
+fn cost_curve(candidates: &[Candidate], estimate_deg: f64, cols: u32, rows: usize) -> Overlay {
+    #[allow(clippy::cast_possible_truncation)]
+    let rows_u32 = rows as u32;
+    let black = vec![0; (cols * rows_u32 * 3) as usize];
+    let mut overlay = Overlay::from_rgb(&black, cols, rows_u32);
+    let (Some(first), Some(last)) = (candidates.first(), candidates.last()) else {
+        return overlay;
+    };
+    let (low, high) = candidates
+        .iter()
+        .map(|candidate| candidate.weighted_rmse)
+        .filter(|cost| cost.is_finite())
+        .fold((f64::INFINITY, f64::NEG_INFINITY), |(low, high), cost| {
+            (low.min(cost), high.max(cost))
+        });
+    let span_deg = (last.yaw_offset_deg - first.yaw_offset_deg).max(f64::EPSILON);
+    let (width, height) = (f64::from(cols) - 1., f64::from(rows_u32) - 1.);
+    let col = |offset_deg: f64| (offset_deg - first.yaw_offset_deg) / span_deg * width;
+    let row = |cost: f64| height - (cost - low) / (high - low).max(f64::EPSILON) * height;
+
+    overlay.draw_line(
+        (0., col(estimate_deg)),
+        (height, col(estimate_deg)),
+        AXIS_RGBA,
+    );
+    let points: Vec<_> = candidates
+        .iter()
+        .filter(|candidate| candidate.weighted_rmse.is_finite())
+        .map(|candidate| (row(candidate.weighted_rmse), col(candidate.yaw_offset_deg)))
+        .collect();
+    for pair in points.windows(2) {
+        overlay.draw_line(pair[0], pair[1], COST_RGBA);
+    }
+    overlay
+}
+
+fn rgb([r, g, b, _]: [u8; 4]) -> u32 {
+    (u32::from(r) << 16) | (u32::from(g) << 8) | u32::from(b)
+}
+
+#[derive(Parser)]
+struct Cli {
+    #[command(flatten)]
+    dataset: DatasetArgs,
+
+    #[command(flatten)]
+    sky: SkyArgs,
+
+    /// Factor to downsample the images and camera by along each axis.
+    #[arg(long, default_value_t = 2)]
+    downsample: usize,
+
+    #[arg(short, long, default_value_t = 1.0)]
+    resolution_deg: f64,
+
+    /// Half width of the yaw sweep.
+    #[arg(long, default_value_t = 10.0)]
+    window_deg: f64,
+
+    /// Frames whose measured AoP histogram is further than this, in degrees, from the one
+    /// simulated at the best candidate are marked, as `test_pattern_match` flags them.
+    #[arg(long)]
+    max_aop_emd_deg: Option<f64>,
+}
+
+/// Moves an optional limit by `step`, from `default` if unset, without going below zero.
+fn step_limit(limit: Option<f64>, default: f64, step: f64) -> f64 {
+    limit.map_or(default, |limit| (limit + step).max(0.))
+}
@@ -0,0 +1,223 @@
+use chrono::{DateTime, Utc};
+use clap::Parser;
+use futures::{StreamExt, executor::LocalPool, future, task::LocalSpawnExt};
+use r2r::{
+    QosProfile,
+    sensor_msgs::msg::{Image, Imu, NavSatFix},
+    std_msgs::msg::Float64,
+};
+use rumpus_benchmark::{
+    camera::CameraModel,
+    cli::parse_turbidity,
+    estimator::{FrameInput, HeadingEstimator, estimate_heading},
+    heading::AdaptiveWindow,
+    io::{MosaicLayout, ray_image_from_mosaic},
+    sky::{LightSourceMode, Sky, SkyModelBackend, SkyTable},
+    systems::{HeadingConventions, InsConvention, InsEnu},
+};
+use sguaba::{engineering::Orientation, systems::Wgs84};
+use std::{cell::RefCell, error::Error, path::PathBuf, rc::Rc, time::Duration};
+use uom::{
+    ConstZero,
+    si::{
+        angle::degree,
+        f64::{Angle, Length},
+        length::{micron, millimeter},
+    },
+};
+
+const FOCAL_LENGTH_MM: f64 = 8.0;
+
+/// Latest INS state received from the navigation topics.
+#[derive(Default)]
+struct InsState {
+    position: Option<Wgs84>,
+    orientation: Option<Orientation<InsEnu>>,
+}
+
+fn main() -> Result<(), Box<dyn Error + 'static>> {
+    let config = Cli::parse();
+
+    let ctx = r2r::Context::create()?;
+    let mut node = r2r::Node::create(ctx, "rumpus_heading", "")?;
+
+    let image_sub = node.subscribe::<Image>(&config.image_topic, QosProfile::sensor_data())?;
+    let fix_sub = node.subscribe::<NavSatFix>(&config.fix_topic, QosProfile::sensor_data())?;
+    let imu_sub = node.subscribe::<Imu>(&config.imu_topic, QosProfile::sensor_data())?;
+    let heading_pub = node.create_publisher::<Float64>("~/heading_deg", QosProfile::default())?;
+    let confidence_pub = node.create_publisher::<Float64>("~/confidence", QosProfile::default())?;
+
+    // Setup camera model.
+    let focal_length = Length::new::<millimeter>(FOCAL_LENGTH_MM);
+    let pixel_size = Length::new::<micron>(3.45);
+    let camera_model = CameraModel::new(focal_length, pixel_size * 2.0, 1024, 1224);
+
+    // Setup sky model used to simulate candidates.
+    let mut sky = Sky::new(config.turbidity)
+        .with_backend(config.sky_model)
+        .with_light_source(config.light_source);
+    if let Some(sky_table) = &config.sky_table {
+        sky = sky.with_table(SkyTable::read_csv(sky_table)?);
+    }
+
+    let estimator = HeadingEstimator::new(camera_model, sky);
+    let mut search = AdaptiveWindow::new(config.window_deg, config.min_window_deg)
+        .with_min_confidence(config.min_confidence);
+
+    let ins_state = Rc::new(RefCell::new(InsState::default()));
+    let mut pool = LocalPool::new();
+    let spawner = pool.spawner();
+
+    let state = ins_state.clone();
+    spawner.spawn_local(fix_sub.for_each(move |fix| {
+        state.borrow_mut().position = Some(InsEnu::position_from_inspva(
+            fix.latitude,
+            fix.longitude,
+            fix.altitude,
+        ));
+        future::ready(())
+    }))?;
+
+    let state = ins_state.clone();
+    spawner.spawn_local(imu_sub.for_each(move |imu| {
+        state.borrow_mut().orientation = Some(orientation_from_imu(&imu));
+        future::ready(())
+    }))?;
+
+    let state = ins_state;
+    let mut frame_index = 0;
+    spawner.spawn_local(image_sub.for_each(move |image| {
+        let state = state.borrow();
+        let (Some(position), Some(car_in_ins_enu)) = (&state.position, state.orientation) else {
+            eprintln!("dropping image until the INS state is known");
+            return future::ready(());
+        };
+
+        if image.encoding != "mono8" {
+            eprintln!(
+                "dropping {} image, expected a raw mono8 mosaic",
+                image.encoding
+            );
+            return future::ready(());
+        }
+
+        let Some(time) = DateTime::<Utc>::from_timestamp(
+            i64::from(image.header.stamp.sec),
+            image.header.stamp.nanosec,
+        ) else {
+            eprintln!("dropping image with invalid stamp");
+            return future::ready(());
+        };
+
+        let measured = match ray_image_from_mosaic(
+            image.width as usize,
+            image.height as usize,
+            &image.data,
+            &config.mosaic_layout,
+        ) {
+            Ok(measured) => measured,
+            Err(e) => {
+                eprintln!("failed to decode image: {e}");
+                return future::ready(());
+            }
+        };
+
+        let (car_yaw, ..) = car_in_ins_enu.to_tait_bryan_angles();
+        let yaw_offsets = search
+            .window(car_yaw.get::<degree>())
+            .offsets(config.resolution_deg);
+        let frame = FrameInput {
+            frame_index,
+            image: &measured,
+            image_circle: None,
+            position,
+            time,
+            car_in_ins_enu,
+            yaw_smear: Angle::ZERO,
+        };
+        let estimate = estimate_heading(&estimator.sweep(&frame, &yaw_offsets));
+        search.update(car_yaw.get::<degree>(), estimate.as_ref());
+        frame_index += 1;
+
+        if let Some(estimate) = estimate {
+            let heading_deg =
+                HeadingConventions::from_ins_yaw(car_yaw.get::<degree>(), estimate.yaw_offset_deg)
+                    .true_heading_deg;
+            let _ = heading_pub.publish(&Float64 { data: heading_deg });
+            let _ = confidence_pub.publish(&Float64 {
+                data: estimate.confidence(),
+            });
+        }
+
+        future::ready(())
+    }))?;
+
+    loop {
+        node.spin_once(Duration::from_millis(100));
+        pool.run_until_stalled();
+    }
+}
+
+/// Reads a REP-103 orientation of a forward-left-up body in an east-north-up frame.
+fn orientation_from_imu(imu: &Imu) -> Orientation<InsEnu> {
+    let q = &imu.orientation;
+    let yaw = (2. * (q.w * q.z + q.x * q.y)).atan2(1. - 2. * (q.y * q.y + q.z * q.z));
+    let pitch = (2. * (q.w * q.y - q.z * q.x)).clamp(-1., 1.).asin();
+    let roll = (2. * (q.w * q.x + q.y * q.z)).atan2(1. - 2. * (q.x * q.x + q.y * q.y));
+    InsEnu::orientation_from(
+        InsConvention::EnuFlu,
+        yaw.to_degrees(),
+        pitch.to_degrees(),
+        roll.to_degrees(),
+    )
+}
+
+/// Estimates heading from ROS 2 image and INS topics.
+#[derive(Parser)]
+struct Cli {
+    #[arg(long, default_value = "/camera_driver_gv_vis/image_raw")]
+    image_topic: String,
+
+    /// GNSS fix used as the camera position.
+    #[arg(long, default_value = "/novatel/oem7/fix")]
+    fix_topic: String,
+
+    /// Attitude reference whose pitch and roll are trusted and whose yaw is refined.
+    #[arg(long, default_value = "/novatel/oem7/imu/data")]
+    imu_topic: String,
+
+    #[arg(short, long, default_value_t = 0.1)]
+    resolution_deg: f64,
+
+    /// Half width of the yaw search around the INS heading.
+    #[arg(long, default_value_t = 5.0)]
+    window_deg: f64,
+
+    /// Smallest half width of the adaptive search window.
+    #[arg(long, default_value_t = 1.0)]
+    min_window_deg: f64,
+
+    /// Match confidence below which the adaptive search falls back to the full window.
+    #[arg(long, default_value_t = 0.0)]
+    min_confidence: f64,
+
+    /// Ratio of total to Rayleigh optical depth used by the sky model.
+    #[arg(long, default_value_t = 1.0, value_parser = parse_turbidity)]
+    turbidity: f64,
+
+    /// Polarization model used to simulate the sky.
+    #[arg(long, value_enum, default_value_t = SkyModelBackend::Rayleigh)]
+    sky_model: SkyModelBackend,
+
+    /// Lookup table for the empirical sky model.
+    #[arg(long, required_if_eq("sky_model", "empirical"))]
+    sky_table: Option<PathBuf>,
+
+    /// Body whose scattered light polarizes the sky.
+    #[arg(long, value_enum, default_value_t = LightSourceMode::Sun)]
+    light_source: LightSourceMode,
+
+    /// Polarizer angles in degrees of each 2x2 super-pixel, row by row.
+    #[arg(long, default_value_t = MosaicLayout::default())]
+    mosaic_layout: MosaicLayout,
+}
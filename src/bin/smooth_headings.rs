@@ -0,0 +1,131 @@
+use clap::Parser;
+use rumpus_benchmark::{
+    smoothing::{HeadingFilter, wrap_deg},
+    stats::mean,
+};
+use std::path::PathBuf;
+
+/// Smooths the headings of a `test_pattern_match` run and compares the errors of each series.
+fn main() {
+    let config = Cli::parse();
+    let results_path = config.results_dir.join("results.csv");
+    let mut reader = csv::Reader::from_path(&results_path)
+        .unwrap_or_else(|e| panic!("cannot read {}: {e}", results_path.display()));
+    let frames: Vec<FrameRecord> = reader
+        .deserialize()
+        .collect::<Result<_, _>>()
+        .unwrap_or_else(|e| panic!("cannot parse {}: {e}", results_path.display()));
+
+    // Badly exposed frames are treated as having no estimate.
+    let raw: Vec<Option<f64>> = frames
+        .iter()
+        .map(|frame| frame.estimated_yaw_deg.filter(|_| !frame.bad_exposure))
+        .collect();
+    let filter = HeadingFilter::new(config.window).with_causal(config.causal);
+    let median = filter.median(&raw);
+    let hampel = filter.hampel(&raw, config.hampel_sigmas);
+
+    let output = config.results_dir.join(&config.output);
+    let mut writer = csv::Writer::from_path(&output).unwrap();
+    let error = |yaw_deg: Option<f64>, frame: &FrameRecord| {
+        yaw_deg.map(|yaw_deg| wrap_deg(yaw_deg - frame.car_yaw_deg))
+    };
+    let mut records = Vec::new();
+    for (i, frame) in frames.iter().enumerate() {
+        let record = SmoothedRecord {
+            frame_index: frame.frame_index,
+            car_yaw_deg: frame.car_yaw_deg,
+            raw_yaw_deg: raw[i],
+            raw_error_deg: error(raw[i], frame),
+            median_yaw_deg: median[i],
+            median_error_deg: error(median[i], frame),
+            hampel_yaw_deg: hampel[i].map(|(yaw_deg, _)| yaw_deg),
+            hampel_outlier: hampel[i].is_some_and(|(_, outlier)| outlier),
+            hampel_error_deg: error(hampel[i].map(|(yaw_deg, _)| yaw_deg), frame),
+        };
+        writer.serialize(record).unwrap();
+        records.push(record);
+    }
+    writer.flush().unwrap();
+
+    println!(
+        "window {} frames, {} latency {} frames",
+        config.window,
+        if config.causal { "causal," } else { "centred," },
+        filter.latency_frames()
+    );
+    for (series, errors) in [
+        (
+            "raw",
+            records.iter().map(|r| r.raw_error_deg).collect::<Vec<_>>(),
+        ),
+        (
+            "median",
+            records.iter().map(|r| r.median_error_deg).collect(),
+        ),
+        (
+            "hampel",
+            records.iter().map(|r| r.hampel_error_deg).collect(),
+        ),
+    ] {
+        let errors: Vec<f64> = errors.into_iter().flatten().collect();
+        let magnitudes: Vec<f64> = errors.iter().map(|e| e.abs()).collect();
+        let squares: Vec<f64> = errors.iter().map(|e| e * e).collect();
+        match mean(&magnitudes).zip(mean(&squares)) {
+            Some((mean_abs, mean_square)) => println!(
+                "{series}: {} frames, mean |e| {mean_abs:.3} deg, rms {:.3} deg",
+                errors.len(),
+                mean_square.sqrt()
+            ),
+            None => println!("{series}: no estimates"),
+        }
+    }
+    println!(
+        "hampel replaced {} outliers, wrote {}",
+        records.iter().filter(|r| r.hampel_outlier).count(),
+        output.display()
+    );
+}
+
+#[derive(Parser)]
+struct Cli {
+    results_dir: PathBuf,
+
+    /// Frames in each filter window.
+    #[arg(short, long, default_value_t = 5)]
+    window: usize,
+
+    /// Use only past frames, as an online filter would, instead of a centred window.
+    #[arg(long)]
+    causal: bool,
+
+    /// Robust standard deviations from the window median beyond which a heading is an outlier.
+    #[arg(long, default_value_t = 3.0)]
+    hampel_sigmas: f64,
+
+    /// Written inside the results directory.
+    #[arg(short, long, default_value = "smoothed.csv")]
+    output: PathBuf,
+}
+
+#[derive(serde::Deserialize)]
+struct FrameRecord {
+    frame_index: usize,
+    car_yaw_deg: f64,
+    estimated_yaw_deg: Option<f64>,
+    #[serde(default)]
+    bad_exposure: bool,
+}
+
+#[derive(Clone, Copy, serde::Serialize)]
+struct SmoothedRecord {
+    frame_index: usize,
+    car_yaw_deg: f64,
+    raw_yaw_deg: Option<f64>,
+    raw_error_deg: Option<f64>,
+    median_yaw_deg: Option<f64>,
+    median_error_deg: Option<f64>,
+    hampel_yaw_deg: Option<f64>,
+    hampel_outlier: bool,
+    hampel_error_deg: Option<f64>,
+}
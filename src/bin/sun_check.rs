@@ -0,0 +1,167 @@
+use chrono::{Duration, Local};
+use clap::Parser;
+use rumpus_benchmark::{
+    camera::CameraModel,
+    ephemeris::CelestialPosition,
+    io::{ImageReader, InsReader, TimeReader},
+    pipeline::Dataset,
+    sky,
+    utils::intensity_peak,
+};
+use std::{path::PathBuf, time::Instant};
+use uom::si::{
+    angle::degree,
+    f64::Length,
+    length::{micron, millimeter},
+};
+
+const FOCAL_LENGTH_MM: f64 = 8.0;
+
+/// Cross-validates the solar ephemeris against the brightest point in frames that see the sun.
+///
+/// A consistent offset between the two usually means the dataset timestamps are in the wrong
+/// time zone or GPS week.
+fn main() {
+    let config = Cli::parse();
+    let dataset = Dataset::new(&config.dataset_path);
+    let timestamp = Local::now().to_rfc3339();
+    let results_dir = PathBuf::from(&timestamp);
+    std::fs::create_dir(&results_dir).unwrap();
+
+    let ins_reader = InsReader::new();
+    let ins_frames = ins_reader.read_csv(dataset.ins_path()).unwrap();
+
+    let time_reader = TimeReader::new().with_leap_seconds(config.leap_seconds);
+    let time_frames = time_reader.read_csv(dataset.time_path()).unwrap();
+
+    let image_reader = ImageReader::new();
+
+    let focal_length = Length::new::<millimeter>(FOCAL_LENGTH_MM);
+    let pixel_size = Length::new::<micron>(3.45);
+    let camera_model = CameraModel::new(focal_length, pixel_size * 2.0, 1024, 1224);
+
+    let csv_path = results_dir.join("sun_check.csv");
+    let mut writer = csv::Writer::from_path(csv_path).unwrap();
+
+    // Total separation for each candidate clock offset in whole hours.
+    let offsets: Vec<i64> = (-config.max_offset_hours..=config.max_offset_hours).collect();
+    let mut offset_errors = vec![0.; offsets.len()];
+    let mut separations = Vec::new();
+
+    for (frame_index, (time_frame, ins_frame)) in
+        time_frames.zip(ins_frames).enumerate().step_by(config.step)
+    {
+        let t0 = Instant::now();
+
+        let car_in_ins_enu = ins_frame.orientation;
+        let sun = CelestialPosition::sun(&ins_frame.position, time_frame.time);
+        if sky::source_pixel(&camera_model, car_in_ins_enu, &sun).is_none() {
+            continue;
+        }
+
+        let image_path = dataset.image_path(frame_index);
+        let raw = match image_reader.read_raw(image_path) {
+            Ok(raw) => raw,
+            Err(e) => {
+                eprintln!("failed to read image: {e}");
+                continue;
+            }
+        };
+
+        let (row, col) = intensity_peak(&raw);
+        let directions = sky::sky_directions(&camera_model, car_in_ins_enu);
+        let Some(observed) = directions[row * camera_model.cols() + col].as_ref() else {
+            println!("intensity peak is below the horizon! skipping...");
+            continue;
+        };
+        let observed = CelestialPosition::from(observed);
+
+        let separation = sun.separation(&observed).get::<degree>();
+        separations.push(separation);
+
+        for (offset, error) in offsets.iter().zip(offset_errors.iter_mut()) {
+            let time = time_frame.time + Duration::hours(*offset);
+            let sun = CelestialPosition::sun(&ins_frame.position, time);
+            *error += sun.separation(&observed).get::<degree>();
+        }
+
+        let _ = writer.serialize(Record {
+            frame_index,
+            sun_azimuth_deg: sun.azimuth.get::<degree>(),
+            sun_elevation_deg: sun.elevation.get::<degree>(),
+            observed_azimuth_deg: observed.azimuth.get::<degree>(),
+            observed_elevation_deg: observed.elevation.get::<degree>(),
+            separation_deg: separation,
+        });
+
+        println!(
+            "[{:04}] frame {:04} off by {:06.2} deg in {:05} ms",
+            separations.len(),
+            frame_index,
+            separation,
+            t0.elapsed().as_millis()
+        );
+
+        if let Some(max_frames) = config.max_frames
+            && separations.len() >= max_frames
+        {
+            break;
+        }
+    }
+
+    if separations.is_empty() {
+        println!("the sun was never inside the camera fov");
+        return;
+    }
+
+    separations.sort_by(f64::total_cmp);
+    let median = separations[separations.len() / 2];
+    let (best_offset, _) = offsets
+        .iter()
+        .zip(&offset_errors)
+        .min_by(|(_, a), (_, b)| a.total_cmp(b))
+        .unwrap();
+
+    println!(
+        "{} frames, median separation {median:.2} deg, best clock offset {best_offset:+} h",
+        separations.len()
+    );
+
+    if median > config.tolerance_deg || *best_offset != 0 {
+        eprintln!("ephemeris does not match the images; check the dataset time zone and GPS week");
+        std::process::exit(1);
+    }
+}
+
+#[derive(Parser)]
+struct Cli {
+    dataset_path: PathBuf,
+
+    #[arg(short, long)]
+    max_frames: Option<usize>,
+
+    #[arg(short, long, default_value_t = 1)]
+    step: usize,
+
+    /// GPS to UTC leap seconds, instead of the offset reported by the receiver.
+    #[arg(long)]
+    leap_seconds: Option<i64>,
+
+    /// Largest median separation between the ephemeris and the observed sun.
+    #[arg(long, default_value_t = 5.0)]
+    tolerance_deg: f64,
+
+    /// Range of whole-hour clock offsets searched for a better match.
+    #[arg(long, default_value_t = 12)]
+    max_offset_hours: i64,
+}
+
+#[derive(serde::Serialize)]
+struct Record {
+    frame_index: usize,
+    sun_azimuth_deg: f64,
+    sun_elevation_deg: f64,
+    observed_azimuth_deg: f64,
+    observed_elevation_deg: f64,
+    separation_deg: f64,
+}
@@ -0,0 +1,188 @@
+use chrono::{DateTime, Utc};
+use clap::Parser;
+use rumpus_benchmark::{
+    dead_reckoning::{DeadReckoner, DriftSummary, LocalPosition, SpeedSeries},
+    io::TimeReader,
+    pipeline::Dataset,
+    systems::heading_from_yaw_deg,
+};
+use std::path::PathBuf;
+
+/// Dead-reckons the route of a `test_pattern_match` run from its polarization headings and the
+/// vehicle speed, and reports how far it drifts from the INS trajectory.
+fn main() {
+    let config = Cli::parse();
+    let dataset = Dataset::new(&config.dataset_path);
+    let results_path = config.results_dir.join("results.csv");
+    let mut reader = csv::Reader::from_path(&results_path)
+        .unwrap_or_else(|e| panic!("cannot read {}: {e}", results_path.display()));
+    let frames: Vec<FrameRecord> = reader
+        .deserialize()
+        .collect::<Result<_, _>>()
+        .unwrap_or_else(|e| panic!("cannot parse {}: {e}", results_path.display()));
+    let times: Vec<DateTime<Utc>> = TimeReader::new()
+        .read_csv(dataset.time_path())
+        .unwrap()
+        .map(|time_frame| time_frame.time)
+        .collect();
+
+    // The compass cannot start the trajectory until it has produced a heading.
+    let usable = |frame: &FrameRecord| !frame.bad_exposure && !frame.ins_degraded;
+    let Some(start) = frames
+        .iter()
+        .position(|frame| usable(frame) && frame.estimated_heading_deg.is_some())
+    else {
+        println!("no polarization headings in {}", results_path.display());
+        return;
+    };
+    let frames = &frames[start..];
+    let origin = (frames[0].latitude_deg, frames[0].longitude_deg);
+    let fixes: Vec<(DateTime<Utc>, LocalPosition)> = frames
+        .iter()
+        .map(|frame| {
+            let time = *times
+                .get(frame.frame_index)
+                .unwrap_or_else(|| panic!("no time for frame {}", frame.frame_index));
+            let position =
+                LocalPosition::from_geodetic(origin, frame.latitude_deg, frame.longitude_deg);
+            (time, position)
+        })
+        .collect();
+
+    let odometry_path = config
+        .odometry
+        .clone()
+        .unwrap_or_else(|| dataset.odometry_path());
+    let speed = if odometry_path.exists() {
+        SpeedSeries::read_csv(&odometry_path).unwrap()
+    } else {
+        eprintln!(
+            "warning: no wheel odometry at {}, using the INS ground speed, so the drift only \
+             reflects heading errors",
+            odometry_path.display()
+        );
+        SpeedSeries::from_fixes(&fixes).expect("at least two frames")
+    };
+
+    let output = config.results_dir.join(&config.output);
+    let mut writer = csv::Writer::from_path(&output).unwrap();
+    let mut compass = DeadReckoner::new(fixes[0].1);
+    let mut control = DeadReckoner::new(fixes[0].1);
+    let mut compass_heading_deg = frames[0].estimated_heading_deg.unwrap_or_default();
+    let mut coasted_frames = 0;
+    let (mut compass_track, mut control_track) = (Vec::new(), Vec::new());
+    for (i, (frame, &(time, ins_position))) in frames.iter().zip(&fixes).enumerate() {
+        if i > 0 {
+            let (previous_time, _) = fixes[i - 1];
+            let dt_s = (time - previous_time).as_seconds_f64();
+            let speed_m_s = speed.at(previous_time + (time - previous_time) / 2);
+            compass.advance(compass_heading_deg, speed_m_s, dt_s);
+            control.advance(
+                heading_from_yaw_deg(frames[i - 1].car_yaw_deg),
+                speed_m_s,
+                dt_s,
+            );
+        }
+        // Between estimates the compass holds its last heading.
+        let estimated_heading_deg = frame.estimated_heading_deg.filter(|_| usable(frame));
+        match estimated_heading_deg {
+            Some(heading_deg) => compass_heading_deg = heading_deg,
+            None => coasted_frames += 1,
+        }
+
+        let record = DeadReckoningRecord {
+            frame_index: frame.frame_index,
+            time,
+            ins_east_m: ins_position.east_m,
+            ins_north_m: ins_position.north_m,
+            compass_heading_deg,
+            coasted: estimated_heading_deg.is_none(),
+            compass_east_m: compass.position().east_m,
+            compass_north_m: compass.position().north_m,
+            compass_drift_m: compass.position().distance_m(&ins_position),
+            control_east_m: control.position().east_m,
+            control_north_m: control.position().north_m,
+            control_drift_m: control.position().distance_m(&ins_position),
+        };
+        writer.serialize(record).unwrap();
+        compass_track.push(compass.position());
+        control_track.push(control.position());
+    }
+    writer.flush().unwrap();
+
+    let reference: Vec<LocalPosition> = fixes.iter().map(|(_, position)| *position).collect();
+    println!(
+        "{} frames from frame {}, {coasted_frames} without a heading",
+        frames.len(),
+        frames[0].frame_index
+    );
+    for (series, track, distance_m) in [
+        ("polarization", &compass_track, compass.distance_m()),
+        ("ins heading", &control_track, control.distance_m()),
+    ] {
+        match DriftSummary::new(&reference, track, distance_m) {
+            Some(summary) => {
+                print!(
+                    "{series}: {:.1} m travelled, final drift {:.2} m ({:.2}%), max {:.2} m",
+                    summary.distance_m,
+                    summary.final_drift_m,
+                    summary.drift_percent,
+                    summary.max_drift_m
+                );
+                match summary.loop_closure_m {
+                    Some(loop_closure_m) => println!(", loop closure {loop_closure_m:.2} m"),
+                    None => println!(),
+                }
+            }
+            None => println!("{series}: too few frames"),
+        }
+    }
+    println!("wrote {}", output.display());
+}
+
+#[derive(Parser)]
+struct Cli {
+    dataset_path: PathBuf,
+
+    /// Results of a `test_pattern_match` run on the dataset.
+    results_dir: PathBuf,
+
+    /// Wheel odometry to use instead of the one in the dataset.
+    #[arg(long)]
+    odometry: Option<PathBuf>,
+
+    /// Written inside the results directory.
+    #[arg(short, long, default_value = "dead_reckoning.csv")]
+    output: PathBuf,
+}
+
+#[derive(serde::Deserialize)]
+struct FrameRecord {
+    frame_index: usize,
+    car_yaw_deg: f64,
+    latitude_deg: f64,
+    longitude_deg: f64,
+    estimated_heading_deg: Option<f64>,
+    #[serde(default)]
+    bad_exposure: bool,
+    #[serde(default)]
+    ins_degraded: bool,
+}
+
+/// Positions are east and north of the INS at the first frame with a heading. The control
+/// trajectory uses the INS heading, so its drift comes from the speed alone.
+#[derive(serde::Serialize)]
+struct DeadReckoningRecord {
+    frame_index: usize,
+    time: DateTime<Utc>,
+    ins_east_m: f64,
+    ins_north_m: f64,
+    compass_heading_deg: f64,
+    coasted: bool,
+    compass_east_m: f64,
+    compass_north_m: f64,
+    compass_drift_m: f64,
+    control_east_m: f64,
+    control_north_m: f64,
+    control_drift_m: f64,
+}
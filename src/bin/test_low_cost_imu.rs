@@ -7,28 +7,27 @@ use rumpus::{
 };
 use rumpus_benchmark::{
     io::{ImageReader, InsReader, TimeReader},
+    pipeline::Dataset,
     systems::{self, CamXyz, up_in_cam},
     utils::{sensor_to_global, weighted_rmse},
 };
 use sguaba::engineering::Orientation;
-use std::{
-    path::{Path, PathBuf},
-    time::Instant,
-};
+use std::{path::PathBuf, time::Instant};
 
 #[allow(clippy::similar_names)]
 fn main() {
     let config = Cli::parse();
+    let dataset = Dataset::new(&config.dataset_path);
     let timestamp = Local::now().to_rfc3339();
     let results_dir = PathBuf::from(&timestamp);
     std::fs::create_dir(&results_dir).unwrap();
 
     let cam_in_car = systems::cam_to_car().transform(Orientation::<CamXyz>::aligned());
-    let ins_path = config.ins_path();
+    let ins_path = dataset.ins_path();
     let ins_reader = InsReader::new();
     let ins_frames = ins_reader.read_csv(&ins_path).unwrap();
 
-    let time_path = config.time_path();
+    let time_path = dataset.time_path();
     let time_reader = TimeReader::new();
     let time_frames = time_reader.read_csv(&time_path).unwrap();
 
@@ -59,19 +58,3 @@ struct Cli {
     #[arg(short, long, default_value_t = 1)]
     step: usize,
 }
-
-impl Cli {
-    fn image_dir(&self) -> PathBuf {
-        self.dataset_path.join("camera_driver_gv_vis_image_raw")
-    }
-
-    fn ins_path(&self) -> PathBuf {
-        self.dataset_path
-            .join("novatel_oem7_inspva/novatel_oem7_inspva.csv")
-    }
-
-    fn time_path(&self) -> PathBuf {
-        self.dataset_path
-            .join("novatel_oem7_time/novatel_oem7_time.csv")
-    }
-}
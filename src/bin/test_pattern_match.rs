@@ -1,245 +1,1081 @@
-use chrono::Local;
+use chrono::{DateTime, Utc};
 use clap::Parser;
+use image::RgbaImage;
 use rumpus::{
-    optic::{Camera, PinholeOptic, RayDirection},
-    simulation::Simulation,
+    image::{Jet, RayImage},
+    ray::SensorFrame,
 };
 use rumpus_benchmark::{
-    io::{ImageReader, InsReader, TimeReader},
-    systems::{self, CamXyz, InsEnu, up_in_cam},
-    utils::{sensor_to_global, weighted_rmse},
+    allan::{self, allan_deviation},
+    camera::CameraModel,
+    cli::{DatasetArgs, NotifyArgs, SkyArgs, interrupt_on_ctrl_c, run_metadata},
+    conventions::ConventionCheck,
+    dashboard,
+    ephemeris::CelestialPosition,
+    error::BenchError,
+    estimator::{ComparisonFrame, FrameInput, HeadingEstimator, SearchMethod, estimate_heading},
+    exposure::{ExposureAction, ExposureGate},
+    glare::{GlareConfig, GlareStrategy},
+    heading::{AdaptiveWindow, HeadingEstimate},
+    io::{ImageReader, InsStatus},
+    leaderboard::{Leaderboard, LeaderboardEntry, config_hash},
+    metrics,
+    monitor::RunMonitor,
+    notify::CompletionReport,
+    outage::{HeadingCoast, OutageErrors},
+    output::{HeadingMessage, OutputFormat, UdpSink},
+    overlay::Overlay,
+    pipeline::{FrameContext, FrameProcessor, FrameSkip, ResultWriter},
+    run::{LatencyPercentiles, RunProfile, SkipReason, TimingRecord, timed},
+    sink::{ResultRow, ResultSink, SinkFormat, open_database},
+    sky::{self, LightSource},
+    smoothing::wrap_deg,
+    stats::{ErrorSummary, weighted_mean},
+    systems::{HeadingConventions, InsEnu, heading_from_yaw_deg},
+    tags::{FrameTags, StratifiedErrors},
+    utils::measured_to_global,
 };
-use sguaba::engineering::Orientation;
-use std::{
-    path::{Path, PathBuf},
-    time::Instant,
-};
-use uom::si::{
-    angle::{degree, radian},
-    f64::{Angle, Length},
-    length::{micron, millimeter},
+use sguaba::{engineering::Orientation, systems::Wgs84};
+use std::{fs::File, net::SocketAddr, path::PathBuf, time::Instant};
+use uom::{
+    ConstZero,
+    si::{
+        angle::degree,
+        f64::{Angle, Length},
+        length::{meter, micron, millimeter},
+    },
 };
 
 const FOCAL_LENGTH_MM: f64 = 8.0;
+const PIXEL_SIZE_UM: f64 = 3.45;
 
 fn main() {
+    let t0 = Instant::now();
     let config = Cli::parse();
+    config.profile.apply().unwrap();
 
-    // Make a new directory to hold results.
-    let timestamp = Local::now().to_rfc3339();
-    let results_dir = PathBuf::from(&timestamp);
-    std::fs::create_dir(&results_dir).unwrap();
+    // Make a new directory to hold results, or a scratch one for a dry run.
+    let results = if config.dry_run {
+        ResultWriter::create_in(std::env::temp_dir())
+    } else {
+        ResultWriter::create()
+    }
+    .unwrap();
+    let mut metadata = run_metadata(
+        env!("CARGO_BIN_NAME"),
+        results.started(),
+        &config.dataset,
+        &config.sky,
+    );
+    metadata.profile = config.profile;
+    metadata.write(results.dir()).unwrap();
 
-    // Setup reader for INS position and orientation measurements.
-    let ins_path = config.ins_path();
-    let ins_reader = InsReader::new();
-    let ins_frames = ins_reader.read_csv(&ins_path).unwrap();
+    // Tell someone when the run ends, including if it fails part way.
+    let hooks = config.notify.hooks().unwrap();
+    let report = CompletionReport::new(env!("CARGO_BIN_NAME"), results.started(), results.dir());
+    if !config.dry_run {
+        hooks.clone().notify_on_panic(report.clone());
+    }
 
-    // Define orientation of the camera in the car frame.
-    let cam_in_car = systems::cam_to_car().transform(Orientation::<CamXyz>::aligned());
+    // Serve the progress of the run to browsers or Prometheus, if requested.
+    let monitor = ((config.serve.is_some() || config.metrics.is_some()) && !config.dry_run)
+        .then(|| RunMonitor::new(env!("CARGO_BIN_NAME"), results.started()));
+    if let Some(monitor) = &monitor {
+        if let Some(addr) = config.serve {
+            let addr = dashboard::serve(addr, monitor.clone()).unwrap();
+            println!("serving run progress on http://{addr}");
+        }
+        if let Some(addr) = config.metrics {
+            let addr = metrics::serve(addr, monitor.clone()).unwrap();
+            println!("serving Prometheus metrics on http://{addr}/metrics");
+        }
+    }
+    let pipeline = config
+        .dataset
+        .pipeline()
+        .unwrap()
+        .with_interrupt(interrupt_on_ctrl_c())
+        .with_monitor(monitor.clone());
+    let sky = config.sky.sky().unwrap();
+    // Report how far the sky lookup table is from the model it stands in for.
+    if let Some(lut_accuracy) = sky.lut_accuracy() {
+        lut_accuracy.print();
+        lut_accuracy.write(results.dir()).unwrap();
+    }
 
-    // Setup reader for INS time measurements.
-    let time_path = config.time_path();
-    let time_reader = TimeReader::new();
-    let time_frames = time_reader.read_csv(&time_path).unwrap();
+    // Setup camera model.
+    let image_reader = config.dataset.prefetching_image_reader(&pipeline).unwrap();
+    let camera_model = |focal_length_mm: f64, pixel_size_um: f64| {
+        let focal_length = Length::new::<millimeter>(focal_length_mm);
+        let pixel_size = Length::new::<micron>(pixel_size_um);
+        CameraModel::for_mosaic(focal_length, pixel_size, 2048, 2448, &image_reader)
+            .with_principal_point(config.dataset.principal_point(&image_reader))
+    };
+    let estimator = HeadingEstimator::new(camera_model(FOCAL_LENGTH_MM, PIXEL_SIZE_UM), sky)
+        .with_glare(config.sky.glare())
+        .with_comparison_frame(config.comparison_frame)
+        .with_aop_emd(config.max_aop_emd_deg.is_some())
+        .with_profile(config.profile);
+    config
+        .profile
+        .preallocate(estimator.scratch(), estimator.camera());
 
-    // Setup reader for polarization images.
-    let image_reader = ImageReader::new();
+    // Every combination of the swept intrinsics, with the nominal value standing in for an
+    // axis that is not swept.
+    let sweep = |values: &[f64], nominal: f64| {
+        if values.is_empty() {
+            vec![nominal]
+        } else {
+            values.to_vec()
+        }
+    };
+    let intrinsics: Vec<_> =
+        if config.sweep_focal_length_mm.is_empty() && config.sweep_pixel_size_um.is_empty() {
+            Vec::new()
+        } else {
+            let pixel_sizes_um = sweep(&config.sweep_pixel_size_um, PIXEL_SIZE_UM);
+            sweep(&config.sweep_focal_length_mm, FOCAL_LENGTH_MM)
+                .into_iter()
+                .flat_map(|focal_length_mm| {
+                    pixel_sizes_um
+                        .iter()
+                        .map(move |&pixel_size_um| (focal_length_mm, pixel_size_um))
+                })
+                .map(|(focal_length_mm, pixel_size_um)| IntrinsicsVariant {
+                    focal_length_mm,
+                    pixel_size_um,
+                    estimator: estimator
+                        .clone()
+                        .with_camera(camera_model(focal_length_mm, pixel_size_um)),
+                    errors_deg: Vec::new(),
+                })
+                .collect()
+        };
 
-    // Setup camera model.
-    let focal_length = Length::new::<millimeter>(FOCAL_LENGTH_MM);
-    let pixel_size = Length::new::<micron>(3.45);
-    let camera = Camera::new(
-        PinholeOptic::from_focal_length(focal_length),
-        pixel_size * 2.0,
-        1024,
-        1224,
-    );
+    let mut processor = PatternMatchProcessor {
+        estimator,
+        glare: config.sky.glare(),
+        image_reader,
+        exposure_gate: config.dataset.exposure_gate(),
+        min_source_elevation_deg: config.sky.min_source_elevation_deg,
+        resolution_deg: config.resolution_deg,
+        search_method: config.search,
+        // The yaw search window stays wide unless adaptive search is enabled.
+        search: AdaptiveWindow::new(config.window_deg, config.min_window_deg)
+            .with_min_confidence(config.min_confidence),
+        adaptive_window: config.adaptive_window,
+        perturb_deg: config.perturb_deg.clone(),
+        max_aop_emd_deg: config.max_aop_emd_deg,
+        convention_check: (!config.dataset.no_convention_check)
+            .then(|| results.csv("convention_check.csv").unwrap()),
+        yaw_errors_deg: Vec::new(),
+        weighted_yaw_errors_deg: Vec::new(),
+        yaw_error_series: Vec::new(),
+        frame_tags: config.dataset.frame_tags().unwrap(),
+        tag_errors: StratifiedErrors::new(),
+        coast: None,
+        last_fix_deg_m: None,
+        outage_errors: OutageErrors::new(config.outage_bin_s),
+        // A shared results database is left alone by dry runs.
+        sink: match config.results_db.as_ref().filter(|_| !config.dry_run) {
+            Some(path) => open_database(path, results.dir(), &metadata),
+            None => config.sink.open(results.dir(), &metadata),
+        }
+        .unwrap(),
+        timings_writer: results.csv("timings.csv").unwrap(),
+        frame_timings: Vec::new(),
+        // Attitude sensitivity results are only written if requested.
+        sensitivity_writer: (!config.perturb_deg.is_empty())
+            .then(|| results.csv("sensitivity.csv").unwrap()),
+        intrinsics_writer: (!intrinsics.is_empty())
+            .then(|| results.csv("intrinsics_sensitivity.csv").unwrap()),
+        intrinsics,
+        // Stream heading estimates to downstream navigation software, if requested.
+        udp_sink: config
+            .udp
+            .filter(|_| !config.dry_run)
+            .map(|addr| UdpSink::new(addr, config.udp_format).unwrap()),
+        monitor,
+        serves_dashboard: config.serve.is_some(),
+    };
 
-    // Open a new CSV file to store results.
-    let csv_path = results_dir.join("results.csv");
-    let mut frame_writer = csv::Writer::from_path(csv_path).unwrap();
+    if config.dry_run {
+        let sweeps = 1 + 4 * config.perturb_deg.len() + processor.intrinsics.len();
+        // A correlation simulates a single template per sweep.
+        let candidates_per_frame = match config.search {
+            SearchMethod::Sweep => processor
+                .search
+                .window(0.)
+                .offsets(config.resolution_deg)
+                .len(),
+            SearchMethod::Correlation => 1,
+        };
+        pipeline
+            .dry_run(processor, results.dir())
+            .with_candidates_per_frame(sweeps * candidates_per_frame)
+            .print();
+        std::fs::remove_dir_all(results.dir()).unwrap();
+        return;
+    }
 
-    let mut frame_count = 0;
-    for (frame_index, (time_frame, ins_frame)) in
-        time_frames.zip(ins_frames).enumerate().step_by(config.step)
-    {
-        print_frame_status(frame_index, frame_count, config.max_frames, None);
+    let mut summary = pipeline.run(&mut processor);
+    summary.latency = LatencyPercentiles::of_frames(&processor.frame_timings);
+    processor.flush().unwrap();
+    processor.sink.finalize().unwrap();
+    summary.print();
+    summary.write(results.dir()).unwrap();
+    if let Some(weighted_error_deg) = weighted_mean(&processor.weighted_yaw_errors_deg) {
+        println!("INS variance weighted mean heading error: {weighted_error_deg:.3} deg");
+    }
+    if let Some(profile) = processor.estimator.sky().profile() {
+        if profile.whole_images() > 0 {
+            eprintln!(
+                "{} rumpus skies were timed as a whole, their rows sharing the time evenly",
+                profile.whole_images()
+            );
+        }
+        profile
+            .write(results.dir(), processor.estimator.camera().cols())
+            .unwrap();
+    }
+    if config.dataset.tags.is_some() || config.sky.weather_csv.is_some() {
+        let mut writer = results.csv("tag_summary.csv").unwrap();
+        for tag_summary in processor.tag_errors.summaries() {
+            tag_summary.print("heading error deg");
+            writer.serialize(tag_summary).unwrap();
+        }
+    }
+    if let Some((samples, period_s)) = allan::resample(&processor.yaw_error_series) {
+        let points = allan_deviation(&samples, period_s);
+        let mut writer = results.csv("allan_deviation.csv").unwrap();
+        for point in &points {
+            writer.serialize(point).unwrap();
+        }
+        writer.flush().unwrap();
+        allan::plot(&points, results.dir(), "allan_deviation", "deg").unwrap();
+        if let Some(floor) = points
+            .iter()
+            .min_by(|a, b| a.deviation.total_cmp(&b.deviation))
+        {
+            println!(
+                "heading error Allan deviation floor: {:.3} deg at {:.1} s",
+                floor.deviation, floor.tau_s
+            );
+        }
+    }
+    if !processor.intrinsics.is_empty() {
+        let nominal_errors_deg: Vec<_> = processor
+            .yaw_error_series
+            .iter()
+            .map(|&(_, error)| error)
+            .collect();
+        let mut writer = results.csv("intrinsics_summary.csv").unwrap();
+        let rows = std::iter::once((true, FOCAL_LENGTH_MM, PIXEL_SIZE_UM, &nominal_errors_deg))
+            .chain(processor.intrinsics.iter().map(|variant| {
+                (
+                    false,
+                    variant.focal_length_mm,
+                    variant.pixel_size_um,
+                    &variant.errors_deg,
+                )
+            }));
+        for (nominal, focal_length_mm, pixel_size_um, errors_deg) in rows {
+            let Some(stats) = ErrorSummary::new(errors_deg) else {
+                continue;
+            };
+            println!(
+                "{focal_length_mm} mm, {pixel_size_um} um{}: heading error mean {:+.3} deg, rms {:.3} deg over {} frames",
+                if nominal { " (nominal)" } else { "" },
+                stats.mean,
+                stats.rms,
+                stats.samples
+            );
+            writer
+                .serialize(IntrinsicsSummaryRecord {
+                    focal_length_mm,
+                    pixel_size_um,
+                    nominal,
+                    frames: stats.samples,
+                    mean_error_deg: stats.mean,
+                    rms_error_deg: stats.rms,
+                    max_abs_error_deg: stats.max_abs,
+                })
+                .unwrap();
+        }
+        writer.flush().unwrap();
+    }
+    if !config.dataset.ins_outage.is_empty() {
+        let mut writer = results.csv("outage_summary.csv").unwrap();
+        for outage_summary in processor.outage_errors.summaries() {
+            outage_summary.print();
+            writer.serialize(outage_summary).unwrap();
+        }
+    }
+    // Partial runs are kept but left off the leaderboard.
+    if summary.interrupted {
+        metadata.interrupted = true;
+        metadata.write(results.dir()).unwrap();
+    } else {
+        let options = (
+            config.search,
+            config.comparison_frame,
+            config.resolution_deg,
+            config.window_deg,
+            config.adaptive_window,
+            config.min_window_deg,
+            config.min_confidence,
+        );
+        Leaderboard::append(
+            pipeline.dataset(),
+            LeaderboardEntry {
+                run_name: config
+                    .run_name
+                    .unwrap_or_else(|| results.started().to_string()),
+                experiment: metadata.experiment.clone(),
+                started: metadata.started.clone(),
+                config_hash: config_hash(&metadata, &options),
+                frames_processed: summary.frames_processed,
+                mean_heading_error_deg: weighted_mean(&processor.yaw_errors_deg),
+                runtime_s: t0.elapsed().as_secs_f64(),
+            },
+        )
+        .unwrap();
+    }
+    // Only once every result is written, so hooks can read them.
+    hooks.notify(&report.with_summary(&summary));
+}
 
+/// Searches for the heading that best explains each measured frame.
+struct PatternMatchProcessor {
+    estimator: HeadingEstimator,
+    image_reader: ImageReader,
+    exposure_gate: ExposureGate,
+    min_source_elevation_deg: Option<f64>,
+    glare: GlareConfig,
+    resolution_deg: f64,
+    search_method: SearchMethod,
+    search: AdaptiveWindow,
+    adaptive_window: bool,
+    perturb_deg: Vec<f64>,
+    max_aop_emd_deg: Option<f64>,
+    /// Where to write the convention self-check of the first frame with an image, until it
+    /// has run.
+    convention_check: Option<csv::Writer<File>>,
+    /// Absolute heading error of every well exposed frame with an estimate, good INS and a
+    /// plausible AoP distribution, with the weight of the frame.
+    yaw_errors_deg: Vec<(f64, f64)>,
+    /// The same errors weighted by the inverse variance of the INS azimuth, when it is known.
+    weighted_yaw_errors_deg: Vec<(f64, f64)>,
+    /// Signed heading error of the same frames over time, for the Allan deviation.
+    yaw_error_series: Vec<(DateTime<Utc>, f64)>,
+    frame_tags: FrameTags,
+    /// Heading errors of the same frames, by scenario tag.
+    tag_errors: StratifiedErrors,
+    /// Yaw the estimator coasts on during a simulated GNSS outage.
+    coast: Option<HeadingCoast>,
+    /// Latitude and longitude in degrees and height in metres of the last INS fix before an
+    /// outage.
+    last_fix_deg_m: Option<(f64, f64, f64)>,
+    /// Heading errors by time into and after the outages.
+    outage_errors: OutageErrors,
+    /// Frame and candidate records.
+    sink: Box<dyn ResultSink>,
+    timings_writer: csv::Writer<File>,
+    /// Stage times of every frame with a result, for the latency percentiles.
+    frame_timings: Vec<TimingRecord>,
+    sensitivity_writer: Option<csv::Writer<File>>,
+    intrinsics: Vec<IntrinsicsVariant>,
+    intrinsics_writer: Option<csv::Writer<File>>,
+    udp_sink: Option<UdpSink>,
+    monitor: Option<RunMonitor>,
+    /// Whether the dashboard shows each frame, which only it needs drawn.
+    serves_dashboard: bool,
+}
+
+impl PatternMatchProcessor {
+    /// Yaw to centre the search on and where to simulate the sky from, if not at the INS
+    /// position.
+    ///
+    /// During an outage these come from the gyro and the last fix rather than the INS. Pitch
+    /// and roll still come from the INS, as the IMU levels itself without GNSS.
+    fn navigation(&mut self, frame: &FrameContext, ins_yaw_deg: f64) -> (f64, Option<Wgs84>) {
+        let position = &frame.ins.position;
+        let fix = (
+            position.latitude().get::<degree>(),
+            position.longitude().get::<degree>(),
+            position.altitude().get::<meter>(),
+        );
+        if !frame.outage.is_denied() {
+            self.coast = Some(HeadingCoast::new(ins_yaw_deg, frame.time));
+            self.last_fix_deg_m = Some(fix);
+            return (ins_yaw_deg, None);
+        }
+
+        // An outage from the first frame starts from the INS alignment.
+        let yaw_deg = self
+            .coast
+            .get_or_insert_with(|| HeadingCoast::new(ins_yaw_deg, frame.time))
+            .propagate(frame.time, frame.yaw_rate_deg_s.unwrap_or(0.));
+        let (lat, lon, height) = *self.last_fix_deg_m.get_or_insert(fix);
+        (
+            yaw_deg,
+            Some(InsEnu::position_from_inspva(lat, lon, height)),
+        )
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        self.timings_writer.flush()?;
+        if let Some(sensitivity_writer) = self.sensitivity_writer.as_mut() {
+            sensitivity_writer.flush()?;
+        }
+        if let Some(intrinsics_writer) = self.intrinsics_writer.as_mut() {
+            intrinsics_writer.flush()?;
+        }
+        Ok(())
+    }
+}
+
+impl FrameProcessor for PatternMatchProcessor {
+    fn process(&mut self, frame: &FrameContext) -> Result<(), FrameSkip> {
         let t0 = Instant::now();
+        let frame_index = frame.frame_index;
+        let mut frame_timing = TimingRecord::frame(frame_index);
+
+        let car_in_ins_enu = frame.ins.orientation;
+        let (car_yaw, pitch, roll) = car_in_ins_enu.to_tait_bryan_angles();
+        let (navigation_yaw_deg, outage_position) = self.navigation(frame, car_yaw.get::<degree>());
+        // Offset of the search centre from the INS yaw, which is zero unless coasting.
+        let coast_offset_deg = wrap_deg(navigation_yaw_deg - car_yaw.get::<degree>());
+
+        let sun = CelestialPosition::sun(&frame.ins.position, frame.time);
+        let sun_in_fov = sky::source_pixel(self.estimator.camera(), car_in_ins_enu, &sun).is_some();
+
+        // Skip frames where the sky is too dark to be polarized by the light source.
+        let (light_source, source_position) = self
+            .estimator
+            .sky()
+            .light_source(&frame.ins.position, frame.time);
+        let source_elevation_deg = source_position.elevation.get::<degree>();
+        let source_too_low = self
+            .min_source_elevation_deg
+            .is_some_and(|min_elevation_deg| source_elevation_deg < min_elevation_deg);
+
+        let weather = self
+            .estimator
+            .sky()
+            .weather()
+            .and_then(|weather| weather.at(frame.time))
+            .copied();
+        let mut tags = self.frame_tags.tags(frame_index);
+        if sun_in_fov {
+            tags.push("sun_in_view");
+        }
+        if let Some(weather) = &weather {
+            tags.extend_from_slice(&weather.tags());
+        }
+        let mut record = FrameRecord {
+            frame_index,
+            tags: tags.join(";"),
+            car_yaw_deg: car_yaw.get::<degree>(),
+            car_pitch_deg: pitch.get::<degree>(),
+            car_roll_deg: roll.get::<degree>(),
+            latitude_deg: frame.ins.position.latitude().get::<degree>(),
+            longitude_deg: frame.ins.position.longitude().get::<degree>(),
+            altitude_m: self
+                .estimator
+                .sky()
+                .altitude()
+                .altitude_m(frame.ins.position.altitude().get::<meter>()),
+            ins_azimuth_sd_deg: frame.ins.covariance.map(|c| c.azimuth_sd_deg()),
+            cloud_cover: weather.and_then(|w| w.cloud_cover),
+            visibility_km: weather.and_then(|w| w.visibility_km),
+            aerosol_index: weather.and_then(|w| w.aerosol_index),
+            light_source,
+            source_elevation_deg,
+            source_too_low,
+            sun_azimuth_deg: sun.azimuth.get::<degree>(),
+            sun_elevation_deg: sun.elevation.get::<degree>(),
+            sun_in_fov,
+            glare_weight: self.glare.frame_weight(sun_in_fov),
+            yaw_rate_deg_s: frame.yaw_rate_deg_s,
+            yaw_rate_exceeded: frame.yaw_rate_exceeded,
+            tilted: frame.tilted,
+            ins_status: frame.ins.status,
+            ins_degraded: frame.ins_degraded,
+            outage_phase: frame.outage.name(),
+            outage_elapsed_s: frame.outage.elapsed_s(),
+            navigation_yaw_deg,
+            saturated_fraction: None,
+            underexposed_fraction: None,
+            bad_exposure: false,
+            aop_emd_deg: None,
+            aop_mismatch: false,
+            estimated_yaw_deg: None,
+            estimated_heading_deg: None,
+            estimated_enu_yaw_deg: None,
+            yaw_error_deg: None,
+            best_weighted_rmse: None,
+            cost_sharpness: None,
+            basin_ratio: None,
+            valid_fraction: None,
+            confidence: None,
+            valid_pixels: None,
+            no_data_pixels: None,
+            outside_image_circle_pixels: None,
+            not_simulated_pixels: None,
+            transform_dropped_pixels: None,
+            masked_pixels: None,
+        };
+
+        if source_too_low {
+            let _ = write_frame(self.sink.as_mut(), &record);
+            return Err(FrameSkip::new(
+                SkipReason::SourceTooLow,
+                "light source is below minimum elevation",
+            ));
+        }
+        if sun_in_fov && self.glare.strategy == GlareStrategy::Skip {
+            let _ = write_frame(self.sink.as_mut(), &record);
+            return Err(FrameSkip::new(SkipReason::SunInView, "sun disc is in view"));
+        }
 
         // Read the polarization image from this frame.
-        let image_path = config.image_dir().join(image_path_from_frame(frame_index));
-        let image = match image_reader.read_image(image_path) {
-            Ok(image) => image,
-            Err(e) => {
-                eprintln!("failed to read image: {e}");
-                continue;
+        let (image, exposure, image_circle) = timed(&mut frame_timing.decode_ms, || {
+            self.image_reader
+                .read_image_with_exposure(&frame.image_path, self.exposure_gate.dark_level())
+        })
+        .map_err(|e| FrameSkip::new(SkipReason::UnreadableImage, e))?;
+        record.saturated_fraction = Some(exposure.saturated_fraction);
+        record.underexposed_fraction = Some(exposure.underexposed_fraction);
+        record.bad_exposure = self.exposure_gate.fails(&exposure);
+        if record.bad_exposure && self.exposure_gate.action() == ExposureAction::Skip {
+            let _ = write_frame(self.sink.as_mut(), &record);
+            return Err(FrameSkip::new(
+                SkipReason::BadExposure,
+                format!(
+                    "{:.1}% of super-pixels are badly exposed",
+                    100. * exposure.bad_fraction()
+                ),
+            ));
+        }
+
+        if let Some(mut writer) = self.convention_check.take() {
+            match ConventionCheck::run(
+                frame_index,
+                self.estimator.camera(),
+                self.estimator.sky(),
+                &image,
+                &frame.ins.position,
+                frame.time,
+                car_in_ins_enu,
+            ) {
+                Ok(check) => {
+                    check.print();
+                    let _ = check.write(&mut writer);
+                }
+                Err(e) => eprintln!("convention self-check failed on frame {frame_index:04}: {e}"),
             }
-        };
+        }
 
-        let csv_path = results_dir.join(format!("frame_{frame_index:04}_results.csv"));
-        let mut candidate_writer = csv::Writer::from_path(csv_path).unwrap();
+        let window = self.search.window(navigation_yaw_deg);
+        let yaw_offsets = window.offsets(self.resolution_deg);
 
-        let interval_size = 10.;
-        let car_in_ins_enu = ins_frame.orientation;
-        let (car_yaw, pitch, roll) = car_in_ins_enu.to_tait_bryan_angles();
-        let mut yaw_offset = -Angle::new::<degree>(interval_size / 2.);
-
-        let iters = config.iters_at_resolution(interval_size);
-        for candidate_index in 0..iters {
-            let t1 = Instant::now();
-
-            // Figure out the orientation of the camera in the ECEF frame.
-            let car_in_ins_enu: Orientation<InsEnu> = Orientation::tait_bryan_builder()
-                .yaw(car_yaw + yaw_offset)
-                .pitch(pitch)
-                .roll(roll)
-                .build();
-            let cam_in_ins_enu = systems::car_to_ins(car_in_ins_enu).transform(cam_in_car);
-            let cam_in_ecef = systems::ins_to_ecef(&ins_frame.position).transform(cam_in_ins_enu);
-
-            let up = up_in_cam(car_in_ins_enu).normalized();
-            let azimuth = up.y().atan2(up.x());
-            // HACK: I do not know why the trait bounds for ...z().acos(); are violated...
-            let polar = Angle::new::<radian>(up.z().value.acos());
-            let ray_direction = RayDirection::from_angles(polar, azimuth);
-            let Some(up_pixel) = camera.trace_from_bearing(ray_direction) else {
-                println!("global zenith is outside of camera fov! skipping...");
-                continue;
-            };
+        // Searches the yaw candidates with the vertical reference tilted by the given amounts.
+        let estimator = &self.estimator;
+        let input = |pitch_offset: Angle, roll_offset: Angle| FrameInput {
+            frame_index,
+            image: &image,
+            image_circle,
+            position: outage_position.as_ref().unwrap_or(&frame.ins.position),
+            time: frame.time,
+            car_in_ins_enu: Orientation::tait_bryan_builder()
+                .yaw(Angle::new::<degree>(navigation_yaw_deg))
+                .pitch(pitch + pitch_offset)
+                .roll(roll + roll_offset)
+                .build(),
+            yaw_smear: frame.yaw_smear,
+        };
 
-            let measured = sensor_to_global(&image, &up_pixel);
-            let simulation = Simulation::new(camera, cam_in_ecef, time_frame.time);
-            let simulated = simulation.par_ray_image();
-            let weighted_rmse = weighted_rmse(&simulated, &measured);
+        let search_method = self.search_method;
+        let resolution_deg = self.resolution_deg;
+        let search_with = |estimator: &HeadingEstimator,
+                           pitch_offset: Angle,
+                           roll_offset: Angle,
+                           timing: &mut TimingRecord| {
+            let input = input(pitch_offset, roll_offset);
+            match search_method {
+                SearchMethod::Sweep => Ok(estimator.sweep(&input, &yaw_offsets)),
+                SearchMethod::Correlation => {
+                    estimator.correlate(&input, &window, resolution_deg, timing)
+                }
+            }
+        };
+        let search = |pitch_offset: Angle, roll_offset: Angle, timing: &mut TimingRecord| {
+            search_with(estimator, pitch_offset, roll_offset, timing)
+        };
 
-            let _ = candidate_writer.serialize(CandidateRecord {
+        let candidates = search(Angle::ZERO, Angle::ZERO, &mut frame_timing)
+            .map_err(|e| FrameSkip::new(SkipReason::SimulationFailed, e))?;
+        for candidate in &candidates {
+            frame_timing.accumulate(&candidate.timing);
+            if search_method == SearchMethod::Sweep {
+                let _ = self.timings_writer.serialize(candidate.timing);
+            }
+            ResultRow::new(&CandidateRecord {
                 frame_index,
                 car_yaw_deg: car_yaw.get::<degree>(),
-                yaw_offset_deg: yaw_offset.get::<degree>(),
-                weighted_rmse,
-            });
+                weighted_rmse: candidate.weighted_rmse,
+                yaw_offset_deg: candidate.yaw_offset_deg + coast_offset_deg,
+                valid_fraction: candidate.valid_fraction,
+            })
+            .and_then(|row| self.sink.write_candidate(frame_index, row))
+            .map_err(|e| FrameSkip::new(SkipReason::UnwritableResults, e))?;
+        }
 
-            match config.max_frames {
-                Some(max_frames) => println!(
-                    "[{:04}/{:04}] frame {:04}: [{:04}/{:04}] candidate in {:05} ms",
-                    frame_count + 1,
-                    max_frames,
-                    frame_index,
-                    candidate_index + 1,
-                    iters,
-                    t1.elapsed().as_millis(),
-                ),
-                None => println!(
-                    "[{:04}/????] frame {:04}: [{:04}/{:04}] candidate in {:05} ms",
-                    frame_count + 1,
-                    frame_index,
-                    candidate_index + 1,
-                    iters,
-                    t1.elapsed().as_millis(),
-                ),
+        // A distribution of AoP unlike the simulated one points at the calibration or the
+        // conventions rather than the heading, and would skew the estimate.
+        record.aop_emd_deg = candidates
+            .iter()
+            .min_by(|a, b| a.weighted_rmse.total_cmp(&b.weighted_rmse))
+            .and_then(|best| best.aop_emd_deg);
+        record.aop_mismatch = record
+            .aop_emd_deg
+            .zip(self.max_aop_emd_deg)
+            .is_some_and(|(emd, max_emd)| emd > max_emd);
+        if record.aop_mismatch {
+            eprintln!(
+                "WARNING: frame {frame_index:04} AoP histograms are {:.1} deg apart; check the calibration and AoP conventions",
+                record.aop_emd_deg.unwrap_or_default()
+            );
+        }
+
+        // Pick the heading that best explains the measured sky.
+        let estimate = estimate_heading(&candidates);
+        frame_timing.latency_ms = Some(t0.elapsed().as_secs_f64() * 1e3);
+        let _ = self.timings_writer.serialize(frame_timing);
+        self.frame_timings.push(frame_timing);
+        if self.adaptive_window {
+            self.search.update(navigation_yaw_deg, estimate.as_ref());
+        }
+        // From here on offsets are from the INS yaw rather than the search centre.
+        let estimate = estimate.map(|e| HeadingEstimate {
+            yaw_offset_deg: e.yaw_offset_deg + coast_offset_deg,
+            ..e
+        });
+        if frame.outage.is_denied()
+            && let Some((coast, estimate)) = self.coast.as_mut().zip(estimate)
+        {
+            coast.update(
+                wrap_deg(car_yaw.get::<degree>() + estimate.yaw_offset_deg),
+                frame.time,
+            );
+        }
+        print_frame_verdict(frame_index, car_yaw.get::<degree>(), estimate.as_ref());
+        if let Some((udp_sink, estimate)) = self.udp_sink.as_ref().zip(estimate) {
+            let message = HeadingMessage {
+                frame_index,
+                time: frame.time,
+                heading_deg: HeadingConventions::from_ins_yaw(
+                    car_yaw.get::<degree>(),
+                    estimate.yaw_offset_deg,
+                )
+                .true_heading_deg,
+                confidence: estimate.confidence(),
+            };
+            if let Err(e) = udp_sink.send(&message) {
+                eprintln!("failed to send heading for frame {frame_index}: {e}");
             }
+        }
 
-            yaw_offset += config.resolution();
+        // Measure how far the heading moves when the vertical reference is wrong.
+        if let Some(sensitivity_writer) = self.sensitivity_writer.as_mut() {
+            for &perturbation_deg in &self.perturb_deg {
+                let perturbation = Angle::new::<degree>(perturbation_deg);
+                for (pitch_offset, roll_offset) in [
+                    (perturbation, Angle::ZERO),
+                    (-perturbation, Angle::ZERO),
+                    (Angle::ZERO, perturbation),
+                    (Angle::ZERO, -perturbation),
+                ] {
+                    let perturbed = search(
+                        pitch_offset,
+                        roll_offset,
+                        &mut TimingRecord::frame(frame_index),
+                    )
+                    .ok()
+                    .and_then(|candidates| estimate_heading(&candidates));
+                    let _ = sensitivity_writer.serialize(SensitivityRecord {
+                        frame_index,
+                        pitch_offset_deg: pitch_offset.get::<degree>(),
+                        roll_offset_deg: roll_offset.get::<degree>(),
+                        yaw_error_deg: perturbed.map(|p| p.yaw_offset_deg + coast_offset_deg),
+                        heading_shift_deg: estimate
+                            .zip(perturbed)
+                            .map(|(e, p)| p.yaw_offset_deg + coast_offset_deg - e.yaw_offset_deg),
+                    });
+                }
+            }
         }
 
         // Write results from this frame to the CSV file.
-        let (_car_yaw, car_pitch, car_roll) = car_in_ins_enu.to_tait_bryan_angles();
-        let _ = frame_writer.serialize(FrameRecord {
-            frame_index,
-            car_yaw_deg: car_yaw.get::<degree>(),
-            car_pitch_deg: car_pitch.get::<degree>(),
-            car_roll_deg: car_roll.get::<degree>(),
-        });
+        let conventions = estimate
+            .map(|e| HeadingConventions::from_ins_yaw(car_yaw.get::<degree>(), e.yaw_offset_deg));
+        record.estimated_yaw_deg = estimate.map(|e| car_yaw.get::<degree>() + e.yaw_offset_deg);
+        record.estimated_heading_deg = conventions.map(|c| c.true_heading_deg);
+        record.estimated_enu_yaw_deg = conventions.map(|c| c.enu_yaw_deg);
+        record.yaw_error_deg = conventions.map(|c| c.ins_offset_deg);
+        let counted = !record.bad_exposure && !record.ins_degraded && !record.aop_mismatch;
+        if counted && let Some(yaw_error_deg) = record.yaw_error_deg {
+            self.yaw_errors_deg
+                .push((yaw_error_deg.abs(), record.glare_weight));
+            if let Some(variance) = frame
+                .ins
+                .covariance
+                .map(|covariance| covariance.attitude_deg2[2])
+                .filter(|&variance| variance > 0.)
+            {
+                self.weighted_yaw_errors_deg
+                    .push((yaw_error_deg.abs(), variance.recip()));
+            }
+            self.tag_errors.add(&tags, yaw_error_deg);
+            self.outage_errors.add(frame.outage, yaw_error_deg);
+            self.yaw_error_series.push((frame.time, yaw_error_deg));
+        }
 
-        print_frame_status(
-            frame_index,
-            frame_count,
-            config.max_frames,
-            Some(t0.elapsed().as_millis()),
-        );
+        // Estimate the frame again through each of the swept camera intrinsics.
+        for variant in &mut self.intrinsics {
+            let variant_estimate = search_with(
+                &variant.estimator,
+                Angle::ZERO,
+                Angle::ZERO,
+                &mut TimingRecord::frame(frame_index),
+            )
+            .ok()
+            .and_then(|candidates| estimate_heading(&candidates));
+            let yaw_error_deg = variant_estimate.map(|e| {
+                HeadingConventions::from_ins_yaw(
+                    car_yaw.get::<degree>(),
+                    e.yaw_offset_deg + coast_offset_deg,
+                )
+                .ins_offset_deg
+            });
+            if counted && let Some(yaw_error_deg) = yaw_error_deg {
+                variant.errors_deg.push(yaw_error_deg);
+            }
+            if let Some(intrinsics_writer) = self.intrinsics_writer.as_mut() {
+                let _ = intrinsics_writer.serialize(IntrinsicsRecord {
+                    frame_index,
+                    focal_length_mm: variant.focal_length_mm,
+                    pixel_size_um: variant.pixel_size_um,
+                    yaw_error_deg,
+                    heading_shift_deg: yaw_error_deg
+                        .zip(record.yaw_error_deg)
+                        .map(|(variant, nominal)| wrap_deg(variant - nominal)),
+                });
+            }
+        }
 
-        frame_count += 1;
-        if let Some(max_frames) = config.max_frames
-            && frame_count >= max_frames
+        record.best_weighted_rmse = estimate.map(|e| e.cost);
+        record.cost_sharpness = estimate.and_then(|e| e.sharpness);
+        record.basin_ratio = estimate.and_then(|e| e.basin_ratio);
+        record.valid_fraction = estimate.map(|e| e.valid_fraction);
+        record.confidence = estimate.map(|e| e.confidence());
+        if let Some(best) = candidates
+            .iter()
+            .min_by(|a, b| a.weighted_rmse.total_cmp(&b.weighted_rmse))
         {
-            break;
+            record.valid_pixels = Some(best.validity.valid);
+            record.no_data_pixels = Some(best.validity.no_data);
+            record.outside_image_circle_pixels = Some(best.validity.outside_image_circle);
+            record.not_simulated_pixels = Some(best.validity.not_simulated);
+            record.transform_dropped_pixels = Some(best.validity.transform);
+            record.masked_pixels = Some(best.validity.masked);
         }
+        let _ = write_frame(self.sink.as_mut(), &record);
+
+        if let Some(monitor) = &self.monitor {
+            monitor.update(|progress| {
+                progress.candidates_evaluated += candidates.len();
+                progress.last_weighted_rmse = record.best_weighted_rmse;
+                progress.cost_curve = candidates
+                    .iter()
+                    .map(|c| (c.yaw_offset_deg + coast_offset_deg, c.weighted_rmse))
+                    .collect();
+                if let Some(yaw_error_deg) = record.yaw_error_deg {
+                    progress
+                        .heading_errors_deg
+                        .push((frame_index, yaw_error_deg));
+                }
+            });
+            if self.serves_dashboard {
+                monitor.set_image(dashboard_image(
+                    &image,
+                    &self.estimator,
+                    car_in_ins_enu,
+                    record.estimated_heading_deg,
+                ));
+            }
+        }
+
+        Ok(())
     }
 }
 
-fn image_path_from_frame(frame_index: usize) -> impl AsRef<Path> {
-    format!("camera_driver_gv_vis_image_raw_{:04}.png", frame_index)
+/// Measured AoP at the INS attitude with the true and estimated headings drawn over it.
+fn dashboard_image(
+    image: &RayImage<SensorFrame>,
+    estimator: &HeadingEstimator,
+    car_in_ins_enu: Orientation<InsEnu>,
+    estimated_heading_deg: Option<f64>,
+) -> RgbaImage {
+    let camera = estimator.camera();
+    let measured = measured_to_global(image, camera, car_in_ins_enu);
+    #[allow(clippy::cast_possible_truncation)]
+    let (cols, rows) = (camera.cols() as u32, camera.rows() as u32);
+    let mut overlay = Overlay::from_rgb(&measured.aop_bytes(&Jet), cols, rows);
+    let (ins_yaw, _, _) = car_in_ins_enu.to_tait_bryan_angles();
+    overlay.draw_heading_comparison(
+        camera,
+        car_in_ins_enu,
+        heading_from_yaw_deg(ins_yaw.get::<degree>()),
+        estimated_heading_deg,
+    );
+    overlay.image().clone()
 }
 
-fn print_frame_status(
-    frame_index: usize,
-    frame_count: usize,
-    max_frames: Option<usize>,
-    elapsed_millis: Option<u128>,
-) {
-    let max_frames_fmt = match max_frames {
-        Some(max_frames) => format!("{max_frames:04}"),
-        None => "????".to_string(),
-    };
+fn write_frame(sink: &mut dyn ResultSink, record: &FrameRecord) -> Result<(), BenchError> {
+    sink.write_frame(ResultRow::new(record)?)
+}
 
-    let elapsed_millis_fmt = match elapsed_millis {
-        Some(elapsed_millis) => format!("in {elapsed_millis:05} ms"),
-        None => "".to_string(),
+fn print_frame_verdict(frame_index: usize, ins_yaw_deg: f64, estimate: Option<&HeadingEstimate>) {
+    let Some(estimate) = estimate else {
+        println!("frame {frame_index:04}: no valid candidates");
+        return;
     };
 
-    let frame_number = frame_count + 1;
-    println!("[{frame_number:04}/{max_frames_fmt}] frame {frame_index:04} {elapsed_millis_fmt}");
+    let yaw_deg = ins_yaw_deg + estimate.yaw_offset_deg;
+    let sharpness_fmt = match estimate.sharpness {
+        Some(sharpness) => format!("sharpness {sharpness:.4}"),
+        None => "minimum at edge of search window".to_string(),
+    };
+    println!(
+        "frame {frame_index:04}: yaw {yaw_deg:.2} deg, error {:+.2} deg vs INS, rmse {:.4}, {sharpness_fmt}, confidence {:.2}",
+        estimate.yaw_offset_deg,
+        estimate.cost,
+        estimate.confidence()
+    );
 }
 
 #[derive(Parser)]
 struct Cli {
-    dataset_path: PathBuf,
-
-    #[arg(short, long)]
-    max_frames: Option<usize>,
+    #[command(flatten)]
+    dataset: DatasetArgs,
 
-    #[arg(short, long)]
-    write_images: bool,
+    #[command(flatten)]
+    sky: SkyArgs,
 
-    #[arg(short, long, default_value_t = 1)]
-    step: usize,
+    #[command(flatten)]
+    notify: NotifyArgs,
 
     #[arg(short, long, default_value_t = 0.1)]
     resolution_deg: f64,
-}
 
-impl Cli {
-    fn image_dir(&self) -> PathBuf {
-        self.dataset_path.join("camera_driver_gv_vis_image_raw")
-    }
+    /// How yaw candidates are scored.
+    ///
+    /// Correlation simulates one sky per frame and matches azimuth profiles at
+    /// `resolution_deg`, writing its cost in place of the weighted RMSE.
+    #[arg(long, value_enum, default_value_t = SearchMethod::Sweep)]
+    search: SearchMethod,
 
-    fn ins_path(&self) -> PathBuf {
-        self.dataset_path
-            .join("novatel_oem7_inspva/novatel_oem7_inspva.csv")
-    }
+    /// Frame swept candidates are compared in.
+    ///
+    /// The sensor frame converts each simulated sky along the meridian of every pixel instead
+    /// of converting the measurement around the zenith pixel. The scattering plane frame refers
+    /// the measurement to the light source found in it, which leaves the model error without
+    /// the ephemeris error but hardly constrains the heading. Correlation always uses the
+    /// global frame.
+    #[arg(long, value_enum, default_value_t = ComparisonFrame::Global)]
+    comparison_frame: ComparisonFrame,
 
-    fn time_path(&self) -> PathBuf {
-        self.dataset_path
-            .join("novatel_oem7_time/novatel_oem7_time.csv")
-    }
+    /// Half width of the yaw search around the INS heading.
+    #[arg(long, default_value_t = 5.0)]
+    window_deg: f64,
 
-    fn iters_at_resolution(&self, interval_size: f64) -> usize {
-        (interval_size / self.resolution_deg) as usize
-    }
+    /// Center and shrink the search window around the previous frame's estimate.
+    #[arg(long)]
+    adaptive_window: bool,
 
-    fn resolution(&self) -> Angle {
-        Angle::new::<degree>(self.resolution_deg)
-    }
+    /// Smallest half width of the adaptive search window.
+    #[arg(long, default_value_t = 1.0)]
+    min_window_deg: f64,
+
+    /// Send each frame's heading estimate as a UDP datagram to this address.
+    #[arg(long)]
+    udp: Option<SocketAddr>,
+
+    /// Format of the heading datagrams.
+    #[arg(long, value_enum, default_value_t = OutputFormat::Nmea)]
+    udp_format: OutputFormat,
+
+    /// Pitch and roll perturbations used to measure heading sensitivity to attitude errors.
+    ///
+    /// Each value is applied to pitch and roll separately in both directions.
+    #[arg(long, value_delimiter = ',')]
+    perturb_deg: Vec<f64>,
+
+    /// Focal lengths to also estimate every frame with, to see how much the heading depends
+    /// on the assumed intrinsics.
+    #[arg(long, value_delimiter = ',')]
+    sweep_focal_length_mm: Vec<f64>,
+
+    /// Pixel sizes to also estimate every frame with, combined with every swept focal length.
+    #[arg(long, value_delimiter = ',')]
+    sweep_pixel_size_um: Vec<f64>,
+
+    /// Match confidence below which the adaptive search falls back to the full window.
+    #[arg(long, default_value_t = 0.0)]
+    min_confidence: f64,
+
+    /// Frames whose measured AoP histogram is further than this, in degrees, from the one
+    /// simulated at the best candidate are flagged as a likely calibration or convention error
+    /// and left out of the aggregate heading error.
+    #[arg(long)]
+    max_aop_emd_deg: Option<f64>,
+
+    /// Name of this run on the dataset leaderboard, instead of its start time.
+    #[arg(long)]
+    run_name: Option<String>,
+
+    /// Where frame and candidate results are stored.
+    #[arg(long, value_enum, default_value_t = SinkFormat::Csv)]
+    sink: SinkFormat,
+
+    /// Add the run and its results to this SQLite database instead, which other runs can share.
+    #[arg(long)]
+    results_db: Option<PathBuf>,
+
+    /// Width of the spans of time into and after a GNSS outage that heading errors are
+    /// summarized over.
+    #[arg(long, default_value_t = 10.0)]
+    outage_bin_s: f64,
+
+    /// Resources the run may use; `embedded` runs on one thread without rayon, scores
+    /// candidates in f32, preallocates its buffers and writes no images.
+    #[arg(long, value_enum, default_value_t = RunProfile::Workstation)]
+    profile: RunProfile,
+
+    /// Estimate the frames, candidates, runtime and output size of the run without starting it.
+    #[arg(long)]
+    dry_run: bool,
+
+    /// Serve a dashboard of the run's progress, last frame and heading errors on this address,
+    /// such as `0.0.0.0:8080`.
+    #[arg(long)]
+    serve: Option<SocketAddr>,
+
+    /// Serve Prometheus metrics of the run at `/metrics` on this address.
+    #[arg(long)]
+    metrics: Option<SocketAddr>,
 }
 
-#[derive(serde::Serialize)]
+#[derive(Clone, serde::Serialize)]
 struct FrameRecord {
     frame_index: usize,
+    tags: String,
     car_pitch_deg: f64,
     car_roll_deg: f64,
     car_yaw_deg: f64,
+    latitude_deg: f64,
+    longitude_deg: f64,
+    /// Altitude the sky was simulated at.
+    altitude_m: f64,
+    ins_azimuth_sd_deg: Option<f64>,
+    cloud_cover: Option<f64>,
+    visibility_km: Option<f64>,
+    aerosol_index: Option<f64>,
+    light_source: LightSource,
+    source_elevation_deg: f64,
+    source_too_low: bool,
+    sun_azimuth_deg: f64,
+    sun_elevation_deg: f64,
+    sun_in_fov: bool,
+    /// Weight of the frame in the aggregate heading error.
+    glare_weight: f64,
+    yaw_rate_deg_s: Option<f64>,
+    yaw_rate_exceeded: bool,
+    tilted: bool,
+    ins_status: Option<InsStatus>,
+    ins_degraded: bool,
+    /// Whether the estimator was without GNSS, and for how long into or after the outage.
+    outage_phase: &'static str,
+    outage_elapsed_s: Option<f64>,
+    /// Yaw the search was centred on, which is the INS yaw unless coasting.
+    navigation_yaw_deg: f64,
+    saturated_fraction: Option<f64>,
+    underexposed_fraction: Option<f64>,
+    bad_exposure: bool,
+    /// Earth mover's distance between the measured and simulated AoP histograms.
+    aop_emd_deg: Option<f64>,
+    aop_mismatch: bool,
+    estimated_yaw_deg: Option<f64>,
+    /// Clockwise from true north.
+    estimated_heading_deg: Option<f64>,
+    /// REP-103, counter-clockwise from east.
+    estimated_enu_yaw_deg: Option<f64>,
+    /// Signed offset of the estimate from the INS yaw, counter-clockwise.
+    yaw_error_deg: Option<f64>,
+    best_weighted_rmse: Option<f64>,
+    cost_sharpness: Option<f64>,
+    basin_ratio: Option<f64>,
+    valid_fraction: Option<f64>,
+    confidence: Option<f64>,
+    /// Pixels of the best candidate compared, and left out for each reason.
+    valid_pixels: Option<usize>,
+    no_data_pixels: Option<usize>,
+    outside_image_circle_pixels: Option<usize>,
+    not_simulated_pixels: Option<usize>,
+    transform_dropped_pixels: Option<usize>,
+    masked_pixels: Option<usize>,
+}
+
+/// Camera intrinsics swept with `--sweep-focal-length-mm` and `--sweep-pixel-size-um`, and the
+/// heading errors of the counted frames estimated through them.
+struct IntrinsicsVariant {
+    focal_length_mm: f64,
+    pixel_size_um: f64,
+    estimator: HeadingEstimator,
+    errors_deg: Vec<f64>,
+}
+
+#[derive(serde::Serialize)]
+struct IntrinsicsRecord {
+    frame_index: usize,
+    focal_length_mm: f64,
+    pixel_size_um: f64,
+    yaw_error_deg: Option<f64>,
+    /// Change of the estimate from the one with the nominal intrinsics.
+    heading_shift_deg: Option<f64>,
+}
+
+#[derive(serde::Serialize)]
+struct IntrinsicsSummaryRecord {
+    focal_length_mm: f64,
+    pixel_size_um: f64,
+    /// Whether these are the intrinsics of the main estimate.
+    nominal: bool,
+    frames: usize,
+    mean_error_deg: f64,
+    rms_error_deg: f64,
+    max_abs_error_deg: f64,
+}
+
+#[derive(serde::Serialize)]
+struct SensitivityRecord {
+    frame_index: usize,
+    pitch_offset_deg: f64,
+    roll_offset_deg: f64,
+    yaw_error_deg: Option<f64>,
+    heading_shift_deg: Option<f64>,
 }
 
 #[derive(serde::Serialize)]
@@ -248,4 +1084,5 @@ struct CandidateRecord {
     car_yaw_deg: f64,
     weighted_rmse: f64,
     yaw_offset_deg: f64,
+    valid_fraction: f64,
 }
@@ -1,202 +1,1003 @@
-use chrono::Local;
+use chrono::{DateTime, Utc};
 use clap::Parser;
 use rumpus::{
-    image::{Gray, Jet, RayImage, RayMap},
-    optic::{Camera, PinholeOptic, RayDirection},
-    simulation::Simulation,
+    image::{Gray, Jet, RayImage},
+    optic::PixelCoordinate,
+    ray::GlobalFrame,
 };
 use rumpus_benchmark::{
-    io::{ImageReader, InsReader, TimeReader},
-    systems::{self, CamXyz, up_in_cam},
-    utils::{sensor_to_global, weighted_rmse},
+    annotate::FrameLabel,
+    camera::CameraModel,
+    cli::{DatasetArgs, NotifyArgs, SkyArgs, interrupt_on_ctrl_c, parse_positive, run_metadata},
+    conventions::ConventionCheck,
+    ephemeris::CelestialPosition,
+    error::BenchError,
+    export::{
+        ImageFormat, NpyArray, NpzRunWriter, StokesFormat, bearing_arrays, ray_image_arrays,
+        sky_direction_arrays, write_npz, write_stokes,
+    },
+    exposure::{ExposureAction, ExposureGate},
+    glare::{GlareConfig, GlareStrategy},
+    histogram::aop_emd_deg,
+    image_writer::ImageWriter,
+    incremental::{IncrementalSky, IncrementalStats},
+    io::{ImageReader, InsStatus},
+    neutral::{NeutralPoint, find_neutral_points},
+    notify::CompletionReport,
+    overlay::{MARKER_RGBA, Overlay, SUN_RGBA, TRUE_HEADING_RGBA},
+    pipeline::{FrameContext, FrameProcessor, FrameSkip, ResultWriter},
+    run::{LatencyPercentiles, RunProfile, SkipReason, TimingRecord, timed},
+    sink::{ResultRow, ResultSink, SinkFormat, open_database},
+    sky::{self, LightSource, Sky},
+    stats::ErrorSummary,
+    systems::{InsEnu, heading_from_yaw_deg},
+    tags::{FrameTags, StratifiedErrors},
+    utils::{
+        banded_weighted_rmse, dop_rmse, measured_to_global, paired_errors, weighted_rmse,
+        weighted_rmse_excluding_low_dop, weighted_rmse_unmasked,
+    },
 };
 use sguaba::engineering::Orientation;
-use std::{
-    path::{Path, PathBuf},
-    time::Instant,
-};
-use uom::si::{
-    angle::{degree, radian},
-    f64::{Angle, Length},
-    length::{micron, millimeter},
+use std::{collections::BTreeSet, fs::File, num::NonZeroUsize, path::PathBuf, time::Instant};
+use uom::{
+    ConstZero,
+    si::{
+        angle::degree,
+        f64::{Angle, Length},
+        length::{meter, micron, millimeter},
+    },
 };
 
 const FOCAL_LENGTH_MM: f64 = 8.0;
 
-#[allow(clippy::similar_names)]
+/// Bands of sky elevation with their own weighted RMSE, since single scattering breaks down
+/// towards the horizon.
+const ELEVATION_BANDS_DEG: [(f64, f64); 3] = [(0., 30.), (30., 60.), (60., 90.)];
+
 fn main() {
     let config = Cli::parse();
-    let timestamp = Local::now().to_rfc3339();
-    let results_dir = PathBuf::from(&timestamp);
-    std::fs::create_dir(&results_dir).unwrap();
+    config.profile.apply().unwrap();
+    let writes_images = config.profile.writes_images();
+    if !writes_images && (config.write_images || config.stokes.is_some()) {
+        eprintln!("the {:?} profile writes no images", config.profile);
+    }
+
+    // Make a new directory to hold results, or a scratch one for a dry run.
+    let results = if config.dry_run {
+        ResultWriter::create_in(std::env::temp_dir())
+    } else {
+        ResultWriter::create()
+    }
+    .unwrap();
+    let mut metadata = run_metadata(
+        env!("CARGO_BIN_NAME"),
+        results.started(),
+        &config.dataset,
+        &config.sky,
+    );
+    metadata.profile = config.profile;
+    metadata.write(results.dir()).unwrap();
 
-    let cam_in_car = systems::cam_to_car().transform(Orientation::<CamXyz>::aligned());
-    let ins_path = config.ins_path();
-    let ins_reader = InsReader::new();
-    let ins_frames = ins_reader.read_csv(&ins_path).unwrap();
+    // Tell someone when the run ends, including if it fails part way.
+    let hooks = config.notify.hooks().unwrap();
+    let report = CompletionReport::new(env!("CARGO_BIN_NAME"), results.started(), results.dir());
+    if !config.dry_run {
+        hooks.clone().notify_on_panic(report.clone());
+    }
 
-    let time_path = config.time_path();
-    let time_reader = TimeReader::new();
-    let time_frames = time_reader.read_csv(&time_path).unwrap();
+    let pipeline = config
+        .dataset
+        .pipeline()
+        .unwrap()
+        .with_interrupt(interrupt_on_ctrl_c());
+    let sky = config
+        .sky
+        .sky()
+        .unwrap()
+        .with_parallel(config.profile.parallel());
+    // Report how far the sky lookup table is from the model it stands in for.
+    if let Some(lut_accuracy) = sky.lut_accuracy() {
+        lut_accuracy.print();
+        lut_accuracy.write(results.dir()).unwrap();
+    }
 
     let focal_length = Length::new::<millimeter>(FOCAL_LENGTH_MM);
     let pixel_size = Length::new::<micron>(3.45);
-    let image_reader = ImageReader::new();
-    let camera = Camera::new(
-        PinholeOptic::from_focal_length(focal_length),
-        pixel_size * 2.0,
-        1024,
-        1224,
-    );
+    let image_reader = config.dataset.prefetching_image_reader(&pipeline).unwrap();
+    let mut processor = SimulationProcessor {
+        camera_model: CameraModel::for_mosaic(focal_length, pixel_size, 2048, 2448, &image_reader)
+            .with_principal_point(config.dataset.principal_point(&image_reader)),
+        sky,
+        image_reader,
+        exposure_gate: config.dataset.exposure_gate(),
+        frame_tags: config.dataset.frame_tags().unwrap(),
+        tag_errors: StratifiedErrors::new(),
+        min_source_elevation_deg: config.sky.min_source_elevation_deg,
+        glare: config.sky.glare(),
+        min_dop: config.min_dop,
+        max_aop_emd_deg: config.max_aop_emd_deg,
+        convention_check: (!config.dataset.no_convention_check)
+            .then(|| results.csv("convention_check.csv").unwrap()),
+        fit_turbidity: config.fit_turbidity,
+        incremental: config.incremental_tolerance_deg.map(|tolerance_deg| {
+            IncrementalSky::new(tolerance_deg, config.incremental_max_error_deg)
+        }),
+        incremental_stats: Vec::new(),
+        // Neutral point diagnostics are only written if requested.
+        neutral_points: config.neutral_points.then(|| NeutralPointSearch {
+            block: config.neutral_block.get(),
+            max_dop: config.neutral_max_dop,
+            max_points: config.max_neutral_points,
+            writer: results.csv("neutral_points.csv").unwrap(),
+        }),
+        images: (config.write_images && writes_images).then(|| ImageOutput {
+            dir: results.dir().to_path_buf(),
+            format: config.image_format,
+            every: config.image_every,
+            frames: config.image_frames.iter().copied().collect(),
+            annotation_scale: config.annotate.then_some(config.annotation_scale),
+            writer: ImageWriter::new(config.image_writers).with_scale(config.image_scale),
+        }),
+        stokes_format: config.stokes.filter(|_| writes_images),
+        npz_writer: config
+            .npz
+            .then(|| NpzRunWriter::new(results.dir().join("run.npz"))),
+        sky_directions: config.sky_directions,
+        results_dir: results.dir().to_path_buf(),
+        // A shared results database is left alone by dry runs.
+        sink: match config.results_db.as_ref().filter(|_| !config.dry_run) {
+            Some(path) => open_database(path, results.dir(), &metadata),
+            None => config.sink.open(results.dir(), &metadata),
+        }
+        .unwrap(),
+        timings_writer: results.csv("timings.csv").unwrap(),
+        frame_timings: Vec::new(),
+        stats_writer: results.csv("frame_stats.csv").unwrap(),
+    };
 
-    let csv_path = results_dir.join("results.csv");
-    let mut writer = csv::Writer::from_path(csv_path).unwrap();
+    if config.dry_run {
+        pipeline.dry_run(processor, results.dir()).print();
+        std::fs::remove_dir_all(results.dir()).unwrap();
+        return;
+    }
 
-    let mut frame_count = 0;
-    for (i, (time_frame, ins_frame)) in time_frames.zip(ins_frames).enumerate().step_by(config.step)
-    {
-        let t0 = Instant::now();
+    // Bearings only depend on the camera, so they are written once per run.
+    if config.sky_directions {
+        let [x, y, z] = bearing_arrays(&processor.camera_model);
+        let path = results.dir().join("camera_bearings.npz");
+        write_npz(
+            &path,
+            &[("bearing_x", x), ("bearing_y", y), ("bearing_z", z)],
+        )
+        .unwrap();
+    }
 
-        let car_in_ins_enu = ins_frame.orientation;
-        let cam_in_ins_enu = systems::car_to_ins(car_in_ins_enu).transform(cam_in_car);
-        let cam_in_ecef = systems::ins_to_ecef(&ins_frame.position).transform(cam_in_ins_enu);
-        let simulation = Simulation::new(camera, cam_in_ecef, time_frame.time);
-        let simulated = simulation.par_ray_image();
-
-        let up = up_in_cam(car_in_ins_enu).normalized();
-        let azimuth = up.y().atan2(up.x());
-        // HACK: I do not know why the trait bounds for ...z().acos(); are violated...
-        let polar = Angle::new::<radian>(up.z().value.acos());
-        let ray_direction = RayDirection::from_angles(polar, azimuth);
-        let Some(up_pixel) = camera.trace_from_bearing(ray_direction) else {
-            println!("global zenith is outside of camera fov! skipping...");
-            continue;
-        };
+    let mut summary = pipeline.run(&mut processor);
+    summary.latency = LatencyPercentiles::of_frames(&processor.frame_timings);
+    processor.flush().unwrap();
+    if let Some(images) = processor.images.take() {
+        images.writer.finish();
+    }
+    processor.sink.finalize().unwrap();
+    summary.print();
+    summary.write(results.dir()).unwrap();
+    print_incremental_stats(&processor.incremental_stats);
+    if config.dataset.tags.is_some() || config.sky.weather_csv.is_some() {
+        let mut writer = results.csv("tag_summary.csv").unwrap();
+        for tag_summary in processor.tag_errors.summaries() {
+            tag_summary.print("weighted rmse");
+            writer.serialize(tag_summary).unwrap();
+        }
+    }
+    if summary.interrupted {
+        metadata.interrupted = true;
+        metadata.write(results.dir()).unwrap();
+    }
+    if let Some(profile) = processor.sky.profile() {
+        if profile.whole_images() > 0 {
+            eprintln!(
+                "{} rumpus skies were timed as a whole, their rows sharing the time evenly",
+                profile.whole_images()
+            );
+        }
+        profile
+            .write(results.dir(), processor.camera_model.cols())
+            .unwrap();
+    }
+    if let Some(npz_writer) = processor.npz_writer {
+        npz_writer.finish().unwrap();
+    }
+    hooks.notify(&report.with_summary(&summary));
+}
 
-        let image_path = config.image_dir().join(image_path_from_frame(i));
-        let image = image_reader.read_image(image_path).unwrap();
-        let measured = sensor_to_global(&image, &up_pixel);
+/// Compares the sky simulated at the INS attitude against each measured frame.
+struct SimulationProcessor {
+    camera_model: CameraModel,
+    sky: Sky,
+    image_reader: ImageReader,
+    exposure_gate: ExposureGate,
+    frame_tags: FrameTags,
+    /// Weighted RMSE of the same frames, by scenario tag.
+    tag_errors: StratifiedErrors,
+    min_source_elevation_deg: Option<f64>,
+    glare: GlareConfig,
+    min_dop: f64,
+    max_aop_emd_deg: Option<f64>,
+    /// Where to write the convention self-check of the first frame with an image, until it
+    /// has run.
+    convention_check: Option<csv::Writer<File>>,
+    fit_turbidity: bool,
+    /// Warps the previous frame's sky instead of simulating every frame, if enabled.
+    incremental: Option<IncrementalSky>,
+    incremental_stats: Vec<IncrementalStats>,
+    neutral_points: Option<NeutralPointSearch>,
+    /// Where and for which frames to write simulated and measured images, if at all.
+    images: Option<ImageOutput>,
+    /// Format to export the Stokes parameters of each frame in, if at all.
+    stokes_format: Option<StokesFormat>,
+    /// Stacks the measured and simulated arrays of every frame, if requested.
+    npz_writer: Option<NpzRunWriter>,
+    /// Adds the sky direction of every pixel to the arrays.
+    sky_directions: bool,
+    results_dir: PathBuf,
+    /// Frame records.
+    sink: Box<dyn ResultSink>,
+    timings_writer: csv::Writer<File>,
+    /// Stage times of every frame with a result, for the latency percentiles.
+    frame_timings: Vec<TimingRecord>,
+    stats_writer: csv::Writer<File>,
+}
+
+impl SimulationProcessor {
+    fn flush(&mut self) -> std::io::Result<()> {
+        self.timings_writer.flush()?;
+        self.stats_writer.flush()?;
+        if let Some(neutral_points) = self.neutral_points.as_mut() {
+            neutral_points.writer.flush()?;
+        }
+        Ok(())
+    }
+}
 
-        let weighted_rmse = weighted_rmse(&simulated, &measured);
+impl FrameProcessor for SimulationProcessor {
+    #[allow(clippy::similar_names)]
+    fn process(&mut self, frame: &FrameContext) -> Result<(), FrameSkip> {
+        let t0 = Instant::now();
+        let i = frame.frame_index;
+        let mut timing = TimingRecord::frame(i);
 
+        let car_in_ins_enu = frame.ins.orientation;
         let (_car_yaw, car_pitch, car_roll) = car_in_ins_enu.to_tait_bryan_angles();
-        let _ = writer.serialize(Record {
+
+        let sun = CelestialPosition::sun(&frame.ins.position, frame.time);
+        let sun_in_fov = sky::source_pixel(&self.camera_model, car_in_ins_enu, &sun).is_some();
+
+        let weather = self
+            .sky
+            .weather()
+            .and_then(|weather| weather.at(frame.time))
+            .copied();
+        let mut tags = self.frame_tags.tags(i);
+        if sun_in_fov {
+            tags.push("sun_in_view");
+        }
+        if let Some(weather) = &weather {
+            tags.extend_from_slice(&weather.tags());
+        }
+        let mut record = Record {
             frame_index: i,
-            origin_row: up_pixel.row(),
-            origin_col: up_pixel.col(),
+            tags: tags.join(";"),
+            origin_row: None,
+            origin_col: None,
             car_pitch_deg: car_pitch.get::<degree>(),
             car_roll_deg: car_roll.get::<degree>(),
-            weighted_rmse,
-        });
+            altitude_m: self
+                .sky
+                .altitude()
+                .altitude_m(frame.ins.position.altitude().get::<meter>()),
+            cloud_cover: weather.and_then(|w| w.cloud_cover),
+            visibility_km: weather.and_then(|w| w.visibility_km),
+            aerosol_index: weather.and_then(|w| w.aerosol_index),
+            weighted_rmse: None,
+            weighted_rmse_excluding_low_dop: None,
+            low_dop_pixels: None,
+            weighted_rmse_elevation_0_30: None,
+            weighted_rmse_elevation_30_60: None,
+            weighted_rmse_elevation_60_90: None,
+            turbidity: None,
+            dop_rmse: None,
+            aop_emd_deg: None,
+            aop_mismatch: false,
+            light_source: LightSource::Sun,
+            source_elevation_deg: 0.,
+            source_too_low: false,
+            sun_azimuth_deg: sun.azimuth.get::<degree>(),
+            sun_elevation_deg: sun.elevation.get::<degree>(),
+            sun_in_fov,
+            yaw_rate_deg_s: frame.yaw_rate_deg_s,
+            yaw_rate_exceeded: frame.yaw_rate_exceeded,
+            tilted: frame.tilted,
+            ins_status: frame.ins.status,
+            ins_degraded: frame.ins_degraded,
+            saturated_fraction: None,
+            underexposed_fraction: None,
+            bad_exposure: false,
+            reused_fraction: None,
+            incremental_error_deg: None,
+            incremental_bypassed: None,
+        };
 
-        if config.write_images {
-            // Get measured dop as a byte.
-            let bytes = measured.dop_bytes(&Gray);
-
-            for (prefix, ray_image) in [("simulated", &simulated), ("measured", &measured)] {
-                let filename = format!("{prefix}_aop_{i:04}.png");
-                let path = results_dir.join(&filename);
-                let aop_bytes = ray_image.aop_bytes(&Jet);
-                let _ = image::save_buffer(
-                    path,
-                    &aop_bytes,
-                    1224,
-                    1024,
-                    image::ExtendedColorType::Rgb8,
-                );
+        // Skip frames where the sky is too dark to be polarized by the light source.
+        let (light_source, source_position) =
+            self.sky.light_source(&frame.ins.position, frame.time);
+        record.light_source = light_source;
+        record.source_elevation_deg = source_position.elevation.get::<degree>();
+        if let Some(min_elevation_deg) = self.min_source_elevation_deg
+            && record.source_elevation_deg < min_elevation_deg
+        {
+            record.source_too_low = true;
+            let _ = write_frame(self.sink.as_mut(), &record);
+            return Err(FrameSkip::new(
+                SkipReason::SourceTooLow,
+                format!("light source is below {min_elevation_deg:.1} deg"),
+            ));
+        }
+        if sun_in_fov && self.glare.strategy == GlareStrategy::Skip {
+            let _ = write_frame(self.sink.as_mut(), &record);
+            return Err(FrameSkip::new(SkipReason::SunInView, "sun disc is in view"));
+        }
+
+        // Frames with the zenith out of view are converted pixel by pixel and have no origin.
+        let up_pixel = self.camera_model.zenith_pixel(car_in_ins_enu);
 
-                // Interleave alpha with RGB bytes.
-                let mut aop_with_alpha = Vec::with_capacity(bytes.len() * 4);
-                for (rgb, &a) in aop_bytes.chunks_exact(3).zip(&bytes) {
-                    aop_with_alpha.extend_from_slice(rgb);
-                    aop_with_alpha.push(a);
+        let (image, exposure, _) = timed(&mut timing.decode_ms, || {
+            self.image_reader
+                .read_image_with_exposure(&frame.image_path, self.exposure_gate.dark_level())
+        })
+        .map_err(|e| FrameSkip::new(SkipReason::UnreadableImage, e))?;
+        record.saturated_fraction = Some(exposure.saturated_fraction);
+        record.underexposed_fraction = Some(exposure.underexposed_fraction);
+        record.bad_exposure = self.exposure_gate.fails(&exposure);
+        if record.bad_exposure && self.exposure_gate.action() == ExposureAction::Skip {
+            let _ = write_frame(self.sink.as_mut(), &record);
+            return Err(FrameSkip::new(
+                SkipReason::BadExposure,
+                format!(
+                    "{:.1}% of super-pixels are badly exposed",
+                    100. * exposure.bad_fraction()
+                ),
+            ));
+        }
+        if let Some(mut writer) = self.convention_check.take() {
+            match ConventionCheck::run(
+                i,
+                &self.camera_model,
+                &self.sky,
+                &image,
+                &frame.ins.position,
+                frame.time,
+                car_in_ins_enu,
+            ) {
+                Ok(check) => {
+                    check.print();
+                    let _ = check.write(&mut writer);
                 }
-                let filename = format!("{prefix}_aop_rgba_{i:04}.png");
-                let path = results_dir.join(&filename);
-                let _ = image::save_buffer(
-                    path,
-                    &aop_with_alpha,
-                    1224,
-                    1024,
-                    image::ExtendedColorType::Rgba8,
-                );
+                Err(e) => eprintln!("convention self-check failed on frame {i:04}: {e}"),
+            }
+        }
+        if let Some(stokes_format) = self.stokes_format {
+            let prefix = format!("stokes_{i:04}");
+            if let Err(e) = self
+                .image_reader
+                .read_stokes(&frame.image_path)
+                .and_then(|stokes| write_stokes(&stokes, &self.results_dir, &prefix, stokes_format))
+            {
+                eprintln!("failed to export Stokes images of frame {i:04}: {e}");
+            }
+        }
+        let measured = timed(&mut timing.transform_ms, || {
+            measured_to_global(&image, &self.camera_model, car_in_ins_enu)
+        });
 
-                let filename = format!("{prefix}_dop_{i:04}.png");
-                let path = results_dir.join(&filename);
-                let _ = image::save_buffer(
-                    path,
-                    &ray_image.dop_bytes(&Jet),
-                    1224,
-                    1024,
-                    image::ExtendedColorType::Rgb8,
+        // Either use the configured turbidity or fit one that best explains the measured DoP.
+        let sky = if self.fit_turbidity {
+            self.sky.clone().with_turbidity(1.0)
+        } else {
+            self.sky.clone()
+        };
+        // Smeared exposures are always simulated in full.
+        let (simulated, incremental) = timed(&mut timing.simulate_ms, || {
+            match self
+                .incremental
+                .as_mut()
+                .filter(|_| frame.yaw_smear == Angle::ZERO)
+            {
+                Some(incremental) => incremental
+                    .simulate(
+                        &sky,
+                        &self.camera_model,
+                        &frame.ins.position,
+                        car_in_ins_enu,
+                        frame.time,
+                    )
+                    .map(|(simulated, stats)| (simulated, Some(stats))),
+                None => sky
+                    .simulate_smeared(
+                        &self.camera_model,
+                        &frame.ins.position,
+                        car_in_ins_enu,
+                        frame.time,
+                        frame.yaw_smear,
+                    )
+                    .map(|simulated| (simulated, None)),
+            }
+        })
+        .map_err(|e| FrameSkip::new(SkipReason::SimulationFailed, e))?;
+        if let Some(stats) = incremental {
+            record.reused_fraction = Some(stats.reused_fraction());
+            record.incremental_error_deg = Some(stats.max_error_deg);
+            record.incremental_bypassed = Some(stats.bypassed);
+            self.incremental_stats.push(stats);
+        }
+        let (turbidity, simulated) = if self.fit_turbidity {
+            let turbidity = sky::fit_turbidity(&simulated, &measured);
+            (turbidity, sky::scale_dop(&simulated, turbidity.recip()))
+        } else {
+            let altitude_m = sky
+                .altitude()
+                .altitude_m(frame.ins.position.altitude().get::<meter>());
+            (sky.turbidity_at(altitude_m), simulated)
+        };
+
+        let (weighted_rmse, dop_rmse, banded_rmse, (polarized_rmse, low_dop_pixels)) =
+            timed(&mut timing.rmse_ms, || {
+                let directions = sky::sky_directions(&self.camera_model, car_in_ins_enu);
+                let weighted_rmse = match self.glare.mask(&self.camera_model, car_in_ins_enu, &sun)
+                {
+                    Some(mask) => weighted_rmse_unmasked(&simulated, &measured, &mask),
+                    None => weighted_rmse(&simulated, &measured),
+                };
+                (
+                    weighted_rmse,
+                    dop_rmse(&simulated, &measured),
+                    banded_weighted_rmse(&simulated, &measured, &directions, &ELEVATION_BANDS_DEG),
+                    weighted_rmse_excluding_low_dop(&simulated, &measured, self.min_dop),
+                )
+            });
+        timing.latency_ms = Some(t0.elapsed().as_secs_f64() * 1e3);
+        let _ = self.timings_writer.serialize(timing);
+        self.frame_timings.push(timing);
+
+        let (aop_errors_deg, dop_errors) = paired_errors(&simulated, &measured);
+        let aop_stats = ErrorSummary::new(&aop_errors_deg);
+        let dop_stats = ErrorSummary::new(&dop_errors);
+        print_error_stats(i, aop_stats.as_ref(), dop_stats.as_ref());
+        for (quantity, stats) in [("aop_deg", aop_stats), ("dop", dop_stats)] {
+            if let Some(stats) = stats {
+                let _ = self.stats_writer.serialize(StatsRecord {
+                    frame_index: i,
+                    quantity,
+                    samples: stats.samples,
+                    mean_error: stats.mean,
+                    rms_error: stats.rms,
+                    standard_error: stats.standard_error,
+                    max_abs_error: stats.max_abs,
+                });
+            }
+        }
+
+        let aop_emd_deg = aop_emd_deg(&simulated, &measured);
+        let aop_mismatch = aop_emd_deg
+            .zip(self.max_aop_emd_deg)
+            .is_some_and(|(emd, max_emd)| emd > max_emd);
+        if aop_mismatch {
+            eprintln!(
+                "WARNING: frame {i:04} AoP histograms are {:.1} deg apart; check the calibration and AoP conventions",
+                aop_emd_deg.unwrap_or_default()
+            );
+        }
+
+        if !record.bad_exposure && !aop_mismatch {
+            self.tag_errors.add(&tags, weighted_rmse);
+        }
+
+        let _ = write_frame(
+            self.sink.as_mut(),
+            &Record {
+                origin_row: up_pixel.as_ref().map(PixelCoordinate::row),
+                origin_col: up_pixel.as_ref().map(PixelCoordinate::col),
+                weighted_rmse: Some(weighted_rmse),
+                weighted_rmse_excluding_low_dop: polarized_rmse
+                    .is_finite()
+                    .then_some(polarized_rmse),
+                low_dop_pixels: Some(low_dop_pixels),
+                weighted_rmse_elevation_0_30: banded_rmse[0],
+                weighted_rmse_elevation_30_60: banded_rmse[1],
+                weighted_rmse_elevation_60_90: banded_rmse[2],
+                turbidity: Some(turbidity),
+                dop_rmse: Some(dop_rmse),
+                aop_emd_deg,
+                aop_mismatch,
+                ..record
+            },
+        );
+
+        // Neutral points found in each image, as an independent check of the attitude.
+        let markers = match self.neutral_points.as_mut() {
+            Some(search) => search.compare(i, &simulated, &measured),
+            None => [Vec::new(), Vec::new()],
+        };
+
+        if let Some(npz_writer) = self.npz_writer.as_mut() {
+            let mut arrays = npz_arrays(&simulated, &measured);
+            if self.sky_directions {
+                let directions = sky::sky_directions(&self.camera_model, car_in_ins_enu);
+                let [azimuth_deg, elevation_deg] = sky_direction_arrays(
+                    &directions,
+                    self.camera_model.rows(),
+                    self.camera_model.cols(),
                 );
+                arrays.push(("sky_azimuth_deg", azimuth_deg));
+                arrays.push(("sky_elevation_deg", elevation_deg));
+            }
+            let path = self.results_dir.join(format!("frame_{i:04}.npz"));
+            if let Err(e) = write_npz(&path, &arrays).and_then(|()| npz_writer.push(i, &arrays)) {
+                eprintln!("failed to export arrays of frame {i:04}: {e}");
             }
         }
 
-        match config.max_frames {
-            Some(max_frames) => println!(
-                "[{:04}/{:04}] frame {:04} in {:05} ms",
-                frame_count + 1,
-                max_frames,
+        if let Some(images) = self.images.as_ref().filter(|images| images.selects(i)) {
+            write_images(
+                images,
                 i,
-                t0.elapsed().as_millis()
-            ),
-            None => println!(
-                "[{:04}/????] in {:05} ms",
-                frame_count + 1,
-                t0.elapsed().as_millis()
-            ),
-        }
-
-        frame_count += 1;
-        if let Some(max_frames) = config.max_frames
-            && frame_count >= max_frames
-        {
-            break;
+                &simulated,
+                &measured,
+                &markers,
+                &Annotations {
+                    camera: &self.camera_model,
+                    car_in_ins_enu,
+                    sun: &sun,
+                    time: frame.time,
+                },
+            );
         }
+
+        Ok(())
     }
 }
 
-fn image_path_from_frame(frame_index: usize) -> impl AsRef<Path> {
-    format!("camera_driver_gv_vis_image_raw_{:04}.png", frame_index)
+fn write_frame(sink: &mut dyn ResultSink, record: &Record) -> Result<(), BenchError> {
+    sink.write_frame(ResultRow::new(record)?)
 }
 
+fn print_incremental_stats(stats: &[IncrementalStats]) {
+    if stats.is_empty() {
+        return;
+    }
+    let reused: usize = stats.iter().map(|stats| stats.reused).sum();
+    let simulated: usize = stats.iter().map(|stats| stats.simulated).sum();
+    let full = stats.iter().filter(|stats| stats.full).count();
+    let bypassed = stats.iter().filter(|stats| stats.bypassed).count();
+    let max_error_deg = stats
+        .iter()
+        .map(|stats| stats.max_error_deg)
+        .fold(0., f64::max);
+    #[allow(clippy::cast_precision_loss)]
+    let reused_percent = 100. * reused as f64 / (reused + simulated).max(1) as f64;
+    println!(
+        "incremental: reused {reused_percent:.1}% of sky pixels, {full} of {} frames simulated in \
+         full, max checked AoP error {max_error_deg:.3} deg",
+        stats.len()
+    );
+    if bypassed > 0 {
+        println!(
+            "incremental: bypassed for {bypassed} frames simulated by rumpus, which cannot be \
+             warped; set --sky-lut-resolution-deg to warp Rayleigh skies"
+        );
+    }
+}
+
+fn print_error_stats(
+    frame_index: usize,
+    aop_stats: Option<&ErrorSummary>,
+    dop_stats: Option<&ErrorSummary>,
+) {
+    let (Some(aop_stats), Some(dop_stats)) = (aop_stats, dop_stats) else {
+        println!("frame {frame_index:04}: no pixels valid in both images");
+        return;
+    };
+    println!(
+        "frame {frame_index:04}: AoP rms {:.3} deg, sem {:.4} deg, max |e| {:.2} deg; DoP rms {:.4}, sem {:.5}, max |e| {:.3}",
+        aop_stats.rms,
+        aop_stats.standard_error,
+        aop_stats.max_abs,
+        dop_stats.rms,
+        dop_stats.standard_error,
+        dop_stats.max_abs
+    );
+}
+
+/// Looks for neutral points in the simulated and measured sky of every frame.
+struct NeutralPointSearch {
+    block: usize,
+    max_dop: f64,
+    max_points: usize,
+    writer: csv::Writer<File>,
+}
+
+impl NeutralPointSearch {
+    /// Pairs each simulated neutral point with the nearest measured one and records the offset.
+    ///
+    /// Returns the simulated and measured points.
+    fn compare(
+        &mut self,
+        frame_index: usize,
+        simulated: &RayImage<GlobalFrame>,
+        measured: &RayImage<GlobalFrame>,
+    ) -> [Vec<NeutralPoint>; 2] {
+        let find = |ray_image| {
+            let mut points = find_neutral_points(ray_image, self.block, self.max_dop);
+            points.truncate(self.max_points);
+            points
+        };
+        let simulated_points = find(simulated);
+        let measured_points = find(measured);
+
+        for simulated_point in &simulated_points {
+            let nearest = measured_points.iter().min_by(|a, b| {
+                a.distance(simulated_point)
+                    .total_cmp(&b.distance(simulated_point))
+            });
+            let _ = self.writer.serialize(NeutralPointRecord {
+                frame_index,
+                simulated_row: simulated_point.row,
+                simulated_col: simulated_point.col,
+                simulated_dop: simulated_point.dop,
+                measured_row: nearest.map(|point| point.row),
+                measured_col: nearest.map(|point| point.col),
+                measured_dop: nearest.map(|point| point.dop),
+                offset_px: nearest.map(|point| point.distance(simulated_point)),
+            });
+        }
+
+        [simulated_points, measured_points]
+    }
+}
+
+/// Which frames diagnostic images are written for, and how.
+struct ImageOutput {
+    dir: PathBuf,
+    format: ImageFormat,
+    every: Option<NonZeroUsize>,
+    frames: BTreeSet<usize>,
+    /// Text size of the annotations, if images are annotated.
+    annotation_scale: Option<usize>,
+    writer: ImageWriter,
+}
+
+impl ImageOutput {
+    /// Every frame, unless some are picked by index or by a multiple of their index.
+    fn selects(&self, frame_index: usize) -> bool {
+        if self.every.is_none() && self.frames.is_empty() {
+            return true;
+        }
+        self.every
+            .is_some_and(|every| frame_index.is_multiple_of(every.get()))
+            || self.frames.contains(&frame_index)
+    }
+}
+
+/// What is drawn onto the AoP images besides neutral points.
+struct Annotations<'a> {
+    camera: &'a CameraModel,
+    car_in_ins_enu: Orientation<InsEnu>,
+    sun: &'a CelestialPosition,
+    time: DateTime<Utc>,
+}
+
+fn write_images(
+    images: &ImageOutput,
+    i: usize,
+    simulated: &RayImage<GlobalFrame>,
+    measured: &RayImage<GlobalFrame>,
+    markers: &[Vec<NeutralPoint>; 2],
+    annotations: &Annotations,
+) {
+    const MARKER_ARM_PX: usize = 8;
+    const SUN_RING_PX: usize = 12;
+
+    // Get measured dop as a byte.
+    let bytes = measured.dop_bytes(&Gray);
+
+    let (car_yaw, _, _) = annotations.car_in_ins_enu.to_tait_bryan_angles();
+    let heading_deg = heading_from_yaw_deg(car_yaw.get::<degree>());
+    let sun_pixel = sky::source_pixel(
+        annotations.camera,
+        annotations.car_in_ins_enu,
+        annotations.sun,
+    );
+    // Graticule, headings and frame details, if requested.
+    let annotate = |overlay: &mut Overlay| {
+        let Some(scale) = images.annotation_scale else {
+            return;
+        };
+        let directions = sky::sky_directions(annotations.camera, annotations.car_in_ins_enu);
+        let label = FrameLabel {
+            frame_index: i,
+            time: annotations.time,
+            heading_deg,
+            estimated_heading_deg: None,
+            sun: *annotations.sun,
+        };
+        overlay.annotate(
+            annotations.camera,
+            annotations.car_in_ins_enu,
+            &directions,
+            &label,
+            scale,
+        );
+    };
+
+    for ((prefix, ray_image), markers) in [("simulated", simulated), ("measured", measured)]
+        .into_iter()
+        .zip(markers)
+    {
+        let (cols, rows) = image_size(ray_image);
+        let mut aop = Overlay::from_rgb(&ray_image.aop_bytes(&Jet), cols, rows);
+        for marker in markers {
+            aop.draw_cross((marker.row, marker.col), MARKER_ARM_PX, MARKER_RGBA);
+        }
+        if let Some(sun_pixel) = sun_pixel {
+            aop.draw_ring(sun_pixel, SUN_RING_PX, SUN_RGBA);
+        }
+        if images.annotation_scale.is_some() {
+            annotate(&mut aop);
+        } else {
+            aop.draw_heading(
+                annotations.camera,
+                annotations.car_in_ins_enu,
+                heading_deg,
+                TRUE_HEADING_RGBA,
+            );
+        }
+        images.writer.save(
+            aop.clone(),
+            images.dir.clone(),
+            format!("{prefix}_aop_{i:04}"),
+            images.format,
+            false,
+        );
+
+        // The measured DoP fades out the AoP where the sky is barely polarized.
+        aop.set_alpha(&bytes);
+        images.writer.save(
+            aop,
+            images.dir.clone(),
+            format!("{prefix}_aop_rgba_{i:04}"),
+            images.format,
+            true,
+        );
+
+        let mut dop = Overlay::from_rgb(&ray_image.dop_bytes(&Jet), cols, rows);
+        annotate(&mut dop);
+        images.writer.save(
+            dop,
+            images.dir.clone(),
+            format!("{prefix}_dop_{i:04}"),
+            images.format,
+            false,
+        );
+    }
+}
+
+/// Columns and rows of the images drawn from a ray image, one pixel per ray.
+#[allow(clippy::cast_possible_truncation)]
+fn image_size(ray_image: &RayImage<GlobalFrame>) -> (u32, u32) {
+    (ray_image.cols() as u32, ray_image.rows() as u32)
+}
+
+/// AoP in degrees, DoP and validity of the simulated and measured sky.
+fn npz_arrays(
+    simulated: &RayImage<GlobalFrame>,
+    measured: &RayImage<GlobalFrame>,
+) -> Vec<(&'static str, NpyArray)> {
+    let [simulated_aop_deg, simulated_dop, simulated_valid] = ray_image_arrays(simulated);
+    let [measured_aop_deg, measured_dop, measured_valid] = ray_image_arrays(measured);
+    vec![
+        ("simulated_aop_deg", simulated_aop_deg),
+        ("simulated_dop", simulated_dop),
+        ("simulated_valid", simulated_valid),
+        ("measured_aop_deg", measured_aop_deg),
+        ("measured_dop", measured_dop),
+        ("measured_valid", measured_valid),
+    ]
+}
+
+/// Draws a white cross on an RGB image at each neutral point.
 #[derive(Parser)]
 struct Cli {
-    dataset_path: PathBuf,
+    #[command(flatten)]
+    dataset: DatasetArgs,
 
-    #[arg(short, long)]
-    max_frames: Option<usize>,
+    #[command(flatten)]
+    sky: SkyArgs,
+
+    #[command(flatten)]
+    notify: NotifyArgs,
 
     #[arg(short, long)]
     write_images: bool,
 
-    #[arg(short, long, default_value_t = 1)]
-    step: usize,
-}
+    /// Only write images of frames whose index is a multiple of this.
+    #[arg(long, requires = "write_images")]
+    image_every: Option<NonZeroUsize>,
 
-impl Cli {
-    fn image_dir(&self) -> PathBuf {
-        self.dataset_path.join("camera_driver_gv_vis_image_raw")
-    }
+    /// Only write images of these frames, as well as those picked by --image-every.
+    #[arg(long, value_delimiter = ',', requires = "write_images")]
+    image_frames: Vec<usize>,
 
-    fn ins_path(&self) -> PathBuf {
-        self.dataset_path
-            .join("novatel_oem7_inspva/novatel_oem7_inspva.csv")
-    }
+    /// Draw a graticule, the headings, an arrow towards the sun and the frame details onto the
+    /// AoP and DoP images.
+    #[arg(long, requires = "write_images")]
+    annotate: bool,
 
-    fn time_path(&self) -> PathBuf {
-        self.dataset_path
-            .join("novatel_oem7_time/novatel_oem7_time.csv")
-    }
+    /// Size of the annotation text, as a multiple of its 5x7 pixel font.
+    #[arg(long, default_value_t = 2)]
+    annotation_scale: usize,
+
+    /// File format of the written images.
+    #[arg(long, value_enum, default_value_t = ImageFormat::Png)]
+    image_format: ImageFormat,
+
+    /// Threads encoding and writing images in the background, while frames go on.
+    #[arg(long, default_value_t = 2)]
+    image_writers: usize,
+
+    /// Resize the written images by this factor, such as 0.5 for half the rays on each side.
+    #[arg(long, default_value_t = 1.0, requires = "write_images", value_parser = parse_positive)]
+    image_scale: f64,
+
+    /// Pixels measured less polarized than this are left out of the second weighted RMSE.
+    #[arg(long, default_value_t = 0.05)]
+    min_dop: f64,
+
+    /// Frames whose AoP histograms are further apart than this, in degrees, are flagged as a
+    /// likely calibration or convention error and left out of the per-tag errors.
+    #[arg(long)]
+    max_aop_emd_deg: Option<f64>,
+
+    /// Fit the turbidity per frame to the measured DoP instead of using --turbidity.
+    #[arg(long)]
+    fit_turbidity: bool,
+
+    /// Also export the S0, S1 and S2 images of each frame in this format.
+    #[arg(long, value_enum)]
+    stokes: Option<StokesFormat>,
+
+    /// Export AoP, DoP and validity masks as NumPy archives per frame and for the whole run.
+    #[arg(long)]
+    npz: bool,
+
+    /// Also export the sky azimuth and elevation of every pixel under each frame's attitude,
+    /// plus the camera-frame bearings of the pixels once in `camera_bearings.npz`.
+    #[arg(long, requires = "npz")]
+    sky_directions: bool,
+
+    /// Locate neutral points in the measured and simulated sky and report their offset.
+    #[arg(long)]
+    neutral_points: bool,
+
+    /// Side of the square of pixels averaged before searching for neutral points.
+    #[arg(long, default_value_t = NonZeroUsize::new(8).unwrap())]
+    neutral_block: NonZeroUsize,
+
+    /// Neutral point candidates more polarized than this are ignored.
+    #[arg(long, default_value_t = 0.1)]
+    neutral_max_dop: f64,
+
+    /// Number of least polarized neutral points kept per image.
+    #[arg(long, default_value_t = 2)]
+    max_neutral_points: usize,
+
+    /// Where frame results are stored.
+    #[arg(long, value_enum, default_value_t = SinkFormat::Csv)]
+    sink: SinkFormat,
+
+    /// Add the run and its results to this SQLite database instead, which other runs can share.
+    #[arg(long)]
+    results_db: Option<PathBuf>,
+
+    /// Reuse the previous frame's sky for pixels looking within this many degrees of where it
+    /// was simulated, instead of simulating every frame in full.
+    /// Sunlit Rayleigh skies without a lookup table are simulated by rumpus, always in full, and
+    /// reported as bypassed.
+    #[arg(long)]
+    incremental_tolerance_deg: Option<f64>,
+
+    /// Simulate a frame in full when a checked pixel reused from the previous frame is off by
+    /// more than this.
+    #[arg(long, default_value_t = 0.5, requires = "incremental_tolerance_deg")]
+    incremental_max_error_deg: f64,
+
+    /// Resources the run may use; `embedded` runs on one thread without rayon and writes no
+    /// images.
+    #[arg(long, value_enum, default_value_t = RunProfile::Workstation)]
+    profile: RunProfile,
+
+    /// Estimate the frames, runtime and output size of the run without starting it.
+    #[arg(long)]
+    dry_run: bool,
 }
 
-#[derive(serde::Serialize)]
+#[derive(Clone, serde::Serialize)]
 struct Record {
     frame_index: usize,
-    origin_row: usize,
-    origin_col: usize,
+    tags: String,
+    origin_row: Option<usize>,
+    origin_col: Option<usize>,
     car_pitch_deg: f64,
     car_roll_deg: f64,
-    weighted_rmse: f64,
+    /// Altitude the sky was simulated at.
+    altitude_m: f64,
+    cloud_cover: Option<f64>,
+    visibility_km: Option<f64>,
+    aerosol_index: Option<f64>,
+    weighted_rmse: Option<f64>,
+    /// Weighted RMSE without the pixels measured below `--min-dop`.
+    weighted_rmse_excluding_low_dop: Option<f64>,
+    low_dop_pixels: Option<usize>,
+    /// Weighted RMSE of the pixels in each of the `ELEVATION_BANDS_DEG`.
+    weighted_rmse_elevation_0_30: Option<f64>,
+    weighted_rmse_elevation_30_60: Option<f64>,
+    weighted_rmse_elevation_60_90: Option<f64>,
+    /// Turbidity at the altitude of the frame.
+    turbidity: Option<f64>,
+    dop_rmse: Option<f64>,
+    /// Earth mover's distance between the simulated and measured AoP histograms.
+    aop_emd_deg: Option<f64>,
+    aop_mismatch: bool,
+    light_source: LightSource,
+    source_elevation_deg: f64,
+    source_too_low: bool,
+    sun_azimuth_deg: f64,
+    sun_elevation_deg: f64,
+    sun_in_fov: bool,
+    yaw_rate_deg_s: Option<f64>,
+    yaw_rate_exceeded: bool,
+    tilted: bool,
+    ins_status: Option<InsStatus>,
+    ins_degraded: bool,
+    saturated_fraction: Option<f64>,
+    underexposed_fraction: Option<f64>,
+    bad_exposure: bool,
+    /// Fraction of the sky reused from the previous frame with `--incremental-tolerance-deg`.
+    reused_fraction: Option<f64>,
+    /// Largest AoP error of the reused pixels that were checked.
+    incremental_error_deg: Option<f64>,
+    /// Whether rumpus simulated the frame in full, as it cannot be warped.
+    incremental_bypassed: Option<bool>,
+}
+
+/// Errors of the AoP or DoP of the pixels valid in both images of a frame.
+#[derive(serde::Serialize)]
+struct StatsRecord {
+    frame_index: usize,
+    quantity: &'static str,
+    samples: usize,
+    mean_error: f64,
+    rms_error: f64,
+    standard_error: f64,
+    max_abs_error: f64,
+}
+
+#[derive(serde::Serialize)]
+struct NeutralPointRecord {
+    frame_index: usize,
+    simulated_row: usize,
+    simulated_col: usize,
+    simulated_dop: f64,
+    measured_row: Option<usize>,
+    measured_col: Option<usize>,
+    measured_dop: Option<f64>,
+    offset_px: Option<f64>,
 }
@@ -0,0 +1,66 @@
+use clap::Parser;
+use rumpus_benchmark::{
+    integrity::DatasetManifest,
+    pipeline::{Dataset, FrameRange},
+    trim::DatasetTrimmer,
+};
+use std::path::PathBuf;
+
+/// Cuts a dataset down to a range of frames, such as for a bug report or CI, and writes the
+/// checksums of the result.
+fn main() {
+    let config = Cli::parse();
+    let from = Dataset::new(&config.dataset_path);
+    let to = Dataset::new(&config.output);
+
+    let summary = DatasetTrimmer::new(config.frames)
+        .with_position_grid(config.position_grid_deg)
+        .with_leap_seconds(config.leap_seconds)
+        .trim(&from, &to)
+        .unwrap_or_else(|e| {
+            eprintln!("{e}");
+            std::process::exit(1);
+        });
+    let jobs = std::thread::available_parallelism().map_or(1, usize::from);
+    DatasetManifest::build(&to, jobs)
+        .and_then(|manifest| manifest.write(&to))
+        .unwrap();
+
+    println!(
+        "wrote frames {} of {} to {} as {} frames",
+        config.frames,
+        from.path().display(),
+        to.path().display(),
+        summary.frames
+    );
+    if summary.missing_images > 0 {
+        println!("{} of them have no image", summary.missing_images);
+    }
+    if let Some((lat_offset, lon_offset)) = summary.position_offset_deg {
+        println!("offset positions by {lat_offset:.6} deg latitude, {lon_offset:.6} deg longitude");
+    }
+    for path in &summary.left_out {
+        println!("left out {}", path.display());
+    }
+}
+
+#[derive(Parser)]
+struct Cli {
+    dataset_path: PathBuf,
+
+    /// Directory of the trimmed dataset, which must not exist yet.
+    output: PathBuf,
+
+    /// Frames to keep, such as `100..500`.
+    #[arg(long)]
+    frames: FrameRange,
+
+    /// Move the trajectory so it starts on a grid of this spacing, such as 0.01, to hide where
+    /// the dataset was recorded; the sun moves by about as much as the longitude does.
+    #[arg(long)]
+    position_grid_deg: Option<f64>,
+
+    /// GPS to UTC leap seconds, instead of the offset reported by the receiver.
+    #[arg(long)]
+    leap_seconds: Option<i64>,
+}
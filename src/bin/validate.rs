@@ -0,0 +1,168 @@
+use clap::Parser;
+use rumpus_benchmark::{
+    archive,
+    cli::DatasetArgs,
+    ephemeris::CelestialPosition,
+    integrity::DatasetManifest,
+    io::{InsFrame, InsReader, TimeReader},
+};
+use std::{error::Error, io::Cursor, path::Path};
+use uom::si::angle::degree;
+
+/// Checks that a dataset fits the configured sensor before spending hours on a benchmark.
+fn main() {
+    let config = Cli::parse();
+    let dataset = config.dataset.dataset();
+    let mut problems = Vec::new();
+
+    let ins_reader = InsReader::new()
+        .with_convention(config.dataset.ins_convention)
+        .with_leap_seconds(config.dataset.leap_seconds)
+        .with_log(config.dataset.ins_log);
+    let ins_frames: Vec<InsFrame> =
+        match ins_reader.read_csv(dataset.ins_log_path(config.dataset.ins_log)) {
+            Ok(frames) => frames.collect(),
+            Err(e) => {
+                problems.push(format!("{e}; check the INS log was exported from the bag"));
+                Vec::new()
+            }
+        };
+
+    // The time reader already rejects timestamps that go backwards.
+    let time_reader = TimeReader::new().with_leap_seconds(config.dataset.leap_seconds);
+    let image_times: Vec<_> = match time_reader.read_csv(dataset.time_path()) {
+        Ok(frames) => frames.map(|frame| frame.time).collect(),
+        Err(e) => {
+            problems.push(format!(
+                "{e}; try --leap-seconds if the receiver offset is wrong"
+            ));
+            Vec::new()
+        }
+    };
+
+    if let Some(i) = ins_frames
+        .windows(2)
+        .position(|pair| pair[1].time < pair[0].time)
+    {
+        problems.push(format!(
+            "INS record {} at {} is earlier than the previous record at {}",
+            i + 1,
+            ins_frames[i + 1].time,
+            ins_frames[i].time
+        ));
+    }
+
+    // Every exposure needs an INS state on either side of it.
+    if let (Some(first_ins), Some(last_ins), Some(first_image), Some(last_image)) = (
+        ins_frames.first(),
+        ins_frames.last(),
+        image_times.first(),
+        image_times.last(),
+    ) && (first_ins.time > *first_image || last_ins.time < *last_image)
+    {
+        problems.push(format!(
+            "INS covers {} to {} but images span {first_image} to {last_image}; \
+             check the GPS week and leap seconds",
+            first_ins.time, last_ins.time
+        ));
+    }
+
+    // Check every image exists and is a polarizer mosaic of the configured sensor.
+    let expected = (2 * config.cols, 2 * config.rows);
+    let mut missing = 0;
+    for frame_index in (0..image_times.len()).step_by(config.dataset.step) {
+        let path = dataset.image_path(frame_index);
+        match image_dimensions(&path) {
+            Ok(dimensions) if dimensions == (expected.0 as u32, expected.1 as u32) => {}
+            Ok((width, height)) => problems.push(format!(
+                "{} is {width}x{height} but a {}x{} sensor needs a {}x{} mosaic; \
+                 set --rows and --cols",
+                path.display(),
+                config.cols,
+                config.rows,
+                expected.0,
+                expected.1
+            )),
+            Err(_) => missing += 1,
+        }
+    }
+    if missing > 0 {
+        problems.push(format!(
+            "{missing} images are missing or unreadable in {}",
+            dataset.image_dir().display()
+        ));
+    }
+
+    // Copies of datasets written by `manifest` must still match it.
+    if !config.dataset.no_verify && DatasetManifest::path(&dataset).exists() {
+        let jobs = std::thread::available_parallelism().map_or(1, usize::from);
+        match DatasetManifest::read(&dataset) {
+            Ok(manifest) => {
+                problems.extend(manifest.problems(&dataset, jobs, config.dataset.verify_hashes))
+            }
+            Err(e) => problems.push(e.to_string()),
+        }
+    }
+
+    // Frames where the sun is down cannot be matched against a polarized sky.
+    let daylight_frames = image_times
+        .iter()
+        .zip(&ins_frames)
+        .filter(|(time, ins_frame)| {
+            CelestialPosition::sun(&ins_frame.position, **time)
+                .elevation
+                .get::<degree>()
+                > 0.
+        })
+        .count();
+    let frame_count = image_times.len().min(ins_frames.len());
+    if frame_count > 0 {
+        #[allow(clippy::cast_precision_loss)]
+        let daylight_percent = 100. * daylight_frames as f64 / frame_count as f64;
+        println!("sun is up in {daylight_percent:.1}% of {frame_count} frames");
+        if daylight_percent < config.min_daylight_percent {
+            problems.push(format!(
+                "sun is up in only {daylight_percent:.1}% of frames, less than {:.1}%; \
+                 check the dataset time zone",
+                config.min_daylight_percent
+            ));
+        }
+    }
+
+    if problems.is_empty() {
+        println!("{} is ready to benchmark", dataset.path().display());
+        return;
+    }
+
+    for problem in &problems {
+        eprintln!("error: {problem}");
+    }
+    std::process::exit(1);
+}
+
+/// Width and height of an image, read from its header alone unless it is archived or remote.
+fn image_dimensions(path: &Path) -> Result<(u32, u32), Box<dyn Error>> {
+    if archive::is_plain_file(path) {
+        return Ok(image::image_dimensions(path)?);
+    }
+    let reader = image::ImageReader::new(Cursor::new(archive::read_file(path)?));
+    Ok(reader.with_guessed_format()?.into_dimensions()?)
+}
+
+#[derive(Parser)]
+struct Cli {
+    #[command(flatten)]
+    dataset: DatasetArgs,
+
+    /// Rows of polarization pixels on the sensor.
+    #[arg(long, default_value_t = 1024)]
+    rows: usize,
+
+    /// Columns of polarization pixels on the sensor.
+    #[arg(long, default_value_t = 1224)]
+    cols: usize,
+
+    /// Smallest share of frames that must be taken with the sun above the horizon.
+    #[arg(long, default_value_t = 90.0)]
+    min_daylight_percent: f64,
+}
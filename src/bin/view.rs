@@ -0,0 +1,406 @@
+use clap::Parser;
+use eframe::egui::{self, Color32, ColorImage, TextureHandle, TextureOptions};
+use egui_plot::{Line, Plot, PlotPoints, VLine};
+use rumpus_benchmark::export::read_npz;
+use std::{
+    collections::BTreeMap,
+    path::{Path, PathBuf},
+};
+
+/// Browses the frames of one or more results directories in a window: the measured and
+/// simulated skies written by `test_simulation --npz`, their difference, and the cost curve
+/// written by `test_pattern_match`.
+///
+/// Left and right step through the frames, and hovering over the sky shows every array at the
+/// pixel under the cursor.
+fn main() -> eframe::Result {
+    let config = Cli::parse();
+    let mut frames = BTreeMap::new();
+    for dir in &config.results_dirs {
+        scan(dir, &mut frames).unwrap();
+    }
+    if frames.is_empty() {
+        eprintln!("no frame_NNNN.npz or frame_NNNN_results.csv files found");
+        std::process::exit(1);
+    }
+
+    let viewer = Viewer::new(frames.into_iter().collect());
+    eframe::run_native(
+        "rumpus viewer",
+        eframe::NativeOptions::default(),
+        Box::new(|_| Ok(Box::new(viewer))),
+    )
+}
+
+/// Files written for one frame.
+#[derive(Default)]
+struct FrameFiles {
+    arrays: Option<PathBuf>,
+    candidates: Option<PathBuf>,
+}
+
+/// Adds the per-frame files of a results directory by frame index.
+fn scan(dir: &Path, frames: &mut BTreeMap<usize, FrameFiles>) -> std::io::Result<()> {
+    for entry in std::fs::read_dir(dir)? {
+        let path = entry?.path();
+        let Some(name) = path.file_name().and_then(|name| name.to_str()) else {
+            continue;
+        };
+        let Some(rest) = name.strip_prefix("frame_") else {
+            continue;
+        };
+        let index = |suffix| rest.strip_suffix(suffix)?.parse::<usize>().ok();
+        if let Some(index) = index(".npz") {
+            frames.entry(index).or_default().arrays = Some(path);
+        } else if let Some(index) = index("_results.csv") {
+            frames.entry(index).or_default().candidates = Some(path);
+        }
+    }
+    Ok(())
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Layer {
+    Measured,
+    Simulated,
+    /// Measured minus simulated.
+    Difference,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Quantity {
+    Aop,
+    Dop,
+}
+
+impl Quantity {
+    fn array_suffix(self) -> &'static str {
+        match self {
+            Self::Aop => "aop_deg",
+            Self::Dop => "dop",
+        }
+    }
+
+    /// Lowest and highest value, which the colour map spans.
+    fn range(self) -> (f32, f32) {
+        match self {
+            Self::Aop => (-90., 90.),
+            Self::Dop => (0., 1.),
+        }
+    }
+
+    /// Measured minus simulated, with AoP differences wrapped into [-90, 90).
+    fn difference(self, measured: f32, simulated: f32) -> f32 {
+        match self {
+            Self::Aop => (measured - simulated + 90.).rem_euclid(180.) - 90.,
+            Self::Dop => measured - simulated,
+        }
+    }
+}
+
+/// Arrays and cost curve of the selected frame.
+#[derive(Default)]
+struct LoadedFrame {
+    rows: usize,
+    cols: usize,
+    /// Row-major image arrays by name, such as `measured_aop_deg`.
+    arrays: BTreeMap<String, Vec<f32>>,
+    /// Yaw offset in degrees and cost of every candidate.
+    cost_curve: Vec<[f64; 2]>,
+    errors: Vec<String>,
+}
+
+#[derive(serde::Deserialize)]
+struct CandidateRecord {
+    yaw_offset_deg: f64,
+    weighted_rmse: f64,
+}
+
+impl LoadedFrame {
+    fn load(files: &FrameFiles) -> Self {
+        let mut frame = Self::default();
+        if let Some(path) = &files.arrays {
+            match read_npz(path) {
+                Ok(arrays) => {
+                    for (name, array) in arrays {
+                        // Only images are shown, not per-run arrays such as `frame_index`.
+                        if let [rows, cols] = *array.shape() {
+                            (frame.rows, frame.cols) = (rows, cols);
+                            frame.arrays.insert(name, array.to_f32());
+                        }
+                    }
+                }
+                Err(e) => frame.errors.push(e.to_string()),
+            }
+        }
+        if let Some(path) = &files.candidates {
+            let cost_curve = csv::Reader::from_path(path).and_then(|mut reader| {
+                reader
+                    .deserialize()
+                    .map(|record| {
+                        record.map(|record: CandidateRecord| {
+                            [record.yaw_offset_deg, record.weighted_rmse]
+                        })
+                    })
+                    .collect()
+            });
+            match cost_curve {
+                Ok(cost_curve) => frame.cost_curve = cost_curve,
+                Err(e) => frame
+                    .errors
+                    .push(format!("cannot read {}: {e}", path.display())),
+            }
+        }
+        frame
+    }
+
+    /// Values of a layer, with NaN where the pixel is invalid or the arrays are missing.
+    fn layer(&self, layer: Layer, quantity: Quantity) -> Vec<f32> {
+        let source = |name: &str| -> Vec<f32> {
+            let values = self
+                .arrays
+                .get(&format!("{name}_{}", quantity.array_suffix()));
+            let valid = self.arrays.get(&format!("{name}_valid"));
+            (0..self.rows * self.cols)
+                .map(|i| match values {
+                    Some(values) if valid.is_none_or(|valid| valid[i] != 0.) => values[i],
+                    _ => f32::NAN,
+                })
+                .collect()
+        };
+        match layer {
+            Layer::Measured => source("measured"),
+            Layer::Simulated => source("simulated"),
+            Layer::Difference => source("measured")
+                .into_iter()
+                .zip(source("simulated"))
+                .map(|(measured, simulated)| quantity.difference(measured, simulated))
+                .collect(),
+        }
+    }
+}
+
+struct Viewer {
+    frames: Vec<(usize, FrameFiles)>,
+    selected: usize,
+    layer: Layer,
+    quantity: Quantity,
+    /// Differences at either end of the diverging colour map.
+    aop_limit_deg: f32,
+    dop_limit: f32,
+    loaded: Option<(usize, LoadedFrame)>,
+    /// Values shown in the texture, for the readout under the cursor.
+    values: Vec<f32>,
+    texture: Option<TextureHandle>,
+    /// What the texture shows, to rebuild it only when that changes.
+    shown: Option<(usize, Layer, Quantity, u32)>,
+}
+
+impl Viewer {
+    fn new(frames: Vec<(usize, FrameFiles)>) -> Self {
+        Self {
+            frames,
+            selected: 0,
+            layer: Layer::Measured,
+            quantity: Quantity::Aop,
+            aop_limit_deg: 10.,
+            dop_limit: 0.1,
+            loaded: None,
+            values: Vec::new(),
+            texture: None,
+            shown: None,
+        }
+    }
+
+    fn difference_limit(&mut self) -> &mut f32 {
+        match self.quantity {
+            Quantity::Aop => &mut self.aop_limit_deg,
+            Quantity::Dop => &mut self.dop_limit,
+        }
+    }
+
+    /// Loads the selected frame and rebuilds the texture when the selection changed.
+    fn refresh(&mut self, ctx: &egui::Context) {
+        let limit = *self.difference_limit();
+        let key = (self.selected, self.layer, self.quantity, limit.to_bits());
+        if self.shown == Some(key) {
+            return;
+        }
+        if self
+            .loaded
+            .as_ref()
+            .is_none_or(|(index, _)| *index != self.selected)
+        {
+            let frame = LoadedFrame::load(&self.frames[self.selected].1);
+            self.loaded = Some((self.selected, frame));
+        }
+        let (_, frame) = self.loaded.as_ref().expect("loaded above");
+
+        self.values = frame.layer(self.layer, self.quantity);
+        let (low, high) = self.quantity.range();
+        let pixels = self
+            .values
+            .iter()
+            .map(|&value| match self.layer {
+                _ if value.is_nan() => Color32::BLACK,
+                Layer::Difference => diverging(value / limit),
+                _ => jet((value - low) / (high - low)),
+            })
+            .collect();
+        let image = ColorImage {
+            size: [frame.cols, frame.rows],
+            pixels,
+        };
+        self.texture = frame
+            .arrays
+            .contains_key(&format!("measured_{}", self.quantity.array_suffix()))
+            .then(|| ctx.load_texture("sky", image, TextureOptions::NEAREST));
+        self.shown = Some(key);
+    }
+
+    fn controls(&mut self, ui: &mut egui::Ui) {
+        ui.horizontal(|ui| {
+            let last = self.frames.len() - 1;
+            ui.add(egui::Slider::new(&mut self.selected, 0..=last).show_value(false));
+            ui.label(format!("frame {:04}", self.frames[self.selected].0));
+            ui.separator();
+            for (layer, label) in [
+                (Layer::Measured, "measured"),
+                (Layer::Simulated, "simulated"),
+                (Layer::Difference, "difference"),
+            ] {
+                ui.selectable_value(&mut self.layer, layer, label);
+            }
+            ui.separator();
+            ui.selectable_value(&mut self.quantity, Quantity::Aop, "AoP");
+            ui.selectable_value(&mut self.quantity, Quantity::Dop, "DoP");
+            if self.layer == Layer::Difference {
+                ui.separator();
+                let range = match self.quantity {
+                    Quantity::Aop => 0.5..=90.,
+                    Quantity::Dop => 0.01..=1.,
+                };
+                ui.add(egui::Slider::new(self.difference_limit(), range).text("colour limit"));
+            }
+        });
+    }
+
+    fn sky(&self, ui: &mut egui::Ui) {
+        let Some((_, frame)) = &self.loaded else {
+            return;
+        };
+        for error in &frame.errors {
+            ui.colored_label(Color32::RED, error);
+        }
+        let Some(texture) = &self.texture else {
+            ui.label("no sky arrays for this frame");
+            return;
+        };
+
+        let response = ui.add(
+            egui::Image::new(texture)
+                .shrink_to_fit()
+                .sense(egui::Sense::hover()),
+        );
+        let Some(pointer) = response.hover_pos() else {
+            return;
+        };
+        let relative = (pointer - response.rect.min) / response.rect.size();
+        #[allow(
+            clippy::cast_possible_truncation,
+            clippy::cast_sign_loss,
+            clippy::cast_precision_loss
+        )]
+        let (row, col) = (
+            (relative.y * frame.rows as f32) as usize,
+            (relative.x * frame.cols as f32) as usize,
+        );
+        if row >= frame.rows || col >= frame.cols {
+            return;
+        }
+        let i = row * frame.cols + col;
+        let mut readout = format!("row {row}, col {col}\nshown {:.3}", self.values[i]);
+        for (name, values) in &frame.arrays {
+            readout.push_str(&format!("\n{name} {:.3}", values[i]));
+        }
+        response.on_hover_text_at_pointer(readout);
+    }
+
+    fn cost_curve(&self, ui: &mut egui::Ui) {
+        let Some((_, frame)) = &self.loaded else {
+            return;
+        };
+        if frame.cost_curve.is_empty() {
+            ui.label("no candidates for this frame");
+            return;
+        }
+        let best = frame
+            .cost_curve
+            .iter()
+            .filter(|[_, cost]| cost.is_finite())
+            .min_by(|a, b| a[1].total_cmp(&b[1]))
+            .copied();
+        Plot::new("cost curve")
+            .x_axis_label("yaw offset (deg)")
+            .y_axis_label("weighted RMSE")
+            .show(ui, |plot_ui| {
+                plot_ui.line(Line::new(PlotPoints::from(frame.cost_curve.clone())));
+                if let Some([offset_deg, _]) = best {
+                    plot_ui.vline(VLine::new(offset_deg));
+                }
+            });
+    }
+}
+
+impl eframe::App for Viewer {
+    fn update(&mut self, ctx: &egui::Context, _frame: &mut eframe::Frame) {
+        let last = self.frames.len() - 1;
+        ctx.input(|input| {
+            if input.key_pressed(egui::Key::ArrowRight) {
+                self.selected = (self.selected + 1).min(last);
+            }
+            if input.key_pressed(egui::Key::ArrowLeft) {
+                self.selected = self.selected.saturating_sub(1);
+            }
+        });
+
+        egui::TopBottomPanel::top("controls").show(ctx, |ui| self.controls(ui));
+        self.refresh(ctx);
+        egui::TopBottomPanel::bottom("cost curve")
+            .resizable(true)
+            .default_height(180.)
+            .show(ctx, |ui| self.cost_curve(ui));
+        egui::CentralPanel::default().show(ctx, |ui| self.sky(ui));
+    }
+}
+
+/// Maps 0 to 1 onto blue, cyan, yellow and red.
+fn jet(t: f32) -> Color32 {
+    let t = t.clamp(0., 1.);
+    let channel = |center: f32| unit_byte(1.5 - (4. * t - center).abs());
+    Color32::from_rgb(channel(3.), channel(2.), channel(1.))
+}
+
+/// Maps -1 to 1 onto blue, white and red.
+fn diverging(t: f32) -> Color32 {
+    let t = t.clamp(-1., 1.);
+    if t < 0. {
+        Color32::from_rgb(unit_byte(1. + t), unit_byte(1. + t), 255)
+    } else {
+        Color32::from_rgb(255, unit_byte(1. - t), unit_byte(1. - t))
+    }
+}
+
+fn unit_byte(value: f32) -> u8 {
+    #[allow(clippy::cast_possible_truncation, clippy::cast_sign_loss)]
+    let byte = (value.clamp(0., 1.) * 255.).round() as u8;
+    byte
+}
+
+#[derive(Parser)]
+struct Cli {
+    /// Results directories with per-frame arrays or candidates, such as a `test_pattern_match`
+    /// run and a `test_simulation --npz` run over the same frames.
+    #[arg(required = true)]
+    results_dirs: Vec<PathBuf>,
+}
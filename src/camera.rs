@@ -0,0 +1,286 @@
+use crate::{
+    error::BenchError,
+    io::ImageReader,
+    systems::{InsEnu, up_in_cam},
+};
+use rumpus::optic::{Camera, PinholeOptic, PixelCoordinate};
+use sguaba::engineering::Orientation;
+use std::{
+    fmt::Display,
+    str::FromStr,
+    sync::{Arc, Mutex},
+};
+use uom::si::{f64::Length, length::meter, ratio::ratio};
+
+/// Where the optical axis hits the sensor, as an offset in pixels from its center.
+///
+/// `cx` increases along the image columns and `cy` along the image rows.
+#[derive(Debug, Default, Clone, Copy, PartialEq, serde::Serialize)]
+pub struct PrincipalPoint {
+    pub cx: f64,
+    pub cy: f64,
+}
+
+impl PrincipalPoint {
+    /// The optical axis through the center of the sensor.
+    pub const CENTERED: Self = Self { cx: 0., cy: 0. };
+
+    pub fn new(cx: f64, cy: f64) -> Self {
+        Self { cx, cy }
+    }
+
+    /// The offset in the images read by `image_reader`, given the offset in raw sensor pixels.
+    pub fn for_mosaic(&self, image_reader: &ImageReader) -> Self {
+        let (cx, cy) = image_reader.orientation().offset(self.cx, self.cy);
+        #[allow(clippy::cast_precision_loss)]
+        let scale = image_reader.demosaic().pixel_scale() as f64;
+        Self::new(cx / scale, cy / scale)
+    }
+}
+
+impl FromStr for PrincipalPoint {
+    type Err = BenchError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let invalid = || BenchError::Config(format!("principal point {s:?} is not like 12.5,-3"));
+        let (cx, cy) = s.split_once(',').ok_or_else(invalid)?;
+        Ok(Self::new(
+            cx.trim().parse().map_err(|_| invalid())?,
+            cy.trim().parse().map_err(|_| invalid())?,
+        ))
+    }
+}
+
+impl Display for PrincipalPoint {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{},{}", self.cx, self.cy)
+    }
+}
+
+/// Pinhole model of the polarization camera.
+///
+/// Mirrors the rumpus camera so the harness can map between pixels and bearings itself.
+/// Bearings are unit vectors in `CamXyz` with the optical axis along +z, image columns
+/// increasing along +x and image rows increasing along -y.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct CameraModel {
+    focal_length: Length,
+    pixel_size: Length,
+    rows: usize,
+    cols: usize,
+    principal_point: PrincipalPoint,
+}
+
+impl CameraModel {
+    pub fn new(focal_length: Length, pixel_size: Length, rows: usize, cols: usize) -> Self {
+        Self {
+            focal_length,
+            pixel_size,
+            rows,
+            cols,
+            principal_point: PrincipalPoint::CENTERED,
+        }
+    }
+
+    /// Moves the optical axis off the center of the sensor.
+    pub fn with_principal_point(mut self, principal_point: PrincipalPoint) -> Self {
+        self.principal_point = principal_point;
+        self
+    }
+
+    /// Models the rays read by `image_reader` from a sensor of `sensor_rows` by `sensor_cols`.
+    pub fn for_mosaic(
+        focal_length: Length,
+        sensor_pixel_size: Length,
+        sensor_rows: usize,
+        sensor_cols: usize,
+        image_reader: &ImageReader,
+    ) -> Self {
+        let scale = image_reader.demosaic().pixel_scale();
+        let (rows, cols) = image_reader.orientation().shape(sensor_rows, sensor_cols);
+        #[allow(clippy::cast_precision_loss)]
+        let pixel_size = sensor_pixel_size * scale as f64;
+        Self::new(focal_length, pixel_size, rows / scale, cols / scale)
+    }
+
+    /// The same camera keeping every `factor`-th pixel along each axis, as
+    /// [`crate::utils::downsample`] does to its images.
+    pub fn downsampled(&self, factor: usize) -> Self {
+        let factor = factor.max(1);
+        #[allow(clippy::cast_precision_loss)]
+        let pixel_size = self.pixel_size * factor as f64;
+        #[allow(clippy::cast_precision_loss)]
+        let principal_point = PrincipalPoint::new(
+            self.principal_point.cx / factor as f64,
+            self.principal_point.cy / factor as f64,
+        );
+        Self::new(
+            self.focal_length,
+            pixel_size,
+            self.rows.div_ceil(factor),
+            self.cols.div_ceil(factor),
+        )
+        .with_principal_point(principal_point)
+    }
+
+    pub fn focal_length(&self) -> Length {
+        self.focal_length
+    }
+
+    pub fn pixel_size(&self) -> Length {
+        self.pixel_size
+    }
+
+    pub fn rows(&self) -> usize {
+        self.rows
+    }
+
+    pub fn cols(&self) -> usize {
+        self.cols
+    }
+
+    pub fn principal_point(&self) -> PrincipalPoint {
+        self.principal_point
+    }
+
+    /// Pixel the optical axis lands on, which need not be a whole pixel.
+    #[allow(clippy::cast_precision_loss)]
+    fn center(&self) -> (f64, f64) {
+        (
+            self.rows as f64 / 2. + self.principal_point.cy,
+            self.cols as f64 / 2. + self.principal_point.cx,
+        )
+    }
+
+    /// The rumpus camera, which always has its optical axis at the center of the sensor.
+    pub fn camera(&self) -> Camera {
+        Camera::new(
+            PinholeOptic::from_focal_length(self.focal_length),
+            self.pixel_size,
+            self.rows,
+            self.cols,
+        )
+    }
+
+    /// A camera with its optical axis centered like the rumpus camera that sees every bearing of
+    /// this one, and the offset in rows and columns of this camera's pixels on its sensor.
+    #[allow(
+        clippy::cast_precision_loss,
+        clippy::cast_possible_truncation,
+        clippy::cast_sign_loss
+    )]
+    pub fn centered(&self) -> (Self, (f64, f64)) {
+        let margin_rows = self.principal_point.cy.abs().ceil() as usize;
+        let margin_cols = self.principal_point.cx.abs().ceil() as usize;
+        let centered = Self::new(
+            self.focal_length,
+            self.pixel_size,
+            self.rows + 2 * margin_rows,
+            self.cols + 2 * margin_cols,
+        );
+        let offset = (
+            margin_rows as f64 - self.principal_point.cy,
+            margin_cols as f64 - self.principal_point.cx,
+        );
+        (centered, offset)
+    }
+
+    /// Returns the pixel the global zenith lands on, if it is inside the sensor.
+    pub fn zenith_pixel(&self, car_in_ins_enu: Orientation<InsEnu>) -> Option<PixelCoordinate> {
+        let up = up_in_cam(car_in_ins_enu).normalized();
+        // Traced here rather than by rumpus, which assumes the optical axis is centered.
+        let (row, col) = self.pixel([
+            up.x().get::<meter>(),
+            up.y().get::<meter>(),
+            up.z().get::<meter>(),
+        ])?;
+        Some(PixelCoordinate::new(row, col))
+    }
+
+    /// Returns the unit bearing of the ray that lands on a pixel.
+    #[allow(clippy::cast_precision_loss)]
+    pub fn bearing(&self, row: usize, col: usize) -> [f64; 3] {
+        let pitch: f64 = (self.pixel_size / self.focal_length).get::<ratio>();
+        let (center_row, center_col) = self.center();
+        let x = (col as f64 - center_col) * pitch;
+        let y = -(row as f64 - center_row) * pitch;
+        let norm = (x * x + y * y + 1.).sqrt();
+
+        [x / norm, y / norm, 1. / norm]
+    }
+
+    /// Returns the pixel a unit bearing lands on, if it is inside the sensor.
+    #[allow(
+        clippy::cast_precision_loss,
+        clippy::cast_possible_truncation,
+        clippy::cast_sign_loss
+    )]
+    pub fn pixel(&self, bearing: [f64; 3]) -> Option<(usize, usize)> {
+        let [x, y, z] = bearing;
+        if z <= 0. {
+            return None;
+        }
+
+        let pitch: f64 = (self.pixel_size / self.focal_length).get::<ratio>();
+        let (center_row, center_col) = self.center();
+        let col = (x / z / pitch + center_col).round();
+        let row = (-y / z / pitch + center_row).round();
+        if col < 0. || row < 0. || col >= self.cols as f64 || row >= self.rows as f64 {
+            return None;
+        }
+
+        Some((row as usize, col as usize))
+    }
+
+    /// Iterates over the bearing of every pixel in row-major order.
+    pub fn bearings(&self) -> impl Iterator<Item = [f64; 3]> + '_ {
+        (0..self.rows).flat_map(move |row| (0..self.cols).map(move |col| self.bearing(row, col)))
+    }
+}
+
+/// Grids built so far, one per distinct camera model.
+static PIXEL_GRIDS: Mutex<Vec<Arc<PixelGrid>>> = Mutex::new(Vec::new());
+
+/// Bearing of every pixel of a camera in row-major order, computed once and shared by every
+/// frame and candidate simulated with that camera.
+#[derive(Debug)]
+pub struct PixelGrid {
+    camera: CameraModel,
+    bearings: Vec<[f64; 3]>,
+}
+
+impl PixelGrid {
+    pub fn new(camera: &CameraModel) -> Self {
+        Self {
+            camera: *camera,
+            bearings: camera.bearings().collect(),
+        }
+    }
+
+    /// The grid of `camera`, built on first use.
+    ///
+    /// Runs only ever see a handful of camera models, so grids are kept until the process
+    /// exits.
+    pub fn shared(camera: &CameraModel) -> Arc<Self> {
+        let mut grids = PIXEL_GRIDS.lock().unwrap();
+        if let Some(grid) = grids.iter().find(|grid| grid.camera == *camera) {
+            return Arc::clone(grid);
+        }
+        let grid = Arc::new(Self::new(camera));
+        grids.push(Arc::clone(&grid));
+        grid
+    }
+
+    pub fn camera(&self) -> &CameraModel {
+        &self.camera
+    }
+
+    pub fn bearings(&self) -> &[[f64; 3]] {
+        &self.bearings
+    }
+
+    /// Same as [`CameraModel::bearing`].
+    pub fn bearing(&self, row: usize, col: usize) -> [f64; 3] {
+        self.bearings[row * self.camera.cols + col]
+    }
+}
@@ -0,0 +1,514 @@
+use crate::{
+    camera::PrincipalPoint,
+    ephemeris::Atmosphere,
+    error::BenchError,
+    exposure::{ExposureAction, ExposureGate},
+    glare::{GlareConfig, GlareStrategy},
+    image_circle::ImageCircle,
+    integrity::verify_dataset,
+    io::{
+        DemosaicMode, ImageFlip, ImageOrientation, ImageReader, ImageRotation, InsLog, InsReader,
+        MosaicLayout, TimeReader,
+    },
+    magnetic::{HeadingReference, MagneticModel},
+    motion::FrameAlignment,
+    notify::CompletionHooks,
+    outage::OutageWindow,
+    pipeline::{Dataset, FrameRange, InsStatusAction, Pipeline, read_frame_list},
+    read_ahead::ReadAhead,
+    run::{RunMetadata, RunProfile},
+    sky::{AltitudeConfig, DopCalibration, LightSourceMode, Sky, SkyModelBackend, SkyTable},
+    systems::InsConvention,
+    tags::FrameTags,
+    time_offset::TimeCalibration,
+    weather::WeatherSeries,
+};
+use std::{
+    path::PathBuf,
+    sync::{
+        Arc,
+        atomic::{AtomicBool, Ordering},
+    },
+};
+use uom::si::{angle::degree, f64::Angle};
+
+/// Arguments that select a dataset and how its frames are paired with INS states.
+#[derive(Debug, clap::Args)]
+pub struct DatasetArgs {
+    /// Dataset directory, or a `.tar.zst` archive of one that is read without extracting it.
+    ///
+    /// Either may also be in S3-compatible storage, such as `s3://bucket/prefix`, with the
+    /// `remote` feature.
+    pub dataset_path: PathBuf,
+
+    #[arg(short, long)]
+    pub max_frames: Option<usize>,
+
+    #[arg(short, long, default_value_t = 1)]
+    pub step: usize,
+
+    /// Only process frames in this range, such as `100..500`.
+    #[arg(long)]
+    pub frames: Option<FrameRange>,
+
+    /// Only process the frames listed in this file, one index per line.
+    #[arg(long)]
+    pub frame_list: Option<PathBuf>,
+
+    /// Scenario tags of frame ranges, such as `100..500 urban` per line, to stratify errors by.
+    #[arg(long)]
+    pub tags: Option<PathBuf>,
+
+    /// Attitude convention of the INS output.
+    #[arg(long, value_enum, default_value_t = InsConvention::Inspva)]
+    pub ins_convention: InsConvention,
+
+    /// NovAtel log to read INS states from; INSPVAX adds their standard deviations.
+    #[arg(long, value_enum, default_value_t = InsLog::Inspva)]
+    pub ins_log: InsLog,
+
+    /// Whether frames where the INS solution is not good are skipped or only flagged.
+    #[arg(long, value_enum, default_value_t = InsStatusAction::Skip)]
+    pub ins_status: InsStatusAction,
+
+    /// Hide the INS from the estimator during these spans of seconds from the first frame, such
+    /// as `120..180`, so it has to coast on polarization and the gyro.
+    #[arg(long, value_delimiter = ',')]
+    pub ins_outage: Vec<OutageWindow>,
+
+    /// Interpolate the INS state to each exposure time instead of pairing records by index.
+    #[arg(long)]
+    pub interpolate_ins: bool,
+
+    /// Delay between exposure and the camera frame time.
+    #[arg(long, default_value_t = 0.0, allow_negative_numbers = true)]
+    pub camera_latency_ms: f64,
+
+    /// Camera latency fitted by `calibrate_time_offset`, which interpolates the INS state and
+    /// replaces --camera-latency-ms.
+    #[arg(long, conflicts_with = "camera_latency_ms")]
+    pub time_calibration: Option<PathBuf>,
+
+    /// Flag frames where the car yaws faster than this.
+    #[arg(long)]
+    pub max_yaw_rate_deg_s: Option<f64>,
+
+    /// Flag and warn about frames where the INS pitch or roll is beyond this.
+    #[arg(long, default_value_t = 2.0)]
+    pub max_tilt_deg: f64,
+
+    /// Simulate the sky averaged over an exposure of this length at the INS yaw rate.
+    #[arg(long)]
+    pub exposure_ms: Option<f64>,
+
+    /// Whether the INS heading is measured from true or magnetic north.
+    #[arg(long, value_enum, default_value_t = HeadingReference::True)]
+    pub heading_reference: HeadingReference,
+
+    /// World Magnetic Model coefficient file used to correct magnetic headings.
+    #[arg(long, required_if_eq("heading_reference", "magnetic"))]
+    pub wmm_cof: Option<PathBuf>,
+
+    /// GPS to UTC leap seconds, instead of the offset reported by the receiver.
+    #[arg(long)]
+    pub leap_seconds: Option<i64>,
+
+    /// How the polarizer mosaic of each image is decoded.
+    #[arg(long, value_enum, default_value_t = DemosaicMode::Bin2x2)]
+    pub demosaic: DemosaicMode,
+
+    /// Polarizer angles in degrees of each 2x2 super-pixel, row by row.
+    #[arg(long, default_value_t = MosaicLayout::default())]
+    pub mosaic_layout: MosaicLayout,
+
+    /// Clockwise rotation in degrees of the images relative to the assumed mounting.
+    #[arg(long, value_enum, default_value_t = ImageRotation::None)]
+    pub image_rotate: ImageRotation,
+
+    /// Mirroring of the images relative to the assumed mounting, applied before rotating.
+    #[arg(long, value_enum)]
+    pub image_flip: Option<ImageFlip>,
+
+    /// Pitch of the camera on the car, added to the INS pitch of every frame.
+    #[arg(long, default_value_t = 0.0, allow_negative_numbers = true)]
+    pub mount_pitch_deg: f64,
+
+    /// Roll of the camera on the car, added to the INS roll of every frame.
+    #[arg(long, default_value_t = 0.0, allow_negative_numbers = true)]
+    pub mount_roll_deg: f64,
+
+    /// Offset of the principal point from the center of the raw sensor in pixels, as `cx,cy`
+    /// along its columns and rows.
+    #[arg(long, default_value_t = PrincipalPoint::CENTERED, allow_hyphen_values = true)]
+    pub principal_point: PrincipalPoint,
+
+    /// Part of the sensor the lens images the sky onto: `full`, `auto` to find it from the
+    /// intensity of each image, or a radius in raw pixels such as `1100`, optionally off
+    /// center as `1100@12,-3`.
+    #[arg(long, default_value_t = ImageCircle::Full, allow_hyphen_values = true)]
+    pub image_circle: ImageCircle,
+
+    /// Decode up to this many images ahead of the frame being processed on a background thread.
+    #[arg(long, default_value_t = 0)]
+    pub prefetch: usize,
+
+    /// Read up to this many images at once with async I/O ahead of decoding them, for datasets
+    /// on network filesystems. Needs the `async-io` feature.
+    #[arg(long, default_value_t = 0)]
+    pub read_ahead: usize,
+
+    /// Largest fraction of saturated or underexposed super-pixels in a usable frame.
+    #[arg(long)]
+    pub max_bad_exposure: Option<f64>,
+
+    /// Mean raw level below which a super-pixel is underexposed.
+    #[arg(long, default_value_t = 8)]
+    pub dark_level: u8,
+
+    /// Whether frames over the bad exposure limit are skipped or only flagged.
+    #[arg(long, value_enum, default_value_t = ExposureAction::Skip)]
+    pub exposure_action: ExposureAction,
+
+    /// Run without checking the dataset against its `checksums.json`.
+    #[arg(long)]
+    pub no_verify: bool,
+
+    /// Also compare the SHA-256 of every dataset file with its `checksums.json`, rather than
+    /// only sizes, modification times and record counts.
+    #[arg(long, conflicts_with = "no_verify")]
+    pub verify_hashes: bool,
+
+    /// Skip checking the first frame against every sign and mirroring convention.
+    #[arg(long)]
+    pub no_convention_check: bool,
+}
+
+impl DatasetArgs {
+    pub fn dataset(&self) -> Dataset {
+        Dataset::new(&self.dataset_path)
+    }
+
+    pub fn image_reader(&self) -> ImageReader {
+        ImageReader::new()
+            .with_demosaic(self.demosaic)
+            .with_layout(self.mosaic_layout)
+            .with_orientation(self.image_orientation())
+            .with_image_circle(self.image_circle)
+    }
+
+    /// Principal point in the images read with `image_reader`.
+    pub fn principal_point(&self, image_reader: &ImageReader) -> PrincipalPoint {
+        self.principal_point.for_mosaic(image_reader)
+    }
+
+    /// Like [`Self::image_reader`], prefetching the images a run of `pipeline` reads if asked to.
+    ///
+    /// Reading ahead also decodes ahead, by at least one image.
+    pub fn prefetching_image_reader(&self, pipeline: &Pipeline) -> Result<ImageReader, BenchError> {
+        let image_reader = self.image_reader();
+        if self.read_ahead > 0 {
+            let read_ahead = ReadAhead::start(pipeline.image_paths(), self.read_ahead)?;
+            return Ok(image_reader.with_read_ahead(
+                read_ahead,
+                self.prefetch.max(1),
+                self.dark_level,
+            ));
+        }
+        let paths = if self.prefetch > 0 {
+            pipeline.image_paths()
+        } else {
+            Vec::new()
+        };
+        Ok(image_reader.with_prefetch(paths, self.prefetch, self.dark_level))
+    }
+
+    /// Tags of each frame, which are empty without a tag file.
+    pub fn frame_tags(&self) -> Result<FrameTags, BenchError> {
+        self.tags
+            .as_ref()
+            .map_or_else(|| Ok(FrameTags::default()), FrameTags::read)
+    }
+
+    pub fn exposure_gate(&self) -> ExposureGate {
+        ExposureGate::new(self.dark_level)
+            .with_max_bad_fraction(self.max_bad_exposure)
+            .with_action(self.exposure_action)
+    }
+
+    pub fn image_orientation(&self) -> ImageOrientation {
+        ImageOrientation::new(self.image_rotate, self.image_flip)
+    }
+
+    pub fn frame_alignment(&self) -> Result<FrameAlignment, BenchError> {
+        let latency_ms = match &self.time_calibration {
+            Some(path) => TimeCalibration::read(path)?.camera_latency_ms,
+            None if self.interpolate_ins => self.camera_latency_ms,
+            None => return Ok(FrameAlignment::Index),
+        };
+        Ok(FrameAlignment::interpolate_ms(latency_ms))
+    }
+
+    pub fn magnetic_model(&self) -> Result<Option<MagneticModel>, BenchError> {
+        match (self.heading_reference, &self.wmm_cof) {
+            (HeadingReference::True, _) => Ok(None),
+            (HeadingReference::Magnetic, Some(wmm_cof)) => {
+                Ok(Some(MagneticModel::read_cof(wmm_cof)?))
+            }
+            (HeadingReference::Magnetic, None) => Err(BenchError::Config(
+                "magnetic headings need a World Magnetic Model coefficient file".to_string(),
+            )),
+        }
+    }
+
+    pub fn pipeline(&self) -> Result<Pipeline, BenchError> {
+        if !self.no_verify {
+            verify_dataset(&self.dataset(), self.verify_hashes)?;
+        }
+        let ins_reader = InsReader::new()
+            .with_convention(self.ins_convention)
+            .with_leap_seconds(self.leap_seconds)
+            .with_log(self.ins_log);
+        let time_reader = TimeReader::new().with_leap_seconds(self.leap_seconds);
+
+        Ok(Pipeline::open(self.dataset(), &ins_reader, &time_reader)?
+            .with_alignment(self.frame_alignment()?)
+            .with_magnetic_model(self.magnetic_model()?)
+            .with_mounting(
+                Angle::new::<degree>(self.mount_pitch_deg),
+                Angle::new::<degree>(self.mount_roll_deg),
+            )
+            .with_step(self.step)
+            .with_max_frames(self.max_frames)
+            .with_frame_range(self.frames)
+            .with_frame_list(self.frame_list.as_ref().map(read_frame_list).transpose()?)
+            .with_max_yaw_rate(self.max_yaw_rate_deg_s)
+            .with_max_tilt(Some(self.max_tilt_deg))
+            .with_ins_status(self.ins_status)
+            .with_outages(self.ins_outage.clone())
+            .with_exposure(self.exposure_ms))
+    }
+}
+
+/// Arguments that configure the simulated sky.
+#[derive(Debug, clap::Args)]
+pub struct SkyArgs {
+    /// Ratio of total to Rayleigh optical depth used by the sky model at sea level, which
+    /// falls towards 1 at the altitude of each frame.
+    #[arg(long, default_value_t = 1.0, value_parser = parse_turbidity)]
+    pub turbidity: f64,
+
+    /// Polarization model used to simulate the sky.
+    #[arg(long, value_enum, default_value_t = SkyModelBackend::Rayleigh)]
+    pub sky_model: SkyModelBackend,
+
+    /// Lookup table for the empirical sky model.
+    #[arg(long, required_if_eq("sky_model", "empirical"))]
+    pub sky_table: Option<PathBuf>,
+
+    /// Body whose scattered light polarizes the sky.
+    #[arg(long, value_enum, default_value_t = LightSourceMode::Sun)]
+    pub light_source: LightSourceMode,
+
+    /// Skip frames where the light source is below this elevation.
+    #[arg(long, allow_negative_numbers = true)]
+    pub min_source_elevation_deg: Option<f64>,
+
+    /// DoP correction by elevation written by `calibrate_dop`.
+    #[arg(long)]
+    pub dop_calibration: Option<PathBuf>,
+
+    /// Evaluate the analytic sky through a lookup table with this grid spacing.
+    ///
+    /// The table is built once per frame and shared by every candidate.
+    #[arg(long, value_parser = parse_positive)]
+    pub sky_lut_resolution_deg: Option<f64>,
+
+    /// Simulate the sky at this altitude above the ellipsoid instead of the INS altitude.
+    #[arg(long, allow_negative_numbers = true)]
+    pub altitude_m: Option<f64>,
+
+    /// Lowest altitude to simulate the sky at.
+    #[arg(long, allow_negative_numbers = true)]
+    pub min_altitude_m: Option<f64>,
+
+    /// Highest altitude to simulate the sky at.
+    #[arg(long, allow_negative_numbers = true)]
+    pub max_altitude_m: Option<f64>,
+
+    /// Correct the light source elevation for atmospheric refraction.
+    #[arg(long)]
+    pub refraction: bool,
+
+    /// Air temperature for the refraction correction, unless the weather log has it.
+    #[arg(long, default_value_t = 10.0, allow_negative_numbers = true)]
+    pub temperature_c: f64,
+
+    /// Air pressure for the refraction correction, unless the weather log has it.
+    #[arg(long, default_value_t = 1010.0)]
+    pub pressure_hpa: f64,
+
+    /// Weather observed during the dataset, with a `time` column.
+    #[arg(long)]
+    pub weather_csv: Option<PathBuf>,
+
+    /// What to do with frames that have the sun disc in view.
+    #[arg(long, value_enum, default_value_t = GlareStrategy::Keep)]
+    pub glare: GlareStrategy,
+
+    /// Angular radius around the sun left out by `--glare mask`.
+    #[arg(long, default_value_t = 15.0)]
+    pub glare_radius_deg: f64,
+
+    /// Weight of frames with the sun in view under `--glare down-weight`.
+    #[arg(long, default_value_t = 0.25)]
+    pub glare_weight: f64,
+
+    /// Time each row of the simulated sky and write the distribution to the results.
+    #[arg(long)]
+    pub profile_simulation: bool,
+}
+
+impl SkyArgs {
+    pub fn sky(&self) -> Result<Sky, BenchError> {
+        let mut sky = Sky::new(self.turbidity)
+            .with_backend(self.sky_model)
+            .with_light_source(self.light_source)
+            .with_lut_resolution(self.sky_lut_resolution_deg)
+            .with_altitude(self.altitude())
+            .with_refraction(self.refraction())
+            .with_profiling(self.profile_simulation);
+        if let Some(sky_table) = &self.sky_table {
+            sky = sky.with_table(SkyTable::read_csv(sky_table)?);
+        }
+        if let Some(weather_csv) = &self.weather_csv {
+            sky = sky.with_weather(WeatherSeries::read_csv(weather_csv)?);
+        }
+        if let Some(dop_calibration) = &self.dop_calibration {
+            sky = sky.with_dop_calibration(DopCalibration::read_csv(dop_calibration)?);
+        }
+        Ok(sky)
+    }
+
+    pub fn glare(&self) -> GlareConfig {
+        GlareConfig {
+            strategy: self.glare,
+            radius_deg: self.glare_radius_deg,
+            weight: self.glare_weight,
+        }
+    }
+
+    pub fn refraction(&self) -> Option<Atmosphere> {
+        self.refraction.then_some(Atmosphere {
+            temperature_c: self.temperature_c,
+            pressure_hpa: self.pressure_hpa,
+        })
+    }
+
+    pub fn altitude(&self) -> AltitudeConfig {
+        AltitudeConfig {
+            override_m: self.altitude_m,
+            min_m: self.min_altitude_m,
+            max_m: self.max_altitude_m,
+        }
+    }
+}
+
+/// Arguments that tell someone when a run ends or fails.
+#[derive(Debug, clap::Args)]
+pub struct NotifyArgs {
+    /// Shell command to run when the run ends, with the summary JSON on its standard input.
+    #[arg(long)]
+    pub on_complete: Vec<String>,
+
+    /// URL to POST the summary JSON to when the run ends, such as a Slack or Matrix webhook.
+    #[arg(long)]
+    pub webhook: Vec<String>,
+}
+
+impl NotifyArgs {
+    pub fn hooks(&self) -> Result<CompletionHooks, BenchError> {
+        CompletionHooks::new(self.on_complete.clone(), self.webhook.clone())
+    }
+}
+
+/// Describes a run configured from the shared arguments.
+pub fn run_metadata(
+    experiment: &str,
+    started: &str,
+    dataset: &DatasetArgs,
+    sky: &SkyArgs,
+) -> RunMetadata {
+    RunMetadata {
+        experiment: experiment.to_string(),
+        started: started.to_string(),
+        dataset_path: dataset.dataset_path.clone(),
+        sky_model: sky.sky_model,
+        sky_table: sky.sky_table.clone(),
+        turbidity: sky.turbidity,
+        light_source: sky.light_source,
+        dop_calibration: sky.dop_calibration.clone(),
+        ins_convention: dataset.ins_convention,
+        ins_log: dataset.ins_log,
+        ins_status: dataset.ins_status,
+        ins_outages: dataset.ins_outage.clone(),
+        heading_reference: dataset.heading_reference,
+        demosaic: dataset.demosaic,
+        mosaic_layout: dataset.mosaic_layout,
+        image_orientation: dataset.image_orientation(),
+        principal_point: dataset.principal_point,
+        mount_pitch_deg: dataset.mount_pitch_deg,
+        mount_roll_deg: dataset.mount_roll_deg,
+        image_circle: dataset.image_circle,
+        exposure_gate: dataset.exposure_gate(),
+        frames: dataset.frames,
+        frame_list: dataset.frame_list.clone(),
+        tags: dataset.tags.clone(),
+        sky_lut_resolution_deg: sky.sky_lut_resolution_deg,
+        altitude: sky.altitude(),
+        refraction: sky.refraction(),
+        weather_csv: sky.weather_csv.clone(),
+        glare: sky.glare(),
+        profile: RunProfile::default(),
+        interrupted: false,
+    }
+}
+
+/// Installs a Ctrl-C handler for a binary, returning the flag it sets to interrupt runs.
+///
+/// The first Ctrl-C lets [`Pipeline::run`] finish the frame in flight, the second exits.
+pub fn interrupt_on_ctrl_c() -> Arc<AtomicBool> {
+    let interrupted = Arc::new(AtomicBool::new(false));
+    let handler_flag = Arc::clone(&interrupted);
+    let installed = ctrlc::set_handler(move || {
+        if handler_flag.swap(true, Ordering::SeqCst) {
+            std::process::exit(130);
+        }
+        eprintln!("interrupted, finishing the current frame (Ctrl-C again to abort)");
+    });
+    if let Err(e) = installed {
+        eprintln!("cannot handle Ctrl-C, interrupting will lose the summary: {e}");
+    }
+    interrupted
+}
+
+/// Parses a finite number above zero, such as a scale or a grid spacing.
+pub fn parse_positive(s: &str) -> Result<f64, String> {
+    let value: f64 = s.trim().parse().map_err(|e| format!("{e}"))?;
+    if value.is_finite() && value > 0. {
+        Ok(value)
+    } else {
+        Err(format!("{s} is not a finite number above 0"))
+    }
+}
+
+/// Parses a turbidity, which is at least 1 for a clear sky.
+pub fn parse_turbidity(s: &str) -> Result<f64, String> {
+    let turbidity: f64 = s.trim().parse().map_err(|e| format!("{e}"))?;
+    if turbidity.is_finite() && turbidity >= 1. {
+        Ok(turbidity)
+    } else {
+        Err(format!(
+            "turbidity {s} is not a finite number of at least 1"
+        ))
+    }
+}
@@ -0,0 +1,232 @@
+use crate::{
+    camera::CameraModel,
+    error::BenchError,
+    sky::Sky,
+    systems::{AzimuthConvention, InsEnu, heading_from_yaw_deg, yaw_from_heading_deg},
+    utils::{measured_to_global, weighted_rmse},
+};
+use chrono::{DateTime, Utc};
+use rumpus::{
+    image::RayImage,
+    ray::{Aop, Ray, SensorFrame},
+};
+use sguaba::{engineering::Orientation, systems::Wgs84};
+use std::fmt;
+use uom::si::{angle::degree, f64::Angle};
+
+/// One way the heading and the measured AoP could have been read, out of the eight the
+/// self-check tries.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, serde::Serialize)]
+pub struct ConventionHypothesis {
+    /// Convention the heading read from the INS is taken to be in.
+    pub azimuth: AzimuthConvention,
+    /// Whether the measured AoP turns the other way, as with a mosaic read with its polarizer
+    /// angles in mirrored order.
+    ///
+    /// Only the angles are negated. An image mirrored by a wrong `--image-flip` also has its
+    /// pixels mirrored, which no hypothesis undoes.
+    pub aop_negated: bool,
+}
+
+impl ConventionHypothesis {
+    /// The heading and AoP as configured.
+    pub const CONFIGURED: Self = Self {
+        azimuth: AzimuthConvention::LeftHandedFromNorth,
+        aop_negated: false,
+    };
+
+    pub fn all() -> impl Iterator<Item = Self> {
+        [
+            AzimuthConvention::LeftHandedFromNorth,
+            AzimuthConvention::RightHandedFromNorth,
+            AzimuthConvention::LeftHandedFromEast,
+            AzimuthConvention::RightHandedFromEast,
+        ]
+        .into_iter()
+        .flat_map(|azimuth| {
+            [false, true].map(|aop_negated| Self {
+                azimuth,
+                aop_negated,
+            })
+        })
+    }
+
+    /// Attitude of the car if the configured heading was really an azimuth in this convention.
+    pub fn attitude(&self, car_in_ins_enu: Orientation<InsEnu>) -> Orientation<InsEnu> {
+        let (yaw, pitch, roll) = car_in_ins_enu.to_tait_bryan_angles();
+        let heading_deg = self
+            .azimuth
+            .to_heading_deg(heading_from_yaw_deg(yaw.get::<degree>()));
+        Orientation::tait_bryan_builder()
+            .yaw(Angle::new::<degree>(yaw_from_heading_deg(heading_deg)))
+            .pitch(pitch)
+            .roll(roll)
+            .build()
+    }
+
+    /// The measured image as this hypothesis reads it, with every pixel left in place.
+    pub fn measured(&self, image: &RayImage<SensorFrame>) -> RayImage<SensorFrame> {
+        if !self.aop_negated {
+            return image.clone();
+        }
+        let rays: Vec<_> = image
+            .pixels()
+            .map(|px| {
+                let ray = px.ray()?;
+                let aop = Aop::from_angle_wrapped(-Angle::from(ray.aop()));
+                Some(Ray::new(aop, ray.dop()))
+            })
+            .collect();
+        RayImage::from_rays(rays, image.rows(), image.cols()).unwrap()
+    }
+}
+
+impl fmt::Display for ConventionHypothesis {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let azimuth = match self.azimuth {
+            AzimuthConvention::LeftHandedFromNorth => "heading clockwise from north",
+            AzimuthConvention::RightHandedFromNorth => "heading counter-clockwise from north",
+            AzimuthConvention::LeftHandedFromEast => "heading clockwise from east",
+            AzimuthConvention::RightHandedFromEast => "heading counter-clockwise from east",
+        };
+        let aop = if self.aop_negated {
+            "AoP negated"
+        } else {
+            "AoP as measured"
+        };
+        write!(f, "{azimuth}, {aop}")
+    }
+}
+
+/// Weighted RMSE of a frame under one hypothesis.
+#[derive(Debug, Clone, Copy)]
+pub struct ConventionResidual {
+    pub hypothesis: ConventionHypothesis,
+    pub weighted_rmse: f64,
+}
+
+/// Residuals of one frame under every sign and mirroring convention, to catch the convention
+/// errors that otherwise only show up as a poor heading estimate.
+#[derive(Debug, Clone)]
+pub struct ConventionCheck {
+    pub frame_index: usize,
+    /// Lowest residual first.
+    pub residuals: Vec<ConventionResidual>,
+}
+
+impl ConventionCheck {
+    /// Simulates the sky of a frame under every hypothesis and compares it to the measured one.
+    pub fn run(
+        frame_index: usize,
+        camera: &CameraModel,
+        sky: &Sky,
+        image: &RayImage<SensorFrame>,
+        position: &Wgs84,
+        time: DateTime<Utc>,
+        car_in_ins_enu: Orientation<InsEnu>,
+    ) -> Result<Self, BenchError> {
+        let mut residuals = ConventionHypothesis::all()
+            .map(|hypothesis| {
+                let car_in_ins_enu = hypothesis.attitude(car_in_ins_enu);
+                let simulated = sky.simulate(camera, position, car_in_ins_enu, time)?;
+                let measured =
+                    measured_to_global(&hypothesis.measured(image), camera, car_in_ins_enu);
+                Ok(ConventionResidual {
+                    hypothesis,
+                    weighted_rmse: weighted_rmse(&simulated, &measured),
+                })
+            })
+            .collect::<Result<Vec<_>, BenchError>>()?;
+        // Hypotheses without a pixel to compare go last.
+        residuals.sort_by(|a, b| {
+            a.weighted_rmse
+                .is_nan()
+                .cmp(&b.weighted_rmse.is_nan())
+                .then(a.weighted_rmse.total_cmp(&b.weighted_rmse))
+        });
+        Ok(Self {
+            frame_index,
+            residuals,
+        })
+    }
+
+    pub fn best(&self) -> Option<&ConventionResidual> {
+        self.residuals.first()
+    }
+
+    pub fn configured(&self) -> Option<&ConventionResidual> {
+        self.residuals
+            .iter()
+            .find(|residual| residual.hypothesis == ConventionHypothesis::CONFIGURED)
+    }
+
+    /// Whether another convention explains the frame better than the configured one.
+    pub fn misconfigured(&self) -> bool {
+        self.best().is_some_and(|best| {
+            best.hypothesis != ConventionHypothesis::CONFIGURED
+                && self
+                    .configured()
+                    .is_none_or(|configured| best.weighted_rmse < configured.weighted_rmse)
+        })
+    }
+
+    /// Writes one row per hypothesis, best first.
+    pub fn write<W: std::io::Write>(&self, writer: &mut csv::Writer<W>) -> csv::Result<()> {
+        #[derive(serde::Serialize)]
+        struct Row {
+            frame_index: usize,
+            azimuth: AzimuthConvention,
+            aop_negated: bool,
+            configured: bool,
+            weighted_rmse: f64,
+        }
+        for residual in &self.residuals {
+            writer.serialize(Row {
+                frame_index: self.frame_index,
+                azimuth: residual.hypothesis.azimuth,
+                aop_negated: residual.hypothesis.aop_negated,
+                configured: residual.hypothesis == ConventionHypothesis::CONFIGURED,
+                weighted_rmse: residual.weighted_rmse,
+            })?;
+        }
+        writer.flush()?;
+        Ok(())
+    }
+
+    /// Prints the residuals, and a warning with what to check if the configured convention is
+    /// not the best.
+    pub fn print(&self) {
+        println!("convention self-check on frame {:04}:", self.frame_index);
+        for residual in &self.residuals {
+            let marker = if residual.hypothesis == ConventionHypothesis::CONFIGURED {
+                " (configured)"
+            } else {
+                ""
+            };
+            println!(
+                "  {:.4}  {}{marker}",
+                residual.weighted_rmse, residual.hypothesis
+            );
+        }
+        if !self.misconfigured() {
+            return;
+        }
+        let Some(best) = self.best() else {
+            return;
+        };
+        let mut advice = Vec::new();
+        if best.hypothesis.azimuth != ConventionHypothesis::CONFIGURED.azimuth {
+            advice.push("--ins-convention");
+        }
+        if best.hypothesis.aop_negated {
+            advice.push("--image-flip and --mosaic-layout");
+        }
+        eprintln!(
+            "WARNING: frame {:04} matches the sky best with {} ({:.4}), not as configured; check {}, or pass --no-convention-check if the frame is not representative",
+            self.frame_index,
+            best.hypothesis,
+            best.weighted_rmse,
+            advice.join(" and ")
+        );
+    }
+}
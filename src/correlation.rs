@@ -0,0 +1,107 @@
+use crate::sky::SkyDirection;
+use rumpus::{image::RayImage, ray::GlobalFrame};
+use rustfft::{FftPlanner, num_complex::Complex};
+use uom::si::{
+    angle::{degree, radian},
+    f64::Angle,
+};
+
+/// Polarization of a sky image summed into bins of azimuth.
+///
+/// Each pixel contributes `dop * exp(2i * aop)` with the AoP measured from the local meridian, so
+/// rays that agree add up and rays that are perpendicular cancel out. Zenith angle is averaged
+/// away, which is what lets a yaw offset become a circular shift of the profile.
+#[derive(Debug, Clone)]
+pub struct AzimuthProfile {
+    bins: Vec<Complex<f64>>,
+}
+
+impl AzimuthProfile {
+    /// Bins the valid rays of an image by the azimuth each pixel looks at.
+    ///
+    /// `directions` are in row-major order, as returned by [`crate::sky::sky_directions`].
+    #[allow(
+        clippy::cast_precision_loss,
+        clippy::cast_possible_truncation,
+        clippy::cast_sign_loss
+    )]
+    pub fn new(
+        rays: &RayImage<GlobalFrame>,
+        directions: &[Option<SkyDirection>],
+        bins: usize,
+    ) -> Self {
+        let mut profile = vec![Complex::new(0., 0.); bins];
+        let bin_width_deg = 360. / bins as f64;
+        for rpx in rays.pixels() {
+            let Some(ray) = rpx.ray() else {
+                continue;
+            };
+            let Some(Some(direction)) = directions.get(rpx.row() * rays.cols() + rpx.col()) else {
+                continue;
+            };
+            let azimuth_deg = direction.azimuth.get::<degree>().rem_euclid(360.);
+            let bin = ((azimuth_deg / bin_width_deg) as usize).min(bins - 1);
+            let aop = Angle::from(ray.aop()).get::<radian>();
+            profile[bin] += Complex::from_polar(ray.dop(), 2. * aop);
+        }
+        Self { bins: profile }
+    }
+
+    pub fn bins(&self) -> usize {
+        self.bins.len()
+    }
+
+    /// Normalized circular cross-correlation with a template profile at every lag.
+    ///
+    /// Lag `k` compares bin `a` of this profile with bin `a - k` of the template, so a peak at
+    /// `k` means this sky is the template rotated by `k` bins of increasing azimuth.
+    /// Values range from -1 for opposite polarization to 1 for a perfect match, and computing
+    /// them all takes O(n log n) through the FFT.
+    #[allow(clippy::cast_precision_loss)]
+    pub fn cross_correlate(&self, template: &Self) -> Vec<f64> {
+        assert_eq!(
+            self.bins(),
+            template.bins(),
+            "profiles must have the same bins"
+        );
+        let n = self.bins();
+
+        let mut planner = FftPlanner::<f64>::new();
+        let forward = planner.plan_fft_forward(n);
+        let inverse = planner.plan_fft_inverse(n);
+
+        let mut measured = self.bins.clone();
+        let mut reference = template.bins.clone();
+        forward.process(&mut measured);
+        forward.process(&mut reference);
+
+        let mut spectrum: Vec<_> = measured
+            .iter()
+            .zip(&reference)
+            .map(|(m, t)| m * t.conj())
+            .collect();
+        inverse.process(&mut spectrum);
+
+        // The inverse FFT is not normalized by rustfft.
+        let norm = n as f64 * (energy(&self.bins) * energy(&template.bins)).sqrt();
+        spectrum
+            .iter()
+            .map(|c| if norm > 0. { c.re / norm } else { 0. })
+            .collect()
+    }
+}
+
+/// Yaw offset in degrees that a lag of the cross-correlation corresponds to, in [-180, 180).
+///
+/// Yaw is counter-clockwise while azimuth is clockwise, so turning the car by a positive yaw
+/// offset moves every pixel to a smaller azimuth, and the sky they see appears shifted towards
+/// larger azimuths of the attitude reference by the same amount.
+#[allow(clippy::cast_precision_loss)]
+pub fn lag_offset_deg(lag: usize, bins: usize) -> f64 {
+    let offset_deg = lag as f64 * 360. / bins as f64;
+    (offset_deg + 180.).rem_euclid(360.) - 180.
+}
+
+fn energy(bins: &[Complex<f64>]) -> f64 {
+    bins.iter().map(Complex::norm_sqr).sum()
+}
@@ -0,0 +1,96 @@
+use crate::{error::BenchError, monitor::RunMonitor};
+use std::{
+    io::{BufRead, BufReader, Cursor, Write},
+    net::{SocketAddr, TcpListener, TcpStream},
+    sync::Arc,
+    time::Duration,
+};
+
+/// Page that polls the run's progress and draws it.
+const PAGE: &str = include_str!("dashboard.html");
+
+/// How long a client may take to send its request, or to read each part of the response,
+/// before it is dropped.
+const CLIENT_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// Content type and body of a response, or `None` for a path that is not served.
+pub(crate) type Response = std::io::Result<Option<(&'static str, Vec<u8>)>>;
+
+/// Serves a page showing the progress of a run from a background thread.
+///
+/// Besides the page at `/`, the progress is served as `/progress.json` and the image of the
+/// last frame as `/frame.png`. Returns the address listened on, which has the port picked
+/// when `addr` asks for port 0.
+pub fn serve(addr: SocketAddr, monitor: RunMonitor) -> Result<SocketAddr, BenchError> {
+    serve_http(addr, move |path| {
+        Ok(match path {
+            "/" => Some(("text/html; charset=utf-8", PAGE.as_bytes().to_vec())),
+            "/progress.json" => Some((
+                "application/json",
+                serde_json::to_vec(&monitor.progress()).map_err(std::io::Error::other)?,
+            )),
+            "/frame.png" => match monitor.image() {
+                Some(image) => {
+                    let mut png = Vec::new();
+                    image
+                        .write_to(&mut Cursor::new(&mut png), image::ImageFormat::Png)
+                        .map_err(std::io::Error::other)?;
+                    Some(("image/png", png))
+                }
+                None => None,
+            },
+            _ => None,
+        })
+    })
+}
+
+/// Answers GET requests by path from background threads, one per connection, so a client
+/// that stalls does not hold up the others.
+pub(crate) fn serve_http(
+    addr: SocketAddr,
+    route: impl Fn(&str) -> Response + Send + Sync + 'static,
+) -> Result<SocketAddr, BenchError> {
+    let listener = TcpListener::bind(addr).map_err(|e| BenchError::output(addr, e))?;
+    let local_addr = listener
+        .local_addr()
+        .map_err(|e| BenchError::output(addr, e))?;
+    let route = Arc::new(route);
+    std::thread::spawn(move || {
+        for stream in listener.incoming().flatten() {
+            let route = Arc::clone(&route);
+            // A client that goes away mid-request is no reason to stop serving.
+            std::thread::spawn(move || respond(stream, &*route));
+        }
+    });
+    Ok(local_addr)
+}
+
+/// Answers a single request.
+fn respond(mut stream: TcpStream, route: impl Fn(&str) -> Response) -> std::io::Result<()> {
+    stream.set_read_timeout(Some(CLIENT_TIMEOUT))?;
+    stream.set_write_timeout(Some(CLIENT_TIMEOUT))?;
+    let mut reader = BufReader::new(&stream);
+    let mut request_line = String::new();
+    reader.read_line(&mut request_line)?;
+    // Nothing in the headers changes the response.
+    let mut header = String::new();
+    while reader.read_line(&mut header)? > 0 && !header.trim().is_empty() {
+        header.clear();
+    }
+
+    let path = request_line
+        .split_whitespace()
+        .nth(1)
+        .and_then(|target| target.split('?').next())
+        .unwrap_or("/");
+    let (status, content_type, body) = match route(path)? {
+        Some((content_type, body)) => ("200 OK", content_type, body),
+        None => ("404 Not Found", "text/plain", b"not found".to_vec()),
+    };
+    write!(
+        stream,
+        "HTTP/1.1 {status}\r\nContent-Type: {content_type}\r\nContent-Length: {}\r\nCache-Control: no-store\r\nConnection: close\r\n\r\n",
+        body.len()
+    )?;
+    stream.write_all(&body)
+}
@@ -0,0 +1,174 @@
+use crate::{archive, error::BenchError};
+use chrono::{DateTime, Utc};
+use std::path::Path;
+
+/// Mean radius of the earth, plenty for the few kilometres of a drive.
+const EARTH_RADIUS_M: f64 = 6_371_008.8;
+
+/// A route counts as a loop when the INS ends this close to where it started.
+pub const LOOP_CLOSURE_M: f64 = 25.;
+
+/// Vehicle speed over time, read from a CSV with `time` and `speed_m_s` columns.
+#[derive(Debug, Clone)]
+pub struct SpeedSeries {
+    samples: Vec<SpeedSample>,
+}
+
+#[derive(Debug, Clone, Copy, serde::Deserialize)]
+pub struct SpeedSample {
+    pub time: DateTime<Utc>,
+    pub speed_m_s: f64,
+}
+
+impl SpeedSeries {
+    pub fn read_csv<P: AsRef<Path>>(path: P) -> Result<Self, BenchError> {
+        let path = path.as_ref();
+        let mut reader = csv::Reader::from_reader(archive::open_file(path)?);
+        let samples = reader
+            .deserialize()
+            .enumerate()
+            .map(|(i, sample)| sample.map_err(|e| BenchError::parse(path, i, e)))
+            .collect::<Result<Vec<SpeedSample>, _>>()?;
+        Self::new(samples).ok_or_else(|| BenchError::parse(path, 0, "odometry log is empty"))
+    }
+
+    /// Returns `None` without any samples.
+    pub fn new(mut samples: Vec<SpeedSample>) -> Option<Self> {
+        samples.sort_by_key(|sample| sample.time);
+        (!samples.is_empty()).then_some(Self { samples })
+    }
+
+    /// Speed between fixes of a reference trajectory, for datasets without wheel odometry.
+    pub fn from_fixes(fixes: &[(DateTime<Utc>, LocalPosition)]) -> Option<Self> {
+        let samples = fixes
+            .windows(2)
+            .filter_map(|pair| {
+                let [(from_time, from), (to_time, to)] = pair else {
+                    unreachable!()
+                };
+                let dt_s = (*to_time - *from_time).as_seconds_f64();
+                (dt_s > 0.).then(|| SpeedSample {
+                    time: *to_time,
+                    speed_m_s: from.distance_m(to) / dt_s,
+                })
+            })
+            .collect();
+        Self::new(samples)
+    }
+
+    /// Linearly interpolated speed, held constant beyond the ends of the log.
+    pub fn at(&self, time: DateTime<Utc>) -> f64 {
+        let next = self.samples.partition_point(|sample| sample.time <= time);
+        match (
+            next.checked_sub(1).and_then(|i| self.samples.get(i)),
+            self.samples.get(next),
+        ) {
+            (Some(before), Some(after)) => {
+                let t = (time - before.time).as_seconds_f64()
+                    / (after.time - before.time).as_seconds_f64();
+                before.speed_m_s + t * (after.speed_m_s - before.speed_m_s)
+            }
+            (Some(sample), None) | (None, Some(sample)) => sample.speed_m_s,
+            (None, None) => unreachable!("a speed series is never empty"),
+        }
+    }
+}
+
+/// East and north of an origin on a plane tangent to the earth there.
+#[derive(Debug, Clone, Copy, Default, PartialEq, serde::Serialize)]
+pub struct LocalPosition {
+    pub east_m: f64,
+    pub north_m: f64,
+}
+
+impl LocalPosition {
+    /// Equirectangular projection around `origin`, given as latitude and longitude in degrees.
+    pub fn from_geodetic(origin: (f64, f64), latitude_deg: f64, longitude_deg: f64) -> Self {
+        let (origin_latitude_deg, origin_longitude_deg) = origin;
+        Self {
+            east_m: EARTH_RADIUS_M
+                * (longitude_deg - origin_longitude_deg).to_radians()
+                * origin_latitude_deg.to_radians().cos(),
+            north_m: EARTH_RADIUS_M * (latitude_deg - origin_latitude_deg).to_radians(),
+        }
+    }
+
+    pub fn distance_m(&self, other: &Self) -> f64 {
+        (self.east_m - other.east_m).hypot(self.north_m - other.north_m)
+    }
+}
+
+/// Integrates a compass heading and a speed into a position.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct DeadReckoner {
+    position: LocalPosition,
+    distance_m: f64,
+}
+
+impl DeadReckoner {
+    pub fn new(start: LocalPosition) -> Self {
+        Self {
+            position: start,
+            distance_m: 0.,
+        }
+    }
+
+    /// Moves along a heading, clockwise from north, at a speed for a time.
+    pub fn advance(&mut self, heading_deg: f64, speed_m_s: f64, dt_s: f64) -> LocalPosition {
+        let step_m = speed_m_s * dt_s;
+        let heading = heading_deg.to_radians();
+        self.position.east_m += step_m * heading.sin();
+        self.position.north_m += step_m * heading.cos();
+        self.distance_m += step_m.abs();
+        self.position
+    }
+
+    pub fn position(&self) -> LocalPosition {
+        self.position
+    }
+
+    /// Path length travelled so far.
+    pub fn distance_m(&self) -> f64 {
+        self.distance_m
+    }
+}
+
+/// How far a dead-reckoned trajectory ended up from the reference.
+#[derive(Debug, Clone, Copy, serde::Serialize)]
+pub struct DriftSummary {
+    pub distance_m: f64,
+    pub final_drift_m: f64,
+    pub max_drift_m: f64,
+    /// Final drift as a percentage of the distance travelled.
+    pub drift_percent: f64,
+    /// Gap between the ends of the dead-reckoned trajectory, if the reference is a loop.
+    pub loop_closure_m: Option<f64>,
+}
+
+impl DriftSummary {
+    /// Compares paired positions, or returns `None` for fewer than two.
+    pub fn new(
+        reference: &[LocalPosition],
+        estimated: &[LocalPosition],
+        distance_m: f64,
+    ) -> Option<Self> {
+        let (&reference_start, &reference_end) = reference.first().zip(reference.last())?;
+        let (&estimated_start, &estimated_end) = estimated.first().zip(estimated.last())?;
+        if reference.len() < 2 || reference.len() != estimated.len() {
+            return None;
+        }
+        let drifts = reference
+            .iter()
+            .zip(estimated)
+            .map(|(reference, estimated)| reference.distance_m(estimated));
+        let final_drift_m = reference_end.distance_m(&estimated_end);
+        Some(Self {
+            distance_m,
+            final_drift_m,
+            max_drift_m: drifts.fold(0., f64::max),
+            drift_percent: 100. * final_drift_m / distance_m.max(f64::EPSILON),
+            loop_closure_m: (reference_start.distance_m(&reference_end) < LOOP_CLOSURE_M)
+                .then(|| estimated_start.distance_m(&estimated_end)),
+        })
+    }
+}
@@ -0,0 +1,185 @@
+use chrono::{DateTime, Utc};
+use sguaba::systems::Wgs84;
+use uom::si::{
+    angle::{degree, radian},
+    f64::Angle,
+};
+
+/// Apparent position of a celestial body in the local sky.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct CelestialPosition {
+    /// Measured clockwise from true north.
+    pub azimuth: Angle,
+    /// Measured up from the horizon.
+    pub elevation: Angle,
+}
+
+impl CelestialPosition {
+    /// Computes the sun position with the NOAA solar calculator equations.
+    ///
+    /// Accurate to about 0.01° for dates between 1800 and 2100, ignoring atmospheric refraction.
+    pub fn sun(position: &Wgs84, time: DateTime<Utc>) -> Self {
+        let longitude = position.longitude().get::<degree>();
+        let t = julian_centuries(time);
+
+        let mean_longitude = (280.466_46 + t * (36_000.769_83 + 0.000_303_2 * t)).rem_euclid(360.);
+        let mean_anomaly = 357.529_11 + t * (35_999.050_29 - 0.000_153_7 * t);
+        let eccentricity = 0.016_708_634 - t * (0.000_042_037 + 0.000_000_126_7 * t);
+
+        let m = mean_anomaly.to_radians();
+        let center = m.sin() * (1.914_602 - t * (0.004_817 + 0.000_014 * t))
+            + (2. * m).sin() * (0.019_993 - 0.000_101 * t)
+            + (3. * m).sin() * 0.000_289;
+
+        let omega = (125.04 - 1934.136 * t).to_radians();
+        let apparent_longitude =
+            (mean_longitude + center - 0.005_69 - 0.004_78 * omega.sin()).to_radians();
+
+        let mean_obliquity =
+            23. + (26. + (21.448 - t * (46.815 + t * (0.000_59 - t * 0.001_813))) / 60.) / 60.;
+        let obliquity = (mean_obliquity + 0.002_56 * omega.cos()).to_radians();
+
+        let declination = (obliquity.sin() * apparent_longitude.sin()).asin();
+
+        // Equation of time in minutes.
+        let l0 = mean_longitude.to_radians();
+        let y = (obliquity / 2.).tan().powi(2);
+        let equation_of_time = 4.
+            * (y * (2. * l0).sin() - 2. * eccentricity * m.sin()
+                + 4. * eccentricity * y * m.sin() * (2. * l0).cos()
+                - 0.5 * y * y * (4. * l0).sin()
+                - 1.25 * eccentricity * eccentricity * (2. * m).sin())
+            .to_degrees();
+
+        #[allow(clippy::cast_precision_loss)]
+        let minutes_of_day = (time.timestamp_millis().rem_euclid(86_400_000)) as f64 / 60_000.0;
+        let true_solar_time =
+            (minutes_of_day + equation_of_time + 4. * longitude).rem_euclid(1440.);
+        let hour_angle = (true_solar_time / 4. - 180.).to_radians();
+
+        horizontal(position, declination, hour_angle)
+    }
+
+    /// Computes the topocentric moon position with the low-precision series from the
+    /// Astronomical Almanac.
+    ///
+    /// Accurate to about 0.3° between 1950 and 2050, ignoring atmospheric refraction.
+    pub fn moon(position: &Wgs84, time: DateTime<Utc>) -> Self {
+        let t = julian_centuries(time);
+        let sin = |a: f64, b: f64| (a + b * t).to_radians().sin();
+        let cos = |a: f64, b: f64| (a + b * t).to_radians().cos();
+
+        let longitude = (218.32 + 481_267.881 * t + 6.29 * sin(135.0, 477_198.87)
+            - 1.27 * sin(259.3, -413_335.36)
+            + 0.66 * sin(235.7, 890_534.22)
+            + 0.21 * sin(269.9, 954_397.74)
+            - 0.19 * sin(357.5, 35_999.05)
+            - 0.11 * sin(186.5, 966_404.03))
+        .to_radians();
+        let latitude = (5.13 * sin(93.3, 483_202.02) + 0.28 * sin(228.2, 960_400.89)
+            - 0.28 * sin(318.3, 6003.15)
+            - 0.17 * sin(217.6, -407_332.21))
+        .to_radians();
+        let parallax = (0.9508
+            + 0.0518 * cos(135.0, 477_198.87)
+            + 0.0095 * cos(259.3, -413_335.36)
+            + 0.0078 * cos(235.7, 890_534.22)
+            + 0.0028 * cos(269.9, 954_397.74))
+        .to_radians();
+
+        // Ecliptic to equatorial coordinates.
+        let l = latitude.cos() * longitude.cos();
+        let m = 0.9175 * latitude.cos() * longitude.sin() - 0.3978 * latitude.sin();
+        let n = 0.3978 * latitude.cos() * longitude.sin() + 0.9175 * latitude.sin();
+        let right_ascension = m.atan2(l);
+        let declination = n.asin();
+
+        let sidereal_time = (280.460_618_37 + 36_525. * 360.985_647_366_29 * t).to_radians()
+            + position.longitude().get::<radian>();
+        let hour_angle = sidereal_time - right_ascension;
+
+        // The moon is close enough that the observer's offset from the geocentre matters.
+        let geocentric = horizontal(position, declination, hour_angle);
+        let elevation = geocentric.elevation.get::<radian>();
+
+        Self {
+            azimuth: geocentric.azimuth,
+            elevation: Angle::new::<radian>(elevation - parallax * elevation.cos()),
+        }
+    }
+}
+
+impl CelestialPosition {
+    /// Great-circle angle between two positions.
+    pub fn separation(&self, other: &Self) -> Angle {
+        let (e1, e2) = (
+            self.elevation.get::<radian>(),
+            other.elevation.get::<radian>(),
+        );
+        let delta_azimuth = (self.azimuth - other.azimuth).get::<radian>();
+        let cos = e1.sin() * e2.sin() + e1.cos() * e2.cos() * delta_azimuth.cos();
+        Angle::new::<radian>(cos.clamp(-1., 1.).acos())
+    }
+}
+
+/// Surface conditions that set how far the atmosphere lifts bodies near the horizon.
+#[derive(Debug, Clone, Copy, PartialEq, serde::Serialize)]
+pub struct Atmosphere {
+    pub temperature_c: f64,
+    pub pressure_hpa: f64,
+}
+
+impl Default for Atmosphere {
+    /// The conditions Sæmundsson's formula is tabulated for.
+    fn default() -> Self {
+        Self {
+            temperature_c: 10.,
+            pressure_hpa: 1010.,
+        }
+    }
+}
+
+impl CelestialPosition {
+    /// Apparent position once atmospheric refraction is accounted for, using Sæmundsson's
+    /// formula scaled by temperature and pressure.
+    ///
+    /// Refraction is held at its value for 1° below the horizon, where the formula breaks down.
+    pub fn refracted(self, atmosphere: &Atmosphere) -> Self {
+        let elevation_deg = self.elevation.get::<degree>().max(-1.);
+        let refraction_arcmin = 1.02
+            / (elevation_deg + 10.3 / (elevation_deg + 5.11))
+                .to_radians()
+                .tan()
+            * (atmosphere.pressure_hpa / 1010.)
+            * (283. / (273. + atmosphere.temperature_c));
+        Self {
+            elevation: self.elevation + Angle::new::<degree>(refraction_arcmin / 60.),
+            ..self
+        }
+    }
+}
+
+/// Julian centuries since J2000.0.
+fn julian_centuries(time: DateTime<Utc>) -> f64 {
+    #[allow(clippy::cast_precision_loss)]
+    let julian_day = time.timestamp_millis() as f64 / 86_400_000.0 + 2_440_587.5;
+    (julian_day - 2_451_545.0) / 36_525.0
+}
+
+/// Converts equatorial coordinates in radians to the local sky.
+fn horizontal(position: &Wgs84, declination: f64, hour_angle: f64) -> CelestialPosition {
+    let latitude = position.latitude().get::<radian>();
+
+    let elevation = (latitude.sin() * declination.sin()
+        + latitude.cos() * declination.cos() * hour_angle.cos())
+    .asin();
+    let azimuth = hour_angle
+        .sin()
+        .atan2(hour_angle.cos() * latitude.sin() - declination.tan() * latitude.cos())
+        + std::f64::consts::PI;
+
+    CelestialPosition {
+        azimuth: Angle::new::<radian>(azimuth.rem_euclid(std::f64::consts::TAU)),
+        elevation: Angle::new::<radian>(elevation),
+    }
+}
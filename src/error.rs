@@ -0,0 +1,82 @@
+use std::{
+    error::Error,
+    fmt::Display,
+    path::{Path, PathBuf},
+};
+
+type Source = Box<dyn Error + Send + Sync + 'static>;
+
+/// Everything that can go wrong while running a benchmark.
+#[derive(Debug, thiserror::Error)]
+pub enum BenchError {
+    /// A dataset or configuration file is missing or unreadable.
+    #[error("cannot read {}: {source}", path.display())]
+    Dataset { path: PathBuf, source: Source },
+
+    /// A record of an INS, time or table log could not be understood.
+    #[error("{} record {record}: {message}", path.display())]
+    Parse {
+        path: PathBuf,
+        record: usize,
+        message: String,
+    },
+
+    /// A polarization image could not be decoded.
+    #[error("cannot decode image {}: {message}", path.display())]
+    Image { path: PathBuf, message: String },
+
+    /// Raw mosaic bytes do not form a polarization image.
+    #[error("invalid polarizer mosaic: {0}")]
+    Mosaic(String),
+
+    /// The sky could not be simulated.
+    #[error("simulation failed: {0}")]
+    Simulation(String),
+
+    /// Results could not be written or sent.
+    #[error("cannot write {target}: {source}")]
+    Output { target: String, source: Source },
+
+    /// Options that do not work together.
+    #[error("{0}")]
+    Config(String),
+
+    /// Any of the above, while processing a particular frame.
+    #[error("frame {frame_index}: {source}")]
+    Frame {
+        frame_index: usize,
+        source: Box<BenchError>,
+    },
+}
+
+impl BenchError {
+    pub(crate) fn dataset(path: impl AsRef<Path>, source: impl Into<Source>) -> Self {
+        Self::Dataset {
+            path: path.as_ref().to_path_buf(),
+            source: source.into(),
+        }
+    }
+
+    pub(crate) fn parse(path: impl AsRef<Path>, record: usize, message: impl Display) -> Self {
+        Self::Parse {
+            path: path.as_ref().to_path_buf(),
+            record,
+            message: message.to_string(),
+        }
+    }
+
+    pub(crate) fn output(target: impl Display, source: impl Into<Source>) -> Self {
+        Self::Output {
+            target: target.to_string(),
+            source: source.into(),
+        }
+    }
+
+    /// Adds the frame being processed when the error happened.
+    pub fn in_frame(self, frame_index: usize) -> Self {
+        Self::Frame {
+            frame_index,
+            source: Box::new(self),
+        }
+    }
+}
@@ -0,0 +1,448 @@
+use crate::{
+    camera::CameraModel,
+    correlation::{AzimuthProfile, lag_offset_deg},
+    ephemeris::CelestialPosition,
+    error::BenchError,
+    glare::GlareConfig,
+    heading::{CostSample, HeadingEstimate, SearchWindow},
+    histogram::aop_emd_deg,
+    image_circle::Circle,
+    run::{RunProfile, TimingRecord, timed},
+    scattering::{estimate_source, global_to_scattering_plane_with},
+    scratch::{Scratch, ScratchPool},
+    sky::{Sky, sky_directions_into},
+    smoothing::wrap_deg,
+    systems::InsEnu,
+    utils::{global_to_sensor_with, measured_to_global_with, valid_fraction},
+    validity::{ValidityCounts, ValidityMask},
+};
+use chrono::{DateTime, Utc};
+use rayon::prelude::*;
+use rumpus::{
+    image::RayImage,
+    ray::{GlobalFrame, SensorFrame},
+};
+use sguaba::{engineering::Orientation, systems::Wgs84};
+use uom::si::{angle::degree, f64::Angle};
+
+/// Everything the estimator needs to know about a single camera frame.
+pub struct FrameInput<'a> {
+    pub frame_index: usize,
+    pub image: &'a RayImage<SensorFrame>,
+    /// Circle in rays outside of which the image reader dropped the rays of `image`, if it did.
+    pub image_circle: Option<Circle>,
+    pub position: &'a Wgs84,
+    pub time: DateTime<Utc>,
+    /// Attitude reference whose pitch and roll are trusted and whose yaw is refined.
+    pub car_in_ins_enu: Orientation<InsEnu>,
+    /// Yaw swept during the exposure.
+    pub yaw_smear: Angle,
+}
+
+/// Cost of one yaw candidate.
+#[derive(Debug, Clone, Copy)]
+pub struct Candidate {
+    pub yaw_offset_deg: f64,
+    /// Weighted AoP RMSE, or the correlation cost when found by [`HeadingEstimator::correlate`].
+    pub weighted_rmse: f64,
+    pub valid_fraction: f64,
+    /// Why pixels were or were not compared.
+    pub validity: ValidityCounts,
+    /// Earth mover's distance in degrees between the measured and simulated AoP histograms, if
+    /// the estimator measures it and any pixel is valid in both.
+    ///
+    /// See [`crate::histogram::aop_emd_deg`] for why this flags calibration and convention
+    /// errors rather than heading errors.
+    pub aop_emd_deg: Option<f64>,
+    pub timing: TimingRecord,
+}
+
+impl Candidate {
+    pub fn cost_sample(&self) -> CostSample {
+        CostSample {
+            yaw_offset_deg: self.yaw_offset_deg,
+            cost: self.weighted_rmse,
+            valid_fraction: self.valid_fraction,
+        }
+    }
+}
+
+/// How the yaw candidates of a frame are scored.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, clap::ValueEnum, serde::Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum SearchMethod {
+    /// Simulate the sky for every candidate and compare the images.
+    #[default]
+    Sweep,
+    /// Cross-correlate azimuth profiles of the measurement and a single simulated template.
+    Correlation,
+}
+
+/// Frame that swept candidates compare the measured and simulated angles of polarization in.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, clap::ValueEnum, serde::Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum ComparisonFrame {
+    /// Convert the measurement to the global frame, around the zenith pixel when it is in view.
+    #[default]
+    Global,
+    /// Convert the simulation to the sensor frame, along the meridian of every pixel.
+    Sensor,
+    /// Refer the measured AoP to the scattering planes through the light source found in the
+    /// measurement, and the simulated AoP to those through the source the ephemeris predicts.
+    ///
+    /// An error in where the ephemeris puts the source moves the simulated sky together with
+    /// its reference, so what is left is how well the model explains the sky around its own
+    /// source. The cost then hardly depends on the yaw offset, which tells model errors apart
+    /// from timing and ephemeris errors rather than estimating the heading.
+    ScatteringPlane,
+}
+
+/// Matches simulated skies against a measured polarization image to find the heading.
+#[derive(Debug, Clone)]
+pub struct HeadingEstimator {
+    camera: CameraModel,
+    sky: Sky,
+    glare: GlareConfig,
+    comparison_frame: ComparisonFrame,
+    aop_emd: bool,
+    profile: RunProfile,
+    scratch: ScratchPool,
+}
+
+impl HeadingEstimator {
+    pub fn new(camera: CameraModel, sky: Sky) -> Self {
+        Self {
+            camera,
+            sky,
+            glare: GlareConfig::default(),
+            comparison_frame: ComparisonFrame::default(),
+            aop_emd: false,
+            profile: RunProfile::default(),
+            scratch: ScratchPool::default(),
+        }
+    }
+
+    /// Masks the glare around the sun out of swept candidates, if configured to.
+    pub fn with_glare(mut self, glare: GlareConfig) -> Self {
+        self.glare = glare;
+        self
+    }
+
+    /// Simulates through another camera, such as to try other intrinsics.
+    pub fn with_camera(mut self, camera: CameraModel) -> Self {
+        self.camera = camera;
+        self
+    }
+
+    /// Compares swept candidates in this frame. Correlation always uses the global frame.
+    pub fn with_comparison_frame(mut self, comparison_frame: ComparisonFrame) -> Self {
+        self.comparison_frame = comparison_frame;
+        self
+    }
+
+    /// Also measures how far apart the AoP histograms of every candidate are.
+    pub fn with_aop_emd(mut self, aop_emd: bool) -> Self {
+        self.aop_emd = aop_emd;
+        self
+    }
+
+    /// Keeps to the resources of a run profile. The embedded profile sweeps candidates one
+    /// after another on the calling thread and scores them in single precision.
+    pub fn with_profile(mut self, profile: RunProfile) -> Self {
+        self.profile = profile;
+        self.sky = self.sky.with_parallel(profile.parallel());
+        self
+    }
+
+    pub fn camera(&self) -> &CameraModel {
+        &self.camera
+    }
+
+    pub fn sky(&self) -> &Sky {
+        &self.sky
+    }
+
+    /// Buffers reused by every candidate of every frame this estimator, or a clone of it, sees.
+    pub fn scratch(&self) -> &ScratchPool {
+        &self.scratch
+    }
+
+    /// Evaluates every yaw offset from the attitude reference, in parallel unless the run
+    /// profile keeps to one thread.
+    ///
+    /// Candidates share the measured image, the light source geometry, the glare mask and the
+    /// sky's lookup table, if it has one, but each simulates its own sky. Candidates are returned
+    /// in sweep order. Candidates that cannot be evaluated are reported once the sweep is done
+    /// and left out.
+    pub fn sweep(&self, frame: &FrameInput, yaw_offsets: &[f64]) -> Vec<Candidate> {
+        let (car_yaw, pitch, roll) = frame.car_in_ins_enu.to_tait_bryan_angles();
+        let sun = CelestialPosition::sun(frame.position, frame.time);
+        // The light source is located once for every candidate of the frame.
+        let geometry = self.sky.source_geometry(frame.position, frame.time);
+        // The glare is masked around the sun at the attitude reference, so every candidate is
+        // scored over the same pixels.
+        let glare_mask = self.glare.mask(&self.camera, frame.car_in_ins_enu, &sun);
+        // The source found in the measurement turns with the candidate yaw just like the
+        // pixels, so the measurement is referred to it once for every candidate.
+        let measured_in_plane = match self.comparison_frame {
+            ComparisonFrame::ScatteringPlane => {
+                let measured = self.scratch.with(|scratch| {
+                    self.measured_to_scattering_plane(frame, &geometry.source, scratch)
+                });
+                let Some(measured) = measured else {
+                    eprintln!(
+                        "frame {}: no light source stands out in the measured AoP",
+                        frame.frame_index
+                    );
+                    return Vec::new();
+                };
+                Some(measured)
+            }
+            ComparisonFrame::Global | ComparisonFrame::Sensor => None,
+        };
+
+        let evaluate = |(candidate_index, &yaw_offset_deg): (usize, &f64)| {
+            self.scratch.with(|scratch| {
+                let mut timing = TimingRecord::candidate(frame.frame_index, candidate_index);
+
+                // Figure out the orientation of the car for this candidate.
+                let car_in_ins_enu: Orientation<InsEnu> = Orientation::tait_bryan_builder()
+                    .yaw(car_yaw + Angle::new::<degree>(yaw_offset_deg))
+                    .pitch(pitch)
+                    .roll(roll)
+                    .build();
+
+                let simulated = timed(&mut timing.simulate_ms, || {
+                    self.sky.simulate_smeared_with_geometry(
+                        &self.camera,
+                        &geometry,
+                        car_in_ins_enu,
+                        frame.yaw_smear,
+                        scratch,
+                    )
+                })?;
+                let (weighted_rmse, validity, aop_emd_deg) = match self.comparison_frame {
+                    ComparisonFrame::Global => {
+                        let measured = timed(&mut timing.transform_ms, || {
+                            measured_to_global_with(
+                                frame.image,
+                                &self.camera,
+                                car_in_ins_enu,
+                                scratch,
+                            )
+                        });
+                        timed(&mut timing.rmse_ms, || {
+                            let images = (frame.image, &simulated, &measured, &simulated);
+                            self.score(images, glare_mask.as_deref(), frame.image_circle, scratch)
+                        })
+                    }
+                    ComparisonFrame::Sensor => {
+                        let converted = timed(&mut timing.transform_ms, || {
+                            global_to_sensor_with(&simulated, &self.camera, car_in_ins_enu, scratch)
+                        });
+                        timed(&mut timing.rmse_ms, || {
+                            let images = (frame.image, &simulated, frame.image, &converted);
+                            self.score(images, glare_mask.as_deref(), frame.image_circle, scratch)
+                        })
+                    }
+                    ComparisonFrame::ScatteringPlane => {
+                        let measured = measured_in_plane
+                            .as_ref()
+                            .expect("the measurement is referred before the sweep");
+                        let converted = timed(&mut timing.transform_ms, || {
+                            global_to_scattering_plane_with(
+                                &simulated,
+                                &self.camera,
+                                car_in_ins_enu,
+                                &geometry.source,
+                                scratch,
+                            )
+                        });
+                        timed(&mut timing.rmse_ms, || {
+                            let images = (frame.image, &simulated, measured, &converted);
+                            self.score(images, glare_mask.as_deref(), frame.image_circle, scratch)
+                        })
+                    }
+                };
+
+                Ok(Candidate {
+                    yaw_offset_deg,
+                    weighted_rmse,
+                    valid_fraction: validity.valid_fraction(),
+                    validity,
+                    aop_emd_deg,
+                    timing,
+                })
+            })
+        };
+        let candidates: Vec<Result<Candidate, BenchError>> = if self.profile.parallel() {
+            yaw_offsets.par_iter().enumerate().map(evaluate).collect()
+        } else {
+            yaw_offsets.iter().enumerate().map(evaluate).collect()
+        };
+
+        // Reported here rather than from the workers, whose messages would interleave.
+        candidates
+            .into_iter()
+            .enumerate()
+            .filter_map(|(candidate_index, candidate)| {
+                candidate
+                    .map_err(|e| eprintln!("failed to simulate candidate {candidate_index}: {e}"))
+                    .ok()
+            })
+            .collect()
+    }
+
+    /// Measured image referred to the scattering planes through the light source found in it at
+    /// the attitude reference, or `None` if no source stands out.
+    ///
+    /// `near` only tells the source from the anti-source, so its error does not carry over.
+    fn measured_to_scattering_plane(
+        &self,
+        frame: &FrameInput,
+        near: &CelestialPosition,
+        scratch: &mut Scratch,
+    ) -> Option<RayImage<GlobalFrame>> {
+        let measured =
+            measured_to_global_with(frame.image, &self.camera, frame.car_in_ins_enu, scratch);
+        sky_directions_into(&self.camera, frame.car_in_ins_enu, &mut scratch.directions);
+        let source = estimate_source(&measured, &scratch.directions, near)?;
+        Some(global_to_scattering_plane_with(
+            &measured,
+            &self.camera,
+            frame.car_in_ins_enu,
+            &source,
+            scratch,
+        ))
+    }
+
+    /// Weighted RMSE of the pixels left after masking the glare, if there is a glare mask, how
+    /// many pixels were valid or left out for each reason, and the AoP histogram distance if it
+    /// is measured.
+    ///
+    /// The images are the measured and simulated ones before and after converting them to the
+    /// frame they are compared in.
+    fn score<F: Copy>(
+        &self,
+        (measured_raw, simulated_raw, measured, simulated): (
+            &RayImage<SensorFrame>,
+            &RayImage<GlobalFrame>,
+            &RayImage<F>,
+            &RayImage<F>,
+        ),
+        mask: Option<&[bool]>,
+        image_circle: Option<Circle>,
+        scratch: &mut Scratch,
+    ) -> (f64, ValidityCounts, Option<f64>) {
+        let validity = ValidityMask::new(
+            measured_raw,
+            simulated_raw,
+            measured,
+            simulated,
+            mask,
+            image_circle,
+        );
+        let aop_emd_deg = self
+            .aop_emd
+            .then(|| aop_emd_deg(simulated, measured))
+            .flatten();
+        let weighted_rmse = if self.profile.single_precision() {
+            validity.weighted_rmse_f32(scratch)
+        } else {
+            validity.weighted_rmse()
+        };
+        (weighted_rmse, validity.counts(), aop_emd_deg)
+    }
+
+    /// Scores every yaw offset in the window by circular cross-correlation over azimuth.
+    ///
+    /// The sky is simulated once at the attitude reference, and both images are binned by the
+    /// azimuth of each pixel at `resolution_deg`. The cost of an offset is one minus the
+    /// normalized correlation at its lag, so it ranges from 0 for a perfect match to 2.
+    /// Candidates are returned sorted by yaw offset. Their stage times are added to the frame's
+    /// `timing` instead. Glare is not masked out of the profiles.
+    pub fn correlate(
+        &self,
+        frame: &FrameInput,
+        window: &SearchWindow,
+        resolution_deg: f64,
+        timing: &mut TimingRecord,
+    ) -> Result<Vec<Candidate>, BenchError> {
+        self.scratch
+            .with(|scratch| self.correlate_with(frame, window, resolution_deg, timing, scratch))
+    }
+
+    #[allow(
+        clippy::cast_precision_loss,
+        clippy::cast_possible_truncation,
+        clippy::cast_sign_loss
+    )]
+    fn correlate_with(
+        &self,
+        frame: &FrameInput,
+        window: &SearchWindow,
+        resolution_deg: f64,
+        timing: &mut TimingRecord,
+        scratch: &mut Scratch,
+    ) -> Result<Vec<Candidate>, BenchError> {
+        let measured = timed(&mut timing.transform_ms, || {
+            measured_to_global_with(frame.image, &self.camera, frame.car_in_ins_enu, scratch)
+        });
+        let template = timed(&mut timing.simulate_ms, || {
+            self.sky.simulate_smeared_with(
+                &self.camera,
+                frame.position,
+                frame.car_in_ins_enu,
+                frame.time,
+                frame.yaw_smear,
+                scratch,
+            )
+        })?;
+
+        let bins = ((360. / resolution_deg).round() as usize).max(1);
+        Ok(timed(&mut timing.rmse_ms, || {
+            sky_directions_into(&self.camera, frame.car_in_ins_enu, &mut scratch.directions);
+            let directions = &scratch.directions;
+            let correlation = AzimuthProfile::new(&measured, directions, bins)
+                .cross_correlate(&AzimuthProfile::new(&template, directions, bins));
+            let validity = ValidityMask::new(
+                frame.image,
+                &template,
+                &measured,
+                &template,
+                None,
+                frame.image_circle,
+            )
+            .counts();
+            let valid_fraction = valid_fraction(&template, &measured);
+            let aop_emd_deg = self
+                .aop_emd
+                .then(|| aop_emd_deg(&template, &measured))
+                .flatten();
+
+            let mut candidates: Vec<_> = correlation
+                .iter()
+                .enumerate()
+                .filter_map(|(lag, correlation)| {
+                    let from_center_deg = wrap_deg(lag_offset_deg(lag, bins) - window.center_deg);
+                    (from_center_deg.abs() <= window.half_width_deg).then(|| Candidate {
+                        yaw_offset_deg: window.center_deg + from_center_deg,
+                        weighted_rmse: 1. - correlation,
+                        valid_fraction,
+                        validity,
+                        aop_emd_deg,
+                        timing: TimingRecord::candidate(frame.frame_index, lag),
+                    })
+                })
+                .collect();
+            candidates.sort_by(|a, b| a.yaw_offset_deg.total_cmp(&b.yaw_offset_deg));
+            candidates
+        }))
+    }
+}
+
+/// Picks the heading that best explains the measured sky.
+pub fn estimate_heading(candidates: &[Candidate]) -> Option<HeadingEstimate> {
+    let samples: Vec<_> = candidates.iter().map(Candidate::cost_sample).collect();
+    HeadingEstimate::from_sweep(&samples)
+}
@@ -0,0 +1,499 @@
+use crate::{camera::CameraModel, error::BenchError, io::StokesImage, sky::SkyDirection};
+use image::{ImageBuffer, Luma};
+use rumpus::image::RayImage;
+use std::{
+    collections::BTreeMap,
+    fs::{File, OpenOptions},
+    io::{BufReader, BufWriter, Read, Seek, SeekFrom, Write},
+    path::{Path, PathBuf},
+};
+use uom::si::{angle::degree, f64::Angle};
+use zip::{CompressionMethod, ZipArchive, ZipWriter, write::SimpleFileOptions};
+
+/// Scale from raw intensity units to 16-bit TIFF counts.
+///
+/// S1 and S2 are signed, so they are offset by half the range.
+const TIFF_STOKES_SCALE: f32 = 128.;
+const TIFF_STOKES_OFFSET: f32 = 32768.;
+
+/// Space reserved for the header of stacked arrays, so it can be rewritten once the frame count
+/// is known.
+const STACKED_HEADER_LEN: usize = 128;
+
+/// File format of exported Stokes images.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, clap::ValueEnum, serde::Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum StokesFormat {
+    /// 16-bit TIFF, with S0 scaled by 128 and S1 and S2 stored as 32768 + 128 S.
+    Tiff,
+    /// Little-endian f32 NumPy arrays in raw intensity units.
+    Npy,
+}
+
+/// Writes S0, S1 and S2 as separate images named `{prefix}_s0` and so on.
+pub fn write_stokes(
+    stokes: &StokesImage,
+    dir: &Path,
+    prefix: &str,
+    format: StokesFormat,
+) -> Result<(), BenchError> {
+    for (name, values, offset) in [
+        ("s0", &stokes.s0, 0.),
+        ("s1", &stokes.s1, TIFF_STOKES_OFFSET),
+        ("s2", &stokes.s2, TIFF_STOKES_OFFSET),
+    ] {
+        match format {
+            StokesFormat::Tiff => {
+                let path = dir.join(format!("{prefix}_{name}.tif"));
+                #[allow(clippy::cast_possible_truncation, clippy::cast_sign_loss)]
+                let counts: Vec<u16> = values
+                    .iter()
+                    .map(|value| {
+                        (offset + value * TIFF_STOKES_SCALE)
+                            .round()
+                            .clamp(0., 65535.) as u16
+                    })
+                    .collect();
+                #[allow(clippy::cast_possible_truncation)]
+                let image: ImageBuffer<Luma<u16>, _> =
+                    ImageBuffer::from_raw(stokes.cols as u32, stokes.rows as u32, counts)
+                        .expect("one value per super-pixel");
+                image
+                    .save(&path)
+                    .map_err(|e| BenchError::output(path.display(), e))?;
+            }
+            StokesFormat::Npy => {
+                let path = dir.join(format!("{prefix}_{name}.npy"));
+                NpyArray::from_f32(vec![stokes.rows, stokes.cols], values).write(&path)?;
+            }
+        }
+    }
+
+    Ok(())
+}
+
+/// File format of diagnostic images.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, clap::ValueEnum, serde::Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum ImageFormat {
+    Png,
+    Tiff,
+    /// Lossy, and without alpha, so RGBA images are still written as PNG.
+    Jpeg,
+}
+
+impl ImageFormat {
+    pub fn extension(self) -> &'static str {
+        match self {
+            Self::Png => "png",
+            Self::Tiff => "tif",
+            Self::Jpeg => "jpg",
+        }
+    }
+
+    /// Saves 8-bit RGB or RGBA bytes as `{stem}.{extension}` in a directory.
+    pub fn save(
+        self,
+        dir: &Path,
+        stem: &str,
+        bytes: &[u8],
+        (width, height): (u32, u32),
+        color: image::ExtendedColorType,
+    ) -> Result<(), BenchError> {
+        let format = match self {
+            Self::Jpeg if color == image::ExtendedColorType::Rgba8 => Self::Png,
+            format => format,
+        };
+        let path = dir.join(format!("{stem}.{}", format.extension()));
+        let image_format = match format {
+            Self::Png => image::ImageFormat::Png,
+            Self::Tiff => image::ImageFormat::Tiff,
+            Self::Jpeg => image::ImageFormat::Jpeg,
+        };
+        image::save_buffer_with_format(&path, bytes, width, height, color, image_format)
+            .map_err(|e| BenchError::output(path.display(), e))
+    }
+}
+
+/// A C-ordered array in the NumPy `.npy` format.
+#[derive(Debug, Clone)]
+pub struct NpyArray {
+    descr: &'static str,
+    shape: Vec<usize>,
+    data: Vec<u8>,
+}
+
+impl NpyArray {
+    pub fn from_f32(shape: Vec<usize>, values: &[f32]) -> Self {
+        Self {
+            descr: "<f4",
+            shape,
+            data: values
+                .iter()
+                .flat_map(|value| value.to_le_bytes())
+                .collect(),
+        }
+    }
+
+    pub fn from_bool(shape: Vec<usize>, values: &[bool]) -> Self {
+        Self {
+            descr: "|b1",
+            shape,
+            data: values.iter().map(|value| u8::from(*value)).collect(),
+        }
+    }
+
+    pub fn from_u64(shape: Vec<usize>, values: &[u64]) -> Self {
+        Self {
+            descr: "<u8",
+            shape,
+            data: values
+                .iter()
+                .flat_map(|value| value.to_le_bytes())
+                .collect(),
+        }
+    }
+
+    /// Encodes the array as version 1.0 `.npy` bytes.
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut bytes = npy_header(self.descr, &self.shape, 0);
+        bytes.extend_from_slice(&self.data);
+        bytes
+    }
+
+    pub fn write(&self, path: &Path) -> Result<(), BenchError> {
+        std::fs::write(path, self.to_bytes()).map_err(|e| BenchError::output(path.display(), e))
+    }
+
+    /// Decodes version 1.0 `.npy` bytes holding one of the types written here.
+    pub fn from_bytes(bytes: &[u8]) -> Result<Self, String> {
+        let [0x93, b'N', b'U', b'M', b'P', b'Y', 1, 0, low, high, ..] = *bytes else {
+            return Err("not a version 1.0 .npy array".to_string());
+        };
+        let data_start = 10 + usize::from(u16::from_le_bytes([low, high]));
+        let header = bytes
+            .get(10..data_start)
+            .and_then(|header| std::str::from_utf8(header).ok())
+            .ok_or("truncated header")?;
+        let descr = ["<f4", "|b1", "<u8"]
+            .into_iter()
+            .find(|descr| header.contains(&format!("'descr': '{descr}'")))
+            .ok_or_else(|| format!("unsupported array type in {}", header.trim_end()))?;
+        if header.contains("'fortran_order': True") {
+            return Err("Fortran ordered arrays are not supported".to_string());
+        }
+        let shape = header
+            .split_once("'shape': (")
+            .and_then(|(_, rest)| rest.split_once(')'))
+            .ok_or("header has no shape")?
+            .0
+            .split(',')
+            .map(str::trim)
+            .filter(|len| !len.is_empty())
+            .map(|len| len.parse().map_err(|_| format!("invalid shape {len:?}")))
+            .collect::<Result<Vec<usize>, _>>()?;
+
+        let data = bytes[data_start..].to_vec();
+        let expected = shape.iter().product::<usize>() * element_size(descr);
+        if data.len() != expected {
+            return Err(format!(
+                "expected {expected} bytes of data, found {}",
+                data.len()
+            ));
+        }
+        Ok(Self { descr, shape, data })
+    }
+
+    pub fn shape(&self) -> &[usize] {
+        &self.shape
+    }
+
+    /// Values in C order as `f32`, with booleans as 0 or 1.
+    pub fn to_f32(&self) -> Vec<f32> {
+        match self.descr {
+            "<f4" => self
+                .data
+                .chunks_exact(4)
+                .map(|bytes| f32::from_le_bytes(bytes.try_into().expect("4 byte chunks")))
+                .collect(),
+            #[allow(clippy::cast_precision_loss)]
+            "<u8" => self
+                .data
+                .chunks_exact(8)
+                .map(|bytes| u64::from_le_bytes(bytes.try_into().expect("8 byte chunks")) as f32)
+                .collect(),
+            _ => self.data.iter().map(|&byte| f32::from(byte)).collect(),
+        }
+    }
+}
+
+fn element_size(descr: &str) -> usize {
+    match descr {
+        "<f4" => 4,
+        "<u8" => 8,
+        _ => 1,
+    }
+}
+
+/// Builds a `.npy` header padded to a multiple of 64 bytes, or to at least `min_len` bytes.
+fn npy_header(descr: &str, shape: &[usize], min_len: usize) -> Vec<u8> {
+    let shape_fmt = match shape {
+        [len] => format!("({len},)"),
+        _ => format!(
+            "({})",
+            shape
+                .iter()
+                .map(usize::to_string)
+                .collect::<Vec<_>>()
+                .join(", ")
+        ),
+    };
+    let mut header =
+        format!("{{'descr': '{descr}', 'fortran_order': False, 'shape': {shape_fmt}, }}");
+    // The header ends with a newline and the data starts on a 64 byte boundary.
+    let unpadded = 10 + header.len() + 1;
+    let padded = unpadded.next_multiple_of(64).max(min_len);
+    header.push_str(&" ".repeat(padded - unpadded));
+    header.push('\n');
+
+    let mut bytes = Vec::with_capacity(padded);
+    bytes.extend_from_slice(b"\x93NUMPY\x01\x00");
+    #[allow(clippy::cast_possible_truncation)]
+    bytes.extend_from_slice(&(header.len() as u16).to_le_bytes());
+    bytes.extend_from_slice(header.as_bytes());
+    bytes
+}
+
+/// Writes named arrays into an uncompressed `.npz` archive, as `numpy.savez` does.
+pub fn write_npz(path: &Path, arrays: &[(&str, NpyArray)]) -> Result<(), BenchError> {
+    let output_error = |e: zip::result::ZipError| BenchError::output(path.display(), e);
+    let file = File::create(path).map_err(|e| BenchError::output(path.display(), e))?;
+    let mut zip = ZipWriter::new(BufWriter::new(file));
+    let options = SimpleFileOptions::default()
+        .compression_method(CompressionMethod::Stored)
+        .large_file(true);
+    for (name, array) in arrays {
+        zip.start_file(format!("{name}.npy"), options)
+            .map_err(output_error)?;
+        zip.write_all(&array.to_bytes())
+            .map_err(|e| BenchError::output(path.display(), e))?;
+    }
+    zip.finish().map_err(output_error)?;
+    Ok(())
+}
+
+/// Reads the arrays of an `.npz` archive, such as one written by [`write_npz`], by name.
+pub fn read_npz(path: &Path) -> Result<BTreeMap<String, NpyArray>, BenchError> {
+    let file = File::open(path).map_err(|e| BenchError::dataset(path, e))?;
+    let mut zip =
+        ZipArchive::new(BufReader::new(file)).map_err(|e| BenchError::dataset(path, e))?;
+    let mut arrays = BTreeMap::new();
+    for i in 0..zip.len() {
+        let mut entry = zip.by_index(i).map_err(|e| BenchError::dataset(path, e))?;
+        let Some(name) = entry.name().strip_suffix(".npy").map(str::to_string) else {
+            continue;
+        };
+        let mut bytes = Vec::with_capacity(usize::try_from(entry.size()).unwrap_or(0));
+        entry
+            .read_to_end(&mut bytes)
+            .map_err(|e| BenchError::dataset(path, e))?;
+        let array = NpyArray::from_bytes(&bytes)
+            .map_err(|message| BenchError::parse(path, i, format!("{name}: {message}")))?;
+        arrays.insert(name, array);
+    }
+    Ok(arrays)
+}
+
+/// AoP in degrees, DoP and validity of every pixel of a ray image, with invalid pixels as NaN.
+pub fn ray_image_arrays<F: Copy>(ray_image: &RayImage<F>) -> [NpyArray; 3] {
+    let shape = vec![ray_image.rows(), ray_image.cols()];
+    let mut aop_deg = Vec::with_capacity(ray_image.rows() * ray_image.cols());
+    let mut dop = Vec::with_capacity(aop_deg.capacity());
+    let mut valid = Vec::with_capacity(aop_deg.capacity());
+    for px in ray_image.pixels() {
+        let ray = px.ray();
+        #[allow(clippy::cast_possible_truncation)]
+        {
+            aop_deg.push(ray.map_or(f32::NAN, |ray| {
+                Angle::from(ray.aop()).get::<degree>() as f32
+            }));
+            dop.push(ray.map_or(f32::NAN, |ray| ray.dop() as f32));
+        }
+        valid.push(ray.is_some());
+    }
+
+    [
+        NpyArray::from_f32(shape.clone(), &aop_deg),
+        NpyArray::from_f32(shape.clone(), &dop),
+        NpyArray::from_bool(shape, &valid),
+    ]
+}
+
+/// Sky azimuth and elevation in degrees that every pixel looks at, with NaN below the horizon.
+///
+/// `directions` must be in row-major order, as returned by [`crate::sky::sky_directions`].
+pub fn sky_direction_arrays(
+    directions: &[Option<SkyDirection>],
+    rows: usize,
+    cols: usize,
+) -> [NpyArray; 2] {
+    #[allow(clippy::cast_possible_truncation)]
+    let (azimuth_deg, elevation_deg): (Vec<f32>, Vec<f32>) = directions
+        .iter()
+        .map(|direction| match direction {
+            Some(direction) => (
+                direction.azimuth.get::<degree>().rem_euclid(360.) as f32,
+                (90. - direction.zenith.get::<degree>()) as f32,
+            ),
+            None => (f32::NAN, f32::NAN),
+        })
+        .unzip();
+
+    [
+        NpyArray::from_f32(vec![rows, cols], &azimuth_deg),
+        NpyArray::from_f32(vec![rows, cols], &elevation_deg),
+    ]
+}
+
+/// Unit bearing of every pixel in the camera frame, which only depends on the camera model.
+pub fn bearing_arrays(camera: &CameraModel) -> [NpyArray; 3] {
+    let shape = vec![camera.rows(), camera.cols()];
+    #[allow(clippy::cast_possible_truncation)]
+    let components = [0, 1, 2].map(|axis| {
+        camera
+            .bearings()
+            .map(|bearing| bearing[axis] as f32)
+            .collect::<Vec<_>>()
+    });
+    components.map(|values| NpyArray::from_f32(shape.clone(), &values))
+}
+
+/// Stacks the arrays of every frame of a run along a new first axis into a single `.npz`.
+///
+/// Frames are streamed to temporary `.npy` files next to the archive, so a long run does not
+/// have to fit in memory.
+pub struct NpzRunWriter {
+    path: PathBuf,
+    frame_indices: Vec<u64>,
+    stacks: Vec<StackedArray>,
+}
+
+struct StackedArray {
+    name: String,
+    descr: &'static str,
+    frame_shape: Vec<usize>,
+    path: PathBuf,
+    file: BufWriter<File>,
+}
+
+impl NpzRunWriter {
+    pub fn new<P: AsRef<Path>>(path: P) -> Self {
+        Self {
+            path: path.as_ref().to_path_buf(),
+            frame_indices: Vec::new(),
+            stacks: Vec::new(),
+        }
+    }
+
+    /// Appends the arrays of a frame, which must have the same names and shapes every frame.
+    pub fn push(
+        &mut self,
+        frame_index: usize,
+        arrays: &[(&str, NpyArray)],
+    ) -> Result<(), BenchError> {
+        if self.stacks.is_empty() {
+            for (name, array) in arrays {
+                let path = self.path.with_extension(format!("{name}.npy.part"));
+                // Opened for reading too, to copy it into the archive when the run finishes.
+                let file = OpenOptions::new()
+                    .read(true)
+                    .write(true)
+                    .create(true)
+                    .truncate(true)
+                    .open(&path)
+                    .map_err(|e| BenchError::output(path.display(), e))?;
+                let mut file = BufWriter::new(file);
+                file.write_all(&[b' '; STACKED_HEADER_LEN])
+                    .map_err(|e| BenchError::output(path.display(), e))?;
+                self.stacks.push(StackedArray {
+                    name: (*name).to_string(),
+                    descr: array.descr,
+                    frame_shape: array.shape.clone(),
+                    path,
+                    file,
+                });
+            }
+        }
+
+        if arrays.len() != self.stacks.len()
+            || arrays
+                .iter()
+                .zip(&self.stacks)
+                .any(|((name, array), stack)| {
+                    *name != stack.name || array.shape != stack.frame_shape
+                })
+        {
+            return Err(BenchError::Config(format!(
+                "frame {frame_index} arrays do not match the earlier frames of {}",
+                self.path.display()
+            )));
+        }
+
+        for ((_, array), stack) in arrays.iter().zip(&mut self.stacks) {
+            stack
+                .file
+                .write_all(&array.data)
+                .map_err(|e| BenchError::output(stack.path.display(), e))?;
+        }
+        self.frame_indices.push(frame_index as u64);
+        Ok(())
+    }
+
+    /// Writes the archive with a `frame_index` array and removes the temporary files.
+    pub fn finish(self) -> Result<(), BenchError> {
+        let output_error = |e: zip::result::ZipError| BenchError::output(self.path.display(), e);
+        let file =
+            File::create(&self.path).map_err(|e| BenchError::output(self.path.display(), e))?;
+        let mut zip = ZipWriter::new(BufWriter::new(file));
+        let options = SimpleFileOptions::default()
+            .compression_method(CompressionMethod::Stored)
+            .large_file(true);
+
+        let frame_count = self.frame_indices.len();
+        zip.start_file("frame_index.npy", options)
+            .map_err(output_error)?;
+        zip.write_all(&NpyArray::from_u64(vec![frame_count], &self.frame_indices).to_bytes())
+            .map_err(|e| BenchError::output(self.path.display(), e))?;
+
+        for stack in self.stacks {
+            let part_error = |e| BenchError::output(stack.path.display(), e);
+            let mut file = stack
+                .file
+                .into_inner()
+                .map_err(|e| part_error(e.into_error()))?;
+
+            // Now that the frame count is known, fill in the space reserved for the header.
+            let shape: Vec<usize> = std::iter::once(frame_count)
+                .chain(stack.frame_shape.iter().copied())
+                .collect();
+            let header = npy_header(stack.descr, &shape, STACKED_HEADER_LEN);
+            assert_eq!(
+                header.len(),
+                STACKED_HEADER_LEN,
+                "stacked header overflowed"
+            );
+            file.seek(SeekFrom::Start(0)).map_err(part_error)?;
+            file.write_all(&header).map_err(part_error)?;
+            file.seek(SeekFrom::Start(0)).map_err(part_error)?;
+
+            zip.start_file(format!("{}.npy", stack.name), options)
+                .map_err(output_error)?;
+            std::io::copy(&mut file, &mut zip).map_err(part_error)?;
+            drop(file);
+            std::fs::remove_file(&stack.path).map_err(part_error)?;
+        }
+
+        zip.finish().map_err(output_error)?;
+        Ok(())
+    }
+}
@@ -0,0 +1,111 @@
+use image::GrayImage;
+
+/// Raw level at which a pixel has clipped.
+pub const SATURATION_LEVEL: u8 = u8::MAX;
+
+/// Fractions of the 2x2 super-pixels of a raw mosaic that are too bright or too dark to decode.
+#[derive(Debug, Default, Clone, Copy, serde::Serialize)]
+pub struct ExposureQuality {
+    /// Super-pixels with any pixel at the saturation level.
+    pub saturated_fraction: f64,
+    /// Super-pixels with a mean level below the dark level.
+    pub underexposed_fraction: f64,
+}
+
+impl ExposureQuality {
+    pub fn measure(raw: &GrayImage, dark_level: u8) -> Self {
+        Self::measure_where(raw, dark_level, |_, _| true)
+    }
+
+    /// Measures only the super-pixels `include` accepts, by super-pixel row and column.
+    #[allow(clippy::cast_precision_loss)]
+    pub fn measure_where(
+        raw: &GrayImage,
+        dark_level: u8,
+        include: impl Fn(usize, usize) -> bool,
+    ) -> Self {
+        let (rows, cols) = (raw.height() / 2, raw.width() / 2);
+        let (mut saturated, mut underexposed, mut measured) = (0usize, 0usize, 0usize);
+        for row in 0..rows {
+            for col in 0..cols {
+                if !include(row as usize, col as usize) {
+                    continue;
+                }
+                measured += 1;
+                let levels = [(0, 0), (0, 1), (1, 0), (1, 1)]
+                    .map(|(dr, dc)| raw.get_pixel(2 * col + dc, 2 * row + dr).0[0]);
+                if levels.contains(&SATURATION_LEVEL) {
+                    saturated += 1;
+                } else if levels.iter().map(|level| u32::from(*level)).sum::<u32>()
+                    < 4 * u32::from(dark_level)
+                {
+                    underexposed += 1;
+                }
+            }
+        }
+
+        let super_pixels = measured.max(1) as f64;
+        Self {
+            saturated_fraction: saturated as f64 / super_pixels,
+            underexposed_fraction: underexposed as f64 / super_pixels,
+        }
+    }
+
+    pub fn bad_fraction(&self) -> f64 {
+        self.saturated_fraction + self.underexposed_fraction
+    }
+}
+
+/// What to do with a frame that fails the exposure gate.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, clap::ValueEnum, serde::Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum ExposureAction {
+    /// Leave the frame out of the results.
+    #[default]
+    Skip,
+    /// Process the frame but mark it and leave it out of aggregate errors.
+    Flag,
+}
+
+/// Rejects frames where too many super-pixels are badly exposed.
+#[derive(Debug, Clone, Copy, serde::Serialize)]
+pub struct ExposureGate {
+    dark_level: u8,
+    max_bad_fraction: Option<f64>,
+    action: ExposureAction,
+}
+
+impl ExposureGate {
+    /// A gate that measures exposure with the given dark level but passes every frame.
+    pub fn new(dark_level: u8) -> Self {
+        Self {
+            dark_level,
+            max_bad_fraction: None,
+            action: ExposureAction::default(),
+        }
+    }
+
+    pub fn with_max_bad_fraction(mut self, max_bad_fraction: Option<f64>) -> Self {
+        self.max_bad_fraction = max_bad_fraction;
+        self
+    }
+
+    pub fn with_action(mut self, action: ExposureAction) -> Self {
+        self.action = action;
+        self
+    }
+
+    pub fn dark_level(&self) -> u8 {
+        self.dark_level
+    }
+
+    pub fn action(&self) -> ExposureAction {
+        self.action
+    }
+
+    /// Whether a frame has more badly exposed super-pixels than allowed.
+    pub fn fails(&self, quality: &ExposureQuality) -> bool {
+        self.max_bad_fraction
+            .is_some_and(|max_bad_fraction| quality.bad_fraction() > max_bad_fraction)
+    }
+}
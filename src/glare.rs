@@ -0,0 +1,89 @@
+use crate::{
+    camera::CameraModel, ephemeris::CelestialPosition, scratch::Scratch, sky::sky_directions_into,
+    systems::InsEnu,
+};
+use sguaba::engineering::Orientation;
+use uom::si::angle::degree;
+
+/// What to do with frames that have the sun disc in view, whose glare the sky models ignore.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, clap::ValueEnum, serde::Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum GlareStrategy {
+    /// Process them like any other frame.
+    #[default]
+    Keep,
+    /// Leave them out of the results.
+    Skip,
+    /// Leave the pixels within the glare radius of the sun out of the comparison.
+    Mask,
+    /// Count them less in the aggregate heading error.
+    DownWeight,
+}
+
+/// How frames with the sun in view are handled.
+#[derive(Debug, Clone, Copy, PartialEq, serde::Serialize)]
+pub struct GlareConfig {
+    pub strategy: GlareStrategy,
+    /// Angular radius around the sun left out by [`GlareStrategy::Mask`].
+    pub radius_deg: f64,
+    /// Weight of frames with the sun in view under [`GlareStrategy::DownWeight`].
+    pub weight: f64,
+}
+
+impl Default for GlareConfig {
+    fn default() -> Self {
+        Self {
+            strategy: GlareStrategy::Keep,
+            radius_deg: 15.,
+            weight: 0.25,
+        }
+    }
+}
+
+impl GlareConfig {
+    /// Row-major mask of the pixels within the glare radius of the sun, when masking.
+    pub fn mask(
+        &self,
+        camera: &CameraModel,
+        car_in_ins_enu: Orientation<InsEnu>,
+        sun: &CelestialPosition,
+    ) -> Option<Vec<bool>> {
+        self.mask_with(camera, car_in_ins_enu, sun, &mut Scratch::default())
+            .map(<[bool]>::to_vec)
+    }
+
+    /// Like [`GlareConfig::mask`], building the mask in a reused buffer.
+    pub fn mask_with<'a>(
+        &self,
+        camera: &CameraModel,
+        car_in_ins_enu: Orientation<InsEnu>,
+        sun: &CelestialPosition,
+        scratch: &'a mut Scratch,
+    ) -> Option<&'a [bool]> {
+        if self.strategy != GlareStrategy::Mask {
+            return None;
+        }
+        sky_directions_into(camera, car_in_ins_enu, &mut scratch.directions);
+        scratch.mask.clear();
+        scratch
+            .mask
+            .extend(scratch.directions.iter().map(|direction| {
+                direction.as_ref().is_some_and(|direction| {
+                    CelestialPosition::from(direction)
+                        .separation(sun)
+                        .get::<degree>()
+                        < self.radius_deg
+                })
+            }));
+        Some(&scratch.mask)
+    }
+
+    /// Weight of a frame in the aggregate heading error.
+    pub fn frame_weight(&self, sun_in_fov: bool) -> f64 {
+        if sun_in_fov && self.strategy == GlareStrategy::DownWeight {
+            self.weight
+        } else {
+            1.
+        }
+    }
+}
@@ -0,0 +1,195 @@
+/// Yaw offset and cost of a single candidate in a heading sweep.
+#[derive(Debug, Clone, Copy)]
+pub struct CostSample {
+    pub yaw_offset_deg: f64,
+    pub cost: f64,
+    /// Fraction of pixels that contributed to the cost.
+    pub valid_fraction: f64,
+}
+
+/// Heading that best explains the measured sky.
+#[derive(Debug, Clone, Copy)]
+pub struct HeadingEstimate {
+    /// Refined yaw offset from the INS heading.
+    pub yaw_offset_deg: f64,
+    /// Cost of the best candidate on the grid.
+    pub cost: f64,
+    /// Second derivative of the cost at the minimum, per square degree.
+    ///
+    /// `None` when the minimum is at the edge of the sweep.
+    pub sharpness: Option<f64>,
+    /// Cost of the best candidate over the lowest other local minimum.
+    ///
+    /// Close to one when another heading explains the sky almost as well. `None` when the
+    /// cost curve has a single basin.
+    pub basin_ratio: Option<f64>,
+    /// Fraction of pixels that contributed to the cost of the best candidate.
+    pub valid_fraction: f64,
+}
+
+impl HeadingEstimate {
+    /// Picks the minimum-cost candidate and refines it by fitting a parabola through its
+    /// neighbours.
+    ///
+    /// Samples must be sorted by yaw offset, but need not be evenly spaced.
+    pub fn from_sweep(samples: &[CostSample]) -> Option<Self> {
+        let (best, sample) = samples
+            .iter()
+            .enumerate()
+            .filter(|(_, sample)| sample.cost.is_finite())
+            .min_by(|(_, a), (_, b)| a.cost.total_cmp(&b.cost))?;
+
+        let mut estimate = Self {
+            yaw_offset_deg: sample.yaw_offset_deg,
+            cost: sample.cost,
+            sharpness: None,
+            basin_ratio: second_basin(samples, best).map(|cost| sample.cost / cost),
+            valid_fraction: sample.valid_fraction,
+        };
+
+        if best == 0 || best + 1 >= samples.len() {
+            return Some(estimate);
+        }
+
+        let (left, right) = (samples[best - 1], samples[best + 1]);
+        let left_step = sample.yaw_offset_deg - left.yaw_offset_deg;
+        let right_step = right.yaw_offset_deg - sample.yaw_offset_deg;
+        if left_step <= 0. || right_step <= 0. {
+            return Some(estimate);
+        }
+        // Slopes of the chords on either side, whose change over the span is the curvature.
+        let left_slope = (sample.cost - left.cost) / left_step;
+        let right_slope = (right.cost - sample.cost) / right_step;
+        let second_derivative = 2. * (right_slope - left_slope) / (left_step + right_step);
+        if second_derivative > 0. && second_derivative.is_finite() {
+            // The left chord's slope is that of the parabola halfway along it.
+            let left_mid = (left.yaw_offset_deg + sample.yaw_offset_deg) / 2.;
+            estimate.yaw_offset_deg = left_mid - left_slope / second_derivative;
+            estimate.sharpness = Some(second_derivative);
+        }
+
+        Some(estimate)
+    }
+
+    /// Combines the match quality metrics into a score from zero to one.
+    ///
+    /// Estimates at the edge of the sweep have no confidence, since the true minimum may be
+    /// outside of it.
+    pub fn confidence(&self) -> f64 {
+        if self.sharpness.is_none() {
+            return 0.;
+        }
+
+        let uniqueness = 1. - self.basin_ratio.unwrap_or(0.).clamp(0., 1.);
+        uniqueness * self.valid_fraction
+    }
+}
+
+/// Returns the cost of the lowest local minimum outside of the basin around `best`.
+fn second_basin(samples: &[CostSample], best: usize) -> Option<f64> {
+    // Walk uphill from the best candidate in both directions to find the edges of its basin.
+    let mut left = best;
+    while left > 0 && samples[left - 1].cost >= samples[left].cost {
+        left -= 1;
+    }
+    let mut right = best;
+    while right + 1 < samples.len() && samples[right + 1].cost >= samples[right].cost {
+        right += 1;
+    }
+
+    samples
+        .iter()
+        .enumerate()
+        .filter(|&(i, _)| i < left || i > right)
+        .filter(|&(i, sample)| {
+            let below_left = i == 0 || sample.cost <= samples[i - 1].cost;
+            let below_right = i + 1 == samples.len() || sample.cost <= samples[i + 1].cost;
+            below_left && below_right && sample.cost.is_finite()
+        })
+        .map(|(_, sample)| sample.cost)
+        .min_by(f64::total_cmp)
+}
+
+/// Range of yaw offsets from the INS heading to sweep.
+#[derive(Debug, Clone, Copy)]
+pub struct SearchWindow {
+    pub center_deg: f64,
+    pub half_width_deg: f64,
+}
+
+impl SearchWindow {
+    /// Yaw offsets spaced by `resolution_deg` across the window, symmetric about its center.
+    #[allow(
+        clippy::cast_precision_loss,
+        clippy::cast_possible_truncation,
+        clippy::cast_sign_loss
+    )]
+    pub fn offsets(&self, resolution_deg: f64) -> Vec<f64> {
+        let steps = (self.half_width_deg / resolution_deg) as usize;
+        (0..=2 * steps)
+            .map(|i| self.center_deg + resolution_deg * (i as f64 - steps as f64))
+            .collect()
+    }
+}
+
+/// Narrows the search around the previous estimate while the match stays confident.
+///
+/// The window grows with the change in INS yaw since the previous frame, and falls back to the
+/// wide search whenever a frame has no confident estimate.
+#[derive(Debug, Clone)]
+pub struct AdaptiveWindow {
+    wide_half_width_deg: f64,
+    min_half_width_deg: f64,
+    yaw_change_gain: f64,
+    min_confidence: f64,
+    previous: Option<(f64, HeadingEstimate)>,
+}
+
+impl AdaptiveWindow {
+    pub fn new(wide_half_width_deg: f64, min_half_width_deg: f64) -> Self {
+        Self {
+            wide_half_width_deg,
+            min_half_width_deg,
+            yaw_change_gain: 1.0,
+            min_confidence: 0.0,
+            previous: None,
+        }
+    }
+
+    /// Extra half width per degree of INS yaw change between frames.
+    pub fn with_yaw_change_gain(mut self, yaw_change_gain: f64) -> Self {
+        self.yaw_change_gain = yaw_change_gain;
+        self
+    }
+
+    /// Estimates with less confidence reset the search to the wide window.
+    pub fn with_min_confidence(mut self, min_confidence: f64) -> Self {
+        self.min_confidence = min_confidence;
+        self
+    }
+
+    pub fn window(&self, ins_yaw_deg: f64) -> SearchWindow {
+        let Some((previous_yaw_deg, previous)) = self.previous else {
+            return SearchWindow {
+                center_deg: 0.,
+                half_width_deg: self.wide_half_width_deg,
+            };
+        };
+
+        let yaw_change_deg = (ins_yaw_deg - previous_yaw_deg + 180.).rem_euclid(360.) - 180.;
+        let half_width_deg = (self.min_half_width_deg
+            + self.yaw_change_gain * yaw_change_deg.abs())
+        .min(self.wide_half_width_deg);
+
+        SearchWindow {
+            center_deg: previous.yaw_offset_deg,
+            half_width_deg,
+        }
+    }
+
+    pub fn update(&mut self, ins_yaw_deg: f64, estimate: Option<&HeadingEstimate>) {
+        self.previous = estimate
+            .filter(|estimate| estimate.confidence() > self.min_confidence)
+            .map(|estimate| (ins_yaw_deg, *estimate));
+    }
+}
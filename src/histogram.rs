@@ -0,0 +1,106 @@
+use rumpus::image::RayImage;
+use uom::si::{angle::degree, f64::Angle};
+
+/// Bins of the AoP histograms compared each frame.
+pub const AOP_BINS: usize = 36;
+
+/// Distribution of the angles of polarization of an image over half a turn, as fractions of
+/// its pixels.
+#[derive(Debug, Clone, PartialEq)]
+pub struct AopHistogram {
+    fractions: Vec<f64>,
+}
+
+impl AopHistogram {
+    /// Bins angles in degrees, which wrap around every 180.
+    #[allow(
+        clippy::cast_precision_loss,
+        clippy::cast_possible_truncation,
+        clippy::cast_sign_loss
+    )]
+    pub fn from_degrees(angles_deg: impl IntoIterator<Item = f64>, bins: usize) -> Option<Self> {
+        let width = 180.0 / bins as f64;
+        let mut counts = vec![0usize; bins];
+        for angle in angles_deg {
+            let bin = ((angle + 90.0).rem_euclid(180.0) / width) as usize;
+            counts[bin.min(bins - 1)] += 1;
+        }
+        let total: usize = counts.iter().sum();
+        (total > 0).then(|| Self {
+            fractions: counts
+                .into_iter()
+                .map(|count| count as f64 / total as f64)
+                .collect(),
+        })
+    }
+
+    /// Histograms of the simulated and measured AoP over the pixels valid in both, so pixels
+    /// only one image has do not count as a mismatch.
+    pub fn pair<F: Copy>(
+        simulated: &RayImage<F>,
+        measured: &RayImage<F>,
+        bins: usize,
+    ) -> Option<(Self, Self)> {
+        let (simulated_deg, measured_deg): (Vec<_>, Vec<_>) = measured
+            .pixels()
+            .filter_map(|rpx| {
+                let measured_ray = rpx.ray()?;
+                let simulated_ray = simulated.ray(rpx.row(), rpx.col())?;
+                Some((
+                    Angle::from(simulated_ray.aop()).get::<degree>(),
+                    Angle::from(measured_ray.aop()).get::<degree>(),
+                ))
+            })
+            .unzip();
+        Some((
+            Self::from_degrees(simulated_deg, bins)?,
+            Self::from_degrees(measured_deg, bins)?,
+        ))
+    }
+
+    pub fn fractions(&self) -> &[f64] {
+        &self.fractions
+    }
+
+    #[allow(clippy::cast_precision_loss)]
+    pub fn bin_width_deg(&self) -> f64 {
+        180.0 / self.fractions.len() as f64
+    }
+
+    /// Earth mover's distance to another histogram with as many bins, in degrees: the mean
+    /// angle the AoP of a pixel has to turn to make one histogram into the other.
+    ///
+    /// Angles wrap around, so mass can move either way around the circle; the cheapest flow
+    /// subtracts the median of the cumulative differences.
+    pub fn earth_movers_distance_deg(&self, other: &Self) -> f64 {
+        assert_eq!(self.fractions.len(), other.fractions.len());
+        let cumulative: Vec<f64> = self
+            .fractions
+            .iter()
+            .zip(&other.fractions)
+            .scan(0.0, |sum, (a, b)| {
+                *sum += a - b;
+                Some(*sum)
+            })
+            .collect();
+        let mut sorted = cumulative.clone();
+        sorted.sort_by(f64::total_cmp);
+        let median = sorted[sorted.len() / 2];
+        self.bin_width_deg()
+            * cumulative
+                .iter()
+                .map(|difference| (difference - median).abs())
+                .sum::<f64>()
+    }
+}
+
+/// Earth mover's distance in degrees between the AoP histograms of the simulated and measured
+/// images, or `None` if no pixel is valid in both.
+///
+/// A heading error mostly moves the AoP between pixels rather than changing its distribution,
+/// whereas a wrong sign or mirroring convention or a bad calibration reshapes it, so a large
+/// distance flags a frame to leave out before it skews the heading estimate.
+pub fn aop_emd_deg<F: Copy>(simulated: &RayImage<F>, measured: &RayImage<F>) -> Option<f64> {
+    let (simulated, measured) = AopHistogram::pair(simulated, measured, AOP_BINS)?;
+    Some(simulated.earth_movers_distance_deg(&measured))
+}
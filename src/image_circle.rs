@@ -0,0 +1,188 @@
+use crate::{error::BenchError, io::ImageOrientation};
+use image::GrayImage;
+use rumpus::{image::RayImage, ray::SensorFrame};
+use std::{fmt::Display, str::FromStr};
+
+/// Width in raw pixels of the rings the intensity is averaged over when finding the circle.
+const RING_WIDTH_PX: f64 = 8.;
+
+/// Fraction of the intensity at the center below which a ring is outside of the circle.
+const DARK_FRACTION: f64 = 0.2;
+
+/// Part of the sensor the lens images the sky onto.
+///
+/// With short lenses the image circle does not cover the corners of the sensor, which then
+/// hold no sky and would dilute the DoP statistics, so rays outside of it are dropped.
+#[derive(Debug, Default, Clone, Copy, PartialEq, serde::Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ImageCircle {
+    /// The whole sensor.
+    #[default]
+    Full,
+    /// Found in each image from where the intensity falls off around the sensor center.
+    Auto,
+    /// A circle of `radius_px` raw pixels, its center `cx`, `cy` raw pixels from the sensor
+    /// center along the columns and rows.
+    Fixed { radius_px: f64, cx: f64, cy: f64 },
+}
+
+impl ImageCircle {
+    /// The circle in an oriented raw image, or `None` if it covers the whole sensor.
+    pub fn locate(&self, raw: &GrayImage, orientation: ImageOrientation) -> Option<Circle> {
+        let (center_row, center_col) = (f64::from(raw.height()) / 2., f64::from(raw.width()) / 2.);
+        let circle = match *self {
+            Self::Full => return None,
+            Self::Auto => Circle {
+                center_row,
+                center_col,
+                radius_px: auto_radius_px(raw)?,
+            },
+            Self::Fixed { radius_px, cx, cy } => {
+                let (dx, dy) = orientation.offset(cx, cy);
+                Circle {
+                    center_row: center_row + dy,
+                    center_col: center_col + dx,
+                    radius_px,
+                }
+            }
+        };
+        (!circle.covers(raw)).then_some(circle)
+    }
+}
+
+impl FromStr for ImageCircle {
+    type Err = BenchError;
+
+    /// Parses `full`, `auto`, a radius such as `1100`, or a radius and center offset such as
+    /// `1100@12,-3`.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let invalid = || {
+            BenchError::Config(format!(
+                "image circle {s:?} is not full, auto or like 1100@12,-3"
+            ))
+        };
+        match s.trim() {
+            "full" => return Ok(Self::Full),
+            "auto" => return Ok(Self::Auto),
+            _ => {}
+        }
+        let (radius, center) = s.split_once('@').unwrap_or((s, "0,0"));
+        let (cx, cy) = center.split_once(',').ok_or_else(invalid)?;
+        let parse = |value: &str| value.trim().parse::<f64>().map_err(|_| invalid());
+        let radius_px = parse(radius)?;
+        if radius_px <= 0. {
+            return Err(invalid());
+        }
+        Ok(Self::Fixed {
+            radius_px,
+            cx: parse(cx)?,
+            cy: parse(cy)?,
+        })
+    }
+}
+
+impl Display for ImageCircle {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Full => write!(f, "full"),
+            Self::Auto => write!(f, "auto"),
+            Self::Fixed { radius_px, cx, cy } => write!(f, "{radius_px}@{cx},{cy}"),
+        }
+    }
+}
+
+/// Image circle located in an oriented raw image, in raw pixels.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Circle {
+    pub center_row: f64,
+    pub center_col: f64,
+    pub radius_px: f64,
+}
+
+impl Circle {
+    /// Whether the center of a raw pixel is inside the circle.
+    pub fn contains(&self, row: f64, col: f64) -> bool {
+        (row - self.center_row).hypot(col - self.center_col) <= self.radius_px
+    }
+
+    /// Whether every pixel of a raw image is inside the circle.
+    fn covers(&self, raw: &GrayImage) -> bool {
+        let (rows, cols) = (f64::from(raw.height()), f64::from(raw.width()));
+        [
+            (0.5, 0.5),
+            (0.5, cols - 0.5),
+            (rows - 0.5, 0.5),
+            (rows - 0.5, cols - 0.5),
+        ]
+        .into_iter()
+        .all(|(row, col)| self.contains(row, col))
+    }
+
+    /// Whether the super-pixel of `scale` by `scale` raw pixels behind a ray is inside.
+    #[allow(clippy::cast_precision_loss)]
+    pub fn contains_ray(&self, row: usize, col: usize, scale: usize) -> bool {
+        let scale = scale as f64;
+        self.contains((row as f64 + 0.5) * scale, (col as f64 + 0.5) * scale)
+    }
+
+    /// The same circle measured in the rays of an image decoded at `scale` raw pixels per ray.
+    #[allow(clippy::cast_precision_loss)]
+    pub fn in_rays(&self, scale: usize) -> Self {
+        let scale = scale as f64;
+        Self {
+            center_row: self.center_row / scale,
+            center_col: self.center_col / scale,
+            radius_px: self.radius_px / scale,
+        }
+    }
+
+    /// Drops the rays outside of the circle from an image decoded at `scale` raw pixels per ray.
+    pub fn mask(&self, ray_image: RayImage<SensorFrame>, scale: usize) -> RayImage<SensorFrame> {
+        let (rows, cols) = (ray_image.rows(), ray_image.cols());
+        let rays: Vec<_> = ray_image
+            .pixels()
+            .map(|px| {
+                px.ray()
+                    .filter(|_| self.contains_ray(px.row(), px.col(), scale))
+            })
+            .collect();
+        RayImage::from_rays(rays, rows, cols).unwrap()
+    }
+}
+
+/// Radius of the image circle, from the mean intensity of rings around the sensor center.
+///
+/// The circle ends at the first ring darker than [`DARK_FRACTION`] of the center, so the
+/// result is `None` if the corners are still lit or the image is dark throughout.
+#[allow(
+    clippy::cast_precision_loss,
+    clippy::cast_possible_truncation,
+    clippy::cast_sign_loss
+)]
+pub fn auto_radius_px(raw: &GrayImage) -> Option<f64> {
+    let (center_row, center_col) = (f64::from(raw.height()) / 2., f64::from(raw.width()) / 2.);
+    let rings = (center_row.hypot(center_col) / RING_WIDTH_PX).ceil() as usize + 1;
+    let mut sums = vec![0u64; rings];
+    let mut counts = vec![0u64; rings];
+    for (col, row, level) in raw.enumerate_pixels() {
+        let radius = (f64::from(row) + 0.5 - center_row).hypot(f64::from(col) + 0.5 - center_col);
+        let ring = (radius / RING_WIDTH_PX) as usize;
+        sums[ring] += u64::from(level.0[0]);
+        counts[ring] += 1;
+    }
+    let mean = |ring: usize| (counts[ring] > 0).then(|| sums[ring] as f64 / counts[ring] as f64);
+
+    // The center quarter of the shorter side is taken to be well inside the circle.
+    let center_rings = ((center_row.min(center_col) / 2. / RING_WIDTH_PX) as usize).max(1);
+    let (center_sum, center_count) = (0..center_rings).fold((0, 0), |(sum, count), ring| {
+        (sum + sums[ring], count + counts[ring])
+    });
+    if center_count == 0 || center_sum == 0 {
+        return None;
+    }
+    let threshold = DARK_FRACTION * center_sum as f64 / center_count as f64;
+
+    (center_rings..rings)
+        .find(|&ring| mean(ring).is_some_and(|mean| mean < threshold))
+        .map(|ring| ring as f64 * RING_WIDTH_PX)
+}
@@ -0,0 +1,115 @@
+use crate::{error::BenchError, export::ImageFormat, overlay::Overlay};
+use std::{
+    path::PathBuf,
+    sync::{
+        Arc, Mutex,
+        mpsc::{Receiver, SyncSender, sync_channel},
+    },
+    thread::{self, JoinHandle},
+};
+
+/// Images that may wait for a writer, per writer thread.
+const QUEUE_PER_WRITER: usize = 4;
+
+type Job = Box<dyn FnOnce() -> Result<(), BenchError> + Send>;
+
+/// Encodes and writes diagnostic images on a pool of background threads, so frames are not
+/// held up by PNG compression and disk writes.
+///
+/// The queue is bounded, so a run that draws images faster than they can be written waits for
+/// room rather than piling them up in memory. Failed writes are reported as they happen.
+pub struct ImageWriter {
+    /// Scale overlays are resized by before they are saved.
+    scale: f64,
+    sender: Option<SyncSender<(String, Job)>>,
+    writers: Vec<JoinHandle<()>>,
+}
+
+impl ImageWriter {
+    /// Starts `writers` threads, at least one.
+    pub fn new(writers: usize) -> Self {
+        let writers = writers.max(1);
+        let (sender, receiver) = sync_channel(writers * QUEUE_PER_WRITER);
+        let receiver = Arc::new(Mutex::new(receiver));
+        Self {
+            scale: 1.,
+            sender: Some(sender),
+            writers: (0..writers)
+                .map(|_| {
+                    let receiver = Arc::clone(&receiver);
+                    thread::spawn(move || write_queued(&receiver))
+                })
+                .collect(),
+        }
+    }
+
+    /// Resizes the saved overlays by `scale` on both sides, on the writer threads.
+    ///
+    /// `scale` must be finite and positive.
+    pub fn with_scale(mut self, scale: f64) -> Self {
+        self.scale = scale;
+        self
+    }
+
+    /// Queues an image to be written, waiting only if the queue is full.
+    pub fn write(
+        &self,
+        name: impl Into<String>,
+        job: impl FnOnce() -> Result<(), BenchError> + Send + 'static,
+    ) {
+        let sender = self
+            .sender
+            .as_ref()
+            .expect("writer is running until dropped");
+        // Writers only stop once the sender is dropped, unless one of them panicked.
+        if sender.send((name.into(), Box::new(job))).is_err() {
+            eprintln!("WARNING: image writers stopped, so images are no longer written");
+        }
+    }
+
+    /// Queues an overlay to be saved as `{stem}` in `dir`, like [`Overlay::save`], at the
+    /// writer's scale.
+    pub fn save(
+        &self,
+        overlay: Overlay,
+        dir: PathBuf,
+        stem: String,
+        format: ImageFormat,
+        alpha: bool,
+    ) {
+        let scale = self.scale;
+        self.write(stem.clone(), move || {
+            overlay.scaled(scale).save(&dir, &stem, format, alpha)
+        });
+    }
+
+    /// Waits until every queued image is written.
+    pub fn finish(mut self) {
+        self.join();
+    }
+
+    fn join(&mut self) {
+        self.sender = None;
+        for writer in self.writers.drain(..) {
+            let _ = writer.join();
+        }
+    }
+}
+
+impl Drop for ImageWriter {
+    fn drop(&mut self) {
+        self.join();
+    }
+}
+
+fn write_queued(receiver: &Mutex<Receiver<(String, Job)>>) {
+    loop {
+        // The lock is only held while waiting for a job, not while writing it.
+        let Ok((name, job)) = receiver.lock().unwrap().recv() else {
+            break;
+        };
+        if let Err(e) = job() {
+            eprintln!("failed to write {name}: {e}");
+        }
+    }
+}
@@ -0,0 +1,246 @@
+use crate::{
+    camera::CameraModel,
+    ephemeris::CelestialPosition,
+    error::BenchError,
+    scratch::Scratch,
+    sky::{LightSource, Sky, SkyAxes, SkyDirection, sky_directions},
+    systems::InsEnu,
+};
+use chrono::{DateTime, Utc};
+use rayon::prelude::*;
+use rumpus::{
+    image::RayImage,
+    ray::{Aop, GlobalFrame, Ray},
+};
+use sguaba::{engineering::Orientation, systems::Wgs84};
+use uom::si::{angle::degree, f64::Angle};
+
+/// Every this many reused pixels one is also simulated exactly to measure the error.
+const CHECK_STRIDE: usize = 64;
+
+/// Simulates consecutive frames by warping the previous frame's sky onto the new attitude.
+///
+/// Each pixel is traced back to the pixel that looked in the same direction last frame. Its
+/// polarization is reused when it was simulated within `tolerance_deg` of the direction and the
+/// light source moved less than that since the last full frame, and simulated otherwise. A
+/// sample of the reused pixels is checked against the model, and the frame is simulated in full
+/// if any is off by more than `max_error_deg`, so the error of a frame never exceeds it by more
+/// than the sampling misses.
+///
+/// Skies that rumpus simulates cannot be evaluated pixel by pixel, so they are always simulated
+/// in full and reported as [`IncrementalStats::bypassed`]. That includes the default sunlit
+/// Rayleigh sky, unless it is sampled into a lookup table.
+#[derive(Debug)]
+pub struct IncrementalSky {
+    tolerance_deg: f64,
+    max_error_deg: f64,
+    previous: Option<PreviousFrame>,
+}
+
+#[derive(Debug)]
+struct PreviousFrame {
+    camera: CameraModel,
+    car_in_ins_enu: Orientation<InsEnu>,
+    light_source: LightSource,
+    /// Light source position of the last frame simulated in full.
+    source: CelestialPosition,
+    /// Direction each pixel's polarization was simulated for, which for reused pixels is
+    /// where an earlier frame looked, so the errors do not accumulate over frames.
+    directions: Vec<Option<SkyDirection>>,
+    /// Modelled polarization before the DoP is scaled.
+    rays: Vec<Option<Ray<GlobalFrame>>>,
+}
+
+/// How much of a frame was reused, and how far the reused pixels were off.
+#[derive(Debug, Default, Clone, Copy, serde::Serialize)]
+pub struct IncrementalStats {
+    pub reused: usize,
+    pub simulated: usize,
+    /// Reused pixels also simulated exactly.
+    pub checked: usize,
+    /// Largest AoP error of the checked pixels, which is zero for frames simulated in full.
+    pub max_error_deg: f64,
+    /// Whether the frame was simulated in full, as the first one or after a failed check.
+    pub full: bool,
+    /// Whether rumpus simulated the frame in full because it cannot be warped, so incremental
+    /// mode saved nothing.
+    pub bypassed: bool,
+}
+
+impl IncrementalStats {
+    /// Fraction of the sky pixels taken from the previous frame.
+    #[allow(clippy::cast_precision_loss)]
+    pub fn reused_fraction(&self) -> f64 {
+        self.reused as f64 / (self.reused + self.simulated).max(1) as f64
+    }
+}
+
+/// Where a pixel's polarization came from.
+#[derive(Clone, Copy)]
+enum Source {
+    Reused { error_deg: Option<f64> },
+    Simulated,
+}
+
+impl IncrementalSky {
+    pub fn new(tolerance_deg: f64, max_error_deg: f64) -> Self {
+        Self {
+            tolerance_deg,
+            max_error_deg,
+            previous: None,
+        }
+    }
+
+    /// Simulates a frame, reusing what it can of the previous one.
+    ///
+    /// Skies that rumpus simulates come back in full with their stats marked as bypassed.
+    pub fn simulate(
+        &mut self,
+        sky: &Sky,
+        camera: &CameraModel,
+        position: &Wgs84,
+        car_in_ins_enu: Orientation<InsEnu>,
+        time: DateTime<Utc>,
+    ) -> Result<(RayImage<GlobalFrame>, IncrementalStats), BenchError> {
+        let geometry = sky.source_geometry(position, time);
+        let (light_source, source) = (geometry.light_source, geometry.source);
+        // rumpus only simulates whole frames, so its skies are never warped.
+        if sky.simulates_with_rumpus(light_source) {
+            self.previous = None;
+            let simulated = sky.simulate_with_geometry(
+                camera,
+                &geometry,
+                car_in_ins_enu,
+                &mut Scratch::default(),
+            )?;
+            let stats = IncrementalStats {
+                simulated: simulated.pixels().filter(|px| px.ray().is_some()).count(),
+                full: true,
+                bypassed: true,
+                ..IncrementalStats::default()
+            };
+            return Ok((simulated, stats));
+        }
+        let model = sky.pixel_model(light_source, &source)?;
+        let directions = sky_directions(camera, car_in_ins_enu);
+        let evaluate = |view: &SkyDirection| {
+            let (aop, dop) = model(view)?;
+            Some(Ray::new(Aop::from_angle_wrapped(aop), dop))
+        };
+
+        let previous = self.previous.as_ref().filter(|previous| {
+            previous.camera == *camera
+                && previous.light_source == light_source
+                && previous.source.separation(&source).get::<degree>() <= self.tolerance_deg
+        });
+        let mut stats = IncrementalStats::default();
+        let (mut rays, mut simulated_for) = (Vec::new(), Vec::new());
+        if let Some(previous) = previous {
+            let axes = SkyAxes::new(previous.car_in_ins_enu);
+            let warp = |(i, direction): (usize, &Option<SkyDirection>)| {
+                let Some(direction) = direction else {
+                    return (None, None, None);
+                };
+                match previous.reuse(&axes, direction, self.tolerance_deg) {
+                    Some(j) => {
+                        let ray = previous.rays[j];
+                        let error_deg = (i % CHECK_STRIDE == 0)
+                            .then(|| aop_error_deg(ray, evaluate(direction)));
+                        (
+                            ray,
+                            previous.directions[j],
+                            Some(Source::Reused { error_deg }),
+                        )
+                    }
+                    None => (
+                        evaluate(direction),
+                        Some(*direction),
+                        Some(Source::Simulated),
+                    ),
+                }
+            };
+            let warped: Vec<_> = if sky.parallel() {
+                directions.par_iter().enumerate().map(warp).collect()
+            } else {
+                directions.iter().enumerate().map(warp).collect()
+            };
+            for (ray, direction, source) in warped {
+                match source {
+                    Some(Source::Reused { error_deg }) => {
+                        stats.reused += 1;
+                        if let Some(error_deg) = error_deg {
+                            stats.checked += 1;
+                            stats.max_error_deg = stats.max_error_deg.max(error_deg);
+                        }
+                    }
+                    Some(Source::Simulated) => stats.simulated += 1,
+                    None => {}
+                }
+                rays.push(ray);
+                simulated_for.push(direction);
+            }
+        }
+        let full = previous.is_none() || stats.max_error_deg > self.max_error_deg;
+        let source = match previous {
+            Some(previous) if !full => previous.source,
+            _ => source,
+        };
+        if full {
+            let simulate = |direction: &Option<SkyDirection>| direction.as_ref().and_then(evaluate);
+            rays = if sky.parallel() {
+                directions.par_iter().map(simulate).collect()
+            } else {
+                directions.iter().map(simulate).collect()
+            };
+            simulated_for = directions;
+            stats = IncrementalStats {
+                simulated: simulated_for.iter().flatten().count(),
+                full: true,
+                ..IncrementalStats::default()
+            };
+        }
+
+        let simulated = RayImage::from_rays(rays.iter().copied(), camera.rows(), camera.cols())
+            .map_err(|e| BenchError::Simulation(e.to_string()))?;
+        self.previous = Some(PreviousFrame {
+            camera: *camera,
+            car_in_ins_enu,
+            light_source,
+            source,
+            directions: simulated_for,
+            rays,
+        });
+        let simulated = sky.finish(
+            &simulated,
+            &geometry,
+            camera,
+            car_in_ins_enu,
+            &mut Scratch::default(),
+        );
+        Ok((simulated, stats))
+    }
+}
+
+impl PreviousFrame {
+    /// Index of the pixel that looked towards `direction` last frame, if its polarization was
+    /// simulated for a direction close enough.
+    fn reuse(&self, axes: &SkyAxes, direction: &SkyDirection, tolerance_deg: f64) -> Option<usize> {
+        let position = CelestialPosition::from(direction);
+        let (row, col) = self.camera.pixel(axes.bearing(&position))?;
+        let i = row * self.camera.cols() + col;
+        let simulated_for = CelestialPosition::from(self.directions[i].as_ref()?);
+        (simulated_for.separation(&position).get::<degree>() <= tolerance_deg).then_some(i)
+    }
+}
+
+/// Magnitude of the AoP difference between a reused ray and the exact one, in degrees.
+fn aop_error_deg(reused: Option<Ray<GlobalFrame>>, exact: Option<Ray<GlobalFrame>>) -> f64 {
+    match (reused, exact) {
+        (Some(reused), Some(exact)) => Angle::from(reused.aop() - exact.aop())
+            .get::<degree>()
+            .abs(),
+        (None, None) => 0.,
+        // A pixel that is valid in only one of them is as wrong as it gets.
+        _ => 90.,
+    }
+}
@@ -0,0 +1,292 @@
+use crate::{archive, batch::run_parallel, error::BenchError, pipeline::Dataset, remote};
+use chrono::Local;
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::{
+    collections::BTreeMap,
+    fs::Metadata,
+    path::{Path, PathBuf},
+    time::UNIX_EPOCH,
+};
+
+/// Files in a dataset that runs write, and so are expected to change.
+const UNCHECKED: [&str; 2] = [DatasetManifest::FILE_NAME, "leaderboard.json"];
+
+/// Size, modification time and SHA-256 of a dataset file.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct FileChecksum {
+    pub bytes: u64,
+    /// Nanoseconds since the Unix epoch, if the filesystem keeps them.
+    #[serde(default)]
+    pub modified_ns: Option<u64>,
+    pub sha256: String,
+}
+
+impl FileChecksum {
+    pub fn of(path: &Path) -> Result<Self, BenchError> {
+        let mut file = std::fs::File::open(path).map_err(|e| BenchError::dataset(path, e))?;
+        let metadata = file.metadata().map_err(|e| BenchError::dataset(path, e))?;
+        let mut hasher = Sha256::new();
+        let bytes =
+            std::io::copy(&mut file, &mut hasher).map_err(|e| BenchError::dataset(path, e))?;
+        let sha256 = hasher
+            .finalize()
+            .iter()
+            .map(|byte| format!("{byte:02x}"))
+            .collect();
+        Ok(Self {
+            bytes,
+            modified_ns: modified_ns(&metadata),
+            sha256,
+        })
+    }
+}
+
+/// Checksums and frame counts of a dataset, kept in `checksums.json` in the dataset, to tell
+/// when a copy of it is truncated or modified.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct DatasetManifest {
+    pub created: String,
+    /// Frames with an image.
+    pub images: usize,
+    /// Records of each CSV log, by path relative to the dataset.
+    pub records: BTreeMap<PathBuf, usize>,
+    /// Every file, by path relative to the dataset.
+    pub files: BTreeMap<PathBuf, FileChecksum>,
+}
+
+impl DatasetManifest {
+    pub const FILE_NAME: &str = "checksums.json";
+
+    pub fn path(dataset: &Dataset) -> PathBuf {
+        dataset.path().join(Self::FILE_NAME)
+    }
+
+    /// Checksums every file of a dataset, `jobs` at a time.
+    pub fn build(dataset: &Dataset, jobs: usize) -> Result<Self, BenchError> {
+        let paths = dataset_files(dataset)?;
+        let checksums = run_parallel(&paths, jobs, |_, path| {
+            FileChecksum::of(&dataset.path().join(path))
+        });
+        let files = paths
+            .iter()
+            .cloned()
+            .zip(checksums)
+            .map(|(path, checksum)| Ok((path, checksum?)))
+            .collect::<Result<BTreeMap<_, _>, BenchError>>()?;
+
+        let mut records = BTreeMap::new();
+        for path in files.keys() {
+            if path.extension().is_some_and(|extension| extension == "csv") {
+                records.insert(path.clone(), count_records(&dataset.path().join(path))?);
+            }
+        }
+        Ok(Self {
+            created: Local::now().to_rfc3339(),
+            images: count_images(dataset),
+            records,
+            files,
+        })
+    }
+
+    pub fn read(dataset: &Dataset) -> Result<Self, BenchError> {
+        let path = Self::path(dataset);
+        let file = std::fs::File::open(&path).map_err(|e| BenchError::dataset(&path, e))?;
+        serde_json::from_reader(file).map_err(|e| BenchError::dataset(&path, e))
+    }
+
+    pub fn write(&self, dataset: &Dataset) -> Result<(), BenchError> {
+        let path = Self::path(dataset);
+        let file =
+            std::fs::File::create(&path).map_err(|e| BenchError::output(path.display(), e))?;
+        serde_json::to_writer_pretty(file, self).map_err(|e| BenchError::output(path.display(), e))
+    }
+
+    /// Every way the dataset differs from the manifest, checking `jobs` files at a time.
+    ///
+    /// Files are compared by size and modification time, which only reads their metadata, and
+    /// also by SHA-256 if `hash` is set. Hashed files are not compared by modification time,
+    /// which copies need not keep.
+    pub fn problems(&self, dataset: &Dataset, jobs: usize, hash: bool) -> Vec<String> {
+        let files: Vec<_> = self.files.iter().collect();
+        let mut problems: Vec<String> = run_parallel(&files, jobs, |_, (path, expected)| {
+            let full_path = dataset.path().join(path);
+            // Sizes are compared first, which finds truncated copies without reading them.
+            let Ok(metadata) = std::fs::metadata(&full_path) else {
+                return Some(format!("{} is missing", path.display()));
+            };
+            if metadata.len() != expected.bytes {
+                return Some(format!(
+                    "{} is {} bytes but was {}",
+                    path.display(),
+                    metadata.len(),
+                    expected.bytes
+                ));
+            }
+            if !hash {
+                let modified = expected
+                    .modified_ns
+                    .zip(modified_ns(&metadata))
+                    .is_some_and(|(expected, modified)| expected != modified);
+                return modified.then(|| {
+                    format!(
+                        "{} changed after the manifest was written, compare its contents with \
+                         --verify-hashes",
+                        path.display()
+                    )
+                });
+            }
+            match FileChecksum::of(&full_path) {
+                Ok(checksum) if checksum.sha256 == expected.sha256 => None,
+                Ok(_) => Some(format!("{} has been modified", path.display())),
+                Err(e) => Some(e.to_string()),
+            }
+        })
+        .into_iter()
+        .flatten()
+        .collect();
+
+        // Logs are small, so their records are counted on every check.
+        for (path, &expected) in &self.records {
+            let full_path = dataset.path().join(path);
+            if !full_path.exists() {
+                continue;
+            }
+            match count_records(&full_path) {
+                Ok(records) if records == expected => {}
+                Ok(records) => problems.push(format!(
+                    "{} has {records} records but had {expected}",
+                    path.display()
+                )),
+                Err(e) => problems.push(e.to_string()),
+            }
+        }
+
+        match dataset_files(dataset) {
+            Ok(paths) => problems.extend(
+                paths
+                    .iter()
+                    .filter(|path| !self.files.contains_key(*path))
+                    .map(|path| format!("{} is not in the manifest", path.display())),
+            ),
+            Err(e) => problems.push(e.to_string()),
+        }
+
+        let images = count_images(dataset);
+        if images != self.images {
+            problems.push(format!("{images} images but there were {}", self.images));
+        }
+        problems
+    }
+
+    /// Takes the modification time of every file from the dataset, such as after a copy was
+    /// hashed and found to match. Returns how many files had another time.
+    pub fn refresh_modified(&mut self, dataset: &Dataset) -> usize {
+        let mut refreshed = 0;
+        for (path, checksum) in &mut self.files {
+            let modified = std::fs::metadata(dataset.path().join(path))
+                .ok()
+                .and_then(|metadata| modified_ns(&metadata));
+            if modified.is_some() && modified != checksum.modified_ns {
+                checksum.modified_ns = modified;
+                refreshed += 1;
+            }
+        }
+        refreshed
+    }
+}
+
+/// Checks a dataset against its manifest before a run, if it has one, hashing every file only
+/// if `hash` is set.
+///
+/// Once hashing finds a copy unchanged, the manifest takes the modification times of the copy,
+/// so later checks without hashing pass.
+///
+/// Archives are left to the checksums zstd keeps of each frame as they are decompressed, and
+/// remote datasets are not checked, as that would download every file up front.
+pub fn verify_dataset(dataset: &Dataset, hash: bool) -> Result<(), BenchError> {
+    if archive::is_archive(dataset.path())
+        || remote::is_remote(dataset.path())
+        || !DatasetManifest::path(dataset).exists()
+    {
+        return Ok(());
+    }
+    let jobs = std::thread::available_parallelism().map_or(1, usize::from);
+    let mut manifest = DatasetManifest::read(dataset)?;
+    let problems = manifest.problems(dataset, jobs, hash);
+    if problems.is_empty() {
+        if hash
+            && manifest.refresh_modified(dataset) > 0
+            && let Err(e) = manifest.write(dataset)
+        {
+            eprintln!("WARNING: cannot update the modification times in the manifest: {e}");
+        }
+        return Ok(());
+    }
+    Err(BenchError::Config(format!(
+        "{} does not match its {}:\n  {}\nrecopy it, or pass --no-verify to run on it anyway",
+        dataset.path().display(),
+        DatasetManifest::FILE_NAME,
+        problems.join("\n  ")
+    )))
+}
+
+/// Paths of every checked file, relative to the dataset.
+pub(crate) fn dataset_files(dataset: &Dataset) -> Result<Vec<PathBuf>, BenchError> {
+    let mut files = Vec::new();
+    let mut dirs = vec![PathBuf::new()];
+    while let Some(dir) = dirs.pop() {
+        let full_dir = dataset.path().join(&dir);
+        let entries =
+            std::fs::read_dir(&full_dir).map_err(|e| BenchError::dataset(&full_dir, e))?;
+        for entry in entries {
+            let entry = entry.map_err(|e| BenchError::dataset(&full_dir, e))?;
+            let path = dir.join(entry.file_name());
+            let file_type = entry
+                .file_type()
+                .map_err(|e| BenchError::dataset(entry.path(), e))?;
+            if file_type.is_dir() {
+                dirs.push(path);
+            } else if !UNCHECKED
+                .iter()
+                .any(|unchecked| path == Path::new(unchecked))
+            {
+                files.push(path);
+            }
+        }
+    }
+    files.sort();
+    Ok(files)
+}
+
+pub(crate) fn modified_ns(metadata: &Metadata) -> Option<u64> {
+    let modified = metadata.modified().ok()?.duration_since(UNIX_EPOCH).ok()?;
+    modified.as_nanos().try_into().ok()
+}
+
+fn count_images(dataset: &Dataset) -> usize {
+    std::fs::read_dir(dataset.image_dir()).map_or(0, |entries| {
+        entries
+            .filter_map(Result::ok)
+            .filter(|entry| {
+                entry
+                    .path()
+                    .extension()
+                    .is_some_and(|extension| extension == "png")
+            })
+            .count()
+    })
+}
+
+fn count_records(path: &Path) -> Result<usize, BenchError> {
+    let mut reader = csv::ReaderBuilder::new()
+        .flexible(true)
+        .from_path(path)
+        .map_err(|e| BenchError::dataset(path, e))?;
+    let mut records = 0;
+    for record in reader.records() {
+        record.map_err(|e| BenchError::parse(path, records + 1, e))?;
+        records += 1;
+    }
+    Ok(records)
+}
@@ -1,44 +1,141 @@
-use crate::systems::InsEnu;
-use chrono::{DateTime, TimeZone, Utc};
+use crate::{
+    archive,
+    error::BenchError,
+    exposure::ExposureQuality,
+    image_circle::{Circle, ImageCircle},
+    prefetch::ImagePrefetch,
+    read_ahead::ReadAhead,
+    systems::{InsConvention, InsEnu},
+};
+use chrono::{DateTime, Duration, TimeZone, Utc};
+use csv::StringRecord;
+use image::GrayImage;
 use rumpus::{
     image::{IntensityImage, RayImage},
-    ray::SensorFrame,
+    ray::{Aop, Ray, SensorFrame},
 };
 use sguaba::{engineering::Orientation, systems::Wgs84};
-use std::{error::Error, path::Path};
+use std::{
+    borrow::Cow,
+    fmt::Display,
+    io::{BufRead, Cursor, Seek},
+    path::{Path, PathBuf},
+    str::FromStr,
+};
+use uom::si::{angle::radian, f64::Angle};
+
+/// Column layout of the NovAtel logs exported from the ROS bag.
+/// The stamp and GPS reference time come from the message headers shared by every log.
+const STAMP_SEC_COL: usize = 3;
+const STAMP_NANOSEC_COL: usize = 4;
+const GPS_WEEK_COL: usize = 11;
+const GPS_WEEK_MSEC_COL: usize = 12;
+const CLOCK_OFFSET_COL: usize = 14;
+const UTC_OFFSET_COL: usize = 16;
+
+/// GPS to UTC offset since 2017, used when the receiver does not report one.
+const DEFAULT_LEAP_SECONDS: i64 = 18;
+
+/// Number of weeks before a 10-bit GPS week number wraps around.
+const GPS_WEEK_ROLLOVER: i64 = 1024;
+
+/// Converts the GPS reference time of the NovAtel TIME log into UTC.
+pub struct TimeReader {
+    leap_seconds: Option<i64>,
+    apply_clock_offset: bool,
+    max_stamp_offset: Option<Duration>,
+}
 
-pub struct TimeReader;
+#[derive(Clone)]
 pub struct TimeFrame {
     pub time: DateTime<Utc>,
 }
 
 impl TimeReader {
     pub fn new() -> Self {
-        Self
+        Self {
+            leap_seconds: None,
+            apply_clock_offset: true,
+            max_stamp_offset: Some(Duration::seconds(5)),
+        }
+    }
+
+    /// Uses a fixed GPS to UTC leap second count instead of the receiver-reported offset.
+    pub fn with_leap_seconds(mut self, leap_seconds: Option<i64>) -> Self {
+        self.leap_seconds = leap_seconds;
+        self
+    }
+
+    /// Whether to correct for the receiver clock offset from GPS time.
+    pub fn with_clock_offset(mut self, apply_clock_offset: bool) -> Self {
+        self.apply_clock_offset = apply_clock_offset;
+        self
+    }
+
+    /// Largest allowed difference between the converted time and the message stamp.
+    pub fn with_max_stamp_offset(mut self, max_stamp_offset: Option<Duration>) -> Self {
+        self.max_stamp_offset = max_stamp_offset;
+        self
     }
 
     pub fn read_csv<P: AsRef<Path>>(
         &self,
         path: P,
-    ) -> Result<Box<dyn Iterator<Item = TimeFrame>>, Box<dyn Error + 'static>> {
-        let mut reader = csv::Reader::from_path(path)?;
-        let mut frames = Vec::new();
-        for result in reader.records() {
-            let record = result?;
-
-            let start_idx = 17;
-            let year: i32 = record.get(start_idx + 0).unwrap().parse()?;
-            assert_eq!(year, 2025);
-            let month: u32 = record.get(start_idx + 1).unwrap().parse()?;
-            let day: u32 = record.get(start_idx + 2).unwrap().parse()?;
-            let hour: u32 = record.get(start_idx + 3).unwrap().parse()?;
-            let min: u32 = record.get(start_idx + 4).unwrap().parse()?;
-            let msec: u32 = record.get(start_idx + 5).unwrap().parse()?;
-            let sec = msec / 1000;
-
-            let time = Utc
-                .with_ymd_and_hms(year, month, day, hour, min, sec)
-                .unwrap();
+    ) -> Result<Box<dyn Iterator<Item = TimeFrame>>, BenchError> {
+        let path = path.as_ref();
+        let mut reader = csv::Reader::from_reader(archive::open_file(path)?);
+        let mut frames: Vec<TimeFrame> = Vec::new();
+        for (i, result) in reader.records().enumerate() {
+            let record = result.map_err(|e| BenchError::parse(path, i, e))?;
+            let stamp_sec: i64 = parse_field(&record, STAMP_SEC_COL, path, i)?;
+            let stamp_nanosec: u32 = parse_field(&record, STAMP_NANOSEC_COL, path, i)?;
+            let stamp = DateTime::from_timestamp(stamp_sec, stamp_nanosec)
+                .ok_or_else(|| BenchError::parse(path, i, "invalid stamp"))?;
+
+            let week: i64 = parse_field(&record, GPS_WEEK_COL, path, i)?;
+            let week_msec: i64 = parse_field(&record, GPS_WEEK_MSEC_COL, path, i)?;
+            let clock_offset: f64 = parse_field(&record, CLOCK_OFFSET_COL, path, i)?;
+            let utc_offset: f64 = parse_field(&record, UTC_OFFSET_COL, path, i)?;
+
+            let week = resolve_week_rollover(week, week_msec, stamp);
+            let mut gps_time =
+                gps_epoch() + Duration::weeks(week) + Duration::milliseconds(week_msec);
+            if self.apply_clock_offset {
+                gps_time -= seconds(clock_offset);
+            }
+
+            // The receiver reports UTC - GPS, which is negative.
+            let time = match self.leap_seconds {
+                Some(leap_seconds) => gps_time - Duration::seconds(leap_seconds),
+                None => gps_time + seconds(utc_offset),
+            };
+
+            if let Some(max_stamp_offset) = self.max_stamp_offset
+                && (time - stamp).abs() > max_stamp_offset
+            {
+                return Err(BenchError::parse(
+                    path,
+                    i,
+                    format!(
+                        "converts to {time} but was stamped {stamp}; \
+                         check the GPS week and leap seconds"
+                    ),
+                ));
+            }
+
+            if let Some(previous) = frames.last()
+                && time < previous.time
+            {
+                return Err(BenchError::parse(
+                    path,
+                    i,
+                    format!(
+                        "{time} is earlier than the previous record at {}",
+                        previous.time
+                    ),
+                ));
+            }
+
             frames.push(TimeFrame { time });
         }
 
@@ -46,39 +143,281 @@ impl TimeReader {
     }
 }
 
-pub struct InsReader;
+/// Parses a single column of a log record.
+fn parse_field<T>(record: &StringRecord, col: usize, path: &Path, i: usize) -> Result<T, BenchError>
+where
+    T: FromStr,
+    T::Err: Display,
+{
+    record
+        .get(col)
+        .ok_or_else(|| BenchError::parse(path, i, format!("missing column {col}")))?
+        .parse()
+        .map_err(|e| BenchError::parse(path, i, format!("column {col}: {e}")))
+}
+
+fn gps_epoch() -> DateTime<Utc> {
+    Utc.with_ymd_and_hms(1980, 1, 6, 0, 0, 0).unwrap()
+}
+
+#[allow(clippy::cast_possible_truncation)]
+fn seconds(seconds: f64) -> Duration {
+    Duration::nanoseconds((seconds * 1e9).round() as i64)
+}
+
+/// Picks the number of 10-bit week rollovers that puts the GPS time closest to the stamp.
+///
+/// Full week numbers are returned unchanged.
+fn resolve_week_rollover(week: i64, week_msec: i64, stamp: DateTime<Utc>) -> i64 {
+    if week >= GPS_WEEK_ROLLOVER {
+        return week;
+    }
+
+    let elapsed = stamp - gps_epoch() - Duration::milliseconds(week_msec);
+    let rollovers = (elapsed.num_weeks() - week + GPS_WEEK_ROLLOVER / 2) / GPS_WEEK_ROLLOVER;
+    week + rollovers.max(0) * GPS_WEEK_ROLLOVER
+}
+
+/// Which NovAtel log the INS states are read from.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, clap::ValueEnum, serde::Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum InsLog {
+    /// Position and attitude.
+    #[default]
+    Inspva,
+    /// Position and attitude with their standard deviations.
+    Inspvax,
+}
+
+/// Columns of a log after the shared headers.
+struct InsColumns {
+    lat: usize,
+    lon: usize,
+    height: usize,
+    /// Geoid undulation, when the height is above mean sea level rather than the ellipsoid.
+    undulation: Option<usize>,
+    roll: usize,
+    pitch: usize,
+    azimuth: usize,
+    /// Inertial solution status, which older exports leave out.
+    status: usize,
+    /// Latitude, longitude and height standard deviations in m, then roll, pitch and azimuth
+    /// standard deviations in deg.
+    std_devs: Option<[usize; 6]>,
+}
+
+impl InsLog {
+    /// Latitude and longitude columns of a record.
+    pub(crate) fn position_columns(self) -> (usize, usize) {
+        let columns = self.columns();
+        (columns.lat, columns.lon)
+    }
+
+    fn columns(self) -> InsColumns {
+        match self {
+            Self::Inspva => InsColumns {
+                lat: 13,
+                lon: 14,
+                height: 15,
+                undulation: None,
+                roll: 19,
+                pitch: 20,
+                azimuth: 21,
+                status: 22,
+                std_devs: None,
+            },
+            // The solution status and position type come before the position.
+            Self::Inspvax => InsColumns {
+                lat: 15,
+                lon: 16,
+                height: 17,
+                undulation: Some(18),
+                roll: 22,
+                pitch: 23,
+                azimuth: 24,
+                status: 13,
+                std_devs: Some([25, 26, 27, 31, 32, 33]),
+            },
+        }
+    }
+}
+
+pub struct InsReader {
+    convention: InsConvention,
+    leap_seconds: i64,
+    log: InsLog,
+}
+
+#[derive(Clone)]
 pub struct InsFrame {
+    pub time: DateTime<Utc>,
     pub position: Wgs84,
     pub orientation: Orientation<InsEnu>,
+    /// Uncertainty of the state, if the log reports it.
+    pub covariance: Option<InsCovariance>,
+    /// Inertial solution status, if the log reports it.
+    pub status: Option<InsStatus>,
+}
+
+/// NovAtel inertial solution status.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, serde::Serialize)]
+#[serde(rename_all = "SCREAMING_SNAKE_CASE")]
+pub enum InsStatus {
+    InsInactive,
+    InsAligning,
+    InsHighVariance,
+    InsSolutionGood,
+    InsSolutionFree,
+    InsAlignmentComplete,
+    DeterminingOrientation,
+    WaitingInitialpos,
+    WaitingAzimuth,
+    InitializingBiases,
+    MotionDetect,
+    WaitingAlignmentorientation,
+    /// A status this reader does not know.
+    Other,
+}
+
+impl InsStatus {
+    pub fn from_code(code: u32) -> Self {
+        match code {
+            0 => Self::InsInactive,
+            1 => Self::InsAligning,
+            2 => Self::InsHighVariance,
+            3 => Self::InsSolutionGood,
+            6 => Self::InsSolutionFree,
+            7 => Self::InsAlignmentComplete,
+            8 => Self::DeterminingOrientation,
+            9 => Self::WaitingInitialpos,
+            10 => Self::WaitingAzimuth,
+            11 => Self::InitializingBiases,
+            12 => Self::MotionDetect,
+            14 => Self::WaitingAlignmentorientation,
+            _ => Self::Other,
+        }
+    }
+
+    /// Whether the solution can be trusted as ground truth.
+    pub fn is_good(self) -> bool {
+        self == Self::InsSolutionGood
+    }
+}
+
+/// Diagonal covariance of an INS state, from the standard deviations reported by INSPVAX.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct InsCovariance {
+    /// North, east and up position variances in m^2.
+    pub position_m2: [f64; 3],
+    /// Roll, pitch and azimuth variances in deg^2.
+    pub attitude_deg2: [f64; 3],
+}
+
+impl InsCovariance {
+    pub fn from_std_devs(position_m: [f64; 3], attitude_deg: [f64; 3]) -> Self {
+        Self {
+            position_m2: position_m.map(|sd| sd * sd),
+            attitude_deg2: attitude_deg.map(|sd| sd * sd),
+        }
+    }
+
+    pub fn azimuth_sd_deg(&self) -> f64 {
+        self.attitude_deg2[2].sqrt()
+    }
+
+    /// Variances between two states, held at the nearest one beyond them.
+    pub fn interpolate(&self, other: &Self, t: f64) -> Self {
+        let t = t.clamp(0., 1.);
+        let lerp = |a: [f64; 3], b: [f64; 3]| std::array::from_fn(|i| a[i] + (b[i] - a[i]) * t);
+        Self {
+            position_m2: lerp(self.position_m2, other.position_m2),
+            attitude_deg2: lerp(self.attitude_deg2, other.attitude_deg2),
+        }
+    }
 }
 
 impl InsReader {
     pub fn new() -> Self {
-        Self
+        Self {
+            convention: InsConvention::default(),
+            leap_seconds: DEFAULT_LEAP_SECONDS,
+            log: InsLog::default(),
+        }
+    }
+
+    /// Sets the GPS to UTC leap seconds used to timestamp INS records.
+    pub fn with_leap_seconds(mut self, leap_seconds: Option<i64>) -> Self {
+        self.leap_seconds = leap_seconds.unwrap_or(DEFAULT_LEAP_SECONDS);
+        self
+    }
+
+    /// Sets the attitude convention of the INS output.
+    pub fn with_convention(mut self, convention: InsConvention) -> Self {
+        self.convention = convention;
+        self
+    }
+
+    /// Reads INSPVAX instead of INSPVA, to attach covariances to the INS states.
+    pub fn with_log(mut self, log: InsLog) -> Self {
+        self.log = log;
+        self
+    }
+
+    pub fn log(&self) -> InsLog {
+        self.log
     }
 
     pub fn read_csv<P: AsRef<Path>>(
         &self,
         path: P,
-    ) -> Result<Box<dyn Iterator<Item = InsFrame>>, Box<dyn Error + 'static>> {
-        let mut reader = csv::Reader::from_path(path)?;
+    ) -> Result<Box<dyn Iterator<Item = InsFrame>>, BenchError> {
+        let path = path.as_ref();
+        let mut reader = csv::Reader::from_reader(archive::open_file(path)?);
+        let columns = self.log.columns();
         let mut frames = Vec::new();
-        for result in reader.records() {
-            let record = result?;
+        for (i, result) in reader.records().enumerate() {
+            let record = result.map_err(|e| BenchError::parse(path, i, e))?;
+            let field = |col: usize| parse_field::<f64>(&record, col, path, i);
+
+            let week: i64 = parse_field(&record, GPS_WEEK_COL, path, i)?;
+            let week_msec: i64 = parse_field(&record, GPS_WEEK_MSEC_COL, path, i)?;
+            let time = gps_epoch() + Duration::weeks(week) + Duration::milliseconds(week_msec)
+                - Duration::seconds(self.leap_seconds);
 
-            let lat = record.get(13).unwrap().parse()?;
-            let lon = record.get(14).unwrap().parse()?;
-            let height = record.get(15).unwrap().parse()?;
+            let lat = field(columns.lat)?;
+            let lon = field(columns.lon)?;
+            let mut height = field(columns.height)?;
+            if let Some(undulation) = columns.undulation {
+                height += field(undulation)?;
+            }
             let position = InsEnu::position_from_inspva(lat, lon, height);
 
-            let roll = record.get(19).unwrap().parse()?;
-            let pitch = record.get(20).unwrap().parse()?;
-            let azimuth = record.get(21).unwrap().parse()?;
-            let orientation = InsEnu::orientation_from_inspva(azimuth, pitch, roll);
+            let roll = field(columns.roll)?;
+            let pitch = field(columns.pitch)?;
+            let azimuth = field(columns.azimuth)?;
+            let orientation = InsEnu::orientation_from(self.convention, azimuth, pitch, roll);
+
+            let covariance = match columns.std_devs {
+                Some([lat_sd, lon_sd, height_sd, roll_sd, pitch_sd, azimuth_sd]) => {
+                    Some(InsCovariance::from_std_devs(
+                        [field(lat_sd)?, field(lon_sd)?, field(height_sd)?],
+                        [field(roll_sd)?, field(pitch_sd)?, field(azimuth_sd)?],
+                    ))
+                }
+                None => None,
+            };
+            let status = record
+                .get(columns.status)
+                .map(|_| parse_field(&record, columns.status, path, i))
+                .transpose()?
+                .map(InsStatus::from_code);
 
             frames.push(InsFrame {
+                time,
                 position,
                 orientation,
+                covariance,
+                status,
             });
         }
 
@@ -86,30 +425,596 @@ impl InsReader {
     }
 }
 
-pub struct ImageReader;
+/// How the 2x2 polarizer mosaic is turned into one ray per pixel.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, clap::ValueEnum, serde::Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum DemosaicMode {
+    /// One ray per 2x2 super-pixel, at half the sensor resolution.
+    #[default]
+    Bin2x2,
+    /// Interpolates each polarizer channel at every sensor pixel from its nearest samples.
+    Bilinear,
+    /// Like bilinear, but interpolates diagonal samples along the direction with the smaller
+    /// gradient to avoid false polarization at intensity edges.
+    EdgeAware,
+}
+
+impl DemosaicMode {
+    /// Side of the square of sensor pixels behind each ray.
+    pub fn pixel_scale(self) -> usize {
+        match self {
+            Self::Bin2x2 => 2,
+            Self::Bilinear | Self::EdgeAware => 1,
+        }
+    }
+}
+
+/// Clockwise rotation of the raw images needed to match the assumed camera mounting.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, clap::ValueEnum, serde::Serialize)]
+pub enum ImageRotation {
+    #[default]
+    #[value(name = "0")]
+    #[serde(rename = "0")]
+    None,
+    #[value(name = "90")]
+    #[serde(rename = "90")]
+    Cw90,
+    #[value(name = "180")]
+    #[serde(rename = "180")]
+    Cw180,
+    #[value(name = "270")]
+    #[serde(rename = "270")]
+    Cw270,
+}
+
+/// Mirroring of the raw images needed to match the assumed camera mounting.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, clap::ValueEnum, serde::Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum ImageFlip {
+    /// Left to right.
+    H,
+    /// Top to bottom.
+    V,
+}
+
+/// Turns raw images, and the polarizer angles of their pixels, to the mounting assumed by the
+/// camera model.
+///
+/// The flip is applied before the rotation.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, serde::Serialize)]
+pub struct ImageOrientation {
+    pub rotation: ImageRotation,
+    pub flip: Option<ImageFlip>,
+}
+
+impl ImageOrientation {
+    pub fn new(rotation: ImageRotation, flip: Option<ImageFlip>) -> Self {
+        Self { rotation, flip }
+    }
+
+    pub fn swaps_axes(&self) -> bool {
+        matches!(self.rotation, ImageRotation::Cw90 | ImageRotation::Cw270)
+    }
+
+    /// Rows and columns of an oriented image of `rows` by `cols`.
+    pub fn shape(&self, rows: usize, cols: usize) -> (usize, usize) {
+        if self.swaps_axes() {
+            (cols, rows)
+        } else {
+            (rows, cols)
+        }
+    }
+
+    /// Pixel of a raw image of `rows` by `cols` that ends up at `row`, `col` once oriented.
+    pub fn source_pixel(&self, row: usize, col: usize, rows: usize, cols: usize) -> (usize, usize) {
+        let (row, col) = match self.rotation {
+            ImageRotation::None => (row, col),
+            ImageRotation::Cw90 => (rows - 1 - col, row),
+            ImageRotation::Cw180 => (rows - 1 - row, cols - 1 - col),
+            ImageRotation::Cw270 => (col, cols - 1 - row),
+        };
+        match self.flip {
+            None => (row, col),
+            Some(ImageFlip::H) => (row, cols - 1 - col),
+            Some(ImageFlip::V) => (rows - 1 - row, col),
+        }
+    }
+
+    /// Offset once oriented of an offset of `dx` columns and `dy` rows in a raw image.
+    pub fn offset(&self, dx: f64, dy: f64) -> (f64, f64) {
+        let (dx, dy) = match self.flip {
+            None => (dx, dy),
+            Some(ImageFlip::H) => (-dx, dy),
+            Some(ImageFlip::V) => (dx, -dy),
+        };
+        match self.rotation {
+            ImageRotation::None => (dx, dy),
+            ImageRotation::Cw90 => (-dy, dx),
+            ImageRotation::Cw180 => (-dx, -dy),
+            ImageRotation::Cw270 => (dy, -dx),
+        }
+    }
+
+    pub fn apply(&self, raw: GrayImage) -> GrayImage {
+        let raw = match self.flip {
+            None => raw,
+            Some(ImageFlip::H) => image::imageops::flip_horizontal(&raw),
+            Some(ImageFlip::V) => image::imageops::flip_vertical(&raw),
+        };
+        match self.rotation {
+            ImageRotation::None => raw,
+            ImageRotation::Cw90 => image::imageops::rotate90(&raw),
+            ImageRotation::Cw180 => image::imageops::rotate180(&raw),
+            ImageRotation::Cw270 => image::imageops::rotate270(&raw),
+        }
+    }
+
+    /// Mosaic layout of an oriented image, given the layout of the raw image of `rows` by `cols`.
+    ///
+    /// Mirroring negates the polarizer angles and a quarter turn adds 90 deg to them.
+    pub fn layout(&self, layout: &MosaicLayout, rows: usize, cols: usize) -> MosaicLayout {
+        let angle_deg = |row, col| {
+            let (row, col) = self.source_pixel(row, col, rows, cols);
+            let mut angle = layout.angle_deg(row, col);
+            if self.flip.is_some() {
+                angle = (180 - angle) % 180;
+            }
+            if self.swaps_axes() {
+                angle = (angle + 90) % 180;
+            }
+            angle
+        };
+        MosaicLayout([
+            [angle_deg(0, 0), angle_deg(0, 1)],
+            [angle_deg(1, 0), angle_deg(1, 1)],
+        ])
+    }
+}
+
+pub struct ImageReader {
+    demosaic: DemosaicMode,
+    layout: MosaicLayout,
+    orientation: ImageOrientation,
+    image_circle: ImageCircle,
+    prefetch: Option<ImagePrefetch>,
+}
 
 impl ImageReader {
     pub fn new() -> Self {
-        Self
+        Self {
+            demosaic: DemosaicMode::default(),
+            layout: MosaicLayout::default(),
+            orientation: ImageOrientation::default(),
+            image_circle: ImageCircle::default(),
+            prefetch: None,
+        }
+    }
+
+    pub fn with_orientation(mut self, orientation: ImageOrientation) -> Self {
+        self.orientation = orientation;
+        self
+    }
+
+    pub fn orientation(&self) -> ImageOrientation {
+        self.orientation
+    }
+
+    pub fn with_layout(mut self, layout: MosaicLayout) -> Self {
+        self.layout = layout;
+        self
+    }
+
+    pub fn with_demosaic(mut self, demosaic: DemosaicMode) -> Self {
+        self.demosaic = demosaic;
+        self
+    }
+
+    pub fn demosaic(&self) -> DemosaicMode {
+        self.demosaic
+    }
+
+    /// Drops the rays outside of the lens image circle.
+    pub fn with_image_circle(mut self, image_circle: ImageCircle) -> Self {
+        self.image_circle = image_circle;
+        self
+    }
+
+    pub fn image_circle(&self) -> ImageCircle {
+        self.image_circle
     }
 
-    pub fn read_image<P: AsRef<Path>>(
+    /// Decodes the images at `paths` on a background thread, keeping up to `depth` of them
+    /// ahead of the one being read. A depth of zero reads every image when asked for.
+    ///
+    /// The exposure of prefetched images is measured at `dark_level`.
+    pub fn with_prefetch(mut self, paths: Vec<PathBuf>, depth: usize, dark_level: u8) -> Self {
+        self.prefetch = (depth > 0)
+            .then(|| ImagePrefetch::start(self.without_prefetch(), paths, depth, dark_level));
+        self
+    }
+
+    /// Like [`Self::with_prefetch`], decoding the files `read_ahead` reads rather than reading
+    /// them on the decoding thread.
+    pub fn with_read_ahead(mut self, read_ahead: ReadAhead, depth: usize, dark_level: u8) -> Self {
+        self.prefetch = Some(ImagePrefetch::start_reading_ahead(
+            self.without_prefetch(),
+            read_ahead,
+            depth,
+            dark_level,
+        ));
+        self
+    }
+
+    /// The same reader, reading every image when asked for.
+    fn without_prefetch(&self) -> Self {
+        Self {
+            demosaic: self.demosaic,
+            layout: self.layout,
+            orientation: self.orientation,
+            image_circle: self.image_circle,
+            prefetch: None,
+        }
+    }
+
+    /// Reads the raw polarizer mosaic as single channel greyscale, oriented to the camera model.
+    pub fn read_raw<P: AsRef<Path>>(&self, path: P) -> Result<GrayImage, BenchError> {
+        let path = path.as_ref();
+        if !archive::is_plain_file(path) {
+            return self.read_raw_from_bytes(path, &archive::read_file(path)?);
+        }
+        let reader = image::ImageReader::open(path).map_err(|e| BenchError::dataset(path, e))?;
+        self.decode_raw(path, reader)
+    }
+
+    /// Like [`Self::read_raw`], from the bytes of the file at `path` once they have been read.
+    pub fn read_raw_from_bytes(&self, path: &Path, bytes: &[u8]) -> Result<GrayImage, BenchError> {
+        let reader = image::ImageReader::new(Cursor::new(bytes))
+            .with_guessed_format()
+            .map_err(|e| BenchError::dataset(path, e))?;
+        self.decode_raw(path, reader)
+    }
+
+    fn decode_raw<R: BufRead + Seek>(
+        &self,
+        path: &Path,
+        reader: image::ImageReader<R>,
+    ) -> Result<GrayImage, BenchError> {
+        let image = reader.decode().map_err(|e| BenchError::Image {
+            path: path.to_path_buf(),
+            message: e.to_string(),
+        })?;
+        Ok(self.orientation.apply(image.into_luma8()))
+    }
+
+    /// Layout of an image returned by [`Self::read_raw`].
+    fn oriented_layout(&self, oriented: &GrayImage) -> MosaicLayout {
+        let (rows, cols) = self
+            .orientation
+            .shape(oriented.height() as usize, oriented.width() as usize);
+        self.orientation.layout(&self.layout, rows, cols)
+    }
+
+    /// Reads the raw polarizer mosaic as Stokes parameters, one per 2x2 super-pixel.
+    pub fn read_stokes<P: AsRef<Path>>(&self, path: P) -> Result<StokesImage, BenchError> {
+        let raw_image = self.read_raw(path)?;
+        Ok(StokesImage::from_mosaic(
+            &raw_image,
+            &self.oriented_layout(&raw_image),
+        ))
+    }
+
+    pub fn read_image<P: AsRef<Path>>(&self, path: P) -> Result<RayImage<SensorFrame>, BenchError> {
+        self.read_image_with_circle(path).map(|(image, _)| image)
+    }
+
+    /// Reads the rays of an image along with the image circle, in rays, outside of which they
+    /// were dropped, if they were.
+    pub fn read_image_with_circle<P: AsRef<Path>>(
+        &self,
+        path: P,
+    ) -> Result<(RayImage<SensorFrame>, Option<Circle>), BenchError> {
+        let path = path.as_ref();
+        if let Some(prefetch) = &self.prefetch
+            && let Some(decoded) = prefetch.take(path, prefetch.dark_level())
+        {
+            return decoded.map(|(image, _, circle)| (image, circle));
+        }
+        let raw_image = self.read_raw(path)?;
+        let circle = self.image_circle.locate(&raw_image, self.orientation);
+        self.decode(path, raw_image, circle)
+    }
+
+    /// Reads the rays of an image along with how well it is exposed and the image circle, in
+    /// rays, outside of which they were dropped, if they were.
+    ///
+    /// Only the super-pixels inside the image circle count towards the exposure.
+    pub fn read_image_with_exposure<P: AsRef<Path>>(
         &self,
         path: P,
-    ) -> Result<RayImage<SensorFrame>, Box<dyn Error + 'static>> {
-        // Open a new image and ensure it is in single channel greyscale format.
-        let raw_image = image::ImageReader::open(&path)?.decode()?.into_luma8();
-
-        // Create a new IntensityImage from the input image.
-        let (width, height) = raw_image.dimensions();
-        let intensity_image =
-            IntensityImage::from_bytes(width as usize, height as usize, &raw_image.into_raw())
-                .expect("image dimensions are even");
-
-        Ok(RayImage::from_rays(
-            intensity_image.rays().map(|ray| Some(ray)),
-            intensity_image.height(),
-            intensity_image.width(),
-        )?)
+        dark_level: u8,
+    ) -> Result<(RayImage<SensorFrame>, ExposureQuality, Option<Circle>), BenchError> {
+        let path = path.as_ref();
+        if let Some(prefetch) = &self.prefetch
+            && let Some(decoded) = prefetch.take(path, dark_level)
+        {
+            return decoded;
+        }
+        self.decode_with_exposure(path, self.read_raw(path)?, dark_level)
+    }
+
+    /// Decodes the rays of a raw image read from `path` along with how well it is exposed and
+    /// the image circle they were cut to.
+    pub(crate) fn decode_with_exposure(
+        &self,
+        path: &Path,
+        raw_image: GrayImage,
+        dark_level: u8,
+    ) -> Result<(RayImage<SensorFrame>, ExposureQuality, Option<Circle>), BenchError> {
+        let circle = self.image_circle.locate(&raw_image, self.orientation);
+        let quality = ExposureQuality::measure_where(&raw_image, dark_level, |row, col| {
+            circle.is_none_or(|circle| circle.contains_ray(row, col, 2))
+        });
+        let (image, circle) = self.decode(path, raw_image, circle)?;
+        Ok((image, quality, circle))
     }
+
+    /// Decodes the rays of a raw image, dropping those outside of `circle`, which is returned
+    /// measured in rays.
+    fn decode(
+        &self,
+        path: &Path,
+        raw_image: GrayImage,
+        circle: Option<Circle>,
+    ) -> Result<(RayImage<SensorFrame>, Option<Circle>), BenchError> {
+        let layout = self.oriented_layout(&raw_image);
+        let ray_image = match self.demosaic {
+            DemosaicMode::Bin2x2 => {
+                let (width, height) = raw_image.dimensions();
+                ray_image_from_mosaic(
+                    width as usize,
+                    height as usize,
+                    &raw_image.into_raw(),
+                    &layout,
+                )
+            }
+            DemosaicMode::Bilinear | DemosaicMode::EdgeAware => {
+                interpolate_mosaic(&raw_image, &layout, self.demosaic)
+            }
+        };
+        let ray_image = ray_image.map_err(|e| BenchError::Image {
+            path: path.to_path_buf(),
+            message: e.to_string(),
+        })?;
+        let scale = self.demosaic.pixel_scale();
+        Ok(match circle {
+            Some(circle) => (circle.mask(ray_image, scale), Some(circle.in_rays(scale))),
+            None => (ray_image, None),
+        })
+    }
+}
+
+/// Decodes a raw 8-bit polarizer mosaic into rays.
+pub fn ray_image_from_mosaic(
+    width: usize,
+    height: usize,
+    bytes: &[u8],
+    layout: &MosaicLayout,
+) -> Result<RayImage<SensorFrame>, BenchError> {
+    // Create a new IntensityImage from the input image.
+    let bytes = layout.to_default(width, bytes);
+    let intensity_image = IntensityImage::from_bytes(width, height, &bytes)
+        .map_err(|e| BenchError::Mosaic(format!("{width}x{height} mosaic: {e}")))?;
+
+    RayImage::from_rays(
+        intensity_image.rays().map(|ray| Some(ray)),
+        intensity_image.height(),
+        intensity_image.width(),
+    )
+    .map_err(|e| BenchError::Mosaic(e.to_string()))
+}
+
+/// Polarizer angle in degrees of each pixel in a 2x2 super-pixel, as on the Sony IMX250MZR.
+pub const MOSAIC_LAYOUT_DEG: [[u16; 2]; 2] = [[90, 45], [135, 0]];
+
+/// Polarizer angles in degrees of a 2x2 super-pixel, written row by row as `90,45;135,0`.
+///
+/// Defaults to [`MOSAIC_LAYOUT_DEG`], which is the layout rumpus decodes.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, serde::Serialize)]
+pub struct MosaicLayout([[u16; 2]; 2]);
+
+impl Default for MosaicLayout {
+    fn default() -> Self {
+        Self(MOSAIC_LAYOUT_DEG)
+    }
+}
+
+impl MosaicLayout {
+    /// Checks that each of 0, 45, 90 and 135 deg appears once.
+    pub fn new(angles_deg: [[u16; 2]; 2]) -> Result<Self, BenchError> {
+        let mut sorted = angles_deg.concat();
+        sorted.sort_unstable();
+        if sorted != [0, 45, 90, 135] {
+            return Err(BenchError::Config(format!(
+                "mosaic layout {angles_deg:?} is not a permutation of 0, 45, 90 and 135 deg"
+            )));
+        }
+        Ok(Self(angles_deg))
+    }
+
+    /// Polarizer angle in degrees of a sensor pixel.
+    pub fn angle_deg(&self, row: usize, col: usize) -> u16 {
+        self.0[row % 2][col % 2]
+    }
+
+    /// Index of the polarizer angle of a sensor pixel, counting up in 45 deg steps from 0 deg.
+    pub fn channel(&self, row: usize, col: usize) -> usize {
+        usize::from(self.angle_deg(row, col) / 45)
+    }
+
+    /// Moves the pixels of every super-pixel to where the default layout has their angle.
+    pub fn to_default<'a>(&self, width: usize, bytes: &'a [u8]) -> Cow<'a, [u8]> {
+        let target = Self::default();
+        if *self == target {
+            return Cow::Borrowed(bytes);
+        }
+
+        // A trailing odd row or column has no complete super-pixel and is left alone.
+        let mut moved = bytes.to_vec();
+        for row in 0..bytes.len() / width / 2 * 2 {
+            for col in 0..width / 2 * 2 {
+                let (dr, dc) = [(0, 0), (0, 1), (1, 0), (1, 1)]
+                    .into_iter()
+                    .find(|&(dr, dc)| target.angle_deg(dr, dc) == self.angle_deg(row, col))
+                    .expect("both layouts hold every angle");
+                moved[(row - row % 2 + dr) * width + col - col % 2 + dc] = bytes[row * width + col];
+            }
+        }
+        Cow::Owned(moved)
+    }
+}
+
+impl FromStr for MosaicLayout {
+    type Err = BenchError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let invalid = || BenchError::Config(format!("mosaic layout {s:?} is not like 90,45;135,0"));
+        let rows: Vec<Vec<u16>> = s
+            .split(';')
+            .map(|row| {
+                row.split(',')
+                    .map(|angle| angle.trim().parse().map_err(|_| invalid()))
+                    .collect()
+            })
+            .collect::<Result<_, _>>()?;
+        match rows.as_slice() {
+            [top, bottom] => match (top.as_slice(), bottom.as_slice()) {
+                (&[a, b], &[c, d]) => Self::new([[a, b], [c, d]]),
+                _ => Err(invalid()),
+            },
+            _ => Err(invalid()),
+        }
+    }
+}
+
+impl Display for MosaicLayout {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let [[a, b], [c, d]] = self.0;
+        write!(f, "{a},{b};{c},{d}")
+    }
+}
+
+/// Linear Stokes parameters of every super-pixel in a polarizer mosaic, in raw intensity units.
+#[derive(Debug, Clone)]
+pub struct StokesImage {
+    pub rows: usize,
+    pub cols: usize,
+    /// Total intensity.
+    pub s0: Vec<f32>,
+    /// Intensity through 0 deg minus 90 deg polarizers.
+    pub s1: Vec<f32>,
+    /// Intensity through 45 deg minus 135 deg polarizers.
+    pub s2: Vec<f32>,
+}
+
+impl StokesImage {
+    pub fn from_mosaic(raw: &GrayImage, layout: &MosaicLayout) -> Self {
+        let (rows, cols) = (raw.height() as usize / 2, raw.width() as usize / 2);
+        let mut stokes = Self {
+            rows,
+            cols,
+            s0: Vec::with_capacity(rows * cols),
+            s1: Vec::with_capacity(rows * cols),
+            s2: Vec::with_capacity(rows * cols),
+        };
+
+        for row in 0..rows {
+            for col in 0..cols {
+                let mut intensity = [0f32; 4];
+                for (dr, dc) in [(0, 0), (0, 1), (1, 0), (1, 1)] {
+                    #[allow(clippy::cast_possible_truncation)]
+                    let pixel = raw.get_pixel((2 * col + dc) as u32, (2 * row + dr) as u32);
+                    intensity[layout.channel(dr, dc)] = f32::from(pixel.0[0]);
+                }
+
+                let [i0, i45, i90, i135] = intensity;
+                stokes.s0.push((i0 + i45 + i90 + i135) / 2.);
+                stokes.s1.push(i0 - i90);
+                stokes.s2.push(i45 - i135);
+            }
+        }
+
+        stokes
+    }
+}
+
+/// Estimates every polarizer channel at every sensor pixel and decodes one ray per pixel.
+///
+/// Pixels without any intensity are invalid.
+fn interpolate_mosaic(
+    raw: &GrayImage,
+    layout: &MosaicLayout,
+    mode: DemosaicMode,
+) -> Result<RayImage<SensorFrame>, BenchError> {
+    let (width, height) = (raw.width() as usize, raw.height() as usize);
+    let sample = |row: usize, col: usize| f64::from(raw.as_raw()[row * width + col]);
+
+    let channel = |row: usize, col: usize| layout.channel(row, col);
+
+    let rays = (0..height)
+        .flat_map(|row| (0..width).map(move |col| (row, col)))
+        .map(|(row, col)| {
+            let mut intensity = [0.; 4];
+            for (target, value) in intensity.iter_mut().enumerate() {
+                // Samples of the channel are either here, at the horizontal or vertical neighbours,
+                // or at the diagonal neighbours.
+                let neighbours = |offsets: &[(isize, isize)]| -> Vec<f64> {
+                    offsets
+                        .iter()
+                        .filter_map(|&(dr, dc)| {
+                            let r = row.checked_add_signed(dr).filter(|r| *r < height)?;
+                            let c = col.checked_add_signed(dc).filter(|c| *c < width)?;
+                            (channel(r, c) == target).then(|| sample(r, c))
+                        })
+                        .collect()
+                };
+
+                *value = if channel(row, col) == target {
+                    sample(row, col)
+                } else if let straight @ [_, ..] =
+                    neighbours(&[(0, -1), (0, 1), (-1, 0), (1, 0)]).as_slice()
+                {
+                    mean(straight)
+                } else {
+                    let falling = neighbours(&[(-1, -1), (1, 1)]);
+                    let rising = neighbours(&[(-1, 1), (1, -1)]);
+                    match (mode, falling.as_slice(), rising.as_slice()) {
+                        (DemosaicMode::EdgeAware, [a, b], [c, d]) => {
+                            if (a - b).abs() <= (c - d).abs() {
+                                (a + b) / 2.
+                            } else {
+                                (c + d) / 2.
+                            }
+                        }
+                        _ => mean(&[falling, rising].concat()),
+                    }
+                };
+            }
+
+            let [i0, i45, i90, i135] = intensity;
+            let s0 = (i0 + i45 + i90 + i135) / 2.;
+            let (s1, s2) = (i0 - i90, i45 - i135);
+            if s0 <= 0. {
+                return None;
+            }
+
+            let aop = Aop::from_angle_wrapped(Angle::new::<radian>(s2.atan2(s1) / 2.));
+            Some(Ray::new(aop, (s1.hypot(s2) / s0).min(1.)))
+        });
+
+    RayImage::from_rays(rays, height, width).map_err(|e| BenchError::Mosaic(e.to_string()))
+}
+
+#[allow(clippy::cast_precision_loss)]
+fn mean(values: &[f64]) -> f64 {
+    values.iter().sum::<f64>() / values.len() as f64
 }
@@ -0,0 +1,86 @@
+use crate::{error::BenchError, pipeline::Dataset, run::RunMetadata};
+use serde::{Deserialize, Serialize};
+use std::path::PathBuf;
+
+/// One completed run on a dataset.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct LeaderboardEntry {
+    pub run_name: String,
+    pub experiment: String,
+    pub started: String,
+    pub config_hash: String,
+    pub frames_processed: usize,
+    /// Mean absolute heading error against the INS, if the run estimated headings.
+    pub mean_heading_error_deg: Option<f64>,
+    pub runtime_s: f64,
+}
+
+/// Every run ever completed on a dataset, kept in `leaderboard.json` next to its logs.
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub struct Leaderboard {
+    pub entries: Vec<LeaderboardEntry>,
+}
+
+impl Leaderboard {
+    pub fn path(dataset: &Dataset) -> PathBuf {
+        dataset.path().join("leaderboard.json")
+    }
+
+    /// Reads the leaderboard of a dataset, which is empty before the first run.
+    pub fn read(dataset: &Dataset) -> Result<Self, BenchError> {
+        let path = Self::path(dataset);
+        if !path.exists() {
+            return Ok(Self::default());
+        }
+        let file = std::fs::File::open(&path).map_err(|e| BenchError::dataset(&path, e))?;
+        serde_json::from_reader(file).map_err(|e| BenchError::dataset(&path, e))
+    }
+
+    /// Adds a run to the leaderboard of a dataset.
+    pub fn append(dataset: &Dataset, entry: LeaderboardEntry) -> Result<(), BenchError> {
+        let mut leaderboard = Self::read(dataset)?;
+        leaderboard.entries.push(entry);
+
+        let path = Self::path(dataset);
+        let file =
+            std::fs::File::create(&path).map_err(|e| BenchError::output(path.display(), e))?;
+        serde_json::to_writer_pretty(file, &leaderboard)
+            .map_err(|e| BenchError::output(path.display(), e))
+    }
+
+    /// Entries from the lowest heading error up, with runs that have none last.
+    pub fn ranked(&self) -> Vec<&LeaderboardEntry> {
+        let mut entries: Vec<_> = self.entries.iter().collect();
+        entries.sort_by(|a, b| {
+            match (a.mean_heading_error_deg, b.mean_heading_error_deg) {
+                (Some(a), Some(b)) => a.total_cmp(&b),
+                (Some(_), None) => std::cmp::Ordering::Less,
+                (None, Some(_)) => std::cmp::Ordering::Greater,
+                (None, None) => std::cmp::Ordering::Equal,
+            }
+            .then_with(|| a.runtime_s.total_cmp(&b.runtime_s))
+        });
+        entries
+    }
+}
+
+/// Identifies runs configured the same way, whenever they were started.
+///
+/// The hash covers the run metadata except its start time, plus any experiment options.
+pub fn config_hash(metadata: &RunMetadata, options: &impl Serialize) -> String {
+    let mut metadata = serde_json::to_value(metadata).unwrap_or_default();
+    if let Some(metadata) = metadata.as_object_mut() {
+        metadata.remove("started");
+    }
+    let options = serde_json::to_value(options).unwrap_or_default();
+    let config = serde_json::Value::Array(vec![metadata, options]).to_string();
+    stable_hash(config.as_bytes())
+}
+
+/// 64-bit FNV-1a in hex, which unlike the std hashers is stable between releases.
+pub(crate) fn stable_hash(bytes: &[u8]) -> String {
+    let hash = bytes.iter().fold(0xcbf2_9ce4_8422_2325_u64, |hash, &byte| {
+        (hash ^ u64::from(byte)).wrapping_mul(0x0100_0000_01b3)
+    });
+    format!("{hash:016x}")
+}
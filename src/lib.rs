@@ -1,3 +1,54 @@
+pub mod allan;
+pub mod annotate;
+pub mod archive;
+pub mod batch;
+pub mod camera;
+pub mod cli;
+pub mod conventions;
+pub mod correlation;
+pub mod dashboard;
+pub mod dead_reckoning;
+pub mod ephemeris;
+pub mod error;
+pub mod estimator;
+pub mod export;
+pub mod exposure;
+pub mod glare;
+pub mod heading;
+pub mod histogram;
+pub mod image_circle;
+pub mod image_writer;
+pub mod incremental;
+pub mod integrity;
 pub mod io;
+pub mod leaderboard;
+pub mod magnetic;
+pub mod metrics;
+pub mod monitor;
+pub mod motion;
+pub mod neutral;
+pub mod notify;
+pub mod outage;
+pub mod output;
+pub mod overlay;
+pub mod pipeline;
+pub mod prefetch;
+#[cfg(feature = "python")]
+mod python;
+pub mod read_ahead;
+pub mod remote;
+pub mod run;
+pub mod scattering;
+pub mod scratch;
+pub mod sink;
+pub mod sky;
+pub mod smoothing;
+pub mod stats;
 pub mod systems;
+pub mod tags;
+pub mod time_offset;
+pub mod trajectory;
+pub mod trim;
 pub mod utils;
+pub mod validity;
+pub mod weather;
@@ -0,0 +1,215 @@
+use crate::{error::BenchError, systems::InsEnu};
+use chrono::{DateTime, Datelike, Timelike, Utc};
+use sguaba::{engineering::Orientation, systems::Wgs84};
+use std::path::Path;
+use uom::si::{angle::radian, f64::Angle, length::meter};
+
+/// Geomagnetic reference radius of the World Magnetic Model.
+const REFERENCE_RADIUS_M: f64 = 6_371_200.0;
+
+const WGS84_SEMI_MAJOR_AXIS_M: f64 = 6_378_137.0;
+const WGS84_FLATTENING: f64 = 1. / 298.257_223_563;
+
+/// Whether an INS heading is measured from true or magnetic north.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, clap::ValueEnum, serde::Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum HeadingReference {
+    #[default]
+    True,
+    Magnetic,
+}
+
+/// Spherical harmonic model of the main geomagnetic field, such as the World Magnetic Model.
+#[derive(Debug, Clone)]
+pub struct MagneticModel {
+    epoch: f64,
+    max_degree: usize,
+    /// Gauss coefficients and their secular variation in nT and nT/year, indexed by `[n][m]`.
+    g: Vec<Vec<(f64, f64)>>,
+    h: Vec<Vec<(f64, f64)>>,
+}
+
+impl MagneticModel {
+    /// Reads a coefficient file in the `WMM.COF` format published by NOAA.
+    pub fn read_cof<P: AsRef<Path>>(path: P) -> Result<Self, BenchError> {
+        let path = path.as_ref();
+        let text = std::fs::read_to_string(path).map_err(|e| BenchError::dataset(path, e))?;
+        let mut lines = text.lines();
+
+        let header = lines
+            .next()
+            .ok_or_else(|| BenchError::parse(path, 0, "coefficient file is empty"))?;
+        let epoch: f64 = header
+            .split_whitespace()
+            .next()
+            .and_then(|epoch| epoch.parse().ok())
+            .ok_or_else(|| BenchError::parse(path, 0, "missing epoch"))?;
+
+        let mut rows = Vec::new();
+        for (i, line) in lines.enumerate() {
+            // The coefficients are terminated by a line of nines.
+            if line.trim_start().starts_with("9999") {
+                break;
+            }
+
+            let malformed = || BenchError::parse(path, i + 1, format!("malformed line: {line}"));
+            let fields: Vec<&str> = line.split_whitespace().collect();
+            let [n, m, g, h, dg, dh] = fields[..] else {
+                return Err(malformed());
+            };
+            let number = |field: &str| field.parse::<f64>().map_err(|_| malformed());
+            let index = |field: &str| field.parse::<usize>().map_err(|_| malformed());
+            let (n, m) = (index(n)?, index(m)?);
+            if m > n {
+                return Err(BenchError::parse(
+                    path,
+                    i + 1,
+                    format!("order {m} exceeds degree {n}"),
+                ));
+            }
+            rows.push((n, m, (number(g)?, number(dg)?), (number(h)?, number(dh)?)));
+        }
+
+        let max_degree = rows.iter().map(|(n, ..)| *n).max().unwrap_or(0);
+        let mut g = vec![vec![(0., 0.); max_degree + 1]; max_degree + 1];
+        let mut h = g.clone();
+        for (n, m, gnm, hnm) in rows {
+            g[n][m] = gnm;
+            h[n][m] = hnm;
+        }
+
+        Ok(Self {
+            epoch,
+            max_degree,
+            g,
+            h,
+        })
+    }
+
+    /// Angle from true north to magnetic north, positive east.
+    pub fn declination(&self, position: &Wgs84, time: DateTime<Utc>) -> Angle {
+        let latitude = position.latitude().get::<radian>();
+        let longitude = position.longitude().get::<radian>();
+        let height = position.altitude().get::<meter>();
+        let dt = decimal_year(time) - self.epoch;
+
+        // Geodetic to geocentric spherical coordinates.
+        let e2 = WGS84_FLATTENING * (2. - WGS84_FLATTENING);
+        let rc = WGS84_SEMI_MAJOR_AXIS_M / (1. - e2 * latitude.sin().powi(2)).sqrt();
+        let p = (rc + height) * latitude.cos();
+        let z = (rc * (1. - e2) + height) * latitude.sin();
+        let r = p.hypot(z);
+        let geocentric_latitude = (z / r).asin();
+
+        let (p, dp) = schmidt_legendre(self.max_degree, geocentric_latitude);
+
+        let mut north = 0.;
+        let mut east = 0.;
+        let mut down = 0.;
+        for n in 1..=self.max_degree {
+            #[allow(clippy::cast_possible_wrap, clippy::cast_possible_truncation)]
+            let radius_ratio = (REFERENCE_RADIUS_M / r).powi(n as i32 + 2);
+            for m in 0..=n {
+                let g = self.g[n][m].0 + dt * self.g[n][m].1;
+                let h = self.h[n][m].0 + dt * self.h[n][m].1;
+                #[allow(clippy::cast_precision_loss)]
+                let (sin, cos) = (m as f64 * longitude).sin_cos();
+
+                north += radius_ratio * (g * cos + h * sin) * dp[n][m];
+                #[allow(clippy::cast_precision_loss)]
+                {
+                    east += radius_ratio * m as f64 * (g * sin - h * cos) * p[n][m];
+                    down -= radius_ratio * (n + 1) as f64 * (g * cos + h * sin) * p[n][m];
+                }
+            }
+        }
+        east /= geocentric_latitude.cos();
+
+        // Rotate from the geocentric to the geodetic horizon.
+        let psi = geocentric_latitude - latitude;
+        let north = north * psi.cos() - down * psi.sin();
+
+        Angle::new::<radian>(east.atan2(north))
+    }
+
+    /// Rotates an INS orientation from magnetic to true heading.
+    pub fn true_heading(
+        &self,
+        orientation: Orientation<InsEnu>,
+        position: &Wgs84,
+        time: DateTime<Utc>,
+    ) -> Orientation<InsEnu> {
+        // Yaw is counter-clockwise from north while declination is clockwise.
+        let (yaw, pitch, roll) = orientation.to_tait_bryan_angles();
+        Orientation::tait_bryan_builder()
+            .yaw(yaw - self.declination(position, time))
+            .pitch(pitch)
+            .roll(roll)
+            .build()
+    }
+}
+
+/// Schmidt semi-normalized associated Legendre functions of the sine of latitude, and their
+/// derivatives with respect to colatitude, indexed by `[n][m]`.
+#[allow(clippy::cast_precision_loss, clippy::type_complexity)]
+fn schmidt_legendre(max_degree: usize, latitude: f64) -> (Vec<Vec<f64>>, Vec<Vec<f64>>) {
+    let (sin_theta, cos_theta) = (latitude.cos(), latitude.sin());
+    let mut p = vec![vec![0.; max_degree + 1]; max_degree + 1];
+    let mut dp = p.clone();
+    p[0][0] = 1.;
+
+    // Gauss-normalized recursion.
+    for n in 1..=max_degree {
+        for m in 0..=n {
+            if n == m {
+                p[n][m] = sin_theta * p[n - 1][m - 1];
+                dp[n][m] = sin_theta * dp[n - 1][m - 1] + cos_theta * p[n - 1][m - 1];
+            } else {
+                let k = if n == 1 {
+                    0.
+                } else {
+                    let (nf, mf) = (n as f64, m as f64);
+                    ((nf - 1.).powi(2) - mf * mf) / ((2. * nf - 1.) * (2. * nf - 3.))
+                };
+                let (p2, dp2) = if n >= 2 {
+                    (p[n - 2][m], dp[n - 2][m])
+                } else {
+                    (0., 0.)
+                };
+                p[n][m] = cos_theta * p[n - 1][m] - k * p2;
+                dp[n][m] = cos_theta * dp[n - 1][m] - sin_theta * p[n - 1][m] - k * dp2;
+            }
+        }
+    }
+
+    // Convert to Schmidt semi-normalization.
+    let mut scale = 1.;
+    for n in 1..=max_degree {
+        scale *= (2. * n as f64 - 1.) / n as f64;
+        let mut s = scale;
+        for m in 0..=n {
+            if m > 0 {
+                let delta = if m == 1 { 2. } else { 1. };
+                s *= ((n - m + 1) as f64 * delta / (n + m) as f64).sqrt();
+            }
+            p[n][m] *= s;
+            dp[n][m] *= s;
+        }
+    }
+
+    (p, dp)
+}
+
+#[allow(clippy::cast_precision_loss)]
+fn decimal_year(time: DateTime<Utc>) -> f64 {
+    let year = time.year();
+    let days_in_year = if chrono::NaiveDate::from_ymd_opt(year, 12, 31)
+        .is_some_and(|date| date.ordinal() == 366)
+    {
+        366.
+    } else {
+        365.
+    };
+    let seconds_of_day = f64::from(time.num_seconds_from_midnight());
+    f64::from(year) + (f64::from(time.ordinal0()) + seconds_of_day / 86_400.) / days_in_year
+}
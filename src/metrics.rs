@@ -0,0 +1,137 @@
+#[cfg(feature = "metrics")]
+use crate::dashboard::serve_http;
+use crate::{
+    error::BenchError,
+    monitor::{RunMonitor, RunProgress},
+    stats::ErrorSummary,
+};
+use std::{fmt::Write, net::SocketAddr};
+
+/// Serves the progress of a run at `/metrics` in the Prometheus text format, from a background
+/// thread. Returns the address listened on.
+#[cfg(feature = "metrics")]
+pub fn serve(addr: SocketAddr, monitor: RunMonitor) -> Result<SocketAddr, BenchError> {
+    serve_http(addr, move |path| {
+        Ok((path == "/metrics").then(|| {
+            let text = render(&monitor.progress(), monitor.elapsed_s());
+            ("text/plain; version=0.0.4", text.into_bytes())
+        }))
+    })
+}
+
+#[cfg(not(feature = "metrics"))]
+pub fn serve(_addr: SocketAddr, _monitor: RunMonitor) -> Result<SocketAddr, BenchError> {
+    Err(BenchError::Config(
+        "the metrics endpoint needs the `metrics` feature; rebuild with `--features metrics`"
+            .to_string(),
+    ))
+}
+
+/// Formats the progress as Prometheus metrics, labelled with the experiment and run.
+pub fn render(progress: &RunProgress, elapsed_s: f64) -> String {
+    let labels = format!(
+        "experiment=\"{}\",run=\"{}\"",
+        escape(&progress.experiment),
+        escape(&progress.started)
+    );
+    let mut text = String::new();
+    let mut metric = |name: &str, kind: &str, help: &str, samples: &[(String, f64)]| {
+        let _ = writeln!(text, "# HELP rumpus_{name} {help}");
+        let _ = writeln!(text, "# TYPE rumpus_{name} {kind}");
+        for (extra_labels, value) in samples {
+            let _ = writeln!(text, "rumpus_{name}{{{labels}{extra_labels}}} {value}");
+        }
+    };
+    let sample = |value: f64| vec![(String::new(), value)];
+
+    #[allow(clippy::cast_precision_loss)]
+    {
+        metric(
+            "frames_processed_total",
+            "counter",
+            "Frames processed so far.",
+            &sample(progress.frames_processed as f64),
+        );
+        let skipped: Vec<_> = progress
+            .frames_skipped
+            .iter()
+            .map(|(reason, &count)| {
+                let reason = serde_json::to_value(reason)
+                    .ok()
+                    .and_then(|reason| reason.as_str().map(str::to_string))
+                    .unwrap_or_default();
+                (format!(",reason=\"{reason}\""), count as f64)
+            })
+            .collect();
+        metric(
+            "frames_skipped_total",
+            "counter",
+            "Frames skipped so far, by reason.",
+            &skipped,
+        );
+        if let Some(frames_total) = progress.frames_total {
+            metric(
+                "frames_planned",
+                "gauge",
+                "Frames the run is expected to reach.",
+                &sample(frames_total as f64),
+            );
+        }
+        metric(
+            "candidates_evaluated_total",
+            "counter",
+            "Yaw candidates scored so far.",
+            &sample(progress.candidates_evaluated as f64),
+        );
+        metric(
+            "candidates_per_second",
+            "gauge",
+            "Yaw candidates scored per second since the run started.",
+            &sample(progress.candidates_evaluated as f64 / elapsed_s.max(f64::EPSILON)),
+        );
+    }
+    if let Some(weighted_rmse) = progress.last_weighted_rmse {
+        metric(
+            "last_weighted_rmse",
+            "gauge",
+            "Cost of the best candidate of the last frame with an estimate.",
+            &sample(weighted_rmse),
+        );
+    }
+    let heading_errors_deg: Vec<f64> = progress
+        .heading_errors_deg
+        .iter()
+        .map(|&(_, error_deg)| error_deg)
+        .collect();
+    if let Some(errors) = ErrorSummary::new(&heading_errors_deg) {
+        metric(
+            "heading_error_rms_degrees",
+            "gauge",
+            "RMS heading error over every frame with an estimate so far.",
+            &sample(errors.rms),
+        );
+    }
+    if let Some(&(_, heading_error_deg)) = progress.heading_errors_deg.last() {
+        metric(
+            "heading_error_degrees",
+            "gauge",
+            "Signed heading error of the last frame with an estimate.",
+            &sample(heading_error_deg),
+        );
+    }
+    metric(
+        "run_finished",
+        "gauge",
+        "Whether the run has finished.",
+        &sample(f64::from(u8::from(progress.finished))),
+    );
+    text
+}
+
+/// Escapes a label value as the text format requires.
+fn escape(value: &str) -> String {
+    value
+        .replace('\\', r"\\")
+        .replace('"', "\\\"")
+        .replace('\n', r"\n")
+}
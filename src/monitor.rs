@@ -0,0 +1,74 @@
+use crate::run::SkipReason;
+use image::RgbaImage;
+use std::{
+    collections::BTreeMap,
+    sync::{Arc, Mutex},
+    time::Instant,
+};
+
+/// Progress of a run so far, as reported while it is still running.
+#[derive(Debug, Clone, Default, serde::Serialize)]
+pub struct RunProgress {
+    pub experiment: String,
+    pub started: String,
+    /// Frames the run is expected to reach, once the pipeline has counted them.
+    pub frames_total: Option<usize>,
+    pub frames_processed: usize,
+    pub frames_skipped: BTreeMap<SkipReason, usize>,
+    pub last_frame_index: Option<usize>,
+    /// Yaw candidates scored over every frame so far.
+    pub candidates_evaluated: usize,
+    /// Cost of the best candidate of the last frame with an estimate.
+    pub last_weighted_rmse: Option<f64>,
+    /// Frame index and signed heading error of every frame with an estimate.
+    pub heading_errors_deg: Vec<(usize, f64)>,
+    /// Yaw offset and cost of every candidate of the last frame with a result.
+    pub cost_curve: Vec<(f64, f64)>,
+    pub finished: bool,
+}
+
+/// Handle on the progress of a run, shared by the pipeline, the experiment and whatever
+/// serves it while the run goes on.
+#[derive(Debug, Clone)]
+pub struct RunMonitor {
+    created: Instant,
+    progress: Arc<Mutex<RunProgress>>,
+    /// Diagnostic image of the last frame with a result.
+    image: Arc<Mutex<Option<RgbaImage>>>,
+}
+
+impl RunMonitor {
+    pub fn new(experiment: &str, started: &str) -> Self {
+        let progress = RunProgress {
+            experiment: experiment.to_string(),
+            started: started.to_string(),
+            ..RunProgress::default()
+        };
+        Self {
+            created: Instant::now(),
+            progress: Arc::new(Mutex::new(progress)),
+            image: Arc::default(),
+        }
+    }
+
+    /// Seconds since the monitor was created, which is about when the run started.
+    pub fn elapsed_s(&self) -> f64 {
+        self.created.elapsed().as_secs_f64()
+    }
+
+    pub fn update(&self, f: impl FnOnce(&mut RunProgress)) {
+        f(&mut self.progress.lock().unwrap());
+    }
+
+    pub fn progress(&self) -> RunProgress {
+        self.progress.lock().unwrap().clone()
+    }
+
+    pub fn set_image(&self, image: RgbaImage) {
+        *self.image.lock().unwrap() = Some(image);
+    }
+
+    pub fn image(&self) -> Option<RgbaImage> {
+        self.image.lock().unwrap().clone()
+    }
+}
@@ -0,0 +1,155 @@
+use crate::{
+    io::{InsFrame, TimeFrame},
+    systems::InsEnu,
+};
+use chrono::{DateTime, Duration, Utc};
+use sguaba::{engineering::Orientation, systems::Wgs84};
+use uom::si::{angle::degree, f64::Angle, length::meter};
+
+/// Yaw rate is differenced across this much time on either side of a frame.
+const YAW_RATE_HALF_WINDOW_MS: i64 = 20;
+
+/// How INS states are matched to camera frames.
+#[derive(Debug, Clone, Copy)]
+pub enum FrameAlignment {
+    /// The nth INS record belongs to the nth camera frame.
+    Index,
+    /// The INS state is interpolated to the exposure time, which is the frame time minus the
+    /// camera latency.
+    Interpolate { latency: Duration },
+}
+
+impl FrameAlignment {
+    /// Interpolates to the exposure `latency_ms` before each frame time, to the microsecond.
+    pub fn interpolate_ms(latency_ms: f64) -> Self {
+        #[allow(clippy::cast_possible_truncation)]
+        let latency = Duration::microseconds((latency_ms * 1e3).round() as i64);
+        Self::Interpolate { latency }
+    }
+}
+
+/// Pairs every camera frame with an INS state.
+///
+/// Frames outside of the INS record have no state when interpolating.
+pub fn align_frames<'a>(
+    time_frames: impl Iterator<Item = TimeFrame> + 'a,
+    model: &'a MotionModel,
+    alignment: FrameAlignment,
+) -> Box<dyn Iterator<Item = (TimeFrame, Option<InsFrame>)> + 'a> {
+    match alignment {
+        FrameAlignment::Index => Box::new(time_frames.zip(model.frames.iter().cloned().map(Some))),
+        FrameAlignment::Interpolate { latency } => Box::new(time_frames.map(move |time_frame| {
+            let ins_frame = model.at(time_frame.time - latency);
+            (time_frame, ins_frame)
+        })),
+    }
+}
+
+/// Interpolates the vehicle state between INS records.
+pub struct MotionModel {
+    frames: Vec<InsFrame>,
+    max_extrapolation: Duration,
+}
+
+impl MotionModel {
+    pub fn new(mut frames: Vec<InsFrame>) -> Self {
+        frames.sort_by_key(|frame| frame.time);
+        Self {
+            frames,
+            max_extrapolation: Duration::milliseconds(50),
+        }
+    }
+
+    /// Furthest the state is extrapolated beyond the first or last record.
+    pub fn with_max_extrapolation(mut self, max_extrapolation: Duration) -> Self {
+        self.max_extrapolation = max_extrapolation;
+        self
+    }
+
+    /// Returns the state at a time, extrapolating linearly from the nearest two records near
+    /// the ends.
+    pub fn at(&self, time: DateTime<Utc>) -> Option<InsFrame> {
+        let (first, last) = (self.frames.first()?, self.frames.last()?);
+        if time < first.time - self.max_extrapolation || time > last.time + self.max_extrapolation {
+            return None;
+        }
+
+        if self.frames.len() == 1 {
+            return Some(first.clone());
+        }
+
+        let next = self
+            .frames
+            .partition_point(|frame| frame.time <= time)
+            .clamp(1, self.frames.len() - 1);
+        let (before, after) = (&self.frames[next - 1], &self.frames[next]);
+
+        let span = (after.time - before.time).as_seconds_f64();
+        if span <= 0. {
+            return Some(before.clone());
+        }
+        let t = (time - before.time).as_seconds_f64() / span;
+
+        Some(InsFrame {
+            time,
+            position: interpolate_position(&before.position, &after.position, t),
+            orientation: interpolate_orientation(before.orientation, after.orientation, t),
+            covariance: before
+                .covariance
+                .zip(after.covariance)
+                .map(|(before, after)| before.interpolate(&after, t)),
+            // A state is only as good as the worse of the records it comes from.
+            status: match before.status {
+                Some(status) if status.is_good() => after.status,
+                status => status.or(after.status),
+            },
+        })
+    }
+}
+
+impl MotionModel {
+    /// Rate of change of yaw in degrees per second, counter-clockwise from above.
+    pub fn yaw_rate(&self, time: DateTime<Utc>) -> Option<f64> {
+        let half_window = Duration::milliseconds(YAW_RATE_HALF_WINDOW_MS);
+        let (before, after) = (self.at(time - half_window)?, self.at(time + half_window)?);
+
+        let (before_yaw, ..) = before.orientation.to_tait_bryan_angles();
+        let (after_yaw, ..) = after.orientation.to_tait_bryan_angles();
+        let delta = ((after_yaw - before_yaw).get::<degree>() + 180.).rem_euclid(360.) - 180.;
+
+        let span = (after.time - before.time).as_seconds_f64();
+        (span > 0.).then(|| delta / span)
+    }
+}
+
+fn interpolate_position(a: &Wgs84, b: &Wgs84, t: f64) -> Wgs84 {
+    let lerp = |a: f64, b: f64| a + (b - a) * t;
+    InsEnu::position_from_inspva(
+        lerp(a.latitude().get::<degree>(), b.latitude().get::<degree>()),
+        lerp(a.longitude().get::<degree>(), b.longitude().get::<degree>()),
+        lerp(a.altitude().get::<meter>(), b.altitude().get::<meter>()),
+    )
+}
+
+/// Interpolates each Tait-Bryan angle along its shortest path.
+///
+/// INS records are close enough in time that this matches spherical interpolation.
+fn interpolate_orientation(
+    a: Orientation<InsEnu>,
+    b: Orientation<InsEnu>,
+    t: f64,
+) -> Orientation<InsEnu> {
+    let lerp = |a: Angle, b: Angle| {
+        let (a, b) = (a.get::<degree>(), b.get::<degree>());
+        let delta = (b - a + 180.).rem_euclid(360.) - 180.;
+        Angle::new::<degree>(a + delta * t)
+    };
+
+    let (a_yaw, a_pitch, a_roll) = a.to_tait_bryan_angles();
+    let (b_yaw, b_pitch, b_roll) = b.to_tait_bryan_angles();
+    Orientation::tait_bryan_builder()
+        .yaw(lerp(a_yaw, b_yaw))
+        .pitch(lerp(a_pitch, b_pitch))
+        .roll(lerp(a_roll, b_roll))
+        .build()
+}
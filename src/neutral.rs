@@ -0,0 +1,116 @@
+use rumpus::image::RayImage;
+use std::f64::consts::PI;
+use uom::si::{angle::radian, f64::Angle};
+
+/// Neighbouring cells in order around the centre, used to measure the AoP winding.
+const RING: [(isize, isize); 8] = [
+    (-1, -1),
+    (-1, 0),
+    (-1, 1),
+    (0, 1),
+    (1, 1),
+    (1, 0),
+    (1, -1),
+    (0, -1),
+];
+
+/// A point where the sky is unpolarized and the AoP turns around it, such as the Babinet and
+/// Brewster points.
+#[derive(Debug, Clone, Copy, serde::Serialize)]
+pub struct NeutralPoint {
+    pub row: usize,
+    pub col: usize,
+    pub dop: f64,
+    /// Number of half turns the AoP makes on a loop around the point, with its sign.
+    pub winding: i32,
+}
+
+impl NeutralPoint {
+    #[allow(clippy::cast_precision_loss)]
+    pub fn distance(&self, other: &Self) -> f64 {
+        (self.row as f64 - other.row as f64).hypot(self.col as f64 - other.col as f64)
+    }
+}
+
+/// Finds DoP minima that are AoP singularities.
+///
+/// The image is averaged over `block` by `block` cells to suppress noise before searching, and
+/// minima with a DoP above `max_dop` are ignored. Points are returned from the least polarized.
+#[allow(clippy::cast_precision_loss, clippy::cast_possible_truncation)]
+pub fn find_neutral_points<F: Copy>(
+    ray_image: &RayImage<F>,
+    block: usize,
+    max_dop: f64,
+) -> Vec<NeutralPoint> {
+    let rows = ray_image.rows() / block;
+    let cols = ray_image.cols() / block;
+
+    // Average the Stokes parameters of each cell, since AoP wraps around.
+    let cells: Vec<Option<(f64, f64)>> = (0..rows * cols)
+        .map(|cell| {
+            let (cell_row, cell_col) = (cell / cols, cell % cols);
+            let (mut q, mut u, mut count) = (0., 0., 0.);
+            for row in cell_row * block..(cell_row + 1) * block {
+                for col in cell_col * block..(cell_col + 1) * block {
+                    let Some(ray) = ray_image.ray(row, col) else {
+                        continue;
+                    };
+                    let aop = Angle::from(ray.aop()).get::<radian>();
+                    q += ray.dop() * (2. * aop).cos();
+                    u += ray.dop() * (2. * aop).sin();
+                    count += 1.;
+                }
+            }
+            (count > 0.).then(|| (q / count, u / count))
+        })
+        .collect();
+    let cell = |row: usize, col: usize, (dr, dc): (isize, isize)| {
+        cells[row.checked_add_signed(dr)? * cols + col.checked_add_signed(dc)?]
+    };
+
+    let mut points = Vec::new();
+    for row in 1..rows.saturating_sub(1) {
+        for col in 1..cols.saturating_sub(1) {
+            let Some((q, u)) = cells[row * cols + col] else {
+                continue;
+            };
+            let dop = q.hypot(u);
+            if dop > max_dop {
+                continue;
+            }
+
+            let Some(ring) = RING
+                .iter()
+                .map(|&offset| cell(row, col, offset))
+                .collect::<Option<Vec<_>>>()
+            else {
+                continue;
+            };
+            if ring.iter().any(|(q, u)| q.hypot(*u) < dop) {
+                continue;
+            }
+
+            // Unwrap the doubled AoP around the loop; a full turn of it is a half turn of AoP.
+            let angles: Vec<f64> = ring.iter().map(|(q, u)| u.atan2(*q)).collect();
+            let total: f64 = angles
+                .iter()
+                .zip(angles.iter().cycle().skip(1))
+                .map(|(a, b)| (b - a + PI).rem_euclid(2. * PI) - PI)
+                .sum();
+            let winding = (total / (2. * PI)).round() as i32;
+            if winding == 0 {
+                continue;
+            }
+
+            points.push(NeutralPoint {
+                row: row * block + block / 2,
+                col: col * block + block / 2,
+                dop,
+                winding,
+            });
+        }
+    }
+
+    points.sort_by(|a, b| a.dop.total_cmp(&b.dop));
+    points
+}
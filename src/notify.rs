@@ -0,0 +1,216 @@
+use crate::{error::BenchError, run::RunSummary};
+#[cfg(feature = "webhooks")]
+use std::time::Duration;
+use std::{
+    io::Write,
+    path::{Path, PathBuf},
+    process::{Command, Stdio},
+    sync::{
+        Arc,
+        atomic::{AtomicBool, Ordering},
+    },
+};
+
+/// How long a webhook may take to answer before it is given up on.
+#[cfg(feature = "webhooks")]
+const WEBHOOK_TIMEOUT: Duration = Duration::from_secs(30);
+
+/// How a run ended.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, serde::Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum RunOutcome {
+    Finished,
+    Interrupted,
+    Failed,
+}
+
+/// What completion hooks are told about a run, as JSON.
+#[derive(Debug, Clone, serde::Serialize)]
+pub struct CompletionReport {
+    /// One line for chat webhooks, which show the `text` field.
+    pub text: String,
+    pub experiment: String,
+    pub started: String,
+    pub outcome: RunOutcome,
+    pub results_dir: PathBuf,
+    pub summary: Option<serde_json::Value>,
+    /// Panic message of a failed run.
+    pub error: Option<String>,
+}
+
+impl CompletionReport {
+    /// Report of a run that has not ended yet, to fill in once it does.
+    pub fn new(experiment: &str, started: &str, results_dir: &Path) -> Self {
+        Self {
+            text: String::new(),
+            experiment: experiment.to_string(),
+            started: started.to_string(),
+            outcome: RunOutcome::Failed,
+            results_dir: results_dir.to_path_buf(),
+            summary: None,
+            error: None,
+        }
+    }
+
+    pub fn with_summary(mut self, summary: &RunSummary) -> Self {
+        self.outcome = if summary.interrupted {
+            RunOutcome::Interrupted
+        } else {
+            RunOutcome::Finished
+        };
+        self.text = format!(
+            "{} {}: processed {} frames, skipped {}",
+            self.title(),
+            if summary.interrupted {
+                "was interrupted"
+            } else {
+                "finished"
+            },
+            summary.frames_processed,
+            summary.frames_skipped()
+        );
+        self.summary = serde_json::to_value(summary).ok();
+        self
+    }
+
+    pub fn with_error(mut self, error: impl ToString) -> Self {
+        let error = error.to_string();
+        self.outcome = RunOutcome::Failed;
+        self.text = format!(
+            "{} failed: {}",
+            self.title(),
+            error.lines().last().unwrap_or_default()
+        );
+        self.error = Some(error);
+        self
+    }
+
+    fn title(&self) -> String {
+        format!("{} run {}", self.experiment, self.started)
+    }
+}
+
+/// Shell commands and webhooks told about the end of a run.
+#[derive(Debug, Clone, Default)]
+pub struct CompletionHooks {
+    /// Run with `sh -c`, with the report on standard input.
+    commands: Vec<String>,
+    /// Sent the report as the body of a POST request.
+    webhooks: Vec<String>,
+    /// Set once the run has been reported, shared by clones so a later panic does not report
+    /// it again.
+    reported: Arc<AtomicBool>,
+}
+
+impl CompletionHooks {
+    /// Checks up front that webhooks can be sent, rather than finding out when the run ends.
+    pub fn new(commands: Vec<String>, webhooks: Vec<String>) -> Result<Self, BenchError> {
+        if !webhooks.is_empty() && !cfg!(feature = "webhooks") {
+            return Err(missing_webhooks());
+        }
+        Ok(Self {
+            commands,
+            webhooks,
+            reported: Arc::default(),
+        })
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.commands.is_empty() && self.webhooks.is_empty()
+    }
+
+    /// Runs every hook with the report, once for these hooks and their clones.
+    ///
+    /// Failures are logged rather than returned, so a broken hook neither hides the others nor
+    /// fails a run that is otherwise done.
+    pub fn notify(&self, report: &CompletionReport) {
+        if self.is_empty() || self.reported.swap(true, Ordering::SeqCst) {
+            return;
+        }
+        let payload = match serde_json::to_vec(report) {
+            Ok(payload) => payload,
+            Err(e) => {
+                eprintln!("cannot encode the completion report: {e}");
+                return;
+            }
+        };
+        for command in &self.commands {
+            if let Err(e) = run_command(command, report, &payload) {
+                eprintln!("completion hook failed: {e}");
+            }
+        }
+        for url in &self.webhooks {
+            if let Err(e) = post_webhook(url, &payload) {
+                eprintln!("completion webhook failed: {e}");
+            }
+        }
+    }
+
+    /// Reports a panic anywhere in the run as a failure, after the usual panic message.
+    pub fn notify_on_panic(self, report: CompletionReport) {
+        if self.is_empty() {
+            return;
+        }
+        let default_hook = std::panic::take_hook();
+        std::panic::set_hook(Box::new(move |info| {
+            default_hook(info);
+            self.notify(&report.clone().with_error(info));
+        }));
+    }
+}
+
+/// Runs a command with the report on its standard input and the outcome and results directory
+/// in `RUMPUS_RUN_OUTCOME` and `RUMPUS_RESULTS_DIR`.
+fn run_command(command: &str, report: &CompletionReport, payload: &[u8]) -> Result<(), BenchError> {
+    let output_error = |e: std::io::Error| BenchError::output(command, e);
+    let outcome = serde_json::to_value(report.outcome)
+        .ok()
+        .and_then(|outcome| outcome.as_str().map(str::to_string))
+        .unwrap_or_default();
+    let mut child = Command::new("sh")
+        .arg("-c")
+        .arg(command)
+        .env("RUMPUS_RUN_OUTCOME", outcome)
+        .env("RUMPUS_RESULTS_DIR", &report.results_dir)
+        .stdin(Stdio::piped())
+        .spawn()
+        .map_err(output_error)?;
+    // A command that ignores its input may close it early, which is fine.
+    if let Some(mut stdin) = child.stdin.take() {
+        let _ = stdin.write_all(payload);
+    }
+    let status = child.wait().map_err(output_error)?;
+    if !status.success() {
+        return Err(BenchError::output(
+            command,
+            std::io::Error::other(format!("exited with {status}")),
+        ));
+    }
+    Ok(())
+}
+
+#[cfg(feature = "webhooks")]
+fn post_webhook(url: &str, payload: &[u8]) -> Result<(), BenchError> {
+    let agent: ureq::Agent = ureq::Agent::config_builder()
+        .timeout_global(Some(WEBHOOK_TIMEOUT))
+        .build()
+        .into();
+    agent
+        .post(url)
+        .header("Content-Type", "application/json")
+        .send(payload)
+        .map_err(|e| BenchError::output(url, e))?;
+    Ok(())
+}
+
+#[cfg(not(feature = "webhooks"))]
+fn post_webhook(_url: &str, _payload: &[u8]) -> Result<(), BenchError> {
+    Err(missing_webhooks())
+}
+
+fn missing_webhooks() -> BenchError {
+    BenchError::Config(
+        "completion webhooks need the `webhooks` feature; rebuild with `--features webhooks`"
+            .to_string(),
+    )
+}
@@ -0,0 +1,192 @@
+use crate::{
+    error::BenchError,
+    smoothing::wrap_deg,
+    stats::{mean, median},
+};
+use chrono::{DateTime, Utc};
+use std::{collections::BTreeMap, fmt::Display, str::FromStr};
+
+/// Seconds from the first camera frame of a dataset during which GNSS is denied, such as
+/// `120..180`.
+#[derive(Debug, Clone, Copy, PartialEq, serde::Serialize)]
+pub struct OutageWindow {
+    pub start_s: f64,
+    pub end_s: f64,
+}
+
+impl FromStr for OutageWindow {
+    type Err = BenchError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let invalid = || BenchError::Config(format!("outage {s:?} is not like 120..180"));
+        let (start, end) = s.split_once("..").ok_or_else(invalid)?;
+        let parse = |bound: &str| bound.trim().parse::<f64>().map_err(|_| invalid());
+        let (start_s, end_s) = (parse(start)?, parse(end)?);
+        if start_s < end_s {
+            Ok(Self { start_s, end_s })
+        } else {
+            Err(invalid())
+        }
+    }
+}
+
+impl Display for OutageWindow {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}..{}", self.start_s, self.end_s)
+    }
+}
+
+/// Where a frame falls relative to the simulated GNSS outages.
+#[derive(Debug, Default, Clone, Copy, PartialEq)]
+pub enum OutagePhase {
+    /// Before the first outage, when the INS is trusted.
+    #[default]
+    Nominal,
+    /// Inside an outage, this long after it began.
+    Denied { elapsed_s: f64 },
+    /// After an outage, this long after it ended.
+    Recovering { elapsed_s: f64 },
+}
+
+impl OutagePhase {
+    /// Where a frame this many seconds into the dataset falls, given outages in any order.
+    pub fn at(outages: &[OutageWindow], seconds: f64) -> Self {
+        if let Some(outage) = outages
+            .iter()
+            .find(|outage| (outage.start_s..outage.end_s).contains(&seconds))
+        {
+            return Self::Denied {
+                elapsed_s: seconds - outage.start_s,
+            };
+        }
+        outages
+            .iter()
+            .map(|outage| seconds - outage.end_s)
+            .filter(|&since_s| since_s >= 0.)
+            .min_by(f64::total_cmp)
+            .map_or(Self::Nominal, |elapsed_s| Self::Recovering { elapsed_s })
+    }
+
+    pub fn is_denied(self) -> bool {
+        matches!(self, Self::Denied { .. })
+    }
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Self::Nominal => "nominal",
+            Self::Denied { .. } => "denied",
+            Self::Recovering { .. } => "recovering",
+        }
+    }
+
+    pub fn elapsed_s(self) -> Option<f64> {
+        match self {
+            Self::Nominal => None,
+            Self::Denied { elapsed_s } | Self::Recovering { elapsed_s } => Some(elapsed_s),
+        }
+    }
+}
+
+/// The heading a navigation system coasts on without GNSS: the last known yaw, integrated
+/// along the gyro rate and reset by every polarization estimate.
+#[derive(Debug, Clone, Copy)]
+pub struct HeadingCoast {
+    yaw_deg: f64,
+    time: DateTime<Utc>,
+}
+
+impl HeadingCoast {
+    pub fn new(yaw_deg: f64, time: DateTime<Utc>) -> Self {
+        Self { yaw_deg, time }
+    }
+
+    /// Integrates the yaw rate up to `time` and returns the coasted yaw.
+    pub fn propagate(&mut self, time: DateTime<Utc>, yaw_rate_deg_s: f64) -> f64 {
+        let dt_s = (time - self.time).as_seconds_f64();
+        self.yaw_deg = wrap_deg(self.yaw_deg + yaw_rate_deg_s * dt_s);
+        self.time = time;
+        self.yaw_deg
+    }
+
+    /// Replaces the coasted yaw with a fix.
+    pub fn update(&mut self, yaw_deg: f64, time: DateTime<Utc>) {
+        *self = Self::new(yaw_deg, time);
+    }
+}
+
+/// Heading error in one span of time into or after the outages.
+#[derive(Debug, Clone, Copy, serde::Serialize)]
+pub struct OutageErrorSummary {
+    pub phase: &'static str,
+    pub from_s: f64,
+    pub to_s: f64,
+    pub frames: usize,
+    pub mean_abs: f64,
+    pub median_abs: f64,
+    pub rms: f64,
+    pub max_abs: f64,
+}
+
+/// Collects heading errors by how long into or after an outage they occurred, showing how
+/// quickly the error grows without GNSS and how quickly it settles once it is back.
+#[derive(Debug, Clone)]
+pub struct OutageErrors {
+    bin_s: f64,
+    /// Errors keyed by the phase and bin index.
+    bins: BTreeMap<(&'static str, i64), Vec<f64>>,
+}
+
+impl OutageErrors {
+    pub fn new(bin_s: f64) -> Self {
+        Self {
+            bin_s,
+            bins: BTreeMap::new(),
+        }
+    }
+
+    #[allow(clippy::cast_possible_truncation)]
+    pub fn add(&mut self, phase: OutagePhase, error_deg: f64) {
+        let Some(elapsed_s) = phase.elapsed_s() else {
+            return;
+        };
+        let bin = (elapsed_s / self.bin_s).floor() as i64;
+        self.bins
+            .entry((phase.name(), bin))
+            .or_default()
+            .push(error_deg);
+    }
+
+    /// Denied bins come before recovering ones, each in order of elapsed time.
+    #[allow(clippy::cast_precision_loss)]
+    pub fn summaries(&self) -> Vec<OutageErrorSummary> {
+        let mut summaries: Vec<_> = self
+            .bins
+            .iter()
+            .filter_map(|(&(phase, bin), errors)| {
+                let magnitudes: Vec<f64> = errors.iter().map(|error| error.abs()).collect();
+                let squares: Vec<f64> = errors.iter().map(|error| error * error).collect();
+                Some(OutageErrorSummary {
+                    phase,
+                    from_s: bin as f64 * self.bin_s,
+                    to_s: (bin + 1) as f64 * self.bin_s,
+                    frames: errors.len(),
+                    mean_abs: mean(&magnitudes)?,
+                    median_abs: median(&magnitudes)?,
+                    rms: mean(&squares)?.sqrt(),
+                    max_abs: magnitudes.iter().copied().fold(0., f64::max),
+                })
+            })
+            .collect();
+        summaries.sort_by_key(|summary| summary.phase != "denied");
+        summaries
+    }
+}
+
+impl OutageErrorSummary {
+    pub fn print(&self) {
+        println!(
+            "{} {:.0}..{:.0} s: {} frames, mean |e| {:.3} deg, rms {:.3} deg, max |e| {:.3} deg",
+            self.phase, self.from_s, self.to_s, self.frames, self.mean_abs, self.rms, self.max_abs
+        );
+    }
+}
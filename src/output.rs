@@ -0,0 +1,84 @@
+use crate::error::BenchError;
+use chrono::{DateTime, Utc};
+use serde::Serialize;
+use std::{
+    fmt::Display,
+    net::{SocketAddr, ToSocketAddrs, UdpSocket},
+};
+
+/// Wire format of heading datagrams.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, clap::ValueEnum, serde::Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum OutputFormat {
+    /// NMEA 0183 `HDT` sentence with the true heading.
+    #[default]
+    Nmea,
+    /// One JSON object per datagram.
+    Json,
+}
+
+/// Heading estimate of a single frame, as sent downstream.
+#[derive(Debug, Clone, Copy, Serialize)]
+pub struct HeadingMessage {
+    pub frame_index: usize,
+    pub time: DateTime<Utc>,
+    /// Clockwise from true north, from 0 up to 360.
+    pub heading_deg: f64,
+    pub confidence: f64,
+}
+
+impl HeadingMessage {
+    /// Formats the heading as a `$HEHDT` sentence terminated by CRLF.
+    pub fn to_nmea(&self) -> String {
+        let body = format!("HEHDT,{:.2},T", self.heading_deg);
+        let checksum = body.bytes().fold(0, |checksum, byte| checksum ^ byte);
+        format!("${body}*{checksum:02X}\r\n")
+    }
+}
+
+/// Sends every heading estimate as a UDP datagram.
+pub struct UdpSink {
+    socket: UdpSocket,
+    addr: SocketAddr,
+    format: OutputFormat,
+}
+
+impl UdpSink {
+    pub fn new<A: ToSocketAddrs + Display>(
+        addr: A,
+        format: OutputFormat,
+    ) -> Result<Self, BenchError> {
+        let output_error = |e: std::io::Error| BenchError::output(&addr, e);
+        let resolved = addr
+            .to_socket_addrs()
+            .map_err(output_error)?
+            .next()
+            .ok_or_else(|| BenchError::Config(format!("{addr} does not resolve to an address")))?;
+        let bind_addr = if resolved.is_ipv4() {
+            "0.0.0.0:0"
+        } else {
+            "[::]:0"
+        };
+        let socket = UdpSocket::bind(bind_addr).map_err(output_error)?;
+        socket.set_broadcast(true).map_err(output_error)?;
+
+        Ok(Self {
+            socket,
+            addr: resolved,
+            format,
+        })
+    }
+
+    pub fn send(&self, message: &HeadingMessage) -> Result<(), BenchError> {
+        let payload = match self.format {
+            OutputFormat::Nmea => message.to_nmea().into_bytes(),
+            OutputFormat::Json => {
+                serde_json::to_vec(message).map_err(|e| BenchError::output(self.addr, e))?
+            }
+        };
+        self.socket
+            .send_to(&payload, self.addr)
+            .map_err(|e| BenchError::output(self.addr, e))?;
+        Ok(())
+    }
+}
@@ -0,0 +1,244 @@
+use crate::{
+    camera::CameraModel, ephemeris::CelestialPosition, error::BenchError, export::ImageFormat,
+    sky::source_pixel, systems::InsEnu,
+};
+use image::{Rgba, RgbaImage};
+use sguaba::engineering::Orientation;
+use std::path::Path;
+use uom::si::{angle::degree, f64::Angle};
+
+/// Elevation the tip of a heading arrow points at, close enough to the zenith to stay in view.
+const HEADING_ARROW_ELEVATION_DEG: f64 = 75.;
+/// Length of the barbs of an arrow head in pixels.
+const ARROW_HEAD_PX: f64 = 12.;
+
+pub const MARKER_RGBA: [u8; 4] = [255, 255, 255, 255];
+pub const SUN_RGBA: [u8; 4] = [255, 220, 0, 255];
+pub const TRUE_HEADING_RGBA: [u8; 4] = [255, 255, 255, 255];
+pub const ESTIMATED_HEADING_RGBA: [u8; 4] = [255, 0, 255, 255];
+
+/// An RGBA image that diagnostics are drawn onto.
+#[derive(Debug, Clone)]
+pub struct Overlay {
+    image: RgbaImage,
+}
+
+impl Overlay {
+    /// Opaque copy of row-major RGB bytes.
+    pub fn from_rgb(rgb: &[u8], cols: u32, rows: u32) -> Self {
+        let rgba = rgb
+            .chunks_exact(3)
+            .flat_map(|rgb| [rgb[0], rgb[1], rgb[2], u8::MAX])
+            .collect();
+        Self::from_rgba(rgba, cols, rows)
+    }
+
+    /// AoP colours with the DoP as alpha, so weakly polarized sky fades out.
+    ///
+    /// `dop` holds one byte per pixel, in the same order as `aop_rgb`.
+    pub fn from_aop_dop(aop_rgb: &[u8], dop: &[u8], cols: u32, rows: u32) -> Self {
+        let mut overlay = Self::from_rgb(aop_rgb, cols, rows);
+        overlay.set_alpha(dop);
+        overlay
+    }
+
+    fn from_rgba(rgba: Vec<u8>, cols: u32, rows: u32) -> Self {
+        Self {
+            image: RgbaImage::from_raw(cols, rows, rgba).expect("one colour per pixel"),
+        }
+    }
+
+    /// Replaces the alpha of every pixel, given one byte per pixel in row-major order.
+    pub fn set_alpha(&mut self, alpha: &[u8]) {
+        for (pixel, &alpha) in self.image.pixels_mut().zip(alpha) {
+            pixel[3] = alpha;
+        }
+    }
+
+    pub fn image(&self) -> &RgbaImage {
+        &self.image
+    }
+
+    /// Resized by `scale` on both sides, once everything is drawn, such as to write smaller
+    /// diagnostic images.
+    #[allow(
+        clippy::cast_possible_truncation,
+        clippy::cast_sign_loss,
+        clippy::float_cmp
+    )]
+    pub fn scaled(self, scale: f64) -> Self {
+        if scale == 1. {
+            return self;
+        }
+        let size = |pixels: u32| ((f64::from(pixels) * scale).round() as u32).max(1);
+        Self {
+            image: image::imageops::resize(
+                &self.image,
+                size(self.image.width()),
+                size(self.image.height()),
+                image::imageops::FilterType::Triangle,
+            ),
+        }
+    }
+
+    /// Blends a colour over the pixels where a row-major mask is set, such as invalid pixels.
+    pub fn mask(&mut self, mask: &[bool], rgba: [u8; 4]) {
+        for (pixel, _) in self
+            .image
+            .pixels_mut()
+            .zip(mask)
+            .filter(|(_, masked)| **masked)
+        {
+            *pixel = blend(*pixel, rgba);
+        }
+    }
+
+    /// Sets a pixel if it is inside the image.
+    #[allow(clippy::cast_possible_truncation, clippy::cast_sign_loss)]
+    pub fn put(&mut self, row: f64, col: f64, rgba: [u8; 4]) {
+        let (row, col) = (row.round(), col.round());
+        if row < 0. || col < 0. {
+            return;
+        }
+        let (row, col) = (row as u32, col as u32);
+        if row < self.image.height() && col < self.image.width() {
+            self.image.put_pixel(col, row, Rgba(rgba));
+        }
+    }
+
+    /// Draws a cross centred on a pixel.
+    #[allow(clippy::cast_precision_loss)]
+    pub fn draw_cross(&mut self, (row, col): (usize, usize), arm: usize, rgba: [u8; 4]) {
+        let (row, col, arm) = (row as f64, col as f64, arm as f64);
+        self.draw_line((row - arm, col), (row + arm, col), rgba);
+        self.draw_line((row, col - arm), (row, col + arm), rgba);
+    }
+
+    /// Draws a ring around a pixel, such as the sun.
+    #[allow(clippy::cast_precision_loss)]
+    pub fn draw_ring(&mut self, (row, col): (usize, usize), radius: usize, rgba: [u8; 4]) {
+        let (row, col, radius) = (row as f64, col as f64, radius as f64);
+        // Enough steps that neighbouring points of the ring touch.
+        let steps = (2. * std::f64::consts::PI * radius).ceil().max(8.);
+        for step in 0..steps as usize {
+            let theta = 2. * std::f64::consts::PI * step as f64 / steps;
+            self.put(row + radius * theta.sin(), col + radius * theta.cos(), rgba);
+        }
+    }
+
+    /// Draws a straight line between two points given as (row, col), clipped to the image.
+    #[allow(clippy::cast_precision_loss, clippy::cast_possible_truncation)]
+    pub fn draw_line(&mut self, from: (f64, f64), to: (f64, f64), rgba: [u8; 4]) {
+        let (d_row, d_col) = (to.0 - from.0, to.1 - from.1);
+        let steps = d_row.abs().max(d_col.abs()).ceil().max(1.) as usize;
+        for step in 0..=steps {
+            let t = step as f64 / steps as f64;
+            self.put(from.0 + t * d_row, from.1 + t * d_col, rgba);
+        }
+    }
+
+    /// Draws an arrow from one point to another, with its head at the second.
+    pub fn draw_arrow(&mut self, from: (f64, f64), to: (f64, f64), rgba: [u8; 4]) {
+        self.draw_line(from, to, rgba);
+        let direction = (to.0 - from.0).atan2(to.1 - from.1);
+        for barb in [-0.85, 0.85] {
+            let angle = direction + std::f64::consts::PI + barb;
+            let tip = (
+                to.0 + ARROW_HEAD_PX * angle.sin(),
+                to.1 + ARROW_HEAD_PX * angle.cos(),
+            );
+            self.draw_line(to, tip, rgba);
+        }
+    }
+
+    /// Draws an arrow from the zenith towards a compass heading, if the zenith is in view.
+    ///
+    /// The arrow points at where the heading meets the sky at a high elevation, so it follows
+    /// the projection of the camera rather than a fixed image direction.
+    pub fn draw_heading(
+        &mut self,
+        camera: &CameraModel,
+        car_in_ins_enu: Orientation<InsEnu>,
+        heading_deg: f64,
+        rgba: [u8; 4],
+    ) {
+        let zenith = CelestialPosition {
+            azimuth: Angle::new::<degree>(heading_deg),
+            elevation: Angle::new::<degree>(90.),
+        };
+        let tip = CelestialPosition {
+            elevation: Angle::new::<degree>(HEADING_ARROW_ELEVATION_DEG),
+            ..zenith
+        };
+        if let (Some(from), Some(to)) = (
+            source_pixel(camera, car_in_ins_enu, &zenith),
+            source_pixel(camera, car_in_ins_enu, &tip),
+        ) {
+            self.draw_arrow(as_point(from), as_point(to), rgba);
+        }
+    }
+
+    /// Draws the true heading, and the estimated one if there is an estimate.
+    pub fn draw_heading_comparison(
+        &mut self,
+        camera: &CameraModel,
+        car_in_ins_enu: Orientation<InsEnu>,
+        true_heading_deg: f64,
+        estimated_heading_deg: Option<f64>,
+    ) {
+        self.draw_heading(camera, car_in_ins_enu, true_heading_deg, TRUE_HEADING_RGBA);
+        if let Some(estimated_heading_deg) = estimated_heading_deg {
+            self.draw_heading(
+                camera,
+                car_in_ins_enu,
+                estimated_heading_deg,
+                ESTIMATED_HEADING_RGBA,
+            );
+        }
+    }
+
+    /// Saves the overlay with its alpha channel, or as opaque RGB.
+    pub fn save(
+        &self,
+        dir: &Path,
+        stem: &str,
+        format: ImageFormat,
+        alpha: bool,
+    ) -> Result<(), BenchError> {
+        let size = (self.image.width(), self.image.height());
+        if alpha {
+            format.save(
+                dir,
+                stem,
+                self.image.as_raw(),
+                size,
+                image::ExtendedColorType::Rgba8,
+            )
+        } else {
+            let rgb: Vec<u8> = self
+                .image
+                .pixels()
+                .flat_map(|Rgba([r, g, b, _])| [*r, *g, *b])
+                .collect();
+            format.save(dir, stem, &rgb, size, image::ExtendedColorType::Rgb8)
+        }
+    }
+}
+
+/// Alpha-composites a colour over a pixel.
+#[allow(clippy::cast_possible_truncation)]
+fn blend(Rgba(below): Rgba<u8>, above: [u8; 4]) -> Rgba<u8> {
+    let alpha = u16::from(above[3]);
+    let mix = |a: u8, b: u8| ((u16::from(a) * alpha + u16::from(b) * (255 - alpha)) / 255) as u8;
+    Rgba([
+        mix(above[0], below[0]),
+        mix(above[1], below[1]),
+        mix(above[2], below[2]),
+        below[3].max(above[3]),
+    ])
+}
+
+#[allow(clippy::cast_precision_loss)]
+fn as_point((row, col): (usize, usize)) -> (f64, f64) {
+    (row as f64, col as f64)
+}
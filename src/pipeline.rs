@@ -0,0 +1,701 @@
+use crate::{
+    error::BenchError,
+    io::{InsFrame, InsLog, InsReader, TimeFrame, TimeReader},
+    magnetic::MagneticModel,
+    monitor::RunMonitor,
+    motion::{FrameAlignment, MotionModel, align_frames},
+    outage::{OutagePhase, OutageWindow},
+    run::{RunSummary, SkipReason, peak_memory_mib},
+};
+use chrono::{DateTime, Local, Utc};
+use sguaba::engineering::Orientation;
+use std::{
+    collections::BTreeSet,
+    fmt::Display,
+    fs::File,
+    path::{Path, PathBuf},
+    str::FromStr,
+    sync::{
+        Arc,
+        atomic::{AtomicBool, Ordering},
+    },
+    time::Instant,
+};
+use uom::{
+    ConstZero,
+    si::{angle::degree, f64::Angle},
+};
+
+/// Layout of a dataset exported from a ROS bag.
+#[derive(Debug, Clone)]
+pub struct Dataset {
+    path: PathBuf,
+}
+
+impl Dataset {
+    pub fn new<P: AsRef<Path>>(path: P) -> Self {
+        Self {
+            path: path.as_ref().to_path_buf(),
+        }
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    pub fn image_dir(&self) -> PathBuf {
+        self.path.join("camera_driver_gv_vis_image_raw")
+    }
+
+    pub fn image_path(&self, frame_index: usize) -> PathBuf {
+        self.image_dir().join(format!(
+            "camera_driver_gv_vis_image_raw_{frame_index:04}.png"
+        ))
+    }
+
+    pub fn ins_path(&self) -> PathBuf {
+        self.path
+            .join("novatel_oem7_inspva/novatel_oem7_inspva.csv")
+    }
+
+    pub fn inspvax_path(&self) -> PathBuf {
+        self.path
+            .join("novatel_oem7_inspvax/novatel_oem7_inspvax.csv")
+    }
+
+    pub fn ins_log_path(&self, log: InsLog) -> PathBuf {
+        match log {
+            InsLog::Inspva => self.ins_path(),
+            InsLog::Inspvax => self.inspvax_path(),
+        }
+    }
+
+    pub fn time_path(&self) -> PathBuf {
+        self.path.join("novatel_oem7_time/novatel_oem7_time.csv")
+    }
+
+    /// Wheel odometry, with `time` and `speed_m_s` columns.
+    pub fn odometry_path(&self) -> PathBuf {
+        self.path.join("odometry/odometry.csv")
+    }
+}
+
+/// Everything known about a frame before its image is read.
+#[derive(Clone)]
+pub struct FrameContext {
+    pub frame_index: usize,
+    pub time: DateTime<Utc>,
+    /// INS state at the exposure time, referenced to true north and tilted by the camera
+    /// mounting.
+    pub ins: InsFrame,
+    pub image_path: PathBuf,
+    pub yaw_rate_deg_s: Option<f64>,
+    pub yaw_rate_exceeded: bool,
+    /// Whether the INS pitch or roll is beyond the tilt tolerance, breaking the assumption of a
+    /// levelled camera.
+    pub tilted: bool,
+    /// Yaw swept during the exposure.
+    pub yaw_smear: Angle,
+    /// Whether the INS reported a solution other than `INS_SOLUTION_GOOD`, such as while it
+    /// was still aligning.
+    pub ins_degraded: bool,
+    /// Whether the estimator is meant to be without GNSS at this frame. The INS state is kept
+    /// as ground truth either way.
+    pub outage: OutagePhase,
+}
+
+/// What to do with frames whose INS solution is not good.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, clap::ValueEnum, serde::Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum InsStatusAction {
+    /// Leave them out of the results.
+    #[default]
+    Skip,
+    /// Process them, but flag them and leave them out of the error summary.
+    Flag,
+}
+
+/// Why a processor left a frame out of the results.
+#[derive(Debug)]
+pub struct FrameSkip {
+    pub reason: SkipReason,
+    pub detail: String,
+}
+
+impl FrameSkip {
+    pub fn new(reason: SkipReason, detail: impl Display) -> Self {
+        Self {
+            reason,
+            detail: detail.to_string(),
+        }
+    }
+}
+
+/// One experiment's work on a single frame.
+pub trait FrameProcessor {
+    fn process(&mut self, frame: &FrameContext) -> Result<(), FrameSkip>;
+}
+
+/// Pairs camera frames with INS states and feeds them to a processor.
+pub struct Pipeline {
+    dataset: Dataset,
+    motion_model: MotionModel,
+    time_frames: Vec<TimeFrame>,
+    alignment: FrameAlignment,
+    magnetic_model: Option<MagneticModel>,
+    mount_pitch: Angle,
+    mount_roll: Angle,
+    step: usize,
+    max_frames: Option<usize>,
+    frame_range: Option<FrameRange>,
+    frame_list: Option<BTreeSet<usize>>,
+    max_yaw_rate_deg_s: Option<f64>,
+    max_tilt_deg: Option<f64>,
+    exposure_ms: Option<f64>,
+    ins_status_action: InsStatusAction,
+    outages: Vec<OutageWindow>,
+    monitor: Option<RunMonitor>,
+    interrupted: Arc<AtomicBool>,
+}
+
+impl Pipeline {
+    pub fn new(dataset: Dataset, ins_frames: Vec<InsFrame>, time_frames: Vec<TimeFrame>) -> Self {
+        Self {
+            dataset,
+            motion_model: MotionModel::new(ins_frames),
+            time_frames,
+            alignment: FrameAlignment::Index,
+            magnetic_model: None,
+            mount_pitch: Angle::ZERO,
+            mount_roll: Angle::ZERO,
+            step: 1,
+            max_frames: None,
+            frame_range: None,
+            frame_list: None,
+            max_yaw_rate_deg_s: None,
+            max_tilt_deg: None,
+            exposure_ms: None,
+            ins_status_action: InsStatusAction::default(),
+            outages: Vec::new(),
+            monitor: None,
+            interrupted: Arc::new(AtomicBool::new(false)),
+        }
+    }
+
+    /// Reads the INS and time logs of a dataset.
+    pub fn open(
+        dataset: Dataset,
+        ins_reader: &InsReader,
+        time_reader: &TimeReader,
+    ) -> Result<Self, BenchError> {
+        let ins_frames = ins_reader
+            .read_csv(dataset.ins_log_path(ins_reader.log()))?
+            .collect();
+        let time_frames = time_reader.read_csv(dataset.time_path())?.collect();
+        Ok(Self::new(dataset, ins_frames, time_frames))
+    }
+
+    pub fn with_alignment(mut self, alignment: FrameAlignment) -> Self {
+        self.alignment = alignment;
+        self
+    }
+
+    /// Corrects magnetic INS headings to true north.
+    pub fn with_magnetic_model(mut self, magnetic_model: Option<MagneticModel>) -> Self {
+        self.magnetic_model = magnetic_model;
+        self
+    }
+
+    /// Tilts the attitude of every frame by the pitch and roll of the camera on the car.
+    pub fn with_mounting(mut self, pitch: Angle, roll: Angle) -> Self {
+        self.mount_pitch = pitch;
+        self.mount_roll = roll;
+        self
+    }
+
+    pub fn with_step(mut self, step: usize) -> Self {
+        self.step = step;
+        self
+    }
+
+    /// Stops after this many frames have been processed.
+    pub fn with_max_frames(mut self, max_frames: Option<usize>) -> Self {
+        self.max_frames = max_frames;
+        self
+    }
+
+    /// Only processes frames in this range, before stepping.
+    pub fn with_frame_range(mut self, frame_range: Option<FrameRange>) -> Self {
+        self.frame_range = frame_range;
+        self
+    }
+
+    /// Only processes these frames, before stepping.
+    pub fn with_frame_list(mut self, frame_list: Option<BTreeSet<usize>>) -> Self {
+        self.frame_list = frame_list;
+        self
+    }
+
+    /// Flags frames where the car yaws faster than this.
+    pub fn with_max_yaw_rate(mut self, max_yaw_rate_deg_s: Option<f64>) -> Self {
+        self.max_yaw_rate_deg_s = max_yaw_rate_deg_s;
+        self
+    }
+
+    /// Flags and warns about frames where the INS pitch or roll is larger than this.
+    pub fn with_max_tilt(mut self, max_tilt_deg: Option<f64>) -> Self {
+        self.max_tilt_deg = max_tilt_deg;
+        self
+    }
+
+    /// Whether frames whose INS solution is not good are skipped or flagged.
+    pub fn with_ins_status(mut self, ins_status_action: InsStatusAction) -> Self {
+        self.ins_status_action = ins_status_action;
+        self
+    }
+
+    /// Simulates GNSS outages, in seconds from the first camera frame.
+    pub fn with_outages(mut self, outages: Vec<OutageWindow>) -> Self {
+        self.outages = outages;
+        self
+    }
+
+    /// Exposure used to work out the yaw smear of each frame.
+    pub fn with_exposure(mut self, exposure_ms: Option<f64>) -> Self {
+        self.exposure_ms = exposure_ms;
+        self
+    }
+
+    /// Reports the progress of runs as they go, such as to a dashboard.
+    pub fn with_monitor(mut self, monitor: Option<RunMonitor>) -> Self {
+        self.monitor = monitor;
+        self
+    }
+
+    /// Stops runs once the frame in flight is done after `interrupted` is set, such as by a
+    /// Ctrl-C handler.
+    pub fn with_interrupt(mut self, interrupted: Arc<AtomicBool>) -> Self {
+        self.interrupted = interrupted;
+        self
+    }
+
+    pub fn dataset(&self) -> &Dataset {
+        &self.dataset
+    }
+
+    pub fn motion_model(&self) -> &MotionModel {
+        &self.motion_model
+    }
+
+    /// Every selected frame with the INS state paired with it, if any.
+    fn frames(&self) -> impl Iterator<Item = (usize, (TimeFrame, Option<InsFrame>))> + '_ {
+        let time_frames = self.time_frames.iter().cloned();
+        align_frames(time_frames, &self.motion_model, self.alignment)
+            .enumerate()
+            .filter(|(frame_index, _)| self.selects(*frame_index))
+            .step_by(self.step)
+    }
+
+    /// Runs the processor over every selected frame and counts what was left out.
+    ///
+    /// An interrupt stops the run once the frame in flight is done, so its results can still be
+    /// finalized.
+    pub fn run(&self, processor: &mut impl FrameProcessor) -> RunSummary {
+        if let Some(monitor) = &self.monitor {
+            let frames_total = self.planned_frames();
+            monitor.update(|progress| progress.frames_total = Some(frames_total));
+        }
+        let mut summary = RunSummary::new();
+        for (frame_index, (time_frame, ins_frame)) in self.frames() {
+            if self.interrupted.load(Ordering::SeqCst) {
+                summary.interrupted = true;
+                break;
+            }
+
+            let t0 = Instant::now();
+            let frame = match self.context(frame_index, time_frame, ins_frame) {
+                Ok(frame) => frame,
+                Err(skip) => {
+                    summary.skip(frame_index, skip.reason, skip.detail);
+                    self.report(&summary, frame_index);
+                    continue;
+                }
+            };
+
+            if let Err(skip) = processor.process(&frame) {
+                summary.skip(frame_index, skip.reason, skip.detail);
+                self.report(&summary, frame_index);
+                continue;
+            }
+
+            summary.processed();
+            self.report(&summary, frame_index);
+            print_frame_status(
+                frame_index,
+                summary.frames_processed,
+                self.max_frames,
+                t0.elapsed().as_millis(),
+            );
+            if self
+                .max_frames
+                .is_some_and(|max_frames| summary.frames_processed >= max_frames)
+            {
+                break;
+            }
+        }
+
+        summary.peak_memory_mib = peak_memory_mib();
+        if let Some(monitor) = &self.monitor {
+            monitor.update(|progress| progress.finished = true);
+        }
+        summary
+    }
+
+    /// Passes the counts so far on to the monitor, if there is one.
+    fn report(&self, summary: &RunSummary, frame_index: usize) {
+        if let Some(monitor) = &self.monitor {
+            monitor.update(|progress| {
+                progress.frames_processed = summary.frames_processed;
+                progress.frames_skipped.clone_from(&summary.frames_skipped);
+                progress.last_frame_index = Some(frame_index);
+            });
+        }
+    }
+
+    /// Images of the selected frames with an INS state, in the order a run reads them.
+    pub fn image_paths(&self) -> Vec<PathBuf> {
+        self.frames()
+            .filter(|(_, (_, ins_frame))| ins_frame.is_some())
+            .map(|(frame_index, _)| self.dataset.image_path(frame_index))
+            .collect()
+    }
+
+    /// Frames with an INS state that a run would process, up to the maximum.
+    fn planned_frames(&self) -> usize {
+        let paired = self
+            .frames()
+            .filter(|(_, (_, ins_frame))| ins_frame.is_some())
+            .count();
+        self.max_frames
+            .map_or(paired, |max_frames| paired.min(max_frames))
+    }
+
+    /// Counts the frames a run would process and times the processor on one of them.
+    ///
+    /// The processor is dropped before measuring how much it wrote to `results_dir`, so buffered
+    /// writers are flushed.
+    pub fn dry_run(&self, mut processor: impl FrameProcessor, results_dir: &Path) -> DryRun {
+        let frames = self.planned_frames();
+        let bytes_before = dir_size(results_dir);
+        let mut benchmark = None;
+        for (frame_index, (time_frame, ins_frame)) in self.frames().take(DRY_RUN_ATTEMPTS) {
+            let Ok(frame) = self.context(frame_index, time_frame, ins_frame) else {
+                continue;
+            };
+            let t0 = Instant::now();
+            match processor.process(&frame) {
+                Ok(()) => {
+                    benchmark = Some((frame_index, t0.elapsed().as_secs_f64()));
+                    break;
+                }
+                Err(skip) => eprintln!(
+                    "dry run skipped frame {frame_index:04} ({:?}): {}",
+                    skip.reason, skip.detail
+                ),
+            }
+        }
+        drop(processor);
+
+        DryRun {
+            frames,
+            benchmark_frame: benchmark.map(|(frame_index, _)| frame_index),
+            frame_time_s: benchmark.map(|(_, frame_time_s)| frame_time_s),
+            output_bytes_per_frame: benchmark
+                .map(|_| dir_size(results_dir).saturating_sub(bytes_before)),
+            candidates_per_frame: None,
+        }
+    }
+
+    fn selects(&self, frame_index: usize) -> bool {
+        self.frame_range
+            .is_none_or(|frame_range| frame_range.contains(frame_index))
+            && self
+                .frame_list
+                .as_ref()
+                .is_none_or(|frame_list| frame_list.contains(&frame_index))
+    }
+
+    fn outage(&self, time: DateTime<Utc>) -> OutagePhase {
+        match self.time_frames.first() {
+            Some(first) => OutagePhase::at(&self.outages, (time - first.time).as_seconds_f64()),
+            None => OutagePhase::Nominal,
+        }
+    }
+
+    fn context(
+        &self,
+        frame_index: usize,
+        time_frame: TimeFrame,
+        ins_frame: Option<InsFrame>,
+    ) -> Result<FrameContext, FrameSkip> {
+        let Some(mut ins_frame) = ins_frame else {
+            return Err(FrameSkip::new(
+                SkipReason::NoInsState,
+                "no INS state at exposure time",
+            ));
+        };
+
+        // Alignment periods are not ground truth.
+        let degraded_status = ins_frame.status.filter(|status| !status.is_good());
+        if let Some(status) = degraded_status
+            && self.ins_status_action == InsStatusAction::Skip
+        {
+            return Err(FrameSkip::new(
+                SkipReason::DegradedIns,
+                format!("INS status is {status:?}"),
+            ));
+        }
+
+        // Compare against true heading, whatever the INS reports.
+        if let Some(magnetic_model) = &self.magnetic_model {
+            ins_frame.orientation = magnetic_model.true_heading(
+                ins_frame.orientation,
+                &ins_frame.position,
+                time_frame.time,
+            );
+        }
+        if self.mount_pitch != Angle::ZERO || self.mount_roll != Angle::ZERO {
+            let (yaw, pitch, roll) = ins_frame.orientation.to_tait_bryan_angles();
+            ins_frame.orientation = Orientation::tait_bryan_builder()
+                .yaw(yaw)
+                .pitch(pitch + self.mount_pitch)
+                .roll(roll + self.mount_roll)
+                .build();
+        }
+
+        // Fast turns smear the sky pattern during the exposure.
+        let yaw_rate_deg_s = self.motion_model.yaw_rate(ins_frame.time);
+        let yaw_rate_exceeded = self
+            .max_yaw_rate_deg_s
+            .zip(yaw_rate_deg_s)
+            .is_some_and(|(max_yaw_rate, yaw_rate)| yaw_rate.abs() > max_yaw_rate);
+        let yaw_smear = match (self.exposure_ms, yaw_rate_deg_s) {
+            (Some(exposure_ms), Some(yaw_rate)) => {
+                Angle::new::<degree>(yaw_rate * exposure_ms / 1e3)
+            }
+            _ => Angle::ZERO,
+        };
+
+        let (_, pitch, roll) = ins_frame.orientation.to_tait_bryan_angles();
+        let (pitch_deg, roll_deg) = (pitch.get::<degree>(), roll.get::<degree>());
+        let tilted = self.max_tilt_deg.is_some_and(|max_tilt_deg| {
+            pitch_deg.abs() > max_tilt_deg || roll_deg.abs() > max_tilt_deg
+        });
+        if tilted {
+            eprintln!(
+                "WARNING: frame {frame_index:04} is tilted (pitch {pitch_deg:+.2} deg, roll {roll_deg:+.2} deg), results assume a level camera"
+            );
+        }
+
+        Ok(FrameContext {
+            frame_index,
+            time: time_frame.time,
+            ins: ins_frame,
+            image_path: self.dataset.image_path(frame_index),
+            yaw_rate_deg_s,
+            yaw_rate_exceeded,
+            tilted,
+            yaw_smear,
+            ins_degraded: degraded_status.is_some(),
+            outage: self.outage(time_frame.time),
+        })
+    }
+}
+
+/// Frames tried before a dry run gives up on timing one.
+const DRY_RUN_ATTEMPTS: usize = 10;
+
+/// Workload of a run, estimated without starting it.
+#[derive(Debug, serde::Serialize)]
+pub struct DryRun {
+    pub frames: usize,
+    /// Frame processed to time the run, if any could be.
+    pub benchmark_frame: Option<usize>,
+    pub frame_time_s: Option<f64>,
+    pub output_bytes_per_frame: Option<u64>,
+    /// Candidate headings evaluated for each frame, for experiments that search.
+    pub candidates_per_frame: Option<usize>,
+}
+
+impl DryRun {
+    pub fn with_candidates_per_frame(mut self, candidates_per_frame: usize) -> Self {
+        self.candidates_per_frame = Some(candidates_per_frame);
+        self
+    }
+
+    #[allow(clippy::cast_precision_loss)]
+    pub fn runtime_s(&self) -> Option<f64> {
+        self.frame_time_s
+            .map(|frame_time_s| frame_time_s * self.frames as f64)
+    }
+
+    pub fn output_bytes(&self) -> Option<u64> {
+        self.output_bytes_per_frame
+            .map(|bytes| bytes * self.frames as u64)
+    }
+
+    #[allow(clippy::cast_precision_loss)]
+    pub fn print(&self) {
+        println!("frames to process: {}", self.frames);
+        if let Some(candidates_per_frame) = self.candidates_per_frame {
+            println!(
+                "candidates: {candidates_per_frame} per frame, {} total",
+                candidates_per_frame * self.frames
+            );
+        }
+        let (Some(frame_index), Some(runtime_s), Some(output_bytes)) =
+            (self.benchmark_frame, self.runtime_s(), self.output_bytes())
+        else {
+            println!("no frame could be processed to estimate runtime and output size");
+            return;
+        };
+        println!(
+            "estimated runtime: {:.1} h ({:.2} s per frame, timed on frame {frame_index:04})",
+            runtime_s / 3600.,
+            self.frame_time_s.unwrap_or_default()
+        );
+        println!("estimated output size: {:.1} MB", output_bytes as f64 / 1e6);
+    }
+}
+
+/// Total size of the files under a directory.
+fn dir_size(dir: &Path) -> u64 {
+    let Ok(entries) = std::fs::read_dir(dir) else {
+        return 0;
+    };
+    entries
+        .filter_map(Result::ok)
+        .map(|entry| match entry.metadata() {
+            Ok(metadata) if metadata.is_dir() => dir_size(&entry.path()),
+            Ok(metadata) => metadata.len(),
+            Err(_) => 0,
+        })
+        .sum()
+}
+
+/// Frame indices from `start` up to but not including `end`, written `100..500`.
+///
+/// Either end can be left out, and `100..=499` includes the end.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, serde::Serialize)]
+pub struct FrameRange {
+    pub start: usize,
+    pub end: Option<usize>,
+}
+
+impl FrameRange {
+    pub fn contains(&self, frame_index: usize) -> bool {
+        frame_index >= self.start && self.end.is_none_or(|end| frame_index < end)
+    }
+}
+
+impl FromStr for FrameRange {
+    type Err = BenchError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let invalid = || BenchError::Config(format!("frame range {s:?} is not like 100..500"));
+        let (start, end) = s.split_once("..").ok_or_else(invalid)?;
+        let (end, inclusive) = match end.strip_prefix('=') {
+            Some(end) => (end, true),
+            None => (end, false),
+        };
+        let parse = |bound: &str| bound.trim().parse::<usize>().map_err(|_| invalid());
+
+        let start = if start.trim().is_empty() {
+            0
+        } else {
+            parse(start)?
+        };
+        let end = match (end.trim().is_empty(), inclusive) {
+            (true, false) => None,
+            (true, true) => return Err(invalid()),
+            (false, inclusive) => Some(parse(end)? + usize::from(inclusive)),
+        };
+        Ok(Self { start, end })
+    }
+}
+
+impl Display for FrameRange {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self.end {
+            Some(end) => write!(f, "{}..{end}", self.start),
+            None => write!(f, "{}..", self.start),
+        }
+    }
+}
+
+/// Reads frame indices listed one per line, with `#` starting a comment.
+pub fn read_frame_list<P: AsRef<Path>>(path: P) -> Result<BTreeSet<usize>, BenchError> {
+    let path = path.as_ref();
+    let contents = std::fs::read_to_string(path).map_err(|e| BenchError::dataset(path, e))?;
+    contents
+        .lines()
+        .enumerate()
+        .map(|(i, line)| (i, line.split('#').next().unwrap_or_default().trim()))
+        .filter(|(_, line)| !line.is_empty())
+        .map(|(i, line)| {
+            line.parse().map_err(|_| {
+                BenchError::parse(path, i + 1, format!("{line:?} is not a frame index"))
+            })
+        })
+        .collect()
+}
+
+fn print_frame_status(
+    frame_index: usize,
+    frame_count: usize,
+    max_frames: Option<usize>,
+    elapsed_millis: u128,
+) {
+    let max_frames_fmt = match max_frames {
+        Some(max_frames) => format!("{max_frames:04}"),
+        None => "????".to_string(),
+    };
+    println!(
+        "[{frame_count:04}/{max_frames_fmt}] frame {frame_index:04} in {elapsed_millis:05} ms"
+    );
+}
+
+/// Directory that holds the results of a single run.
+pub struct ResultWriter {
+    dir: PathBuf,
+    started: String,
+}
+
+impl ResultWriter {
+    /// Makes a new directory named after the current time in the working directory.
+    pub fn create() -> Result<Self, BenchError> {
+        Self::create_in(".")
+    }
+
+    pub fn create_in<P: AsRef<Path>>(parent: P) -> Result<Self, BenchError> {
+        let started = Local::now().to_rfc3339();
+        let dir = parent.as_ref().join(&started);
+        std::fs::create_dir(&dir).map_err(|e| BenchError::output(dir.display(), e))?;
+        Ok(Self { dir, started })
+    }
+
+    pub fn dir(&self) -> &Path {
+        &self.dir
+    }
+
+    /// When the run started, in RFC 3339.
+    pub fn started(&self) -> &str {
+        &self.started
+    }
+
+    /// Opens a new CSV file in the results directory.
+    pub fn csv(&self, name: &str) -> Result<csv::Writer<File>, BenchError> {
+        let path = self.dir.join(name);
+        csv::Writer::from_path(&path).map_err(|e| BenchError::output(path.display(), e))
+    }
+}
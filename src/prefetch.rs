@@ -0,0 +1,120 @@
+use crate::{
+    error::BenchError, exposure::ExposureQuality, image_circle::Circle, io::ImageReader,
+    read_ahead::ReadAhead,
+};
+use rumpus::{image::RayImage, ray::SensorFrame};
+use std::{
+    path::{Path, PathBuf},
+    sync::{
+        Mutex,
+        mpsc::{Receiver, SyncSender, sync_channel},
+    },
+    thread,
+};
+
+type Decoded = Result<(RayImage<SensorFrame>, ExposureQuality, Option<Circle>), BenchError>;
+
+/// Images decoded on a background thread ahead of the frame being processed, so reading and
+/// decoding the next image overlaps with matching the current one.
+///
+/// Images are decoded in the order they are expected to be read, and at most `depth` of them
+/// wait in the queue at a time.
+pub struct ImagePrefetch {
+    dark_level: u8,
+    queue: Mutex<Queue>,
+}
+
+struct Queue {
+    receiver: Receiver<(PathBuf, Decoded)>,
+    /// Paths not taken off the queue yet, in order.
+    pending: Vec<PathBuf>,
+    next: usize,
+}
+
+impl ImagePrefetch {
+    /// Starts decoding `paths` with `image_reader`, measuring their exposure at `dark_level`.
+    ///
+    /// The thread stops once every image is decoded or the prefetch is dropped.
+    pub fn start(
+        image_reader: ImageReader,
+        paths: Vec<PathBuf>,
+        depth: usize,
+        dark_level: u8,
+    ) -> Self {
+        let queued = paths.clone();
+        Self::spawn(paths, depth, dark_level, move |sender| {
+            for path in queued {
+                let decoded = image_reader.read_image_with_exposure(&path, dark_level);
+                if sender.send((path, decoded)).is_err() {
+                    break;
+                }
+            }
+        })
+    }
+
+    /// Like [`Self::start`], decoding the files in the order `read_ahead` hands them on.
+    pub fn start_reading_ahead(
+        image_reader: ImageReader,
+        read_ahead: ReadAhead,
+        depth: usize,
+        dark_level: u8,
+    ) -> Self {
+        let paths = read_ahead.paths().to_vec();
+        Self::spawn(paths, depth, dark_level, move |sender| {
+            while let Some((path, bytes)) = read_ahead.recv() {
+                let decoded = bytes
+                    .map_err(|e| BenchError::dataset(&path, e))
+                    .and_then(|bytes| image_reader.read_raw_from_bytes(&path, &bytes))
+                    .and_then(|raw| image_reader.decode_with_exposure(&path, raw, dark_level));
+                if sender.send((path, decoded)).is_err() {
+                    break;
+                }
+            }
+        })
+    }
+
+    /// Runs `decode` on a background thread, feeding the queue of `paths`.
+    fn spawn(
+        paths: Vec<PathBuf>,
+        depth: usize,
+        dark_level: u8,
+        decode: impl FnOnce(SyncSender<(PathBuf, Decoded)>) + Send + 'static,
+    ) -> Self {
+        let (sender, receiver) = sync_channel(depth.max(1));
+        thread::spawn(move || decode(sender));
+        Self {
+            dark_level,
+            queue: Mutex::new(Queue {
+                receiver,
+                pending: paths,
+                next: 0,
+            }),
+        }
+    }
+
+    pub fn dark_level(&self) -> u8 {
+        self.dark_level
+    }
+
+    /// Takes the image at `path` off the queue, dropping the ones queued before it.
+    ///
+    /// Returns `None` for images that were not queued, or were measured at another dark level,
+    /// which the caller then reads itself.
+    pub fn take(&self, path: &Path, dark_level: u8) -> Option<Decoded> {
+        if dark_level != self.dark_level {
+            return None;
+        }
+        let mut queue = self.queue.lock().unwrap();
+        let position = queue.pending[queue.next..]
+            .iter()
+            .position(|pending| pending == path)?;
+        for _ in 0..=position {
+            let (decoded_path, decoded) = queue.receiver.recv().ok()?;
+            queue.next += 1;
+            if decoded_path == path {
+                return Some(decoded);
+            }
+        }
+        None
+    }
+}
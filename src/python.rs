@@ -0,0 +1,165 @@
+//! Python bindings for prototyping cost functions and estimators against the benchmark pipeline.
+
+use crate::{
+    camera::CameraModel,
+    io::ImageReader,
+    sky::Sky,
+    systems::{AzimuthConvention, InsEnu},
+    utils::{self, measured_to_global},
+};
+use chrono::{DateTime, Utc};
+use pyo3::{exceptions::PyValueError, prelude::*};
+use rumpus::{
+    image::RayImage,
+    ray::{Aop, GlobalFrame, Ray},
+};
+use uom::si::{
+    angle::degree,
+    f64::{Angle, Length},
+    length::{micron, millimeter},
+};
+
+const FOCAL_LENGTH_MM: f64 = 8.0;
+const PIXEL_SIZE_UM: f64 = 3.45;
+
+fn camera_model() -> CameraModel {
+    let focal_length = Length::new::<millimeter>(FOCAL_LENGTH_MM);
+    let pixel_size = Length::new::<micron>(PIXEL_SIZE_UM);
+    CameraModel::new(focal_length, pixel_size * 2.0, 1024, 1224)
+}
+
+fn to_py_err(e: impl std::fmt::Display) -> PyErr {
+    PyValueError::new_err(e.to_string())
+}
+
+/// Polarization image in the global frame, with AoP in degrees.
+#[pyclass(name = "RayImage")]
+#[derive(Clone)]
+pub struct PyRayImage {
+    inner: RayImage<GlobalFrame>,
+}
+
+#[pymethods]
+impl PyRayImage {
+    /// Builds an image from nested rows of AoP and DoP, where `None` marks invalid pixels.
+    #[new]
+    fn new(aop_deg: Vec<Vec<Option<f64>>>, dop: Vec<Vec<Option<f64>>>) -> PyResult<Self> {
+        let rows = aop_deg.len();
+        let cols = aop_deg.first().map_or(0, Vec::len);
+        if dop.len() != rows || aop_deg.iter().chain(&dop).any(|row| row.len() != cols) {
+            return Err(PyValueError::new_err(
+                "AoP and DoP must have the same shape",
+            ));
+        }
+
+        let rays = aop_deg
+            .iter()
+            .flatten()
+            .zip(dop.iter().flatten())
+            .map(|(aop, dop)| {
+                let aop = Aop::from_angle_wrapped(Angle::new::<degree>((*aop)?));
+                Some(Ray::new(aop, (*dop)?))
+            });
+        let inner = RayImage::from_rays(rays, rows, cols).map_err(to_py_err)?;
+        Ok(Self { inner })
+    }
+
+    #[getter]
+    fn rows(&self) -> usize {
+        self.inner.rows()
+    }
+
+    #[getter]
+    fn cols(&self) -> usize {
+        self.inner.cols()
+    }
+
+    fn aop_deg(&self) -> Vec<Vec<Option<f64>>> {
+        self.map(|ray| Angle::from(ray.aop()).get::<degree>())
+    }
+
+    fn dop(&self) -> Vec<Vec<Option<f64>>> {
+        self.map(Ray::dop)
+    }
+}
+
+impl PyRayImage {
+    fn map(&self, f: impl Fn(&Ray<GlobalFrame>) -> f64) -> Vec<Vec<Option<f64>>> {
+        (0..self.inner.rows())
+            .map(|row| {
+                (0..self.inner.cols())
+                    .map(|col| self.inner.ray(row, col).map(&f))
+                    .collect()
+            })
+            .collect()
+    }
+}
+
+/// Reads a polarization image and rotates it into the global frame for the given INSPVA
+/// attitude.
+#[pyfunction]
+fn load_frame(path: &str, azimuth_deg: f64, pitch_deg: f64, roll_deg: f64) -> PyResult<PyRayImage> {
+    let image = ImageReader::new().read_image(path).map_err(to_py_err)?;
+    let car_in_ins_enu = InsEnu::orientation_from_inspva(
+        AzimuthConvention::LeftHandedFromNorth,
+        azimuth_deg,
+        pitch_deg,
+        roll_deg,
+    );
+    Ok(PyRayImage {
+        inner: measured_to_global(&image, &camera_model(), car_in_ins_enu),
+    })
+}
+
+/// Simulates the sky seen by the camera at an INSPVA position and attitude.
+///
+/// The time is an RFC 3339 string in any offset.
+#[pyfunction]
+#[pyo3(signature = (lat, lon, height, time, azimuth_deg, pitch_deg, roll_deg, turbidity = 1.0))]
+#[allow(clippy::too_many_arguments)]
+fn simulate(
+    lat: f64,
+    lon: f64,
+    height: f64,
+    time: &str,
+    azimuth_deg: f64,
+    pitch_deg: f64,
+    roll_deg: f64,
+    turbidity: f64,
+) -> PyResult<PyRayImage> {
+    let time: DateTime<Utc> = DateTime::parse_from_rfc3339(time)
+        .map_err(to_py_err)?
+        .with_timezone(&Utc);
+    let position = InsEnu::position_from_inspva(lat, lon, height);
+    let car_in_ins_enu = InsEnu::orientation_from_inspva(
+        AzimuthConvention::LeftHandedFromNorth,
+        azimuth_deg,
+        pitch_deg,
+        roll_deg,
+    );
+
+    if !(turbidity.is_finite() && turbidity >= 1.) {
+        return Err(PyValueError::new_err(format!(
+            "turbidity {turbidity} is not a finite number of at least 1"
+        )));
+    }
+    let inner = Sky::new(turbidity)
+        .simulate(&camera_model(), &position, car_in_ins_enu, time)
+        .map_err(to_py_err)?;
+    Ok(PyRayImage { inner })
+}
+
+/// DoP-weighted AoP error in degrees, the cost used by the benchmark.
+#[pyfunction]
+fn weighted_rmse(simulated: &PyRayImage, measured: &PyRayImage) -> f64 {
+    utils::weighted_rmse(&simulated.inner, &measured.inner)
+}
+
+#[pymodule]
+fn rumpus_benchmark(m: &Bound<'_, PyModule>) -> PyResult<()> {
+    m.add_class::<PyRayImage>()?;
+    m.add_function(wrap_pyfunction!(load_frame, m)?)?;
+    m.add_function(wrap_pyfunction!(simulate, m)?)?;
+    m.add_function(wrap_pyfunction!(weighted_rmse, m)?)?;
+    Ok(())
+}
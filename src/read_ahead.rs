@@ -0,0 +1,99 @@
+use crate::error::BenchError;
+#[cfg(feature = "async-io")]
+use crate::{archive, remote};
+#[cfg(feature = "async-io")]
+use std::{collections::VecDeque, sync::mpsc::sync_channel, thread};
+use std::{io, path::PathBuf, sync::mpsc::Receiver};
+
+/// Bytes of a file read ahead, or why it could not be read.
+pub type ReadResult = (PathBuf, io::Result<Vec<u8>>);
+
+/// Reads the files of a dataset with async I/O, keeping several reads in flight at a time.
+///
+/// Sequential blocking reads stall on network filesystems such as NFS, where the latency of
+/// each read dwarfs its transfer time. The files are handed on in order through a bounded
+/// channel to the synchronous stage that decodes them.
+pub struct ReadAhead {
+    paths: Vec<PathBuf>,
+    receiver: Receiver<ReadResult>,
+}
+
+impl ReadAhead {
+    /// Starts reading `paths` with up to `depth` reads in flight.
+    ///
+    /// Files of an archive all come out of one decompression stream, so they are read one after
+    /// the other in order instead.
+    #[cfg(feature = "async-io")]
+    pub fn start(paths: Vec<PathBuf>, depth: usize) -> Result<Self, BenchError> {
+        let depth = depth.max(1);
+        if paths.iter().any(|path| archive::is_archived(path)) {
+            let (sender, receiver) = sync_channel(depth);
+            let queued = paths.clone();
+            thread::spawn(move || {
+                for path in queued {
+                    let bytes = archive::read_file(&path).map_err(io::Error::other);
+                    if sender.send((path, bytes)).is_err() {
+                        break;
+                    }
+                }
+            });
+            return Ok(Self { paths, receiver });
+        }
+
+        let runtime = tokio::runtime::Builder::new_multi_thread()
+            .thread_name("read-ahead")
+            .build()
+            .map_err(|e| BenchError::Config(format!("cannot start the read-ahead runtime: {e}")))?;
+        let (sender, receiver) = sync_channel(depth);
+        let queued = paths.clone();
+        thread::spawn(move || {
+            let mut queued = queued.into_iter();
+            let mut in_flight = VecDeque::with_capacity(depth);
+            loop {
+                while in_flight.len() < depth
+                    && let Some(path) = queued.next()
+                {
+                    // Remote files come out of the download cache instead.
+                    let read = if remote::is_remote(&path) {
+                        let remote = path.clone();
+                        runtime.spawn_blocking(move || {
+                            archive::read_file(&remote).map_err(io::Error::other)
+                        })
+                    } else {
+                        runtime.spawn(tokio::fs::read(path.clone()))
+                    };
+                    in_flight.push_back((path, read));
+                }
+                let Some((path, read)) = in_flight.pop_front() else {
+                    break;
+                };
+                // Reads further ahead go on while this one waits for room in the channel.
+                let bytes = runtime
+                    .block_on(read)
+                    .unwrap_or_else(|e| Err(io::Error::other(e)));
+                if sender.send((path, bytes)).is_err() {
+                    break;
+                }
+            }
+        });
+        Ok(Self { paths, receiver })
+    }
+
+    #[cfg(not(feature = "async-io"))]
+    pub fn start(_paths: Vec<PathBuf>, _depth: usize) -> Result<Self, BenchError> {
+        Err(BenchError::Config(
+            "reading ahead needs the `async-io` feature; rebuild with `--features async-io`"
+                .to_string(),
+        ))
+    }
+
+    /// Files in the order they are handed on.
+    pub fn paths(&self) -> &[PathBuf] {
+        &self.paths
+    }
+
+    /// Waits for the next file, or returns `None` once every file has been handed on.
+    pub fn recv(&self) -> Option<ReadResult> {
+        self.receiver.recv().ok()
+    }
+}
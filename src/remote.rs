@@ -0,0 +1,164 @@
+use crate::error::BenchError;
+#[cfg(feature = "remote")]
+use object_store::{ObjectStore, aws::AmazonS3Builder, path::Path as ObjectPath};
+use std::{
+    env,
+    path::{Path, PathBuf},
+};
+#[cfg(feature = "remote")]
+use std::{
+    io::Write,
+    sync::{
+        Arc, Mutex, OnceLock,
+        atomic::{AtomicUsize, Ordering},
+    },
+};
+
+/// Prefix of dataset paths in S3-compatible object storage, such as `s3://bucket/prefix`.
+const SCHEME: &str = "s3://";
+
+/// Clients of the buckets used so far, kept until the process exits.
+#[cfg(feature = "remote")]
+static STORES: Mutex<Vec<(String, Arc<dyn ObjectStore>)>> = Mutex::new(Vec::new());
+
+/// Downloads started so far, to give each its own partial file.
+#[cfg(feature = "remote")]
+static DOWNLOADS: AtomicUsize = AtomicUsize::new(0);
+
+/// Whether a dataset path is an object in S3-compatible storage.
+pub fn is_remote(path: &Path) -> bool {
+    path.to_str().is_some_and(|path| path.starts_with(SCHEME))
+}
+
+/// Bucket and key of a remote path.
+#[cfg(feature = "remote")]
+fn bucket_and_key(path: &Path) -> Option<(&str, &str)> {
+    let (bucket, key) = path.to_str()?.strip_prefix(SCHEME)?.split_once('/')?;
+    let key = key.trim_start_matches('/');
+    (!bucket.is_empty() && !key.is_empty()).then_some((bucket, key))
+}
+
+/// Directory remote files are cached in.
+///
+/// `RUMPUS_CACHE_DIR` if it is set, else `rumpus_benchmark` in the user's cache directory.
+/// Objects of a dataset are not expected to change, so the cache is never invalidated; delete
+/// it to download a dataset again.
+pub fn cache_dir() -> PathBuf {
+    if let Some(dir) = env::var_os("RUMPUS_CACHE_DIR") {
+        return PathBuf::from(dir);
+    }
+    env::var_os("XDG_CACHE_HOME")
+        .map(PathBuf::from)
+        .or_else(|| env::var_os("HOME").map(|home| Path::new(&home).join(".cache")))
+        .unwrap_or_else(env::temp_dir)
+        .join("rumpus_benchmark")
+}
+
+/// Local copy of a dataset file, downloaded into the [`cache_dir`] on first use if it is remote.
+///
+/// Credentials, the region and, for other S3-compatible stores, the endpoint are read from
+/// the usual `AWS_*` environment variables.
+pub fn local_path(path: &Path) -> Result<PathBuf, BenchError> {
+    local_path_in(path, &cache_dir())
+}
+
+/// Same as [`local_path`], caching remote files in `cache_dir`.
+#[cfg(feature = "remote")]
+pub fn local_path_in(path: &Path, cache_dir: &Path) -> Result<PathBuf, BenchError> {
+    if !is_remote(path) {
+        return Ok(path.to_path_buf());
+    }
+    let (bucket, key) = bucket_and_key(path).ok_or_else(|| invalid(path))?;
+    let cached = cache_dir.join(bucket).join(key);
+    if cached.exists() {
+        return Ok(cached);
+    }
+
+    let error = |e: std::io::Error| BenchError::dataset(path, e);
+    std::fs::create_dir_all(cached.parent().unwrap()).map_err(error)?;
+    // Partial downloads are moved into place only once complete, so an interrupted run never
+    // leaves a truncated file in the cache.
+    let mut partial = cached.clone().into_os_string();
+    partial.push(format!(
+        ".{}-{}.part",
+        std::process::id(),
+        DOWNLOADS.fetch_add(1, Ordering::Relaxed)
+    ));
+    let partial = PathBuf::from(partial);
+    let store = store(bucket).map_err(|e| BenchError::dataset(path, e))?;
+    let location = ObjectPath::from(key);
+    let downloaded = runtime()?.block_on(async {
+        use futures::StreamExt;
+        let mut stream = store.get(&location).await?.into_stream();
+        let mut file = std::fs::File::create(&partial).map_err(object_error)?;
+        while let Some(chunk) = stream.next().await {
+            file.write_all(&chunk?).map_err(object_error)?;
+        }
+        file.sync_all().map_err(object_error)
+    });
+    if let Err(e) = downloaded {
+        let _ = std::fs::remove_file(&partial);
+        return Err(BenchError::dataset(path, e));
+    }
+    std::fs::rename(&partial, &cached).map_err(error)?;
+    Ok(cached)
+}
+
+#[cfg(not(feature = "remote"))]
+pub fn local_path_in(path: &Path, _cache_dir: &Path) -> Result<PathBuf, BenchError> {
+    if is_remote(path) {
+        return Err(missing_remote());
+    }
+    Ok(path.to_path_buf())
+}
+
+/// Client of a bucket, configured from the environment on first use.
+#[cfg(feature = "remote")]
+fn store(bucket: &str) -> object_store::Result<Arc<dyn ObjectStore>> {
+    let mut stores = STORES.lock().unwrap();
+    if let Some((_, store)) = stores.iter().find(|(name, _)| name == bucket) {
+        return Ok(Arc::clone(store));
+    }
+    let store: Arc<dyn ObjectStore> = Arc::new(
+        AmazonS3Builder::from_env()
+            .with_bucket_name(bucket)
+            .build()?,
+    );
+    stores.push((bucket.to_string(), Arc::clone(&store)));
+    Ok(store)
+}
+
+/// Runtime the object store client runs on, shared by every download.
+#[cfg(feature = "remote")]
+fn runtime() -> Result<&'static tokio::runtime::Runtime, BenchError> {
+    static RUNTIME: OnceLock<tokio::runtime::Runtime> = OnceLock::new();
+    if let Some(runtime) = RUNTIME.get() {
+        return Ok(runtime);
+    }
+    let runtime = tokio::runtime::Builder::new_multi_thread()
+        .thread_name("remote")
+        .enable_all()
+        .build()
+        .map_err(|e| BenchError::Config(format!("cannot start the download runtime: {e}")))?;
+    Ok(RUNTIME.get_or_init(|| runtime))
+}
+
+#[cfg(feature = "remote")]
+fn object_error(e: std::io::Error) -> object_store::Error {
+    object_store::Error::Generic {
+        store: "local cache",
+        source: Box::new(e),
+    }
+}
+
+#[cfg(feature = "remote")]
+fn invalid(path: &Path) -> BenchError {
+    BenchError::Config(format!("{} is not like s3://bucket/prefix", path.display()))
+}
+
+#[cfg(not(feature = "remote"))]
+fn missing_remote() -> BenchError {
+    BenchError::Config(
+        "s3:// datasets need the `remote` feature; rebuild with `--features remote`".to_string(),
+    )
+}
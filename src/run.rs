@@ -0,0 +1,424 @@
+use crate::{
+    camera::{CameraModel, PrincipalPoint},
+    ephemeris::Atmosphere,
+    error::BenchError,
+    exposure::ExposureGate,
+    glare::GlareConfig,
+    image_circle::ImageCircle,
+    io::{DemosaicMode, ImageOrientation, InsLog, MosaicLayout},
+    magnetic::HeadingReference,
+    outage::OutageWindow,
+    pipeline::{FrameRange, InsStatusAction},
+    scratch::ScratchPool,
+    sky::{AltitudeConfig, LightSourceMode, SkyModelBackend},
+    stats::percentile,
+    systems::InsConvention,
+};
+use std::{
+    collections::BTreeMap,
+    fmt::Display,
+    fs::File,
+    path::{Path, PathBuf},
+    time::Instant,
+};
+
+/// Describes how a results directory was produced, so runs can be compared fairly.
+#[derive(Debug, serde::Serialize)]
+pub struct RunMetadata {
+    pub experiment: String,
+    pub started: String,
+    pub dataset_path: PathBuf,
+    pub sky_model: SkyModelBackend,
+    pub sky_table: Option<PathBuf>,
+    pub turbidity: f64,
+    pub light_source: LightSourceMode,
+    pub dop_calibration: Option<PathBuf>,
+    pub ins_convention: InsConvention,
+    pub ins_log: InsLog,
+    pub ins_status: InsStatusAction,
+    /// Spans without GNSS, in seconds from the first frame.
+    pub ins_outages: Vec<OutageWindow>,
+    pub heading_reference: HeadingReference,
+    pub demosaic: DemosaicMode,
+    pub mosaic_layout: MosaicLayout,
+    pub image_orientation: ImageOrientation,
+    /// Offset of the optical axis from the center of the raw sensor, in pixels.
+    pub principal_point: PrincipalPoint,
+    pub mount_pitch_deg: f64,
+    pub mount_roll_deg: f64,
+    pub image_circle: ImageCircle,
+    pub exposure_gate: ExposureGate,
+    pub frames: Option<FrameRange>,
+    pub frame_list: Option<PathBuf>,
+    pub tags: Option<PathBuf>,
+    pub sky_lut_resolution_deg: Option<f64>,
+    pub altitude: AltitudeConfig,
+    /// Default conditions of the refraction correction, if enabled.
+    pub refraction: Option<Atmosphere>,
+    pub weather_csv: Option<PathBuf>,
+    pub glare: GlareConfig,
+    pub profile: RunProfile,
+    /// Set when the run is stopped early, after which the metadata is written again.
+    pub interrupted: bool,
+}
+
+impl RunMetadata {
+    pub fn write<P: AsRef<Path>>(&self, results_dir: P) -> Result<(), BenchError> {
+        write_json(results_dir.as_ref().join("metadata.json"), self)
+    }
+}
+
+/// Resources a run is allowed to use.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, clap::ValueEnum, serde::Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum RunProfile {
+    /// Every core, writing whatever outputs are asked for.
+    #[default]
+    Workstation,
+    /// A single thread without rayon, candidates scored in f32, scratch buffers allocated up
+    /// front and no image outputs, to check whether the estimator fits on a Jetson or
+    /// Raspberry Pi class board.
+    ///
+    /// rumpus only simulates sunlit Rayleigh skies in parallel, so those still run on rayon,
+    /// whose pool is then one thread.
+    Embedded,
+}
+
+impl RunProfile {
+    /// Sizes the global thread pool, which has to happen before any parallel work.
+    ///
+    /// The embedded profile leaves the pool to rumpus alone, see [`Self::parallel`].
+    pub fn apply(self) -> Result<(), BenchError> {
+        match self {
+            Self::Workstation => Ok(()),
+            Self::Embedded => rayon::ThreadPoolBuilder::new()
+                .num_threads(1)
+                .build_global()
+                .map_err(|e| BenchError::Config(format!("cannot limit the thread pool: {e}"))),
+        }
+    }
+
+    /// Allocates the scratch buffers of the embedded profile's one thread for a camera before
+    /// the run, so its memory peaks on the first frame rather than creeping up.
+    pub fn preallocate(self, scratch: &ScratchPool, camera: &CameraModel) {
+        if self == Self::Embedded {
+            scratch.preallocate(camera.rows() * camera.cols(), 1);
+        }
+    }
+
+    /// Whether the harness sweeps candidates and evaluates skies on the rayon pool.
+    pub fn parallel(self) -> bool {
+        self == Self::Workstation
+    }
+
+    /// Whether candidates are scored in f32 rather than f64.
+    pub fn single_precision(self) -> bool {
+        self == Self::Embedded
+    }
+
+    pub fn writes_images(self) -> bool {
+        self == Self::Workstation
+    }
+}
+
+/// Peak resident memory of this process so far in MiB, where the kernel reports it.
+pub fn peak_memory_mib() -> Option<f64> {
+    let status = std::fs::read_to_string("/proc/self/status").ok()?;
+    let kib: f64 = status
+        .lines()
+        .find_map(|line| line.strip_prefix("VmHWM:"))?
+        .trim()
+        .strip_suffix("kB")?
+        .trim()
+        .parse()
+        .ok()?;
+    Some(kib / 1024.)
+}
+
+/// Why a frame was left out of the results.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, serde::Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum SkipReason {
+    NoInsState,
+    DegradedIns,
+    UnreadableImage,
+    SourceTooLow,
+    SunInView,
+    BadExposure,
+    SimulationFailed,
+    UnwritableResults,
+}
+
+/// Counts processed and skipped frames so long runs can report what they left out.
+#[derive(Debug, Default, serde::Serialize)]
+pub struct RunSummary {
+    pub frames_processed: usize,
+    pub frames_skipped: BTreeMap<SkipReason, usize>,
+    /// Whether the run was stopped before every frame was processed.
+    pub interrupted: bool,
+    /// Percentiles of the time each stage took per frame, if the experiment timed them.
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub latency: Vec<LatencyPercentiles>,
+    /// Peak resident memory by the end of the run, on Linux.
+    pub peak_memory_mib: Option<f64>,
+}
+
+impl RunSummary {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn processed(&mut self) {
+        self.frames_processed += 1;
+    }
+
+    /// Logs why a frame was skipped and counts it.
+    pub fn skip(&mut self, frame_index: usize, reason: SkipReason, detail: impl Display) {
+        eprintln!("skipping frame {frame_index:04} ({reason:?}): {detail}");
+        *self.frames_skipped.entry(reason).or_default() += 1;
+    }
+
+    pub fn frames_skipped(&self) -> usize {
+        self.frames_skipped.values().sum()
+    }
+
+    pub fn print(&self) {
+        println!(
+            "processed {} frames, skipped {}{}",
+            self.frames_processed,
+            self.frames_skipped(),
+            if self.interrupted {
+                " before being interrupted"
+            } else {
+                ""
+            }
+        );
+        for (reason, count) in &self.frames_skipped {
+            println!("  {reason:?}: {count}");
+        }
+        for latency in &self.latency {
+            latency.print();
+        }
+        if let Some(peak_memory_mib) = self.peak_memory_mib {
+            println!("  peak memory: {peak_memory_mib:.1} MiB");
+        }
+    }
+
+    pub fn write<P: AsRef<Path>>(&self, results_dir: P) -> Result<(), BenchError> {
+        write_json(results_dir.as_ref().join("summary.json"), self)
+    }
+}
+
+pub(crate) fn write_json(path: PathBuf, value: &impl serde::Serialize) -> Result<(), BenchError> {
+    let file = File::create(&path).map_err(|e| BenchError::output(path.display(), e))?;
+    serde_json::to_writer_pretty(file, value).map_err(|e| BenchError::output(path.display(), e))
+}
+
+/// Time spent in each stage of processing a frame, or one candidate of a frame.
+#[derive(Debug, Default, Clone, Copy, serde::Serialize)]
+pub struct TimingRecord {
+    pub frame_index: usize,
+    pub candidate_index: Option<usize>,
+    pub decode_ms: f64,
+    pub transform_ms: f64,
+    pub simulate_ms: f64,
+    pub rmse_ms: f64,
+    /// Wall-clock time from when the frame's image was available to when its result was,
+    /// which is less than the sum of the stages when candidates run in parallel.
+    pub latency_ms: Option<f64>,
+}
+
+impl TimingRecord {
+    pub fn frame(frame_index: usize) -> Self {
+        Self {
+            frame_index,
+            ..Self::default()
+        }
+    }
+
+    pub fn candidate(frame_index: usize, candidate_index: usize) -> Self {
+        Self {
+            frame_index,
+            candidate_index: Some(candidate_index),
+            ..Self::default()
+        }
+    }
+
+    /// Adds the stage times of a candidate to this frame.
+    pub fn accumulate(&mut self, other: &Self) {
+        self.decode_ms += other.decode_ms;
+        self.transform_ms += other.transform_ms;
+        self.simulate_ms += other.simulate_ms;
+        self.rmse_ms += other.rmse_ms;
+    }
+}
+
+/// Spread of the time a stage took over the frames of a run.
+#[derive(Debug, Clone, Copy, serde::Serialize)]
+pub struct LatencyPercentiles {
+    pub stage: &'static str,
+    pub frames: usize,
+    pub p50_ms: f64,
+    pub p90_ms: f64,
+    pub p99_ms: f64,
+    pub max_ms: f64,
+}
+
+impl LatencyPercentiles {
+    /// Returns `None` for no samples.
+    pub fn new(stage: &'static str, samples_ms: &[f64]) -> Option<Self> {
+        Some(Self {
+            stage,
+            frames: samples_ms.len(),
+            p50_ms: percentile(samples_ms, 50.)?,
+            p90_ms: percentile(samples_ms, 90.)?,
+            p99_ms: percentile(samples_ms, 99.)?,
+            max_ms: percentile(samples_ms, 100.)?,
+        })
+    }
+
+    /// Percentiles of every stage of the given frame timings, ending with the end-to-end
+    /// latency.
+    pub fn of_frames(timings: &[TimingRecord]) -> Vec<Self> {
+        ["decode", "transform", "simulate", "rmse", "end_to_end"]
+            .into_iter()
+            .filter_map(|stage| {
+                let samples: Vec<f64> = timings
+                    .iter()
+                    .filter_map(|timing| match stage {
+                        "decode" => Some(timing.decode_ms),
+                        "transform" => Some(timing.transform_ms),
+                        "simulate" => Some(timing.simulate_ms),
+                        "rmse" => Some(timing.rmse_ms),
+                        _ => timing.latency_ms,
+                    })
+                    .collect();
+                Self::new(stage, &samples)
+            })
+            .collect()
+    }
+
+    pub fn print(&self) {
+        println!(
+            "  {} latency: p50 {:.1} ms, p90 {:.1} ms, p99 {:.1} ms, max {:.1} ms",
+            self.stage, self.p50_ms, self.p90_ms, self.p99_ms, self.max_ms
+        );
+    }
+}
+
+/// Runs a closure and adds its wall-clock time in milliseconds to a stage.
+pub fn timed<T>(stage_ms: &mut f64, f: impl FnOnce() -> T) -> T {
+    let t0 = Instant::now();
+    let result = f();
+    *stage_ms += t0.elapsed().as_secs_f64() * 1e3;
+    result
+}
+
+/// Upper edge of the largest bin of the pixel time histogram, in log2 nanoseconds.
+const PROFILE_HISTOGRAM_BINS: usize = 32;
+
+/// How long the sky model takes per pixel across the rows of the image.
+///
+/// Rows are timed as a whole, and each row's time is spread evenly over its pixels. Skies
+/// rumpus simulates can only be timed as a whole image, which is spread evenly over its rows.
+#[derive(Debug, Clone, Default)]
+pub struct SimulationProfile {
+    row_ns: Vec<f64>,
+    row_samples: Vec<usize>,
+    /// Images timed as a whole rather than by row.
+    whole_images: usize,
+    /// Rows by their time per pixel, in bins of powers of two nanoseconds.
+    histogram: [usize; PROFILE_HISTOGRAM_BINS],
+}
+
+#[derive(serde::Serialize)]
+struct ProfileRowRecord {
+    row: usize,
+    samples: usize,
+    mean_pixel_ns: f64,
+}
+
+#[derive(serde::Serialize)]
+struct ProfileBinRecord {
+    lower_pixel_ns: u64,
+    upper_pixel_ns: u64,
+    rows: usize,
+}
+
+impl SimulationProfile {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Adds the time taken by each row of one simulated image.
+    #[allow(
+        clippy::cast_precision_loss,
+        clippy::cast_possible_truncation,
+        clippy::cast_sign_loss
+    )]
+    pub fn add(&mut self, row_ns: &[f64], cols: usize) {
+        if self.row_ns.len() < row_ns.len() {
+            self.row_ns.resize(row_ns.len(), 0.);
+            self.row_samples.resize(row_ns.len(), 0);
+        }
+        for (row, ns) in row_ns.iter().enumerate() {
+            self.row_ns[row] += ns;
+            self.row_samples[row] += 1;
+            let pixel_ns = ns / cols.max(1) as f64;
+            let bin = pixel_ns.max(1.).log2().floor() as usize;
+            self.histogram[bin.min(PROFILE_HISTOGRAM_BINS - 1)] += 1;
+        }
+    }
+
+    /// Adds the time taken by an image of `rows` that could only be timed as a whole.
+    #[allow(clippy::cast_precision_loss)]
+    pub fn add_whole(&mut self, image_ns: f64, rows: usize, cols: usize) {
+        self.add(&vec![image_ns / rows.max(1) as f64; rows], cols);
+        self.whole_images += 1;
+    }
+
+    /// Number of images added with [`Self::add_whole`], whose rows all took the same time.
+    pub fn whole_images(&self) -> usize {
+        self.whole_images
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.row_samples.is_empty()
+    }
+
+    /// Writes the mean time per pixel of each row and the histogram of row times.
+    #[allow(clippy::cast_precision_loss)]
+    pub fn write<P: AsRef<Path>>(&self, results_dir: P, cols: usize) -> Result<(), BenchError> {
+        let path = results_dir.as_ref().join("simulation_rows.csv");
+        let output_error = |e| BenchError::output(path.display(), e);
+        let mut writer = csv::Writer::from_path(&path).map_err(output_error)?;
+        for (row, (ns, samples)) in self.row_ns.iter().zip(&self.row_samples).enumerate() {
+            writer
+                .serialize(ProfileRowRecord {
+                    row,
+                    samples: *samples,
+                    mean_pixel_ns: ns / (*samples * cols.max(1)) as f64,
+                })
+                .map_err(output_error)?;
+        }
+        writer
+            .flush()
+            .map_err(|e| BenchError::output(path.display(), e))?;
+
+        let path = results_dir.as_ref().join("simulation_histogram.csv");
+        let output_error = |e| BenchError::output(path.display(), e);
+        let mut writer = csv::Writer::from_path(&path).map_err(output_error)?;
+        for (bin, rows) in self.histogram.iter().enumerate() {
+            writer
+                .serialize(ProfileBinRecord {
+                    lower_pixel_ns: if bin == 0 { 0 } else { 1 << bin },
+                    upper_pixel_ns: 1 << (bin + 1),
+                    rows: *rows,
+                })
+                .map_err(output_error)?;
+        }
+        writer
+            .flush()
+            .map_err(|e| BenchError::output(path.display(), e))
+    }
+}
@@ -0,0 +1,198 @@
+use crate::{
+    camera::CameraModel,
+    ephemeris::CelestialPosition,
+    scratch::Scratch,
+    sky::{SkyDirection, sky_directions_into},
+    systems::InsEnu,
+};
+use rumpus::{
+    image::RayImage,
+    ray::{Aop, GlobalFrame, Ray},
+};
+use sguaba::engineering::Orientation;
+use uom::si::{angle::radian, f64::Angle};
+
+/// How many times more the e-vectors must spread about the second best direction than about
+/// the source for the source to stand out.
+const MIN_CONDITION: f64 = 10.;
+
+/// Rotations of the eigenvalue solver, far more than a 3x3 matrix needs to converge.
+const JACOBI_SWEEPS: usize = 16;
+
+/// Angle from the meridian to the scattering plane through a view direction and the light
+/// source, in the convention of the global frame AoP.
+///
+/// Single scattering polarizes perpendicular to this plane. `None` at the source and opposite
+/// it, where the plane is undefined.
+pub fn scattering_plane_angle(view: &SkyDirection, source: &CelestialPosition) -> Option<Angle> {
+    let (v, s) = (enu(view), enu(&SkyDirection::from(source)));
+    let along = dot(&s, &v);
+    let towards_source = [0, 1, 2].map(|i| s[i] - along * v[i]);
+    if dot(&towards_source, &towards_source) < 1e-18 {
+        return None;
+    }
+    let (meridian, azimuthal) = tangents(view);
+    Some(Angle::new::<radian>(
+        dot(&towards_source, &azimuthal).atan2(dot(&towards_source, &meridian)),
+    ))
+}
+
+/// Light source that best explains the measured AoP under single scattering.
+///
+/// Every e-vector is perpendicular to its scattering plane, so to the direction of the source.
+/// The source is taken to be the direction most nearly perpendicular to all of them, weighted
+/// by their DoP, which is the eigenvector of the smallest eigenvalue of their scatter matrix.
+/// That direction and its opposite explain the AoP equally well, so the one on the side of
+/// `near` is returned, which only tells the source from the anti-source.
+///
+/// `directions` are those of the pixels of `measured` in row-major order. Returns `None` if no
+/// direction stands out, as when the e-vectors are all nearly parallel.
+pub fn estimate_source(
+    measured: &RayImage<GlobalFrame>,
+    directions: &[Option<SkyDirection>],
+    near: &CelestialPosition,
+) -> Option<CelestialPosition> {
+    let mut scatter = [[0.; 3]; 3];
+    for px in measured.pixels() {
+        let (Some(ray), Some(direction)) = (
+            px.ray(),
+            directions[px.row() * measured.cols() + px.col()].as_ref(),
+        ) else {
+            continue;
+        };
+        let aop = Angle::from(ray.aop()).get::<radian>();
+        let (meridian, azimuthal) = tangents(direction);
+        let e_vector = [0, 1, 2].map(|i| aop.cos() * meridian[i] + aop.sin() * azimuthal[i]);
+        for (row, e_row) in scatter.iter_mut().zip(e_vector) {
+            for (cell, e_col) in row.iter_mut().zip(e_vector) {
+                *cell += ray.dop() * e_row * e_col;
+            }
+        }
+    }
+
+    let (values, vectors) = symmetric_eigen(scatter);
+    let mut order = [0, 1, 2];
+    order.sort_by(|&a, &b| values[a].total_cmp(&values[b]));
+    let (smallest, second) = (values[order[0]].max(0.), values[order[1]]);
+    if !(second > 0. && second >= MIN_CONDITION * smallest) {
+        return None;
+    }
+
+    let mut source = vectors[order[0]];
+    if dot(&source, &enu(&SkyDirection::from(near))) < 0. {
+        source = source.map(|x| -x);
+    }
+    let [east, north, up] = source;
+    Some(CelestialPosition {
+        azimuth: Angle::new::<radian>(east.atan2(north)),
+        elevation: Angle::new::<radian>(up.clamp(-1., 1.).asin()),
+    })
+}
+
+/// Refers every ray to the scattering plane through its pixel and `source` instead of the
+/// meridian, so single scattering has an AoP of 90° everywhere.
+///
+/// Pixels at the source or opposite it, or that do not see the sky, are dropped.
+pub fn global_to_scattering_plane(
+    ray_image: &RayImage<GlobalFrame>,
+    camera: &CameraModel,
+    car_in_ins_enu: Orientation<InsEnu>,
+    source: &CelestialPosition,
+) -> RayImage<GlobalFrame> {
+    global_to_scattering_plane_with(
+        ray_image,
+        camera,
+        car_in_ins_enu,
+        source,
+        &mut Scratch::default(),
+    )
+}
+
+/// Like [`global_to_scattering_plane`], converting the rays in a reused buffer.
+pub fn global_to_scattering_plane_with(
+    ray_image: &RayImage<GlobalFrame>,
+    camera: &CameraModel,
+    car_in_ins_enu: Orientation<InsEnu>,
+    source: &CelestialPosition,
+    scratch: &mut Scratch,
+) -> RayImage<GlobalFrame> {
+    sky_directions_into(camera, car_in_ins_enu, &mut scratch.directions);
+    let directions = &scratch.directions;
+    scratch.rays.clear();
+    scratch.rays.extend(ray_image.pixels().map(|px| {
+        let ray = px.ray()?;
+        let direction = directions[px.row() * ray_image.cols() + px.col()].as_ref()?;
+        let plane = scattering_plane_angle(direction, source)?;
+        let angle = Aop::from_angle_wrapped(Angle::from(ray.aop()) - plane);
+        Some(Ray::<GlobalFrame>::new(angle, ray.dop()))
+    }));
+    RayImage::from_rays(scratch.rays.drain(..), ray_image.rows(), ray_image.cols()).unwrap()
+}
+
+/// Unit vector towards a sky direction, in east, north and up.
+fn enu(direction: &SkyDirection) -> [f64; 3] {
+    let (zenith, azimuth) = (
+        direction.zenith.get::<radian>(),
+        direction.azimuth.get::<radian>(),
+    );
+    [
+        zenith.sin() * azimuth.sin(),
+        zenith.sin() * azimuth.cos(),
+        zenith.cos(),
+    ]
+}
+
+/// Unit vectors along the meridian towards the horizon and towards increasing azimuth at a sky
+/// direction, which the global frame AoP is measured from and towards.
+fn tangents(direction: &SkyDirection) -> ([f64; 3], [f64; 3]) {
+    let (zenith, azimuth) = (
+        direction.zenith.get::<radian>(),
+        direction.azimuth.get::<radian>(),
+    );
+    (
+        [
+            zenith.cos() * azimuth.sin(),
+            zenith.cos() * azimuth.cos(),
+            -zenith.sin(),
+        ],
+        [azimuth.cos(), -azimuth.sin(), 0.],
+    )
+}
+
+fn dot(a: &[f64; 3], b: &[f64; 3]) -> f64 {
+    a[0] * b[0] + a[1] * b[1] + a[2] * b[2]
+}
+
+/// Eigenvalues and unit eigenvectors of a symmetric matrix, by Jacobi rotations.
+fn symmetric_eigen(mut a: [[f64; 3]; 3]) -> ([f64; 3], [[f64; 3]; 3]) {
+    let mut v = [[1., 0., 0.], [0., 1., 0.], [0., 0., 1.]];
+    for _ in 0..JACOBI_SWEEPS {
+        for (p, q) in [(0, 1), (0, 2), (1, 2)] {
+            if a[p][q] == 0. {
+                continue;
+            }
+            // Rotates the (p, q) plane so that a[p][q] becomes zero.
+            let theta = (a[q][q] - a[p][p]) / (2. * a[p][q]);
+            let t = theta.signum() / (theta.abs() + theta.hypot(1.));
+            let c = 1. / t.hypot(1.);
+            let s = t * c;
+            for row in &mut a {
+                let (kp, kq) = (row[p], row[q]);
+                row[p] = c * kp - s * kq;
+                row[q] = s * kp + c * kq;
+            }
+            let (row_p, row_q) = (a[p], a[q]);
+            a[p] = [0, 1, 2].map(|k| c * row_p[k] - s * row_q[k]);
+            a[q] = [0, 1, 2].map(|k| s * row_p[k] + c * row_q[k]);
+            for row in &mut v {
+                let (kp, kq) = (row[p], row[q]);
+                row[p] = c * kp - s * kq;
+                row[q] = s * kp + c * kq;
+            }
+        }
+    }
+    (
+        [a[0][0], a[1][1], a[2][2]],
+        [0, 1, 2].map(|k| [v[0][k], v[1][k], v[2][k]]),
+    )
+}
@@ -0,0 +1,66 @@
+use crate::sky::SkyDirection;
+use rumpus::ray::{GlobalFrame, Ray, SensorFrame};
+use std::sync::{Arc, Mutex};
+
+/// Buffers reused by the per-candidate stages instead of allocating a fresh `Vec` each time.
+///
+/// Buffers are drained rather than dropped, so they keep the capacity of their largest use.
+/// Only the images handed back to the caller are still allocated.
+#[derive(Debug, Default)]
+pub struct Scratch {
+    pub(crate) directions: Vec<Option<SkyDirection>>,
+    pub(crate) rays: Vec<Option<Ray<GlobalFrame>>>,
+    pub(crate) sensor_rays: Vec<Option<Ray<SensorFrame>>>,
+    pub(crate) mask: Vec<bool>,
+    /// Weights and squared AoP errors of the pixels scored in single precision.
+    pub(crate) weights: Vec<f32>,
+    pub(crate) errors: Vec<f32>,
+}
+
+/// Scratch buffers shared by clones and sweep threads, so they outlive a single frame.
+///
+/// Each task takes a whole [`Scratch`] out of the pool and returns it afterwards, which stays
+/// sound when rayon runs another candidate on the same thread while one is still in flight.
+#[derive(Debug, Clone, Default)]
+pub struct ScratchPool {
+    free: Arc<Mutex<Vec<Scratch>>>,
+}
+
+impl Scratch {
+    /// Buffers with room for images of `pixels` rays.
+    pub fn with_capacity(pixels: usize) -> Self {
+        Self {
+            directions: Vec::with_capacity(pixels),
+            rays: Vec::with_capacity(pixels),
+            sensor_rays: Vec::with_capacity(pixels),
+            mask: Vec::with_capacity(pixels),
+            weights: Vec::with_capacity(pixels),
+            errors: Vec::with_capacity(pixels),
+        }
+    }
+}
+
+impl ScratchPool {
+    /// Adds `count` scratches with room for images of `pixels` rays to the pool, so runs that
+    /// never use more at once allocate them all before the first frame.
+    pub fn preallocate(&self, pixels: usize, count: usize) {
+        let mut free = self.free.lock().unwrap();
+        free.extend((0..count).map(|_| Scratch::with_capacity(pixels)));
+    }
+
+    pub fn with<T>(&self, f: impl FnOnce(&mut Scratch) -> T) -> T {
+        let mut scratch = self.free.lock().unwrap().pop().unwrap_or_default();
+        let result = f(&mut scratch);
+        self.free.lock().unwrap().push(scratch);
+        result
+    }
+
+    /// Scratch buffers currently waiting to be reused.
+    pub fn len(&self) -> usize {
+        self.free.lock().unwrap().len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+}
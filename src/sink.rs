@@ -0,0 +1,700 @@
+use crate::{error::BenchError, run::RunMetadata};
+#[cfg(feature = "parquet")]
+use parquet::{
+    basic::{LogicalType, Repetition, Type as PhysicalType},
+    data_type::{BoolType, ByteArray, ByteArrayType, DataType, DoubleType, Int64Type},
+    errors::ParquetError,
+    file::{
+        properties::WriterProperties,
+        writer::{SerializedColumnWriter, SerializedFileWriter},
+    },
+    schema::types::Type,
+};
+use serde::Serialize;
+#[cfg(feature = "parquet")]
+use std::sync::Arc;
+#[cfg(feature = "sqlite")]
+use std::{collections::HashMap, time::Duration};
+use std::{
+    fs::File,
+    io::Write,
+    path::{Path, PathBuf},
+};
+
+/// Compression level of candidate CSVs, which favours speed as zstd does by default.
+const ZSTD_LEVEL: i32 = 3;
+/// Rows buffered before they are appended to a Parquet file.
+#[cfg(feature = "parquet")]
+const PARQUET_ROW_GROUP_ROWS: usize = 10_000;
+/// How long to wait for another run to finish writing to a shared SQLite database.
+#[cfg(feature = "sqlite")]
+const SQLITE_BUSY_TIMEOUT: Duration = Duration::from_secs(60);
+
+/// Where the per-frame and per-candidate results of a run are stored.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, clap::ValueEnum, serde::Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum SinkFormat {
+    /// `results.csv` plus a `frame_NNNN_results.csv` of candidates per frame.
+    Csv,
+    /// `results.csv` plus every candidate in a zstd-compressed `candidates.csv.zst`.
+    CsvZstd,
+    /// `runs`, `frames` and `candidates` tables of `results.sqlite`.
+    Sqlite,
+    /// `results.parquet` and `candidates.parquet`.
+    Parquet,
+}
+
+impl SinkFormat {
+    /// Opens a sink that writes into a results directory.
+    pub fn open(
+        self,
+        dir: &Path,
+        metadata: &RunMetadata,
+    ) -> Result<Box<dyn ResultSink>, BenchError> {
+        match self {
+            Self::Csv => Ok(Box::new(CsvSink::new(dir, false)?)),
+            Self::CsvZstd => Ok(Box::new(CsvSink::new(dir, true)?)),
+            Self::Sqlite => open_database(&dir.join("results.sqlite"), dir, metadata),
+            #[cfg(feature = "parquet")]
+            Self::Parquet => Ok(Box::new(ParquetSink::new(dir))),
+            #[cfg(not(feature = "parquet"))]
+            Self::Parquet => Err(missing_feature("parquet")),
+        }
+    }
+}
+
+/// Opens a sink that adds the run to an SQLite database, which may be shared between runs.
+#[cfg(feature = "sqlite")]
+pub fn open_database(
+    path: &Path,
+    results_dir: &Path,
+    metadata: &RunMetadata,
+) -> Result<Box<dyn ResultSink>, BenchError> {
+    Ok(Box::new(SqliteSink::new(path, results_dir, metadata)?))
+}
+
+#[cfg(not(feature = "sqlite"))]
+pub fn open_database(
+    _path: &Path,
+    _results_dir: &Path,
+    _metadata: &RunMetadata,
+) -> Result<Box<dyn ResultSink>, BenchError> {
+    Err(missing_feature("sqlite"))
+}
+
+#[cfg(any(not(feature = "sqlite"), not(feature = "parquet")))]
+fn missing_feature(feature: &str) -> BenchError {
+    BenchError::Config(format!(
+        "{feature} results need the `{feature}` feature; rebuild with `--features {feature}`"
+    ))
+}
+
+/// Destination of the records a run produces for each frame and each of its candidates.
+pub trait ResultSink {
+    fn write_frame(&mut self, row: ResultRow) -> Result<(), BenchError>;
+
+    /// The candidates of a frame are written one after another.
+    fn write_candidate(&mut self, frame_index: usize, row: ResultRow) -> Result<(), BenchError>;
+
+    /// Writes whatever is still buffered. Results may be incomplete until this is called.
+    fn finalize(self: Box<Self>) -> Result<(), BenchError>;
+}
+
+/// Column names and values of a record, formatted as they would be in a CSV file.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ResultRow {
+    columns: Vec<String>,
+    values: Vec<String>,
+}
+
+impl ResultRow {
+    /// Flattens a record with `csv`, so `None` becomes an empty value.
+    pub fn new(record: &impl Serialize) -> Result<Self, BenchError> {
+        let error = |e: csv::Error| BenchError::output("result row", e);
+        let mut writer = csv::Writer::from_writer(Vec::new());
+        writer.serialize(record).map_err(error)?;
+        let bytes = writer
+            .into_inner()
+            .map_err(|e| BenchError::output("result row", e.into_error()))?;
+
+        let mut reader = csv::Reader::from_reader(bytes.as_slice());
+        let columns = reader
+            .headers()
+            .map_err(error)?
+            .iter()
+            .map(String::from)
+            .collect();
+        let values = reader
+            .records()
+            .next()
+            .transpose()
+            .map_err(error)?
+            .map(|record| record.iter().map(String::from).collect())
+            .unwrap_or_default();
+        Ok(Self { columns, values })
+    }
+
+    pub fn columns(&self) -> &[String] {
+        &self.columns
+    }
+
+    pub fn values(&self) -> &[String] {
+        &self.values
+    }
+}
+
+/// A value of a [`ResultRow`] read back as the type it was most likely written from.
+#[cfg(any(feature = "sqlite", feature = "parquet"))]
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Value<'a> {
+    Null,
+    Boolean(bool),
+    Integer(i64),
+    Real(f64),
+    Text(&'a str),
+}
+
+#[cfg(any(feature = "sqlite", feature = "parquet"))]
+impl<'a> Value<'a> {
+    fn parse(value: &'a str) -> Self {
+        match value {
+            "" => Self::Null,
+            "true" => Self::Boolean(true),
+            "false" => Self::Boolean(false),
+            _ => value
+                .parse()
+                .map(Self::Integer)
+                .or_else(|_| value.parse().map(Self::Real))
+                .unwrap_or(Self::Text(value)),
+        }
+    }
+}
+
+/// Writes frames to `results.csv` and the candidates of each frame to a CSV file of their own,
+/// or all of them to a single compressed one.
+pub struct CsvSink {
+    dir: PathBuf,
+    frames: csv::Writer<File>,
+    frames_header: bool,
+    candidates: Option<(usize, csv::Writer<File>)>,
+    /// Every candidate so far, and whether the header is written.
+    compressed_candidates: Option<(csv::Writer<zstd::Encoder<'static, File>>, bool)>,
+}
+
+impl CsvSink {
+    pub fn new(dir: &Path, compress_candidates: bool) -> Result<Self, BenchError> {
+        let compressed_candidates = if compress_candidates {
+            let path = dir.join("candidates.csv.zst");
+            let encoder = File::create(&path)
+                .and_then(|file| zstd::Encoder::new(file, ZSTD_LEVEL))
+                .map_err(|e| BenchError::output(path.display(), e))?;
+            Some((csv::Writer::from_writer(encoder), false))
+        } else {
+            None
+        };
+        Ok(Self {
+            dir: dir.to_path_buf(),
+            frames: open_csv(&dir.join("results.csv"))?,
+            frames_header: false,
+            candidates: None,
+            compressed_candidates,
+        })
+    }
+
+    fn candidates_path(&self, frame_index: usize) -> PathBuf {
+        self.dir.join(format!("frame_{frame_index:04}_results.csv"))
+    }
+
+    /// Closes the candidates file of the last frame.
+    fn flush_candidates(&mut self) -> Result<(), BenchError> {
+        let Some((frame_index, mut writer)) = self.candidates.take() else {
+            return Ok(());
+        };
+        writer
+            .flush()
+            .map_err(|e| BenchError::output(self.candidates_path(frame_index).display(), e))
+    }
+}
+
+impl ResultSink for CsvSink {
+    fn write_frame(&mut self, row: ResultRow) -> Result<(), BenchError> {
+        let path = self.dir.join("results.csv");
+        if !self.frames_header {
+            write_csv_row(&mut self.frames, &path, row.columns())?;
+            self.frames_header = true;
+        }
+        write_csv_row(&mut self.frames, &path, row.values())
+    }
+
+    fn write_candidate(&mut self, frame_index: usize, row: ResultRow) -> Result<(), BenchError> {
+        if let Some((writer, header)) = &mut self.compressed_candidates {
+            let path = self.dir.join("candidates.csv.zst");
+            if !*header {
+                write_csv_row(writer, &path, row.columns())?;
+                *header = true;
+            }
+            return write_csv_row(writer, &path, row.values());
+        }
+
+        let path = self.candidates_path(frame_index);
+        if self
+            .candidates
+            .as_ref()
+            .is_none_or(|(index, _)| *index != frame_index)
+        {
+            self.flush_candidates()?;
+            let mut writer = open_csv(&path)?;
+            write_csv_row(&mut writer, &path, row.columns())?;
+            self.candidates = Some((frame_index, writer));
+        }
+        let (_, writer) = self.candidates.as_mut().expect("opened above");
+        write_csv_row(writer, &path, row.values())
+    }
+
+    fn finalize(mut self: Box<Self>) -> Result<(), BenchError> {
+        let path = self.dir.join("results.csv");
+        self.frames
+            .flush()
+            .map_err(|e| BenchError::output(path.display(), e))?;
+        self.flush_candidates()?;
+        if let Some((writer, _)) = self.compressed_candidates.take() {
+            let path = self.dir.join("candidates.csv.zst");
+            writer
+                .into_inner()
+                .map_err(|e| e.into_error())
+                .and_then(zstd::Encoder::finish)
+                .map_err(|e| BenchError::output(path.display(), e))?;
+        }
+        Ok(())
+    }
+}
+
+fn open_csv(path: &Path) -> Result<csv::Writer<File>, BenchError> {
+    csv::Writer::from_path(path).map_err(|e| BenchError::output(path.display(), e))
+}
+
+fn write_csv_row<W: Write>(
+    writer: &mut csv::Writer<W>,
+    path: &Path,
+    fields: &[String],
+) -> Result<(), BenchError> {
+    writer
+        .write_record(fields)
+        .map_err(|e| BenchError::output(path.display(), e))
+}
+
+/// Inserts frames and candidates into tables of the same name, keyed by the id of the run in
+/// the `runs` table.
+///
+/// Several runs, even of different experiments, can share a database: tables gain the columns
+/// of each new kind of record. Columns are untyped, so every value is stored as the integer,
+/// real or text it parses as, and empty values are stored as NULL.
+#[cfg(feature = "sqlite")]
+pub struct SqliteSink {
+    path: PathBuf,
+    connection: rusqlite::Connection,
+    run_id: i64,
+    /// Columns of the last row inserted into each table.
+    columns: HashMap<&'static str, Vec<String>>,
+    in_transaction: bool,
+}
+
+#[cfg(feature = "sqlite")]
+impl SqliteSink {
+    /// Opens or creates a database and adds the run to it.
+    pub fn new(
+        path: &Path,
+        results_dir: &Path,
+        metadata: &RunMetadata,
+    ) -> Result<Self, BenchError> {
+        let error = |e: rusqlite::Error| BenchError::output(path.display(), e);
+        let connection = rusqlite::Connection::open(path).map_err(error)?;
+        // Runs of a batch may write to the same database at once.
+        connection
+            .busy_timeout(SQLITE_BUSY_TIMEOUT)
+            .map_err(error)?;
+        connection
+            .execute_batch(
+                "PRAGMA journal_mode = WAL;
+                 CREATE TABLE IF NOT EXISTS runs (
+                     run_id INTEGER PRIMARY KEY,
+                     experiment TEXT NOT NULL,
+                     started TEXT NOT NULL,
+                     dataset_path TEXT NOT NULL,
+                     results_dir TEXT NOT NULL,
+                     metadata TEXT NOT NULL
+                 );",
+            )
+            .map_err(error)?;
+
+        let results_dir = std::fs::canonicalize(results_dir).unwrap_or_else(|_| results_dir.into());
+        let metadata_json =
+            serde_json::to_string(metadata).map_err(|e| BenchError::output(path.display(), e))?;
+        connection
+            .execute(
+                "INSERT INTO runs (experiment, started, dataset_path, results_dir, metadata)
+                 VALUES (?1, ?2, ?3, ?4, ?5)",
+                (
+                    &metadata.experiment,
+                    &metadata.started,
+                    metadata.dataset_path.display().to_string(),
+                    results_dir.display().to_string(),
+                    metadata_json,
+                ),
+            )
+            .map_err(error)?;
+        Ok(Self {
+            path: path.to_path_buf(),
+            run_id: connection.last_insert_rowid(),
+            connection,
+            columns: HashMap::new(),
+            in_transaction: false,
+        })
+    }
+
+    /// Id of this run in the `runs` table.
+    pub fn run_id(&self) -> i64 {
+        self.run_id
+    }
+
+    fn insert(&mut self, table: &'static str, row: &ResultRow) -> Result<(), BenchError> {
+        let error = |e: rusqlite::Error| BenchError::output(self.path.display(), e);
+        // Rows are committed a frame at a time; one transaction per row is far too slow.
+        if !self.in_transaction {
+            self.connection
+                .execute_batch("BEGIN IMMEDIATE")
+                .map_err(error)?;
+            self.in_transaction = true;
+        }
+        if self.columns.get(table).map(Vec::as_slice) != Some(row.columns()) {
+            self.add_columns(table, row).map_err(error)?;
+            self.columns.insert(table, row.columns().to_vec());
+        }
+
+        let columns: Vec<String> = row.columns().iter().map(|c| quote(c)).collect();
+        let placeholders = vec!["?"; columns.len()].join(", ");
+        let mut statement = self
+            .connection
+            .prepare_cached(&format!(
+                "INSERT INTO {table} (run_id, {}) VALUES (?, {placeholders})",
+                columns.join(", ")
+            ))
+            .map_err(error)?;
+        let values = row.values().iter().map(|value| match Value::parse(value) {
+            Value::Null => rusqlite::types::Value::Null,
+            Value::Boolean(value) => rusqlite::types::Value::Integer(i64::from(value)),
+            Value::Integer(value) => rusqlite::types::Value::Integer(value),
+            Value::Real(value) => rusqlite::types::Value::Real(value),
+            Value::Text(value) => rusqlite::types::Value::Text(value.to_string()),
+        });
+        statement
+            .execute(rusqlite::params_from_iter(
+                std::iter::once(rusqlite::types::Value::Integer(self.run_id)).chain(values),
+            ))
+            .map_err(error)?;
+        Ok(())
+    }
+
+    /// Creates a table, or adds the columns of a row it does not have yet.
+    fn add_columns(&self, table: &str, row: &ResultRow) -> rusqlite::Result<()> {
+        self.connection.execute(
+            &format!(
+                "CREATE TABLE IF NOT EXISTS {table} (run_id INTEGER NOT NULL REFERENCES runs (run_id))"
+            ),
+            [],
+        )?;
+        let existing = self
+            .connection
+            .prepare(&format!("SELECT name FROM pragma_table_info('{table}')"))?
+            .query_map([], |column| column.get::<_, String>(0))?
+            .collect::<rusqlite::Result<Vec<_>>>()?;
+        for column in row.columns() {
+            if !existing.contains(column) {
+                self.connection.execute(
+                    &format!("ALTER TABLE {table} ADD COLUMN {}", quote(column)),
+                    [],
+                )?;
+            }
+        }
+        if row.columns().iter().any(|column| column == "frame_index") {
+            self.connection.execute(
+                &format!(
+                    "CREATE INDEX IF NOT EXISTS {table}_frame ON {table} (run_id, frame_index)"
+                ),
+                [],
+            )?;
+        }
+        Ok(())
+    }
+
+    fn commit(&mut self) -> Result<(), BenchError> {
+        if self.in_transaction {
+            self.connection
+                .execute_batch("COMMIT")
+                .map_err(|e| BenchError::output(self.path.display(), e))?;
+            self.in_transaction = false;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(feature = "sqlite")]
+impl ResultSink for SqliteSink {
+    fn write_frame(&mut self, row: ResultRow) -> Result<(), BenchError> {
+        self.insert("frames", &row)?;
+        self.commit()
+    }
+
+    fn write_candidate(&mut self, _frame_index: usize, row: ResultRow) -> Result<(), BenchError> {
+        self.insert("candidates", &row)
+    }
+
+    fn finalize(mut self: Box<Self>) -> Result<(), BenchError> {
+        self.commit()
+    }
+}
+
+/// Quotes a column name for SQL.
+#[cfg(feature = "sqlite")]
+fn quote(column: &str) -> String {
+    format!("\"{}\"", column.replace('"', "\"\""))
+}
+
+/// Writes frames to `results.parquet` and candidates to `candidates.parquet`.
+///
+/// Rows are appended in row groups of [`PARQUET_ROW_GROUP_ROWS`], and the first group of each
+/// file decides its column types.
+#[cfg(feature = "parquet")]
+pub struct ParquetSink {
+    frames: ParquetTable,
+    candidates: ParquetTable,
+}
+
+#[cfg(feature = "parquet")]
+impl ParquetSink {
+    pub fn new(dir: &Path) -> Self {
+        Self {
+            frames: ParquetTable::new(dir.join("results.parquet")),
+            candidates: ParquetTable::new(dir.join("candidates.parquet")),
+        }
+    }
+}
+
+#[cfg(feature = "parquet")]
+impl ResultSink for ParquetSink {
+    fn write_frame(&mut self, row: ResultRow) -> Result<(), BenchError> {
+        self.frames.push(row)
+    }
+
+    fn write_candidate(&mut self, _frame_index: usize, row: ResultRow) -> Result<(), BenchError> {
+        self.candidates.push(row)
+    }
+
+    fn finalize(self: Box<Self>) -> Result<(), BenchError> {
+        self.frames.close()?;
+        self.candidates.close()
+    }
+}
+
+/// A Parquet file of optional columns, written a row group at a time.
+#[cfg(feature = "parquet")]
+struct ParquetTable {
+    path: PathBuf,
+    pending: Vec<ResultRow>,
+    /// Opened with the first row group, once the column types are known.
+    writer: Option<(SerializedFileWriter<File>, Vec<ColumnKind>)>,
+}
+
+#[cfg(feature = "parquet")]
+impl ParquetTable {
+    fn new(path: PathBuf) -> Self {
+        Self {
+            path,
+            pending: Vec::new(),
+            writer: None,
+        }
+    }
+
+    fn push(&mut self, row: ResultRow) -> Result<(), BenchError> {
+        self.pending.push(row);
+        if self.pending.len() >= PARQUET_ROW_GROUP_ROWS {
+            self.write_row_group()?;
+        }
+        Ok(())
+    }
+
+    /// Writes the pending rows and the footer, or nothing if no rows were pushed.
+    fn close(mut self) -> Result<(), BenchError> {
+        self.write_row_group()?;
+        if let Some((writer, _)) = self.writer {
+            writer
+                .close()
+                .map_err(|e| BenchError::output(self.path.display(), e))?;
+        }
+        Ok(())
+    }
+
+    fn write_row_group(&mut self) -> Result<(), BenchError> {
+        let error = |e: ParquetError| BenchError::output(self.path.display(), e);
+        let Some(first) = self.pending.first() else {
+            return Ok(());
+        };
+        let (writer, kinds) = match &mut self.writer {
+            Some(writer) => writer,
+            writer => {
+                let kinds: Vec<ColumnKind> = (0..first.columns().len())
+                    .map(|i| {
+                        ColumnKind::of(self.pending.iter().map(|row| row.values()[i].as_str()))
+                    })
+                    .collect();
+                let file = File::create(&self.path)
+                    .map_err(|e| BenchError::output(self.path.display(), e))?;
+                let schema = parquet_schema(first.columns(), &kinds).map_err(error)?;
+                let properties = WriterProperties::builder().build();
+                let file_writer =
+                    SerializedFileWriter::new(file, Arc::new(schema), Arc::new(properties))
+                        .map_err(error)?;
+                writer.insert((file_writer, kinds))
+            }
+        };
+
+        let mut row_group = writer.next_row_group().map_err(error)?;
+        let mut i = 0;
+        while let Some(mut column) = row_group.next_column().map_err(error)? {
+            let values = self.pending.iter().map(|row| row.values()[i].as_str());
+            let mismatch = |value: &str| {
+                BenchError::output(
+                    self.path.display(),
+                    format!(
+                        "column {} holds {value:?}, which is not {} like the first row group",
+                        first.columns()[i],
+                        kinds[i].name()
+                    ),
+                )
+            };
+            match kinds[i] {
+                ColumnKind::Boolean => {
+                    write_column::<BoolType>(&mut column, values, |value| match value {
+                        "true" => Some(true),
+                        "false" => Some(false),
+                        _ => None,
+                    })
+                }
+                ColumnKind::Integer => {
+                    write_column::<Int64Type>(&mut column, values, |value| value.parse().ok())
+                }
+                ColumnKind::Real => {
+                    write_column::<DoubleType>(&mut column, values, |value| value.parse().ok())
+                }
+                ColumnKind::Text => write_column::<ByteArrayType>(&mut column, values, |value| {
+                    Some(ByteArray::from(value))
+                }),
+            }
+            .map_err(|e| match e {
+                ColumnError::Mismatch(value) => mismatch(&value),
+                ColumnError::Parquet(e) => error(e),
+            })?;
+            column.close().map_err(error)?;
+            i += 1;
+        }
+        row_group.close().map_err(error)?;
+        self.pending.clear();
+        Ok(())
+    }
+}
+
+/// Parquet type of a column, wide enough for every value in it.
+#[cfg(feature = "parquet")]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum ColumnKind {
+    Boolean,
+    Integer,
+    Real,
+    Text,
+}
+
+#[cfg(feature = "parquet")]
+impl ColumnKind {
+    /// Columns without any values are written as text.
+    fn of<'a>(values: impl Iterator<Item = &'a str>) -> Self {
+        values
+            .filter_map(|value| match Value::parse(value) {
+                Value::Null => None,
+                Value::Boolean(_) => Some(Self::Boolean),
+                Value::Integer(_) => Some(Self::Integer),
+                Value::Real(_) => Some(Self::Real),
+                Value::Text(_) => Some(Self::Text),
+            })
+            .reduce(|kind, other| match (kind, other) {
+                _ if kind == other => kind,
+                (Self::Integer, Self::Real) | (Self::Real, Self::Integer) => Self::Real,
+                _ => Self::Text,
+            })
+            .unwrap_or(Self::Text)
+    }
+
+    fn name(self) -> &'static str {
+        match self {
+            Self::Boolean => "a boolean",
+            Self::Integer => "an integer",
+            Self::Real => "a number",
+            Self::Text => "text",
+        }
+    }
+}
+
+#[cfg(feature = "parquet")]
+fn parquet_schema(columns: &[String], kinds: &[ColumnKind]) -> Result<Type, ParquetError> {
+    let fields = columns
+        .iter()
+        .zip(kinds)
+        .map(|(column, kind)| {
+            let (physical_type, logical_type) = match kind {
+                ColumnKind::Boolean => (PhysicalType::BOOLEAN, None),
+                ColumnKind::Integer => (PhysicalType::INT64, None),
+                ColumnKind::Real => (PhysicalType::DOUBLE, None),
+                ColumnKind::Text => (PhysicalType::BYTE_ARRAY, Some(LogicalType::String)),
+            };
+            Type::primitive_type_builder(column, physical_type)
+                .with_repetition(Repetition::OPTIONAL)
+                .with_logical_type(logical_type)
+                .build()
+                .map(Arc::new)
+        })
+        .collect::<Result<Vec<_>, _>>()?;
+    Type::group_type_builder("schema")
+        .with_fields(fields)
+        .build()
+}
+
+/// Why a column could not be written.
+#[cfg(feature = "parquet")]
+enum ColumnError {
+    /// A value that does not parse as the type of the column.
+    Mismatch(String),
+    Parquet(ParquetError),
+}
+
+/// Writes the values of a column, with empty values as nulls.
+#[cfg(feature = "parquet")]
+fn write_column<'a, T: DataType>(
+    column: &mut SerializedColumnWriter<'_>,
+    values: impl Iterator<Item = &'a str>,
+    parse: impl Fn(&str) -> Option<T::T>,
+) -> Result<(), ColumnError> {
+    let mut data = Vec::new();
+    let mut def_levels = Vec::new();
+    for value in values {
+        if value.is_empty() {
+            def_levels.push(0);
+            continue;
+        }
+        data.push(parse(value).ok_or_else(|| ColumnError::Mismatch(value.to_string()))?);
+        def_levels.push(1);
+    }
+    column
+        .typed::<T>()
+        .write_batch(&data, Some(&def_levels), None)
+        .map_err(ColumnError::Parquet)?;
+    Ok(())
+}
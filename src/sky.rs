@@ -0,0 +1,1568 @@
+use crate::{
+    camera::{CameraModel, PixelGrid},
+    ephemeris::{Atmosphere, CelestialPosition},
+    error::BenchError,
+    run::{SimulationProfile, timed, write_json},
+    scratch::Scratch,
+    systems::{self, CamXyz, InsEnu},
+    weather::WeatherSeries,
+};
+use chrono::{DateTime, Utc};
+use rayon::prelude::*;
+use rumpus::{
+    image::RayImage,
+    ray::{Aop, GlobalFrame, Ray},
+    simulation::Simulation,
+};
+use sguaba::{Vector, engineering::Orientation, systems::Wgs84};
+use std::{
+    path::Path,
+    sync::{Arc, Mutex},
+};
+use uom::{
+    ConstZero,
+    si::{
+        angle::{degree, radian},
+        f64::Angle,
+        length::meter,
+    },
+};
+
+/// Angular distance of the Babinet and Brewster neutral points from the sun.
+const NEUTRAL_POINT_DISTANCE_DEG: f64 = 20.0;
+
+/// Sun elevation at the end of astronomical twilight, below which the moon can dominate.
+const TWILIGHT_ELEVATION_DEG: f64 = -18.0;
+
+/// Measured moonlit skies are slightly less polarized than sunlit ones (Gál et al. 2001).
+const LUNAR_DOP_SCALE: f64 = 0.8;
+
+/// Heights over which the density of air and of aerosols fall by a factor of e.
+const RAYLEIGH_SCALE_HEIGHT_M: f64 = 8_400.0;
+const AEROSOL_SCALE_HEIGHT_M: f64 = 1_200.0;
+
+/// Number of sub-exposures averaged when simulating motion blur.
+const SMEAR_SAMPLES: usize = 5;
+
+/// Sun elevations at which the accuracy of a lookup table is measured.
+const LUT_ACCURACY_ELEVATIONS_DEG: [f64; 9] = [5., 15., 25., 35., 45., 55., 65., 75., 85.];
+
+/// Polarization models the simulation can be run with.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, clap::ValueEnum, serde::Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum SkyModelBackend {
+    /// Single-scattering Rayleigh sky simulated by rumpus.
+    Rayleigh,
+    /// Berry et al. (2004) sky with the neutral points split around the source and anti-source.
+    Berry,
+    /// Lookup table of AoP and DoP binned around the source.
+    Empirical,
+}
+
+/// AoP relative to the local meridian and DoP of the sky in a direction.
+pub(crate) type PixelModel<'a> =
+    Box<dyn Fn(&SkyDirection) -> Option<(Angle, f64)> + Send + Sync + 'a>;
+
+/// Body whose scattered light polarizes the sky.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, serde::Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum LightSource {
+    Sun,
+    Moon,
+}
+
+/// How the light source of each frame is chosen.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, clap::ValueEnum, serde::Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum LightSourceMode {
+    Sun,
+    Moon,
+    /// Use the sun until the end of astronomical twilight, then the moon.
+    Auto,
+}
+
+/// Altitude the sky is simulated at, which follows the INS unless overridden or clamped.
+#[derive(Debug, Default, Clone, Copy, PartialEq, serde::Serialize)]
+pub struct AltitudeConfig {
+    /// Fixed altitude above the ellipsoid in m, replacing the INS altitude.
+    pub override_m: Option<f64>,
+    pub min_m: Option<f64>,
+    pub max_m: Option<f64>,
+}
+
+impl AltitudeConfig {
+    /// Altitude in m to simulate a frame at, given the INS altitude.
+    pub fn altitude_m(&self, ins_altitude_m: f64) -> f64 {
+        let altitude_m = self.override_m.unwrap_or(ins_altitude_m);
+        let altitude_m = self.min_m.map_or(altitude_m, |min_m| altitude_m.max(min_m));
+        self.max_m.map_or(altitude_m, |max_m| altitude_m.min(max_m))
+    }
+
+    /// The INS position at the altitude to simulate.
+    pub fn apply(&self, position: &Wgs84) -> Wgs84 {
+        InsEnu::position_from_inspva(
+            position.latitude().get::<degree>(),
+            position.longitude().get::<degree>(),
+            self.altitude_m(position.altitude().get::<meter>()),
+        )
+    }
+}
+
+/// Wraps a sky polarization model with an atmospheric turbidity.
+///
+/// Turbidity is the ratio of total to Rayleigh optical depth at sea level, so a turbidity of
+/// 1.0 is a perfectly clear sky. It falls with altitude, as aerosols stay nearer the ground.
+/// Light scattered by aerosols is treated as unpolarized, which scales the simulated DoP by
+/// `1 / turbidity` and leaves the AoP untouched.
+///
+/// The harness backends report AoP in the global frame, measured from the horizon-pointing
+/// local meridian towards increasing azimuth.
+/// Moonlit skies are always evaluated by the harness since rumpus only simulates the sun.
+#[derive(Debug, Clone)]
+pub struct Sky {
+    backend: SkyModelBackend,
+    turbidity: f64,
+    table: Option<SkyTable>,
+    light_source: LightSourceMode,
+    dop_calibration: Option<DopCalibration>,
+    lut_resolution_deg: Option<f64>,
+    altitude: AltitudeConfig,
+    /// Surface conditions to refract the light source with, if refraction is corrected for.
+    refraction: Option<Atmosphere>,
+    /// Observed conditions that take precedence over `refraction`.
+    weather: Option<Arc<WeatherSeries>>,
+    /// Lookup table of the last light source position, shared by clones and sweep threads.
+    lut_cache: Arc<Mutex<Option<CachedLut>>>,
+    profile: Option<Arc<Mutex<SimulationProfile>>>,
+    /// Whether the harness evaluates pixels on the rayon pool rather than the calling thread.
+    parallel: bool,
+}
+
+#[derive(Debug)]
+struct CachedLut {
+    source: LightSource,
+    position: CelestialPosition,
+    lut: Arc<SkyLut>,
+}
+
+impl Sky {
+    /// A Rayleigh sky lit by the sun, clear at a `turbidity` of 1 and hazier above.
+    pub fn new(turbidity: f64) -> Self {
+        Self {
+            backend: SkyModelBackend::Rayleigh,
+            turbidity,
+            table: None,
+            light_source: LightSourceMode::Sun,
+            dop_calibration: None,
+            lut_resolution_deg: None,
+            altitude: AltitudeConfig::default(),
+            refraction: None,
+            weather: None,
+            lut_cache: Arc::default(),
+            profile: None,
+            parallel: true,
+        }
+    }
+
+    pub fn with_backend(mut self, backend: SkyModelBackend) -> Self {
+        self.backend = backend;
+        self
+    }
+
+    pub fn with_turbidity(mut self, turbidity: f64) -> Self {
+        self.turbidity = turbidity;
+        self
+    }
+
+    pub fn with_table(mut self, table: SkyTable) -> Self {
+        self.table = Some(table);
+        self
+    }
+
+    /// Overrides or clamps the altitude the sky is simulated at.
+    pub fn with_altitude(mut self, altitude: AltitudeConfig) -> Self {
+        self.altitude = altitude;
+        self
+    }
+
+    pub fn altitude(&self) -> AltitudeConfig {
+        self.altitude
+    }
+
+    /// Corrects the light source position for atmospheric refraction under these conditions.
+    pub fn with_refraction(mut self, refraction: Option<Atmosphere>) -> Self {
+        self.refraction = refraction;
+        self
+    }
+
+    /// Takes the temperature and pressure for refraction from a weather log where it has them.
+    pub fn with_weather(mut self, weather: WeatherSeries) -> Self {
+        self.weather = Some(Arc::new(weather));
+        self
+    }
+
+    pub fn weather(&self) -> Option<&WeatherSeries> {
+        self.weather.as_deref()
+    }
+
+    /// Conditions the light source is refracted with at a time, if refraction is enabled.
+    pub fn atmosphere(&self, time: DateTime<Utc>) -> Option<Atmosphere> {
+        let default = self.refraction?;
+        Some(match &self.weather {
+            Some(weather) => weather.atmosphere(time, default),
+            None => default,
+        })
+    }
+
+    pub fn with_light_source(mut self, light_source: LightSourceMode) -> Self {
+        self.light_source = light_source;
+        self
+    }
+
+    /// Corrects the simulated DoP towards what the camera measures at each elevation.
+    pub fn with_dop_calibration(mut self, dop_calibration: DopCalibration) -> Self {
+        self.dop_calibration = Some(dop_calibration);
+        self
+    }
+
+    /// Evaluates the analytic models through a lookup table with the given grid spacing.
+    ///
+    /// The table is computed once per light source position, which every candidate of a frame
+    /// shares, and is interpolated bilinearly. The Rayleigh sky is then evaluated by the harness
+    /// instead of rumpus. Empirical skies are already a table and are not affected. The spacing
+    /// must be finite and positive.
+    pub fn with_lut_resolution(mut self, lut_resolution_deg: Option<f64>) -> Self {
+        self.lut_resolution_deg = lut_resolution_deg;
+        self.lut_cache = Arc::default();
+        self
+    }
+
+    /// Times every row of the skies evaluated by the harness, and every Rayleigh sky simulated
+    /// by rumpus as a whole.
+    ///
+    /// Clones of the sky add to the same profile.
+    pub fn with_profiling(mut self, profiling: bool) -> Self {
+        self.profile = profiling.then(Arc::default);
+        self
+    }
+
+    /// Evaluates the pixels of harness skies on the calling thread when not `parallel`.
+    ///
+    /// rumpus simulates sunlit Rayleigh skies on the rayon pool either way.
+    pub fn with_parallel(mut self, parallel: bool) -> Self {
+        self.parallel = parallel;
+        self
+    }
+
+    pub(crate) fn parallel(&self) -> bool {
+        self.parallel
+    }
+
+    /// Row times gathered so far, if profiling.
+    pub fn profile(&self) -> Option<SimulationProfile> {
+        self.profile
+            .as_ref()
+            .map(|profile| profile.lock().unwrap().clone())
+    }
+
+    pub fn backend(&self) -> SkyModelBackend {
+        self.backend
+    }
+
+    pub fn turbidity(&self) -> f64 {
+        self.turbidity
+    }
+
+    pub fn light_source_mode(&self) -> LightSourceMode {
+        self.light_source
+    }
+
+    pub fn lut_resolution_deg(&self) -> Option<f64> {
+        self.lut_resolution_deg
+    }
+
+    /// Compares the lookup table against the analytic model at the centre of every cell, where
+    /// interpolation is least accurate, for the sun at a range of elevations.
+    ///
+    /// `None` without a lookup table or for an empirical sky.
+    pub fn lut_accuracy(&self) -> Option<LutAccuracy> {
+        let resolution_deg = self.lut_resolution_deg?;
+        let distance = self.neutral_point_distance()?;
+
+        let mut accuracy = LutAccuracy::new(resolution_deg);
+        for elevation_deg in LUT_ACCURACY_ELEVATIONS_DEG {
+            let source = CelestialPosition {
+                azimuth: Angle::ZERO,
+                elevation: Angle::new::<degree>(elevation_deg),
+            };
+            let model = |view: &SkyDirection| berry(view, &source, distance);
+            accuracy.add(&SkyLut::build(resolution_deg, model), model);
+        }
+        Some(accuracy)
+    }
+
+    /// Neutral point distance of the analytic models that a lookup table can stand in for.
+    fn neutral_point_distance(&self) -> Option<Angle> {
+        match self.backend {
+            SkyModelBackend::Rayleigh => Some(Angle::ZERO),
+            SkyModelBackend::Berry => Some(Angle::new::<degree>(NEUTRAL_POINT_DISTANCE_DEG)),
+            SkyModelBackend::Empirical => None,
+        }
+    }
+
+    /// Lookup table of the light source at its current position, computed on first use.
+    fn lut(&self, light_source: LightSource, source: &CelestialPosition) -> Option<Arc<SkyLut>> {
+        let resolution_deg = self.lut_resolution_deg?;
+        let distance = self.neutral_point_distance()?;
+        // Holding the lock while building keeps parallel candidates from building it again.
+        let mut cache = self.lut_cache.lock().unwrap();
+        if let Some(cached) = cache.as_ref()
+            && cached.source == light_source
+            && cached.position == *source
+        {
+            return Some(Arc::clone(&cached.lut));
+        }
+
+        let lut = Arc::new(SkyLut::build(resolution_deg, |view| {
+            berry(view, source, distance)
+        }));
+        *cache = Some(CachedLut {
+            source: light_source,
+            position: *source,
+            lut: Arc::clone(&lut),
+        });
+        Some(lut)
+    }
+
+    /// Picks the light source for a frame and returns where it is in the sky.
+    pub fn light_source(
+        &self,
+        position: &Wgs84,
+        time: DateTime<Utc>,
+    ) -> (LightSource, CelestialPosition) {
+        let atmosphere = self.atmosphere(time);
+        let refract = |position: CelestialPosition| match &atmosphere {
+            Some(atmosphere) => position.refracted(atmosphere),
+            None => position,
+        };
+        let sun = || {
+            (
+                LightSource::Sun,
+                refract(CelestialPosition::sun(position, time)),
+            )
+        };
+        let moon = || {
+            (
+                LightSource::Moon,
+                refract(CelestialPosition::moon(position, time)),
+            )
+        };
+
+        match self.light_source {
+            LightSourceMode::Sun => sun(),
+            LightSourceMode::Moon => moon(),
+            LightSourceMode::Auto => {
+                let (source, sun_position) = sun();
+                if sun_position.elevation >= Angle::new::<degree>(TWILIGHT_ELEVATION_DEG) {
+                    (source, sun_position)
+                } else {
+                    moon()
+                }
+            }
+        }
+    }
+
+    pub fn simulate(
+        &self,
+        camera: &CameraModel,
+        position: &Wgs84,
+        car_in_ins_enu: Orientation<InsEnu>,
+        time: DateTime<Utc>,
+    ) -> Result<RayImage<GlobalFrame>, BenchError> {
+        self.simulate_with(
+            camera,
+            position,
+            car_in_ins_enu,
+            time,
+            &mut Scratch::default(),
+        )
+    }
+
+    /// Like [`Sky::simulate`], evaluating the sky models in reused buffers.
+    pub fn simulate_with(
+        &self,
+        camera: &CameraModel,
+        position: &Wgs84,
+        car_in_ins_enu: Orientation<InsEnu>,
+        time: DateTime<Utc>,
+        scratch: &mut Scratch,
+    ) -> Result<RayImage<GlobalFrame>, BenchError> {
+        let geometry = self.source_geometry(position, time);
+        self.simulate_with_geometry(camera, &geometry, car_in_ins_enu, scratch)
+    }
+
+    /// Where the light source is for a frame, to share between every attitude simulated for it.
+    pub fn source_geometry(&self, position: &Wgs84, time: DateTime<Utc>) -> SourceGeometry {
+        let position = self.altitude.apply(position);
+        let (light_source, source) = self.light_source(&position, time);
+        let sun_observer = self
+            .simulates_with_rumpus(light_source)
+            .then(|| SunObserver::new(&position, time, &source));
+        SourceGeometry {
+            position,
+            time,
+            light_source,
+            source,
+            sun_observer,
+        }
+    }
+
+    /// Like [`Sky::simulate_with`], with the light source already located.
+    pub fn simulate_with_geometry(
+        &self,
+        camera: &CameraModel,
+        geometry: &SourceGeometry,
+        car_in_ins_enu: Orientation<InsEnu>,
+        scratch: &mut Scratch,
+    ) -> Result<RayImage<GlobalFrame>, BenchError> {
+        let light_source = geometry.light_source;
+        let simulated = if self.simulates_with_rumpus(light_source) {
+            let mut image_ms = 0.;
+            let simulated = timed(&mut image_ms, || {
+                simulate_with_rumpus(camera, geometry, car_in_ins_enu)
+            })?;
+            if let Some(profile) = &self.profile {
+                profile
+                    .lock()
+                    .unwrap()
+                    .add_whole(image_ms * 1e6, camera.rows(), camera.cols());
+            }
+            simulated
+        } else {
+            let model = self.pixel_model(light_source, &geometry.source)?;
+            self.simulate_per_pixel(camera, car_in_ins_enu, scratch, model)?
+        };
+        Ok(self.finish(&simulated, geometry, camera, car_in_ins_enu, scratch))
+    }
+
+    /// Whether skies lit by `light_source` are simulated by rumpus rather than pixel by pixel,
+    /// which it does for the sun without a lookup table.
+    pub(crate) fn simulates_with_rumpus(&self, light_source: LightSource) -> bool {
+        self.lut_resolution_deg.is_none()
+            && self.backend == SkyModelBackend::Rayleigh
+            && light_source == LightSource::Sun
+    }
+
+    /// Polarization the configured model predicts in each direction, before the DoP is scaled.
+    ///
+    /// Rayleigh skies are evaluated as a Berry sky without neutral point separation here, which
+    /// agrees with rumpus.
+    pub(crate) fn pixel_model(
+        &self,
+        light_source: LightSource,
+        source: &CelestialPosition,
+    ) -> Result<PixelModel<'_>, BenchError> {
+        if let Some(lut) = self.lut(light_source, source) {
+            return Ok(Box::new(move |view| lut.lookup(view)));
+        }
+        let source = *source;
+        Ok(match self.backend {
+            SkyModelBackend::Rayleigh => Box::new(move |view| berry(view, &source, Angle::ZERO)),
+            SkyModelBackend::Berry => {
+                let distance = Angle::new::<degree>(NEUTRAL_POINT_DISTANCE_DEG);
+                Box::new(move |view| berry(view, &source, distance))
+            }
+            SkyModelBackend::Empirical => {
+                let table = self.table.as_ref().ok_or_else(|| {
+                    BenchError::Config("empirical sky model requires a table".to_string())
+                })?;
+                Box::new(move |view| table.lookup(view, &source))
+            }
+        })
+    }
+
+    /// Turbidity at an altitude in m, taking the configured turbidity to be at sea level.
+    ///
+    /// Aerosols thin out with height faster than the air does, so skies above the haze are
+    /// closer to clear.
+    pub fn turbidity_at(&self, altitude_m: f64) -> f64 {
+        let thinning = (-altitude_m.max(0.)
+            * (AEROSOL_SCALE_HEIGHT_M.recip() - RAYLEIGH_SCALE_HEIGHT_M.recip()))
+        .exp();
+        1. + (self.turbidity - 1.) * thinning
+    }
+
+    /// Scales the DoP of a modelled sky for the turbidity at its altitude and its light source,
+    /// then calibrates it.
+    pub(crate) fn finish(
+        &self,
+        simulated: &RayImage<GlobalFrame>,
+        geometry: &SourceGeometry,
+        camera: &CameraModel,
+        car_in_ins_enu: Orientation<InsEnu>,
+        scratch: &mut Scratch,
+    ) -> RayImage<GlobalFrame> {
+        let turbidity = self.turbidity_at(geometry.position.altitude().get::<meter>());
+        let dop_scale = match geometry.light_source {
+            LightSource::Sun => turbidity.recip(),
+            LightSource::Moon => turbidity.recip() * LUNAR_DOP_SCALE,
+        };
+        let simulated = scale_dop(simulated, dop_scale);
+        match &self.dop_calibration {
+            Some(dop_calibration) => {
+                sky_directions_into(camera, car_in_ins_enu, &mut scratch.directions);
+                dop_calibration.apply(&simulated, &scratch.directions)
+            }
+            None => simulated,
+        }
+    }
+
+    /// Simulates the sky averaged over an exposure during which the car yawed by `yaw_smear`.
+    pub fn simulate_smeared(
+        &self,
+        camera: &CameraModel,
+        position: &Wgs84,
+        car_in_ins_enu: Orientation<InsEnu>,
+        time: DateTime<Utc>,
+        yaw_smear: Angle,
+    ) -> Result<RayImage<GlobalFrame>, BenchError> {
+        self.simulate_smeared_with(
+            camera,
+            position,
+            car_in_ins_enu,
+            time,
+            yaw_smear,
+            &mut Scratch::default(),
+        )
+    }
+
+    /// Like [`Sky::simulate_smeared`], evaluating the sky models in reused buffers.
+    pub fn simulate_smeared_with(
+        &self,
+        camera: &CameraModel,
+        position: &Wgs84,
+        car_in_ins_enu: Orientation<InsEnu>,
+        time: DateTime<Utc>,
+        yaw_smear: Angle,
+        scratch: &mut Scratch,
+    ) -> Result<RayImage<GlobalFrame>, BenchError> {
+        let geometry = self.source_geometry(position, time);
+        self.simulate_smeared_with_geometry(camera, &geometry, car_in_ins_enu, yaw_smear, scratch)
+    }
+
+    /// Like [`Sky::simulate_smeared_with`], with the light source already located.
+    pub fn simulate_smeared_with_geometry(
+        &self,
+        camera: &CameraModel,
+        geometry: &SourceGeometry,
+        car_in_ins_enu: Orientation<InsEnu>,
+        yaw_smear: Angle,
+        scratch: &mut Scratch,
+    ) -> Result<RayImage<GlobalFrame>, BenchError> {
+        if yaw_smear == Angle::ZERO {
+            return self.simulate_with_geometry(camera, geometry, car_in_ins_enu, scratch);
+        }
+
+        let (yaw, pitch, roll) = car_in_ins_enu.to_tait_bryan_angles();
+        let exposures = (0..SMEAR_SAMPLES)
+            .map(|i| {
+                #[allow(clippy::cast_precision_loss)]
+                let fraction = i as f64 / (SMEAR_SAMPLES - 1) as f64 - 0.5;
+                let car_in_ins_enu = Orientation::tait_bryan_builder()
+                    .yaw(yaw + yaw_smear * fraction)
+                    .pitch(pitch)
+                    .roll(roll)
+                    .build();
+                self.simulate_with_geometry(camera, geometry, car_in_ins_enu, scratch)
+            })
+            .collect::<Result<Vec<_>, _>>()?;
+
+        Ok(average_rays(&exposures))
+    }
+
+    fn simulate_per_pixel<M>(
+        &self,
+        camera: &CameraModel,
+        car_in_ins_enu: Orientation<InsEnu>,
+        scratch: &mut Scratch,
+        model: M,
+    ) -> Result<RayImage<GlobalFrame>, BenchError>
+    where
+        M: Fn(&SkyDirection) -> Option<(Angle, f64)> + Sync,
+    {
+        let evaluate = |view: &Option<SkyDirection>| {
+            let (aop, dop) = model(view.as_ref()?)?;
+            Some(Ray::new(Aop::from_angle_wrapped(aop), dop))
+        };
+        sky_directions_into(camera, car_in_ins_enu, &mut scratch.directions);
+
+        match &self.profile {
+            None if self.parallel => scratch
+                .directions
+                .par_iter()
+                .map(evaluate)
+                .collect_into_vec(&mut scratch.rays),
+            None => {
+                scratch.rays.clear();
+                scratch.rays.extend(scratch.directions.iter().map(evaluate));
+            }
+            Some(profile) => {
+                // Evaluate row by row so each row can be timed.
+                let time_row = |row: &[Option<SkyDirection>]| {
+                    let mut row_ns = 0.;
+                    let rays: Vec<_> = timed(&mut row_ns, || row.iter().map(evaluate).collect());
+                    // `timed` measures milliseconds.
+                    (rays, row_ns * 1e6)
+                };
+                let rows: Vec<(Vec<_>, f64)> = if self.parallel {
+                    scratch
+                        .directions
+                        .par_chunks(camera.cols())
+                        .map(time_row)
+                        .collect()
+                } else {
+                    scratch
+                        .directions
+                        .chunks(camera.cols())
+                        .map(time_row)
+                        .collect()
+                };
+                let row_ns: Vec<f64> = rows.iter().map(|(_, ns)| *ns).collect();
+                profile.lock().unwrap().add(&row_ns, camera.cols());
+                scratch.rays.clear();
+                scratch
+                    .rays
+                    .extend(rows.into_iter().flat_map(|(rays, _)| rays));
+            }
+        }
+
+        RayImage::from_rays(scratch.rays.drain(..), camera.rows(), camera.cols())
+            .map_err(|e| BenchError::Simulation(e.to_string()))
+    }
+}
+
+impl Default for Sky {
+    fn default() -> Self {
+        Self::new(1.0)
+    }
+}
+
+/// Finds the turbidity that minimizes the DoP residuals between a clear sky and a measurement.
+///
+/// The fit is the least-squares scale between simulated and measured DoP, clamped to a clear
+/// sky when the measurement is more polarized than the simulation.
+pub fn fit_turbidity<F: Copy>(clear: &RayImage<F>, measured: &RayImage<F>) -> f64 {
+    let mut sum_product = 0.0f64;
+    let mut sum_squares = 0.0f64;
+
+    for rpx in measured.pixels() {
+        if let Some(measured_ray) = rpx.ray()
+            && let Some(clear_ray) = clear.ray(rpx.row(), rpx.col())
+        {
+            sum_product += measured_ray.dop() * clear_ray.dop();
+            sum_squares += clear_ray.dop().powf(2.);
+        }
+    }
+
+    let scale = sum_product / sum_squares;
+    if scale.is_finite() && scale > 0.0 {
+        scale.recip().max(1.0)
+    } else {
+        1.0
+    }
+}
+
+pub fn scale_dop<F: Copy>(ray_image: &RayImage<F>, scale: f64) -> RayImage<F> {
+    let rays: Vec<_> = ray_image
+        .pixels()
+        .map(|px| {
+            let ray = px.ray()?;
+            Some(Ray::<F>::new(ray.aop(), ray.dop() * scale))
+        })
+        .collect();
+
+    RayImage::from_rays(rays, ray_image.rows(), ray_image.cols()).unwrap()
+}
+
+/// Averages the linear polarization of several images pixel by pixel.
+///
+/// Pixels that are invalid in any image are invalid in the average.
+pub fn average_rays<F: Copy>(ray_images: &[RayImage<F>]) -> RayImage<F> {
+    let (rows, cols) = (ray_images[0].rows(), ray_images[0].cols());
+    #[allow(clippy::cast_precision_loss)]
+    let count = ray_images.len() as f64;
+
+    let rays: Vec<_> = (0..rows)
+        .flat_map(|row| (0..cols).map(move |col| (row, col)))
+        .map(|(row, col)| {
+            // Average the normalized Stokes parameters, since AoP wraps around.
+            let (mut q, mut u) = (0., 0.);
+            for ray_image in ray_images {
+                let ray = ray_image.ray(row, col)?;
+                let aop = Angle::from(ray.aop()).get::<radian>();
+                q += ray.dop() * (2. * aop).cos();
+                u += ray.dop() * (2. * aop).sin();
+            }
+
+            let aop = Angle::new::<radian>(u.atan2(q) / 2.);
+            Some(Ray::new(Aop::from_angle_wrapped(aop), q.hypot(u) / count))
+        })
+        .collect();
+
+    RayImage::from_rays(rays, rows, cols).unwrap()
+}
+
+/// Position of the light source for one frame, found once and shared by every candidate
+/// attitude simulated for it.
+///
+/// Skies simulated by rumpus locate the sun themselves, from an observer moved to see it here.
+#[derive(Debug, Clone)]
+pub struct SourceGeometry {
+    /// Where the sky is simulated from, after any altitude override.
+    pub position: Wgs84,
+    pub time: DateTime<Utc>,
+    pub light_source: LightSource,
+    pub source: CelestialPosition,
+    /// Where rumpus is simulated from, if it simulates this sky.
+    pub sun_observer: Option<SunObserver>,
+}
+
+/// Where an observer sees the unrefracted sun at the position of a light source, and how far
+/// the car turns about the vertical there to keep the same bearing to it.
+///
+/// Walking towards the sun along a great circle raises it by the angle walked, and the turn
+/// makes up for the change of north, which leaves the AoP relative to the local meridian and
+/// the DoP of every pixel unchanged.
+#[derive(Debug, Clone, Copy)]
+pub struct SunObserver {
+    pub position: Wgs84,
+    /// Change of the sun's azimuth, clockwise.
+    pub turn: Angle,
+}
+
+impl SunObserver {
+    pub fn new(position: &Wgs84, time: DateTime<Utc>, source: &CelestialPosition) -> Self {
+        let sun = CelestialPosition::sun(position, time);
+        let lift = (source.elevation - sun.elevation).get::<radian>();
+        if lift == 0. {
+            return Self {
+                position: *position,
+                turn: Angle::ZERO,
+            };
+        }
+
+        let latitude = position.latitude().get::<radian>();
+        let azimuth = sun.azimuth.get::<radian>();
+        let moved_latitude =
+            (latitude.sin() * lift.cos() + latitude.cos() * lift.sin() * azimuth.cos()).asin();
+        let moved_longitude = position.longitude().get::<radian>()
+            + (azimuth.sin() * lift.sin() * latitude.cos())
+                .atan2(lift.cos() - latitude.sin() * moved_latitude.sin());
+        let moved = InsEnu::position_from_inspva(
+            moved_latitude.to_degrees(),
+            (moved_longitude.to_degrees() + 180.).rem_euclid(360.) - 180.,
+            position.altitude().get::<meter>(),
+        );
+        Self {
+            position: moved,
+            turn: CelestialPosition::sun(&moved, time).azimuth - source.azimuth,
+        }
+    }
+
+    /// The attitude of the car at the observer's position.
+    pub fn attitude(&self, car_in_ins_enu: Orientation<InsEnu>) -> Orientation<InsEnu> {
+        // Yaw is right-handed about the vertical, so it turns against the azimuth.
+        let (yaw, pitch, roll) = car_in_ins_enu.to_tait_bryan_angles();
+        Orientation::tait_bryan_builder()
+            .yaw(yaw - self.turn)
+            .pitch(pitch)
+            .roll(roll)
+            .build()
+    }
+}
+
+/// Direction in the sky as seen from the camera.
+#[derive(Debug, Clone, Copy)]
+pub struct SkyDirection {
+    /// Measured clockwise from true north.
+    pub azimuth: Angle,
+    /// Measured from the zenith.
+    pub zenith: Angle,
+}
+
+impl From<&CelestialPosition> for SkyDirection {
+    fn from(position: &CelestialPosition) -> Self {
+        Self {
+            azimuth: position.azimuth,
+            zenith: Angle::HALF_TURN / 2. - position.elevation,
+        }
+    }
+}
+
+impl From<&SkyDirection> for CelestialPosition {
+    fn from(direction: &SkyDirection) -> Self {
+        Self {
+            azimuth: direction.azimuth,
+            elevation: Angle::HALF_TURN / 2. - direction.zenith,
+        }
+    }
+}
+
+/// Returns the pixel that looks at a celestial body, if it is inside the camera FOV.
+pub fn source_pixel(
+    camera: &CameraModel,
+    car_in_ins_enu: Orientation<InsEnu>,
+    source: &CelestialPosition,
+) -> Option<(usize, usize)> {
+    camera.pixel(SkyAxes::new(car_in_ins_enu).bearing(source))
+}
+
+/// East, north and up as unit vectors in the camera frame under one attitude.
+pub(crate) struct SkyAxes {
+    east: [f64; 3],
+    north: [f64; 3],
+    up: [f64; 3],
+}
+
+impl SkyAxes {
+    pub(crate) fn new(car_in_ins_enu: Orientation<InsEnu>) -> Self {
+        Self {
+            east: unit(systems::east_in_cam(car_in_ins_enu)),
+            north: unit(systems::north_in_cam(car_in_ins_enu)),
+            up: unit(systems::up_in_cam(car_in_ins_enu)),
+        }
+    }
+
+    /// Unit bearing in the camera frame towards a position in the sky.
+    pub(crate) fn bearing(&self, position: &CelestialPosition) -> [f64; 3] {
+        let azimuth = position.azimuth.get::<radian>();
+        let elevation = position.elevation.get::<radian>();
+        let (e, n, u) = (
+            elevation.cos() * azimuth.sin(),
+            elevation.cos() * azimuth.cos(),
+            elevation.sin(),
+        );
+        [0, 1, 2].map(|i| e * self.east[i] + n * self.north[i] + u * self.up[i])
+    }
+
+    /// Sky direction along a unit bearing in the camera frame, if it is above the horizon.
+    pub(crate) fn direction(&self, bearing: &[f64; 3]) -> Option<SkyDirection> {
+        let u = dot(bearing, &self.up);
+        if u <= 0. {
+            return None;
+        }
+
+        Some(SkyDirection {
+            azimuth: Angle::new::<radian>(
+                dot(bearing, &self.east).atan2(dot(bearing, &self.north)),
+            ),
+            zenith: Angle::new::<radian>(u.acos()),
+        })
+    }
+}
+
+/// Returns the sky direction seen by every pixel in row-major order.
+///
+/// Pixels that look below the horizon are `None`.
+pub fn sky_directions(
+    camera: &CameraModel,
+    car_in_ins_enu: Orientation<InsEnu>,
+) -> Vec<Option<SkyDirection>> {
+    let mut directions = Vec::new();
+    sky_directions_into(camera, car_in_ins_enu, &mut directions);
+    directions
+}
+
+/// Like [`sky_directions`], replacing the contents of `directions` without reallocating it.
+pub fn sky_directions_into(
+    camera: &CameraModel,
+    car_in_ins_enu: Orientation<InsEnu>,
+    directions: &mut Vec<Option<SkyDirection>>,
+) {
+    let axes = SkyAxes::new(car_in_ins_enu);
+    directions.clear();
+    directions.extend(
+        PixelGrid::shared(camera)
+            .bearings()
+            .iter()
+            .map(|bearing| axes.direction(bearing)),
+    );
+}
+
+/// Simulates the sunlit sky of `geometry` with rumpus.
+///
+/// rumpus places the sun itself, so the camera is moved to the geometry's [`SunObserver`] to see
+/// the sun at `geometry.source`, such as after refraction. rumpus also always centers the
+/// optical axis, so an off-center camera is cut out of a larger centered one, to the nearest
+/// pixel.
+#[allow(
+    clippy::cast_precision_loss,
+    clippy::cast_possible_truncation,
+    clippy::cast_sign_loss
+)]
+fn simulate_with_rumpus(
+    camera: &CameraModel,
+    geometry: &SourceGeometry,
+    car_in_ins_enu: Orientation<InsEnu>,
+) -> Result<RayImage<GlobalFrame>, BenchError> {
+    let observer = geometry
+        .sun_observer
+        .unwrap_or_else(|| SunObserver::new(&geometry.position, geometry.time, &geometry.source));
+    let (position, car_in_ins_enu) = (observer.position, observer.attitude(car_in_ins_enu));
+    let cam_in_car = systems::cam_to_car().transform(Orientation::<CamXyz>::aligned());
+    let cam_in_ins_enu = systems::car_to_ins(car_in_ins_enu).transform(cam_in_car);
+    let cam_in_ecef = systems::ins_to_ecef(&position).transform(cam_in_ins_enu);
+
+    let (centered, (row_offset, col_offset)) = camera.centered();
+    let simulated = Simulation::new(centered.camera(), cam_in_ecef, geometry.time).par_ray_image();
+    if centered == *camera {
+        return Ok(simulated);
+    }
+    let rays = (0..camera.rows())
+        .flat_map(|row| (0..camera.cols()).map(move |col| (row, col)))
+        .map(|(row, col)| {
+            simulated.ray(
+                (row as f64 + row_offset).round() as usize,
+                (col as f64 + col_offset).round() as usize,
+            )
+        });
+    RayImage::from_rays(rays, camera.rows(), camera.cols())
+        .map_err(|e| BenchError::Simulation(e.to_string()))
+}
+
+/// Evaluates the Berry, Dennis and Lee (2004) model of the clear sky.
+///
+/// The sky is stereographically projected so that the polarization is the complex field
+/// `w = -4 (z - a)(z - b)(1 + z a*)(1 + z b*) / ((1 + |z|²)² (1 + |a|²)(1 + |b|²))` where `a` and
+/// `b` are the Babinet and Brewster points.
+/// With a zero neutral point distance this reduces to single-scattering Rayleigh.
+fn berry(view: &SkyDirection, source: &CelestialPosition, distance: Angle) -> Option<(Angle, f64)> {
+    let source = SkyDirection::from(source);
+
+    let z = stereographic(view.zenith, view.azimuth);
+    let babinet = stereographic(source.zenith - distance, source.azimuth);
+    let brewster = stereographic(source.zenith + distance, source.azimuth);
+
+    let numerator = (z - babinet)
+        * (z - brewster)
+        * (Complex::ONE + z * babinet.conj())
+        * (Complex::ONE + z * brewster.conj());
+    let denominator =
+        (1. + z.norm_sqr()).powi(2) * (1. + babinet.norm_sqr()) * (1. + brewster.norm_sqr());
+    let w = numerator.scale(-4. / denominator);
+
+    // The polarized fraction follows the Rayleigh intensity `2 - sin²γ` in the limit.
+    let magnitude = w.norm_sqr().sqrt().min(1.);
+    let dop = magnitude / (2. - magnitude);
+
+    // Angles are preserved by the projection, so the AoP relative to the meridian is the angle
+    // of the field relative to the radial direction in the plane.
+    let aop = Angle::new::<radian>(w.arg() / 2.) - view.azimuth;
+
+    Some((aop, dop))
+}
+
+/// AoP and DoP of an analytic model sampled on a grid of zenith angle and azimuth.
+///
+/// Polarization is stored as doubled-angle Stokes components so interpolation does not break
+/// where the AoP wraps around.
+#[derive(Debug, Clone)]
+pub struct SkyLut {
+    zenith_step_deg: f64,
+    azimuth_step_deg: f64,
+    azimuth_nodes: usize,
+    /// Row per zenith node from the zenith to the horizon inclusive.
+    nodes: Vec<Option<(f64, f64)>>,
+}
+
+impl SkyLut {
+    /// Samples a model at grid nodes spaced by about `resolution_deg`.
+    #[allow(
+        clippy::cast_precision_loss,
+        clippy::cast_possible_truncation,
+        clippy::cast_sign_loss
+    )]
+    pub fn build<M>(resolution_deg: f64, model: M) -> Self
+    where
+        M: Fn(&SkyDirection) -> Option<(Angle, f64)>,
+    {
+        let zenith_steps = (90. / resolution_deg).ceil() as usize;
+        let azimuth_nodes = (360. / resolution_deg).ceil() as usize;
+        let zenith_step_deg = 90. / zenith_steps as f64;
+        let azimuth_step_deg = 360. / azimuth_nodes as f64;
+
+        // Built on the calling thread, since rayon could otherwise run another candidate that
+        // waits on the table cache from inside the build.
+        let nodes = (0..(zenith_steps + 1) * azimuth_nodes)
+            .map(|index| {
+                let view = SkyDirection {
+                    azimuth: Angle::new::<degree>(
+                        (index % azimuth_nodes) as f64 * azimuth_step_deg,
+                    ),
+                    zenith: Angle::new::<degree>((index / azimuth_nodes) as f64 * zenith_step_deg),
+                };
+                let (aop, dop) = model(&view)?;
+                let aop = 2. * aop.get::<radian>();
+                Some((dop * aop.cos(), dop * aop.sin()))
+            })
+            .collect();
+
+        Self {
+            zenith_step_deg,
+            azimuth_step_deg,
+            azimuth_nodes,
+            nodes,
+        }
+    }
+
+    /// Interpolates the AoP and DoP between the four nodes around a view direction.
+    ///
+    /// `None` below the horizon or next to a node the model could not evaluate.
+    #[allow(clippy::cast_possible_truncation, clippy::cast_sign_loss)]
+    pub fn lookup(&self, view: &SkyDirection) -> Option<(Angle, f64)> {
+        let zenith = view.zenith.get::<degree>() / self.zenith_step_deg;
+        let azimuth = view.azimuth.get::<degree>().rem_euclid(360.) / self.azimuth_step_deg;
+        let zenith_nodes = self.nodes.len() / self.azimuth_nodes;
+        if !(0. ..=(zenith_nodes - 1) as f64).contains(&zenith) {
+            return None;
+        }
+
+        let (z0, a0) = (zenith.floor() as usize, azimuth.floor() as usize);
+        let (z1, a1) = ((z0 + 1).min(zenith_nodes - 1), a0 + 1);
+        let (tz, ta) = (zenith.fract(), azimuth.fract());
+        let node = |z: usize, a: usize| self.nodes[z * self.azimuth_nodes + a % self.azimuth_nodes];
+
+        let (mut q, mut u) = (0., 0.);
+        for (z, a, weight) in [
+            (z0, a0, (1. - tz) * (1. - ta)),
+            (z0, a1, (1. - tz) * ta),
+            (z1, a0, tz * (1. - ta)),
+            (z1, a1, tz * ta),
+        ] {
+            let (node_q, node_u) = node(z, a)?;
+            q += weight * node_q;
+            u += weight * node_u;
+        }
+
+        Some((Angle::new::<radian>(u.atan2(q) / 2.), q.hypot(u)))
+    }
+}
+
+/// Worst and RMS differences between a lookup table and the model it samples.
+#[derive(Debug, Clone, Copy, serde::Serialize)]
+pub struct LutAccuracy {
+    pub resolution_deg: f64,
+    pub samples: usize,
+    pub aop_rms_deg: f64,
+    pub aop_max_deg: f64,
+    pub dop_rms: f64,
+    pub dop_max: f64,
+}
+
+impl LutAccuracy {
+    fn new(resolution_deg: f64) -> Self {
+        Self {
+            resolution_deg,
+            samples: 0,
+            aop_rms_deg: 0.,
+            aop_max_deg: 0.,
+            dop_rms: 0.,
+            dop_max: 0.,
+        }
+    }
+
+    /// Adds the differences at the centre of every cell of a table.
+    #[allow(clippy::cast_precision_loss)]
+    fn add<M>(&mut self, lut: &SkyLut, model: M)
+    where
+        M: Fn(&SkyDirection) -> Option<(Angle, f64)>,
+    {
+        // Keep running sums of squares in the RMS fields until the end.
+        let (mut aop_sum, mut dop_sum) = (
+            self.aop_rms_deg.powi(2) * self.samples as f64,
+            self.dop_rms.powi(2) * self.samples as f64,
+        );
+        let zenith_cells = lut.nodes.len() / lut.azimuth_nodes - 1;
+        for z in 0..zenith_cells {
+            for a in 0..lut.azimuth_nodes {
+                let view = SkyDirection {
+                    azimuth: Angle::new::<degree>((a as f64 + 0.5) * lut.azimuth_step_deg),
+                    zenith: Angle::new::<degree>((z as f64 + 0.5) * lut.zenith_step_deg),
+                };
+                let (Some((lut_aop, lut_dop)), Some((aop, dop))) =
+                    (lut.lookup(&view), model(&view))
+                else {
+                    continue;
+                };
+
+                // AoP is axial, so differences wrap at 180 degrees.
+                let aop_error_deg = ((lut_aop - aop).get::<degree>() + 90.).rem_euclid(180.) - 90.;
+                let dop_error = lut_dop - dop;
+                aop_sum += aop_error_deg.powi(2);
+                dop_sum += dop_error.powi(2);
+                self.aop_max_deg = self.aop_max_deg.max(aop_error_deg.abs());
+                self.dop_max = self.dop_max.max(dop_error.abs());
+                self.samples += 1;
+            }
+        }
+
+        let samples = self.samples.max(1) as f64;
+        self.aop_rms_deg = (aop_sum / samples).sqrt();
+        self.dop_rms = (dop_sum / samples).sqrt();
+    }
+
+    pub fn write<P: AsRef<Path>>(&self, results_dir: P) -> Result<(), BenchError> {
+        write_json(results_dir.as_ref().join("sky_lut_accuracy.json"), self)
+    }
+
+    pub fn print(&self) {
+        println!(
+            "sky lookup table at {} deg: AoP error rms {:.4} deg, max {:.4} deg; DoP error rms {:.5}, max {:.5} over {} samples",
+            self.resolution_deg,
+            self.aop_rms_deg,
+            self.aop_max_deg,
+            self.dop_rms,
+            self.dop_max,
+            self.samples
+        );
+    }
+}
+
+/// AoP and DoP binned by zenith angle and azimuth relative to the light source.
+#[derive(Debug, Clone)]
+pub struct SkyTable {
+    resolution_deg: f64,
+    cells: Vec<Option<(f64, f64)>>,
+}
+
+#[derive(Debug, serde::Serialize, serde::Deserialize)]
+struct SkyTableRecord {
+    resolution_deg: f64,
+    zenith_deg: f64,
+    relative_azimuth_deg: f64,
+    aop_deg: f64,
+    dop: f64,
+}
+
+impl SkyTable {
+    /// Reads a table with one row per populated cell.
+    ///
+    /// Cells are keyed by their lower zenith and relative-azimuth edge in degrees.
+    pub fn read_csv<P: AsRef<Path>>(path: P) -> Result<Self, BenchError> {
+        let path = path.as_ref();
+        let mut reader = csv::Reader::from_path(path).map_err(|e| BenchError::dataset(path, e))?;
+        let records = reader
+            .deserialize()
+            .enumerate()
+            .map(|(i, record)| record.map_err(|e| BenchError::parse(path, i, e)))
+            .collect::<Result<Vec<SkyTableRecord>, _>>()?;
+
+        let resolution_deg = records
+            .first()
+            .ok_or_else(|| BenchError::parse(path, 0, "sky table is empty"))?
+            .resolution_deg;
+        if let Some(i) = records
+            .iter()
+            .position(|record| record.resolution_deg != resolution_deg)
+        {
+            return Err(BenchError::parse(path, i, "sky table mixes resolutions"));
+        }
+
+        let mut table = Self::empty(resolution_deg);
+        for (i, record) in records.into_iter().enumerate() {
+            // Index by the cell centre so rounding on the edges can't shift a record.
+            let half = resolution_deg / 2.;
+            let index = table
+                .index(record.zenith_deg + half, record.relative_azimuth_deg + half)
+                .ok_or_else(|| BenchError::parse(path, i, "cell out of range"))?;
+            table.cells[index] = Some((record.aop_deg, record.dop));
+        }
+
+        Ok(table)
+    }
+
+    fn empty(resolution_deg: f64) -> Self {
+        let (zenith_bins, azimuth_bins) = Self::bins(resolution_deg);
+        Self {
+            resolution_deg,
+            cells: vec![None; zenith_bins * azimuth_bins],
+        }
+    }
+
+    #[allow(clippy::cast_possible_truncation, clippy::cast_sign_loss)]
+    fn bins(resolution_deg: f64) -> (usize, usize) {
+        (
+            (90. / resolution_deg).ceil() as usize,
+            (360. / resolution_deg).ceil() as usize,
+        )
+    }
+
+    #[allow(clippy::cast_possible_truncation, clippy::cast_sign_loss)]
+    fn index(&self, zenith_deg: f64, relative_azimuth_deg: f64) -> Option<usize> {
+        let (zenith_bins, azimuth_bins) = Self::bins(self.resolution_deg);
+        let zenith = (zenith_deg / self.resolution_deg).floor();
+        let azimuth =
+            ((relative_azimuth_deg + 180.).rem_euclid(360.) / self.resolution_deg).floor();
+        if zenith < 0. || zenith as usize >= zenith_bins {
+            return None;
+        }
+
+        Some(zenith as usize * azimuth_bins + (azimuth as usize).min(azimuth_bins - 1))
+    }
+
+    pub fn write_csv<P: AsRef<Path>>(&self, path: P) -> Result<(), BenchError> {
+        let path = path.as_ref();
+        let output_error = |e| BenchError::output(path.display(), e);
+        let (_, azimuth_bins) = Self::bins(self.resolution_deg);
+        let mut writer = csv::Writer::from_path(path).map_err(output_error)?;
+        for (index, cell) in self.cells.iter().enumerate() {
+            let Some((aop_deg, dop)) = *cell else {
+                continue;
+            };
+
+            #[allow(clippy::cast_precision_loss)]
+            writer
+                .serialize(SkyTableRecord {
+                    resolution_deg: self.resolution_deg,
+                    zenith_deg: (index / azimuth_bins) as f64 * self.resolution_deg,
+                    relative_azimuth_deg: (index % azimuth_bins) as f64 * self.resolution_deg
+                        - 180.,
+                    aop_deg,
+                    dop,
+                })
+                .map_err(output_error)?;
+        }
+
+        writer
+            .flush()
+            .map_err(|e| BenchError::output(path.display(), e))?;
+        Ok(())
+    }
+
+    /// Returns the AoP and DoP of the cell a view direction falls into.
+    pub fn lookup(&self, view: &SkyDirection, source: &CelestialPosition) -> Option<(Angle, f64)> {
+        let relative_azimuth = (view.azimuth - source.azimuth).get::<degree>();
+        let index = self.index(view.zenith.get::<degree>(), relative_azimuth)?;
+        let (aop_deg, dop) = self.cells[index]?;
+
+        Some((Angle::new::<degree>(aop_deg), dop))
+    }
+}
+
+/// Accumulates measured polarization from frames with a trusted attitude into a [`SkyTable`].
+#[derive(Debug, Clone)]
+pub struct SkyTableBuilder {
+    table: SkyTable,
+    sums: Vec<CellSum>,
+}
+
+#[derive(Debug, Clone, Copy, Default)]
+struct CellSum {
+    cos_2aop: f64,
+    sin_2aop: f64,
+    dop: f64,
+    samples: usize,
+}
+
+impl SkyTableBuilder {
+    pub fn new(resolution_deg: f64) -> Self {
+        let table = SkyTable::empty(resolution_deg);
+        let sums = vec![CellSum::default(); table.cells.len()];
+        Self { table, sums }
+    }
+
+    /// Adds every pixel of a measured frame to the cell matching its position relative to the sun.
+    ///
+    /// `directions` must be in row-major order, as returned by [`sky_directions`].
+    pub fn add(
+        &mut self,
+        measured: &RayImage<GlobalFrame>,
+        directions: &[Option<SkyDirection>],
+        sun: &CelestialPosition,
+    ) {
+        for rpx in measured.pixels() {
+            let Some(ray) = rpx.ray() else {
+                continue;
+            };
+            let Some(view) = directions[rpx.row() * measured.cols() + rpx.col()] else {
+                continue;
+            };
+
+            let relative_azimuth = (view.azimuth - sun.azimuth).get::<degree>();
+            let Some(index) = self
+                .table
+                .index(view.zenith.get::<degree>(), relative_azimuth)
+            else {
+                continue;
+            };
+
+            // AoP is axial, so average the doubled angle.
+            let aop = 2. * Angle::from(ray.aop()).get::<radian>();
+            let sum = &mut self.sums[index];
+            sum.cos_2aop += aop.cos();
+            sum.sin_2aop += aop.sin();
+            sum.dop += ray.dop();
+            sum.samples += 1;
+        }
+    }
+
+    /// Averages every cell with at least `min_samples` samples.
+    #[allow(clippy::cast_precision_loss)]
+    pub fn build(mut self, min_samples: usize) -> SkyTable {
+        for (cell, sum) in self.table.cells.iter_mut().zip(&self.sums) {
+            if sum.samples == 0 || sum.samples < min_samples {
+                continue;
+            }
+
+            let aop_deg = sum.sin_2aop.atan2(sum.cos_2aop).to_degrees() / 2.;
+            *cell = Some((aop_deg, sum.dop / sum.samples as f64));
+        }
+
+        self.table
+    }
+}
+
+/// Ratio of measured to simulated DoP as a piecewise linear function of sky elevation.
+#[derive(Debug, Clone)]
+pub struct DopCalibration {
+    /// Sorted by elevation.
+    points: Vec<DopCalibrationRecord>,
+}
+
+#[derive(Debug, Clone, Copy, serde::Serialize, serde::Deserialize)]
+struct DopCalibrationRecord {
+    /// Centre of the elevation bin.
+    elevation_deg: f64,
+    dop_scale: f64,
+    samples: usize,
+}
+
+impl DopCalibration {
+    pub fn read_csv<P: AsRef<Path>>(path: P) -> Result<Self, BenchError> {
+        let path = path.as_ref();
+        let mut reader = csv::Reader::from_path(path).map_err(|e| BenchError::dataset(path, e))?;
+        let mut points = reader
+            .deserialize()
+            .enumerate()
+            .map(|(i, record)| record.map_err(|e| BenchError::parse(path, i, e)))
+            .collect::<Result<Vec<DopCalibrationRecord>, _>>()?;
+        if points.is_empty() {
+            return Err(BenchError::parse(path, 0, "DoP calibration is empty"));
+        }
+
+        points.sort_by(|a, b| a.elevation_deg.total_cmp(&b.elevation_deg));
+        Ok(Self { points })
+    }
+
+    pub fn write_csv<P: AsRef<Path>>(&self, path: P) -> Result<(), BenchError> {
+        let path = path.as_ref();
+        let output_error = |e| BenchError::output(path.display(), e);
+        let mut writer = csv::Writer::from_path(path).map_err(output_error)?;
+        for point in &self.points {
+            writer.serialize(point).map_err(output_error)?;
+        }
+
+        writer
+            .flush()
+            .map_err(|e| BenchError::output(path.display(), e))?;
+        Ok(())
+    }
+
+    /// Elevation bins in the calibration.
+    pub fn len(&self) -> usize {
+        self.points.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.points.is_empty()
+    }
+
+    /// DoP scale at an elevation, held constant beyond the first and last bins.
+    pub fn scale(&self, elevation_deg: f64) -> f64 {
+        let upper = self
+            .points
+            .partition_point(|point| point.elevation_deg < elevation_deg);
+        match (upper.checked_sub(1), self.points.get(upper)) {
+            (Some(lower), Some(upper)) => {
+                let lower = &self.points[lower];
+                let fraction = (elevation_deg - lower.elevation_deg)
+                    / (upper.elevation_deg - lower.elevation_deg);
+                lower.dop_scale + (upper.dop_scale - lower.dop_scale) * fraction
+            }
+            (None, Some(point)) => point.dop_scale,
+            (Some(lower), None) => self.points[lower].dop_scale,
+            (None, None) => 1.,
+        }
+    }
+
+    /// Scales the DoP of every pixel by the calibration at the elevation it looks at.
+    ///
+    /// `directions` must be in row-major order, as returned by [`sky_directions`].
+    pub fn apply(
+        &self,
+        simulated: &RayImage<GlobalFrame>,
+        directions: &[Option<SkyDirection>],
+    ) -> RayImage<GlobalFrame> {
+        let rays: Vec<_> = simulated
+            .pixels()
+            .map(|px| {
+                let ray = px.ray()?;
+                let scale = directions[px.row() * simulated.cols() + px.col()]
+                    .map_or(1., |view| self.scale(90. - view.zenith.get::<degree>()));
+                Some(Ray::new(ray.aop(), (ray.dop() * scale).clamp(0., 1.)))
+            })
+            .collect();
+
+        RayImage::from_rays(rays, simulated.rows(), simulated.cols()).unwrap()
+    }
+}
+
+/// Accumulates measured against simulated DoP by sky elevation into a [`DopCalibration`].
+#[derive(Debug, Clone)]
+pub struct DopCalibrationBuilder {
+    resolution_deg: f64,
+    bins: Vec<DopBin>,
+}
+
+#[derive(Debug, Clone, Copy, Default)]
+struct DopBin {
+    sum_product: f64,
+    sum_squares: f64,
+    samples: usize,
+}
+
+impl DopCalibrationBuilder {
+    #[allow(clippy::cast_possible_truncation, clippy::cast_sign_loss)]
+    pub fn new(resolution_deg: f64) -> Self {
+        Self {
+            resolution_deg,
+            bins: vec![DopBin::default(); (90. / resolution_deg).ceil() as usize],
+        }
+    }
+
+    /// Adds every pixel that is valid in both images to the bin of its elevation.
+    ///
+    /// `directions` must be in row-major order, as returned by [`sky_directions`].
+    #[allow(clippy::cast_possible_truncation, clippy::cast_sign_loss)]
+    pub fn add(
+        &mut self,
+        simulated: &RayImage<GlobalFrame>,
+        measured: &RayImage<GlobalFrame>,
+        directions: &[Option<SkyDirection>],
+    ) {
+        for rpx in measured.pixels() {
+            let Some(measured_ray) = rpx.ray() else {
+                continue;
+            };
+            let Some(simulated_ray) = simulated.ray(rpx.row(), rpx.col()) else {
+                continue;
+            };
+            let Some(view) = directions[rpx.row() * measured.cols() + rpx.col()] else {
+                continue;
+            };
+
+            let elevation_deg = 90. - view.zenith.get::<degree>();
+            let index = (elevation_deg / self.resolution_deg).floor().max(0.) as usize;
+            let Some(bin) = self.bins.get_mut(index) else {
+                continue;
+            };
+            // Least-squares scale from simulated to measured, as in `fit_turbidity`.
+            bin.sum_product += measured_ray.dop() * simulated_ray.dop();
+            bin.sum_squares += simulated_ray.dop().powi(2);
+            bin.samples += 1;
+        }
+    }
+
+    /// Fits the scale of every bin with at least `min_samples` samples.
+    #[allow(clippy::cast_precision_loss)]
+    pub fn build(self, min_samples: usize) -> DopCalibration {
+        let points = self
+            .bins
+            .iter()
+            .enumerate()
+            .filter(|(_, bin)| bin.samples >= min_samples.max(1) && bin.sum_squares > 0.)
+            .map(|(i, bin)| DopCalibrationRecord {
+                elevation_deg: (i as f64 + 0.5) * self.resolution_deg,
+                dop_scale: bin.sum_product / bin.sum_squares,
+                samples: bin.samples,
+            })
+            .collect();
+        DopCalibration { points }
+    }
+}
+
+fn unit(vector: Vector<CamXyz>) -> [f64; 3] {
+    let vector = vector.normalized();
+    [
+        vector.x().get::<meter>(),
+        vector.y().get::<meter>(),
+        vector.z().get::<meter>(),
+    ]
+}
+
+fn dot(a: &[f64; 3], b: &[f64; 3]) -> f64 {
+    a[0] * b[0] + a[1] * b[1] + a[2] * b[2]
+}
+
+fn stereographic(zenith: Angle, azimuth: Angle) -> Complex {
+    let radius = (zenith.get::<radian>() / 2.).tan();
+    let azimuth = azimuth.get::<radian>();
+    Complex::new(radius * azimuth.cos(), radius * azimuth.sin())
+}
+
+#[derive(Debug, Clone, Copy)]
+struct Complex {
+    re: f64,
+    im: f64,
+}
+
+impl Complex {
+    const ONE: Self = Self { re: 1., im: 0. };
+
+    fn new(re: f64, im: f64) -> Self {
+        Self { re, im }
+    }
+
+    fn conj(self) -> Self {
+        Self::new(self.re, -self.im)
+    }
+
+    fn scale(self, factor: f64) -> Self {
+        Self::new(self.re * factor, self.im * factor)
+    }
+
+    fn norm_sqr(self) -> f64 {
+        self.re * self.re + self.im * self.im
+    }
+
+    fn arg(self) -> f64 {
+        self.im.atan2(self.re)
+    }
+}
+
+impl std::ops::Add for Complex {
+    type Output = Self;
+
+    fn add(self, rhs: Self) -> Self {
+        Self::new(self.re + rhs.re, self.im + rhs.im)
+    }
+}
+
+impl std::ops::Sub for Complex {
+    type Output = Self;
+
+    fn sub(self, rhs: Self) -> Self {
+        Self::new(self.re - rhs.re, self.im - rhs.im)
+    }
+}
+
+impl std::ops::Mul for Complex {
+    type Output = Self;
+
+    fn mul(self, rhs: Self) -> Self {
+        Self::new(
+            self.re * rhs.re - self.im * rhs.im,
+            self.re * rhs.im + self.im * rhs.re,
+        )
+    }
+}
@@ -0,0 +1,87 @@
+use crate::stats::median;
+
+/// Scales the median absolute deviation to a standard deviation for normal noise.
+const MAD_TO_SIGMA: f64 = 1.4826;
+
+/// Wraps a difference of headings into [-180, 180) deg.
+pub fn wrap_deg(deg: f64) -> f64 {
+    (deg + 180.).rem_euclid(360.) - 180.
+}
+
+/// Smooths a series of per-frame headings over a sliding window of frames.
+///
+/// Frames without an estimate are left out of the windows of their neighbours and stay empty.
+#[derive(Debug, Clone, Copy)]
+pub struct HeadingFilter {
+    window: usize,
+    causal: bool,
+}
+
+impl HeadingFilter {
+    /// The window is centred on each frame, so it should hold an odd number of frames.
+    pub fn new(window: usize) -> Self {
+        Self {
+            window: window.max(1),
+            causal: false,
+        }
+    }
+
+    /// Only uses the frame and those before it, as a filter running online would.
+    pub fn with_causal(mut self, causal: bool) -> Self {
+        self.causal = causal;
+        self
+    }
+
+    /// Frames that must arrive after a frame before its smoothed heading is known.
+    pub fn latency_frames(&self) -> usize {
+        if self.causal { 0 } else { self.window / 2 }
+    }
+
+    /// Median of each window.
+    pub fn median(&self, headings_deg: &[Option<f64>]) -> Vec<Option<f64>> {
+        (0..headings_deg.len())
+            .map(|i| {
+                let (heading_deg, offsets) = self.window_offsets(headings_deg, i)?;
+                Some(wrap_deg(heading_deg + median(&offsets)?))
+            })
+            .collect()
+    }
+
+    /// Replaces headings more than `n_sigmas` robust standard deviations from the median of their
+    /// window with that median, and marks them as outliers.
+    pub fn hampel(&self, headings_deg: &[Option<f64>], n_sigmas: f64) -> Vec<Option<(f64, bool)>> {
+        (0..headings_deg.len())
+            .map(|i| {
+                let (heading_deg, offsets) = self.window_offsets(headings_deg, i)?;
+                let center = median(&offsets)?;
+                let deviations: Vec<f64> = offsets.iter().map(|o| (o - center).abs()).collect();
+                let sigma = MAD_TO_SIGMA * median(&deviations)?;
+                // The frame itself is at offset zero.
+                if center.abs() > n_sigmas * sigma {
+                    Some((wrap_deg(heading_deg + center), true))
+                } else {
+                    Some((heading_deg, false))
+                }
+            })
+            .collect()
+    }
+
+    /// Heading of a frame and the offsets of the headings in its window from it.
+    fn window_offsets(&self, headings_deg: &[Option<f64>], i: usize) -> Option<(f64, Vec<f64>)> {
+        let heading_deg = headings_deg[i]?;
+        let (start, end) = if self.causal {
+            ((i + 1).saturating_sub(self.window), i)
+        } else {
+            (
+                i.saturating_sub(self.window / 2),
+                (i + self.window / 2).min(headings_deg.len() - 1),
+            )
+        };
+        let offsets = headings_deg[start..=end]
+            .iter()
+            .flatten()
+            .map(|other| wrap_deg(other - heading_deg))
+            .collect();
+        Some((heading_deg, offsets))
+    }
+}
@@ -0,0 +1,149 @@
+/// Arithmetic mean, or `None` for no samples.
+#[allow(clippy::cast_precision_loss)]
+pub fn mean(samples: &[f64]) -> Option<f64> {
+    (!samples.is_empty()).then(|| samples.iter().sum::<f64>() / samples.len() as f64)
+}
+
+/// Mean of `(sample, weight)` pairs, or `None` without any weight.
+pub fn weighted_mean(samples: &[(f64, f64)]) -> Option<f64> {
+    let total_weight: f64 = samples.iter().map(|(_, weight)| weight).sum();
+    (total_weight > 0.).then(|| {
+        samples
+            .iter()
+            .map(|(sample, weight)| sample * weight)
+            .sum::<f64>()
+            / total_weight
+    })
+}
+
+/// Median, or `None` for no samples.
+pub fn median(samples: &[f64]) -> Option<f64> {
+    let mut sorted = samples.to_vec();
+    sorted.sort_by(f64::total_cmp);
+    let mid = sorted.len() / 2;
+    match sorted.len() {
+        0 => None,
+        len if len % 2 == 0 => Some((sorted[mid - 1] + sorted[mid]) / 2.),
+        _ => Some(sorted[mid]),
+    }
+}
+
+/// Linearly interpolated percentile, from 0 to 100, or `None` for no samples.
+#[allow(
+    clippy::cast_precision_loss,
+    clippy::cast_possible_truncation,
+    clippy::cast_sign_loss
+)]
+pub fn percentile(samples: &[f64], percent: f64) -> Option<f64> {
+    let mut sorted = samples.to_vec();
+    sorted.sort_by(f64::total_cmp);
+    let rank = (percent.clamp(0., 100.) / 100.) * (sorted.len().checked_sub(1)? as f64);
+    let (below, above) = (sorted[rank.floor() as usize], sorted[rank.ceil() as usize]);
+    Some(below + (rank - rank.floor()) * (above - below))
+}
+
+/// Size and spread of the errors between paired samples.
+#[derive(Debug, Clone, Copy, serde::Serialize)]
+pub struct ErrorSummary {
+    pub samples: usize,
+    pub mean: f64,
+    pub rms: f64,
+    /// Standard error of the mean error.
+    pub standard_error: f64,
+    pub max_abs: f64,
+}
+
+impl ErrorSummary {
+    /// Returns `None` for no errors.
+    #[allow(clippy::cast_precision_loss)]
+    pub fn new(errors: &[f64]) -> Option<Self> {
+        let mean = mean(errors)?;
+        let n = errors.len() as f64;
+        let sum_squares: f64 = errors.iter().map(|error| error * error).sum();
+        let variance = errors
+            .iter()
+            .map(|error| (error - mean).powi(2))
+            .sum::<f64>()
+            / (n - 1.).max(1.);
+        Some(Self {
+            samples: errors.len(),
+            mean,
+            rms: (sum_squares / n).sqrt(),
+            standard_error: (variance / n).sqrt(),
+            max_abs: errors.iter().fold(0., |max, error| error.abs().max(max)),
+        })
+    }
+}
+
+/// Wilcoxon signed-rank test of whether paired differences are centered on zero.
+#[derive(Debug, Clone, Copy, serde::Serialize)]
+pub struct SignedRankTest {
+    /// Pairs with a non-zero difference.
+    pub pairs: usize,
+    /// Sum of the ranks of positive differences.
+    pub statistic: f64,
+    pub z: f64,
+    /// Two-sided, from the normal approximation.
+    pub p_value: f64,
+}
+
+impl SignedRankTest {
+    /// Returns `None` when every difference is zero.
+    #[allow(clippy::cast_precision_loss)]
+    pub fn new(differences: &[f64]) -> Option<Self> {
+        let mut nonzero: Vec<f64> = differences
+            .iter()
+            .copied()
+            .filter(|d| *d != 0. && d.is_finite())
+            .collect();
+        if nonzero.is_empty() {
+            return None;
+        }
+        nonzero.sort_by(|a, b| a.abs().total_cmp(&b.abs()));
+
+        // Tied magnitudes share the average of their ranks.
+        let n = nonzero.len();
+        let mut statistic = 0.;
+        let mut tie_correction = 0.;
+        let mut start = 0;
+        while start < n {
+            let mut end = start + 1;
+            while end < n && nonzero[end].abs() == nonzero[start].abs() {
+                end += 1;
+            }
+            let rank = (start + end + 1) as f64 / 2.;
+            statistic += rank * nonzero[start..end].iter().filter(|d| **d > 0.).count() as f64;
+            let ties = (end - start) as f64;
+            tie_correction += ties.powi(3) - ties;
+            start = end;
+        }
+
+        let n = n as f64;
+        let expected = n * (n + 1.) / 4.;
+        let variance = n * (n + 1.) * (2. * n + 1.) / 24. - tie_correction / 48.;
+        let z = (statistic - expected) / variance.sqrt();
+
+        Some(Self {
+            pairs: nonzero.len(),
+            statistic,
+            z,
+            p_value: 2. * (1. - normal_cdf(z.abs())),
+        })
+    }
+}
+
+/// Standard normal CDF, accurate to about 1e-7.
+pub fn normal_cdf(x: f64) -> f64 {
+    // Abramowitz and Stegun 7.1.26.
+    let t = 1. / (1. + 0.327_591_1 * x.abs() / std::f64::consts::SQRT_2);
+    let poly = t
+        * (0.254_829_592
+            + t * (-0.284_496_736
+                + t * (1.421_413_741 + t * (-1.453_152_027 + t * 1.061_405_429))));
+    let erf = 1. - poly * (-x * x / 2.).exp();
+    if x >= 0. {
+        (1. + erf) / 2.
+    } else {
+        (1. - erf) / 2.
+    }
+}
@@ -1,3 +1,4 @@
+use crate::smoothing::wrap_deg;
 use sguaba::{
     Vector,
     engineering::Orientation,
@@ -26,11 +27,154 @@ system!(pub struct CarXyz using right-handed XYZ);
 // The earth bounded frame provided by the INS.
 system!(pub struct InsEnu using ENU);
 
+/// Attitude convention of the INS output.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, clap::ValueEnum, serde::Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum InsConvention {
+    /// NovAtel INSPVA: azimuth clockwise from north, pitch nose up, roll right wing down.
+    #[default]
+    Inspva,
+    /// Aerospace yaw, pitch and roll of a forward-right-down body in a north-east-down frame.
+    NedFrd,
+    /// REP-103 yaw, pitch and roll of a forward-left-up body in an east-north-up frame.
+    EnuFlu,
+    /// INSPVA pitch and roll with the azimuth counter-clockwise from north, as in the yaw of
+    /// [`InsEnu`] orientations.
+    InspvaCcw,
+    /// INSPVA pitch and roll with the azimuth clockwise from east.
+    InspvaFromEast,
+}
+
+impl InsConvention {
+    /// Converts heading, pitch and roll in degrees into INSPVA azimuth, pitch and roll.
+    pub fn to_inspva(self, heading: f64, pitch: f64, roll: f64) -> (f64, f64, f64) {
+        let azimuth = self.azimuth().to_heading_deg(heading);
+        match self {
+            // Pitch is about the left axis.
+            Self::EnuFlu => (azimuth, -pitch, roll),
+            _ => (azimuth, pitch, roll),
+        }
+    }
+
+    /// How the heading of this convention is measured about the up axis.
+    pub fn azimuth(self) -> AzimuthConvention {
+        match self {
+            // Same rotations in the same order as INSPVA, only the axis names differ.
+            Self::Inspva | Self::NedFrd => AzimuthConvention::LeftHandedFromNorth,
+            Self::EnuFlu => AzimuthConvention::RightHandedFromEast,
+            Self::InspvaCcw => AzimuthConvention::RightHandedFromNorth,
+            Self::InspvaFromEast => AzimuthConvention::LeftHandedFromEast,
+        }
+    }
+}
+
+/// How a receiver measures the azimuth of the vehicle about the up axis.
+///
+/// Left-handed azimuths turn clockwise seen from above, right-handed ones counter-clockwise.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, serde::Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum AzimuthConvention {
+    /// Clockwise from north, as in NovAtel INSPVA and compasses.
+    #[default]
+    LeftHandedFromNorth,
+    /// Counter-clockwise from north, as in the yaw of [`InsEnu`] orientations.
+    RightHandedFromNorth,
+    /// Clockwise from east.
+    LeftHandedFromEast,
+    /// Counter-clockwise from east, as in REP-103.
+    RightHandedFromEast,
+}
+
+impl AzimuthConvention {
+    /// Compass heading in [0, 360) deg of an azimuth in this convention.
+    pub fn to_heading_deg(self, azimuth: f64) -> f64 {
+        match self {
+            Self::LeftHandedFromNorth => normalize_heading_deg(azimuth),
+            Self::RightHandedFromNorth => heading_from_yaw_deg(azimuth),
+            Self::LeftHandedFromEast => normalize_heading_deg(azimuth + 90.),
+            Self::RightHandedFromEast => heading_from_enu_yaw_deg(azimuth),
+        }
+    }
+}
+
+/// Compass heading, clockwise from true north in [0, 360) deg, of a yaw counter-clockwise from
+/// north as in the tait-bryan angles of an [`InsEnu`] orientation.
+pub fn heading_from_yaw_deg(yaw_deg: f64) -> f64 {
+    normalize_heading_deg(-yaw_deg)
+}
+
+/// Yaw counter-clockwise from north in [-180, 180) deg of a compass heading.
+pub fn yaw_from_heading_deg(heading_deg: f64) -> f64 {
+    wrap_deg(-heading_deg)
+}
+
+/// REP-103 yaw, counter-clockwise from east in [-180, 180) deg, of a compass heading.
+pub fn enu_yaw_from_heading_deg(heading_deg: f64) -> f64 {
+    wrap_deg(90. - heading_deg)
+}
+
+/// Compass heading of a REP-103 yaw.
+pub fn heading_from_enu_yaw_deg(enu_yaw_deg: f64) -> f64 {
+    normalize_heading_deg(90. - enu_yaw_deg)
+}
+
+/// Wraps a heading into [0, 360) deg.
+fn normalize_heading_deg(heading_deg: f64) -> f64 {
+    let heading_deg = heading_deg.rem_euclid(360.);
+    // rem_euclid rounds tiny negative headings up to 360, and keeps the sign of -0.
+    if heading_deg >= 360. || heading_deg == 0. {
+        0.
+    } else {
+        heading_deg
+    }
+}
+
+/// A heading estimate in each of the conventions it gets compared in.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct HeadingConventions {
+    /// Clockwise from true north in [0, 360) deg.
+    pub true_heading_deg: f64,
+    /// REP-103 yaw, counter-clockwise from east in [-180, 180) deg.
+    pub enu_yaw_deg: f64,
+    /// Estimated minus INS yaw, counter-clockwise in [-180, 180) deg.
+    pub ins_offset_deg: f64,
+}
+
+impl HeadingConventions {
+    /// From the yaw of the car according to the INS and the offset of the estimate from it,
+    /// both counter-clockwise from north.
+    pub fn from_ins_yaw(ins_yaw_deg: f64, yaw_offset_deg: f64) -> Self {
+        let true_heading_deg = heading_from_yaw_deg(ins_yaw_deg + yaw_offset_deg);
+        Self {
+            true_heading_deg,
+            enu_yaw_deg: enu_yaw_from_heading_deg(true_heading_deg),
+            ins_offset_deg: wrap_deg(yaw_offset_deg),
+        }
+    }
+}
+
 impl InsEnu {
-    pub fn orientation_from_inspva(azimuth: f64, pitch: f64, roll: f64) -> Orientation<Self> {
-        // azimuth is left-handed from north in the INSPVA spec.
-        // convert it to right-handed by negating.
-        let yaw = Angle::new::<degree>(-azimuth);
+    /// Builds the orientation of the car from INS attitude in any supported convention.
+    pub fn orientation_from(
+        convention: InsConvention,
+        heading: f64,
+        pitch: f64,
+        roll: f64,
+    ) -> Orientation<Self> {
+        let (azimuth, pitch, roll) = convention.to_inspva(heading, pitch, roll);
+        Self::orientation_from_inspva(AzimuthConvention::LeftHandedFromNorth, azimuth, pitch, roll)
+    }
+
+    /// Builds the orientation of the car from INSPVA pitch and roll, and an azimuth that is
+    /// left-handed from north in the INSPVA spec but may follow another receiver's convention.
+    pub fn orientation_from_inspva(
+        convention: AzimuthConvention,
+        azimuth: f64,
+        pitch: f64,
+        roll: f64,
+    ) -> Orientation<Self> {
+        // convert the azimuth to right-handed from north by negating the compass heading.
+        let yaw = Angle::new::<degree>(-convention.to_heading_deg(azimuth));
         let pitch = Angle::new::<degree>(pitch);
         let roll = Angle::new::<degree>(roll);
 
@@ -89,11 +233,25 @@ pub fn ins_to_ecef(ins_position: &Wgs84) -> RigidBodyTransform<InsEnu, Ecef> {
     unsafe { RigidBodyTransform::ecef_to_enu_at(ins_position) }.inverse()
 }
 
-#[allow(clippy::similar_names)]
 pub fn up_in_cam(car_in_ins: Orientation<InsEnu>) -> Vector<CamXyz> {
     let up_ins_enu =
         vector!(e = Length::ZERO, n = Length::ZERO, u = Length::new::<meter>(1.); in InsEnu);
-    let up_car_xyz = car_to_ins(car_in_ins).inverse_transform(up_ins_enu);
+    ins_to_cam(car_in_ins, up_ins_enu)
+}
+
+pub fn north_in_cam(car_in_ins: Orientation<InsEnu>) -> Vector<CamXyz> {
+    let north_ins_enu =
+        vector!(e = Length::ZERO, n = Length::new::<meter>(1.), u = Length::ZERO; in InsEnu);
+    ins_to_cam(car_in_ins, north_ins_enu)
+}
+
+pub fn east_in_cam(car_in_ins: Orientation<InsEnu>) -> Vector<CamXyz> {
+    let east_ins_enu =
+        vector!(e = Length::new::<meter>(1.), n = Length::ZERO, u = Length::ZERO; in InsEnu);
+    ins_to_cam(car_in_ins, east_ins_enu)
+}
 
-    cam_to_car().inverse_transform(up_car_xyz)
+fn ins_to_cam(car_in_ins: Orientation<InsEnu>, ins_enu: Vector<InsEnu>) -> Vector<CamXyz> {
+    let car_xyz = car_to_ins(car_in_ins).inverse_transform(ins_enu);
+    cam_to_car().inverse_transform(car_xyz)
 }
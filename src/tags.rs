@@ -0,0 +1,128 @@
+use crate::{
+    error::BenchError,
+    pipeline::FrameRange,
+    stats::{mean, median},
+};
+use std::{collections::BTreeMap, path::Path};
+
+/// Tag of the statistics over every frame.
+const ALL_TAG: &str = "all";
+/// Tag of the statistics over frames without any tag.
+const UNTAGGED_TAG: &str = "untagged";
+
+/// Driving scenarios of frames, such as `highway` or `tunnel-exit`.
+#[derive(Debug, Default, Clone)]
+pub struct FrameTags {
+    ranges: Vec<(FrameRange, String)>,
+}
+
+impl FrameTags {
+    /// Reads a frame index or range and a tag per line, such as `100..500 urban`.
+    ///
+    /// A frame can have several tags, and `#` starts a comment.
+    pub fn read<P: AsRef<Path>>(path: P) -> Result<Self, BenchError> {
+        let path = path.as_ref();
+        let contents = std::fs::read_to_string(path).map_err(|e| BenchError::dataset(path, e))?;
+        let mut ranges = Vec::new();
+        for (i, line) in contents.lines().enumerate() {
+            let line = line.split('#').next().unwrap_or_default().trim();
+            if line.is_empty() {
+                continue;
+            }
+            let Some((frames, tag)) = line.split_once(char::is_whitespace) else {
+                return Err(BenchError::parse(path, i + 1, "expected frames and a tag"));
+            };
+            let range = match frames.parse::<usize>() {
+                Ok(frame_index) => FrameRange {
+                    start: frame_index,
+                    end: Some(frame_index + 1),
+                },
+                Err(_) => frames
+                    .parse()
+                    .map_err(|e| BenchError::parse(path, i + 1, e))?,
+            };
+            ranges.push((range, tag.trim().to_string()));
+        }
+        Ok(Self { ranges })
+    }
+
+    /// Tags of a frame, without repeats.
+    pub fn tags(&self, frame_index: usize) -> Vec<&str> {
+        let mut tags: Vec<&str> = self
+            .ranges
+            .iter()
+            .filter(|(range, _)| range.contains(frame_index))
+            .map(|(_, tag)| tag.as_str())
+            .collect();
+        tags.sort_unstable();
+        tags.dedup();
+        tags
+    }
+}
+
+/// Collects an error metric of each frame by tag.
+#[derive(Debug, Default)]
+pub struct StratifiedErrors {
+    errors: BTreeMap<String, Vec<f64>>,
+}
+
+impl StratifiedErrors {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Adds the error of a frame to each of its tags and to the overall statistics.
+    pub fn add(&mut self, tags: &[&str], error: f64) {
+        let untagged = [UNTAGGED_TAG];
+        let tags = if tags.is_empty() { &untagged[..] } else { tags };
+        for tag in std::iter::once(&ALL_TAG).chain(tags) {
+            self.errors
+                .entry((*tag).to_string())
+                .or_default()
+                .push(error);
+        }
+    }
+
+    /// Statistics of the magnitude of the errors of each tag, starting with every frame.
+    pub fn summaries(&self) -> Vec<TagSummary> {
+        let mut summaries: Vec<TagSummary> = self
+            .errors
+            .iter()
+            .filter_map(|(tag, errors)| TagSummary::new(tag, errors))
+            .collect();
+        summaries.sort_by_key(|summary| summary.tag != ALL_TAG);
+        summaries
+    }
+}
+
+#[derive(Debug, Clone, serde::Serialize)]
+pub struct TagSummary {
+    pub tag: String,
+    pub frames: usize,
+    pub mean_abs: f64,
+    pub median_abs: f64,
+    pub rms: f64,
+    pub max_abs: f64,
+}
+
+impl TagSummary {
+    fn new(tag: &str, errors: &[f64]) -> Option<Self> {
+        let magnitudes: Vec<f64> = errors.iter().map(|error| error.abs()).collect();
+        let squares: Vec<f64> = errors.iter().map(|error| error * error).collect();
+        Some(Self {
+            tag: tag.to_string(),
+            frames: errors.len(),
+            mean_abs: mean(&magnitudes)?,
+            median_abs: median(&magnitudes)?,
+            rms: mean(&squares)?.sqrt(),
+            max_abs: magnitudes.iter().copied().fold(0., f64::max),
+        })
+    }
+
+    pub fn print(&self, metric: &str) {
+        println!(
+            "{}: {} frames, {metric} mean |e| {:.4}, median |e| {:.4}, rms {:.4}, max |e| {:.4}",
+            self.tag, self.frames, self.mean_abs, self.median_abs, self.rms, self.max_abs
+        );
+    }
+}
@@ -0,0 +1,89 @@
+use crate::error::BenchError;
+use chrono::Local;
+use serde::{Deserialize, Serialize};
+use std::path::Path;
+
+/// Constant offset between the camera and INS clocks, as fitted by `calibrate_time_offset` and
+/// loaded by runs with `--time-calibration`.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct TimeCalibration {
+    /// Delay between exposure and the camera frame time, as `--camera-latency-ms` takes it.
+    pub camera_latency_ms: f64,
+    pub created: String,
+    /// Frames the offset was fitted to.
+    pub frames: usize,
+    /// Mean weighted RMSE of those frames at the offset.
+    pub mean_weighted_rmse: f64,
+}
+
+impl TimeCalibration {
+    pub fn new(camera_latency_ms: f64, frames: usize, mean_weighted_rmse: f64) -> Self {
+        Self {
+            camera_latency_ms,
+            created: Local::now().to_rfc3339(),
+            frames,
+            mean_weighted_rmse,
+        }
+    }
+
+    pub fn read<P: AsRef<Path>>(path: P) -> Result<Self, BenchError> {
+        let path = path.as_ref();
+        let file = std::fs::File::open(path).map_err(|e| BenchError::dataset(path, e))?;
+        serde_json::from_reader(file).map_err(|e| BenchError::dataset(path, e))
+    }
+
+    pub fn write<P: AsRef<Path>>(&self, path: P) -> Result<(), BenchError> {
+        let path = path.as_ref();
+        let file =
+            std::fs::File::create(path).map_err(|e| BenchError::output(path.display(), e))?;
+        serde_json::to_writer_pretty(file, self).map_err(|e| BenchError::output(path.display(), e))
+    }
+}
+
+/// Aggregate residual of a dataset segment with the INS states taken at one camera latency.
+#[derive(Debug, Clone, Copy, Serialize)]
+pub struct OffsetSample {
+    pub camera_latency_ms: f64,
+    pub mean_weighted_rmse: f64,
+    /// Frames with a residual at this latency.
+    pub frames: usize,
+}
+
+/// Latencies from `min_ms` to `max_ms` inclusive, `step_ms` apart.
+#[allow(
+    clippy::cast_precision_loss,
+    clippy::cast_possible_truncation,
+    clippy::cast_sign_loss
+)]
+pub fn latencies_ms(min_ms: f64, max_ms: f64, step_ms: f64) -> Vec<f64> {
+    if step_ms <= 0. || max_ms < min_ms {
+        return Vec::new();
+    }
+    let steps = ((max_ms - min_ms) / step_ms).round() as usize;
+    (0..=steps).map(|i| min_ms + i as f64 * step_ms).collect()
+}
+
+/// Latency of the lowest residual, refined by fitting a parabola through its neighbours.
+///
+/// Samples must be sorted by latency, evenly spaced and taken over the same frames.
+pub fn best_latency(samples: &[OffsetSample]) -> Option<OffsetSample> {
+    let (best, sample) = samples
+        .iter()
+        .enumerate()
+        .filter(|(_, sample)| sample.mean_weighted_rmse.is_finite())
+        .min_by(|(_, a), (_, b)| a.mean_weighted_rmse.total_cmp(&b.mean_weighted_rmse))?;
+    let mut estimate = *sample;
+    if best == 0 || best + 1 >= samples.len() {
+        return Some(estimate);
+    }
+
+    let (left, right) = (samples[best - 1], samples[best + 1]);
+    let step = right.camera_latency_ms - sample.camera_latency_ms;
+    let curvature =
+        left.mean_weighted_rmse - 2. * sample.mean_weighted_rmse + right.mean_weighted_rmse;
+    if curvature > 0. && step > 0. {
+        estimate.camera_latency_ms +=
+            step * (left.mean_weighted_rmse - right.mean_weighted_rmse) / (2. * curvature);
+    }
+    Some(estimate)
+}
@@ -0,0 +1,128 @@
+use crate::error::BenchError;
+use serde_json::json;
+use std::{fmt::Write as _, path::Path};
+
+/// Colour of frames without a heading estimate.
+const NO_ESTIMATE_RGB: [u8; 3] = [128, 128, 128];
+
+/// Map format of an exported trajectory.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, clap::ValueEnum, serde::Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum TrajectoryFormat {
+    Geojson,
+    Kml,
+}
+
+impl TrajectoryFormat {
+    pub fn extension(self) -> &'static str {
+        match self {
+            Self::Geojson => "geojson",
+            Self::Kml => "kml",
+        }
+    }
+}
+
+/// Where the vehicle was at a frame, and how far the estimated heading was off.
+#[derive(Debug, Clone, Copy, serde::Deserialize)]
+pub struct TrajectoryPoint {
+    pub frame_index: usize,
+    pub latitude_deg: f64,
+    pub longitude_deg: f64,
+    #[serde(rename = "yaw_error_deg")]
+    pub heading_error_deg: Option<f64>,
+}
+
+/// Green for no error through yellow to red at `max_error_deg` and beyond.
+#[allow(clippy::cast_possible_truncation, clippy::cast_sign_loss)]
+pub fn error_color(heading_error_deg: Option<f64>, max_error_deg: f64) -> [u8; 3] {
+    let Some(heading_error_deg) = heading_error_deg else {
+        return NO_ESTIMATE_RGB;
+    };
+    let t = (heading_error_deg.abs() / max_error_deg).clamp(0., 1.);
+    let (red, green) = if t < 0.5 {
+        (255. * 2. * t, 200. + 20. * 2. * t)
+    } else {
+        (255. - 35. * (2. * t - 1.), 220. * (2. - 2. * t))
+    };
+    [red.round() as u8, green.round() as u8, 0]
+}
+
+/// Writes the route as a line plus one point per frame, styled with simplestyle colours.
+pub fn write_geojson(
+    points: &[TrajectoryPoint],
+    path: &Path,
+    max_error_deg: f64,
+) -> Result<(), BenchError> {
+    let route = json!({
+        "type": "Feature",
+        "geometry": {
+            "type": "LineString",
+            "coordinates": points
+                .iter()
+                .map(|point| [point.longitude_deg, point.latitude_deg])
+                .collect::<Vec<_>>(),
+        },
+        "properties": { "name": "route", "stroke": "#404040" },
+    });
+    let frames = points.iter().map(|point| {
+        let [red, green, blue] = error_color(point.heading_error_deg, max_error_deg);
+        json!({
+            "type": "Feature",
+            "geometry": {
+                "type": "Point",
+                "coordinates": [point.longitude_deg, point.latitude_deg],
+            },
+            "properties": {
+                "frame_index": point.frame_index,
+                "heading_error_deg": point.heading_error_deg,
+                "marker-color": format!("#{red:02x}{green:02x}{blue:02x}"),
+                "marker-size": "small",
+            },
+        })
+    });
+    let collection = json!({
+        "type": "FeatureCollection",
+        "features": std::iter::once(route).chain(frames).collect::<Vec<_>>(),
+    });
+
+    let file = std::fs::File::create(path).map_err(|e| BenchError::output(path.display(), e))?;
+    serde_json::to_writer(file, &collection).map_err(|e| BenchError::output(path.display(), e))
+}
+
+/// Writes the route as a line plus one coloured placemark per frame.
+pub fn write_kml(
+    points: &[TrajectoryPoint],
+    path: &Path,
+    max_error_deg: f64,
+) -> Result<(), BenchError> {
+    let mut kml = String::from(
+        "<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n\
+         <kml xmlns=\"http://www.opengis.net/kml/2.2\">\n<Document>\n",
+    );
+    let coordinates: Vec<String> = points
+        .iter()
+        .map(|point| format!("{},{}", point.longitude_deg, point.latitude_deg))
+        .collect();
+    let _ = writeln!(
+        kml,
+        "<Placemark><name>route</name><LineString><coordinates>{}</coordinates></LineString></Placemark>",
+        coordinates.join(" ")
+    );
+    for point in points {
+        // KML colours are alpha, blue, green, red.
+        let [red, green, blue] = error_color(point.heading_error_deg, max_error_deg);
+        let error_fmt = point
+            .heading_error_deg
+            .map_or_else(|| "no estimate".to_string(), |e| format!("{e:+.2} deg"));
+        let _ = writeln!(
+            kml,
+            "<Placemark><name>{:04}</name><description>{error_fmt}</description>\
+             <Style><IconStyle><color>ff{blue:02x}{green:02x}{red:02x}</color><scale>0.5</scale></IconStyle></Style>\
+             <Point><coordinates>{},{}</coordinates></Point></Placemark>",
+            point.frame_index, point.longitude_deg, point.latitude_deg
+        );
+    }
+    kml.push_str("</Document>\n</kml>\n");
+
+    std::fs::write(path, kml).map_err(|e| BenchError::output(path.display(), e))
+}
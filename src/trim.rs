@@ -0,0 +1,264 @@
+use crate::{
+    error::BenchError,
+    integrity::dataset_files,
+    io::{InsLog, TimeReader},
+    pipeline::{Dataset, FrameRange},
+};
+use chrono::{DateTime, Utc};
+use csv::StringRecord;
+use std::path::{Path, PathBuf};
+
+/// What trimming wrote.
+#[derive(Debug, Clone, Default)]
+pub struct TrimSummary {
+    pub frames: usize,
+    /// Frames in the range without an image, which the trimmed dataset is also missing.
+    pub missing_images: usize,
+    /// Latitude and longitude in degrees added to every position.
+    pub position_offset_deg: Option<(f64, f64)>,
+    /// Files of the dataset that could not be trimmed, and so were left out.
+    pub left_out: Vec<PathBuf>,
+}
+
+/// Cuts a dataset down to a range of its frames, to share it in a bug report or test on it.
+///
+/// The time and INS logs keep the records of the frames in the range, so frames stay paired
+/// with the same INS states, and the images are renumbered from zero. Odometry keeps the
+/// samples spanning the frames.
+#[derive(Debug, Clone)]
+pub struct DatasetTrimmer {
+    frames: FrameRange,
+    position_grid_deg: Option<f64>,
+    leap_seconds: Option<i64>,
+}
+
+impl DatasetTrimmer {
+    pub fn new(frames: FrameRange) -> Self {
+        Self {
+            frames,
+            position_grid_deg: None,
+            leap_seconds: None,
+        }
+    }
+
+    /// Offsets every position by the same amount so the first lies on a grid of this spacing,
+    /// which hides where the dataset was recorded while keeping the shape of the trajectory.
+    pub fn with_position_grid(mut self, position_grid_deg: Option<f64>) -> Self {
+        self.position_grid_deg = position_grid_deg;
+        self
+    }
+
+    /// GPS to UTC leap seconds used to line odometry up with the frames.
+    pub fn with_leap_seconds(mut self, leap_seconds: Option<i64>) -> Self {
+        self.leap_seconds = leap_seconds;
+        self
+    }
+
+    /// Writes the trimmed dataset to `to`, which must not exist yet.
+    pub fn trim(&self, from: &Dataset, to: &Dataset) -> Result<TrimSummary, BenchError> {
+        if to.path().exists() {
+            return Err(BenchError::Config(format!(
+                "{} already exists",
+                to.path().display()
+            )));
+        }
+        let time_frames: Vec<_> = TimeReader::new()
+            .with_leap_seconds(self.leap_seconds)
+            .read_csv(from.time_path())?
+            .collect();
+        let start = self.frames.start;
+        let end = self
+            .frames
+            .end
+            .map_or(time_frames.len(), |end| end.min(time_frames.len()));
+        if start >= end {
+            return Err(BenchError::Config(format!(
+                "frames {} are outside the {} frames of {}",
+                self.frames,
+                time_frames.len(),
+                from.path().display()
+            )));
+        }
+        let keep = |i: usize| (start..end).contains(&i);
+
+        let mut summary = TrimSummary {
+            frames: end - start,
+            ..TrimSummary::default()
+        };
+        rewrite_csv(&from.time_path(), &to.time_path(), |i, _| Ok(keep(i)))?;
+
+        let ins_logs: Vec<_> = [InsLog::Inspva, InsLog::Inspvax]
+            .into_iter()
+            .filter(|&log| from.ins_log_path(log).exists())
+            .collect();
+        if let Some(grid) = self.position_grid_deg
+            && let Some(&log) = ins_logs.first()
+        {
+            summary.position_offset_deg =
+                Some(position_offset(&from.ins_log_path(log), log, start, grid)?);
+        }
+        let position_offset_deg = summary.position_offset_deg;
+        for log in ins_logs {
+            let (lat_col, lon_col) = log.position_columns();
+            let path = from.ins_log_path(log);
+            rewrite_csv(&path, &to.ins_log_path(log), |i, record| {
+                if let (true, Some((lat_offset, lon_offset))) = (keep(i), position_offset_deg) {
+                    offset_field(record, lat_col, lat_offset, &path, i)?;
+                    offset_field(record, lon_col, lon_offset, &path, i)?;
+                }
+                Ok(keep(i))
+            })?;
+        }
+
+        if from.odometry_path().exists() {
+            let first = time_frames[start].time;
+            let last = time_frames[end - 1].time;
+            trim_odometry(&from.odometry_path(), &to.odometry_path(), first, last)?;
+        }
+
+        let target_dir = to.image_dir();
+        std::fs::create_dir_all(&target_dir)
+            .map_err(|e| BenchError::output(target_dir.display(), e))?;
+        for (i, frame_index) in (start..end).enumerate() {
+            let image = from.image_path(frame_index);
+            if !image.exists() {
+                summary.missing_images += 1;
+                continue;
+            }
+            let target = to.image_path(i);
+            std::fs::copy(&image, &target).map_err(|e| BenchError::output(target.display(), e))?;
+        }
+
+        // Anything else cannot be cut to the range without knowing what it holds.
+        let image_dir = from.image_dir();
+        let trimmed = [
+            from.time_path(),
+            from.ins_path(),
+            from.inspvax_path(),
+            from.odometry_path(),
+        ];
+        summary.left_out = dataset_files(from)?
+            .into_iter()
+            .filter(|path| {
+                let full_path = from.path().join(path);
+                !trimmed.contains(&full_path) && full_path.parent() != Some(image_dir.as_path())
+            })
+            .collect();
+        Ok(summary)
+    }
+}
+
+/// Copies the records of a CSV that `keep` returns true for, after it has had the chance to
+/// change them.
+fn rewrite_csv(
+    from: &Path,
+    to: &Path,
+    mut keep: impl FnMut(usize, &mut StringRecord) -> Result<bool, BenchError>,
+) -> Result<(), BenchError> {
+    let mut reader = csv::Reader::from_path(from).map_err(|e| BenchError::dataset(from, e))?;
+    let headers = reader
+        .headers()
+        .map_err(|e| BenchError::dataset(from, e))?
+        .clone();
+    if let Some(dir) = to.parent() {
+        std::fs::create_dir_all(dir).map_err(|e| BenchError::output(dir.display(), e))?;
+    }
+    let mut writer = csv::Writer::from_path(to).map_err(|e| BenchError::output(to.display(), e))?;
+    writer
+        .write_record(&headers)
+        .map_err(|e| BenchError::output(to.display(), e))?;
+    for (i, record) in reader.records().enumerate() {
+        let mut record = record.map_err(|e| BenchError::parse(from, i, e))?;
+        if keep(i, &mut record)? {
+            writer
+                .write_record(&record)
+                .map_err(|e| BenchError::output(to.display(), e))?;
+        }
+    }
+    writer
+        .flush()
+        .map_err(|e| BenchError::output(to.display(), e))
+}
+
+/// Offset that moves the position of the first kept record onto the grid.
+fn position_offset(
+    path: &Path,
+    log: InsLog,
+    start: usize,
+    grid_deg: f64,
+) -> Result<(f64, f64), BenchError> {
+    let (lat_col, lon_col) = log.position_columns();
+    let mut reader = csv::Reader::from_path(path).map_err(|e| BenchError::dataset(path, e))?;
+    let record = reader
+        .records()
+        .nth(start)
+        .ok_or_else(|| BenchError::parse(path, start, "no INS record for the first frame"))?
+        .map_err(|e| BenchError::parse(path, start, e))?;
+    let field = |col: usize| -> Result<f64, BenchError> {
+        record
+            .get(col)
+            .and_then(|value| value.parse().ok())
+            .ok_or_else(|| BenchError::parse(path, start, format!("column {col} is not a number")))
+    };
+    let (lat, lon) = (field(lat_col)?, field(lon_col)?);
+    let snap = |value: f64| (value / grid_deg).round() * grid_deg - value;
+    Ok((snap(lat), snap(lon)))
+}
+
+fn offset_field(
+    record: &mut StringRecord,
+    col: usize,
+    offset: f64,
+    path: &Path,
+    i: usize,
+) -> Result<(), BenchError> {
+    let value: f64 = record
+        .get(col)
+        .and_then(|value| value.parse().ok())
+        .ok_or_else(|| BenchError::parse(path, i, format!("column {col} is not a number")))?;
+    *record = record
+        .iter()
+        .enumerate()
+        .map(|(j, field)| {
+            if j == col {
+                (value + offset).to_string()
+            } else {
+                field.to_string()
+            }
+        })
+        .collect();
+    Ok(())
+}
+
+/// Keeps the odometry samples from the last one before `first` to the first one after `last`,
+/// so the speed can still be interpolated at every frame.
+fn trim_odometry(
+    from: &Path,
+    to: &Path,
+    first: DateTime<Utc>,
+    last: DateTime<Utc>,
+) -> Result<(), BenchError> {
+    let mut reader = csv::Reader::from_path(from).map_err(|e| BenchError::dataset(from, e))?;
+    let time_col = reader
+        .headers()
+        .map_err(|e| BenchError::dataset(from, e))?
+        .iter()
+        .position(|header| header == "time")
+        .ok_or_else(|| BenchError::parse(from, 0, "missing column time"))?;
+    let times = reader
+        .records()
+        .enumerate()
+        .map(|(i, record)| {
+            let record = record.map_err(|e| BenchError::parse(from, i, e))?;
+            record
+                .get(time_col)
+                .and_then(|time| time.parse::<DateTime<Utc>>().ok())
+                .ok_or_else(|| BenchError::parse(from, i, "invalid time"))
+        })
+        .collect::<Result<Vec<_>, _>>()?;
+    let start = times
+        .partition_point(|&time| time <= first)
+        .saturating_sub(1);
+    let end = (times.partition_point(|&time| time < last) + 1).min(times.len());
+    rewrite_csv(from, to, |i, _| Ok((start..end).contains(&i)))
+}
@@ -1,14 +1,88 @@
+use crate::{
+    camera::{CameraModel, PixelGrid},
+    scratch::Scratch,
+    sky::SkyDirection,
+    systems::{InsEnu, up_in_cam},
+};
+use image::GrayImage;
 use rumpus::{
     image::RayImage,
     optic::PixelCoordinate,
-    ray::{GlobalFrame, Ray, SensorFrame},
+    ray::{Aop, GlobalFrame, Ray, SensorFrame},
 };
+use sguaba::engineering::Orientation;
 use uom::si::{
     angle::{degree, radian},
     f64::Angle,
+    length::meter,
 };
 
 pub fn weighted_rmse<F: Copy>(simulated: &RayImage<F>, measured: &RayImage<F>) -> f64 {
+    weighted_rmse_where(simulated, measured, |_, _| true)
+}
+
+/// Weighted RMSE of the pixels looking at each band of sky elevation, in degrees.
+///
+/// `directions` must be in row-major order, as returned by [`crate::sky::sky_directions`].
+/// Bands include both edges, and are `None` when no pixel falls in them.
+pub fn banded_weighted_rmse<F: Copy>(
+    simulated: &RayImage<F>,
+    measured: &RayImage<F>,
+    directions: &[Option<SkyDirection>],
+    bands_deg: &[(f64, f64)],
+) -> Vec<Option<f64>> {
+    bands_deg
+        .iter()
+        .map(|&(lower_deg, upper_deg)| {
+            let rmse = weighted_rmse_where(simulated, measured, |row, col| {
+                directions[row * measured.cols() + col].is_some_and(|direction| {
+                    let elevation_deg = 90. - direction.zenith.get::<degree>();
+                    (lower_deg..=upper_deg).contains(&elevation_deg)
+                })
+            });
+            rmse.is_finite().then_some(rmse)
+        })
+        .collect()
+}
+
+/// Weighted RMSE of the pixels not set in a row-major mask, such as the glare around the sun.
+pub fn weighted_rmse_unmasked<F: Copy>(
+    simulated: &RayImage<F>,
+    measured: &RayImage<F>,
+    mask: &[bool],
+) -> f64 {
+    weighted_rmse_where(simulated, measured, |row, col| {
+        !mask[row * measured.cols() + col]
+    })
+}
+
+/// Weighted RMSE of the pixels measured with at least `min_dop`, and the number of pixels
+/// valid in both images that were left out for being less polarized.
+pub fn weighted_rmse_excluding_low_dop<F: Copy>(
+    simulated: &RayImage<F>,
+    measured: &RayImage<F>,
+    min_dop: f64,
+) -> (f64, usize) {
+    let low_dop_pixels = measured
+        .pixels()
+        .filter(|rpx| {
+            rpx.ray().is_some_and(|ray| ray.dop() < min_dop)
+                && simulated.ray(rpx.row(), rpx.col()).is_some()
+        })
+        .count();
+    let rmse = weighted_rmse_where(simulated, measured, |row, col| {
+        measured
+            .ray(row, col)
+            .is_some_and(|ray| ray.dop() >= min_dop)
+    });
+    (rmse, low_dop_pixels)
+}
+
+pub(crate) fn weighted_rmse_where<F: Copy>(
+    simulated: &RayImage<F>,
+    measured: &RayImage<F>,
+    include: impl Fn(usize, usize) -> bool,
+) -> f64 {
     let mut sum_weighted_errors = 0.0f64;
     let mut sum_weights = 0.0f64;
     let mut samples = 0.;
@@ -16,6 +90,7 @@ pub fn weighted_rmse<F: Copy>(simulated: &RayImage<F>, measured: &RayImage<F>) -
     for rpx in measured.pixels() {
         if let Some(measured_ray) = rpx.ray()
             && let Some(simulated_ray) = simulated.ray(rpx.row(), rpx.col())
+            && include(rpx.row(), rpx.col())
         {
             let weight = measured_ray.dop();
             let error = Angle::from(measured_ray.aop() - simulated_ray.aop())
@@ -31,27 +106,250 @@ pub fn weighted_rmse<F: Copy>(simulated: &RayImage<F>, measured: &RayImage<F>) -
     (sum_weighted_errors / sum_weights / samples).sqrt()
 }
 
+/// Same as [`weighted_rmse_where`] in single precision, for boards where f64 math is slow.
+///
+/// The weights and squared errors of the included pixels are staged in `scratch`, so the sums
+/// run over contiguous `f32` slices.
+#[allow(clippy::cast_possible_truncation, clippy::cast_precision_loss)]
+pub(crate) fn weighted_rmse_where_f32<F: Copy>(
+    simulated: &RayImage<F>,
+    measured: &RayImage<F>,
+    include: impl Fn(usize, usize) -> bool,
+    scratch: &mut Scratch,
+) -> f64 {
+    let (weights, errors) = (&mut scratch.weights, &mut scratch.errors);
+    weights.clear();
+    errors.clear();
+
+    for rpx in measured.pixels() {
+        if let Some(measured_ray) = rpx.ray()
+            && let Some(simulated_ray) = simulated.ray(rpx.row(), rpx.col())
+            && include(rpx.row(), rpx.col())
+        {
+            let error =
+                Angle::from(measured_ray.aop() - simulated_ray.aop()).get::<degree>() as f32;
+            weights.push(measured_ray.dop() as f32);
+            errors.push(error * error);
+        }
+    }
+
+    let sum_weights: f32 = weights.iter().sum();
+    let sum_weighted_errors: f32 = weights.iter().zip(errors.iter()).map(|(w, e)| w * e).sum();
+    let samples = weights.len() as f32;
+    f64::from((sum_weighted_errors / sum_weights / samples).sqrt())
+}
+
+pub fn dop_rmse<F: Copy>(simulated: &RayImage<F>, measured: &RayImage<F>) -> f64 {
+    let mut sum_errors = 0.0f64;
+    let mut samples = 0.;
+
+    for rpx in measured.pixels() {
+        if let Some(measured_ray) = rpx.ray()
+            && let Some(simulated_ray) = simulated.ray(rpx.row(), rpx.col())
+        {
+            sum_errors += (measured_ray.dop() - simulated_ray.dop()).powf(2.);
+            samples += 1.;
+        }
+    }
+
+    (sum_errors / samples).sqrt()
+}
+
+/// Measured minus simulated AoP in degrees and DoP of every pixel valid in both images.
+pub fn paired_errors<F: Copy>(
+    simulated: &RayImage<F>,
+    measured: &RayImage<F>,
+) -> (Vec<f64>, Vec<f64>) {
+    measured
+        .pixels()
+        .filter_map(|rpx| {
+            let measured_ray = rpx.ray()?;
+            let simulated_ray = simulated.ray(rpx.row(), rpx.col())?;
+            Some((
+                Angle::from(measured_ray.aop() - simulated_ray.aop()).get::<degree>(),
+                measured_ray.dop() - simulated_ray.dop(),
+            ))
+        })
+        .unzip()
+}
+
+/// Fraction of pixels that are valid in both images.
+#[allow(clippy::cast_precision_loss)]
+pub fn valid_fraction<F: Copy>(simulated: &RayImage<F>, measured: &RayImage<F>) -> f64 {
+    let valid = measured
+        .pixels()
+        .filter(|rpx| rpx.ray().is_some() && simulated.ray(rpx.row(), rpx.col()).is_some())
+        .count();
+
+    valid as f64 / (measured.rows() * measured.cols()) as f64
+}
+
+/// Keeps every `factor`-th pixel along each axis, trading accuracy for speed.
+///
+/// Angles of polarization are relative to the image axes, which do not change.
+pub fn downsample(ray_image: &RayImage<SensorFrame>, factor: usize) -> RayImage<SensorFrame> {
+    let factor = factor.max(1);
+    let rows = ray_image.rows().div_ceil(factor);
+    let cols = ray_image.cols().div_ceil(factor);
+    let rays: Vec<_> = (0..rows)
+        .flat_map(|row| (0..cols).map(move |col| (row * factor, col * factor)))
+        .map(|(row, col)| ray_image.ray(row, col))
+        .collect();
+    RayImage::from_rays(rays, rows, cols).unwrap()
+}
+
 /// Shifts the ray_image ignoring any tilt!
 pub fn sensor_to_global(
     ray_image: &RayImage<SensorFrame>,
     origin: &PixelCoordinate,
 ) -> RayImage<GlobalFrame> {
-    let rays: Vec<_> = ray_image
-        .pixels()
-        .map(|px| {
-            let ray = px.ray()?;
+    let mut rays = Vec::new();
+    sensor_to_global_into(ray_image, origin, &mut rays);
+    RayImage::from_rays(rays, ray_image.rows(), ray_image.cols()).unwrap()
+}
 
-            let px_coord = PixelCoordinate::new(px.row(), px.col());
+fn sensor_to_global_into(
+    ray_image: &RayImage<SensorFrame>,
+    origin: &PixelCoordinate,
+    rays: &mut Vec<Option<Ray<GlobalFrame>>>,
+) {
+    rays.clear();
+    rays.extend(ray_image.pixels().map(|px| {
+        let ray = px.ray()?;
 
-            let shift = shift_by(px_coord, origin);
-            let angle = ray.aop().into_global_frame(-shift);
-            Some(Ray::<GlobalFrame>::new(angle, ray.dop()))
-        })
-        .collect();
+        let px_coord = PixelCoordinate::new(px.row(), px.col());
+
+        let shift = shift_by(px_coord, origin);
+        let angle = ray.aop().into_global_frame(-shift);
+        Some(Ray::<GlobalFrame>::new(angle, ray.dop()))
+    }));
+}
 
+/// Shifts every ray by the direction of its own local meridian in the image.
+///
+/// The meridian of a pixel is found from its bearing and the known attitude, so unlike
+/// [`sensor_to_global`] this works when the zenith is outside of the image, as with cameras
+/// tilted towards the horizon. For a pinhole camera with the zenith in view both agree.
+pub fn sensor_to_global_per_pixel(
+    ray_image: &RayImage<SensorFrame>,
+    camera: &CameraModel,
+    car_in_ins_enu: Orientation<InsEnu>,
+) -> RayImage<GlobalFrame> {
+    let mut rays = Vec::new();
+    sensor_to_global_per_pixel_into(ray_image, camera, car_in_ins_enu, &mut rays);
     RayImage::from_rays(rays, ray_image.rows(), ray_image.cols()).unwrap()
 }
 
+fn sensor_to_global_per_pixel_into(
+    ray_image: &RayImage<SensorFrame>,
+    camera: &CameraModel,
+    car_in_ins_enu: Orientation<InsEnu>,
+    rays: &mut Vec<Option<Ray<GlobalFrame>>>,
+) {
+    let up = camera_up(car_in_ins_enu);
+    let grid = PixelGrid::shared(camera);
+    rays.clear();
+    rays.extend(ray_image.pixels().map(|px| {
+        let ray = px.ray()?;
+        let shift = meridian_shift(&grid, up, px.row(), px.col())?;
+        let angle = ray.aop().into_global_frame(-shift);
+        Some(Ray::<GlobalFrame>::new(angle, ray.dop()))
+    }));
+}
+
+/// Converts a simulated image to the sensor frame, undoing the shift of every ray by its own
+/// local meridian that [`sensor_to_global_per_pixel`] applies.
+///
+/// Comparing in the sensor frame this way never depends on locating the zenith pixel.
+pub fn global_to_sensor(
+    ray_image: &RayImage<GlobalFrame>,
+    camera: &CameraModel,
+    car_in_ins_enu: Orientation<InsEnu>,
+) -> RayImage<SensorFrame> {
+    global_to_sensor_with(ray_image, camera, car_in_ins_enu, &mut Scratch::default())
+}
+
+/// Like [`global_to_sensor`], converting the rays in a reused buffer.
+pub fn global_to_sensor_with(
+    ray_image: &RayImage<GlobalFrame>,
+    camera: &CameraModel,
+    car_in_ins_enu: Orientation<InsEnu>,
+    scratch: &mut Scratch,
+) -> RayImage<SensorFrame> {
+    let up = camera_up(car_in_ins_enu);
+    let grid = PixelGrid::shared(camera);
+    scratch.sensor_rays.clear();
+    scratch.sensor_rays.extend(ray_image.pixels().map(|px| {
+        let ray = px.ray()?;
+        let shift = meridian_shift(&grid, up, px.row(), px.col())?;
+        let angle = Aop::from_angle_wrapped(Angle::from(ray.aop()) + shift);
+        Some(Ray::<SensorFrame>::new(angle, ray.dop()))
+    }));
+    RayImage::from_rays(
+        scratch.sensor_rays.drain(..),
+        ray_image.rows(),
+        ray_image.cols(),
+    )
+    .unwrap()
+}
+
+/// Unit vector towards the zenith in camera coordinates.
+fn camera_up(car_in_ins_enu: Orientation<InsEnu>) -> [f64; 3] {
+    let up = up_in_cam(car_in_ins_enu).normalized();
+    [
+        up.x().get::<meter>(),
+        up.y().get::<meter>(),
+        up.z().get::<meter>(),
+    ]
+}
+
+/// Direction in the image of the meridian through a pixel, pointing away from the zenith,
+/// or nothing at the zenith itself.
+fn meridian_shift(grid: &PixelGrid, up: [f64; 3], row: usize, col: usize) -> Option<Angle> {
+    let [x, y, z] = grid.bearing(row, col);
+
+    // Direction along the meridian away from the zenith, perpendicular to the bearing.
+    let elevation = x * up[0] + y * up[1] + z * up[2];
+    let away = [
+        elevation * x - up[0],
+        elevation * y - up[1],
+        elevation * z - up[2],
+    ];
+    // Project a small step along it onto the image plane, with y pointing up.
+    let dx = away[0] * z - x * away[2];
+    let dy = away[1] * z - y * away[2];
+    if dx == 0. && dy == 0. {
+        return None;
+    }
+    Some(Angle::new::<radian>(dy.atan2(dx)))
+}
+
+/// Converts a measured image to the global frame around the zenith pixel, or pixel by pixel
+/// when the zenith is outside of the image.
+pub fn measured_to_global(
+    ray_image: &RayImage<SensorFrame>,
+    camera: &CameraModel,
+    car_in_ins_enu: Orientation<InsEnu>,
+) -> RayImage<GlobalFrame> {
+    measured_to_global_with(ray_image, camera, car_in_ins_enu, &mut Scratch::default())
+}
+
+/// Like [`measured_to_global`], converting the rays in a reused buffer.
+pub fn measured_to_global_with(
+    ray_image: &RayImage<SensorFrame>,
+    camera: &CameraModel,
+    car_in_ins_enu: Orientation<InsEnu>,
+    scratch: &mut Scratch,
+) -> RayImage<GlobalFrame> {
+    match camera.zenith_pixel(car_in_ins_enu) {
+        Some(up_pixel) => sensor_to_global_into(ray_image, &up_pixel, &mut scratch.rays),
+        None => {
+            sensor_to_global_per_pixel_into(ray_image, camera, car_in_ins_enu, &mut scratch.rays);
+        }
+    }
+    RayImage::from_rays(scratch.rays.drain(..), ray_image.rows(), ray_image.cols()).unwrap()
+}
+
 #[allow(clippy::cast_precision_loss)]
 fn shift_by(coord: PixelCoordinate, origin: &PixelCoordinate) -> Angle {
     let y0 = origin.row() as f64;
@@ -65,3 +363,42 @@ fn shift_by(coord: PixelCoordinate, origin: &PixelCoordinate) -> Angle {
 
     Angle::new::<radian>(y.atan2(x))
 }
+
+/// Returns the ray image pixel with the most total intensity in a raw polarizer mosaic.
+///
+/// Each ray comes from a 2x2 superpixel. Saturated regions are reduced to their centroid.
+#[allow(
+    clippy::cast_precision_loss,
+    clippy::cast_possible_truncation,
+    clippy::cast_sign_loss
+)]
+pub fn intensity_peak(raw: &GrayImage) -> (usize, usize) {
+    let (rows, cols) = (raw.height() / 2, raw.width() / 2);
+    let intensity = |row: u32, col: u32| -> u32 {
+        [(0, 0), (0, 1), (1, 0), (1, 1)]
+            .iter()
+            .map(|(dr, dc)| u32::from(raw.get_pixel(2 * col + dc, 2 * row + dr).0[0]))
+            .sum()
+    };
+
+    let peak = (0..rows)
+        .flat_map(|row| (0..cols).map(move |col| intensity(row, col)))
+        .max()
+        .unwrap_or(0);
+
+    let (mut row_sum, mut col_sum, mut count) = (0., 0., 0.);
+    for row in 0..rows {
+        for col in 0..cols {
+            if intensity(row, col) == peak {
+                row_sum += f64::from(row);
+                col_sum += f64::from(col);
+                count += 1.;
+            }
+        }
+    }
+
+    (
+        (row_sum / count).round() as usize,
+        (col_sum / count).round() as usize,
+    )
+}
@@ -0,0 +1,146 @@
+use crate::{
+    image_circle::Circle,
+    scratch::Scratch,
+    utils::{weighted_rmse_where, weighted_rmse_where_f32},
+};
+use rumpus::{
+    image::RayImage,
+    ray::{GlobalFrame, SensorFrame},
+};
+
+/// Why a pixel does or does not count towards the metrics of a candidate.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, serde::Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum PixelValidity {
+    Valid,
+    /// The camera measured no polarization there.
+    NoData,
+    /// The image reader dropped the measured ray as outside of the lens image circle.
+    OutsideImageCircle,
+    /// The sky model has no polarization there, such as below the horizon.
+    NotSimulated,
+    /// Both images had a ray, but converting one of them to the other's frame dropped it,
+    /// such as at the zenith where there is no meridian.
+    Transform,
+    /// Left out of the comparison, such as the glare around the sun.
+    Masked,
+}
+
+/// Validity of every pixel of a candidate, worked out pixel by pixel from the images compared.
+#[derive(Debug, Clone, Copy)]
+pub struct ValidityMask<'a, F> {
+    measured_raw: &'a RayImage<SensorFrame>,
+    simulated_raw: &'a RayImage<GlobalFrame>,
+    measured: &'a RayImage<F>,
+    simulated: &'a RayImage<F>,
+    mask: Option<&'a [bool]>,
+    image_circle: Option<Circle>,
+}
+
+impl<'a, F: Copy> ValidityMask<'a, F> {
+    /// Tells apart why pixels are missing from the images compared, using the images before
+    /// either was converted between frames.
+    ///
+    /// `mask` is a row-major mask of the pixels left out, if any, and `image_circle` the circle
+    /// in rays outside of which the image reader dropped the measured rays, if it did.
+    pub fn new(
+        measured_raw: &'a RayImage<SensorFrame>,
+        simulated_raw: &'a RayImage<GlobalFrame>,
+        measured: &'a RayImage<F>,
+        simulated: &'a RayImage<F>,
+        mask: Option<&'a [bool]>,
+        image_circle: Option<Circle>,
+    ) -> Self {
+        Self {
+            measured_raw,
+            simulated_raw,
+            measured,
+            simulated,
+            mask,
+            image_circle,
+        }
+    }
+
+    pub fn get(&self, row: usize, col: usize) -> PixelValidity {
+        if self.measured_raw.ray(row, col).is_none() {
+            if self
+                .image_circle
+                .is_some_and(|circle| !circle.contains_ray(row, col, 1))
+            {
+                PixelValidity::OutsideImageCircle
+            } else {
+                PixelValidity::NoData
+            }
+        } else if self.simulated_raw.ray(row, col).is_none() {
+            PixelValidity::NotSimulated
+        } else if self.measured.ray(row, col).is_none() || self.simulated.ray(row, col).is_none() {
+            PixelValidity::Transform
+        } else if self
+            .mask
+            .is_some_and(|mask| mask[row * self.measured_raw.cols() + col])
+        {
+            PixelValidity::Masked
+        } else {
+            PixelValidity::Valid
+        }
+    }
+
+    pub fn counts(&self) -> ValidityCounts {
+        let mut counts = ValidityCounts::default();
+        for row in 0..self.measured_raw.rows() {
+            for col in 0..self.measured_raw.cols() {
+                *match self.get(row, col) {
+                    PixelValidity::Valid => &mut counts.valid,
+                    PixelValidity::NoData => &mut counts.no_data,
+                    PixelValidity::OutsideImageCircle => &mut counts.outside_image_circle,
+                    PixelValidity::NotSimulated => &mut counts.not_simulated,
+                    PixelValidity::Transform => &mut counts.transform,
+                    PixelValidity::Masked => &mut counts.masked,
+                } += 1;
+            }
+        }
+        counts
+    }
+
+    /// Weighted RMSE of the valid pixels.
+    pub fn weighted_rmse(&self) -> f64 {
+        weighted_rmse_where(self.simulated, self.measured, |row, col| {
+            self.get(row, col) == PixelValidity::Valid
+        })
+    }
+
+    /// Weighted RMSE of the valid pixels in single precision, staged in `scratch`.
+    pub fn weighted_rmse_f32(&self, scratch: &mut Scratch) -> f64 {
+        weighted_rmse_where_f32(
+            self.simulated,
+            self.measured,
+            |row, col| self.get(row, col) == PixelValidity::Valid,
+            scratch,
+        )
+    }
+}
+
+/// Pixels of a candidate in each [`PixelValidity`].
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, serde::Serialize)]
+pub struct ValidityCounts {
+    pub valid: usize,
+    pub no_data: usize,
+    pub not_simulated: usize,
+    pub transform: usize,
+    pub masked: usize,
+    pub outside_image_circle: usize,
+}
+
+impl ValidityCounts {
+    /// Fraction of the pixels that are valid in both images, whether masked or not.
+    #[allow(clippy::cast_precision_loss)]
+    pub fn valid_fraction(&self) -> f64 {
+        let total = self.valid
+            + self.no_data
+            + self.outside_image_circle
+            + self.not_simulated
+            + self.transform
+            + self.masked;
+        (self.valid + self.masked) as f64 / total as f64
+    }
+}
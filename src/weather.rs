@@ -0,0 +1,100 @@
+use crate::{ephemeris::Atmosphere, error::BenchError};
+use chrono::{DateTime, Duration, Utc};
+use std::path::Path;
+
+/// Observations further than this from a frame are not attached to it.
+const MAX_OBSERVATION_GAP_MIN: i64 = 60;
+
+/// Cloud cover below which the sky counts as clear, and above which as overcast.
+const CLEAR_CLOUD_COVER: f64 = 0.1;
+const OVERCAST_CLOUD_COVER: f64 = 0.7;
+/// Visibility below which the air counts as hazy.
+const HAZY_VISIBILITY_KM: f64 = 10.;
+
+/// Weather observed during a dataset, read from a CSV with a `time` column and any of the
+/// other columns of [`WeatherRecord`].
+#[derive(Debug, Clone)]
+pub struct WeatherSeries {
+    records: Vec<WeatherRecord>,
+}
+
+#[derive(Debug, Clone, Copy, serde::Deserialize)]
+pub struct WeatherRecord {
+    pub time: DateTime<Utc>,
+    #[serde(default)]
+    pub temperature_c: Option<f64>,
+    #[serde(default)]
+    pub pressure_hpa: Option<f64>,
+    /// Fraction of the sky covered by cloud, from 0 to 1.
+    #[serde(default)]
+    pub cloud_cover: Option<f64>,
+    #[serde(default)]
+    pub visibility_km: Option<f64>,
+    #[serde(default)]
+    pub aerosol_index: Option<f64>,
+}
+
+impl WeatherRecord {
+    /// Conditions to break down heading errors by, alongside the scenario tags.
+    pub fn tags(&self) -> Vec<&'static str> {
+        let mut tags = Vec::new();
+        if let Some(cloud_cover) = self.cloud_cover {
+            tags.push(if cloud_cover < CLEAR_CLOUD_COVER {
+                "clear_sky"
+            } else if cloud_cover <= OVERCAST_CLOUD_COVER {
+                "partly_cloudy"
+            } else {
+                "overcast"
+            });
+        }
+        if self
+            .visibility_km
+            .is_some_and(|visibility_km| visibility_km < HAZY_VISIBILITY_KM)
+        {
+            tags.push("hazy");
+        }
+        tags
+    }
+}
+
+impl WeatherSeries {
+    pub fn read_csv<P: AsRef<Path>>(path: P) -> Result<Self, BenchError> {
+        let path = path.as_ref();
+        let mut reader = csv::Reader::from_path(path).map_err(|e| BenchError::dataset(path, e))?;
+        let mut records = reader
+            .deserialize()
+            .enumerate()
+            .map(|(i, record)| record.map_err(|e| BenchError::parse(path, i, e)))
+            .collect::<Result<Vec<WeatherRecord>, _>>()?;
+        if records.is_empty() {
+            return Err(BenchError::parse(path, 0, "weather log is empty"));
+        }
+        records.sort_by_key(|record| record.time);
+        Ok(Self { records })
+    }
+
+    /// The observation closest in time, if there is one within the hour.
+    pub fn at(&self, time: DateTime<Utc>) -> Option<&WeatherRecord> {
+        let next = self.records.partition_point(|record| record.time <= time);
+        let before = &self.records[next.saturating_sub(1)];
+        let closest = match self.records.get(next) {
+            Some(after) if after.time - time < time - before.time => after,
+            _ => before,
+        };
+        ((closest.time - time).abs() <= Duration::minutes(MAX_OBSERVATION_GAP_MIN))
+            .then_some(closest)
+    }
+
+    /// Surface conditions at a time, falling back to `default` for anything not observed.
+    pub fn atmosphere(&self, time: DateTime<Utc>, default: Atmosphere) -> Atmosphere {
+        let record = self.at(time);
+        Atmosphere {
+            temperature_c: record
+                .and_then(|record| record.temperature_c)
+                .unwrap_or(default.temperature_c),
+            pressure_hpa: record
+                .and_then(|record| record.pressure_hpa)
+                .unwrap_or(default.pressure_hpa),
+        }
+    }
+}
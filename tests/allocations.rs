@@ -0,0 +1,225 @@
+//! Guards the per-candidate stages against allocating once their scratch buffers are warm.
+//!
+//! Simulating and converting still allocate the images they return, so those stages are
+//! checked to allocate less than with fresh buffers by at least the buffers they reuse.
+
+use chrono::{TimeZone, Utc};
+use rumpus::{
+    image::RayImage,
+    ray::{Aop, GlobalFrame, Ray, SensorFrame},
+};
+use rumpus_benchmark::{
+    camera::{CameraModel, PixelGrid},
+    ephemeris::CelestialPosition,
+    glare::{GlareConfig, GlareStrategy},
+    scratch::{Scratch, ScratchPool},
+    sky::{Sky, SkyDirection, SkyModelBackend, sky_directions_into},
+    systems::InsEnu,
+    utils::{measured_to_global, measured_to_global_with},
+};
+use sguaba::engineering::Orientation;
+use std::{
+    alloc::{GlobalAlloc, Layout, System},
+    cell::Cell,
+};
+use uom::{
+    ConstZero,
+    si::{
+        angle::degree,
+        f64::{Angle, Length},
+        length::{micron, millimeter},
+    },
+};
+
+/// Counts the allocations, and the bytes they ask for, made by the current thread, so parallel
+/// tests do not interfere.
+struct CountingAllocator;
+
+thread_local! {
+    static ALLOCATIONS: Cell<usize> = const { Cell::new(0) };
+    static ALLOCATED_BYTES: Cell<usize> = const { Cell::new(0) };
+}
+
+unsafe impl GlobalAlloc for CountingAllocator {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        ALLOCATIONS.with(|count| count.set(count.get() + 1));
+        ALLOCATED_BYTES.with(|bytes| bytes.set(bytes.get() + layout.size()));
+        unsafe { System.alloc(layout) }
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        unsafe { System.dealloc(ptr, layout) }
+    }
+
+    unsafe fn realloc(&self, ptr: *mut u8, layout: Layout, new_size: usize) -> *mut u8 {
+        ALLOCATIONS.with(|count| count.set(count.get() + 1));
+        ALLOCATED_BYTES.with(|bytes| bytes.set(bytes.get() + new_size));
+        unsafe { System.realloc(ptr, layout, new_size) }
+    }
+}
+
+#[global_allocator]
+static ALLOCATOR: CountingAllocator = CountingAllocator;
+
+/// Allocations made by `f` on this thread.
+fn allocations(f: impl FnOnce()) -> usize {
+    let before = ALLOCATIONS.with(Cell::get);
+    f();
+    ALLOCATIONS.with(Cell::get) - before
+}
+
+/// Bytes allocated by `f` on this thread.
+fn allocated_bytes<T>(f: impl FnOnce() -> T) -> usize {
+    let before = ALLOCATED_BYTES.with(Cell::get);
+    f();
+    ALLOCATED_BYTES.with(Cell::get) - before
+}
+
+fn camera_model() -> CameraModel {
+    let focal_length = Length::new::<millimeter>(8.0);
+    let pixel_size = Length::new::<micron>(3.45);
+    CameraModel::new(focal_length, pixel_size * 8.0, 256, 306)
+}
+
+fn car_in_ins_enu(yaw_deg: f64) -> Orientation<InsEnu> {
+    Orientation::tait_bryan_builder()
+        .yaw(Angle::new::<degree>(yaw_deg))
+        .pitch(Angle::new::<degree>(2.))
+        .roll(Angle::new::<degree>(-1.))
+        .build()
+}
+
+#[test]
+fn sky_directions_reuse_their_buffer() {
+    let camera = camera_model();
+    let mut directions = Vec::new();
+    sky_directions_into(&camera, car_in_ins_enu(0.), &mut directions);
+
+    let count = allocations(|| {
+        for yaw_deg in [0.5, 1., 1.5] {
+            sky_directions_into(&camera, car_in_ins_enu(yaw_deg), &mut directions);
+        }
+    });
+    assert_eq!(count, 0);
+    assert_eq!(directions.len(), camera.rows() * camera.cols());
+}
+
+#[test]
+fn glare_masks_reuse_their_buffer() {
+    let camera = camera_model();
+    let glare = GlareConfig {
+        strategy: GlareStrategy::Mask,
+        ..GlareConfig::default()
+    };
+    let sun = CelestialPosition {
+        azimuth: Angle::new::<degree>(180.),
+        elevation: Angle::new::<degree>(70.),
+    };
+    let mut scratch = Scratch::default();
+    glare.mask_with(&camera, car_in_ins_enu(0.), &sun, &mut scratch);
+
+    let count = allocations(|| {
+        for yaw_deg in [0.5, 1., 1.5] {
+            let mask = glare.mask_with(&camera, car_in_ins_enu(yaw_deg), &sun, &mut scratch);
+            assert!(mask.is_some_and(|mask| mask.contains(&true)));
+        }
+    });
+    assert_eq!(count, 0);
+}
+
+#[test]
+fn scratch_pool_hands_back_the_same_buffers() {
+    let camera = camera_model();
+    let pool = ScratchPool::default();
+    let sun = CelestialPosition {
+        azimuth: Angle::ZERO,
+        elevation: Angle::new::<degree>(45.),
+    };
+    let glare = GlareConfig {
+        strategy: GlareStrategy::Mask,
+        ..GlareConfig::default()
+    };
+    pool.with(|scratch| {
+        glare
+            .mask_with(&camera, car_in_ins_enu(0.), &sun, scratch)
+            .is_some()
+    });
+
+    let count = allocations(|| {
+        for yaw_deg in [0.5, 1., 1.5] {
+            pool.with(|scratch| {
+                glare
+                    .mask_with(&camera, car_in_ins_enu(yaw_deg), &sun, scratch)
+                    .is_some()
+            });
+        }
+    });
+    assert_eq!(count, 0);
+    assert_eq!(pool.len(), 1);
+}
+
+#[test]
+fn preallocated_scratch_is_warm_from_the_first_frame() {
+    let camera = camera_model();
+    let pool = ScratchPool::default();
+    pool.preallocate(camera.rows() * camera.cols(), 1);
+    // Bearings are built once per camera, apart from the scratch buffers.
+    PixelGrid::shared(&camera);
+    let sun = CelestialPosition {
+        azimuth: Angle::ZERO,
+        elevation: Angle::new::<degree>(45.),
+    };
+    let glare = GlareConfig {
+        strategy: GlareStrategy::Mask,
+        ..GlareConfig::default()
+    };
+
+    let count = allocations(|| {
+        pool.with(|scratch| {
+            glare
+                .mask_with(&camera, car_in_ins_enu(0.), &sun, scratch)
+                .is_some()
+        });
+    });
+    assert_eq!(count, 0);
+    assert_eq!(pool.len(), 1);
+}
+
+#[test]
+fn warm_simulations_only_allocate_the_skies_they_return() {
+    let camera = camera_model();
+    let sky = Sky::new(1.).with_backend(SkyModelBackend::Berry);
+    let position = InsEnu::position_from_inspva(44.2253, -76.4951, 100.);
+    let time = Utc.with_ymd_and_hms(2024, 6, 21, 16, 0, 0).unwrap();
+    let mut scratch = Scratch::default();
+    sky.simulate_with(&camera, &position, car_in_ins_enu(0.), time, &mut scratch)
+        .unwrap();
+
+    let cold = allocated_bytes(|| sky.simulate(&camera, &position, car_in_ins_enu(0.5), time));
+    let warm = allocated_bytes(|| {
+        sky.simulate_with(&camera, &position, car_in_ins_enu(0.5), time, &mut scratch)
+    });
+    // The directions and rays are the buffers kept in the scratch.
+    let buffers = camera.rows()
+        * camera.cols()
+        * (size_of::<Option<SkyDirection>>() + size_of::<Option<Ray<GlobalFrame>>>());
+    assert!(cold >= warm + buffers, "{cold} bytes cold, {warm} warm");
+}
+
+#[test]
+fn warm_conversions_only_allocate_the_images_they_return() {
+    let camera = camera_model();
+    let pixels = camera.rows() * camera.cols();
+    let ray = Ray::new(Aop::from_angle_wrapped(Angle::new::<degree>(30.)), 0.4);
+    let measured: RayImage<SensorFrame> =
+        RayImage::from_rays(vec![Some(ray); pixels], camera.rows(), camera.cols()).unwrap();
+    let mut scratch = Scratch::default();
+    measured_to_global_with(&measured, &camera, car_in_ins_enu(0.), &mut scratch);
+
+    let cold = allocated_bytes(|| measured_to_global(&measured, &camera, car_in_ins_enu(0.5)));
+    let warm = allocated_bytes(|| {
+        measured_to_global_with(&measured, &camera, car_in_ins_enu(0.5), &mut scratch)
+    });
+    let buffer = pixels * size_of::<Option<Ray<GlobalFrame>>>();
+    assert!(cold >= warm + buffer, "{cold} bytes cold, {warm} warm");
+}
@@ -0,0 +1,122 @@
+"""Writes the miniature dataset used by the golden-file regression tests.
+
+The images are synthetic 64x76 polarizer mosaics with a smooth AoP and DoP field, so the
+dataset stays small and the results only change when the processing changes. Run from this
+directory with plain Python; no packages are needed.
+"""
+
+import math
+import os
+import struct
+import zlib
+from datetime import datetime, timedelta, timezone
+
+ROWS, COLS = 64, 76
+FRAMES = 5
+START = datetime(2024, 6, 21, 16, 0, 0, tzinfo=timezone.utc)
+LEAP_SECONDS = 18
+GPS_EPOCH = datetime(1980, 1, 6, tzinfo=timezone.utc)
+
+# Polarizer angle of each pixel in a 2x2 super-pixel.
+LAYOUT = [[90.0, 45.0], [135.0, 0.0]]
+
+IMAGE_DIR = "camera_driver_gv_vis_image_raw"
+INS_DIR = "novatel_oem7_inspva"
+INSPVAX_DIR = "novatel_oem7_inspvax"
+TIME_DIR = "novatel_oem7_time"
+
+
+def write_png(path, rows):
+    raw = b"".join(b"\x00" + bytes(row) for row in rows)
+
+    def chunk(kind, data):
+        body = kind + data
+        return struct.pack(">I", len(data)) + body + struct.pack(">I", zlib.crc32(body))
+
+    header = struct.pack(">IIBBBBB", COLS, ROWS, 8, 0, 0, 0, 0)
+    with open(path, "wb") as f:
+        f.write(b"\x89PNG\r\n\x1a\n")
+        f.write(chunk(b"IHDR", header))
+        f.write(chunk(b"IDAT", zlib.compress(raw, 9)))
+        f.write(chunk(b"IEND", b""))
+
+
+def mosaic(frame):
+    # The pattern turns by 3 degrees per frame, like a car slowly yawing.
+    rotation = math.radians(3.0 * frame)
+    center_row, center_col = ROWS / 2, COLS / 2
+    rows = []
+    for row in range(ROWS):
+        pixels = []
+        for col in range(COLS):
+            dy, dx = row - center_row, col - center_col
+            radius = math.hypot(dx, dy) / math.hypot(center_row, center_col)
+            aop = math.atan2(dy, dx) + rotation
+            dop = 0.1 + 0.5 * radius
+            polarizer = math.radians(LAYOUT[row % 2][col % 2])
+            intensity = 0.5 * (1.0 + dop * math.cos(2.0 * (polarizer - aop)))
+            pixels.append(round(40 + 180 * intensity))
+        rows.append(pixels)
+    return rows
+
+
+def gps_week(time):
+    elapsed = time + timedelta(seconds=LEAP_SECONDS) - GPS_EPOCH
+    week = elapsed.days // 7
+    week_msec = round((elapsed - timedelta(weeks=week)).total_seconds() * 1000)
+    return week, week_msec
+
+
+def header_fields(time, columns=22):
+    # Column layout shared by the NovAtel logs exported from the ROS bag.
+    week, week_msec = gps_week(time)
+    fields = ["0"] * columns
+    fields[3] = str(int(time.timestamp()))
+    fields[4] = str(time.microsecond * 1000)
+    fields[11] = str(week)
+    fields[12] = str(week_msec)
+    return fields
+
+
+def main():
+    for directory in (IMAGE_DIR, INS_DIR, INSPVAX_DIR, TIME_DIR):
+        os.makedirs(directory, exist_ok=True)
+
+    columns = ",".join(f"field_{i}" for i in range(22))
+    ins_lines = [columns]
+    inspvax_lines = [",".join(f"field_{i}" for i in range(36))]
+    time_lines = [columns]
+    for frame in range(FRAMES):
+        time = START + timedelta(milliseconds=500 * frame)
+
+        write_png(f"{IMAGE_DIR}/{IMAGE_DIR}_{frame:04}.png", mosaic(frame))
+
+        ins = header_fields(time)
+        ins[13], ins[14], ins[15] = "44.2253", "-76.4951", "100.0"
+        ins[19], ins[20], ins[21] = "0.5", "-1.0", f"{90.0 + 3.0 * frame:.1f}"
+        ins_lines.append(",".join(ins))
+
+        # The same state with its standard deviations, the height above mean sea level.
+        inspvax = header_fields(time, 36)
+        inspvax[13], inspvax[14] = "3", "56"
+        inspvax[15], inspvax[16], inspvax[17], inspvax[18] = ins[13], ins[14], "134.0", "-34.0"
+        inspvax[22], inspvax[23], inspvax[24] = ins[19], ins[20], ins[21]
+        inspvax[25], inspvax[26], inspvax[27] = "0.02", "0.03", "0.05"
+        inspvax[31], inspvax[32], inspvax[33] = "0.01", "0.01", f"{0.1 + 0.1 * frame:.1f}"
+        inspvax_lines.append(",".join(inspvax))
+
+        gps_time = header_fields(time)
+        gps_time[14] = "0.0"
+        gps_time[16] = f"{-LEAP_SECONDS:.1f}"
+        time_lines.append(",".join(gps_time))
+
+    with open(f"{INS_DIR}/{INS_DIR}.csv", "w") as f:
+        f.write("\n".join(ins_lines) + "\n")
+    with open(f"{INSPVAX_DIR}/{INSPVAX_DIR}.csv", "w") as f:
+        f.write("\n".join(inspvax_lines) + "\n")
+    with open(f"{TIME_DIR}/{TIME_DIR}.csv", "w") as f:
+        f.write("\n".join(time_lines) + "\n")
+
+
+if __name__ == "__main__":
+    main()
@@ -0,0 +1,191 @@
+//! Regression tests against the miniature dataset in `tests/data/mini`.
+//!
+//! The golden values are recorded by the first run on a checkout without them. Record new
+//! ones after an intended change with `UPDATE_GOLDEN=1 cargo test --test golden`.
+
+use rumpus_benchmark::{
+    camera::CameraModel,
+    estimator::{FrameInput, HeadingEstimator, estimate_heading},
+    io::{ImageReader, InsReader, TimeReader},
+    pipeline::{Dataset, FrameContext, FrameProcessor, FrameSkip, Pipeline},
+    run::{RunProfile, SkipReason},
+    sky::Sky,
+    utils::{measured_to_global, weighted_rmse},
+};
+use serde::{Deserialize, Serialize};
+use std::path::{Path, PathBuf};
+use uom::si::{
+    angle::degree,
+    f64::Length,
+    length::{micron, millimeter},
+};
+
+const WEIGHTED_RMSE_TOLERANCE: f64 = 1e-6;
+const YAW_TOLERANCE_DEG: f64 = 1e-3;
+
+/// How far the estimated yaw may move when candidates are scored in single precision.
+const F32_YAW_TOLERANCE_DEG: f64 = 0.05;
+
+/// Yaw offsets swept around the INS heading.
+const SEARCH_HALF_WIDTH_DEG: i32 = 10;
+
+fn data_dir() -> PathBuf {
+    Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/data/mini")
+}
+
+fn golden_path() -> PathBuf {
+    data_dir().join("golden.csv")
+}
+
+/// The flight camera binned down to the 64x76 mosaic of the miniature dataset.
+fn camera_model() -> CameraModel {
+    let focal_length = Length::new::<millimeter>(8.0);
+    let pixel_size = Length::new::<micron>(3.45);
+    CameraModel::new(focal_length, pixel_size * 2.0 * 32.0, 32, 38)
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+struct GoldenRecord {
+    frame_index: usize,
+    weighted_rmse: f64,
+    estimated_yaw_deg: Option<f64>,
+}
+
+/// Scores the INS attitude and estimates the yaw of every frame.
+struct GoldenProcessor {
+    estimator: HeadingEstimator,
+    image_reader: ImageReader,
+    records: Vec<GoldenRecord>,
+}
+
+impl FrameProcessor for GoldenProcessor {
+    fn process(&mut self, frame: &FrameContext) -> Result<(), FrameSkip> {
+        let car_in_ins_enu = frame.ins.orientation;
+        let (car_yaw, _, _) = car_in_ins_enu.to_tait_bryan_angles();
+
+        let (image, image_circle) = self
+            .image_reader
+            .read_image_with_circle(&frame.image_path)
+            .map_err(|e| FrameSkip::new(SkipReason::UnreadableImage, e))?;
+        let measured = measured_to_global(&image, self.estimator.camera(), car_in_ins_enu);
+        let simulated = self
+            .estimator
+            .sky()
+            .simulate(
+                self.estimator.camera(),
+                &frame.ins.position,
+                car_in_ins_enu,
+                frame.time,
+            )
+            .map_err(|e| FrameSkip::new(SkipReason::SimulationFailed, e))?;
+
+        let input = FrameInput {
+            frame_index: frame.frame_index,
+            image: &image,
+            image_circle,
+            position: &frame.ins.position,
+            time: frame.time,
+            car_in_ins_enu,
+            yaw_smear: frame.yaw_smear,
+        };
+        let yaw_offsets: Vec<f64> = (-SEARCH_HALF_WIDTH_DEG..=SEARCH_HALF_WIDTH_DEG)
+            .map(f64::from)
+            .collect();
+        let estimate = estimate_heading(&self.estimator.sweep(&input, &yaw_offsets));
+
+        self.records.push(GoldenRecord {
+            frame_index: frame.frame_index,
+            weighted_rmse: weighted_rmse(&simulated, &measured),
+            estimated_yaw_deg: estimate.map(|e| car_yaw.get::<degree>() + e.yaw_offset_deg),
+        });
+        Ok(())
+    }
+}
+
+fn run_mini_dataset() -> Vec<GoldenRecord> {
+    run_mini_dataset_with(RunProfile::Workstation)
+}
+
+fn run_mini_dataset_with(profile: RunProfile) -> Vec<GoldenRecord> {
+    let pipeline = Pipeline::open(
+        Dataset::new(data_dir()),
+        &InsReader::new(),
+        &TimeReader::new(),
+    )
+    .unwrap();
+    let mut processor = GoldenProcessor {
+        estimator: HeadingEstimator::new(camera_model(), Sky::new(1.0)).with_profile(profile),
+        image_reader: ImageReader::new(),
+        records: Vec::new(),
+    };
+    let summary = pipeline.run(&mut processor);
+    assert_eq!(
+        summary.frames_processed, 5,
+        "every frame should be processed"
+    );
+    processor.records
+}
+
+fn write_golden(records: &[GoldenRecord]) {
+    let mut writer = csv::Writer::from_path(golden_path()).unwrap();
+    for record in records {
+        writer.serialize(record).unwrap();
+    }
+    writer.flush().unwrap();
+}
+
+fn read_golden() -> Vec<GoldenRecord> {
+    let mut reader = csv::Reader::from_path(golden_path()).unwrap();
+    reader.deserialize().map(Result::unwrap).collect()
+}
+
+#[test]
+fn mini_dataset_matches_golden_values() {
+    let records = run_mini_dataset();
+    if std::env::var_os("UPDATE_GOLDEN").is_some() || !golden_path().exists() {
+        write_golden(&records);
+        eprintln!("recorded golden values in {}", golden_path().display());
+        return;
+    }
+
+    let golden = read_golden();
+    assert_eq!(records.len(), golden.len());
+    for (record, golden) in records.iter().zip(&golden) {
+        let frame_index = golden.frame_index;
+        assert_eq!(record.frame_index, frame_index);
+        assert!(
+            (record.weighted_rmse - golden.weighted_rmse).abs() <= WEIGHTED_RMSE_TOLERANCE,
+            "frame {frame_index}: weighted RMSE {} differs from golden {}",
+            record.weighted_rmse,
+            golden.weighted_rmse
+        );
+        match (record.estimated_yaw_deg, golden.estimated_yaw_deg) {
+            (Some(yaw), Some(golden_yaw)) => assert!(
+                (yaw - golden_yaw).abs() <= YAW_TOLERANCE_DEG,
+                "frame {frame_index}: estimated yaw {yaw} differs from golden {golden_yaw}"
+            ),
+            (yaw, golden_yaw) => assert_eq!(
+                yaw, golden_yaw,
+                "frame {frame_index}: estimate found {yaw:?}, golden {golden_yaw:?}"
+            ),
+        }
+    }
+}
+
+#[test]
+fn embedded_profile_estimates_like_the_workstation() {
+    let workstation = run_mini_dataset();
+    let embedded = run_mini_dataset_with(RunProfile::Embedded);
+
+    assert_eq!(embedded.len(), workstation.len());
+    for (embedded, workstation) in embedded.iter().zip(&workstation) {
+        let frame_index = workstation.frame_index;
+        match (embedded.estimated_yaw_deg, workstation.estimated_yaw_deg) {
+            (Some(yaw), Some(workstation_yaw)) => assert!(
+                (yaw - workstation_yaw).abs() <= F32_YAW_TOLERANCE_DEG,
+                "frame {frame_index}: estimated yaw {yaw} differs from {workstation_yaw}"
+            ),
+            (yaw, workstation_yaw) => assert_eq!(yaw, workstation_yaw, "frame {frame_index}"),
+        }
+    }
+}
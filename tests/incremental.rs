@@ -0,0 +1,80 @@
+//! Reuse of the previous frame's sky by the incremental simulation.
+
+use chrono::{DateTime, TimeZone, Utc};
+use rumpus_benchmark::{
+    camera::CameraModel,
+    incremental::{IncrementalSky, IncrementalStats},
+    sky::{Sky, SkyModelBackend},
+    systems::InsEnu,
+};
+use sguaba::engineering::Orientation;
+use uom::{
+    ConstZero,
+    si::{
+        angle::degree,
+        f64::{Angle, Length},
+        length::{micron, millimeter},
+    },
+};
+
+fn camera_model() -> CameraModel {
+    let focal_length = Length::new::<millimeter>(8.0);
+    let pixel_size = Length::new::<micron>(3.45);
+    CameraModel::new(focal_length, pixel_size * 2.0, 1024, 1224).downsampled(8)
+}
+
+fn time() -> DateTime<Utc> {
+    Utc.with_ymd_and_hms(2024, 6, 21, 16, 0, 0).unwrap()
+}
+
+fn attitude(yaw_deg: f64) -> Orientation<InsEnu> {
+    Orientation::<InsEnu>::tait_bryan_builder()
+        .yaw(Angle::new::<degree>(yaw_deg))
+        .pitch(Angle::ZERO)
+        .roll(Angle::ZERO)
+        .build()
+}
+
+/// Simulates a frame at each yaw in turn, returning how much of each was reused.
+fn simulate_frames(sky: &Sky, yaws_deg: &[f64]) -> Vec<IncrementalStats> {
+    let camera_model = camera_model();
+    let position = InsEnu::position_from_inspva(44.2253, -76.4951, 100.);
+    let mut incremental = IncrementalSky::new(0.05, 0.5);
+    yaws_deg
+        .iter()
+        .map(|&yaw_deg| {
+            incremental
+                .simulate(sky, &camera_model, &position, attitude(yaw_deg), time())
+                .unwrap()
+                .1
+        })
+        .collect()
+}
+
+#[test]
+fn incremental_sky_reuses_a_repeated_frame() {
+    let sky = Sky::new(1.0).with_backend(SkyModelBackend::Berry);
+    let [first, repeated, turned] = simulate_frames(&sky, &[0., 0., 10.])[..] else {
+        unreachable!();
+    };
+
+    assert!(first.full);
+    assert!(!first.bypassed);
+    assert!(!repeated.full);
+    assert_eq!(repeated.simulated, 0);
+    assert!(repeated.max_error_deg < 1e-9);
+    // Whatever is reused after a turn stays within the error bound.
+    assert!(turned.max_error_deg <= 0.5);
+}
+
+#[test]
+fn rumpus_skies_are_always_simulated_in_full() {
+    let stats = simulate_frames(&Sky::new(1.0), &[0., 0.]);
+
+    for frame in stats {
+        assert!(frame.full);
+        assert!(frame.bypassed);
+        assert_eq!(frame.reused, 0);
+        assert!(frame.simulated > 0);
+    }
+}
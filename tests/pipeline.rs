@@ -0,0 +1,835 @@
+use chrono::{DateTime, Duration, TimeZone, Utc};
+use rumpus_benchmark::{
+    archive,
+    batch::{CompletedRuns, dataset_version, run_hash},
+    dashboard,
+    error::BenchError,
+    export::{ImageFormat, NpyArray, read_npz, write_npz},
+    image_circle::{ImageCircle, auto_radius_px},
+    image_writer::ImageWriter,
+    integrity::{DatasetManifest, verify_dataset},
+    io::{
+        ImageOrientation, ImageReader, InsFrame, InsLog, InsReader, InsStatus, TimeFrame,
+        TimeReader,
+    },
+    metrics,
+    monitor::{RunMonitor, RunProgress},
+    motion::FrameAlignment,
+    notify::{CompletionHooks, CompletionReport},
+    outage::OutagePhase,
+    overlay::Overlay,
+    pipeline::{
+        Dataset, FrameContext, FrameProcessor, FrameSkip, InsStatusAction, Pipeline, ResultWriter,
+    },
+    prefetch::ImagePrefetch,
+    read_ahead::ReadAhead,
+    remote,
+    run::{RunSummary, SkipReason},
+    systems::{AzimuthConvention, InsEnu},
+    time_offset::{OffsetSample, TimeCalibration, best_latency, latencies_ms},
+    trim::DatasetTrimmer,
+};
+use std::{
+    io::{Read, Write},
+    net::TcpStream,
+    path::{Path, PathBuf},
+    sync::{Arc, atomic::AtomicBool},
+    time::Instant,
+};
+use uom::si::{angle::degree, length::meter};
+
+fn start() -> DateTime<Utc> {
+    Utc.with_ymd_and_hms(2024, 6, 21, 16, 0, 0).unwrap()
+}
+
+/// One INS record and one camera frame every 100 ms, turning at 10 deg/s.
+fn pipeline(frames: usize) -> Pipeline {
+    pipeline_with_ins_status(frames, |_| None)
+}
+
+/// Like [`pipeline`], with the INS reporting a solution status for each record.
+fn pipeline_with_ins_status(
+    frames: usize,
+    status: impl Fn(usize) -> Option<InsStatus>,
+) -> Pipeline {
+    let times: Vec<_> = (0..frames)
+        .map(|i| start() + Duration::milliseconds(100 * i as i64))
+        .collect();
+    let ins_frames = times
+        .iter()
+        .enumerate()
+        .map(|(i, &time)| InsFrame {
+            time,
+            position: InsEnu::position_from_inspva(44.2253, -76.4951, 100.),
+            orientation: InsEnu::orientation_from_inspva(
+                AzimuthConvention::LeftHandedFromNorth,
+                i as f64,
+                0.,
+                0.,
+            ),
+            covariance: None,
+            status: status(i),
+        })
+        .collect();
+    let time_frames = times.into_iter().map(|time| TimeFrame { time }).collect();
+    Pipeline::new(Dataset::new("dataset"), ins_frames, time_frames)
+}
+
+/// Records every frame it sees and skips the ones it is told to.
+#[derive(Default)]
+struct Recorder {
+    frames: Vec<FrameContext>,
+    skip: Vec<usize>,
+}
+
+impl FrameProcessor for Recorder {
+    fn process(&mut self, frame: &FrameContext) -> Result<(), FrameSkip> {
+        self.frames.push(frame.clone());
+        if self.skip.contains(&frame.frame_index) {
+            return Err(FrameSkip::new(SkipReason::UnreadableImage, "missing"));
+        }
+        Ok(())
+    }
+}
+
+impl Recorder {
+    fn indices(&self) -> Vec<usize> {
+        self.frames.iter().map(|frame| frame.frame_index).collect()
+    }
+}
+
+#[test]
+fn dataset_paths_follow_the_ros_bag_export() {
+    let dataset = Dataset::new("/data/run");
+    assert_eq!(
+        dataset.image_path(7),
+        PathBuf::from(
+            "/data/run/camera_driver_gv_vis_image_raw/camera_driver_gv_vis_image_raw_0007.png"
+        )
+    );
+    assert_eq!(
+        dataset.ins_path(),
+        PathBuf::from("/data/run/novatel_oem7_inspva/novatel_oem7_inspva.csv")
+    );
+    assert_eq!(
+        dataset.time_path(),
+        PathBuf::from("/data/run/novatel_oem7_time/novatel_oem7_time.csv")
+    );
+}
+
+#[test]
+fn run_visits_every_step_th_frame() {
+    let mut recorder = Recorder::default();
+    let summary = pipeline(10).with_step(3).run(&mut recorder);
+
+    assert_eq!(recorder.indices(), vec![0, 3, 6, 9]);
+    assert_eq!(summary.frames_processed, 4);
+    assert_eq!(summary.frames_skipped(), 0);
+}
+
+#[test]
+fn run_stops_after_max_frames_are_processed() {
+    let mut recorder = Recorder {
+        skip: vec![1],
+        ..Recorder::default()
+    };
+    let summary = pipeline(10).with_max_frames(Some(3)).run(&mut recorder);
+
+    // Skipped frames do not count towards the limit.
+    assert_eq!(recorder.indices(), vec![0, 1, 2, 3]);
+    assert_eq!(summary.frames_processed, 3);
+    assert_eq!(summary.frames_skipped[&SkipReason::UnreadableImage], 1);
+}
+
+#[test]
+fn interrupts_only_stop_the_pipeline_they_are_passed_to() {
+    let interrupted = Arc::new(AtomicBool::new(true));
+    let mut recorder = Recorder::default();
+    let summary = pipeline(10)
+        .with_interrupt(Arc::clone(&interrupted))
+        .run(&mut recorder);
+    assert!(summary.interrupted);
+    assert_eq!(summary.frames_processed, 0);
+
+    let mut recorder = Recorder::default();
+    let summary = pipeline(10).run(&mut recorder);
+    assert!(!summary.interrupted);
+    assert_eq!(summary.frames_processed, 10);
+}
+
+#[test]
+fn run_skips_frames_without_an_ins_state() {
+    // Shifting every exposure by a second leaves the last frames past the INS record.
+    let latency = Duration::milliseconds(-1000);
+    let mut recorder = Recorder::default();
+    let summary = pipeline(20)
+        .with_alignment(FrameAlignment::Interpolate { latency })
+        .run(&mut recorder);
+
+    assert_eq!(summary.frames_processed, 10);
+    assert_eq!(summary.frames_skipped[&SkipReason::NoInsState], 10);
+    assert_eq!(recorder.indices(), (0..10).collect::<Vec<_>>());
+}
+
+#[test]
+fn run_skips_or_flags_frames_while_the_ins_aligns() {
+    let status = |i| {
+        Some(if i < 3 {
+            InsStatus::InsAligning
+        } else {
+            InsStatus::InsSolutionGood
+        })
+    };
+
+    let mut recorder = Recorder::default();
+    let summary = pipeline_with_ins_status(10, status).run(&mut recorder);
+    assert_eq!(summary.frames_skipped[&SkipReason::DegradedIns], 3);
+    assert_eq!(recorder.indices(), (3..10).collect::<Vec<_>>());
+
+    let mut recorder = Recorder::default();
+    let summary = pipeline_with_ins_status(10, status)
+        .with_ins_status(InsStatusAction::Flag)
+        .run(&mut recorder);
+    assert_eq!(summary.frames_processed, 10);
+    let degraded: Vec<_> = recorder
+        .frames
+        .iter()
+        .map(|frame| frame.ins_degraded)
+        .collect();
+    assert_eq!(degraded, (0..10).map(|i| i < 3).collect::<Vec<_>>());
+}
+
+#[test]
+fn run_marks_frames_inside_and_after_an_outage() {
+    let mut recorder = Recorder::default();
+    pipeline(10)
+        .with_outages(vec!["0.25..0.55".parse().unwrap()])
+        .run(&mut recorder);
+
+    let phases: Vec<_> = recorder
+        .frames
+        .iter()
+        .map(|frame| frame.outage.name())
+        .collect();
+    let expected: Vec<_> = ["nominal"; 3]
+        .into_iter()
+        .chain(["denied"; 3])
+        .chain(["recovering"; 4])
+        .collect();
+    assert_eq!(phases, expected);
+    let elapsed = |i: usize| recorder.frames[i].outage.elapsed_s().unwrap();
+    assert!((elapsed(4) - 0.15).abs() < 1e-9);
+    assert!((elapsed(9) - 0.35).abs() < 1e-9);
+    assert_eq!(recorder.frames[0].outage, OutagePhase::Nominal);
+}
+
+#[test]
+fn run_flags_fast_turns_and_smears_the_exposure() {
+    let mut recorder = Recorder::default();
+    pipeline(10)
+        .with_max_yaw_rate(Some(5.))
+        .with_exposure(Some(20.))
+        .run(&mut recorder);
+
+    let frame = &recorder.frames[5];
+    let yaw_rate = frame.yaw_rate_deg_s.expect("inside the INS record");
+    // Azimuth increases clockwise, so yaw decreases.
+    assert!((yaw_rate + 10.).abs() < 1e-6, "{yaw_rate}");
+    assert!(frame.yaw_rate_exceeded);
+    assert!((frame.yaw_smear.get::<degree>() + 0.2).abs() < 1e-6);
+    assert_eq!(frame.image_path, Dataset::new("dataset").image_path(5));
+}
+
+#[test]
+fn result_writer_creates_a_fresh_directory() {
+    let parent = std::env::temp_dir().join(format!("rumpus_results_{}", std::process::id()));
+    std::fs::create_dir_all(&parent).unwrap();
+
+    let results = ResultWriter::create_in(&parent).unwrap();
+    assert!(results.dir().is_dir());
+    assert!(results.dir().ends_with(results.started()));
+
+    let mut writer = results.csv("results.csv").unwrap();
+    writer
+        .write_record(["frame_index", "weighted_rmse"])
+        .unwrap();
+    writer.write_record(["0", "1.5"]).unwrap();
+    writer.flush().unwrap();
+    let written = std::fs::read_to_string(results.dir().join("results.csv")).unwrap();
+    assert_eq!(written, "frame_index,weighted_rmse\n0,1.5\n");
+
+    std::fs::remove_dir_all(&parent).unwrap();
+}
+
+#[test]
+fn npz_archives_read_back_as_written() {
+    let path = std::env::temp_dir().join(format!("rumpus_arrays_{}.npz", std::process::id()));
+    write_npz(
+        &path,
+        &[
+            (
+                "aop_deg",
+                NpyArray::from_f32(vec![2, 3], &[0., -45., 90., 1.5, f32::NAN, 3.]),
+            ),
+            (
+                "valid",
+                NpyArray::from_bool(vec![2, 3], &[true, true, true, true, false, true]),
+            ),
+            ("frame_index", NpyArray::from_u64(vec![1], &[7])),
+        ],
+    )
+    .unwrap();
+
+    let arrays = read_npz(&path).unwrap();
+    std::fs::remove_file(&path).unwrap();
+    assert_eq!(
+        arrays.keys().collect::<Vec<_>>(),
+        ["aop_deg", "frame_index", "valid"]
+    );
+    let aop_deg = arrays["aop_deg"].to_f32();
+    assert_eq!(arrays["aop_deg"].shape(), [2, 3]);
+    assert_eq!(aop_deg[..4], [0., -45., 90., 1.5]);
+    assert!(aop_deg[4].is_nan());
+    assert_eq!(arrays["valid"].to_f32(), [1., 1., 1., 1., 0., 1.]);
+    assert_eq!(arrays["frame_index"].shape(), [1]);
+    assert_eq!(arrays["frame_index"].to_f32(), [7.]);
+}
+
+#[test]
+fn dashboard_serves_the_progress_of_a_run() {
+    let monitor = RunMonitor::new("test_pattern_match", "2024-06-01_12-00-00");
+    let addr = dashboard::serve("127.0.0.1:0".parse().unwrap(), monitor.clone()).unwrap();
+    monitor.update(|progress| {
+        progress.frames_processed = 3;
+        progress.heading_errors_deg.push((2, -0.5));
+    });
+
+    let get = |path: &str| {
+        let mut stream = TcpStream::connect(addr).unwrap();
+        write!(stream, "GET {path} HTTP/1.1\r\nHost: localhost\r\n\r\n").unwrap();
+        let mut response = String::new();
+        stream.read_to_string(&mut response).unwrap();
+        response
+    };
+    let response = get("/progress.json");
+    assert!(response.starts_with("HTTP/1.1 200 OK"), "{response}");
+    let body = response.split("\r\n\r\n").nth(1).unwrap();
+    let progress: serde_json::Value = serde_json::from_str(body).unwrap();
+    assert_eq!(progress["frames_processed"], 3);
+    assert_eq!(
+        progress["heading_errors_deg"],
+        serde_json::json!([[2, -0.5]])
+    );
+
+    assert!(get("/").contains("<title>rumpus run</title>"));
+    // No frame has an image yet.
+    assert!(get("/frame.png").starts_with("HTTP/1.1 404"));
+
+    // A client that connects and never sends its request holds up no one else.
+    let _stalled = TcpStream::connect(addr).unwrap();
+    let started = Instant::now();
+    assert!(get("/progress.json").starts_with("HTTP/1.1 200 OK"));
+    assert!(started.elapsed().as_secs_f64() < 2.);
+}
+
+#[test]
+fn metrics_follow_the_prometheus_text_format() {
+    let mut progress = RunProgress {
+        experiment: "test_pattern_match".to_string(),
+        started: "2024-06-01_12-00-00".to_string(),
+        frames_processed: 10,
+        candidates_evaluated: 1010,
+        last_weighted_rmse: Some(0.25),
+        ..RunProgress::default()
+    };
+    progress.frames_skipped.insert(SkipReason::NoInsState, 2);
+    let text = metrics::render(&progress, 10.);
+
+    let labels = r#"experiment="test_pattern_match",run="2024-06-01_12-00-00""#;
+    for line in [
+        "# TYPE rumpus_frames_processed_total counter".to_string(),
+        format!("rumpus_frames_processed_total{{{labels}}} 10"),
+        format!(r#"rumpus_frames_skipped_total{{{labels},reason="no_ins_state"}} 2"#),
+        format!("rumpus_candidates_per_second{{{labels}}} 101"),
+        format!("rumpus_last_weighted_rmse{{{labels}}} 0.25"),
+        format!("rumpus_run_finished{{{labels}}} 0"),
+    ] {
+        assert!(
+            text.lines().any(|l| l == line),
+            "{line} missing from\n{text}"
+        );
+    }
+    // Frames without an estimate yet have no heading error to report.
+    assert!(!text.contains("rumpus_heading_error_degrees"));
+    assert!(!text.contains("rumpus_heading_error_rms_degrees"));
+
+    progress.heading_errors_deg = vec![(0, 3.), (1, -4.)];
+    let text = metrics::render(&progress, 10.);
+    for line in [
+        format!("rumpus_heading_error_degrees{{{labels}}} -4"),
+        format!(
+            "rumpus_heading_error_rms_degrees{{{labels}}} {}",
+            12.5_f64.sqrt()
+        ),
+    ] {
+        assert!(
+            text.lines().any(|l| l == line),
+            "{line} missing from\n{text}"
+        );
+    }
+}
+
+#[test]
+fn completion_commands_receive_the_report() {
+    let dir = std::env::temp_dir().join(format!("rumpus_hooks_{}", std::process::id()));
+    std::fs::create_dir_all(&dir).unwrap();
+    let report_path = dir.join("report.json");
+    let hooks = CompletionHooks::new(
+        vec![format!(
+            "cat > '{}' && test \"$RUMPUS_RUN_OUTCOME\" = finished",
+            report_path.display()
+        )],
+        Vec::new(),
+    )
+    .unwrap();
+
+    let mut summary = RunSummary::new();
+    summary.processed();
+    summary.skip(4, SkipReason::SunInView, "sun disc is in view");
+    hooks.notify(
+        &CompletionReport::new("test_pattern_match", "2024-06-01_12-00-00", &dir)
+            .with_summary(&summary),
+    );
+
+    let report: serde_json::Value =
+        serde_json::from_str(&std::fs::read_to_string(&report_path).unwrap()).unwrap();
+    std::fs::remove_dir_all(&dir).unwrap();
+    assert_eq!(report["outcome"], "finished");
+    assert_eq!(
+        report["text"],
+        "test_pattern_match run 2024-06-01_12-00-00 finished: processed 1 frames, skipped 1"
+    );
+    assert_eq!(report["summary"]["frames_skipped"]["sun_in_view"], 1);
+}
+
+#[test]
+fn completed_runs_are_reused_until_their_results_are_removed() {
+    let dir = std::env::temp_dir().join(format!("rumpus_completed_{}", std::process::id()));
+    let results_dir = dir.join("run");
+    std::fs::create_dir_all(&results_dir).unwrap();
+    let args = vec!["--window-deg".to_string(), "5".to_string()];
+    let hash = run_hash("0123", "test_pattern_match", &dir, "89ab", &args);
+    assert_ne!(
+        hash,
+        run_hash("4567", "test_pattern_match", &dir, "89ab", &args),
+        "a rebuilt experiment must run again"
+    );
+
+    let dataset = dir.join("dataset");
+    std::fs::create_dir_all(&dataset).unwrap();
+    std::fs::write(dataset.join("time.csv"), "time\n1.0\n").unwrap();
+    let version = dataset_version(&dataset).unwrap();
+    assert_eq!(dataset_version(&dataset).unwrap(), version);
+    std::fs::write(dataset.join("time.csv"), "time\n1.0\n2.0\n").unwrap();
+    assert_ne!(
+        dataset_version(&dataset).unwrap(),
+        version,
+        "a changed dataset must run again"
+    );
+
+    let mut completed = CompletedRuns::default();
+    completed.insert(hash.clone(), &results_dir);
+    assert!(completed.get(&hash).is_none(), "the run wrote no summary");
+    RunSummary::new().write(&results_dir).unwrap();
+
+    let path = dir.join("completed_runs.json");
+    completed.write(&path).unwrap();
+    let completed = CompletedRuns::read(&path).unwrap();
+    assert!(completed.get(&hash).is_some());
+    std::fs::remove_dir_all(&results_dir).unwrap();
+    assert!(completed.get(&hash).is_none());
+    std::fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn dataset_manifests_catch_truncated_and_modified_files() {
+    let dir = std::env::temp_dir().join(format!("rumpus_integrity_{}", std::process::id()));
+    let dataset = Dataset::new(&dir);
+    std::fs::create_dir_all(dataset.image_dir()).unwrap();
+    std::fs::create_dir_all(dataset.time_path().parent().unwrap()).unwrap();
+    std::fs::write(dataset.time_path(), "time\n1.0\n2.0\n").unwrap();
+    std::fs::write(dataset.image_path(0), [0_u8; 64]).unwrap();
+    std::fs::write(dataset.image_path(1), [1_u8; 64]).unwrap();
+
+    let manifest = DatasetManifest::build(&dataset, 2).unwrap();
+    manifest.write(&dataset).unwrap();
+    assert_eq!(manifest.images, 2);
+    assert_eq!(manifest.files.len(), 3);
+    assert_eq!(manifest.records.values().copied().collect::<Vec<_>>(), [2]);
+    verify_dataset(&dataset, false).unwrap();
+    verify_dataset(&dataset, true).unwrap();
+
+    std::fs::write(dataset.image_path(0), [0_u8; 32]).unwrap();
+    std::fs::write(dataset.image_path(1), [2_u8; 64]).unwrap();
+    std::fs::write(dataset.time_path(), "time\n1.0\n2.5\n3.0\n").unwrap();
+    std::fs::write(dir.join("notes.txt"), "copied from the car").unwrap();
+    let quick = manifest.problems(&dataset, 2, false);
+    let hashed = manifest.problems(&dataset, 2, true);
+    let result = verify_dataset(&dataset, false);
+    std::fs::remove_dir_all(&dir).unwrap();
+
+    assert_eq!(hashed.len(), 5, "{hashed:?}");
+    assert!(hashed[0].contains("is 32 bytes but was 64"));
+    assert!(hashed[1].contains("has been modified"));
+    assert!(hashed[2].contains("is 17 bytes but was 13"));
+    assert!(hashed[3].contains("has 3 records but had 2"));
+    assert!(hashed[4].contains("notes.txt is not in the manifest"));
+    // Without hashing, the rewritten image is only caught by its modification time.
+    assert!(quick[1].contains("changed after the manifest was written"));
+    assert_eq!(quick[2..], hashed[2..]);
+    assert!(result.is_err());
+}
+
+#[test]
+fn inspvax_records_read_like_their_inspva_twins() {
+    let dataset = Dataset::new(Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/data/mini"));
+    let read = |log: InsLog| -> Vec<InsFrame> {
+        InsReader::new()
+            .with_log(log)
+            .read_csv(dataset.ins_log_path(log))
+            .unwrap()
+            .collect()
+    };
+    let (inspva, inspvax) = (read(InsLog::Inspva), read(InsLog::Inspvax));
+
+    assert_eq!(inspvax.len(), inspva.len());
+    for (i, (inspva, inspvax)) in inspva.iter().zip(&inspvax).enumerate() {
+        assert_eq!(inspvax.time, inspva.time);
+        // The height is above mean sea level until the undulation is added back.
+        assert!((inspvax.position.altitude().get::<meter>() - 100.).abs() < 1e-9);
+        assert_eq!(inspvax.position.latitude(), inspva.position.latitude());
+        assert_eq!(inspvax.position.longitude(), inspva.position.longitude());
+        assert_eq!(
+            inspvax.orientation.to_tait_bryan_angles(),
+            inspva.orientation.to_tait_bryan_angles()
+        );
+        // The INSPVA export leaves the status out.
+        assert_eq!(inspvax.status, Some(InsStatus::InsSolutionGood));
+        assert_eq!(inspva.status, None);
+
+        let covariance = inspvax.covariance.unwrap();
+        assert!((covariance.position_m2[2] - 0.05 * 0.05).abs() < 1e-12);
+        assert!((covariance.attitude_deg2[0] - 0.01 * 0.01).abs() < 1e-12);
+        assert!((covariance.azimuth_sd_deg() - 0.1 * (i + 1) as f64).abs() < 1e-9);
+        assert!(inspva.covariance.is_none());
+    }
+}
+
+#[test]
+fn trimmed_datasets_keep_their_frames_paired() {
+    let from = Dataset::new(Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/data/mini"));
+    let dir = std::env::temp_dir().join(format!("rumpus_trim_{}", std::process::id()));
+    let to = Dataset::new(&dir);
+    let summary = DatasetTrimmer::new("1..4".parse().unwrap())
+        .with_position_grid(Some(0.01))
+        .trim(&from, &to)
+        .unwrap();
+
+    // Frames are paired with INS records by index, whose GPS time is in column 12.
+    let times = |dataset: &Dataset| -> Vec<_> {
+        let time_frames = TimeReader::new().read_csv(dataset.time_path()).unwrap();
+        let ins_records = csv::Reader::from_path(dataset.ins_path())
+            .unwrap()
+            .into_records()
+            .map(|record| record.unwrap()[12].to_string());
+        time_frames
+            .map(|frame| frame.time)
+            .zip(ins_records)
+            .collect()
+    };
+    let (original, trimmed) = (times(&from), times(&to));
+    let image = std::fs::read(to.image_path(0)).unwrap();
+    let latitudes: Vec<f64> = csv::Reader::from_path(to.ins_path())
+        .unwrap()
+        .records()
+        .map(|record| record.unwrap()[13].parse().unwrap())
+        .collect();
+    std::fs::remove_dir_all(&dir).unwrap();
+
+    assert_eq!(summary.frames, 3);
+    assert_eq!(trimmed, original[1..4]);
+    assert_eq!(image, std::fs::read(from.image_path(1)).unwrap());
+    assert!(
+        summary
+            .left_out
+            .iter()
+            .any(|path| path.ends_with("generate.py"))
+    );
+
+    // The trajectory moves onto the grid by less than half its spacing.
+    let (lat_offset, _) = summary.position_offset_deg.unwrap();
+    assert!(lat_offset.abs() <= 0.005);
+    assert!((latitudes[0] / 0.01 - (latitudes[0] / 0.01).round()).abs() < 1e-6);
+}
+
+#[test]
+fn time_offset_scans_refine_the_best_latency() {
+    let latencies = latencies_ms(-50., 50., 10.);
+    assert_eq!(latencies.len(), 11);
+    let samples: Vec<_> = latencies
+        .iter()
+        .map(|&camera_latency_ms| OffsetSample {
+            camera_latency_ms,
+            mean_weighted_rmse: 0.1 + (camera_latency_ms - 13.).powi(2) * 1e-4,
+            frames: 20,
+        })
+        .collect();
+    let best = best_latency(&samples).unwrap();
+    assert!((best.camera_latency_ms - 13.).abs() < 1e-9);
+
+    let path = std::env::temp_dir().join(format!(
+        "rumpus_time_calibration_{}.json",
+        std::process::id()
+    ));
+    TimeCalibration::new(best.camera_latency_ms, best.frames, best.mean_weighted_rmse)
+        .write(&path)
+        .unwrap();
+    let calibration = TimeCalibration::read(&path).unwrap();
+    std::fs::remove_file(&path).unwrap();
+    assert!((calibration.camera_latency_ms - 13.).abs() < 1e-9);
+    assert_eq!(calibration.frames, 20);
+}
+
+#[test]
+fn image_circle_is_found_where_the_corners_go_dark() {
+    // A lit circle of radius 90 around the center of a 200 by 240 sensor.
+    let raw = image::GrayImage::from_fn(240, 200, |x, y| {
+        let radius = (f64::from(y) + 0.5 - 100.).hypot(f64::from(x) + 0.5 - 120.);
+        image::Luma([if radius <= 90. { 180 } else { 3 }])
+    });
+    let radius = auto_radius_px(&raw).unwrap();
+    assert!((radius - 90.).abs() <= 8., "found a radius of {radius} px");
+
+    let circle = ImageCircle::Auto
+        .locate(&raw, ImageOrientation::default())
+        .unwrap();
+    assert!(circle.contains(100., 120.));
+    assert!(!circle.contains(2., 2.));
+
+    // Without dark corners there is nothing to mask.
+    let lit = image::GrayImage::from_pixel(240, 200, image::Luma([180]));
+    assert_eq!(auto_radius_px(&lit), None);
+    assert_eq!(
+        ImageCircle::Auto.locate(&lit, ImageOrientation::default()),
+        None
+    );
+
+    let fixed: ImageCircle = "1100@12,-3".parse().unwrap();
+    assert_eq!(
+        fixed,
+        ImageCircle::Fixed {
+            radius_px: 1100.,
+            cx: 12.,
+            cy: -3.
+        }
+    );
+    assert_eq!(fixed.to_string().parse::<ImageCircle>().unwrap(), fixed);
+    assert!("-5".parse::<ImageCircle>().is_err());
+}
+
+#[test]
+fn prefetched_images_are_taken_in_order() {
+    let dir = std::env::temp_dir().join(format!("rumpus_prefetch_{}", std::process::id()));
+    let paths: Vec<_> = (0..4).map(|i| dir.join(format!("{i:04}.png"))).collect();
+    let prefetch = ImagePrefetch::start(ImageReader::new(), paths.clone(), 2, 8);
+
+    // Missing images are queued as errors like any other decode result.
+    assert!(prefetch.take(&paths[1], 8).unwrap().is_err());
+    // Images before the one taken are dropped, and others are not prefetched.
+    assert!(prefetch.take(&paths[0], 8).is_none());
+    assert!(prefetch.take(&dir.join("other.png"), 8).is_none());
+    assert!(prefetch.take(&paths[3], 16).is_none());
+    assert!(prefetch.take(&paths[3], 8).unwrap().is_err());
+    assert!(prefetch.take(&paths[2], 8).is_none());
+}
+
+#[test]
+fn read_ahead_hands_files_on_in_order() {
+    let dir = std::env::temp_dir().join(format!("rumpus_read_ahead_{}", std::process::id()));
+    std::fs::create_dir_all(&dir).unwrap();
+    let paths: Vec<_> = (0..5).map(|i| dir.join(format!("{i:04}.bin"))).collect();
+    for (i, path) in paths.iter().enumerate().skip(1) {
+        std::fs::write(path, vec![i as u8; 16 * i]).unwrap();
+    }
+
+    let read_ahead = ReadAhead::start(paths.clone(), 2);
+    if !cfg!(feature = "async-io") {
+        assert!(read_ahead.is_err());
+        std::fs::remove_dir_all(&dir).unwrap();
+        return;
+    }
+    let read_ahead = read_ahead.unwrap();
+    assert_eq!(read_ahead.paths(), paths.as_slice());
+    for (i, path) in paths.iter().enumerate() {
+        let (read_path, bytes) = read_ahead.recv().unwrap();
+        assert_eq!(&read_path, path);
+        // The first file is missing.
+        match bytes {
+            Ok(bytes) => assert_eq!(bytes, vec![i as u8; 16 * i]),
+            Err(_) => assert_eq!(i, 0),
+        }
+    }
+    assert!(read_ahead.recv().is_none());
+    std::fs::remove_dir_all(&dir).unwrap();
+}
+
+/// A ustar entry for a regular file.
+fn tar_entry(name: &str, data: &[u8]) -> Vec<u8> {
+    let mut header = [0u8; 512];
+    header[..name.len()].copy_from_slice(name.as_bytes());
+    header[100..107].copy_from_slice(b"0000644");
+    header[124..135].copy_from_slice(format!("{:011o}", data.len()).as_bytes());
+    header[156] = b'0';
+    header[257..263].copy_from_slice(b"ustar\0");
+    header[263..265].copy_from_slice(b"00");
+    header[148..156].fill(b' ');
+    let checksum: u32 = header.iter().map(|&byte| u32::from(byte)).sum();
+    header[148..155].copy_from_slice(format!("{checksum:06o}\0").as_bytes());
+
+    let mut entry = header.to_vec();
+    entry.extend_from_slice(data);
+    entry.resize(entry.len().div_ceil(512) * 512, 0);
+    entry
+}
+
+#[test]
+fn archived_datasets_read_like_extracted_ones() {
+    let from = Dataset::new(Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/data/mini"));
+    let mut files = vec![from.time_path(), from.ins_path()];
+    files.extend((0..5).map(|i| from.image_path(i)));
+    let mut tar = Vec::new();
+    for file in &files {
+        let name = Path::new("./mini").join(file.strip_prefix(from.path()).unwrap());
+        tar.extend(tar_entry(
+            name.to_str().unwrap(),
+            &std::fs::read(file).unwrap(),
+        ));
+    }
+    tar.extend([0; 1024]);
+    let path = std::env::temp_dir().join(format!("rumpus_archive_{}.tar.zst", std::process::id()));
+    std::fs::write(&path, zstd::encode_all(tar.as_slice(), 3).unwrap()).unwrap();
+
+    let archived = Dataset::new(&path);
+    let times = |dataset: &Dataset| -> Vec<_> {
+        TimeReader::new()
+            .read_csv(dataset.time_path())
+            .unwrap()
+            .map(|frame| frame.time)
+            .collect()
+    };
+    let (original, unpacked) = (times(&from), times(&archived));
+    // Going back to an earlier image starts the stream over.
+    let images: Vec<_> = [3, 1, 4]
+        .map(|i| archive::read_file(&archived.image_path(i)).unwrap())
+        .into();
+    let indexed = archive::DatasetArchive::shared(&path).unwrap().images();
+    let missing = archive::read_file(&archived.image_path(5));
+    // Reading ahead goes through the archive in order, one file at a time.
+    let read_ahead =
+        ReadAhead::start((0..5).map(|i| archived.image_path(i)).collect(), 3).map(|read_ahead| {
+            std::iter::from_fn(|| read_ahead.recv())
+                .map(|(path, bytes)| (path, bytes.unwrap()))
+                .collect::<Vec<_>>()
+        });
+    std::fs::remove_file(&path).unwrap();
+
+    assert_eq!(unpacked, original);
+    for (image, i) in images.iter().zip([3, 1, 4]) {
+        assert_eq!(image, &std::fs::read(from.image_path(i)).unwrap());
+    }
+    assert_eq!(indexed, 5);
+    assert!(missing.is_err());
+    if cfg!(feature = "async-io") {
+        for (i, (path, bytes)) in read_ahead.unwrap().into_iter().enumerate() {
+            assert_eq!(path, archived.image_path(i));
+            assert_eq!(bytes, std::fs::read(from.image_path(i)).unwrap());
+        }
+    } else {
+        assert!(read_ahead.is_err());
+    }
+}
+
+#[test]
+fn remote_files_are_read_from_the_cache() {
+    let cache_dir = std::env::temp_dir().join(format!("rumpus_cache_{}", std::process::id()));
+    let cached = cache_dir.join("bucket/run/novatel/time.csv");
+    std::fs::create_dir_all(cached.parent().unwrap()).unwrap();
+    std::fs::write(&cached, "cached").unwrap();
+
+    let path = Path::new("s3://bucket/run").join("novatel/time.csv");
+    let local = remote::local_path_in(&path, &cache_dir);
+    let read = local
+        .as_ref()
+        .ok()
+        .map(|local| std::fs::read(local).unwrap());
+    std::fs::remove_dir_all(&cache_dir).unwrap();
+
+    assert!(remote::is_remote(&path));
+    assert!(!remote::is_remote(Path::new("tests/data/mini")));
+    if cfg!(feature = "remote") {
+        assert_eq!(local.unwrap(), cached);
+        assert_eq!(read.unwrap(), b"cached");
+    } else {
+        assert!(matches!(local, Err(BenchError::Config(_))));
+    }
+}
+
+#[test]
+fn image_writer_finishes_every_queued_image() {
+    let dir = std::env::temp_dir().join(format!("rumpus_image_writer_{}", std::process::id()));
+    std::fs::create_dir_all(&dir).unwrap();
+    let writer = ImageWriter::new(3);
+    // A failed write is reported without stopping the others.
+    writer.write("nothing", || Err(BenchError::Config("failed".to_string())));
+    for i in 0..20 {
+        let overlay = Overlay::from_rgb(&[i; 4 * 3 * 3], 4, 3);
+        writer.save(
+            overlay,
+            dir.clone(),
+            format!("image_{i:02}"),
+            ImageFormat::Png,
+            false,
+        );
+    }
+    writer.finish();
+
+    let written = std::fs::read_dir(&dir).unwrap().count();
+    let last = image::open(dir.join("image_19.png")).unwrap().to_rgb8();
+    std::fs::remove_dir_all(&dir).unwrap();
+
+    assert_eq!(written, 20);
+    assert_eq!(last.dimensions(), (4, 3));
+    assert_eq!(last.get_pixel(3, 2).0, [19; 3]);
+}
+
+#[test]
+fn image_writer_resizes_by_its_scale() {
+    let dir = std::env::temp_dir().join(format!("rumpus_image_scale_{}", std::process::id()));
+    std::fs::create_dir_all(&dir).unwrap();
+    let writer = ImageWriter::new(1).with_scale(0.5);
+    let overlay = Overlay::from_rgb(&[200; 1224 * 1024 * 3], 1224, 1024);
+    writer.save(
+        overlay,
+        dir.clone(),
+        "half".to_string(),
+        ImageFormat::Png,
+        false,
+    );
+    writer.finish();
+
+    let half = image::open(dir.join("half.png")).unwrap().to_rgb8();
+    std::fs::remove_dir_all(&dir).unwrap();
+
+    assert_eq!(half.dimensions(), (612, 512));
+    assert_eq!(half.get_pixel(300, 200).0, [200; 3]);
+}
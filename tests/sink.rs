@@ -0,0 +1,259 @@
+//! Round trips of result rows through every sink, checking each value keeps its type.
+
+use clap::Parser;
+use rumpus_benchmark::{
+    cli::{DatasetArgs, SkyArgs, run_metadata},
+    error::BenchError,
+    run::RunMetadata,
+    sink::{ResultRow, SinkFormat},
+};
+use serde::Serialize;
+use std::path::{Path, PathBuf};
+
+#[derive(Serialize)]
+struct Row {
+    frame_index: usize,
+    weighted_rmse: f64,
+    found: bool,
+    label: &'static str,
+    yaw_deg: Option<f64>,
+}
+
+fn rows() -> Vec<ResultRow> {
+    [
+        Row {
+            frame_index: 0,
+            weighted_rmse: 0.25,
+            found: true,
+            label: "urban",
+            yaw_deg: Some(12.5),
+        },
+        Row {
+            frame_index: 1,
+            weighted_rmse: 3.,
+            found: false,
+            label: "open sky",
+            yaw_deg: None,
+        },
+    ]
+    .iter()
+    .map(|row| ResultRow::new(row).unwrap())
+    .collect()
+}
+
+#[derive(Parser)]
+struct Args {
+    #[command(flatten)]
+    dataset: DatasetArgs,
+    #[command(flatten)]
+    sky: SkyArgs,
+}
+
+fn metadata() -> RunMetadata {
+    let args = Args::parse_from(["sink", "tests/data/mini"]);
+    run_metadata("sink", "2024-06-21T16:00:00Z", &args.dataset, &args.sky)
+}
+
+fn results_dir(name: &str) -> PathBuf {
+    let dir = std::env::temp_dir().join(format!("rumpus_sink_{name}_{}", std::process::id()));
+    std::fs::create_dir_all(&dir).unwrap();
+    dir
+}
+
+/// Writes every row as a frame and as a candidate of frame 0.
+fn write_rows(format: SinkFormat, dir: &Path) -> Result<(), BenchError> {
+    let mut sink = format.open(dir, &metadata())?;
+    for row in rows() {
+        sink.write_candidate(0, row.clone())?;
+        sink.write_frame(row)?;
+    }
+    sink.finalize()
+}
+
+fn read_csv(reader: impl std::io::Read) -> Vec<Vec<String>> {
+    let mut reader = csv::Reader::from_reader(reader);
+    let mut records = vec![reader.headers().unwrap().iter().map(String::from).collect()];
+    records.extend(
+        reader
+            .records()
+            .map(|record| record.unwrap().iter().map(String::from).collect()),
+    );
+    records
+}
+
+fn expected_csv() -> Vec<Vec<String>> {
+    let rows = rows();
+    let mut expected = vec![rows[0].columns().to_vec()];
+    expected.extend(rows.iter().map(|row| row.values().to_vec()));
+    expected
+}
+
+#[test]
+fn result_rows_flatten_records_like_csv() {
+    let rows = rows();
+    assert_eq!(
+        rows[0].columns(),
+        ["frame_index", "weighted_rmse", "found", "label", "yaw_deg"]
+    );
+    assert_eq!(rows[0].values(), ["0", "0.25", "true", "urban", "12.5"]);
+    assert_eq!(rows[1].values(), ["1", "3.0", "false", "open sky", ""]);
+}
+
+#[test]
+fn csv_sinks_write_rows_back_unchanged() {
+    let dir = results_dir("csv");
+    write_rows(SinkFormat::Csv, &dir).unwrap();
+    let frames = read_csv(std::fs::File::open(dir.join("results.csv")).unwrap());
+    let candidates = read_csv(std::fs::File::open(dir.join("frame_0000_results.csv")).unwrap());
+    std::fs::remove_dir_all(&dir).unwrap();
+
+    let zstd_dir = results_dir("csv_zstd");
+    write_rows(SinkFormat::CsvZstd, &zstd_dir).unwrap();
+    let compressed = std::fs::File::open(zstd_dir.join("candidates.csv.zst")).unwrap();
+    let compressed = read_csv(zstd::Decoder::new(compressed).unwrap());
+    std::fs::remove_dir_all(&zstd_dir).unwrap();
+
+    assert_eq!(frames, expected_csv());
+    assert_eq!(candidates, expected_csv());
+    assert_eq!(compressed, expected_csv());
+}
+
+#[cfg(feature = "sqlite")]
+#[test]
+fn sqlite_sink_stores_values_as_their_type() {
+    use rusqlite::types::Value;
+
+    let dir = results_dir("sqlite");
+    write_rows(SinkFormat::Sqlite, &dir).unwrap();
+    let connection = rusqlite::Connection::open(dir.join("results.sqlite")).unwrap();
+    let read = |table: &str| -> Vec<Vec<Value>> {
+        connection
+            .prepare(&format!(
+                "SELECT frame_index, weighted_rmse, found, label, yaw_deg FROM {table} \
+                 ORDER BY frame_index"
+            ))
+            .unwrap()
+            .query_map([], |row| (0..5).map(|i| row.get::<_, Value>(i)).collect())
+            .unwrap()
+            .map(Result::unwrap)
+            .collect()
+    };
+    let (frames, candidates) = (read("frames"), read("candidates"));
+    let runs: i64 = connection
+        .query_row("SELECT COUNT(*) FROM runs", [], |row| row.get(0))
+        .unwrap();
+    drop(connection);
+    std::fs::remove_dir_all(&dir).unwrap();
+
+    let expected = vec![
+        vec![
+            Value::Integer(0),
+            Value::Real(0.25),
+            Value::Integer(1),
+            Value::Text("urban".to_string()),
+            Value::Real(12.5),
+        ],
+        vec![
+            Value::Integer(1),
+            Value::Real(3.),
+            Value::Integer(0),
+            Value::Text("open sky".to_string()),
+            Value::Null,
+        ],
+    ];
+    assert_eq!(frames, expected);
+    assert_eq!(candidates, expected);
+    assert_eq!(runs, 1);
+}
+
+#[cfg(feature = "parquet")]
+#[test]
+fn parquet_sink_types_columns_by_their_values() {
+    use parquet::{
+        basic::Type,
+        file::reader::{FileReader, SerializedFileReader},
+        record::Field,
+    };
+
+    let dir = results_dir("parquet");
+    write_rows(SinkFormat::Parquet, &dir).unwrap();
+    let reader =
+        SerializedFileReader::new(std::fs::File::open(dir.join("results.parquet")).unwrap())
+            .unwrap();
+    let schema = reader.metadata().file_metadata().schema_descr_ptr();
+    let types: Vec<Type> = (0..schema.num_columns())
+        .map(|i| schema.column(i).physical_type())
+        .collect();
+    let rows: Vec<Vec<Field>> = reader
+        .get_row_iter(None)
+        .unwrap()
+        .map(|row| {
+            row.unwrap()
+                .get_column_iter()
+                .map(|(_, field)| field.clone())
+                .collect()
+        })
+        .collect();
+    std::fs::remove_dir_all(&dir).unwrap();
+
+    assert_eq!(
+        types,
+        [
+            Type::INT64,
+            Type::DOUBLE,
+            Type::BOOLEAN,
+            Type::BYTE_ARRAY,
+            Type::DOUBLE
+        ]
+    );
+    assert_eq!(
+        rows,
+        [
+            vec![
+                Field::Long(0),
+                Field::Double(0.25),
+                Field::Bool(true),
+                Field::Str("urban".to_string()),
+                Field::Double(12.5),
+            ],
+            vec![
+                Field::Long(1),
+                Field::Double(3.),
+                Field::Bool(false),
+                Field::Str("open sky".to_string()),
+                Field::Null,
+            ],
+        ]
+    );
+}
+
+#[cfg(feature = "parquet")]
+#[test]
+fn parquet_sink_rejects_values_of_another_type() {
+    #[derive(Serialize)]
+    struct Reading {
+        frame_index: usize,
+        reading: String,
+    }
+
+    let dir = results_dir("parquet_mismatch");
+    let mut sink = SinkFormat::Parquet.open(&dir, &metadata()).unwrap();
+    // The first row group makes `reading` an integer column.
+    for frame_index in 0..10_001 {
+        let reading = if frame_index < 10_000 {
+            frame_index.to_string()
+        } else {
+            "n/a".to_string()
+        };
+        let row = ResultRow::new(&Reading {
+            frame_index,
+            reading,
+        })
+        .unwrap();
+        sink.write_frame(row).unwrap();
+    }
+    let error = sink.finalize().unwrap_err();
+    std::fs::remove_dir_all(&dir).unwrap();
+
+    assert!(error.to_string().contains("column reading"), "{error}");
+}
@@ -0,0 +1,665 @@
+//! Invariants of the frame transforms that must hold for any attitude and position.
+
+use chrono::{TimeZone, Utc};
+use proptest::prelude::*;
+use rumpus::{
+    image::RayImage,
+    optic::PixelCoordinate,
+    ray::{Aop, GlobalFrame, Ray, SensorFrame},
+};
+use rumpus_benchmark::{
+    camera::{CameraModel, PixelGrid, PrincipalPoint},
+    conventions::{ConventionCheck, ConventionHypothesis},
+    dead_reckoning::{DeadReckoner, DriftSummary, LocalPosition},
+    ephemeris::CelestialPosition,
+    heading::{CostSample, HeadingEstimate, SearchWindow},
+    histogram::AopHistogram,
+    image_circle::Circle,
+    scattering::{estimate_source, global_to_scattering_plane, scattering_plane_angle},
+    sky::{Sky, SkyDirection, SkyModelBackend, sky_directions},
+    systems::{
+        AzimuthConvention, CamXyz, HeadingConventions, InsConvention, InsEnu, cam_to_car,
+        car_to_ins, enu_yaw_from_heading_deg, heading_from_enu_yaw_deg, heading_from_yaw_deg,
+        ins_to_ecef, yaw_from_heading_deg,
+    },
+    utils::{global_to_sensor, measured_to_global, sensor_to_global, sensor_to_global_per_pixel},
+    validity::{PixelValidity, ValidityCounts, ValidityMask},
+};
+use sguaba::{Vector, systems::Ecef, vector};
+use std::sync::Arc;
+use uom::{
+    ConstZero,
+    si::{
+        angle::degree,
+        f64::{Angle, Length},
+        length::{meter, micron, millimeter},
+    },
+};
+
+const TOLERANCE: f64 = 1e-9;
+
+fn camera_model() -> CameraModel {
+    let focal_length = Length::new::<millimeter>(8.0);
+    let pixel_size = Length::new::<micron>(3.45);
+    CameraModel::new(focal_length, pixel_size * 2.0, 1024, 1224)
+}
+
+fn components(vector: Vector<Ecef>) -> [f64; 3] {
+    [
+        vector.x().get::<meter>(),
+        vector.y().get::<meter>(),
+        vector.z().get::<meter>(),
+    ]
+}
+
+fn dot(a: [f64; 3], b: [f64; 3]) -> f64 {
+    a.iter().zip(&b).map(|(a, b)| a * b).sum()
+}
+
+/// Difference of two angles in deg, ignoring whole turns.
+fn angle_between(a: f64, b: f64) -> f64 {
+    let d = (a - b).rem_euclid(360.);
+    d.min(360. - d)
+}
+
+#[test]
+fn compass_points_in_each_convention() {
+    // (heading clockwise from north, yaw counter-clockwise from north, REP-103 yaw)
+    for (heading, yaw, enu_yaw) in [
+        (0., 0., 90.),
+        (90., -90., 0.),
+        (180., -180., -90.),
+        (270., 90., -180.),
+        (350., 10., 100.),
+    ] {
+        assert!(angle_between(heading_from_yaw_deg(yaw), heading) < TOLERANCE);
+        assert!(angle_between(yaw_from_heading_deg(heading), yaw) < TOLERANCE);
+        assert!(angle_between(enu_yaw_from_heading_deg(heading), enu_yaw) < TOLERANCE);
+        assert!(angle_between(heading_from_enu_yaw_deg(enu_yaw), heading) < TOLERANCE);
+    }
+}
+
+#[test]
+fn offset_from_ins_is_counter_clockwise() {
+    // The INS says north; the estimate is 10 deg counter-clockwise of it, so west of north.
+    let conventions = HeadingConventions::from_ins_yaw(0., 10.);
+    assert!((conventions.true_heading_deg - 350.).abs() < TOLERANCE);
+    assert!((conventions.enu_yaw_deg - 100.).abs() < TOLERANCE);
+    assert!((conventions.ins_offset_deg - 10.).abs() < TOLERANCE);
+}
+
+#[test]
+fn headings_stay_below_a_full_turn() {
+    for yaw in [0., -0., 1e-17, -1e-17, 360., -360., 720.] {
+        let heading = heading_from_yaw_deg(yaw);
+        assert!((0. ..360.).contains(&heading), "{yaw} became {heading}");
+        assert!(heading.is_sign_positive(), "{yaw} became {heading}");
+    }
+}
+
+#[test]
+fn rep_103_attitude_matches_inspva() {
+    // Facing east is yaw 0 in REP-103 and azimuth 90 in INSPVA.
+    let (azimuth, pitch, roll) = InsConvention::EnuFlu.to_inspva(0., 5., 2.);
+    assert!((azimuth - 90.).abs() < TOLERANCE);
+    assert!((pitch + 5.).abs() < TOLERANCE);
+    assert!((roll - 2.).abs() < TOLERANCE);
+    // REP-103 yaw is an azimuth counter-clockwise from east.
+    assert_eq!(
+        InsConvention::EnuFlu.azimuth(),
+        AzimuthConvention::RightHandedFromEast
+    );
+}
+
+#[test]
+fn other_azimuth_conventions_keep_inspva_pitch_and_roll() {
+    // Facing north-east.
+    for (convention, azimuth) in [
+        (InsConvention::InspvaCcw, -45.),
+        (InsConvention::InspvaFromEast, -45.),
+    ] {
+        let (heading, pitch, roll) = convention.to_inspva(azimuth, 5., 2.);
+        assert!(angle_between(heading, 45.) < TOLERANCE, "{convention:?}");
+        assert!((pitch - 5.).abs() < TOLERANCE, "{convention:?}");
+        assert!((roll - 2.).abs() < TOLERANCE, "{convention:?}");
+    }
+}
+
+#[test]
+fn tilted_cameras_are_converted_pixel_by_pixel() {
+    let camera = camera_model().downsampled(16);
+    // Pitched so far forward that the zenith is out of view.
+    let car_in_ins_enu =
+        InsEnu::orientation_from_inspva(AzimuthConvention::LeftHandedFromNorth, 30., 60., 0.);
+    assert!(camera.zenith_pixel(car_in_ins_enu).is_none());
+    let ray = Ray::new(Aop::from_angle_wrapped(Angle::new::<degree>(20.)), 0.5);
+    let pixels = camera.rows() * camera.cols();
+    let sensor =
+        RayImage::<SensorFrame>::from_rays(vec![Some(ray); pixels], camera.rows(), camera.cols())
+            .unwrap();
+
+    let global = measured_to_global(&sensor, &camera, car_in_ins_enu);
+    assert_eq!(
+        global.pixels().filter(|px| px.ray().is_some()).count(),
+        pixels
+    );
+}
+
+#[test]
+fn inspva_attitude_keeps_its_sign() {
+    // INSPVA azimuth is clockwise and tait-bryan yaw counter-clockwise; pitch and roll agree.
+    let (yaw, pitch, roll) =
+        InsEnu::orientation_from_inspva(AzimuthConvention::LeftHandedFromNorth, 30., 5., -2.)
+            .to_tait_bryan_angles();
+    assert!(angle_between(yaw.get::<degree>(), -30.) < 1e-6);
+    assert!((pitch.get::<degree>() - 5.).abs() < 1e-6);
+    assert!((roll.get::<degree>() + 2.).abs() < 1e-6);
+}
+
+#[test]
+fn dead_reckoning_a_square_closes_the_loop() {
+    let mut reckoner = DeadReckoner::default();
+    let mut track = vec![reckoner.position()];
+    for heading_deg in [0., 90., 180., 270.] {
+        track.push(reckoner.advance(heading_deg, 10., 10.));
+    }
+    assert!(reckoner.position().distance_m(&LocalPosition::default()) < 1e-9);
+    assert!((reckoner.distance_m() - 400.).abs() < 1e-9);
+
+    // Turning every leg by a degree still closes the loop, but strays from the track on the way.
+    let mut rotated = DeadReckoner::default();
+    let mut rotated_track = vec![rotated.position()];
+    for heading_deg in [1., 91., 181., 271.] {
+        rotated_track.push(rotated.advance(heading_deg, 10., 10.));
+    }
+    let summary = DriftSummary::new(&track, &rotated_track, rotated.distance_m()).unwrap();
+    assert!(summary.final_drift_m < 1e-9);
+    assert_eq!(summary.loop_closure_m.map(|gap| gap < 1e-9), Some(true));
+    assert!(summary.max_drift_m > 1.);
+}
+
+#[test]
+fn pixel_grids_are_shared_per_camera() {
+    let camera_model = camera_model();
+    let grid = PixelGrid::shared(&camera_model);
+    assert!(Arc::ptr_eq(&grid, &PixelGrid::shared(&camera_model)));
+    assert!(!Arc::ptr_eq(
+        &grid,
+        &PixelGrid::shared(&camera_model.downsampled(2))
+    ));
+    assert_eq!(grid.bearings().len(), 1024 * 1224);
+    for (row, col) in [(0, 0), (511, 612), (1023, 1223)] {
+        assert_eq!(grid.bearing(row, col), camera_model.bearing(row, col));
+    }
+}
+
+#[test]
+fn convention_check_tries_eight_distinct_hypotheses() {
+    let hypotheses: Vec<_> = ConventionHypothesis::all().collect();
+    assert_eq!(hypotheses.len(), 8);
+    for (i, hypothesis) in hypotheses.iter().enumerate() {
+        assert!(!hypotheses[..i].contains(hypothesis));
+    }
+    assert!(hypotheses.contains(&ConventionHypothesis::CONFIGURED));
+}
+
+#[test]
+fn convention_check_catches_negated_aop_and_enu_yaw() {
+    let camera_model = camera_model().downsampled(8);
+    let sky = Sky::new(1.0).with_backend(SkyModelBackend::Berry);
+    let position = InsEnu::position_from_inspva(44.2253, -76.4951, 100.);
+    let time = Utc.with_ymd_and_hms(2024, 6, 21, 16, 0, 0).unwrap();
+    let attitude = |heading_deg: f64| {
+        InsEnu::orientation_from_inspva(AzimuthConvention::LeftHandedFromNorth, heading_deg, 0., 0.)
+    };
+    let heading_deg = 60.;
+    let simulated = sky
+        .simulate(&camera_model, &position, attitude(heading_deg), time)
+        .unwrap();
+    let measured = global_to_sensor(&simulated, &camera_model, attitude(heading_deg));
+    let best = |image: &RayImage<SensorFrame>, reported_heading_deg: f64| {
+        let check = ConventionCheck::run(
+            0,
+            &camera_model,
+            &sky,
+            image,
+            &position,
+            time,
+            attitude(reported_heading_deg),
+        )
+        .unwrap();
+        (check.best().unwrap().hypothesis, check.misconfigured())
+    };
+
+    assert_eq!(
+        best(&measured, heading_deg),
+        (ConventionHypothesis::CONFIGURED, false)
+    );
+
+    // A mosaic read with its polarizer angles in mirrored order.
+    let negated = ConventionHypothesis {
+        aop_negated: true,
+        ..ConventionHypothesis::CONFIGURED
+    };
+    assert_eq!(
+        best(&negated.measured(&measured), heading_deg),
+        (negated, true)
+    );
+
+    // An ENU yaw, counter-clockwise from east, read as an INSPVA azimuth.
+    let enu = ConventionHypothesis {
+        azimuth: AzimuthConvention::RightHandedFromEast,
+        ..ConventionHypothesis::CONFIGURED
+    };
+    assert_eq!(best(&measured, 90. - heading_deg), (enu, true));
+}
+
+#[test]
+fn aop_histogram_distance_wraps_around_half_a_turn() {
+    let histogram = |angle_deg: f64| AopHistogram::from_degrees([angle_deg; 10], 36).unwrap();
+    let zero = histogram(0.);
+    assert_eq!(zero.earth_movers_distance_deg(&zero), 0.);
+    assert!((zero.earth_movers_distance_deg(&histogram(45.)) - 45.).abs() < 1e-9);
+    // 170 deg is the same polarization as -10 deg, so only 10 deg away.
+    assert!((zero.earth_movers_distance_deg(&histogram(170.)) - 10.).abs() < 1e-9);
+    assert!(AopHistogram::from_degrees([], 36).is_none());
+}
+
+#[test]
+fn validity_masks_tell_apart_why_pixels_are_missing() {
+    fn image<F>(valid: [bool; 6]) -> RayImage<F> {
+        let ray = || Ray::new(Aop::from_angle_wrapped(Angle::new::<degree>(10.)), 0.5);
+        RayImage::from_rays(valid.map(|valid| valid.then(ray)), 1, 6).unwrap()
+    }
+    let measured_raw = image::<SensorFrame>([false, true, true, true, true, false]);
+    let simulated_raw = image::<GlobalFrame>([true, false, true, true, true, true]);
+    let measured = image::<GlobalFrame>([false, true, false, true, true, false]);
+    let simulated = image::<GlobalFrame>([true, false, true, true, true, true]);
+    let mask = [false, false, false, true, false, false];
+    // Only the last ray is outside of the image circle.
+    let image_circle = Circle {
+        center_row: 0.5,
+        center_col: 2.5,
+        radius_px: 2.6,
+    };
+
+    let validity = ValidityMask::new(
+        &measured_raw,
+        &simulated_raw,
+        &measured,
+        &simulated,
+        Some(&mask),
+        Some(image_circle),
+    );
+    assert_eq!(validity.get(0, 0), PixelValidity::NoData);
+    assert_eq!(validity.get(0, 2), PixelValidity::Transform);
+    assert_eq!(validity.get(0, 5), PixelValidity::OutsideImageCircle);
+    let counts = validity.counts();
+    assert_eq!(
+        counts,
+        ValidityCounts {
+            valid: 1,
+            no_data: 1,
+            not_simulated: 1,
+            transform: 1,
+            masked: 1,
+            outside_image_circle: 1,
+        }
+    );
+    assert!((counts.valid_fraction() - 2. / 6.).abs() < TOLERANCE);
+    assert!(validity.weighted_rmse().abs() < TOLERANCE);
+}
+
+#[test]
+fn turbidity_falls_towards_clear_with_altitude() {
+    let hazy = Sky::new(3.);
+    let turbidities = [0., 1_000., 3_000.].map(|altitude_m| hazy.turbidity_at(altitude_m));
+    assert!((turbidities[0] - 3.).abs() < TOLERANCE);
+    assert!(turbidities[0] > turbidities[1] && turbidities[1] > turbidities[2]);
+    assert!(turbidities[2] > 1.);
+    assert!((Sky::new(1.).turbidity_at(3_000.) - 1.).abs() < TOLERANCE);
+}
+
+#[test]
+fn scattering_planes_pass_through_the_source() {
+    let sun = CelestialPosition {
+        azimuth: Angle::new::<degree>(120.),
+        elevation: Angle::new::<degree>(30.),
+    };
+    let view = |azimuth, zenith| SkyDirection {
+        azimuth: Angle::new::<degree>(azimuth),
+        zenith: Angle::new::<degree>(zenith),
+    };
+    let plane = |direction| scattering_plane_angle(&direction, &sun).map(|a| a.get::<degree>());
+
+    // Above the sun its plane is the meridian towards the horizon, and across the zenith the
+    // meridian back over it.
+    assert!(angle_between(plane(view(120., 30.)).unwrap(), 0.) < 1e-6);
+    assert!(angle_between(plane(view(300., 30.)).unwrap(), 180.) < 1e-6);
+    // Along the horizon the plane leaves the meridian.
+    assert!(angle_between(plane(view(210., 90.)).unwrap(), 0.) > 10.);
+    assert!(plane(view(120., 60.)).is_none());
+    assert!(plane(view(300., 120.)).is_none());
+}
+
+#[test]
+fn light_source_is_found_in_a_single_scattering_sky() {
+    let camera = camera_model().downsampled(16);
+    let car_in_ins_enu =
+        InsEnu::orientation_from_inspva(AzimuthConvention::LeftHandedFromNorth, 30., 0., 0.);
+    let sun = CelestialPosition {
+        azimuth: Angle::new::<degree>(200.),
+        elevation: Angle::new::<degree>(40.),
+    };
+    let directions = sky_directions(&camera, car_in_ins_enu);
+    let rays = directions.iter().map(|direction| {
+        let plane = scattering_plane_angle(direction.as_ref()?, &sun)?;
+        let aop = Aop::from_angle_wrapped(plane + Angle::new::<degree>(90.));
+        Some(Ray::<GlobalFrame>::new(aop, 0.5))
+    });
+    let measured = RayImage::from_rays(rays, camera.rows(), camera.cols()).unwrap();
+
+    // The ephemeris only tells the sun from the anti-sun, so its error does not carry over.
+    let near = CelestialPosition {
+        azimuth: Angle::new::<degree>(230.),
+        elevation: Angle::new::<degree>(20.),
+    };
+    let found = estimate_source(&measured, &directions, &near).unwrap();
+    assert!(found.separation(&sun).get::<degree>() < 1e-6);
+
+    let referred = global_to_scattering_plane(&measured, &camera, car_in_ins_enu, &found);
+    for ray in referred.pixels().filter_map(|px| px.ray()) {
+        // AoP repeats every half turn.
+        let aop = Angle::from(ray.aop()).get::<degree>();
+        assert!(angle_between(2. * aop, 180.) < 1e-6);
+    }
+}
+
+prop_compose! {
+    fn attitude()(
+        azimuth in 0.0..360.0,
+        pitch in -30.0..30.0,
+        roll in -30.0..30.0,
+    ) -> (f64, f64, f64) {
+        (azimuth, pitch, roll)
+    }
+}
+
+proptest! {
+    #[test]
+    fn pixel_bearing_round_trip(row in 0usize..1024, col in 0usize..1224) {
+        let camera_model = camera_model();
+        let bearing = camera_model.bearing(row, col);
+        prop_assert!((dot(bearing, bearing) - 1.).abs() < TOLERANCE);
+        prop_assert_eq!(camera_model.pixel(bearing), Some((row, col)));
+    }
+
+    #[test]
+    fn principal_point_moves_the_optical_axis(
+        row in 0usize..1024,
+        col in 0usize..1224,
+        cx in -40.0..40.0,
+        cy in -40.0..40.0,
+    ) {
+        let camera_model = camera_model().with_principal_point(PrincipalPoint::new(cx, cy));
+        let bearing = camera_model.bearing(row, col);
+        prop_assert_eq!(camera_model.pixel(bearing), Some((row, col)));
+
+        let axis = camera_model.pixel([0., 0., 1.]).unwrap();
+        prop_assert_eq!(axis, ((512. + cy).round() as usize, (612. + cx).round() as usize));
+    }
+
+    #[test]
+    fn centered_camera_sees_every_bearing_off_center(
+        row in 0usize..1024,
+        col in 0usize..1224,
+        cx in -40.0..40.0,
+        cy in -40.0..40.0,
+    ) {
+        let camera_model = camera_model().with_principal_point(PrincipalPoint::new(cx, cy));
+        let (centered, (row_offset, col_offset)) = camera_model.centered();
+        prop_assert_eq!(centered.principal_point(), PrincipalPoint::CENTERED);
+
+        let pixel = centered.pixel(camera_model.bearing(row, col));
+        let expected = (
+            (row as f64 + row_offset).round() as usize,
+            (col as f64 + col_offset).round() as usize,
+        );
+        prop_assert_eq!(pixel, Some(expected));
+    }
+
+    #[test]
+    fn sensor_to_global_keeps_pixels_and_dop(
+        rays in prop::collection::vec(prop::option::of((-90.0..90.0, 0.0..1.0)), 6 * 8),
+        origin_row in 0usize..6,
+        origin_col in 0usize..8,
+    ) {
+        let sensor = RayImage::<SensorFrame>::from_rays(
+            rays.iter().map(|ray| {
+                ray.map(|(aop, dop)| {
+                    Ray::new(Aop::from_angle_wrapped(Angle::new::<degree>(aop)), dop)
+                })
+            }),
+            6,
+            8,
+        )
+        .unwrap();
+        let origin = PixelCoordinate::new(origin_row, origin_col);
+        let global = sensor_to_global(&sensor, &origin);
+
+        prop_assert_eq!((global.rows(), global.cols()), (6, 8));
+        for (i, ray) in rays.iter().enumerate() {
+            let (row, col) = (i / 8, i % 8);
+            match (ray, global.ray(row, col)) {
+                (Some((_, dop)), Some(global_ray)) => {
+                    prop_assert!((global_ray.dop() - dop).abs() < TOLERANCE);
+                }
+                (None, None) => {}
+                (ray, global_ray) => prop_assert!(
+                    false,
+                    "pixel ({row}, {col}) was {ray:?} in the sensor frame but {} in the global frame",
+                    if global_ray.is_some() { "valid" } else { "invalid" }
+                ),
+            }
+        }
+    }
+
+    #[test]
+    fn global_to_sensor_undoes_the_per_pixel_shift(
+        rays in prop::collection::vec(prop::option::of((-90.0..90.0, 0.0..1.0)), 6 * 8),
+        (azimuth, pitch, roll) in attitude(),
+    ) {
+        let camera = CameraModel::new(
+            Length::new::<millimeter>(8.0),
+            Length::new::<micron>(3.45) * 2.0,
+            6,
+            8,
+        );
+        let car_in_ins_enu = InsEnu::orientation_from_inspva(AzimuthConvention::LeftHandedFromNorth, azimuth, pitch, roll);
+        let sensor = RayImage::<SensorFrame>::from_rays(
+            rays.iter().map(|ray| {
+                ray.map(|(aop, dop)| {
+                    Ray::new(Aop::from_angle_wrapped(Angle::new::<degree>(aop)), dop)
+                })
+            }),
+            6,
+            8,
+        )
+        .unwrap();
+        let global = sensor_to_global_per_pixel(&sensor, &camera, car_in_ins_enu);
+        let round_trip = global_to_sensor(&global, &camera, car_in_ins_enu);
+
+        let zenith = camera
+            .zenith_pixel(car_in_ins_enu)
+            .map(|pixel| (pixel.row(), pixel.col()));
+        for (row, col) in (0..6).flat_map(|row| (0..8).map(move |col| (row, col))) {
+            let (ray, round_trip_ray) = match (sensor.ray(row, col), round_trip.ray(row, col)) {
+                (Some(ray), Some(round_trip_ray)) => (ray, round_trip_ray),
+                (None, None) => continue,
+                // Only the zenith pixel, which has no meridian, may be lost.
+                (Some(_), None) if zenith == Some((row, col)) => continue,
+                (ray, _) => {
+                    return Err(TestCaseError::fail(format!(
+                        "pixel ({row}, {col}) has a ray only {}",
+                        if ray.is_some() { "before the round trip" } else { "after the round trip" }
+                    )));
+                }
+            };
+            let error = Angle::from(ray.aop() - round_trip_ray.aop()).get::<degree>();
+            prop_assert!(angle_between(error, 0.) < 1e-6, "pixel ({row}, {col}) is off by {error} deg");
+            prop_assert!((ray.dop() - round_trip_ray.dop()).abs() < TOLERANCE);
+        }
+    }
+
+    #[test]
+    fn per_pixel_shift_agrees_with_the_zenith_pixel(
+        aop in -90.0..90.0,
+        azimuth in 0.0..360.0,
+        pitch in -3.0..3.0,
+        roll in -3.0..3.0,
+    ) {
+        // Pixels of about 0.4 deg, so the zenith of a nearly level car is in view.
+        let camera = CameraModel::new(
+            Length::new::<millimeter>(8.0),
+            Length::new::<micron>(3.45) * 16.0,
+            48,
+            64,
+        );
+        let car_in_ins_enu = InsEnu::orientation_from_inspva(AzimuthConvention::LeftHandedFromNorth, azimuth, pitch, roll);
+        let zenith = camera.zenith_pixel(car_in_ins_enu).expect("zenith in view");
+        let ray = Ray::new(Aop::from_angle_wrapped(Angle::new::<degree>(aop)), 0.5);
+        let sensor = RayImage::<SensorFrame>::from_rays(vec![Some(ray); 48 * 64], 48, 64).unwrap();
+        let around_zenith = sensor_to_global(&sensor, &zenith);
+        let per_pixel = sensor_to_global_per_pixel(&sensor, &camera, car_in_ins_enu);
+
+        for (row, col) in (0..48).flat_map(|row| (0..64).map(move |col| (row, col))) {
+            // The zenith pixel is rounded, which turns the meridians of nearby pixels by up to
+            // asin(0.71 / distance).
+            let distance = (row as f64 - zenith.row() as f64).hypot(col as f64 - zenith.col() as f64);
+            if distance < 20. {
+                continue;
+            }
+            let (Some(expected), Some(actual)) = (around_zenith.ray(row, col), per_pixel.ray(row, col)) else {
+                return Err(TestCaseError::fail(format!("pixel ({row}, {col}) lost its ray")));
+            };
+            let error = Angle::from(expected.aop() - actual.aop()).get::<degree>();
+            prop_assert!(angle_between(error, 0.).min(angle_between(error, 180.)) < 2.1, "pixel ({row}, {col}) is off by {error} deg");
+        }
+    }
+
+    #[test]
+    fn camera_to_ecef_is_orthonormal(
+        (azimuth, pitch, roll) in attitude(),
+        lat in -80.0..80.0,
+        lon in -180.0..180.0,
+        height in -100.0..3000.0,
+    ) {
+        let car_in_ins_enu = InsEnu::orientation_from_inspva(AzimuthConvention::LeftHandedFromNorth, azimuth, pitch, roll);
+        let position = InsEnu::position_from_inspva(lat, lon, height);
+        let to_ecef = |vector: Vector<CamXyz>| {
+            let car_xyz = cam_to_car().transform(vector);
+            let ins_enu = car_to_ins(car_in_ins_enu).transform(car_xyz);
+            components(ins_to_ecef(&position).transform(ins_enu))
+        };
+
+        let unit = Length::new::<meter>(1.);
+        let axes = [
+            to_ecef(vector!(x = unit, y = Length::ZERO, z = Length::ZERO; in CamXyz)),
+            to_ecef(vector!(x = Length::ZERO, y = unit, z = Length::ZERO; in CamXyz)),
+            to_ecef(vector!(x = Length::ZERO, y = Length::ZERO, z = unit; in CamXyz)),
+        ];
+        for (i, a) in axes.iter().enumerate() {
+            for (j, b) in axes.iter().enumerate() {
+                let expected = if i == j { 1. } else { 0. };
+                prop_assert!(
+                    (dot(*a, *b) - expected).abs() < TOLERANCE,
+                    "axes {i} and {j} have dot product {}",
+                    dot(*a, *b)
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn yaw_offsets_are_symmetric_around_zero(
+        half_width_deg in 0.0..30.0,
+        resolution_deg in 0.01..5.0,
+    ) {
+        let window = SearchWindow { center_deg: 0., half_width_deg };
+        let offsets = window.offsets(resolution_deg);
+
+        prop_assert!(offsets.len() % 2 == 1, "zero should be a candidate");
+        prop_assert!(offsets.contains(&0.));
+        for (low, high) in offsets.iter().zip(offsets.iter().rev()) {
+            prop_assert!((low + high).abs() < TOLERANCE, "{low} has no mirror in {high}");
+        }
+        prop_assert!(offsets.iter().all(|offset| offset.abs() <= half_width_deg + TOLERANCE));
+    }
+
+    #[test]
+    fn refinement_finds_the_vertex_of_unevenly_spaced_samples(
+        vertex_deg in -1.0f64..1.0,
+        left_step in 0.1f64..3.0,
+        right_step in 0.1f64..3.0,
+        curvature in 0.01f64..10.0,
+    ) {
+        // The sample at zero is then the closest to the vertex.
+        prop_assume!(-left_step / 2. < vertex_deg && vertex_deg < right_step / 2.);
+        let samples: Vec<_> = [-left_step, 0., right_step, 2. * right_step + 5.]
+            .into_iter()
+            .map(|yaw_offset_deg| CostSample {
+                yaw_offset_deg,
+                cost: curvature * (yaw_offset_deg - vertex_deg).powi(2) + 1.,
+                valid_fraction: 1.,
+            })
+            .collect();
+
+        let estimate = HeadingEstimate::from_sweep(&samples).unwrap();
+        prop_assert!((estimate.yaw_offset_deg - vertex_deg).abs() < 1e-6, "{estimate:?}");
+        prop_assert!((estimate.sharpness.unwrap() - 2. * curvature).abs() < 1e-6 * curvature.max(1.));
+    }
+
+    #[test]
+    fn heading_conventions_round_trip(heading in 0.0..360.0) {
+        prop_assert!(angle_between(heading_from_yaw_deg(yaw_from_heading_deg(heading)), heading) < TOLERANCE);
+        prop_assert!(angle_between(heading_from_enu_yaw_deg(enu_yaw_from_heading_deg(heading)), heading) < TOLERANCE);
+        prop_assert!((-180. ..180.).contains(&yaw_from_heading_deg(heading)));
+        prop_assert!((-180. ..180.).contains(&enu_yaw_from_heading_deg(heading)));
+    }
+
+    #[test]
+    fn inspva_azimuth_is_the_compass_heading((azimuth, pitch, roll) in attitude()) {
+        let (yaw, _, _) = InsEnu::orientation_from_inspva(AzimuthConvention::LeftHandedFromNorth, azimuth, pitch, roll).to_tait_bryan_angles();
+        let heading = heading_from_yaw_deg(yaw.get::<degree>());
+        prop_assert!(angle_between(heading, azimuth) < 1e-6, "{azimuth} came back as {heading}");
+    }
+
+    #[test]
+    fn estimates_are_reported_consistently(ins_yaw in -180.0..180.0, offset in -30.0..30.0) {
+        let conventions = HeadingConventions::from_ins_yaw(ins_yaw, offset);
+        let ins_heading = heading_from_yaw_deg(ins_yaw);
+
+        prop_assert!((0. ..360.).contains(&conventions.true_heading_deg));
+        prop_assert!(angle_between(heading_from_enu_yaw_deg(conventions.enu_yaw_deg), conventions.true_heading_deg) < TOLERANCE);
+        // A counter-clockwise offset turns the compass heading the other way.
+        prop_assert!(angle_between(conventions.true_heading_deg, ins_heading - offset) < TOLERANCE);
+        prop_assert!((conventions.ins_offset_deg - offset).abs() < TOLERANCE);
+    }
+
+    #[test]
+    fn azimuth_conventions_agree_on_the_attitude(heading in 0.0..360.0, pitch in -30.0..30.0, roll in -30.0..30.0) {
+        let expected = InsEnu::orientation_from_inspva(AzimuthConvention::LeftHandedFromNorth, heading, pitch, roll)
+            .to_tait_bryan_angles();
+        for (convention, azimuth) in [
+            (AzimuthConvention::RightHandedFromNorth, -heading),
+            (AzimuthConvention::LeftHandedFromEast, heading - 90.),
+            (AzimuthConvention::RightHandedFromEast, 90. - heading),
+        ] {
+            prop_assert!(angle_between(convention.to_heading_deg(azimuth), heading) < TOLERANCE);
+            let (yaw, p, r) = InsEnu::orientation_from_inspva(convention, azimuth, pitch, roll).to_tait_bryan_angles();
+            prop_assert!(angle_between(yaw.get::<degree>(), expected.0.get::<degree>()) < 1e-6, "{convention:?}");
+            prop_assert!((p - expected.1).get::<degree>().abs() < 1e-6, "{convention:?}");
+            prop_assert!((r - expected.2).get::<degree>().abs() < 1e-6, "{convention:?}");
+        }
+    }
+}